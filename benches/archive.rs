@@ -0,0 +1,55 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use twixelbox_bot::{CanvasEvent, Colour, Cube, CubeArchive, EventMetadata, Position};
+use uuid::Uuid;
+
+// Side of the canvas the archive is filled on.
+const SIDE_LEN: u32 = 16;
+
+// An archive with a cube at each position of the canvas.
+fn filled_archive(dir: &tempfile::TempDir) -> CubeArchive {
+    let mut archive = CubeArchive::new(dir.path().join("bench.sqlite"));
+    let events: Vec<CanvasEvent> = (0..SIDE_LEN.pow(3))
+        .map(|i| {
+            let (x, y, z) = (i % SIDE_LEN, i / SIDE_LEN % SIDE_LEN, i / SIDE_LEN.pow(2));
+            CanvasEvent::CubePlaced(Cube::new(x, y, z, Colour::new(x as u8, y as u8, z as u8)))
+        })
+        .collect();
+    archive
+        .append_events(&events, &EventMetadata::default())
+        .unwrap();
+    archive
+}
+
+fn bench_archive(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let mut archive = filled_archive(&dir);
+    let metadata = EventMetadata {
+        author: Some("chatter".to_owned()),
+        ..Default::default()
+    };
+    let mut i = 0;
+    c.bench_function("append_event", |b| {
+        b.iter(|| {
+            i += 1;
+            let cube = Cube::new(i % SIDE_LEN, 0, 0, Colour::new(1, 2, 3));
+            archive
+                .append_event(Uuid::new_v4(), &CanvasEvent::CubePlaced(cube), &metadata)
+                .unwrap()
+        })
+    });
+    c.bench_function("contains_command", |b| {
+        b.iter(|| archive.contains_command(Uuid::new_v4()).unwrap())
+    });
+    c.bench_function("lookup", |b| {
+        b.iter(|| archive.lookup(Position::new(3, 4, 5)).unwrap())
+    });
+    c.bench_function("history", |b| {
+        b.iter(|| archive.history(Position::new(3, 0, 0), 10).unwrap())
+    });
+    c.bench_function("reopen", |b| {
+        b.iter(|| archive.reopen().contains_command(Uuid::nil()).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_archive);
+criterion_main!(benches);
@@ -0,0 +1,287 @@
+use crate::{CanvasEvent, EventMetadata, Position};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct AchievementConfig {
+    /// Announces in chat the achievements of the chatters, which they list
+    /// with `!achievements`.
+    pub enabled: bool,
+    /// Cubes to place for the achievement of that many cubes.
+    pub cubes: u64,
+    /// Days in a row to place cubes on for the streak.
+    pub streak_days: u32,
+}
+
+impl Default for AchievementConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cubes: 100,
+            streak_days: 7,
+        }
+    }
+}
+
+/// Milestone of a chatter on the canvas, unlocked once and for all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Achievement {
+    FirstCube,
+    /// Placed as many cubes as configured.
+    Cubes,
+    /// Placed cubes on as many days in a row as configured.
+    Streak,
+    /// Placed the last cube of a row of the canvas, along any axis.
+    FullLine,
+}
+
+impl Achievement {
+    /// Name the achievement is archived as, and announced by after
+    /// `achievement_`.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Achievement::FirstCube => "first_cube",
+            Achievement::Cubes => "cubes",
+            Achievement::Streak => "streak",
+            Achievement::FullLine => "full_line",
+        }
+    }
+}
+
+impl FromStr for Achievement {
+    type Err = String;
+
+    fn from_str(id: &str) -> Result<Self, Self::Err> {
+        match id {
+            "first_cube" => Ok(Achievement::FirstCube),
+            "cubes" => Ok(Achievement::Cubes),
+            "streak" => Ok(Achievement::Streak),
+            "full_line" => Ok(Achievement::FullLine),
+            _ => Err(format!("unknown achievement {}", id)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Builder {
+    placed: u64,
+    // Last day they placed a cube on, and how many days in a row up to it.
+    last_day: Option<NaiveDate>,
+    streak: u32,
+    unlocked: HashSet<Achievement>,
+}
+
+/// Follows what each chatter placed from chat, from the journal, and tells
+/// which achievements their placements unlock. The cubes the bot places only
+/// fill the canvas.
+#[derive(Clone, Debug)]
+pub struct AchievementTracker {
+    cubes: u64,
+    streak_days: u32,
+    side_len: u32,
+    builders: HashMap<String, Builder>,
+    occupied: HashSet<Position>,
+    // Cubes in each row of the canvas, by axis and the coordinates on the
+    // other two.
+    rows: HashMap<(usize, u32, u32), u32>,
+}
+
+impl AchievementTracker {
+    /// Returns None when achievements are disabled.
+    pub fn new(config: &AchievementConfig, side_len: u32) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        Some(Self {
+            cubes: config.cubes.max(1),
+            streak_days: config.streak_days.max(1),
+            side_len,
+            builders: HashMap::new(),
+            occupied: HashSet::new(),
+            rows: HashMap::new(),
+        })
+    }
+
+    /// Restores an achievement `login` unlocked before, as archived.
+    pub fn restore(&mut self, login: &str, achievement: Achievement) {
+        let builder = self.builders.entry(login.to_owned()).or_default();
+        builder.unlocked.insert(achievement);
+    }
+
+    /// Achievements of `login`, in the order of `Achievement`.
+    pub fn unlocked(&self, login: &str) -> Vec<Achievement> {
+        let mut unlocked: Vec<_> = self
+            .builders
+            .get(login)
+            .map(|builder| builder.unlocked.iter().copied().collect())
+            .unwrap_or_default();
+        unlocked.sort();
+        unlocked
+    }
+
+    /// Cubes to place for `Achievement::Cubes`.
+    pub fn cubes(&self) -> u64 {
+        self.cubes
+    }
+
+    /// Days in a row to place cubes on for `Achievement::Streak`.
+    pub fn streak_days(&self) -> u32 {
+        self.streak_days
+    }
+
+    /// Mirrors an event applied to the canvas, placed on the local `day`, and
+    /// returns the achievements it unlocked for its author.
+    pub fn apply(
+        &mut self,
+        event: &CanvasEvent,
+        metadata: &EventMetadata,
+        day: NaiveDate,
+    ) -> Vec<Achievement> {
+        let position = match event {
+            CanvasEvent::CubePlaced(cube) => cube.position,
+            CanvasEvent::CubeRemoved(position) => {
+                if self.occupied.remove(position) {
+                    for row in rows(*position) {
+                        if let Some(count) = self.rows.get_mut(&row) {
+                            *count -= 1;
+                        }
+                    }
+                }
+                return Vec::new();
+            }
+            CanvasEvent::CanvasCleared => {
+                self.occupied.clear();
+                self.rows.clear();
+                return Vec::new();
+            }
+            CanvasEvent::Recoloured { .. } => return Vec::new(),
+        };
+        let mut full_line = false;
+        if self.occupied.insert(position) {
+            for row in rows(position) {
+                let count = self.rows.entry(row).or_default();
+                *count += 1;
+                full_line |= *count >= self.side_len;
+            }
+        }
+        let author = match &metadata.author {
+            Some(author) if metadata.viewer => author,
+            _ => return Vec::new(),
+        };
+        let builder = self.builders.entry(author.clone()).or_default();
+        builder.placed += 1;
+        match builder.last_day {
+            Some(last_day) if last_day == day => {}
+            Some(last_day) if last_day.succ_opt() == Some(day) => builder.streak += 1,
+            _ => builder.streak = 1,
+        }
+        builder.last_day = Some(day);
+        let reached = [
+            (Achievement::FirstCube, true),
+            (Achievement::Cubes, builder.placed >= self.cubes),
+            (Achievement::Streak, builder.streak >= self.streak_days),
+            (Achievement::FullLine, full_line),
+        ];
+        reached
+            .iter()
+            .filter(|(_, reached)| *reached)
+            .map(|(achievement, _)| *achievement)
+            .filter(|achievement| builder.unlocked.insert(*achievement))
+            .collect()
+    }
+}
+
+// Rows of the canvas going through `position`, along each axis.
+fn rows(position: Position) -> [(usize, u32, u32); 3] {
+    let Position { x, y, z } = position;
+    [(0, y, z), (1, x, z), (2, x, y)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colour, Cube};
+
+    fn placed(x: u32, y: u32) -> CanvasEvent {
+        CanvasEvent::CubePlaced(Cube::new(x, y, 0, Colour::new(255, 0, 0)))
+    }
+
+    fn by(author: &str) -> EventMetadata {
+        EventMetadata {
+            author: Some(author.to_owned()),
+            viewer: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_achievements() {
+        assert!(AchievementTracker::new(&AchievementConfig::default(), 3).is_none());
+        let config = AchievementConfig {
+            enabled: true,
+            cubes: 3,
+            streak_days: 3,
+        };
+        let mut tracker = AchievementTracker::new(&config, 3).unwrap();
+        let day = NaiveDate::from_ymd_opt(2021, 3, 10).unwrap();
+        let next_day = |day: NaiveDate| day.succ_opt().unwrap();
+
+        tracker.restore("bob", Achievement::FirstCube);
+        assert_eq!(
+            tracker.apply(&placed(0, 0), &by("alice"), day),
+            vec![Achievement::FirstCube]
+        );
+        assert!(tracker.apply(&placed(0, 1), &by("bob"), day).is_empty());
+        // Placed again where there's a cube, it doesn't count towards a row.
+        assert_eq!(tracker.apply(&placed(0, 1), &by("alice"), day), Vec::new());
+        assert_eq!(
+            tracker.apply(&placed(0, 2), &by("alice"), next_day(day)),
+            vec![Achievement::Cubes, Achievement::FullLine]
+        );
+        // Filling the row again unlocks it for whoever does.
+        tracker.apply(
+            &CanvasEvent::CubeRemoved(Position::new(0, 1, 0)),
+            &by("bob"),
+            day,
+        );
+        assert_eq!(
+            tracker.apply(&placed(0, 1), &by("bob"), day),
+            vec![Achievement::FullLine]
+        );
+        assert_eq!(
+            tracker.apply(&placed(1, 0), &by("alice"), next_day(next_day(day))),
+            vec![Achievement::Streak]
+        );
+        assert_eq!(
+            tracker.unlocked("alice"),
+            vec![
+                Achievement::FirstCube,
+                Achievement::Cubes,
+                Achievement::Streak,
+                Achievement::FullLine
+            ]
+        );
+        assert_eq!(
+            tracker.unlocked("bob"),
+            vec![Achievement::FirstCube, Achievement::FullLine]
+        );
+        assert!(tracker.unlocked("carol").is_empty());
+        // Restored from a checkpoint, as placed by the bot.
+        let restored = EventMetadata {
+            viewer: false,
+            ..by("carol")
+        };
+        assert!(tracker.apply(&placed(1, 1), &restored, day).is_empty());
+        assert!(tracker.unlocked("carol").is_empty());
+
+        // A day skipped starts the streak over.
+        tracker.apply(&placed(2, 2), &by("bob"), next_day(day));
+        tracker.apply(&placed(2, 1), &by("bob"), next_day(next_day(next_day(day))));
+        assert!(!tracker.unlocked("bob").contains(&Achievement::Streak));
+        assert_eq!("full_line".parse(), Ok(Achievement::FullLine));
+        assert!("rainbow".parse::<Achievement>().is_err());
+    }
+}
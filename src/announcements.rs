@@ -0,0 +1,422 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Display;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum AnnouncementError {
+    #[error("there's no announcement called {0}")]
+    UnknownAnnouncement(String),
+    #[error("the {announcement} announcement has no {{{variable}}}, only {known}")]
+    UnknownVariable {
+        announcement: String,
+        variable: String,
+        known: String,
+    },
+    #[error("the {0} announcement has a {{ which isn't closed, write {{{{ for a brace")]
+    Unclosed(String),
+}
+
+// Announcements of the bot, the variables of their templates, and their
+// default templates.
+const DEFAULTS: &[(&str, &[&str], &str)] = &[
+    (
+        "vote_started",
+        &["user", "action", "seconds"],
+        "{user} started a vote to {action}! Type !vote clear, !vote lock, !vote unlock or !vote theme <name> in the next {seconds} seconds.",
+    ),
+    (
+        "vote_won",
+        &["action", "tally"],
+        "The vote is over, chat chose to {action}! ({tally})",
+    ),
+    (
+        "vote_tied",
+        &["tally"],
+        "The vote is over, no winner this time. ({tally})",
+    ),
+    (
+        "today",
+        &["user", "url"],
+        "@{user} here's what happened on the canvas today: {url}",
+    ),
+    (
+        "canvas_busy",
+        &["user", "x", "y", "z"],
+        "@{user} the canvas is busy, your cube was not placed. Try again in a bit!",
+    ),
+    ("alias_saved", &["name", "body"], "!{name} now does: {body}"),
+    ("alias_removed", &["name"], "!{name} is no more"),
+    (
+        "checkpoint_saved",
+        &["name", "count"],
+        "Checkpoint {name} saved, {count} cubes.",
+    ),
+    (
+        "checkpoint_restored",
+        &["name", "count"],
+        "The canvas is back to checkpoint {name}, {count} cubes.",
+    ),
+    (
+        "checkpoint_unknown",
+        &["name", "checkpoints"],
+        "There's no checkpoint {name}. Saved: {checkpoints}",
+    ),
+    ("user_ignored", &["user"], "{user} can't place cubes anymore."),
+    ("user_unignored", &["user"], "{user} can place cubes again."),
+    ("user_not_ignored", &["user"], "{user} isn't ignored."),
+    (
+        "region_protected",
+        &["region"],
+        "The cubes at {region} are protected, chat can't change them anymore.",
+    ),
+    (
+        "region_unprotected",
+        &["count"],
+        "Lifted the protection of {count} regions.",
+    ),
+    (
+        "region_not_protected",
+        &["region"],
+        "Nothing is protected at {region}.",
+    ),
+    (
+        "cubes_restored",
+        &["user", "count"],
+        "Restored {count} cubes of {user}.",
+    ),
+    (
+        "competition_running",
+        &["name"],
+        "\"{name}\" is still running!",
+    ),
+    (
+        "competition_started",
+        &["name", "minutes"],
+        "The build competition \"{name}\" starts now and lasts {minutes} minutes, every cube counts!",
+    ),
+    (
+        "competition_empty",
+        &["name"],
+        "\"{name}\" is over, nobody placed a cube this time!",
+    ),
+    (
+        "competition_over",
+        &["name", "count", "winners"],
+        "\"{name}\" is over! {count} builders took part, top builders: {winners}",
+    ),
+    (
+        "team_unknown",
+        &["user", "teams"],
+        "@{user} pick one of the teams: {teams}",
+    ),
+    ("team_joined", &["user", "team"], "@{user} joined team {team}!"),
+    (
+        "stats",
+        &["fill", "count", "builders", "last_hour"],
+        "The canvas is {fill}% full with {count} cubes, {builders} builders today and {last_hour} cubes placed in the last hour. Add yours with x y z r g b!",
+    ),
+    (
+        "stage_unlocked",
+        &["side", "count"],
+        "New area unlocked! The middle {side}x{side}x{side} of the canvas is open for building. {count} more cubes to unlock the next one!",
+    ),
+    (
+        "last_stage_unlocked",
+        &["side"],
+        "New area unlocked! The middle {side}x{side}x{side} of the canvas is open for building.",
+    ),
+    (
+        "palette",
+        &["colours"],
+        "New palette! Cubes take the closest of these colours: {colours}",
+    ),
+    (
+        "palette_lifted",
+        &[],
+        "The palette is lifted, every colour is allowed again.",
+    ),
+    (
+        "build_sheet",
+        &["url"],
+        "Let's build this together! Here's the colour of each cube: {url}",
+    ),
+    (
+        "exports",
+        &["schematic", "goxel", "qubicle"],
+        "Take the canvas to Minecraft with WorldEdit: {schematic}, or to Goxel: {goxel} and Qubicle: {qubicle}",
+    ),
+    (
+        "credits",
+        &["url"],
+        "Thanks to everyone who built with us! Roll the credits: {url}",
+    ),
+    ("resynced", &[], "Rebuilt the overlay from the archive."),
+    (
+        "terrain_generated",
+        &["seed"],
+        "Here's some terrain to build on! Seed {seed}, to get it back with !terrain {seed}.",
+    ),
+    (
+        "cube_placed",
+        &["user", "x", "y", "z"],
+        "@{user} your cube is at {x} {y} {z}!",
+    ),
+    (
+        "cubes_placed",
+        &["count", "users", "seconds"],
+        "Placed {count} cubes for {users} builders in the last {seconds}s!",
+    ),
+    (
+        "edits_queued",
+        &["seconds"],
+        "Chat is changing the canvas too fast! New cubes wait their turn, the next one goes in {seconds}s.",
+    ),
+    (
+        "edits_rejected",
+        &["seconds"],
+        "Chat is changing the canvas too fast! Cubes are dropped, try again in {seconds}s.",
+    ),
+    (
+        "shoutout",
+        &["user", "count"],
+        "Go check out {user} at https://twitch.tv/{user} ! Their {count} cubes are lit up on the canvas.",
+    ),
+    (
+        "goal",
+        &["placed", "target", "percent"],
+        "Today's goal: {placed}/{target} cubes placed, {percent}% there!",
+    ),
+    ("no_goal", &[], "There's no goal today, build for fun!"),
+    ("goal_set", &["target"], "New goal: {target} cubes placed today!"),
+    (
+        "goal_reached",
+        &["target"],
+        "Goal reached! Chat placed {target} cubes today, thank you all!",
+    ),
+    (
+        "colours",
+        &["top", "share", "count", "colours"],
+        "Chat's favourite colour today is {top}, {share}% of the {count} cubes placed. Top colours: {colours}",
+    ),
+    ("no_colours", &[], "No cube placed today yet, be the first!"),
+    (
+        "achievement_first_cube",
+        &["user"],
+        "Welcome to the canvas @{user}! Achievement unlocked: first cube",
+    ),
+    (
+        "achievement_cubes",
+        &["user", "count"],
+        "@{user} placed {count} cubes! Achievement unlocked: {count} cubes",
+    ),
+    (
+        "achievement_streak",
+        &["user", "days"],
+        "@{user} built {days} days in a row! Achievement unlocked: {days}-day streak",
+    ),
+    (
+        "achievement_full_line",
+        &["user"],
+        "@{user} filled a whole row of the canvas! Achievement unlocked: full line",
+    ),
+    (
+        "achievements",
+        &["user", "achievements"],
+        "@{user} unlocked: {achievements}",
+    ),
+    (
+        "no_achievements",
+        &["user"],
+        "@{user} has no achievement yet, place a cube to get the first one!",
+    ),
+    (
+        "summary",
+        &["period", "count", "builders", "top"],
+        "The canvas this past {period}: {count} cubes placed by {builders} builders. Top builders: {top}",
+    ),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Text(String),
+    Variable(String),
+}
+
+/// What the bot says in chat, from templates in which `{variable}`s are
+/// filled in, e.g. `@{user} joined team {team}!`. `{{` and `}}` write braces.
+/// The configured templates replace the default ones, and are checked when
+/// the configuration is read.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "HashMap<String, String>")]
+pub struct Announcements {
+    templates: HashMap<String, Vec<Segment>>,
+}
+
+impl Default for Announcements {
+    fn default() -> Self {
+        Self::new(&HashMap::new()).expect("the default templates are valid")
+    }
+}
+
+impl TryFrom<HashMap<String, String>> for Announcements {
+    type Error = AnnouncementError;
+
+    fn try_from(templates: HashMap<String, String>) -> Result<Self, Self::Error> {
+        Self::new(&templates)
+    }
+}
+
+impl Announcements {
+    /// Checks the `templates` keyed by announcement, the others keep their
+    /// default.
+    pub fn new(templates: &HashMap<String, String>) -> Result<Self, AnnouncementError> {
+        if let Some(unknown) = templates
+            .keys()
+            .find(|name| DEFAULTS.iter().all(|(known, _, _)| known != name))
+        {
+            return Err(AnnouncementError::UnknownAnnouncement(unknown.clone()));
+        }
+        let mut parsed = HashMap::new();
+        for (name, variables, default) in DEFAULTS {
+            let template = templates.get(*name).map_or(*default, String::as_str);
+            let segments =
+                parse(template).ok_or_else(|| AnnouncementError::Unclosed(name.to_string()))?;
+            for segment in &segments {
+                match segment {
+                    Segment::Variable(variable) if !variables.contains(&variable.as_str()) => {
+                        let known = match variables.is_empty() {
+                            true => "plain text".to_owned(),
+                            false => format!("{{{}}}", variables.join("}, {")),
+                        };
+                        return Err(AnnouncementError::UnknownVariable {
+                            announcement: name.to_string(),
+                            variable: variable.clone(),
+                            known,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            parsed.insert(name.to_string(), segments);
+        }
+        Ok(Self { templates: parsed })
+    }
+
+    /// The `name` announcement, with its variables filled in from
+    /// `variables`.
+    pub fn format(&self, name: &str, variables: &[(&str, &dyn Display)]) -> String {
+        let segments = match self.templates.get(name) {
+            Some(segments) => segments,
+            None => return String::new(),
+        };
+        segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.clone(),
+                Segment::Variable(variable) => variables
+                    .iter()
+                    .find(|(name, _)| name == variable)
+                    .map(|(_, value)| value.to_string())
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+// Splits a template into text and variables, `None` when a brace isn't
+// closed.
+fn parse(template: &str) -> Option<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut variable = String::new();
+                loop {
+                    match chars.next()? {
+                        '}' => break,
+                        c => variable.push(c),
+                    }
+                }
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(Segment::Variable(variable.trim().to_owned()));
+            }
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Some(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn templates(name: &str, template: &str) -> HashMap<String, String> {
+        let mut templates = HashMap::new();
+        templates.insert(name.to_owned(), template.to_owned());
+        templates
+    }
+
+    #[test]
+    fn test_announcements() {
+        let announcements = Announcements::default();
+        assert_eq!(
+            announcements.format("team_joined", &[("user", &"bob"), ("team", &"Red")]),
+            "@bob joined team Red!"
+        );
+
+        let announcements =
+            Announcements::new(&templates("team_joined", "{{ {user} }} is {team} now")).unwrap();
+        assert_eq!(
+            announcements.format("team_joined", &[("user", &"bob"), ("team", &"Red")]),
+            "{ bob } is Red now"
+        );
+        assert_eq!(
+            announcements.format("cubes_restored", &[("user", &"bob"), ("count", &3)]),
+            "Restored 3 cubes of bob."
+        );
+
+        assert_eq!(
+            Announcements::new(&templates("team_left", "bye")),
+            Err(AnnouncementError::UnknownAnnouncement(
+                "team_left".to_owned()
+            ))
+        );
+        assert_eq!(
+            Announcements::new(&templates("team_joined", "{user} joined {colour}")),
+            Err(AnnouncementError::UnknownVariable {
+                announcement: "team_joined".to_owned(),
+                variable: "colour".to_owned(),
+                known: "{user}, {team}".to_owned(),
+            })
+        );
+        assert_eq!(
+            Announcements::new(&templates("resynced", "{user} resynced")),
+            Err(AnnouncementError::UnknownVariable {
+                announcement: "resynced".to_owned(),
+                variable: "user".to_owned(),
+                known: "plain text".to_owned(),
+            })
+        );
+        assert_eq!(
+            Announcements::new(&templates("team_joined", "@{user joined")),
+            Err(AnnouncementError::Unclosed("team_joined".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use thiserror::Error;
+
+/// Who a request to the API comes from, by the token it carries. Each role
+/// can do what the ones before it can.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Requests without a known token.
+    Anyone,
+    Viewer,
+    Moderator,
+    Admin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Role::Anyone => "anyone",
+            Role::Viewer => "viewer",
+            Role::Moderator => "moderator",
+            Role::Admin => "admin",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What a route of the API does, which sets the role it needs unless its
+/// path is configured.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    /// Reads the canvas, e.g. the statistics, the cubes or the timelapse.
+    Read,
+    /// Downloads the canvas exported for voxel editors and Minecraft, or its
+    /// octree.
+    Export,
+    /// Changes the canvas or the bot, e.g. imports a model or sets the
+    /// palette.
+    Mutation,
+    /// Renders a snapshot or a timelapse on demand.
+    Render,
+    /// Lists the recent requests to the API, for admins unless its path is
+    /// configured.
+    Audit,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct AccessConfig {
+    /// Tokens granting each role, passed as `Authorization: Bearer <token>`
+    /// over HTTP and in the `token` field over gRPC.
+    pub viewer_tokens: Vec<String>,
+    pub moderator_tokens: Vec<String>,
+    pub admin_tokens: Vec<String>,
+    /// Role needed by the routes of each kind.
+    pub reads: Role,
+    pub exports: Role,
+    pub mutations: Role,
+    pub renders: Role,
+    /// Role needed by the routes at these paths, whatever they do, e.g.
+    /// `'/api/history' = 'moderator'`.
+    pub routes: HashMap<String, Role>,
+}
+
+impl Default for AccessConfig {
+    fn default() -> Self {
+        Self {
+            viewer_tokens: Vec::new(),
+            moderator_tokens: Vec::new(),
+            admin_tokens: Vec::new(),
+            reads: Role::Anyone,
+            exports: Role::Anyone,
+            mutations: Role::Admin,
+            renders: Role::Admin,
+            routes: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum AccessError {
+    #[error("an API token can't be empty")]
+    EmptyToken,
+    #[error("an API token is given to both the {0} and {1} roles")]
+    DuplicateToken(Role, Role),
+    #[error("invalid API route {0}, expected a path like /api/history")]
+    InvalidRoute(String),
+}
+
+/// Tells which requests to the API are allowed, from the roles their tokens
+/// grant and those the routes need.
+#[derive(Clone, Debug)]
+pub struct ApiAccess {
+    tokens: HashMap<String, Role>,
+    config: AccessConfig,
+}
+
+impl ApiAccess {
+    pub fn new(config: &AccessConfig) -> Result<Self, AccessError> {
+        let mut tokens = HashMap::new();
+        let roles = [
+            (Role::Viewer, &config.viewer_tokens),
+            (Role::Moderator, &config.moderator_tokens),
+            (Role::Admin, &config.admin_tokens),
+        ];
+        for (role, role_tokens) in roles.iter() {
+            for token in role_tokens.iter() {
+                if token.is_empty() {
+                    return Err(AccessError::EmptyToken);
+                }
+                if let Some(other) = tokens.insert(token.clone(), *role) {
+                    return Err(AccessError::DuplicateToken(other, *role));
+                }
+            }
+        }
+        // gRPC routes are named after their method, e.g. `Render`.
+        let invalid = config
+            .routes
+            .keys()
+            .find(|path| path.is_empty() || path.contains(char::is_whitespace));
+        if let Some(path) = invalid {
+            return Err(AccessError::InvalidRoute(path.clone()));
+        }
+        Ok(Self {
+            tokens,
+            config: config.clone(),
+        })
+    }
+
+    /// Role `token` grants.
+    pub fn role(&self, token: &str) -> Role {
+        self.tokens.get(token).copied().unwrap_or(Role::Anyone)
+    }
+
+    /// Role needed by the route at `path`, doing what `permission` is for.
+    pub fn required(&self, path: &str, permission: Permission) -> Role {
+        if let Some(role) = self.config.routes.get(path) {
+            return *role;
+        }
+        match permission {
+            Permission::Read => self.config.reads,
+            Permission::Export => self.config.exports,
+            Permission::Mutation => self.config.mutations,
+            Permission::Render => self.config.renders,
+            Permission::Audit => Role::Admin,
+        }
+    }
+
+    /// Whether a request carrying `token` can use the route at `path`.
+    pub fn allows(&self, token: &str, path: &str, permission: Permission) -> bool {
+        self.role(token) >= self.required(path, permission)
+    }
+}
+
+impl Default for ApiAccess {
+    fn default() -> Self {
+        Self::new(&AccessConfig::default()).expect("Invalid default API access")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access() {
+        let access = ApiAccess::default();
+        assert!(access.allows("", "/api/cubes", Permission::Read));
+        assert!(access.allows("", "/twixelbox.qb", Permission::Export));
+        assert!(!access.allows("", "/api/import", Permission::Mutation));
+
+        let config = AccessConfig {
+            viewer_tokens: vec!["v".to_owned()],
+            moderator_tokens: vec!["m".to_owned()],
+            admin_tokens: vec!["a".to_owned()],
+            reads: Role::Viewer,
+            exports: Role::Moderator,
+            routes: vec![
+                ("/api/history".to_owned(), Role::Moderator),
+                ("/api/palette".to_owned(), Role::Moderator),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let access = ApiAccess::new(&config).unwrap();
+        assert_eq!(access.role("m"), Role::Moderator);
+        assert_eq!(access.role("x"), Role::Anyone);
+        assert!(!access.allows("", "/api/cubes", Permission::Read));
+        assert!(access.allows("v", "/api/cubes", Permission::Read));
+        assert!(!access.allows("v", "/api/history", Permission::Read));
+        assert!(access.allows("a", "/api/history", Permission::Read));
+        assert!(!access.allows("v", "/twixelbox.qb", Permission::Export));
+        assert!(access.allows("m", "/twixelbox.qb", Permission::Export));
+        assert!(!access.allows("m", "/api/import", Permission::Mutation));
+        assert!(access.allows("m", "/api/palette", Permission::Mutation));
+        assert!(access.allows("a", "Render", Permission::Render));
+        assert!(!access.allows("m", "/api/activity", Permission::Audit));
+
+        let mut invalid = config.clone();
+        invalid.admin_tokens.push("v".to_owned());
+        assert_eq!(
+            ApiAccess::new(&invalid).unwrap_err(),
+            AccessError::DuplicateToken(Role::Viewer, Role::Admin)
+        );
+        let mut invalid = config.clone();
+        invalid.viewer_tokens.push(String::new());
+        assert_eq!(
+            ApiAccess::new(&invalid).unwrap_err(),
+            AccessError::EmptyToken
+        );
+        let mut invalid = config;
+        invalid
+            .routes
+            .insert("/api/ history".to_owned(), Role::Admin);
+        assert!(ApiAccess::new(&invalid).is_err());
+    }
+}
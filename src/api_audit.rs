@@ -0,0 +1,398 @@
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// File the requests changing something through the API are appended
+    /// to, as JSON lines, only kept in memory when unset.
+    pub filepath: Option<String>,
+    /// Requests listed by the activity endpoint, the most recent since the
+    /// start.
+    pub recent: usize,
+    /// Secret the requests carrying each token are signed with, by token.
+    /// It's never sent, unlike the token.
+    pub signing_secrets: HashMap<String, String>,
+    /// Refuses the requests changing something that aren't signed, which
+    /// rules out the tokens without a signing secret, e.g. web logins.
+    pub require_signatures: bool,
+    /// Seconds a signed request is accepted for around its timestamp, a
+    /// replay within them is refused.
+    pub max_age_secs: u64,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            filepath: None,
+            recent: 200,
+            signing_secrets: HashMap::new(),
+            require_signatures: false,
+            max_age_secs: 300,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AuditError {
+    #[error("error from the API audit log {0}")]
+    Io(#[from] std::io::Error),
+    #[error("error from serde_json {0}")]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("a signing secret can't be empty nor the token it signs for")]
+    InvalidSecret,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SignatureError {
+    #[error("the request must be signed")]
+    Missing,
+    #[error("no signing secret for this token")]
+    NoSecret,
+    #[error("invalid signature")]
+    Invalid,
+    #[error("the signature expired, check the clock")]
+    Expired,
+    #[error("the request was already made")]
+    Replayed,
+}
+
+/// Request changing something through the API, as audited.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ApiActivity {
+    /// Unix timestamp in seconds.
+    pub timestamp: i64,
+    pub path: String,
+    /// Who made it: the chatter logged in, or the role of its token and a
+    /// fingerprint of it.
+    pub identity: String,
+    /// SHA-256 of the path, query and body, in hex. None when refused
+    /// before reading the body.
+    pub request_hash: Option<String>,
+    pub signed: bool,
+    /// HTTP status it was answered with.
+    pub status: u16,
+}
+
+/// Signature of a request, as passed in the `X-Twixelbox-Timestamp` and
+/// `X-Twixelbox-Signature` headers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestSignature {
+    /// Unix timestamp in seconds the request was signed at.
+    pub timestamp: i64,
+    /// HMAC-SHA256 of `<timestamp>\n<request hash>` keyed with the signing
+    /// secret of the token of the request, in hex.
+    pub signature: String,
+}
+
+/// SHA-256 of a request, in hex, which its signature covers.
+pub fn request_hash(path: &str, query: &str, body: &[u8]) -> String {
+    let mut request = format!("{}\n{}\n", path, query).into_bytes();
+    request.extend_from_slice(body);
+    hex(&openssl::sha::sha256(&request))
+}
+
+/// Signature of the request hashed as `request_hash`, made with the signing
+/// secret `secret` at the unix timestamp `timestamp`, in hex.
+pub fn sign_request(secret: &str, timestamp: i64, request_hash: &str) -> String {
+    hex(&hmac(
+        secret.as_bytes(),
+        format!("{}\n{}", timestamp, request_hash).as_bytes(),
+    ))
+}
+
+/// Short fingerprint of `token`, telling tokens apart in the audit without
+/// revealing them.
+pub fn token_fingerprint(token: &str) -> String {
+    hex(&openssl::sha::sha256(token.as_bytes())[..4])
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = PKey::hmac(key).expect("Unable to make an HMAC key");
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).expect("Unable to sign");
+    signer.update(data).expect("Unable to sign");
+    signer.sign_to_vec().expect("Unable to sign")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+struct AuditLog {
+    recent: VecDeque<ApiActivity>,
+    // Signatures accepted, and when they expire.
+    seen: HashMap<String, i64>,
+    file: Option<File>,
+}
+
+/// Audit of the requests changing something through the API, who made them
+/// and how they were answered, and the guard against replayed signed
+/// requests. Cloning gives another handle to the same audit.
+#[derive(Clone)]
+pub struct ApiAudit {
+    recent: usize,
+    signing_secrets: HashMap<String, String>,
+    require_signatures: bool,
+    max_age_secs: i64,
+    log: Arc<Mutex<AuditLog>>,
+}
+
+impl ApiAudit {
+    /// Appends to the file, if any, without reading it: the recent requests
+    /// start empty.
+    pub fn new(config: &AuditConfig) -> Result<Self, AuditError> {
+        let invalid = config
+            .signing_secrets
+            .iter()
+            .any(|(token, secret)| secret.is_empty() || secret == token);
+        if invalid {
+            return Err(AuditError::InvalidSecret);
+        }
+        let file = match &config.filepath {
+            Some(filepath) => {
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .read(true)
+                    .append(true)
+                    .open(filepath)?;
+                // A last line cut short, e.g. by a crash, is ended so that
+                // the next one doesn't run into it.
+                let len = file.metadata()?.len();
+                if len > 0 {
+                    let mut last = [0];
+                    file.seek(SeekFrom::Start(len - 1))?;
+                    file.read_exact(&mut last)?;
+                    if last != *b"\n" {
+                        file.write_all(b"\n")?;
+                    }
+                }
+                Some(file)
+            }
+            None => None,
+        };
+        Ok(Self {
+            recent: config.recent,
+            signing_secrets: config.signing_secrets.clone(),
+            require_signatures: config.require_signatures,
+            max_age_secs: config.max_age_secs as i64,
+            log: Arc::new(Mutex::new(AuditLog {
+                recent: VecDeque::new(),
+                seen: HashMap::new(),
+                file,
+            })),
+        })
+    }
+
+    /// Checks the `signature` of the request hashed as `request_hash`,
+    /// carrying `token`, at the unix timestamp `now`, against the signing
+    /// secret of the token. Tells whether it's signed.
+    pub fn verify(
+        &self,
+        token: &str,
+        request_hash: &str,
+        signature: Option<&RequestSignature>,
+        now: i64,
+    ) -> Result<bool, SignatureError> {
+        let signature = match signature {
+            Some(signature) => signature,
+            None if self.require_signatures => return Err(SignatureError::Missing),
+            None => return Ok(false),
+        };
+        let secret = self
+            .signing_secrets
+            .get(token)
+            .ok_or(SignatureError::NoSecret)?;
+        let expected = sign_request(secret, signature.timestamp, request_hash);
+        let matches = signature.signature.len() == expected.len()
+            && openssl::memcmp::eq(
+                signature.signature.to_lowercase().as_bytes(),
+                expected.as_bytes(),
+            );
+        if !matches {
+            return Err(SignatureError::Invalid);
+        }
+        if (now - signature.timestamp).abs() > self.max_age_secs {
+            return Err(SignatureError::Expired);
+        }
+        let mut log = self.log.lock().unwrap();
+        log.seen.retain(|_, expires_at| *expires_at >= now);
+        let expires_at = signature.timestamp + self.max_age_secs;
+        if log.seen.insert(expected, expires_at).is_some() {
+            return Err(SignatureError::Replayed);
+        }
+        Ok(true)
+    }
+
+    /// Adds `activity` to the audit. Failing to write it down is only
+    /// reported.
+    pub fn record(&self, activity: ApiActivity) {
+        let mut log = self.log.lock().unwrap();
+        if let Some(file) = log.file.as_mut() {
+            let written = serde_json::to_vec(&activity)
+                .map_err(AuditError::from)
+                .and_then(|mut line| {
+                    line.push(b'\n');
+                    Ok(file.write_all(&line)?)
+                });
+            if let Err(e) = written {
+                eprintln!("Unable to audit the API request: {}", e);
+            }
+        }
+        log.recent.push_back(activity);
+        if log.recent.len() > self.recent {
+            log.recent.pop_front();
+        }
+    }
+
+    /// The last `limit` requests, the most recent first.
+    pub fn recent(&self, limit: usize) -> Vec<ApiActivity> {
+        let log = self.log.lock().unwrap();
+        log.recent.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+impl Default for ApiAudit {
+    fn default() -> Self {
+        Self::new(&AuditConfig::default()).expect("Unable to audit in memory")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(path: &str, status: u16) -> ApiActivity {
+        ApiActivity {
+            timestamp: 1_600_000_000,
+            path: path.to_owned(),
+            identity: "admin 1a2b3c4d".to_owned(),
+            request_hash: Some(request_hash(path, "", b"")),
+            signed: false,
+            status,
+        }
+    }
+
+    #[test]
+    fn test_signatures() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            hex(&hmac(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            request_hash("/api/place", "", b"1 2 3 red"),
+            hex(&openssl::sha::sha256(b"/api/place\n\n1 2 3 red"))
+        );
+        assert_eq!(token_fingerprint("secret").len(), 8);
+
+        let mut config = AuditConfig {
+            signing_secrets: vec![
+                ("token".to_owned(), "secret".to_owned()),
+                ("other".to_owned(), "another secret".to_owned()),
+            ]
+            .into_iter()
+            .collect(),
+            require_signatures: true,
+            max_age_secs: 60,
+            ..Default::default()
+        };
+        let audit = ApiAudit::new(&config).unwrap();
+        let now = 1_600_000_000;
+        let hash = request_hash("/api/import", "x=1", b"model");
+        let signed_with = |secret: &str, timestamp: i64| RequestSignature {
+            timestamp,
+            signature: sign_request(secret, timestamp, &hash),
+        };
+        let signed = |timestamp: i64| signed_with("secret", timestamp);
+        assert_eq!(
+            audit.verify("token", &hash, None, now),
+            Err(SignatureError::Missing)
+        );
+        assert_eq!(
+            audit.verify("other", &hash, Some(&signed(now)), now),
+            Err(SignatureError::Invalid)
+        );
+        // The token, which goes with the request, doesn't sign it.
+        assert_eq!(
+            audit.verify("token", &hash, Some(&signed_with("token", now)), now),
+            Err(SignatureError::Invalid)
+        );
+        assert_eq!(
+            audit.verify("session", &hash, Some(&signed(now)), now),
+            Err(SignatureError::NoSecret)
+        );
+        assert_eq!(
+            audit.verify("token", &hash, Some(&signed(now - 61)), now),
+            Err(SignatureError::Expired)
+        );
+        assert_eq!(
+            audit.verify("token", &hash, Some(&signed(now)), now + 5),
+            Ok(true)
+        );
+        assert_eq!(
+            audit.verify("token", &hash, Some(&signed(now)), now + 10),
+            Err(SignatureError::Replayed)
+        );
+        // The same request, signed again.
+        assert_eq!(
+            audit.verify("token", &hash, Some(&signed(now + 1)), now + 10),
+            Ok(true)
+        );
+        assert_eq!(ApiAudit::default().verify("", &hash, None, now), Ok(false));
+
+        config
+            .signing_secrets
+            .insert("same".to_owned(), "same".to_owned());
+        assert!(matches!(
+            ApiAudit::new(&config),
+            Err(AuditError::InvalidSecret)
+        ));
+    }
+
+    #[test]
+    fn test_recent() {
+        let dir = tempfile::tempdir().unwrap();
+        let filepath = dir.path().join("audit.jsonl");
+        let config = AuditConfig {
+            filepath: Some(filepath.to_string_lossy().into_owned()),
+            recent: 2,
+            ..Default::default()
+        };
+        let audit = ApiAudit::new(&config).unwrap();
+        audit.record(activity("/api/palette", 200));
+        audit.record(activity("/api/import", 401));
+        audit.record(activity("/api/place", 200));
+        let recent = audit.recent(10);
+        assert_eq!(
+            recent,
+            vec![activity("/api/place", 200), activity("/api/import", 401)]
+        );
+        assert_eq!(audit.recent(1), recent[..1].to_vec());
+
+        // Appended to after a truncated line, which is left on its own.
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&filepath)
+            .unwrap()
+            .write_all(b"{\"timestamp\":")
+            .unwrap();
+        let audit = ApiAudit::new(&config).unwrap();
+        assert!(audit.recent(10).is_empty());
+        audit.record(activity("/api/palette", 403));
+        let lines = std::fs::read_to_string(&filepath).unwrap();
+        let lines: Vec<&str> = lines.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[3], "{\"timestamp\":");
+        assert_eq!(
+            serde_json::from_str::<ApiActivity>(lines[4]).unwrap(),
+            activity("/api/palette", 403)
+        );
+    }
+}
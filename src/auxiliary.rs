@@ -0,0 +1,133 @@
+use crate::Position;
+use image::{DynamicImage, GrayImage, RgbImage};
+use serde::Deserialize;
+
+/// Buffer rendered alongside the colour of a snapshot, for compositing
+/// elsewhere, e.g. outlines or relighting in OBS filters.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuxiliaryBuffer {
+    /// Grey levels from white for the closest cube to dark grey for the
+    /// furthest, black for the background.
+    Depth,
+    /// Direction each face looks at, on the grid of the canvas with y growing
+    /// downwards, its axes from [-1, 1] mapped to the channels' [0, 255].
+    /// Black for the background.
+    Normals,
+    /// The cube seen in each pixel, see `cube_id`.
+    CubeIds,
+}
+
+impl AuxiliaryBuffer {
+    /// Name of the buffer, in its file name and URL.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuxiliaryBuffer::Depth => "depth",
+            AuxiliaryBuffer::Normals => "normals",
+            AuxiliaryBuffer::CubeIds => "cube-ids",
+        }
+    }
+}
+
+/// Id of the cube at `position` of a canvas of side `side_len`, written in
+/// the cube id map as a big endian 24 bits colour. 0 is the background.
+pub fn cube_id(position: Position, side_len: u32) -> u32 {
+    let side = side_len as u64;
+    let id = 1 + position.x as u64 + side * (position.y as u64 + side * position.z as u64);
+    id.min(0xff_ffff) as u32
+}
+
+/// Position of the cube of id `id`, the inverse of `cube_id`, `None` for the
+/// background.
+pub fn cube_at(id: u32, side_len: u32) -> Option<Position> {
+    let side = side_len.max(1);
+    let index = id.checked_sub(1)?;
+    Some(Position::new(
+        index % side,
+        index / side % side,
+        index / side / side,
+    ))
+}
+
+/// What the ray through the centre of each pixel hits first, row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuxiliaryBuffers {
+    pub size: u32,
+    pub side_len: u32,
+    /// Distance from the camera, infinite for the background.
+    pub depth: Vec<f32>,
+    pub normals: Vec<[f32; 3]>,
+    pub cubes: Vec<Option<Position>>,
+}
+
+impl AuxiliaryBuffers {
+    pub fn image(&self, buffer: AuxiliaryBuffer) -> DynamicImage {
+        match buffer {
+            AuxiliaryBuffer::Depth => DynamicImage::ImageLuma8(self.depth_image()),
+            AuxiliaryBuffer::Normals => {
+                DynamicImage::ImageRgb8(self.pixels(|i| match self.cubes[i] {
+                    Some(_) => self.normals[i].map(|n| ((n + 1.0) * 127.5).round() as u8),
+                    None => [0; 3],
+                }))
+            }
+            AuxiliaryBuffer::CubeIds => DynamicImage::ImageRgb8(self.pixels(|i| {
+                let id = self.cubes[i].map_or(0, |p| cube_id(p, self.side_len));
+                [(id >> 16) as u8, (id >> 8) as u8, id as u8]
+            })),
+        }
+    }
+
+    fn depth_image(&self) -> GrayImage {
+        let hits = || self.depth.iter().copied().filter(|d| d.is_finite());
+        let near = hits().fold(f32::INFINITY, f32::min);
+        let far = hits().fold(0.0, f32::max);
+        let range = (far - near).max(1e-6);
+        GrayImage::from_fn(self.size, self.size, |x, y| {
+            let d = self.depth[(y * self.size + x) as usize];
+            match d.is_finite() {
+                // The furthest cube stays a bit brighter than the background.
+                true => image::Luma([(255.0 - (d - near) / range * 223.0).round() as u8]),
+                false => image::Luma([0]),
+            }
+        })
+    }
+
+    fn pixels(&self, pixel: impl Fn(usize) -> [u8; 3]) -> RgbImage {
+        RgbImage::from_fn(self.size, self.size, |x, y| {
+            image::Rgb(pixel((y * self.size + x) as usize))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auxiliary_buffers() {
+        let position = Position::new(3, 1, 2);
+        assert_eq!(cube_id(position, 4), 1 + 3 + 4 * (1 + 4 * 2));
+        assert_eq!(cube_at(cube_id(position, 4), 4), Some(position));
+        assert_eq!(cube_at(0, 4), None);
+
+        let buffers = AuxiliaryBuffers {
+            size: 2,
+            side_len: 4,
+            depth: vec![2.0, 4.0, 3.0, f32::INFINITY],
+            normals: vec![[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0; 3]],
+            cubes: vec![Some(position), Some(position), Some(position), None],
+        };
+        let depth = buffers.image(AuxiliaryBuffer::Depth).into_luma8();
+        assert_eq!(depth.get_pixel(0, 0).0, [255]);
+        assert_eq!(depth.get_pixel(1, 0).0, [32]);
+        assert_eq!(depth.get_pixel(1, 1).0, [0]);
+        let normals = buffers.image(AuxiliaryBuffer::Normals).into_rgb8();
+        assert_eq!(normals.get_pixel(0, 1).0, [128, 0, 128]);
+        assert_eq!(normals.get_pixel(1, 1).0, [0, 0, 0]);
+        let ids = buffers.image(AuxiliaryBuffer::CubeIds).into_rgb8();
+        let [r, g, b] = ids.get_pixel(0, 0).0;
+        let id = (r as u32) << 16 | (g as u32) << 8 | b as u32;
+        assert_eq!(cube_at(id, 4), Some(position));
+        assert_eq!(ids.get_pixel(1, 1).0, [0, 0, 0]);
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{Colour, CubeError};
+use image::RgbImage;
+use serde::Deserialize;
+use std::f32::consts::PI;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// What's drawn behind the canvas, colours by name or hex code as in chat.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum BackgroundConfig {
+    Solid {
+        colour: String,
+    },
+    /// From `top` at the top of the image to `bottom` at its bottom.
+    Gradient {
+        top: String,
+        bottom: String,
+    },
+    /// Equirectangular panorama around the canvas, up at the top of the
+    /// image. Only raytraced images show it, the other renderers are cleared
+    /// with its mean colour.
+    Skybox {
+        filepath: String,
+    },
+    /// Pure green, for OBS to key out.
+    ChromaKey,
+}
+
+impl Default for BackgroundConfig {
+    fn default() -> Self {
+        BackgroundConfig::Solid {
+            colour: "#fafafa".to_owned(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum BackgroundError {
+    #[error("invalid background colour: {0}")]
+    InvalidColour(#[from] CubeError),
+    #[error("unable to read the skybox: {0}")]
+    Image(#[from] image::ImageError),
+}
+
+/// Background set up from its config, with the skybox loaded.
+#[derive(Clone, Debug)]
+pub struct Background {
+    kind: Kind,
+}
+
+#[derive(Clone, Debug)]
+enum Kind {
+    Solid(Colour),
+    Gradient { top: Colour, bottom: Colour },
+    Skybox { image: Arc<RgbImage>, mean: Colour },
+}
+
+const CHROMA_KEY_GREEN: Colour = Colour::new(0, 255, 0);
+
+impl Background {
+    pub fn new(config: &BackgroundConfig) -> Result<Self, BackgroundError> {
+        let kind = match config {
+            BackgroundConfig::Solid { colour } => Kind::Solid(colour.parse()?),
+            BackgroundConfig::Gradient { top, bottom } => Kind::Gradient {
+                top: top.parse()?,
+                bottom: bottom.parse()?,
+            },
+            BackgroundConfig::Skybox { filepath } => {
+                let image = image::open(filepath)?.to_rgb8();
+                let mean = mean_colour(&image);
+                Kind::Skybox {
+                    image: Arc::new(image),
+                    mean,
+                }
+            }
+            BackgroundConfig::ChromaKey => Kind::Solid(CHROMA_KEY_GREEN),
+        };
+        Ok(Self { kind })
+    }
+
+    /// Single colour standing for the background, for the renderers which
+    /// only clear their frame: the middle of a gradient, the mean colour of
+    /// a skybox.
+    pub fn clear_colour(&self) -> Colour {
+        match &self.kind {
+            Kind::Solid(colour) => *colour,
+            Kind::Gradient { .. } => self.at_height(0.5),
+            Kind::Skybox { mean, .. } => *mean,
+        }
+    }
+
+    /// Background of the image at `v`, from 0 at its top to 1 at its bottom,
+    /// whatever the camera looks at.
+    pub fn at_height(&self, v: f32) -> Colour {
+        match &self.kind {
+            Kind::Gradient { top, bottom } => {
+                let v = v.clamp(0.0, 1.0);
+                let mix = |top: u8, bottom: u8| {
+                    (top as f32 + (bottom as f32 - top as f32) * v).round() as u8
+                };
+                Colour::new(
+                    mix(top.r, bottom.r),
+                    mix(top.g, bottom.g),
+                    mix(top.b, bottom.b),
+                )
+            }
+            _ => self.clear_colour(),
+        }
+    }
+
+    /// Background seen along `dir`, on the grid of the canvas with y growing
+    /// downwards, at `v` of the image as in `at_height`.
+    pub fn in_direction(&self, dir: [f32; 3], v: f32) -> Colour {
+        let image = match &self.kind {
+            Kind::Skybox { image, .. } => image,
+            _ => return self.at_height(v),
+        };
+        let length = (dir[0] * dir[0] + dir[1] * dir[1] + dir[2] * dir[2]).sqrt();
+        if length == 0.0 {
+            return self.clear_colour();
+        }
+        // Straight up at the top of the panorama, the back of the canvas in
+        // its middle.
+        let longitude = dir[0].atan2(-dir[2]);
+        let latitude = (-dir[1] / length).clamp(-1.0, 1.0).acos();
+        let (width, height) = image.dimensions();
+        let x = ((0.5 + longitude / (2.0 * PI)) * width as f32) as u32;
+        let y = (latitude / PI * height as f32) as u32;
+        let [r, g, b] = image.get_pixel(x.min(width - 1), y.min(height - 1)).0;
+        Colour::new(r, g, b)
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self {
+            kind: Kind::Solid(Colour::new(250, 250, 250)),
+        }
+    }
+}
+
+fn mean_colour(image: &RgbImage) -> Colour {
+    let mut sums = [0u64; 3];
+    for pixel in image.pixels() {
+        for (sum, c) in sums.iter_mut().zip(pixel.0.iter()) {
+            *sum += *c as u64;
+        }
+    }
+    let count = (image.width() as u64 * image.height() as u64).max(1);
+    Colour::new(
+        (sums[0] / count) as u8,
+        (sums[1] / count) as u8,
+        (sums[2] / count) as u8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_background() {
+        let background = Background::new(&BackgroundConfig::default()).unwrap();
+        assert_eq!(background.clear_colour(), Colour::new(250, 250, 250));
+        assert_eq!(background.at_height(0.0), Colour::new(250, 250, 250));
+
+        let gradient = Background::new(&BackgroundConfig::Gradient {
+            top: "black".to_owned(),
+            bottom: "#ffffff".to_owned(),
+        })
+        .unwrap();
+        assert_eq!(gradient.at_height(0.0), Colour::new(0, 0, 0));
+        assert_eq!(gradient.at_height(1.0), Colour::new(255, 255, 255));
+        assert_eq!(gradient.clear_colour(), Colour::new(128, 128, 128));
+        assert_eq!(
+            gradient.in_direction([0.0, 0.0, -1.0], 0.25),
+            Colour::new(64, 64, 64)
+        );
+
+        let chroma_key = Background::new(&BackgroundConfig::ChromaKey).unwrap();
+        assert_eq!(chroma_key.at_height(0.3), CHROMA_KEY_GREEN);
+
+        assert!(Background::new(&BackgroundConfig::Solid {
+            colour: "mud".to_owned()
+        })
+        .is_err());
+        assert!(Background::new(&BackgroundConfig::Skybox {
+            filepath: ".missing.png".to_owned()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_skybox() {
+        // Sky blue on the upper half, grass green on the lower one.
+        let image = RgbImage::from_fn(8, 4, |_, y| match y < 2 {
+            true => image::Rgb([100, 150, 255]),
+            false => image::Rgb([50, 200, 50]),
+        });
+        let background = Background {
+            kind: Kind::Skybox {
+                mean: mean_colour(&image),
+                image: Arc::new(image),
+            },
+        };
+        assert_eq!(background.clear_colour(), Colour::new(75, 175, 152));
+        // Up is -y on the grid.
+        assert_eq!(
+            background.in_direction([0.0, -1.0, 0.0], 1.0),
+            Colour::new(100, 150, 255)
+        );
+        assert_eq!(
+            background.in_direction([0.3, 1.0, -0.2], 0.0),
+            Colour::new(50, 200, 50)
+        );
+    }
+}
@@ -1,84 +1,167 @@
+use std::convert::TryFrom;
 use std::fs::File;
-use std::io::{self, BufRead};
-use std::path::Path;
-use std::str::FromStr;
-use twixelbox_bot::Cube;
-use twixelbox_bot::CubeArchive;
-
-#[derive(Debug)]
-struct ChatCommand {
-    x: i32,
-    y: i32,
-    z: i32,
-    r: u8,
-    g: u8,
-    b: u8,
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use twixelbox_bot::{ChatCommand, Cube, Position};
+use twixelbox_bot::{CubeArchive, Region};
+use uuid::Uuid;
+
+// Width of the progress bar, in characters.
+const BAR_WIDTH: usize = 40;
+
+/// Imports cubes into the archive from a file with a `x y z r g b` line per
+/// cube, in chunks journaled one at a time.
+#[derive(StructOpt)]
+struct Cli {
+    /// File to import.
+    #[structopt(default_value = "./test.ply")]
+    input: PathBuf,
+
+    /// Archive the cubes are journaled to.
+    #[structopt(long, default_value = "cube_archive.db")]
+    archive: PathBuf,
+
+    /// Added to the coordinates of each line, as `x,y,z`.
+    #[structopt(long, default_value = "300,50,100", parse(try_from_str = parse_offset))]
+    offset: [i64; 3],
+
+    /// Cubes journaled in each transaction.
+    #[structopt(long, default_value = "10000")]
+    chunk_size: usize,
+
+    /// Most cubes imported, the import stops once reached.
+    #[structopt(long)]
+    limit: Option<usize>,
+
+    /// Only imports the cubes within this box of the canvas, as
+    /// `x0,y0,z0,x1,y1,z1` with both corners included.
+    #[structopt(long, parse(try_from_str = parse_bbox))]
+    bbox: Option<Region>,
+
+    /// Token printed by an interrupted import, to continue after its last
+    /// journaled chunk. Use the same input and filters.
+    #[structopt(long)]
+    resume: Option<String>,
 }
 
-impl FromStr for ChatCommand {
-    type Err = &'static str;
-
-    fn from_str(value: &str) -> Result<Self, Self::Err> {
-        let r: Result<Vec<_>, _> = value.split(' ').map(|v| v.parse::<i32>()).collect();
-        match r {
-            Ok(v) => {
-                if v.len() != 6usize {
-                    return Err("too many args");
-                }
-                if v[3] > 255 || v[4] > 255 || v[5] > 255 {
-                    return Err("invalid r g b");
-                }
-                Ok(ChatCommand {
-                    x: v[0],
-                    y: v[1],
-                    z: v[2],
-                    r: v[3] as u8,
-                    g: v[4] as u8,
-                    b: v[5] as u8,
-                })
-            }
-            Err(_) => Err("error parsing"),
-        }
+fn parse_coordinates(value: &str, len: usize) -> Result<Vec<i64>, String> {
+    let coordinates = value
+        .split(',')
+        .map(|v| v.trim().parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    match coordinates.len() == len {
+        true => Ok(coordinates),
+        false => Err(format!("expected {} coordinates", len)),
     }
 }
 
+fn parse_offset(value: &str) -> Result<[i64; 3], String> {
+    let c = parse_coordinates(value, 3)?;
+    Ok([c[0], c[1], c[2]])
+}
+
+fn parse_bbox(value: &str) -> Result<Region, String> {
+    let c = parse_coordinates(value, 6)?;
+    if c.iter().any(|&c| c < 0 || c > u32::MAX as i64) {
+        return Err("the box must be within the canvas".to_owned());
+    }
+    let corner = |i: usize, j: usize| (c[i].min(c[j]) as u32, c[i].max(c[j]) as u32);
+    let (x, y, z) = (corner(0, 3), corner(1, 4), corner(2, 5));
+    Ok(Region {
+        min: Position::new(x.0, y.0, z.0),
+        max: Position::new(x.1, y.1, z.1),
+    })
+}
+
+// Cube of a line, `None` if it's invalid or lands outside of the canvas.
+fn parse_cube(line: &str, offset: [i64; 3]) -> Option<Cube> {
+    let c = line.parse::<ChatCommand>().ok()?;
+    let coordinate = |value: i64, axis: usize| u32::try_from(value + offset[axis]).ok();
+    Some(Cube::new(
+        coordinate(c.x, 0)?,
+        coordinate(c.y, 1)?,
+        coordinate(c.z, 2)?,
+        c.colour,
+    ))
+}
+
+// Redraws the progress bar in place, on stderr.
+fn draw_progress(lines: u64, total: u64, imported: usize, token: &str) {
+    let ratio = match total {
+        0 => 1.0,
+        total => lines as f64 / total as f64,
+    };
+    let filled = (ratio * BAR_WIDTH as f64) as usize;
+    eprint!(
+        "\r[{}{}] {:>3}% {}/{} lines, {} cubes, resume with --resume {}",
+        "#".repeat(filled),
+        "-".repeat(BAR_WIDTH - filled),
+        (ratio * 100.0) as u32,
+        lines,
+        total,
+        imported,
+        token
+    );
+    let _ = io::stderr().flush();
+}
+
 fn main() {
-    if let Ok(lines) = read_lines("./test.ply") {
-        // Consumes the iterator, returns an (Optional) String
-        for line in lines {
-            if let Ok(twixel_msg) = line {
-                let chat_command = match twixel_msg.parse::<ChatCommand>() {
-                    Err(_) => continue,
-                    Ok(c) => c,
-                };
-                let chat_command = ChatCommand {
-                    x: chat_command.x + 300,
-                    y: chat_command.y + 50,
-                    z: chat_command.z + 100,
-                    ..chat_command
-                };
-                println!("{:?}", chat_command);
-                // create a cube with the above params
-                // TODO check that x, y, z are all > 0
-                if chat_command.x < 0 || chat_command.y < 0 || chat_command.z < 0 {
-                    continue;
-                }
-
-                let cube = Cube {
-                    position: (
-                        chat_command.x as u32,
-                        chat_command.y as u32,
-                        chat_command.z as u32,
-                    ),
-                    colour: (chat_command.r, chat_command.g, chat_command.b),
-                };
-
-                let sqlite_path = std::path::PathBuf::from("cube_archive.db");
-                let mut archive = CubeArchive::new(sqlite_path.clone());
-                archive.add_cube(cube.clone()).unwrap();
+    let cli = Cli::from_args();
+    if let Err(e) = import(&cli) {
+        eprintln!("\nImport failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn import(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let mut archive = CubeArchive::new(cli.archive.clone());
+    let (token, mut progress) = match &cli.resume {
+        Some(token) => match archive.import_progress(token)? {
+            Some(progress) => (token.clone(), progress),
+            None => return Err(format!("no import to resume with token {}", token).into()),
+        },
+        None => (Uuid::new_v4().to_string(), 0),
+    };
+    // Counted first for the progress bar.
+    let total = read_lines(&cli.input)?.count() as u64;
+    let limit = cli.limit.unwrap_or(usize::MAX);
+    let chunk_size = cli.chunk_size.max(1);
+    let mut imported = 0;
+    let mut skipped = 0;
+    let mut chunk = Vec::with_capacity(chunk_size);
+    draw_progress(progress, total, imported, &token);
+    for line in read_lines(&cli.input)?.skip(progress as usize) {
+        let line = line?;
+        progress += 1;
+        match parse_cube(&line, cli.offset) {
+            Some(cube) if cli.bbox.is_none_or(|bbox| bbox.contains(cube.position)) => {
+                chunk.push(cube)
             }
+            _ => skipped += 1,
         }
+        let done = imported + chunk.len() >= limit;
+        if chunk.len() >= chunk_size || done {
+            archive.import_chunk(&token, &chunk, progress)?;
+            imported += chunk.len();
+            chunk.clear();
+            draw_progress(progress, total, imported, &token);
+        }
+        if done {
+            break;
+        }
+    }
+    if !chunk.is_empty() {
+        archive.import_chunk(&token, &chunk, progress)?;
+        imported += chunk.len();
+        draw_progress(progress, total, imported, &token);
     }
+    eprintln!(
+        "\nImported {} cubes, skipped {} invalid or filtered out lines",
+        imported, skipped
+    );
+    Ok(())
 }
 
 fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use structopt::StructOpt;
+use twixelbox_bot::{CubeArchive, Resize, ResizeStrategy};
+
+/// Resizes the canvas of an archive, moving or dropping the cubes that no
+/// longer fit. The archive is backed up first, next to it, and migrated in a
+/// single transaction. Stop the bot while it runs, then set `cube_size` to
+/// the new side in the configuration.
+#[derive(StructOpt)]
+struct Cli {
+    /// Archive to migrate.
+    #[structopt(long, default_value = "cube_archive.db")]
+    archive: PathBuf,
+
+    /// Current side of the canvas, the `cube_size` of the configuration.
+    #[structopt(long)]
+    from: u32,
+
+    /// New side of the canvas.
+    #[structopt(long)]
+    to: u32,
+
+    /// What happens to the cubes: crop keeps them in place, scale stretches
+    /// the build with the canvas by a whole factor, recentre keeps it in the
+    /// middle.
+    #[structopt(long, default_value = "crop")]
+    strategy: ResizeStrategy,
+}
+
+fn main() {
+    let cli = Cli::from_args();
+    if let Err(e) = resize(&cli) {
+        eprintln!("Resize failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn resize(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let resize = Resize::new(cli.from, cli.to, cli.strategy)?;
+    if !cli.archive.exists() {
+        return Err(format!("no archive at {}", cli.archive.display()).into());
+    }
+    let mut archive = CubeArchive::new(cli.archive.clone());
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut backup = cli.archive.clone().into_os_string();
+    backup.push(format!(".bak-{}", timestamp));
+    let backup = PathBuf::from(backup);
+    archive.backup(&backup)?;
+    eprintln!("Backed up the archive to {}", backup.display());
+
+    // Scaling redraws the build block by block, moving the events one by
+    // one would leave holes between them.
+    match resize.resample() {
+        Some(resample) => {
+            let cubes = archive.resample(resample)?;
+            eprintln!(
+                "Scaled the canvas from {} to {}, {} cubes on it",
+                cli.from, cli.to, cubes
+            );
+        }
+        None => {
+            let (kept, dropped) = archive.remap_positions(|position| resize.position(position))?;
+            let cubes = archive.get_cubes()?.len();
+            eprintln!(
+                "Resized the canvas from {} to {}: kept {} events, dropped {} outside of the canvas, {} cubes on it",
+                cli.from, cli.to, kept, dropped, cubes
+            );
+        }
+    }
+    eprintln!("Set cube_size = {} in the configuration", cli.to);
+    Ok(())
+}
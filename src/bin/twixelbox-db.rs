@@ -0,0 +1,220 @@
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use structopt::StructOpt;
+use twixelbox_bot::{parse_duration, CanvasEvent, Colour, CubeArchive, EventMetadata};
+use twixelbox_bot::{JournalEntry, Position, Region};
+
+/// Inspects and edits the archive of the canvas. Edits are journaled as
+/// events, a running bot draws them within seconds.
+#[derive(StructOpt)]
+struct Cli {
+    /// Archive to inspect.
+    #[structopt(long, default_value = "cube_archive.db")]
+    archive: PathBuf,
+
+    #[structopt(subcommand)]
+    command: DbCommand,
+}
+
+#[derive(StructOpt)]
+enum DbCommand {
+    /// Prints the statistics of the canvas.
+    Stats {
+        /// Side of the canvas, the `cube_size` of the configuration.
+        #[structopt(long, default_value = "500")]
+        side: u32,
+    },
+    /// Lists the cubes on the canvas, with who placed them and when.
+    List {
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    /// Takes cubes off the canvas.
+    Delete {
+        #[structopt(flatten)]
+        filter: Filter,
+
+        /// Doesn't ask for confirmation.
+        #[structopt(long)]
+        yes: bool,
+    },
+    /// Changes the colour of cubes of the canvas.
+    Recolor {
+        #[structopt(flatten)]
+        filter: Filter,
+
+        /// Only recolours the cubes of this colour, a name or a hex code.
+        #[structopt(long)]
+        from: Option<Colour>,
+
+        /// New colour of the cubes.
+        #[structopt(long)]
+        to: Colour,
+
+        /// Doesn't ask for confirmation.
+        #[structopt(long)]
+        yes: bool,
+    },
+    /// Prints the whole journal, an event per line as JSON.
+    Dump,
+}
+
+/// Cubes a command applies to, all of them by default.
+#[derive(StructOpt)]
+struct Filter {
+    /// Only the cubes placed by this chatter.
+    #[structopt(long)]
+    user: Option<String>,
+
+    /// Only the cubes within this box, as `x0,y0,z0,x1,y1,z1` with both
+    /// corners included.
+    #[structopt(long, parse(try_from_str = parse_bbox))]
+    bbox: Option<Region>,
+
+    /// Only the cubes placed in the last `90s`, `30m`, `2h`...
+    #[structopt(long, parse(try_from_str = parse_since))]
+    since: Option<Duration>,
+}
+
+impl Filter {
+    fn matches(&self, position: Position, entry: &JournalEntry, now: i64) -> bool {
+        let metadata = &entry.metadata;
+        self.user
+            .as_deref()
+            .is_none_or(|user| metadata.author.as_deref() == Some(user))
+            && self.bbox.is_none_or(|bbox| bbox.contains(position))
+            && self
+                .since
+                .is_none_or(|since| metadata.timestamp >= now - since.as_secs() as i64)
+    }
+}
+
+fn parse_bbox(value: &str) -> Result<Region, String> {
+    let c = value
+        .split(',')
+        .map(|v| v.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    if c.len() != 6 {
+        return Err("expected 6 coordinates".to_owned());
+    }
+    Ok(Region {
+        min: Position::new(c[0].min(c[3]), c[1].min(c[4]), c[2].min(c[5])),
+        max: Position::new(c[0].max(c[3]), c[1].max(c[4]), c[2].max(c[5])),
+    })
+}
+
+fn parse_since(value: &str) -> Result<Duration, String> {
+    parse_duration(value).ok_or_else(|| format!("invalid duration {}, try 30m or 2h", value))
+}
+
+// Asks on stderr, anything but `y` is a no.
+fn confirm(question: &str) -> io::Result<bool> {
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
+
+fn main() {
+    let cli = Cli::from_args();
+    if let Err(e) = run(&cli) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    if !cli.archive.exists() {
+        return Err(format!("no archive at {}", cli.archive.display()).into());
+    }
+    let mut archive = CubeArchive::new(cli.archive.clone());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match &cli.command {
+        DbCommand::Stats { side } => {
+            let stats = archive.stats(*side, now)?;
+            writeln!(out, "Cubes: {}", stats.cubes)?;
+            writeln!(out, "Filled: {:.2}%", stats.fill_percentage)?;
+            writeln!(out, "Builders today: {}", stats.builders_today)?;
+            writeln!(out, "Cubes in the last hour: {}", stats.cubes_last_hour)?;
+        }
+        DbCommand::List { filter } => {
+            let mut placements: Vec<_> = archive
+                .placements()?
+                .into_iter()
+                .filter(|(position, (_, entry))| filter.matches(*position, entry, now))
+                .collect();
+            placements.sort_by_key(|(position, _)| *position);
+            for (position, (colour, entry)) in placements {
+                let metadata = entry.metadata;
+                writeln!(
+                    out,
+                    "{} {} {} {}",
+                    position,
+                    colour,
+                    metadata.author.as_deref().unwrap_or("-"),
+                    metadata.timestamp
+                )?;
+            }
+        }
+        DbCommand::Delete { filter, yes } => {
+            let events: Vec<CanvasEvent> = archive
+                .placements()?
+                .into_iter()
+                .filter(|(position, (_, entry))| filter.matches(*position, entry, now))
+                .map(|(position, _)| CanvasEvent::CubeRemoved(position))
+                .collect();
+            let question = format!("Take {} cubes off the canvas?", events.len());
+            if events.is_empty() || !(*yes || confirm(&question)?) {
+                return Ok(());
+            }
+            archive.append_events(&events, &operator_metadata(now))?;
+            eprintln!("Took {} cubes off the canvas", events.len());
+        }
+        DbCommand::Recolor {
+            filter,
+            from,
+            to,
+            yes,
+        } => {
+            let events: Vec<CanvasEvent> = archive
+                .placements()?
+                .into_iter()
+                .filter(|(position, (colour, entry))| {
+                    from.is_none_or(|from| from == *colour)
+                        && colour != to
+                        && filter.matches(*position, entry, now)
+                })
+                .map(|(position, _)| CanvasEvent::Recoloured {
+                    position,
+                    colour: *to,
+                })
+                .collect();
+            let question = format!("Recolour {} cubes to {}?", events.len(), to);
+            if events.is_empty() || !(*yes || confirm(&question)?) {
+                return Ok(());
+            }
+            archive.append_events(&events, &operator_metadata(now))?;
+            eprintln!("Recoloured {} cubes", events.len());
+        }
+        DbCommand::Dump => {
+            for entry in archive.get_journal()? {
+                writeln!(out, "{}", serde_json::to_string(&entry)?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Journaled with the edits, which no chatter made.
+fn operator_metadata(now: i64) -> EventMetadata {
+    EventMetadata {
+        timestamp: now,
+        message: Some("twixelbox-db".to_owned()),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{Axis, Colour, Palette, Position};
+use image::imageops::FilterType;
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+// 4x4 Bayer matrix, thresholds from 0 to 15.
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+// How far the thresholds push a channel, either way, before picking the
+// closest colour of the palette.
+const DITHER_SPREAD: f32 = 48.0;
+// Widest image a build sheet is made from, in cubes.
+const MAX_WIDTH: u32 = 256;
+
+/// Where an image is projected on the canvas: the plane at `layer` along
+/// `axis`, from `origin` towards the right and the bottom of the image.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Projection {
+    pub axis: Axis,
+    pub layer: u32,
+    /// Canvas coordinates of the top left pixel within the plane, i.e. x and
+    /// y for the z axis, z and y for the x axis, x and z for the y axis.
+    pub origin: (u32, u32),
+    /// Width of the image on the canvas, in cubes.
+    pub width: u32,
+}
+
+impl Projection {
+    fn position(&self, u: u32, v: u32) -> Position {
+        let (u, v) = (self.origin.0 + u, self.origin.1 + v);
+        match self.axis {
+            Axis::X => Position::new(self.layer, v, u),
+            Axis::Y => Position::new(u, self.layer, v),
+            Axis::Z => Position::new(u, v, self.layer),
+        }
+    }
+}
+
+/// Colour suggested for each cube of an image projected on the canvas, for
+/// chat to reproduce it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuildSheet {
+    width: u32,
+    height: u32,
+    // Row by row, `None` for pixels falling outside of the canvas.
+    cells: Vec<Option<(Position, Colour)>>,
+}
+
+impl BuildSheet {
+    /// Scales `img` to the width of the projection and matches each pixel to
+    /// the palette with ordered dithering. Without palette pixels keep their
+    /// own colour.
+    pub fn new(
+        img: &RgbImage,
+        projection: &Projection,
+        palette: Option<&Palette>,
+        side_len: u32,
+    ) -> Self {
+        let width = projection.width.clamp(1, MAX_WIDTH);
+        let height = ((img.height() as u64 * width as u64) / img.width().max(1) as u64).max(1);
+        let img = image::imageops::resize(img, width, height as u32, FilterType::Triangle);
+        let mut cells = Vec::with_capacity(img.len() / 3);
+        for (u, v, pixel) in img.enumerate_pixels() {
+            let position = projection.position(u, v);
+            if !position.is_within(side_len) {
+                cells.push(None);
+                continue;
+            }
+            let colour = match palette {
+                Some(palette) => {
+                    let threshold = BAYER[(v % 4) as usize][(u % 4) as usize] as f32 / 16.0;
+                    let offset = (threshold - 0.5) * DITHER_SPREAD;
+                    let [r, g, b] =
+                        [0, 1, 2].map(|c| (pixel[c] as f32 + offset).clamp(0.0, 255.0) as u8);
+                    palette.nearest(Colour::new(r, g, b))
+                }
+                None => Colour::new(pixel[0], pixel[1], pixel[2]),
+            };
+            cells.push(Some((position, colour)));
+        }
+        Self {
+            width,
+            height: img.height(),
+            cells,
+        }
+    }
+
+    /// Cubes to place, row by row.
+    pub fn cubes(&self) -> impl Iterator<Item = &(Position, Colour)> {
+        self.cells.iter().flatten()
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = "x,y,z,r,g,b,colour\n".to_owned();
+        for (position, colour) in self.cubes() {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{}",
+                position.x, position.y, position.z, colour.r, colour.g, colour.b, colour
+            );
+        }
+        csv
+    }
+
+    /// Page with the image as a grid of cells, each labelled with its
+    /// coordinates and colour.
+    pub fn to_html(&self) -> String {
+        let mut html = "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+            <title>Build sheet</title><style>\
+            table { border-collapse: collapse; font: 9px monospace; }\
+            td { width: 24px; height: 24px; border: 1px solid #ccc; text-align: center; }\
+            </style></head><body><table>\n"
+            .to_owned();
+        for row in self.cells.chunks(self.width as usize) {
+            html.push_str("<tr>");
+            for cell in row {
+                match cell {
+                    Some((position, colour)) => {
+                        // Dark text on light colours and the other way round.
+                        let luma = colour.r as u32 * 3 + colour.g as u32 * 6 + colour.b as u32;
+                        let text = if luma > 1280 { "#000" } else { "#fff" };
+                        let _ = write!(
+                            html,
+                            "<td style=\"background:{0};color:{1}\" title=\"{2} {0}\">{2}</td>",
+                            colour, text, position
+                        );
+                    }
+                    None => html.push_str("<td></td>"),
+                }
+            }
+            html.push_str("</tr>\n");
+        }
+        let _ = writeln!(
+            html,
+            "</table><p>{} x {} cubes</p></body></html>",
+            self.width, self.height
+        );
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_build_sheet() {
+        // Mid grey, dithered between black and white.
+        let img = RgbImage::from_pixel(8, 4, Rgb([128, 128, 128]));
+        let palette = Palette::new(vec![Colour::new(0, 0, 0), Colour::new(255, 255, 255)]);
+        let projection = Projection {
+            axis: Axis::X,
+            layer: 2,
+            origin: (1, 0),
+            width: 4,
+        };
+        let sheet = BuildSheet::new(&img, &projection, palette.as_ref(), 4);
+        // The last column falls outside of the canvas.
+        let cubes: Vec<_> = sheet.cubes().copied().collect();
+        assert_eq!(cubes.len(), 6);
+        assert_eq!(cubes[0].0, Position::new(2, 0, 1));
+        assert_eq!(cubes[3].0, Position::new(2, 1, 1));
+        let white = cubes.iter().filter(|(_, c)| c.r == 255).count();
+        assert!(white > 0 && white < cubes.len());
+
+        let sheet = BuildSheet::new(&img, &projection, None, 4);
+        let csv = sheet.to_csv();
+        assert!(csv.starts_with("x,y,z,r,g,b,colour\n2,0,1,128,128,128,#808080\n"));
+        assert_eq!(csv.lines().count(), 7);
+        assert_eq!(sheet.to_html().matches("<td").count(), 8);
+    }
+}
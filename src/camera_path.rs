@@ -0,0 +1,315 @@
+use crate::transform::FIELD_OF_VIEW;
+use crate::{Raytracer, Renderer, Watermark};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame, ImageResult};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CameraPathError {
+    #[error("invalid camera path: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("the camera path has no keyframes")]
+    NoKeyframes,
+    #[error("the keyframes of the camera path must be in order of time")]
+    UnorderedKeyframes,
+}
+
+type Vec3 = [f32; 3];
+
+/// Where the camera stands and what it looks at, on the grid of the canvas:
+/// in cubes from the centre of the cube at 0 0 0, y growing downwards.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraPose {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+}
+
+impl CameraPose {
+    /// Ray through `(x, y)` of the image, as fractions of its side from the
+    /// top left, with the field of view of the overlay's camera. Returns its
+    /// origin and direction, which isn't normalised.
+    pub fn ray(&self, x: f32, y: f32) -> (Vec3, Vec3) {
+        let (forward, right, down) = self.axes();
+        let tan = (FIELD_OF_VIEW / 2.0).tan();
+        let (u, v) = ((x * 2.0 - 1.0) * tan, (y * 2.0 - 1.0) * tan);
+        let dir = [0, 1, 2].map(|i| forward[i] + right[i] * u + down[i] * v);
+        (self.position, dir)
+    }
+
+    /// Where `point` of the grid shows in the image, as fractions of its side
+    /// from the top left, the inverse of `ray`. `None` when the point is
+    /// behind the camera.
+    pub fn project(&self, point: Vec3) -> Option<(f32, f32)> {
+        let (forward, right, down) = self.axes();
+        let d = sub(point, self.position);
+        let depth = dot(d, forward);
+        if depth <= 0.0 {
+            return None;
+        }
+        let tan = (FIELD_OF_VIEW / 2.0).tan();
+        let (u, v) = (dot(d, right) / depth / tan, dot(d, down) / depth / tan);
+        Some(((u + 1.0) / 2.0, (v + 1.0) / 2.0))
+    }
+
+    /// Pose `t` of the way from this one to `other`, between 0 and 1.
+    pub fn lerp(&self, other: &CameraPose, t: f32) -> CameraPose {
+        let mix = |a: Vec3, b: Vec3| [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t);
+        CameraPose {
+            position: mix(self.position, other.position),
+            target: mix(self.target, other.target),
+        }
+    }
+
+    /// The camera moved `distance` to its right, or left when negative,
+    /// still looking at its target.
+    pub fn sideways(&self, distance: f32) -> CameraPose {
+        let (_, right, _) = self.axes();
+        CameraPose {
+            position: [0, 1, 2].map(|i| self.position[i] + right[i] * distance),
+            target: self.target,
+        }
+    }
+
+    /// Distance from the camera to its target.
+    pub fn focus(&self) -> f32 {
+        let d = sub(self.target, self.position);
+        (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt()
+    }
+
+    // Directions of the camera's view and of the right and bottom of its
+    // image. Image rows go down the canvas, as y does, whichever way the
+    // camera looks but straight up or down.
+    fn axes(&self) -> (Vec3, Vec3, Vec3) {
+        let forward = normalize(sub(self.target, self.position)).unwrap_or([0.0, 0.0, -1.0]);
+        let right = normalize(cross(forward, [0.0, 1.0, 0.0])).unwrap_or([1.0, 0.0, 0.0]);
+        (forward, right, cross(right, forward))
+    }
+}
+
+/// Pose of the camera at `time`, in seconds from the start of the path.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub time: f32,
+    #[serde(flatten)]
+    pub pose: CameraPose,
+}
+
+/// Flight of the camera through its keyframes, as stored in JSON:
+/// `{"keyframes": [{"time": 0, "position": [x, y, z], "target": [x, y, z]}]}`.
+/// In between keyframes, the camera follows a Catmull-Rom spline, so that it
+/// moves smoothly through each.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    pub fn new(keyframes: Vec<Keyframe>) -> Result<Self, CameraPathError> {
+        if keyframes.is_empty() {
+            return Err(CameraPathError::NoKeyframes);
+        }
+        if keyframes
+            .windows(2)
+            .any(|pair| pair[0].time >= pair[1].time)
+        {
+            return Err(CameraPathError::UnorderedKeyframes);
+        }
+        Ok(Self { keyframes })
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, CameraPathError> {
+        let path: CameraPath = serde_json::from_str(json)?;
+        Self::new(path.keyframes)
+    }
+
+    /// Seconds from the first keyframe to the last.
+    pub fn duration(&self) -> f32 {
+        let first = self.keyframes.first().map_or(0.0, |k| k.time);
+        let last = self.keyframes.last().map_or(0.0, |k| k.time);
+        last - first
+    }
+
+    /// Pose of the camera `time` seconds after the first keyframe, held at
+    /// the ends of the path.
+    pub fn pose(&self, time: f32) -> CameraPose {
+        let keyframes = &self.keyframes;
+        let time = keyframes[0].time + time.clamp(0.0, self.duration());
+        let i = keyframes
+            .windows(2)
+            .position(|pair| time <= pair[1].time)
+            .unwrap_or(0);
+        let (a, b) = match keyframes.get(i + 1) {
+            Some(b) => (&keyframes[i], b),
+            None => return keyframes[i].pose,
+        };
+        let h = b.time - a.time;
+        let s = (time - a.time) / h;
+        // Cubic Hermite basis.
+        let (s2, s3) = (s * s, s * s * s);
+        let h00 = 2.0 * s3 - 3.0 * s2 + 1.0;
+        let h10 = s3 - 2.0 * s2 + s;
+        let h01 = -2.0 * s3 + 3.0 * s2;
+        let h11 = s3 - s2;
+        let point = |p: fn(&CameraPose) -> Vec3| {
+            let (m0, m1) = (self.tangent(i, p), self.tangent(i + 1, p));
+            [0, 1, 2].map(|axis| {
+                h00 * p(&a.pose)[axis]
+                    + h10 * h * m0[axis]
+                    + h01 * p(&b.pose)[axis]
+                    + h11 * h * m1[axis]
+            })
+        };
+        CameraPose {
+            position: point(|pose| pose.position),
+            target: point(|pose| pose.target),
+        }
+    }
+
+    // Velocity of the camera through keyframe `i`, from its neighbours, or
+    // towards its only neighbour at the ends.
+    fn tangent(&self, i: usize, p: fn(&CameraPose) -> Vec3) -> Vec3 {
+        let keyframes = &self.keyframes;
+        let before = &keyframes[i.saturating_sub(1)];
+        let after = &keyframes[(i + 1).min(keyframes.len() - 1)];
+        let dt = after.time - before.time;
+        if dt <= 0.0 {
+            return [0.0; 3];
+        }
+        let (p0, p1) = (p(&before.pose), p(&after.pose));
+        [0, 1, 2].map(|axis| (p1[axis] - p0[axis]) / dt)
+    }
+}
+
+/// Renders the flight of the camera along `path` through the canvas of
+/// `raytracer` as an animated GIF of `fps` frames per second, with the
+/// watermark on every frame if any.
+pub fn render_camera_path(
+    raytracer: &Raytracer,
+    path: &CameraPath,
+    fps: f32,
+    watermark: Option<&Watermark>,
+) -> ImageResult<Vec<u8>> {
+    let fps = fps.max(1.0);
+    let delay = Delay::from_numer_denom_ms((1000.0 / fps).round() as u32, 1);
+    let count = (path.duration() * fps).floor() as u32 + 1;
+    let mut raytracer = raytracer.clone();
+    let frames: Vec<Frame> = (0..count)
+        .map(|i| {
+            raytracer.set_camera(Some(path.pose(i as f32 / fps)));
+            let mut img = raytracer.render_image();
+            if let Some(watermark) = watermark {
+                watermark.draw(&mut img);
+            }
+            let img = DynamicImage::ImageRgb8(img).into_rgba8();
+            Frame::from_parts(img, 0, 0, delay)
+        })
+        .collect();
+
+    let mut gif = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut gif);
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames)?;
+    }
+    Ok(gif)
+}
+
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+// `None` for a null vector, which has no direction.
+fn normalize(a: Vec3) -> Option<Vec3> {
+    let length = (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt();
+    match length > 1e-6 {
+        true => Some([a[0] / length, a[1] / length, a[2] / length]),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colour, Cube};
+    use image::codecs::gif::GifDecoder;
+    use image::AnimationDecoder;
+
+    fn keyframe(time: f32, x: f32) -> Keyframe {
+        Keyframe {
+            time,
+            pose: CameraPose {
+                position: [x, 0.0, 10.0],
+                target: [x, 0.0, 0.0],
+            },
+        }
+    }
+
+    #[test]
+    fn test_camera_path() {
+        assert!(matches!(
+            CameraPath::from_json(r#"{"keyframes": []}"#),
+            Err(CameraPathError::NoKeyframes)
+        ));
+        assert!(matches!(
+            CameraPath::new(vec![keyframe(1.0, 0.0), keyframe(1.0, 2.0)]),
+            Err(CameraPathError::UnorderedKeyframes)
+        ));
+        let path = CameraPath::from_json(
+            r#"{"keyframes": [
+                {"time": 1, "position": [0, 0, 10], "target": [0, 0, 0]},
+                {"time": 3, "position": [4, 0, 10], "target": [4, 0, 0]},
+                {"time": 4, "position": [8, 0, 10], "target": [8, 0, 0]}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(path.duration(), 3.0);
+        // Through each keyframe, held at the ends.
+        assert_eq!(path.pose(0.0), keyframe(1.0, 0.0).pose);
+        assert_eq!(path.pose(2.0), keyframe(3.0, 4.0).pose);
+        assert_eq!(path.pose(9.0), keyframe(4.0, 8.0).pose);
+        // Smoothly in between.
+        let x = |time: f32| path.pose(time).position[0];
+        assert!(x(0.5) > 0.0 && x(0.5) < x(1.0) && x(1.0) < 4.0);
+        assert!(x(2.5) > 4.0 && x(2.5) < 8.0);
+
+        // The camera of the overlay looks along -z, rows going down y.
+        let pose = CameraPose {
+            position: [0.0, 0.0, 10.0],
+            target: [0.0, 0.0, 0.0],
+        };
+        assert_eq!(pose.ray(0.5, 0.5), ([0.0, 0.0, 10.0], [0.0, 0.0, -1.0]));
+        let (_, dir) = pose.ray(1.0, 1.0);
+        assert!(dir[0] > 0.0 && dir[1] > 0.0);
+        let (x, y) = pose.project([1.0, 2.0, 3.0]).unwrap();
+        let (eye, dir) = pose.ray(x, y);
+        let t = (3.0 - eye[2]) / dir[2];
+        assert!((eye[0] + dir[0] * t - 1.0).abs() < 1e-4);
+        assert!((eye[1] + dir[1] * t - 2.0).abs() < 1e-4);
+        assert_eq!(pose.project([0.0, 0.0, 11.0]), None);
+        let halfway = pose.lerp(&keyframe(0.0, 4.0).pose, 0.5);
+        assert_eq!(halfway.position, [2.0, 0.0, 10.0]);
+
+        let mut raytracer = Raytracer::new(8, 10, 1);
+        raytracer.add_cube(&Cube::new(4, 4, 4, Colour::new(255, 0, 0)));
+        let gif = render_camera_path(&raytracer, &path, 2.0, None).unwrap();
+        let frames = GifDecoder::new(&gif[..])
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(frames.len(), 7);
+    }
+}
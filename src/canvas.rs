@@ -0,0 +1,415 @@
+use crate::{Axis, Colour, Cube, Position};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use thiserror::Error;
+
+/// In-memory state of the canvas, independent of any renderer. Every change to
+/// the canvas goes through here, renderers and the archive only mirror it.
+/// It serializes as its side and the list of its cubes, and deserializing
+/// rejects cubes outside of the canvas.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(into = "CanvasData", try_from = "CanvasData")]
+pub struct Canvas {
+    side_len: u32,
+    cubes: HashMap<Position, Colour>,
+    // XOR of the hashes of the cubes, kept up to date by each change.
+    checksum: u64,
+}
+
+// Hash of a cube in the checksum, a mix of its coordinates and colour so that
+// neighbours don't cancel out.
+fn cube_hash(position: Position, colour: Colour) -> u64 {
+    let mix = |mut z: u64| {
+        // splitmix64's finalizer.
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+    // Opaque full cubes which don't glow hash as they did before either.
+    let colour = u64::from_le_bytes([
+        colour.r,
+        colour.g,
+        colour.b,
+        !colour.a,
+        colour.emissive as u8,
+        colour.shape as u8,
+        0,
+        0,
+    ]);
+    [position.y as u64, position.z as u64, colour]
+        .iter()
+        .fold(mix(position.x as u64), |hash, value| mix(hash ^ value))
+}
+
+/// Where two canvases differ, with the colour of the cube in each if any.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CanvasDifference {
+    pub position: Position,
+    pub actual: Option<Colour>,
+    pub expected: Option<Colour>,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CanvasError {
+    #[error("{axis} {coordinate} is outside of the canvas")]
+    OutOfBounds { axis: Axis, coordinate: i64 },
+    #[error("there is no cube at {0}")]
+    NoCube(Position),
+    #[error("there is already a cube at {}", by.position)]
+    Occupied { by: Cube },
+}
+
+/// Position at `coordinates` on a canvas of side `side_len`, or the first
+/// axis along which they're outside of it.
+pub fn bounded_position(coordinates: [i64; 3], side_len: u32) -> Result<Position, CanvasError> {
+    let axes = [Axis::X, Axis::Y, Axis::Z];
+    for (axis, coordinate) in axes.iter().zip(coordinates.iter()) {
+        if !(0..side_len as i64).contains(coordinate) {
+            return Err(CanvasError::OutOfBounds {
+                axis: *axis,
+                coordinate: *coordinate,
+            });
+        }
+    }
+    let [x, y, z] = coordinates;
+    Ok(Position::new(x as u32, y as u32, z as u32))
+}
+
+// Serialized form of the canvas, JSON maps can't have positions as keys.
+#[derive(Serialize, Deserialize)]
+struct CanvasData {
+    side_len: u32,
+    cubes: Vec<Cube>,
+}
+
+impl From<Canvas> for CanvasData {
+    fn from(canvas: Canvas) -> Self {
+        let mut cubes: Vec<Cube> = canvas.cubes().collect();
+        cubes.sort_by_key(|cube| cube.position);
+        Self {
+            side_len: canvas.side_len,
+            cubes,
+        }
+    }
+}
+
+impl TryFrom<CanvasData> for Canvas {
+    type Error = CanvasError;
+
+    fn try_from(data: CanvasData) -> Result<Self, Self::Error> {
+        let mut canvas = Canvas::new(data.side_len);
+        for cube in data.cubes {
+            canvas.add_cube(cube)?;
+        }
+        Ok(canvas)
+    }
+}
+
+impl Canvas {
+    /// Creates an empty canvas, positions go from 0 to `side_len` - 1.
+    pub fn new(side_len: u32) -> Self {
+        Self {
+            side_len,
+            cubes: HashMap::new(),
+            checksum: 0,
+        }
+    }
+
+    /// Hash of the cubes on the canvas, the same for canvases holding the
+    /// same cubes however they got there.
+    pub fn checksum(&self) -> u64 {
+        self.checksum
+    }
+
+    /// Cubes which differ from those of `expected`, sorted by position.
+    pub fn differences(&self, expected: &Canvas) -> Vec<CanvasDifference> {
+        let mut differences: Vec<CanvasDifference> = self
+            .cubes
+            .keys()
+            .chain(
+                expected
+                    .cubes
+                    .keys()
+                    .filter(|p| !self.cubes.contains_key(p)),
+            )
+            .map(|&position| CanvasDifference {
+                position,
+                actual: self.cubes.get(&position).copied(),
+                expected: expected.cubes.get(&position).copied(),
+            })
+            .filter(|difference| difference.actual != difference.expected)
+            .collect();
+        differences.sort_by_key(|difference| difference.position);
+        differences
+    }
+
+    pub fn side_len(&self) -> u32 {
+        self.side_len
+    }
+
+    pub fn len(&self) -> usize {
+        self.cubes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cubes.is_empty()
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        self.cubes.contains_key(&position)
+    }
+
+    pub fn get(&self, position: Position) -> Option<Cube> {
+        self.cubes
+            .get(&position)
+            .map(|&colour| Cube { position, colour })
+    }
+
+    /// Iterates over all the cubes, in no particular order.
+    pub fn cubes(&self) -> impl Iterator<Item = Cube> + '_ {
+        self.cubes
+            .iter()
+            .map(|(&position, &colour)| Cube { position, colour })
+    }
+
+    /// Places a cube, returns the cube it replaced if any.
+    pub fn add_cube(&mut self, cube: Cube) -> Result<Option<Cube>, CanvasError> {
+        self.check_bounds(cube.position)?;
+        self.checksum ^= cube_hash(cube.position, cube.colour);
+        let replaced = self.cubes.insert(cube.position, cube.colour);
+        if let Some(colour) = replaced {
+            self.checksum ^= cube_hash(cube.position, colour);
+        }
+        Ok(replaced.map(|colour| Cube {
+            position: cube.position,
+            colour,
+        }))
+    }
+
+    /// Places a cube where there's none, unlike `add_cube`.
+    pub fn try_place(&mut self, cube: Cube) -> Result<(), CanvasError> {
+        self.check_free(cube.position)?;
+        self.add_cube(cube).map(|_| ())
+    }
+
+    /// Checks whether a cube can be placed at `position` without replacing
+    /// another.
+    pub fn check_free(&self, position: Position) -> Result<(), CanvasError> {
+        self.check_bounds(position)?;
+        match self.get(position) {
+            Some(by) => Err(CanvasError::Occupied { by }),
+            None => Ok(()),
+        }
+    }
+
+    /// Removes the cube at `position`, returning it.
+    pub fn remove_cube(&mut self, position: Position) -> Result<Cube, CanvasError> {
+        self.check_bounds(position)?;
+        match self.cubes.remove(&position) {
+            Some(colour) => {
+                self.checksum ^= cube_hash(position, colour);
+                Ok(Cube { position, colour })
+            }
+            None => Err(CanvasError::NoCube(position)),
+        }
+    }
+
+    /// Changes the colour of an existing cube, returns the previous colour.
+    pub fn recolour(&mut self, position: Position, colour: Colour) -> Result<Colour, CanvasError> {
+        self.check_bounds(position)?;
+        match self.cubes.get_mut(&position) {
+            Some(existing) => {
+                self.checksum ^= cube_hash(position, *existing) ^ cube_hash(position, colour);
+                Ok(std::mem::replace(existing, colour))
+            }
+            None => Err(CanvasError::NoCube(position)),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cubes.clear();
+        self.checksum = 0;
+    }
+
+    pub(crate) fn check_bounds(&self, position: Position) -> Result<(), CanvasError> {
+        let coordinates = [position.x, position.y, position.z].map(|c| c as i64);
+        bounded_position(coordinates, self.side_len).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(position: (u32, u32, u32), colour: (u8, u8, u8)) -> Cube {
+        Cube {
+            position: position.into(),
+            colour: colour.into(),
+        }
+    }
+
+    fn pos(x: u32, y: u32, z: u32) -> Position {
+        Position::new(x, y, z)
+    }
+
+    #[test]
+    fn test_add_get() {
+        let mut canvas = Canvas::new(10);
+        assert!(canvas.is_empty());
+        assert_eq!(canvas.add_cube(cube((1, 2, 3), (4, 5, 6))), Ok(None));
+        assert_eq!(canvas.len(), 1);
+        assert!(canvas.contains(pos(1, 2, 3)));
+        assert_eq!(canvas.get(pos(1, 2, 3)), Some(cube((1, 2, 3), (4, 5, 6))));
+        assert_eq!(canvas.get(pos(3, 2, 1)), None);
+        assert_eq!(
+            canvas.cubes().collect::<Vec<_>>(),
+            vec![cube((1, 2, 3), (4, 5, 6))]
+        );
+    }
+
+    #[test]
+    fn test_add_replaces() {
+        let mut canvas = Canvas::new(10);
+        canvas.add_cube(cube((1, 2, 3), (4, 5, 6))).unwrap();
+        assert_eq!(
+            canvas.add_cube(cube((1, 2, 3), (7, 8, 9))),
+            Ok(Some(cube((1, 2, 3), (4, 5, 6))))
+        );
+        assert_eq!(canvas.len(), 1);
+        assert_eq!(canvas.get(pos(1, 2, 3)), Some(cube((1, 2, 3), (7, 8, 9))));
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        let mut canvas = Canvas::new(10);
+        let out = |axis, coordinate| CanvasError::OutOfBounds { axis, coordinate };
+        assert_eq!(
+            canvas.add_cube(cube((10, 0, 0), (0, 0, 0))),
+            Err(out(Axis::X, 10))
+        );
+        assert_eq!(canvas.remove_cube(pos(0, 0, 10)), Err(out(Axis::Z, 10)));
+        assert_eq!(
+            canvas.recolour(pos(0, 10, 0), Colour::new(0, 0, 0)),
+            Err(out(Axis::Y, 10))
+        );
+        assert!(canvas.is_empty());
+        assert_eq!(bounded_position([1, -1, 12], 10), Err(out(Axis::Y, -1)));
+        assert_eq!(bounded_position([1, 2, 9], 10), Ok(pos(1, 2, 9)));
+    }
+
+    #[test]
+    fn test_try_place() {
+        let mut canvas = Canvas::new(10);
+        assert_eq!(canvas.try_place(cube((1, 2, 3), (4, 5, 6))), Ok(()));
+        assert_eq!(
+            canvas.try_place(cube((1, 2, 3), (7, 8, 9))),
+            Err(CanvasError::Occupied {
+                by: cube((1, 2, 3), (4, 5, 6))
+            })
+        );
+        assert_eq!(
+            canvas.try_place(cube((1, 2, 10), (7, 8, 9))),
+            Err(CanvasError::OutOfBounds {
+                axis: Axis::Z,
+                coordinate: 10
+            })
+        );
+        assert_eq!(canvas.get(pos(1, 2, 3)), Some(cube((1, 2, 3), (4, 5, 6))));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut canvas = Canvas::new(10);
+        canvas.add_cube(cube((1, 2, 3), (4, 5, 6))).unwrap();
+        assert_eq!(
+            canvas.remove_cube(pos(1, 2, 3)),
+            Ok(cube((1, 2, 3), (4, 5, 6)))
+        );
+        assert_eq!(
+            canvas.remove_cube(pos(1, 2, 3)),
+            Err(CanvasError::NoCube(pos(1, 2, 3)))
+        );
+        assert!(canvas.is_empty());
+    }
+
+    #[test]
+    fn test_recolour() {
+        let mut canvas = Canvas::new(10);
+        assert_eq!(
+            canvas.recolour(pos(1, 2, 3), Colour::new(7, 8, 9)),
+            Err(CanvasError::NoCube(pos(1, 2, 3)))
+        );
+        canvas.add_cube(cube((1, 2, 3), (4, 5, 6))).unwrap();
+        assert_eq!(
+            canvas.recolour(pos(1, 2, 3), Colour::new(7, 8, 9)),
+            Ok(Colour::new(4, 5, 6))
+        );
+        assert_eq!(canvas.get(pos(1, 2, 3)), Some(cube((1, 2, 3), (7, 8, 9))));
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut canvas = Canvas::new(10);
+        canvas.add_cube(cube((1, 2, 3), (4, 5, 6))).unwrap();
+        canvas.add_cube(cube((3, 2, 1), (4, 5, 6))).unwrap();
+        canvas.clear();
+        assert!(canvas.is_empty());
+        assert_eq!(canvas.side_len(), 10);
+    }
+
+    #[test]
+    fn test_serde() {
+        let mut canvas = Canvas::new(10);
+        canvas.add_cube(cube((3, 2, 1), (4, 5, 6))).unwrap();
+        canvas.add_cube(cube((1, 2, 3), (7, 8, 9))).unwrap();
+        let json = serde_json::to_string(&canvas).unwrap();
+        assert_eq!(
+            json,
+            r#"{"side_len":10,"cubes":[{"position":{"x":1,"y":2,"z":3},"colour":{"r":7,"g":8,"b":9}},{"position":{"x":3,"y":2,"z":1},"colour":{"r":4,"g":5,"b":6}}]}"#
+        );
+        assert_eq!(serde_json::from_str::<Canvas>(&json).unwrap(), canvas);
+        assert!(serde_json::from_str::<Canvas>(&json.replace("10", "2")).is_err());
+    }
+
+    #[test]
+    fn test_checksum() {
+        let mut canvas = Canvas::new(10);
+        canvas.add_cube(cube((1, 2, 3), (4, 5, 6))).unwrap();
+        canvas.add_cube(cube((3, 2, 1), (4, 5, 6))).unwrap();
+        canvas.recolour(pos(1, 2, 3), Colour::new(7, 8, 9)).unwrap();
+        canvas.add_cube(cube((5, 5, 5), (1, 1, 1))).unwrap();
+        canvas.remove_cube(pos(5, 5, 5)).unwrap();
+        let mut expected = Canvas::new(10);
+        expected.add_cube(cube((3, 2, 1), (4, 5, 6))).unwrap();
+        expected.add_cube(cube((1, 2, 3), (7, 8, 9))).unwrap();
+        assert_eq!(canvas.checksum(), expected.checksum());
+        assert!(canvas.differences(&expected).is_empty());
+
+        expected
+            .recolour(pos(1, 2, 3), Colour::new(4, 5, 6))
+            .unwrap();
+        expected.add_cube(cube((0, 0, 0), (1, 1, 1))).unwrap();
+        canvas.add_cube(cube((9, 9, 9), (1, 1, 1))).unwrap();
+        assert_ne!(canvas.checksum(), expected.checksum());
+        let difference = |position, actual, expected| CanvasDifference {
+            position,
+            actual,
+            expected,
+        };
+        assert_eq!(
+            canvas.differences(&expected),
+            vec![
+                difference(pos(0, 0, 0), None, Some(Colour::new(1, 1, 1))),
+                difference(
+                    pos(1, 2, 3),
+                    Some(Colour::new(7, 8, 9)),
+                    Some(Colour::new(4, 5, 6))
+                ),
+                difference(pos(9, 9, 9), Some(Colour::new(1, 1, 1)), None),
+            ]
+        );
+        canvas.clear();
+        assert_eq!(canvas.checksum(), Canvas::new(10).checksum());
+    }
+}
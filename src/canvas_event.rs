@@ -0,0 +1,170 @@
+use crate::{Canvas, CanvasError, Colour, Cube, Position};
+use serde::{Deserialize, Serialize};
+
+/// A change to the canvas. The canvas is the result of applying, in order,
+/// every event journaled in the archive.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CanvasEvent {
+    CubePlaced(Cube),
+    CubeRemoved(Position),
+    CanvasCleared,
+    Recoloured { position: Position, colour: Colour },
+}
+
+impl CanvasEvent {
+    /// Position the event changes, `None` when it changes the whole canvas.
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            CanvasEvent::CubePlaced(cube) => Some(cube.position),
+            CanvasEvent::CubeRemoved(position) | CanvasEvent::Recoloured { position, .. } => {
+                Some(*position)
+            }
+            CanvasEvent::CanvasCleared => None,
+        }
+    }
+
+    /// The event, moved to the position `remap` gives for its own. `None`
+    /// when `remap` drops its position.
+    pub fn remap(&self, remap: impl FnOnce(Position) -> Option<Position>) -> Option<CanvasEvent> {
+        Some(match self {
+            CanvasEvent::CubePlaced(cube) => CanvasEvent::CubePlaced(Cube {
+                position: remap(cube.position)?,
+                colour: cube.colour,
+            }),
+            CanvasEvent::CubeRemoved(position) => CanvasEvent::CubeRemoved(remap(*position)?),
+            CanvasEvent::CanvasCleared => CanvasEvent::CanvasCleared,
+            CanvasEvent::Recoloured { position, colour } => CanvasEvent::Recoloured {
+                position: remap(*position)?,
+                colour: *colour,
+            },
+        })
+    }
+}
+
+impl Canvas {
+    /// Checks whether an event could be applied, without applying it.
+    pub fn check(&self, event: &CanvasEvent) -> Result<(), CanvasError> {
+        match event {
+            CanvasEvent::CubePlaced(cube) => self.check_bounds(cube.position),
+            CanvasEvent::CubeRemoved(position) | CanvasEvent::Recoloured { position, .. } => {
+                self.check_bounds(*position)?;
+                if !self.contains(*position) {
+                    return Err(CanvasError::NoCube(*position));
+                }
+                Ok(())
+            }
+            CanvasEvent::CanvasCleared => Ok(()),
+        }
+    }
+
+    /// Applies an event to the canvas, leaving it untouched if the event is
+    /// not valid for the current state.
+    pub fn apply(&mut self, event: &CanvasEvent) -> Result<(), CanvasError> {
+        match event {
+            CanvasEvent::CubePlaced(cube) => self.add_cube(cube.clone()).map(|_| ()),
+            CanvasEvent::CubeRemoved(position) => self.remove_cube(*position).map(|_| ()),
+            CanvasEvent::CanvasCleared => {
+                self.clear();
+                Ok(())
+            }
+            CanvasEvent::Recoloured { position, colour } => {
+                self.recolour(*position, *colour).map(|_| ())
+            }
+        }
+    }
+
+    /// Rebuilds a canvas from a journal. Events which can't be applied, e.g.
+    /// because the canvas got smaller since they were recorded, are returned
+    /// along with the reason.
+    pub fn replay<'a>(
+        side_len: u32,
+        events: impl IntoIterator<Item = &'a CanvasEvent>,
+    ) -> (Canvas, Vec<(&'a CanvasEvent, CanvasError)>) {
+        let mut canvas = Canvas::new(side_len);
+        let mut skipped = Vec::new();
+        for event in events {
+            if let Err(e) = canvas.apply(event) {
+                skipped.push((event, e));
+            }
+        }
+        (canvas, skipped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Axis;
+
+    #[test]
+    fn test_replay() {
+        let events = vec![
+            CanvasEvent::CubePlaced(Cube::new(1, 2, 3, Colour::new(4, 5, 6))),
+            CanvasEvent::CubePlaced(Cube::new(3, 2, 1, Colour::new(4, 5, 6))),
+            CanvasEvent::Recoloured {
+                position: Position::new(1, 2, 3),
+                colour: Colour::new(7, 8, 9),
+            },
+            CanvasEvent::CubeRemoved(Position::new(3, 2, 1)),
+            CanvasEvent::CubeRemoved(Position::new(3, 2, 1)),
+        ];
+        let (canvas, skipped) = Canvas::replay(10, &events);
+        assert_eq!(
+            canvas.cubes().collect::<Vec<_>>(),
+            vec![Cube::new(1, 2, 3, Colour::new(7, 8, 9))]
+        );
+        assert_eq!(
+            skipped,
+            vec![(&events[4], CanvasError::NoCube(Position::new(3, 2, 1)))]
+        );
+
+        let (canvas, _) = Canvas::replay(
+            10,
+            events
+                .iter()
+                .chain(std::iter::once(&CanvasEvent::CanvasCleared)),
+        );
+        assert!(canvas.is_empty());
+    }
+
+    #[test]
+    fn test_check() {
+        let mut canvas = Canvas::new(10);
+        let placed = CanvasEvent::CubePlaced(Cube::new(1, 2, 3, Colour::new(4, 5, 6)));
+        let removed = CanvasEvent::CubeRemoved(Position::new(1, 2, 3));
+        assert_eq!(
+            canvas.check(&removed),
+            Err(CanvasError::NoCube(Position::new(1, 2, 3)))
+        );
+        assert_eq!(canvas.check(&placed), Ok(()));
+        assert!(canvas.is_empty());
+        canvas.apply(&placed).unwrap();
+        assert_eq!(canvas.check(&removed), Ok(()));
+        assert_eq!(
+            canvas.check(&CanvasEvent::CubePlaced(Cube::new(
+                10,
+                0,
+                0,
+                Colour::new(0, 0, 0)
+            ))),
+            Err(CanvasError::OutOfBounds {
+                axis: Axis::X,
+                coordinate: 10
+            })
+        );
+    }
+
+    #[test]
+    fn test_serde() {
+        let event = CanvasEvent::Recoloured {
+            position: Position::new(1, 2, 3),
+            colour: Colour::new(4, 5, 6),
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            json,
+            r#"{"Recoloured":{"position":{"x":1,"y":2,"z":3},"colour":{"r":4,"g":5,"b":6}}}"#
+        );
+        assert_eq!(serde_json::from_str::<CanvasEvent>(&json).unwrap(), event);
+    }
+}
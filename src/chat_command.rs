@@ -0,0 +1,266 @@
+use crate::{Colour, Shape};
+use std::convert::TryFrom;
+use std::str::FromStr;
+use thiserror::Error;
+
+// Longest placement accepted, in bytes once normalized: `x y z r g b a shape`
+// with coordinates of `MAX_DIGITS` digits is well under. Messages longer than
+// that many characters are refused before being normalized.
+const MAX_COMMAND_LEN: usize = 64;
+// Digits of a number at most, so that no coordinate overflows while parsed,
+// more than any canvas needs.
+const MAX_DIGITS: usize = 10;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ChatCommandError {
+    #[error("longer than {} characters", MAX_COMMAND_LEN)]
+    TooLong,
+    #[error("expected x y z r g b, or x y z r g b a, then optionally a shape")]
+    WrongArity,
+    #[error("invalid number {0}")]
+    InvalidNumber(String),
+    #[error("invalid r g b")]
+    InvalidColour,
+    #[error("invalid shape {0}, expected cube, slab or sphere")]
+    InvalidShape(String),
+}
+
+/// Placement sent in chat as `x y z r g b`, the coordinates in those of the
+/// channel, with the opacity `a` after them for a translucent cube, and last
+/// the shape, e.g. `slab`, for other shapes than cubes. Spaces and commas
+/// separate the numbers, however many of them, as mobile keyboards and other
+/// locales type them. Digits and a leading `-` make a number, the fullwidth
+/// and Arabic-Indic digits read as ASCII ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChatCommand {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+    pub colour: Colour,
+}
+
+impl FromStr for ChatCommand {
+    type Err = ChatCommandError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.chars().count() > MAX_COMMAND_LEN {
+            return Err(ChatCommandError::TooLong);
+        }
+        let value = normalize(value);
+        if value.len() > MAX_COMMAND_LEN {
+            return Err(ChatCommandError::TooLong);
+        }
+        let mut words: Vec<&str> = value.split_whitespace().collect();
+        let shape = match words.last() {
+            Some(word) if word.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                let shape = word
+                    .parse::<Shape>()
+                    .map_err(|_| ChatCommandError::InvalidShape((*word).to_owned()))?;
+                words.pop();
+                shape
+            }
+            _ => Shape::Cube,
+        };
+        let numbers = words
+            .into_iter()
+            .map(parse_number)
+            .collect::<Result<Vec<i64>, _>>()?;
+        let [x, y, z, r, g, b, a] = match numbers[..] {
+            [x, y, z, r, g, b] => [x, y, z, r, g, b, 255],
+            [x, y, z, r, g, b, a] => [x, y, z, r, g, b, a],
+            _ => return Err(ChatCommandError::WrongArity),
+        };
+        let channel = |c: i64| u8::try_from(c).map_err(|_| ChatCommandError::InvalidColour);
+        Ok(ChatCommand {
+            x,
+            y,
+            z,
+            colour: Colour::new(channel(r)?, channel(g)?, channel(b)?)
+                .with_alpha(channel(a)?)
+                .with_shape(shape),
+        })
+    }
+}
+
+// `value` with the digits and minus signs of other scripts in ASCII, and
+// commas as spaces.
+fn normalize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '０'..='９' => shift(c, '０', '0'),
+            '٠'..='٩' => shift(c, '٠', '0'),
+            '۰'..='۹' => shift(c, '۰', '0'),
+            '－' | '−' => '-',
+            ',' | '，' | '、' => ' ',
+            c => c,
+        })
+        .collect()
+}
+
+// `c` moved from the range starting at `from` to the one starting at `to`.
+fn shift(c: char, from: char, to: char) -> char {
+    char::from_u32(c as u32 - from as u32 + to as u32).unwrap_or(c)
+}
+
+// Number of at most `MAX_DIGITS` ASCII digits, negative with a leading `-`.
+// `str::parse` alone would take a `+`.
+fn parse_number(value: &str) -> Result<i64, ChatCommandError> {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    let valid = !digits.is_empty()
+        && digits.len() <= MAX_DIGITS
+        && digits.bytes().all(|b| b.is_ascii_digit());
+    match valid {
+        true => value
+            .parse()
+            .map_err(|_| ChatCommandError::InvalidNumber(value.to_owned())),
+        false => Err(ChatCommandError::InvalidNumber(value.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "1 -2 3 4 5 255".parse(),
+            Ok(ChatCommand {
+                x: 1,
+                y: -2,
+                z: 3,
+                colour: Colour::new(4, 5, 255),
+            })
+        );
+        let error = |value: &str| value.parse::<ChatCommand>().unwrap_err();
+        assert_eq!(error("1 2 3 4 5"), ChatCommandError::WrongArity);
+        assert_eq!(
+            "1 2 3 4 5 6 128".parse::<ChatCommand>().unwrap().colour,
+            Colour::new(4, 5, 6).with_alpha(128)
+        );
+        assert_eq!(error("1 2 3 4 5 6 7 8"), ChatCommandError::WrongArity);
+        assert_eq!(
+            "1 2 3 4 5 6 slab".parse::<ChatCommand>().unwrap().colour,
+            Colour::new(4, 5, 6).with_shape(Shape::Slab)
+        );
+        assert_eq!(
+            "1 2 3 4 5 6 128 sphere"
+                .parse::<ChatCommand>()
+                .unwrap()
+                .colour,
+            Colour::new(4, 5, 6)
+                .with_alpha(128)
+                .with_shape(Shape::Sphere)
+        );
+        assert_eq!(
+            error("1 2 3 4 5 6 pyramid"),
+            ChatCommandError::InvalidShape("pyramid".to_owned())
+        );
+        assert_eq!(error("1 2 3 slab"), ChatCommandError::WrongArity);
+        assert_eq!(
+            error("1 2 3 slab 4 5 6"),
+            ChatCommandError::InvalidNumber("slab".to_owned())
+        );
+        assert_eq!(error("1 2 3 4 5 6 256"), ChatCommandError::InvalidColour);
+        assert_eq!(error("1 2 3 4 5 256"), ChatCommandError::InvalidColour);
+        assert_eq!(error("1 2 3 4 5 -1"), ChatCommandError::InvalidColour);
+        assert_eq!(error(&"1 ".repeat(5000)), ChatCommandError::TooLong);
+        let invalid = |number: &str| ChatCommandError::InvalidNumber(number.to_owned());
+        assert_eq!(error("+1 2 3 4 5 6"), invalid("+1"));
+        assert_eq!(error("- 2 3 4 5 6"), invalid("-"));
+        assert_eq!(error("1 2 3 4 5 6."), invalid("6."));
+        // Mathematical digits aren't typed by keyboards.
+        assert_eq!(error("𝟙 2 3 4 5 6"), invalid("𝟙"));
+        assert_eq!(error("99999999999 2 3 4 5 6"), invalid("99999999999"));
+        assert_eq!(
+            error("9999999999 2 3 4 5 6999"),
+            ChatCommandError::InvalidColour
+        );
+    }
+
+    #[test]
+    fn test_parse_separators() {
+        let expected = Ok(ChatCommand {
+            x: 1,
+            y: -2,
+            z: 3,
+            colour: Colour::new(4, 5, 255),
+        });
+        for message in &[
+            "1  -2 3\t4 5 255",
+            " 1 -2 3 4 5 255 ",
+            "1,-2,3,4,5,255",
+            "1, -2, 3, 4, 5, 255",
+            "１ －２ ３ ４ ５ ２５５",
+            "１，−２，３、４、５、２５５",
+            "1\u{3000}-2\u{3000}3 4 5 255",
+            "١ -٢ ٣ ٤ ٥ ٢٥٥",
+            "۱ -۲ ۳ ۴ ۵ ۲۵۵",
+        ] {
+            assert_eq!(message.parse(), expected, "{:?}", message);
+        }
+        assert_eq!(
+            "1,2,3,4,5,6,slab".parse::<ChatCommand>().unwrap().colour,
+            Colour::new(4, 5, 6).with_shape(Shape::Slab)
+        );
+        // Fullwidth digits are longer in bytes, but not refused for it.
+        assert!("１００ １００ １００ ２５５ ２５５ ２５５ ２５５"
+            .parse::<ChatCommand>()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_fuzz() {
+        // Mostly valid pieces, mixed with what chat throws at the bot.
+        const PIECES: &[&str] = &[
+            "0",
+            "1",
+            "42",
+            "255",
+            "256",
+            "-",
+            "-7",
+            " ",
+            "  ",
+            "+",
+            "9999999999",
+            "18446744073709551616",
+            "٣",
+            "５",
+            "𝟙",
+            "é",
+            "\u{200b}",
+            "\t",
+            "\n",
+            "!",
+            "x",
+            "🟥",
+            "slab",
+            "Sphere",
+        ];
+        let rng = fastrand::Rng::with_seed(7);
+        for _ in 0..20_000 {
+            let len = rng.usize(..16);
+            let message: String = (0..len)
+                .map(|_| PIECES[rng.usize(..PIECES.len())])
+                .collect();
+            if let Ok(command) = message.parse::<ChatCommand>() {
+                let normalized = normalize(&message);
+                assert!(normalized.len() <= MAX_COMMAND_LEN, "{:?}", message);
+                let numbers = normalized.trim_end().trim_end_matches(char::is_alphabetic);
+                assert!(numbers
+                    .chars()
+                    .all(|c| c.is_whitespace() || c == '-' || c.is_ascii_digit()));
+                let Colour {
+                    r, g, b, a, shape, ..
+                } = command.colour;
+                let formatted = format!(
+                    "{} {} {} {} {} {} {} {}",
+                    command.x, command.y, command.z, r, g, b, a, shape
+                );
+                assert_eq!(formatted.parse(), Ok(command), "{:?}", message);
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use crate::Colour;
+use serde::Deserialize;
+
+/// Corrections applied to the colours of the cubes before they're drawn, on
+/// their sRGB values. The defaults leave the colours as chosen.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ColourCorrection {
+    /// Above 1 lifts the dark colours, below 1 darkens them.
+    pub gamma: f32,
+    /// Added to every channel, from -1 (black) to 1 (white).
+    pub brightness: f32,
+    /// Spread of the channels around mid-grey, 1 keeps it and 0 makes every
+    /// colour grey.
+    pub contrast: f32,
+}
+
+impl Default for ColourCorrection {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            brightness: 0.0,
+            contrast: 1.0,
+        }
+    }
+}
+
+impl ColourCorrection {
+    /// Corrects a channel in [0, 1]: contrast first, then brightness, then
+    /// gamma.
+    pub fn channel(&self, c: f32) -> f32 {
+        let c = ((c - 0.5) * self.contrast + 0.5 + self.brightness).clamp(0.0, 1.0);
+        c.powf(1.0 / self.gamma.max(f32::EPSILON))
+    }
+
+    /// Channels of `colour` corrected and scaled to [0, 1], still in sRGB, as
+    /// drawn by the renderers which don't convert colours.
+    pub fn srgb(&self, colour: Colour) -> (f32, f32, f32) {
+        let (r, g, b) = colour.to_f32();
+        (self.channel(r), self.channel(g), self.channel(b))
+    }
+
+    /// Channels of `colour` corrected and in linear light, as shaded by the
+    /// raytracer.
+    pub fn linear(&self, colour: Colour) -> [f32; 3] {
+        let (r, g, b) = self.srgb(colour);
+        [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b)]
+    }
+}
+
+/// Decodes an sRGB channel in [0, 1] to linear light, with the piecewise
+/// curve of the standard rather than a 2.2 power, which crushes the darkest
+/// colours.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes linear light in [0, 1] to an sRGB channel, see `srgb_to_linear`.
+pub fn linear_to_srgb(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "{} instead of {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_srgb() {
+        // Reference values of the sRGB transfer function.
+        for (srgb, linear) in [
+            (0.0, 0.0),
+            (0.04045, 0.003_130_8),
+            (10.0 / 255.0, 0.003_035_3),
+            (0.5, 0.214_041),
+            (128.0 / 255.0, 0.215_861),
+            (0.735_357, 0.5),
+            (1.0, 1.0),
+        ] {
+            assert_close(srgb_to_linear(srgb), linear);
+            assert_close(linear_to_srgb(linear), srgb);
+        }
+        for c in 0..=255u8 {
+            let encoded = linear_to_srgb(srgb_to_linear(c as f32 / 255.0));
+            assert_eq!((encoded * 255.0).round() as u8, c);
+        }
+        assert_close(linear_to_srgb(1.5), 1.0);
+    }
+
+    #[test]
+    fn test_colour_correction() {
+        let colour = Colour::new(51, 102, 255);
+        let (r, g, b) = ColourCorrection::default().srgb(colour);
+        assert_close(r, 0.2);
+        assert_close(g, 0.4);
+        assert_close(b, 1.0);
+
+        let brighter = ColourCorrection {
+            brightness: 0.1,
+            ..Default::default()
+        };
+        let (r, g, b) = brighter.srgb(colour);
+        assert_close(r, 0.3);
+        assert_close(g, 0.5);
+        assert_close(b, 1.0);
+
+        let contrast = ColourCorrection {
+            contrast: 0.5,
+            ..Default::default()
+        };
+        assert_close(contrast.channel(0.0), 0.25);
+        assert_close(contrast.channel(0.5), 0.5);
+        assert_close(contrast.channel(1.0), 0.75);
+
+        let gamma = ColourCorrection {
+            gamma: 2.0,
+            ..Default::default()
+        };
+        assert_close(gamma.channel(0.25), 0.5);
+        assert_close(gamma.linear(Colour::new(0, 0, 0))[0], 0.0);
+        assert_close(gamma.linear(Colour::new(255, 255, 255))[0], 1.0);
+    }
+}
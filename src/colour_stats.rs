@@ -0,0 +1,237 @@
+use crate::{CanvasEvent, Colour, JournalEntry, Palette};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Cubes placed in a colour, whatever their opacity.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ColourCount {
+    pub colour: Colour,
+    pub count: u64,
+}
+
+/// Cubes placed in colours closest to a named one.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NamedCount {
+    pub name: &'static str,
+    pub count: u64,
+}
+
+/// Cubes placed over a period, and how many of them in a colour of the
+/// palette.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Compliance {
+    /// Unix timestamp the period starts at.
+    pub since: i64,
+    pub placed: u64,
+    pub in_palette: u64,
+}
+
+/// Colours chat placed its cubes in, as served by the HTTP API, charted by
+/// the report and turned into trivia by `!colours`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ColourStats {
+    pub placed: u64,
+    /// Most placed first.
+    pub exact: Vec<ColourCount>,
+    /// Most placed first.
+    pub named: Vec<NamedCount>,
+    /// By periods of the bucket from the first one with a placement, empty
+    /// ones included. Empty without a palette.
+    pub compliance: Vec<Compliance>,
+}
+
+impl ColourStats {
+    /// Counts the cubes placed in `journal` since the unix timestamp `since`,
+    /// the compliance with `palette` by periods of `bucket_secs`.
+    pub fn new(
+        journal: &[JournalEntry],
+        since: i64,
+        bucket_secs: i64,
+        palette: Option<&Palette>,
+    ) -> Self {
+        let bucket_secs = bucket_secs.max(1);
+        let mut exact: HashMap<(u8, u8, u8), u64> = HashMap::new();
+        let mut named: HashMap<&'static str, u64> = HashMap::new();
+        let mut buckets: HashMap<i64, (u64, u64)> = HashMap::new();
+        let mut placed = 0;
+        for entry in journal {
+            let timestamp = entry.metadata.timestamp;
+            let colour = match &entry.event {
+                CanvasEvent::CubePlaced(cube) if timestamp >= since => cube.colour,
+                _ => continue,
+            };
+            placed += 1;
+            *exact.entry((colour.r, colour.g, colour.b)).or_default() += 1;
+            *named.entry(colour.name()).or_default() += 1;
+            if let Some(palette) = palette {
+                let bucket = buckets
+                    .entry(timestamp.div_euclid(bucket_secs) * bucket_secs)
+                    .or_default();
+                bucket.0 += 1;
+                if in_palette(palette, colour) {
+                    bucket.1 += 1;
+                }
+            }
+        }
+
+        let mut exact: Vec<_> = exact
+            .into_iter()
+            .map(|((r, g, b), count)| ColourCount {
+                colour: Colour::new(r, g, b),
+                count,
+            })
+            .collect();
+        exact.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then(a.colour.to_string().cmp(&b.colour.to_string()))
+        });
+        let mut named: Vec<_> = named
+            .into_iter()
+            .map(|(name, count)| NamedCount { name, count })
+            .collect();
+        named.sort_by(|a, b| b.count.cmp(&a.count).then(a.name.cmp(b.name)));
+        let compliance = match (buckets.keys().min(), buckets.keys().max()) {
+            (Some(&first), Some(&last)) => (0..=(last - first) / bucket_secs)
+                .map(|i| first + i * bucket_secs)
+                .map(|since| {
+                    let (placed, in_palette) = buckets.get(&since).copied().unwrap_or((0, 0));
+                    Compliance {
+                        since,
+                        placed,
+                        in_palette,
+                    }
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        Self {
+            placed,
+            exact,
+            named,
+            compliance,
+        }
+    }
+
+    /// Share of the cubes placed in a colour of the palette, from 0 to 1,
+    /// `None` without a palette or cubes.
+    pub fn compliance_share(&self) -> Option<f32> {
+        let (placed, in_palette) = self
+            .compliance
+            .iter()
+            .fold((0, 0), |(placed, in_palette), bucket| {
+                (placed + bucket.placed, in_palette + bucket.in_palette)
+            });
+        match placed {
+            0 => None,
+            placed => Some(in_palette as f32 / placed as f32),
+        }
+    }
+}
+
+// Whether `colour` is exactly one of the palette, whatever its opacity.
+fn in_palette(palette: &Palette, colour: Colour) -> bool {
+    palette
+        .colours()
+        .iter()
+        .any(|c| (c.r, c.g, c.b) == (colour.r, colour.g, colour.b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cube, EventMetadata, Position};
+    use uuid::Uuid;
+
+    fn entry(event: CanvasEvent, timestamp: i64) -> JournalEntry {
+        JournalEntry {
+            command_id: Uuid::new_v4(),
+            event,
+            metadata: EventMetadata {
+                timestamp,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn placed(x: u32, colour: Colour, timestamp: i64) -> JournalEntry {
+        entry(
+            CanvasEvent::CubePlaced(Cube::new(x, 0, 0, colour)),
+            timestamp,
+        )
+    }
+
+    #[test]
+    fn test_colour_stats() {
+        let red = Colour::new(255, 0, 0);
+        let dark_red = Colour::new(230, 10, 10);
+        let blue = Colour::new(0, 0, 255);
+        let journal = vec![
+            placed(0, blue, 100),
+            placed(1, red, 3600),
+            placed(2, dark_red, 3700),
+            entry(CanvasEvent::CubeRemoved(Position::new(1, 0, 0)), 3800),
+            placed(3, blue.with_alpha(128), 3 * 3600),
+            placed(4, red, 3 * 3600 + 5),
+        ];
+        let palette = Palette::new(vec![red, Colour::new(0, 0, 0)]).unwrap();
+        let stats = ColourStats::new(&journal, 3600, 3600, Some(&palette));
+        assert_eq!(stats.placed, 4);
+        assert_eq!(
+            stats.exact,
+            vec![
+                ColourCount {
+                    colour: red,
+                    count: 2
+                },
+                ColourCount {
+                    colour: blue,
+                    count: 1
+                },
+                ColourCount {
+                    colour: dark_red,
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(
+            stats.named,
+            vec![
+                NamedCount {
+                    name: "red",
+                    count: 3
+                },
+                NamedCount {
+                    name: "blue",
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(
+            stats.compliance,
+            vec![
+                Compliance {
+                    since: 3600,
+                    placed: 2,
+                    in_palette: 1
+                },
+                Compliance {
+                    since: 7200,
+                    placed: 0,
+                    in_palette: 0
+                },
+                Compliance {
+                    since: 3 * 3600,
+                    placed: 2,
+                    in_palette: 1
+                },
+            ]
+        );
+        assert_eq!(stats.compliance_share(), Some(0.5));
+
+        let stats = ColourStats::new(&journal, 0, 3600, None);
+        assert_eq!(stats.placed, 5);
+        assert!(stats.compliance.is_empty());
+        assert_eq!(stats.compliance_share(), None);
+    }
+}
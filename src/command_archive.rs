@@ -1,99 +1,1520 @@
-use crate::Cube;
-use rusqlite::Connection;
+use crate::retention::prunable;
+use crate::{
+    Canvas, CanvasEvent, Colour, Competition, Cube, Position, PruneReport, Region, Resample, Shape,
+};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 use thiserror::Error;
+use uuid::Uuid;
 
 // CubeArchive
 //    new
+//    getEvents -> Vec<CanvasEvent>
+//    getJournal -> Vec<JournalEntry>
+//    appendEvent / appendEvents
+//    containsCommand
 //    getCubes -> Vec<Cube>
+//    placements -> HashMap<Position, (Colour, JournalEntry)>
+//    lookup -> Option<(Colour, JournalEntry)>
+//    history -> Vec<JournalEntry>
+//    stats -> CanvasStats
 //    addCube
-//    deleteCube
+//    startCompetition / activeCompetition / finishCompetition
+//    competitionContributions -> Vec<(String, u64)>
+//    setTeam / getTeams -> HashMap<String, String>
+//    setIgnored / getIgnored -> HashSet<String>
+//    quarantine / releaseQuarantine -> Vec<Cube>
+//    getProgression -> (stage, cubes placed) / setProgressionStage
+//    setProtected / getProtected -> Vec<Region>
+//    setAlias / getAliases -> HashMap<String, String>
+//    importChunk / importProgress -> Option<u64>
+//    backup / remapPositions -> (kept, dropped)
+//    resample -> cubes
+//    prune -> PruneReport
+//    externalEvents -> Vec<JournalEntry>
+//    reopen -> CubeArchive
+//
+// The archive is an append-only journal of canvas events, stored as JSON in
+// the events table. Older archives only had the placed cubes in the cubes
+// table, those are imported as CubePlaced events the first time they're
+// opened. Each event records the id of the command that produced it, so a
+// command delivered more than once is journaled only once.
 pub struct CubeArchive {
     sqlite_path: std::path::PathBuf,
+    pool: Arc<ConnectionPool>,
     connection: Option<Connection>,
+    watch: Option<Watch>,
+}
+
+// Idle connections kept by a pool at most.
+const POOL_SIZE: usize = 4;
+
+// Connections to an archive, shared by the CubeArchive and those reopened
+// from it. Each archive holds one while open, which goes back to the pool
+// when it's dropped, its prepared statements still cached, so that reopening
+// the archive neither connects nor prepares them again.
+#[derive(Default)]
+struct ConnectionPool {
+    idle: Mutex<Vec<Connection>>,
+    // Whether the tables were brought up to date, once for all connections.
+    migrated: Mutex<bool>,
+}
+
+impl ConnectionPool {
+    fn put(&self, conn: Connection) {
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < POOL_SIZE {
+            idle.push(conn);
+        }
+    }
+}
+
+// What's known of the journal while watching for events journaled by other
+// processes: SQLite's data_version only changes with their commits.
+struct Watch {
+    data_version: i64,
+    last_id: i64,
+    // Events journaled here since the last check, which they're told from.
+    own_ids: HashSet<i64>,
+}
+
+/// Journaled along with each event.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EventMetadata {
+    /// Login of the chatter who caused the event, if any.
+    pub author: Option<String>,
+    /// Unix timestamp in seconds, 0 for events journaled before timestamps
+    /// were recorded.
+    pub timestamp: i64,
+    /// Competition running when the event was journaled.
+    pub competition_id: Option<i64>,
+    /// Team of the author when the event was journaled.
+    pub team: Option<String>,
+    /// Chat message the event originates from, if any.
+    pub message: Option<String>,
+    /// Whether a chatter placed it from chat, on the viewer lane, rather than
+    /// the bot, e.g. terrain, imports, templates or a restored checkpoint.
+    /// Only those count towards the goal of the day and the progression.
+    #[serde(default)]
+    pub viewer: bool,
+}
+
+/// Figures about the canvas and its builders.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CanvasStats {
+    pub cubes: usize,
+    /// Share of the canvas volume filled with cubes, between 0 and 100.
+    pub fill_percentage: f64,
+    /// Chatters who placed cubes in the last 24 hours.
+    pub builders_today: usize,
+    /// Cubes placed in the last hour.
+    pub cubes_last_hour: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Id of the command which produced the event.
+    pub command_id: Uuid,
+    pub event: CanvasEvent,
+    pub metadata: EventMetadata,
+}
+
+// Cube of a checkpoint, with the metadata of its placement.
+#[derive(Serialize, Deserialize)]
+struct CheckpointCube {
+    cube: Cube,
+    metadata: EventMetadata,
 }
 
 #[derive(Error, Debug)]
 pub enum CubeArchiveError {
     #[error("error from rusqlite {0}")]
     Rusqlite(#[from] rusqlite::Error),
+    #[error("error from serde_json {0}")]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("invalid command id {0}")]
+    Uuid(#[from] uuid::Error),
+    #[error("unexpected event {0:?} in the journal")]
+    UnexpectedEvent(CanvasEvent),
 }
 
 impl CubeArchive {
     pub fn new(sqlite_path: std::path::PathBuf) -> Self {
         Self {
             sqlite_path,
+            pool: Arc::new(ConnectionPool::default()),
+            connection: None,
+            watch: None,
+        }
+    }
+
+    /// Another connection to the same archive, e.g. to read it from another
+    /// thread, taken from the connections of the archives reopened before
+    /// and since dropped if any.
+    pub fn reopen(&self) -> Self {
+        Self {
+            sqlite_path: self.sqlite_path.clone(),
+            pool: self.pool.clone(),
             connection: None,
+            watch: None,
         }
     }
 
+    /// Takes a connection to the archive, creating its tables or bringing
+    /// them up to date the first time.
     pub fn init(&mut self) -> Result<(), CubeArchiveError> {
-        let conn = Connection::open(&self.sqlite_path)?;
+        let idle = self.pool.idle.lock().unwrap().pop();
+        let mut conn = match idle {
+            Some(conn) => conn,
+            None => Connection::open(&self.sqlite_path)?,
+        };
+        let mut migrated = self.pool.migrated.lock().unwrap();
+        if !*migrated {
+            migrate(&mut conn)?;
+            *migrated = true;
+        }
+        drop(migrated);
+        if let Some(previous) = self.connection.replace(conn) {
+            self.pool.put(previous);
+        }
+        Ok(())
+    }
 
-        // create tables if not exist
-        conn.execute(
-            "create table if not exists cubes (
-             x integer not null,
-             y integer not null,
-             z integer not null,
-             r integer not null,
-             g integer not null,
-             b integer not null
-         )",
+    /// Journals the event produced by the command `command_id`. Returns false,
+    /// without journaling anything, if the command was already journaled.
+    pub fn append_event(
+        &mut self,
+        command_id: Uuid,
+        event: &CanvasEvent,
+        metadata: &EventMetadata,
+    ) -> Result<bool, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let conn = self.connection.as_ref().unwrap();
+        let inserted = insert_event(conn, command_id, event, metadata)?;
+        if let (true, Some(watch)) = (inserted, self.watch.as_mut()) {
+            watch.own_ids.insert(conn.last_insert_rowid());
+        }
+        Ok(inserted)
+    }
+
+    /// Journals `events` in a single transaction, each with a command id of
+    /// its own, e.g. for the edits of an operator.
+    pub fn append_events(
+        &mut self,
+        events: &[CanvasEvent],
+        metadata: &EventMetadata,
+    ) -> Result<(), CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let tx = self.connection.as_mut().unwrap().transaction()?;
+        for event in events {
+            insert_event(&tx, Uuid::new_v4(), event, metadata)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Events journaled by other processes since the last call, oldest
+    /// first, e.g. by `populate_from_csv` while the bot runs. The first call
+    /// starts watching, and returns none.
+    pub fn external_events(&mut self) -> Result<Vec<JournalEntry>, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let conn = self.connection.as_ref().unwrap();
+        // Read first: what's journaled before an unchanged version is ours.
+        let max_id: i64 = conn.query_row("SELECT coalesce(max(id), 0) from events", [], |row| {
+            row.get(0)
+        })?;
+        let data_version: i64 = conn.query_row("PRAGMA data_version", [], |row| row.get(0))?;
+        let watch = match self.watch.as_mut() {
+            Some(watch) => watch,
+            None => {
+                self.watch = Some(Watch {
+                    data_version,
+                    last_id: max_id,
+                    own_ids: HashSet::new(),
+                });
+                return Ok(Vec::new());
+            }
+        };
+        if data_version == watch.data_version {
+            watch.last_id = watch.last_id.max(max_id);
+            watch.own_ids.clear();
+            return Ok(Vec::new());
+        }
+        let entries = read_journal(conn, watch.last_id)?;
+        watch.data_version = data_version;
+        if let Some((id, _)) = entries.last() {
+            watch.last_id = *id;
+        }
+        let own_ids = std::mem::take(&mut watch.own_ids);
+        Ok(entries
+            .into_iter()
+            .filter(|(id, _)| !own_ids.contains(id))
+            .map(|(_, entry)| entry)
+            .collect())
+    }
+
+    pub fn contains_command(&mut self, command_id: Uuid) -> Result<bool, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let count: i64 = self
+            .connection
+            .as_ref()
+            .unwrap()
+            .prepare_cached("SELECT count(*) from events where command_id = ?1")?
+            .query_row([command_id.to_string()], |row| row.get(0))?;
+        Ok(count > 0)
+    }
+
+    /// All the journaled events, oldest first.
+    pub fn get_journal(&mut self) -> Result<Vec<JournalEntry>, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let journal = read_journal(self.connection.as_ref().unwrap(), 0)?;
+        Ok(journal.into_iter().map(|(_, entry)| entry).collect())
+    }
+
+    /// All the journaled events, oldest first.
+    pub fn get_events(&mut self) -> Result<Vec<CanvasEvent>, CubeArchiveError> {
+        Ok(self
+            .get_journal()?
+            .into_iter()
+            .map(|entry| entry.event)
+            .collect())
+    }
+
+    pub fn add_cube(&mut self, cube: Cube) -> Result<(), CubeArchiveError> {
+        self.append_event(
+            Uuid::new_v4(),
+            &CanvasEvent::CubePlaced(cube),
+            &EventMetadata::default(),
+        )
+        .map(|_| ())
+    }
+
+    /// Cubes currently on the canvas according to the journal, sorted by
+    /// position.
+    pub fn get_cubes(&mut self) -> Result<Vec<Cube>, CubeArchiveError> {
+        let events = self.get_events()?;
+        // The archive doesn't know the size of the canvas, bounds are checked
+        // by whoever consumes the cubes.
+        let (canvas, _) = Canvas::replay(u32::MAX, &events);
+        let mut cubes: Vec<Cube> = canvas.cubes().collect();
+        cubes.sort_by_key(|cube| cube.position);
+        Ok(cubes)
+    }
+
+    /// Current colour of each cube on the canvas, along with the entry of the
+    /// event which placed it. Recolouring, e.g. when the cube decays, doesn't
+    /// change who placed it.
+    pub fn placements(
+        &mut self,
+    ) -> Result<HashMap<Position, (Colour, JournalEntry)>, CubeArchiveError> {
+        self.query_placements(None)
+    }
+
+    /// The cube at `position`, see `placements`.
+    pub fn lookup(
+        &mut self,
+        position: Position,
+    ) -> Result<Option<(Colour, JournalEntry)>, CubeArchiveError> {
+        Ok(self.query_placements(Some(position))?.remove(&position))
+    }
+
+    /// The last `limit` events at `position`, latest first, e.g. to find who
+    /// overwrote a cube.
+    pub fn history(
+        &mut self,
+        position: Position,
+        limit: usize,
+    ) -> Result<Vec<JournalEntry>, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let history = read_entries(
+            self.connection.as_ref().unwrap(),
+            "e.x = ?1 and e.y = ?2 and e.z = ?3 order by e.id desc limit ?4",
+            rusqlite::params![position.x, position.y, position.z, limit as i64],
+        )?;
+        Ok(history.into_iter().map(|(_, entry)| entry).collect())
+    }
+
+    // Placements of the cubes on the canvas, or at `position` only, from the
+    // positions of the events journaled since the last clear: a cube is there
+    // if it was placed after it was last removed, with the colour of the
+    // last recolouring after that, if any.
+    fn query_placements(
+        &mut self,
+        position: Option<Position>,
+    ) -> Result<HashMap<Position, (Colour, JournalEntry)>, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let conn = self.connection.as_ref().unwrap();
+        let filter = match position {
+            Some(_) => "and e.x = ?1 and e.y = ?2 and e.z = ?3",
+            None => "",
+        };
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT p.command_id, p.event, p.author, p.timestamp, p.competition_id, p.team,
+             p.message, p.viewer, r.event
+             from ({}) s
+             join events p on p.id = s.placed
+             left join events r on r.id = s.recoloured and s.recoloured > s.placed
+             where s.placed > coalesce(s.removed, 0)",
+            live_positions(filter)
+        ))?;
+        let params: Vec<u32> = position.iter().flat_map(|p| vec![p.x, p.y, p.z]).collect();
+        let mapped_entries = stmt.query_map(rusqlite::params_from_iter(params), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                EventMetadata {
+                    author: row.get(2)?,
+                    timestamp: row.get(3)?,
+                    competition_id: row.get(4)?,
+                    team: row.get(5)?,
+                    message: row.get(6)?,
+                    viewer: row.get(7)?,
+                },
+                row.get::<_, Option<String>>(8)?,
+            ))
+        })?;
+        let mut placements = HashMap::new();
+        for entry in mapped_entries {
+            let (command_id, event, metadata, recoloured) = entry?;
+            let cube = match serde_json::from_str(&event)? {
+                CanvasEvent::CubePlaced(cube) => cube,
+                event => return Err(CubeArchiveError::UnexpectedEvent(event)),
+            };
+            let colour = match recoloured.map(|event| serde_json::from_str(&event)) {
+                Some(Ok(CanvasEvent::Recoloured { colour, .. })) => colour,
+                Some(Ok(event)) => return Err(CubeArchiveError::UnexpectedEvent(event)),
+                Some(Err(e)) => return Err(e.into()),
+                None => cube.colour,
+            };
+            let entry = JournalEntry {
+                command_id: Uuid::parse_str(&command_id)?,
+                event: CanvasEvent::CubePlaced(cube.clone()),
+                metadata,
+            };
+            placements.insert(cube.position, (colour, entry));
+        }
+        Ok(placements)
+    }
+
+    /// Statistics of a canvas of side `side_len`, at the unix timestamp `now`.
+    pub fn stats(&mut self, side_len: u32, now: i64) -> Result<CanvasStats, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let conn = self.connection.as_ref().unwrap();
+        let cubes = conn.query_row(
+            &format!(
+                "SELECT count(*) from ({}) s where s.placed > coalesce(s.removed, 0)",
+                live_positions("")
+            ),
             [],
+            |row| row.get::<_, i64>(0),
+        )? as usize;
+        // Events are stored as externally tagged JSON.
+        let placed_since = |since: i64, count: &str| -> Result<usize, rusqlite::Error> {
+            conn.query_row(
+                &format!(
+                    "SELECT {} from events e
+                     where e.timestamp >= ?1 and e.event like '{{\"CubePlaced\"%'",
+                    count
+                ),
+                [since],
+                |row| row.get::<_, i64>(0).map(|count| count as usize),
+            )
+        };
+        let volume = side_len as f64 * side_len as f64 * side_len as f64;
+        Ok(CanvasStats {
+            cubes,
+            fill_percentage: if volume > 0.0 {
+                cubes as f64 * 100.0 / volume
+            } else {
+                0.0
+            },
+            builders_today: placed_since(now - 24 * 3600, "count(distinct e.author)")?,
+            cubes_last_hour: placed_since(now - 3600, "count(*)")?,
+        })
+    }
+
+    pub fn start_competition(
+        &mut self,
+        name: &str,
+        started_at: i64,
+        ends_at: i64,
+    ) -> Result<Competition, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let conn = self.connection.as_ref().unwrap();
+        conn.execute(
+            "INSERT INTO competitions (name, started_at, ends_at) values (?1, ?2, ?3)",
+            rusqlite::params![name, started_at, ends_at],
         )?;
-        self.connection = Some(conn);
+        Ok(Competition {
+            id: conn.last_insert_rowid(),
+            name: name.to_owned(),
+            started_at,
+            ends_at,
+        })
+    }
+
+    /// The competition not finished yet, if any, e.g. to resume it after a
+    /// restart.
+    pub fn active_competition(&mut self) -> Result<Option<Competition>, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        Ok(self
+            .connection
+            .as_ref()
+            .unwrap()
+            .query_row(
+                "SELECT c.id, c.name, c.started_at, c.ends_at from competitions c
+                 where c.finished = 0 order by c.id desc limit 1",
+                [],
+                |row| {
+                    Ok(Competition {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        started_at: row.get(2)?,
+                        ends_at: row.get(3)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    pub fn finish_competition(&mut self, id: i64) -> Result<(), CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        self.connection
+            .as_ref()
+            .unwrap()
+            .execute("UPDATE competitions SET finished = 1 where id = ?1", [id])?;
         Ok(())
     }
 
-    pub fn add_cube(&mut self, cube: Cube) -> Result<(), CubeArchiveError> {
+    /// Cubes each chatter placed from chat during a competition, most first.
+    pub fn competition_contributions(
+        &mut self,
+        id: i64,
+    ) -> Result<Vec<(String, u64)>, CubeArchiveError> {
+        let mut contributions: Vec<(String, u64)> = Vec::new();
+        for entry in self.get_journal()? {
+            let author = match (&entry.event, entry.metadata.author) {
+                (CanvasEvent::CubePlaced(_), Some(author))
+                    if entry.metadata.competition_id == Some(id) && entry.metadata.viewer =>
+                {
+                    author
+                }
+                _ => continue,
+            };
+            match contributions.iter_mut().find(|(a, _)| *a == author) {
+                Some((_, count)) => *count += 1,
+                None => contributions.push((author, 1)),
+            }
+        }
+        // Stable, so ties are in order of first placement.
+        contributions.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        Ok(contributions)
+    }
+
+    /// Makes `login` a member of `team`, leaving any previous team.
+    pub fn set_team(&mut self, login: &str, team: &str) -> Result<(), CubeArchiveError> {
         if self.connection.is_none() {
             self.init()?;
         }
         self.connection.as_ref().unwrap().execute(
-            "INSERT INTO cubes (x, y, z, r, g, b) values (?1, ?2, ?3, ?4, ?5, ?6)",
-            rusqlite::params![
-                cube.position.0,
-                cube.position.1,
-                cube.position.2,
-                cube.colour.0,
-                cube.colour.1,
-                cube.colour.2,
-            ],
+            "INSERT OR REPLACE INTO team_members (login, team) values (?1, ?2)",
+            [login, team],
         )?;
         Ok(())
     }
 
-    pub fn get_cubes(&mut self) -> Result<Vec<Cube>, CubeArchiveError> {
+    /// Team of each chatter who joined one.
+    pub fn get_teams(&mut self) -> Result<HashMap<String, String>, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let conn = self.connection.as_ref().unwrap();
+        let mut stmt = conn.prepare("SELECT t.login, t.team from team_members t")?;
+        let mapped_members = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(mapped_members.collect::<Result<_, _>>()?)
+    }
+
+    /// Saves the achievement `login` unlocked at the unix timestamp
+    /// `unlocked_at`, unless they already had it.
+    pub fn unlock_achievement(
+        &mut self,
+        login: &str,
+        achievement: &str,
+        unlocked_at: i64,
+    ) -> Result<(), CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        self.connection.as_ref().unwrap().execute(
+            "INSERT OR IGNORE INTO achievements (login, achievement, unlocked_at)
+             values (?1, ?2, ?3)",
+            rusqlite::params![login, achievement, unlocked_at],
+        )?;
+        Ok(())
+    }
+
+    /// Achievements unlocked by each chatter, in the order they were.
+    pub fn get_achievements(&mut self) -> Result<Vec<(String, String)>, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let conn = self.connection.as_ref().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT a.login, a.achievement from achievements a order by a.unlocked_at, a.rowid",
+        )?;
+        let mapped_achievements = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(mapped_achievements.collect::<Result<_, _>>()?)
+    }
+
+    /// Ignores or stops ignoring the placements of `login`.
+    pub fn set_ignored(&mut self, login: &str, ignored: bool) -> Result<(), CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let sql = if ignored {
+            "INSERT OR IGNORE INTO ignored_users (login) values (?1)"
+        } else {
+            "DELETE FROM ignored_users where login = ?1"
+        };
+        self.connection.as_ref().unwrap().execute(sql, [login])?;
+        Ok(())
+    }
+
+    /// Keeps the cubes removed from the canvas because of `login`, e.g. when
+    /// they were banned, so that they can be restored.
+    pub fn quarantine(&mut self, login: &str, cubes: &[Cube]) -> Result<(), CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let tx = self.connection.as_mut().unwrap().transaction()?;
+        for cube in cubes {
+            tx.execute(
+                "INSERT INTO quarantine (login, x, y, z, r, g, b, a, emissive, shape)
+                 values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                rusqlite::params![
+                    login,
+                    cube.position.x,
+                    cube.position.y,
+                    cube.position.z,
+                    cube.colour.r,
+                    cube.colour.g,
+                    cube.colour.b,
+                    cube.colour.a,
+                    cube.colour.emissive,
+                    cube.shape().to_string()
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Takes the quarantined cubes of `login` out of the quarantine.
+    pub fn release_quarantine(&mut self, login: &str) -> Result<Vec<Cube>, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let tx = self.connection.as_mut().unwrap().transaction()?;
+        let cubes = {
+            let mut stmt = tx.prepare(
+                "SELECT q.x, q.y, q.z, q.r, q.g, q.b, q.a, q.emissive, q.shape
+                 from quarantine q
+                 where q.login = ?1 order by rowid",
+            )?;
+            let mapped_cubes = stmt.query_map([login], |row| {
+                Ok(Cube::new(
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    Colour::new(row.get(3)?, row.get(4)?, row.get(5)?)
+                        .with_alpha(row.get(6)?)
+                        .with_emissive(row.get(7)?)
+                        .with_shape(read_shape(row.get(8)?)),
+                ))
+            })?;
+            mapped_cubes.collect::<Result<Vec<_>, _>>()?
+        };
+        tx.execute("DELETE FROM quarantine where login = ?1", [login])?;
+        tx.commit()?;
+        Ok(cubes)
+    }
+
+    /// Chatters ignored by the moderators.
+    pub fn get_ignored(&mut self) -> Result<HashSet<String>, CubeArchiveError> {
         if self.connection.is_none() {
             self.init()?;
         }
         let conn = self.connection.as_ref().unwrap();
-        let mut stmt = conn.prepare("SELECT c.x, c.y, c.z, c.r, c.g, c.b from cubes c")?;
+        let mut stmt = conn.prepare("SELECT i.login from ignored_users i")?;
+        let mapped_logins = stmt.query_map([], |row| row.get(0))?;
+        Ok(mapped_logins.collect::<Result<_, _>>()?)
+    }
 
-        let mapped_cubes = stmt.query_map([], |row| {
-            Ok(Cube {
-                position: (row.get(0)?, row.get(1)?, row.get(2)?),
-                colour: (row.get(3)?, row.get(4)?, row.get(5)?),
+    /// Saves the regions protected by the moderators, in place of the previous
+    /// ones.
+    pub fn set_protected(&mut self, regions: &[Region]) -> Result<(), CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let tx = self.connection.as_mut().unwrap().transaction()?;
+        tx.execute("DELETE FROM protected_regions", [])?;
+        for region in regions {
+            tx.execute(
+                "INSERT INTO protected_regions (min_x, min_y, min_z, max_x, max_y, max_z)
+                 values (?1, ?2, ?3, ?4, ?5, ?6)",
+                rusqlite::params![
+                    region.min.x,
+                    region.min.y,
+                    region.min.z,
+                    region.max.x,
+                    region.max.y,
+                    region.max.z
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Regions protected by the moderators, in the order they were.
+    pub fn get_protected(&mut self) -> Result<Vec<Region>, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let conn = self.connection.as_ref().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT p.min_x, p.min_y, p.min_z, p.max_x, p.max_y, p.max_z
+             from protected_regions p order by rowid",
+        )?;
+        let mapped_regions = stmt.query_map([], |row| {
+            Ok(Region {
+                min: Position::new(row.get(0)?, row.get(1)?, row.get(2)?),
+                max: Position::new(row.get(3)?, row.get(4)?, row.get(5)?),
             })
         })?;
-        let mut cubes = Vec::<Cube>::new();
-        for cube in mapped_cubes {
-            cubes.push(cube?);
+        Ok(mapped_regions.collect::<Result<_, _>>()?)
+    }
+
+    /// The last unlocked stage of the progression, and the number of cubes
+    /// chatters ever placed.
+    pub fn get_progression(&mut self) -> Result<(usize, u64), CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
         }
-        Ok(cubes)
+        let conn = self.connection.as_ref().unwrap();
+        let stage = conn
+            .query_row("SELECT p.stage from progression p", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .optional()?
+            .unwrap_or(0);
+        let placed: i64 = conn.query_row(
+            "SELECT count(*) from events e
+             where e.viewer and e.author is not null and e.event like '{\"CubePlaced\"%'",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok((stage as usize, placed as u64))
+    }
+
+    /// Number of cubes chatters placed since the unix timestamp `since`,
+    /// towards the goal of the day.
+    pub fn placed_since(&mut self, since: i64) -> Result<u64, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let placed: i64 = self.connection.as_ref().unwrap().query_row(
+            "SELECT count(*) from events e
+             where e.timestamp >= ?1 and e.viewer and e.author is not null
+             and e.event like '{\"CubePlaced\"%'",
+            [since],
+            |row| row.get(0),
+        )?;
+        Ok(placed as u64)
+    }
+
+    pub fn set_progression_stage(&mut self, stage: usize) -> Result<(), CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let tx = self.connection.as_mut().unwrap().transaction()?;
+        tx.execute("DELETE FROM progression", [])?;
+        tx.execute(
+            "INSERT INTO progression (stage) values (?1)",
+            [stage as i64],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Defines the chat macro `name`, or removes it when `body` is `None`.
+    pub fn set_alias(&mut self, name: &str, body: Option<&str>) -> Result<(), CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let conn = self.connection.as_ref().unwrap();
+        match body {
+            Some(body) => conn.execute(
+                "INSERT OR REPLACE INTO aliases (name, body) values (?1, ?2)",
+                [name, body],
+            )?,
+            None => conn.execute("DELETE FROM aliases where name = ?1", [name])?,
+        };
+        Ok(())
+    }
+
+    /// Steps of each chat macro, by name.
+    pub fn get_aliases(&mut self) -> Result<HashMap<String, String>, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let conn = self.connection.as_ref().unwrap();
+        let mut stmt = conn.prepare("SELECT a.name, a.body from aliases a")?;
+        let mapped_aliases = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(mapped_aliases.collect::<Result<_, _>>()?)
+    }
+
+    /// Saves the cubes on the canvas as the checkpoint `name`, along with the
+    /// metadata of their placements, replacing any checkpoint of that name,
+    /// `saved_at` being a unix timestamp in seconds. Returns how many cubes
+    /// it has.
+    pub fn save_checkpoint(
+        &mut self,
+        name: &str,
+        saved_at: i64,
+    ) -> Result<usize, CubeArchiveError> {
+        let mut cubes: Vec<CheckpointCube> = self
+            .placements()?
+            .into_iter()
+            .map(|(position, (colour, entry))| CheckpointCube {
+                cube: Cube { position, colour },
+                metadata: entry.metadata,
+            })
+            .collect();
+        // Restored in the order they were placed.
+        cubes.sort_by_key(|c| (c.metadata.timestamp, c.cube.position));
+        self.connection.as_ref().unwrap().execute(
+            "INSERT OR REPLACE INTO checkpoints (name, saved_at, cubes) values (?1, ?2, ?3)",
+            rusqlite::params![name, saved_at, serde_json::to_string(&cubes)?],
+        )?;
+        Ok(cubes.len())
+    }
+
+    /// Puts the canvas back as it was saved in the checkpoint `name`,
+    /// journaling a CanvasCleared event with `metadata` followed by its
+    /// cubes, in a single transaction. The cubes keep the author and time of
+    /// their placements, as placed by the bot rather than chat. Returns the
+    /// entries journaled, None if there's no such checkpoint.
+    pub fn restore_checkpoint(
+        &mut self,
+        name: &str,
+        metadata: &EventMetadata,
+    ) -> Result<Option<Vec<JournalEntry>>, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let tx = self.connection.as_mut().unwrap().transaction()?;
+        let cubes: Option<String> = tx
+            .query_row(
+                "SELECT c.cubes from checkpoints c where c.name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let cubes: Vec<CheckpointCube> = match cubes {
+            Some(cubes) => serde_json::from_str(&cubes)?,
+            None => return Ok(None),
+        };
+        let cleared = JournalEntry {
+            command_id: Uuid::new_v4(),
+            event: CanvasEvent::CanvasCleared,
+            metadata: EventMetadata {
+                viewer: false,
+                ..metadata.clone()
+            },
+        };
+        let placed = cubes.into_iter().map(|c| JournalEntry {
+            command_id: Uuid::new_v4(),
+            event: CanvasEvent::CubePlaced(c.cube),
+            metadata: EventMetadata {
+                viewer: false,
+                ..c.metadata
+            },
+        });
+        let entries: Vec<JournalEntry> = std::iter::once(cleared).chain(placed).collect();
+        for entry in &entries {
+            insert_event(&tx, entry.command_id, &entry.event, &entry.metadata)?;
+        }
+        tx.commit()?;
+        Ok(Some(entries))
+    }
+
+    /// Names of the checkpoints saved, the most recent first.
+    pub fn get_checkpoints(&mut self) -> Result<Vec<String>, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let conn = self.connection.as_ref().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT c.name from checkpoints c order by c.saved_at desc, c.name")?;
+        let names = stmt.query_map([], |row| row.get(0))?;
+        Ok(names.collect::<Result<_, _>>()?)
     }
+
+    /// Writes a copy of the whole archive to `path`, which must not exist.
+    pub fn backup(&mut self, path: &std::path::Path) -> Result<(), CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        self.connection
+            .as_ref()
+            .unwrap()
+            .execute("VACUUM INTO ?1", [path.to_string_lossy()])?;
+        Ok(())
+    }
+
+    /// Moves every position recorded in the archive, journaled events and
+    /// quarantined cubes, to the one `remap` gives, e.g. when the canvas is
+    /// resized. The events and cubes `remap` drops are deleted. Happens in a
+    /// single transaction, returns how many events were kept and dropped.
+    pub fn remap_positions(
+        &mut self,
+        remap: impl Fn(Position) -> Option<Position>,
+    ) -> Result<(usize, usize), CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let tx = self.connection.as_mut().unwrap().transaction()?;
+        let events = {
+            let mut stmt = tx.prepare("SELECT e.id, e.event from events e")?;
+            let mapped_events = stmt.query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?;
+            mapped_events.collect::<Result<Vec<_>, _>>()?
+        };
+        let (mut kept, mut dropped) = (0, 0);
+        for (id, event) in events {
+            let event: CanvasEvent = serde_json::from_str(&event)?;
+            match event.remap(&remap) {
+                Some(event) => {
+                    let position = event.position();
+                    tx.execute(
+                        "UPDATE events SET event = ?1, x = ?2, y = ?3, z = ?4 where id = ?5",
+                        rusqlite::params![
+                            serde_json::to_string(&event)?,
+                            position.map(|p| p.x),
+                            position.map(|p| p.y),
+                            position.map(|p| p.z),
+                            id
+                        ],
+                    )?;
+                    kept += 1;
+                }
+                None => {
+                    tx.execute("DELETE FROM events where id = ?1", [id])?;
+                    dropped += 1;
+                }
+            }
+        }
+        let quarantined = {
+            let mut stmt = tx.prepare("SELECT q.rowid, q.x, q.y, q.z from quarantine q")?;
+            let mapped_cubes = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    Position::new(row.get(1)?, row.get(2)?, row.get(3)?),
+                ))
+            })?;
+            mapped_cubes.collect::<Result<Vec<_>, _>>()?
+        };
+        for (rowid, position) in quarantined {
+            match remap(position) {
+                Some(p) => tx.execute(
+                    "UPDATE quarantine SET x = ?1, y = ?2, z = ?3 where rowid = ?4",
+                    rusqlite::params![p.x, p.y, p.z, rowid],
+                )?,
+                None => tx.execute("DELETE FROM quarantine where rowid = ?1", [rowid])?,
+            };
+        }
+        tx.commit()?;
+        Ok((kept, dropped))
+    }
+
+    /// Changes the resolution of the canvas, journaling a CanvasCleared event
+    /// followed by the resampled cubes, in a single transaction. A split cube
+    /// keeps its author and time in each cube of its block, a merged block
+    /// those of its latest cube. Quarantined cubes are resampled too. Returns
+    /// how many cubes are on the canvas.
+    pub fn resample(&mut self, resample: Resample) -> Result<usize, CubeArchiveError> {
+        let cubes = resample.apply(&self.get_cubes()?);
+        let block = |position: Position, factor: u32| {
+            let factor = factor.max(1);
+            Position::new(
+                position.x / factor,
+                position.y / factor,
+                position.z / factor,
+            )
+        };
+        let mut placements: Vec<(Position, EventMetadata)> = self
+            .placements()?
+            .into_iter()
+            .map(|(position, (_, entry))| (position, entry.metadata))
+            .collect();
+        placements.sort_by_key(|(position, metadata)| (metadata.timestamp, *position));
+        // Metadata of the cubes by block when merging, the latest last.
+        let metadata: HashMap<Position, EventMetadata> = placements
+            .into_iter()
+            .map(|(position, metadata)| match resample {
+                Resample::Down(factor, _) => (block(position, factor), metadata),
+                Resample::Up(_) => (position, metadata),
+            })
+            .collect();
+        let tx = self.connection.as_mut().unwrap().transaction()?;
+        insert_event(
+            &tx,
+            Uuid::new_v4(),
+            &CanvasEvent::CanvasCleared,
+            &EventMetadata::default(),
+        )?;
+        for cube in &cubes {
+            let source = match resample {
+                Resample::Down(_, _) => cube.position,
+                Resample::Up(factor) => block(cube.position, factor),
+            };
+            let metadata = metadata.get(&source).cloned().unwrap_or_default();
+            let event = CanvasEvent::CubePlaced(cube.clone());
+            insert_event(&tx, Uuid::new_v4(), &event, &metadata)?;
+        }
+        let quarantined = {
+            let mut stmt = tx.prepare(
+                "SELECT q.login, q.x, q.y, q.z, q.r, q.g, q.b, q.a, q.emissive, q.shape
+                 from quarantine q
+                 order by rowid",
+            )?;
+            let mapped_cubes = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    Cube::new(
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        Colour::new(row.get(4)?, row.get(5)?, row.get(6)?)
+                            .with_alpha(row.get(7)?)
+                            .with_emissive(row.get(8)?)
+                            .with_shape(read_shape(row.get(9)?)),
+                    ),
+                ))
+            })?;
+            let mut quarantined: HashMap<String, Vec<Cube>> = HashMap::new();
+            for row in mapped_cubes {
+                let (login, cube) = row?;
+                quarantined.entry(login).or_default().push(cube);
+            }
+            quarantined
+        };
+        tx.execute("DELETE FROM quarantine", [])?;
+        for (login, cubes) in quarantined {
+            for cube in resample.apply(&cubes) {
+                tx.execute(
+                    "INSERT INTO quarantine (login, x, y, z, r, g, b, a, emissive, shape)
+                 values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    rusqlite::params![
+                        login,
+                        cube.position.x,
+                        cube.position.y,
+                        cube.position.z,
+                        cube.colour.r,
+                        cube.colour.g,
+                        cube.colour.b,
+                        cube.colour.a,
+                        cube.colour.emissive,
+                        cube.shape().to_string()
+                    ],
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(cubes.len())
+    }
+
+    /// Deletes the events journaled before the unix timestamp `cutoff` and,
+    /// while there are more than `max_events`, the next oldest ones, keeping
+    /// those the canvas is still made of, in a single transaction. Nothing is
+    /// deleted on a `dry_run`, which reports what would be.
+    pub fn prune(
+        &mut self,
+        cutoff: Option<i64>,
+        max_events: Option<usize>,
+        dry_run: bool,
+    ) -> Result<PruneReport, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let tx = self.connection.as_mut().unwrap().transaction()?;
+        let journal = {
+            let mut stmt =
+                tx.prepare("SELECT e.id, e.timestamp, e.event from events e order by e.id")?;
+            let mapped_events = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?;
+            let mut journal = Vec::new();
+            for event in mapped_events {
+                let (id, timestamp, event) = event?;
+                journal.push((id, timestamp, serde_json::from_str(&event)?));
+            }
+            journal
+        };
+        let ids = prunable(&journal, cutoff, max_events);
+        let until = ids.last().and_then(|last| {
+            journal
+                .iter()
+                .find(|(id, _, _)| id == last)
+                .map(|(_, timestamp, _)| *timestamp)
+        });
+        if !dry_run {
+            for id in &ids {
+                tx.execute("DELETE FROM events where id = ?1", [id])?;
+            }
+            tx.commit()?;
+        }
+        Ok(PruneReport {
+            events: journal.len(),
+            pruned: ids.len(),
+            until,
+        })
+    }
+
+    /// Journals a chunk of the import `token` in a single transaction, along
+    /// with the `progress` of the import once the chunk is in, e.g. the lines
+    /// of its input read so far. An interrupted import is either in before a
+    /// chunk or after it, never half way.
+    pub fn import_chunk(
+        &mut self,
+        token: &str,
+        cubes: &[Cube],
+        progress: u64,
+    ) -> Result<(), CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let tx = self.connection.as_mut().unwrap().transaction()?;
+        for cube in cubes {
+            insert_event(
+                &tx,
+                Uuid::new_v4(),
+                &CanvasEvent::CubePlaced(cube.clone()),
+                &EventMetadata::default(),
+            )?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO imports (token, progress) values (?1, ?2)",
+            rusqlite::params![token, progress as i64],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Progress of the import `token` as of its last chunk, `None` if it
+    /// never journaled one.
+    pub fn import_progress(&mut self, token: &str) -> Result<Option<u64>, CubeArchiveError> {
+        if self.connection.is_none() {
+            self.init()?;
+        }
+        let progress: Option<i64> = self
+            .connection
+            .as_ref()
+            .unwrap()
+            .query_row(
+                "SELECT i.progress from imports i where token = ?1",
+                [token],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(progress.map(|progress| progress as u64))
+    }
+}
+
+// Creates the tables of the archive, or brings those of an older one up to
+// date.
+fn migrate(conn: &mut Connection) -> Result<(), CubeArchiveError> {
+    // create tables if not exist
+    conn.execute(
+        "create table if not exists cubes (
+         x integer not null,
+         y integer not null,
+         z integer not null,
+         r integer not null,
+         g integer not null,
+         b integer not null
+     )",
+        [],
+    )?;
+    conn.execute(
+        "create table if not exists events (
+         id integer primary key autoincrement,
+         event text not null,
+         command_id text unique
+     )",
+        [],
+    )?;
+
+    let tx = conn.transaction()?;
+    // Journals created before command ids were introduced get a fresh id
+    // for each of their events.
+    let has_command_id = tx
+        .prepare("SELECT * from events limit 0")?
+        .column_names()
+        .contains(&"command_id");
+    if !has_command_id {
+        tx.execute("ALTER TABLE events ADD COLUMN command_id text", [])?;
+        tx.execute(
+            "CREATE UNIQUE INDEX events_command_id on events (command_id)",
+            [],
+        )?;
+        let ids = {
+            let mut stmt = tx.prepare("SELECT e.id from events e")?;
+            let mapped_ids = stmt.query_map([], |row| row.get::<_, i64>(0))?;
+            mapped_ids.collect::<Result<Vec<_>, _>>()?
+        };
+        for id in ids {
+            tx.execute(
+                "UPDATE events SET command_id = ?1 where id = ?2",
+                rusqlite::params![Uuid::new_v4().to_string(), id],
+            )?;
+        }
+    }
+    add_missing_column(&tx, "events", "author", "text")?;
+    add_missing_column(&tx, "events", "timestamp", "integer not null default 0")?;
+    add_missing_column(&tx, "events", "competition_id", "integer")?;
+    add_missing_column(&tx, "events", "team", "text")?;
+    add_missing_column(&tx, "events", "message", "text")?;
+    // Before the lane of the events was journaled, chatters placed the cubes
+    // which have an author.
+    if add_missing_column(&tx, "events", "viewer", "integer not null default 0")? {
+        tx.execute("UPDATE events SET viewer = 1 where author is not null", [])?;
+    }
+    // Position each event is about, null for clears, to query the cubes
+    // of the canvas without replaying the whole journal.
+    let has_position = !add_missing_column(&tx, "events", "x", "integer")?;
+    add_missing_column(&tx, "events", "y", "integer")?;
+    add_missing_column(&tx, "events", "z", "integer")?;
+    if !has_position {
+        let events = {
+            let mut stmt = tx.prepare("SELECT e.id, e.event from events e")?;
+            let mapped_events = stmt.query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?;
+            mapped_events.collect::<Result<Vec<_>, _>>()?
+        };
+        for (id, event) in events {
+            let position = serde_json::from_str::<CanvasEvent>(&event)?.position();
+            tx.execute(
+                "UPDATE events SET x = ?1, y = ?2, z = ?3 where id = ?4",
+                rusqlite::params![
+                    position.map(|p| p.x),
+                    position.map(|p| p.y),
+                    position.map(|p| p.z),
+                    id
+                ],
+            )?;
+        }
+    }
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS events_position on events (x, y, z)",
+        [],
+    )?;
+    tx.execute(
+        "CREATE INDEX IF NOT EXISTS events_timestamp on events (timestamp)",
+        [],
+    )?;
+    tx.execute(
+        "create table if not exists team_members (
+         login text primary key,
+         team text not null
+     )",
+        [],
+    )?;
+    tx.execute(
+        "create table if not exists quarantine (
+         login text not null,
+         x integer not null,
+         y integer not null,
+         z integer not null,
+         r integer not null,
+         g integer not null,
+         b integer not null
+     )",
+        [],
+    )?;
+    // Opacity of the quarantined cubes, opaque for those quarantined before
+    // translucent cubes.
+    add_missing_column(&tx, "quarantine", "a", "integer not null default 255")?;
+    // Whether the quarantined cubes glow, none of those quarantined before
+    // glowing cubes did.
+    add_missing_column(&tx, "quarantine", "emissive", "integer not null default 0")?;
+    // Shape of the quarantined cubes, full cubes for those quarantined before
+    // other shapes.
+    add_missing_column(&tx, "quarantine", "shape", "text not null default 'cube'")?;
+    tx.execute(
+        "create table if not exists achievements (
+         login text not null,
+         achievement text not null,
+         unlocked_at integer not null,
+         primary key (login, achievement)
+     )",
+        [],
+    )?;
+    tx.execute(
+        "create table if not exists ignored_users (
+         login text primary key
+     )",
+        [],
+    )?;
+    tx.execute(
+        "create table if not exists aliases (
+         name text primary key,
+         body text not null
+     )",
+        [],
+    )?;
+    tx.execute(
+        "create table if not exists checkpoints (
+         name text primary key,
+         saved_at integer not null,
+         cubes text not null
+     )",
+        [],
+    )?;
+    tx.execute(
+        "create table if not exists imports (
+         token text primary key,
+         progress integer not null
+     )",
+        [],
+    )?;
+    tx.execute(
+        "create table if not exists progression (
+         stage integer not null
+     )",
+        [],
+    )?;
+    tx.execute(
+        "create table if not exists protected_regions (
+         min_x integer not null,
+         min_y integer not null,
+         min_z integer not null,
+         max_x integer not null,
+         max_y integer not null,
+         max_z integer not null
+     )",
+        [],
+    )?;
+    tx.execute(
+        "create table if not exists competitions (
+         id integer primary key autoincrement,
+         name text not null,
+         started_at integer not null,
+         ends_at integer not null,
+         finished integer not null default 0
+     )",
+        [],
+    )?;
+    let journaled: i64 = tx.query_row("SELECT count(*) from events", [], |row| row.get(0))?;
+    if journaled == 0 {
+        let legacy_cubes = {
+            let mut stmt =
+                tx.prepare("SELECT c.x, c.y, c.z, c.r, c.g, c.b from cubes c order by rowid")?;
+            let mapped_cubes = stmt.query_map([], |row| {
+                Ok(Cube::new(
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    Colour::new(row.get(3)?, row.get(4)?, row.get(5)?),
+                ))
+            })?;
+            mapped_cubes.collect::<Result<Vec<_>, _>>()?
+        };
+        for cube in legacy_cubes {
+            insert_event(
+                &tx,
+                Uuid::new_v4(),
+                &CanvasEvent::CubePlaced(cube),
+                &EventMetadata::default(),
+            )?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+impl Drop for CubeArchive {
+    fn drop(&mut self) {
+        if let Some(conn) = self.connection.take() {
+            self.pool.put(conn);
+        }
+    }
+}
+
+// Events journaled after the one of id `after_id`, with their ids, oldest
+// first.
+fn read_journal(
+    conn: &Connection,
+    after_id: i64,
+) -> Result<Vec<(i64, JournalEntry)>, CubeArchiveError> {
+    read_entries(conn, "e.id > ?1 order by e.id", [after_id])
+}
+
+// Journaled events matching the `condition` on the events `e`, with their
+// ids.
+fn read_entries<P: rusqlite::Params>(
+    conn: &Connection,
+    condition: &str,
+    params: P,
+) -> Result<Vec<(i64, JournalEntry)>, CubeArchiveError> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT e.id, e.command_id, e.event, e.author, e.timestamp, e.competition_id, e.team,
+         e.message, e.viewer from events e where {}",
+        condition
+    ))?;
+    let mapped_entries = stmt.query_map(params, |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            EventMetadata {
+                author: row.get(3)?,
+                timestamp: row.get(4)?,
+                competition_id: row.get(5)?,
+                team: row.get(6)?,
+                message: row.get(7)?,
+                viewer: row.get(8)?,
+            },
+        ))
+    })?;
+    let mut journal = Vec::new();
+    for entry in mapped_entries {
+        let (id, command_id, event, metadata) = entry?;
+        journal.push((
+            id,
+            JournalEntry {
+                command_id: Uuid::parse_str(&command_id)?,
+                event: serde_json::from_str(&event)?,
+                metadata,
+            },
+        ));
+    }
+    Ok(journal)
+}
+
+// Journals an event, unless its command already was. Returns whether it was.
+fn insert_event(
+    conn: &Connection,
+    command_id: Uuid,
+    event: &CanvasEvent,
+    metadata: &EventMetadata,
+) -> Result<bool, CubeArchiveError> {
+    let position = event.position();
+    // Prepared once per connection, as chat places cubes one by one.
+    let mut stmt = conn.prepare_cached(
+        "INSERT OR IGNORE INTO events
+         (event, command_id, author, timestamp, competition_id, team, message, viewer, x, y,
+         z)
+         values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+    )?;
+    let inserted = stmt.execute(rusqlite::params![
+        serde_json::to_string(event)?,
+        command_id.to_string(),
+        metadata.author,
+        metadata.timestamp,
+        metadata.competition_id,
+        metadata.team,
+        metadata.message,
+        metadata.viewer,
+        position.map(|p| p.x),
+        position.map(|p| p.y),
+        position.map(|p| p.z),
+    ])?;
+    Ok(inserted > 0)
+}
+
+// For each position with events since the last clear, the ids of its last
+// placement, removal and recolouring, `filter` narrowing down the events.
+// Events are stored as externally tagged JSON.
+fn live_positions(filter: &str) -> String {
+    format!(
+        "SELECT max(case when e.event like '{{\"CubePlaced\"%' then e.id end) as placed,
+         max(case when e.event like '{{\"CubeRemoved\"%' then e.id end) as removed,
+         max(case when e.event like '{{\"Recoloured\"%' then e.id end) as recoloured
+         from events e
+         where e.x is not null {}
+         and e.id > (SELECT coalesce(max(c.id), 0) from events c where c.x is null)
+         group by e.x, e.y, e.z",
+        filter
+    )
+}
+
+// Adds a column to tables created before it was introduced. Returns whether
+// it was missing.
+fn add_missing_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<bool, CubeArchiveError> {
+    let exists = conn
+        .prepare(&format!("SELECT * from {} limit 0", table))?
+        .column_names()
+        .contains(&column);
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(!exists)
+}
+
+// Shape stored by name, full cubes for names of shapes this version doesn't
+// know, e.g. written by a newer one.
+fn read_shape(name: String) -> Shape {
+    name.parse().unwrap_or_default()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
     #[test]
     fn test_add_get() {
         let sqlite_path = std::path::PathBuf::from(".testlite"); // TODO make it a tempfile
-        let expected_cube = Cube {
-            position: (0, 0, 0),
-            colour: (0, 0, 0),
-        };
+        let expected_cube = Cube::new(0, 0, 0, Colour::new(0, 0, 0));
         let mut archive = CubeArchive::new(sqlite_path.clone());
         archive.add_cube(expected_cube.clone()).unwrap();
         assert_eq!(archive.get_cubes().unwrap(), &[expected_cube.clone()][..]);
@@ -101,4 +1522,617 @@ mod tests {
         let mut archive = CubeArchive::new(sqlite_path);
         assert_eq!(archive.get_cubes().unwrap(), &[expected_cube][..]);
     }
+
+    #[test]
+    fn test_journal() {
+        let sqlite_path = std::path::PathBuf::from(".testlite-journal");
+        let _ = std::fs::remove_file(&sqlite_path);
+        // An archive from before the journal, with a cube placed twice, and a
+        // journal from before command ids.
+        {
+            let conn = Connection::open(&sqlite_path).unwrap();
+            conn.execute_batch(
+                "create table cubes (x integer, y integer, z integer, r integer, g integer, b integer);
+                 insert into cubes values (1, 2, 3, 4, 5, 6);
+                 insert into cubes values (1, 2, 3, 7, 8, 9);
+                 create table events (id integer primary key autoincrement, event text not null);",
+            )
+            .unwrap();
+        }
+
+        let mut archive = CubeArchive::new(sqlite_path.clone());
+        let placed = Uuid::new_v4();
+        let event = CanvasEvent::CubePlaced(Cube::new(3, 2, 1, Colour::new(0, 0, 0)));
+        let metadata = EventMetadata {
+            author: Some("someone".to_owned()),
+            timestamp: 1234,
+            competition_id: None,
+            team: Some("red".to_owned()),
+            message: Some("3 2 1 0 0 0".to_owned()),
+            viewer: true,
+        };
+        assert!(archive.append_event(placed, &event, &metadata).unwrap());
+        // Delivered again, e.g. after a reconnection.
+        assert!(!archive.append_event(placed, &event, &metadata).unwrap());
+        assert!(archive.contains_command(placed).unwrap());
+        assert!(!archive.contains_command(Uuid::new_v4()).unwrap());
+        assert_eq!(
+            archive.lookup(Position::new(3, 2, 1)).unwrap(),
+            Some((
+                Colour::new(0, 0, 0),
+                JournalEntry {
+                    command_id: placed,
+                    event: event.clone(),
+                    metadata: metadata.clone(),
+                }
+            ))
+        );
+        archive
+            .append_event(
+                Uuid::new_v4(),
+                &CanvasEvent::CubeRemoved(Position::new(3, 2, 1)),
+                &EventMetadata::default(),
+            )
+            .unwrap();
+
+        let mut archive = CubeArchive::new(sqlite_path.clone());
+        let journal = archive.get_journal().unwrap();
+        assert_eq!(journal.len(), 4);
+        assert_eq!(
+            journal[2],
+            JournalEntry {
+                command_id: placed,
+                event,
+                metadata,
+            }
+        );
+        assert_eq!(
+            archive.get_cubes().unwrap(),
+            vec![Cube::new(1, 2, 3, Colour::new(7, 8, 9))]
+        );
+        assert_eq!(archive.lookup(Position::new(3, 2, 1)).unwrap(), None);
+        let (colour, entry) = archive.lookup(Position::new(1, 2, 3)).unwrap().unwrap();
+        assert_eq!(colour, Colour::new(7, 8, 9));
+        assert_eq!(entry.metadata, EventMetadata::default());
+        std::fs::remove_file(&sqlite_path).unwrap();
+    }
+
+    #[test]
+    fn test_serde() {
+        let entry = JournalEntry {
+            command_id: Uuid::new_v4(),
+            event: CanvasEvent::CubePlaced(Cube::new(3, 2, 1, Colour::new(0, 0, 0))),
+            metadata: EventMetadata {
+                author: Some("someone".to_owned()),
+                timestamp: 1234,
+                competition_id: Some(1),
+                team: Some("red".to_owned()),
+                message: None,
+                viewer: true,
+            },
+        };
+        let json = serde_json::to_string(&entry).unwrap();
+        assert_eq!(serde_json::from_str::<JournalEntry>(&json).unwrap(), entry);
+
+        let stats = CanvasStats {
+            cubes: 12,
+            fill_percentage: 1.2,
+            builders_today: 3,
+            cubes_last_hour: 4,
+        };
+        let json = serde_json::to_string(&stats).unwrap();
+        assert_eq!(serde_json::from_str::<CanvasStats>(&json).unwrap(), stats);
+    }
+
+    #[test]
+    fn test_competition() {
+        let sqlite_path = std::path::PathBuf::from(".testlite-competition");
+        let _ = std::fs::remove_file(&sqlite_path);
+        let mut archive = CubeArchive::new(sqlite_path.clone());
+        let competition = archive.start_competition("build a tree", 10, 20).unwrap();
+        assert_eq!(
+            archive.active_competition().unwrap(),
+            Some(competition.clone())
+        );
+        let mut place = |author: &str, competition_id| {
+            let metadata = EventMetadata {
+                author: Some(author.to_owned()),
+                timestamp: 15,
+                competition_id,
+                team: None,
+                message: None,
+                viewer: true,
+            };
+            let event = CanvasEvent::CubePlaced(Cube::new(0, 0, 0, Colour::new(0, 0, 0)));
+            archive
+                .append_event(Uuid::new_v4(), &event, &metadata)
+                .unwrap();
+        };
+        place("a", Some(competition.id));
+        place("b", Some(competition.id));
+        place("b", Some(competition.id));
+        place("a", None);
+        assert_eq!(
+            archive.competition_contributions(competition.id).unwrap(),
+            vec![("b".to_owned(), 2), ("a".to_owned(), 1)]
+        );
+        archive.finish_competition(competition.id).unwrap();
+        assert_eq!(archive.active_competition().unwrap(), None);
+        std::fs::remove_file(&sqlite_path).unwrap();
+    }
+
+    #[test]
+    fn test_chatters() {
+        let sqlite_path = std::path::PathBuf::from(".testlite-teams");
+        let _ = std::fs::remove_file(&sqlite_path);
+        let mut archive = CubeArchive::new(sqlite_path.clone());
+        archive.set_team("a", "red").unwrap();
+        archive.set_team("b", "red").unwrap();
+        archive.set_team("a", "blue").unwrap();
+        let teams = archive.get_teams().unwrap();
+        assert_eq!(teams.len(), 2);
+        assert_eq!(teams["a"], "blue");
+        assert_eq!(teams["b"], "red");
+
+        archive.set_ignored("nightbot", true).unwrap();
+        archive.set_ignored("spammer", true).unwrap();
+        archive.set_ignored("spammer", true).unwrap();
+        archive.set_ignored("nightbot", false).unwrap();
+        let logo = Region {
+            min: Position::new(0, 0, 0),
+            max: Position::new(3, 1, 0),
+        };
+        archive.set_protected(&[logo]).unwrap();
+        assert_eq!(archive.get_protected().unwrap(), vec![logo]);
+        archive.set_protected(&[]).unwrap();
+        assert!(archive.get_protected().unwrap().is_empty());
+        assert_eq!(
+            archive.get_ignored().unwrap(),
+            vec!["spammer".to_owned()].into_iter().collect()
+        );
+
+        let cubes = vec![
+            Cube::new(1, 2, 3, Colour::new(4, 5, 6)),
+            Cube::new(0, 0, 0, Colour::new(255, 0, 0)),
+            Cube::new(0, 1, 0, Colour::new(0, 0, 255).with_alpha(100)),
+            Cube::new(0, 2, 0, Colour::new(0, 255, 0).with_emissive(true)),
+            Cube::new(0, 3, 0, Colour::new(0, 0, 0).with_shape(Shape::Sphere)),
+        ];
+        archive.quarantine("spammer", &cubes).unwrap();
+        assert_eq!(archive.release_quarantine("someone").unwrap(), vec![]);
+        assert_eq!(archive.release_quarantine("spammer").unwrap(), cubes);
+        assert_eq!(archive.release_quarantine("spammer").unwrap(), vec![]);
+
+        archive.set_alias("tree", Some("!px $1 $2 green")).unwrap();
+        archive.set_alias("wall", Some("$1 0 0 0 0 0")).unwrap();
+        archive.set_alias("tree", Some("!px $1 $2 brown")).unwrap();
+        archive.set_alias("wall", None).unwrap();
+        let aliases = archive.get_aliases().unwrap();
+        assert_eq!(aliases.len(), 1);
+        assert_eq!(aliases["tree"], "!px $1 $2 brown");
+
+        archive.unlock_achievement("a", "cubes", 20).unwrap();
+        archive.unlock_achievement("a", "first_cube", 10).unwrap();
+        archive.unlock_achievement("a", "first_cube", 30).unwrap();
+        archive.unlock_achievement("b", "first_cube", 15).unwrap();
+        assert_eq!(
+            archive.get_achievements().unwrap(),
+            vec![
+                ("a".to_owned(), "first_cube".to_owned()),
+                ("b".to_owned(), "first_cube".to_owned()),
+                ("a".to_owned(), "cubes".to_owned()),
+            ]
+        );
+        std::fs::remove_file(&sqlite_path).unwrap();
+    }
+
+    #[test]
+    fn test_placements() {
+        let sqlite_path = std::path::PathBuf::from(".testlite-placements");
+        let _ = std::fs::remove_file(&sqlite_path);
+        let mut archive = CubeArchive::new(sqlite_path.clone());
+        let mut append = |event: CanvasEvent, author: &str| {
+            let metadata = EventMetadata {
+                author: Some(author.to_owned()),
+                ..Default::default()
+            };
+            archive
+                .append_event(Uuid::new_v4(), &event, &metadata)
+                .unwrap();
+        };
+        let (red, blue) = (Colour::new(255, 0, 0), Colour::new(0, 0, 255));
+        let place = |x, colour| CanvasEvent::CubePlaced(Cube::new(x, 0, 0, colour));
+        let recolour = |x, colour| CanvasEvent::Recoloured {
+            position: Position::new(x, 0, 0),
+            colour,
+        };
+        append(place(0, red), "a");
+        append(recolour(0, blue), "decay");
+        append(place(1, red), "b");
+        append(CanvasEvent::CubeRemoved(Position::new(1, 0, 0)), "mod");
+        // Too late, there's no cube left to recolour.
+        append(recolour(1, blue), "decay");
+        append(place(2, red), "c");
+        append(place(2, blue), "d");
+        let author = |archive: &mut CubeArchive, x| {
+            archive
+                .lookup(Position::new(x, 0, 0))
+                .unwrap()
+                .map(|(colour, entry)| (colour, entry.metadata.author.unwrap()))
+        };
+        assert_eq!(author(&mut archive, 0), Some((blue, "a".to_owned())));
+        assert_eq!(author(&mut archive, 1), None);
+        assert_eq!(author(&mut archive, 2), Some((blue, "d".to_owned())));
+        assert_eq!(archive.placements().unwrap().len(), 2);
+        let authors = |history: Vec<JournalEntry>| -> Vec<String> {
+            history
+                .into_iter()
+                .map(|entry| entry.metadata.author.unwrap())
+                .collect()
+        };
+        let position = Position::new(1, 0, 0);
+        assert_eq!(
+            authors(archive.history(position, 10).unwrap()),
+            vec!["decay", "mod", "b"]
+        );
+        assert_eq!(
+            authors(archive.reopen().history(position, 2).unwrap()),
+            vec!["decay", "mod"]
+        );
+
+        let mut archive = CubeArchive::new(sqlite_path.clone());
+        archive
+            .append_event(
+                Uuid::new_v4(),
+                &CanvasEvent::CanvasCleared,
+                &Default::default(),
+            )
+            .unwrap();
+        archive.add_cube(Cube::new(2, 0, 0, red)).unwrap();
+        assert_eq!(author(&mut archive, 0), None);
+        assert_eq!(
+            archive.lookup(Position::new(2, 0, 0)).unwrap().unwrap().0,
+            red
+        );
+        assert_eq!(archive.placements().unwrap().len(), 1);
+        std::fs::remove_file(&sqlite_path).unwrap();
+    }
+
+    #[test]
+    fn test_stats() {
+        let sqlite_path = std::path::PathBuf::from(".testlite-stats");
+        let _ = std::fs::remove_file(&sqlite_path);
+        let mut archive = CubeArchive::new(sqlite_path.clone());
+        let mut append = |event: CanvasEvent, author: Option<&str>, timestamp| {
+            let metadata = EventMetadata {
+                author: author.map(str::to_owned),
+                timestamp,
+                viewer: author.is_some(),
+                ..Default::default()
+            };
+            archive
+                .append_event(Uuid::new_v4(), &event, &metadata)
+                .unwrap();
+        };
+        let cube = |x| CanvasEvent::CubePlaced(Cube::new(x, 0, 0, Colour::new(0, 0, 0)));
+        let now = 100_000;
+        append(cube(0), Some("old"), now - 25 * 3600);
+        append(cube(1), Some("a"), now - 2 * 3600);
+        append(cube(2), Some("a"), now - 60);
+        append(cube(3), Some("b"), now - 30);
+        append(CanvasEvent::CubeRemoved(Position::new(3, 0, 0)), None, now);
+        assert_eq!(
+            archive.stats(10, now).unwrap(),
+            CanvasStats {
+                cubes: 3,
+                fill_percentage: 0.3,
+                builders_today: 2,
+                cubes_last_hour: 2,
+            }
+        );
+        assert_eq!(archive.get_progression().unwrap(), (0, 4));
+        archive.set_progression_stage(2).unwrap();
+        archive.set_progression_stage(3).unwrap();
+        assert_eq!(archive.get_progression().unwrap(), (3, 4));
+        assert_eq!(archive.placed_since(now - 3600).unwrap(), 2);
+        // Placed by the bot, e.g. `!terrain` or a restored checkpoint.
+        let bot = EventMetadata {
+            author: Some("a".to_owned()),
+            timestamp: now,
+            ..Default::default()
+        };
+        archive.append_events(&[cube(4)], &bot).unwrap();
+        archive
+            .append_events(&[cube(5)], &EventMetadata::default())
+            .unwrap();
+        assert_eq!(archive.placed_since(now - 3600).unwrap(), 2);
+        assert_eq!(archive.get_progression().unwrap(), (3, 4));
+        std::fs::remove_file(&sqlite_path).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let sqlite_path = std::path::PathBuf::from(".testlite-checkpoints");
+        let _ = std::fs::remove_file(&sqlite_path);
+        let mut archive = CubeArchive::new(sqlite_path.clone());
+        let mut append = |event: CanvasEvent, author: Option<&str>, timestamp| {
+            let metadata = EventMetadata {
+                author: author.map(str::to_owned),
+                timestamp,
+                viewer: author.is_some(),
+                ..Default::default()
+            };
+            archive
+                .append_event(Uuid::new_v4(), &event, &metadata)
+                .unwrap();
+        };
+        let cube = |x, r| Cube::new(x, 0, 0, Colour::new(r, 0, 0));
+        append(CanvasEvent::CubePlaced(cube(1, 2)), Some("b"), 20);
+        append(CanvasEvent::CubePlaced(cube(0, 1)), Some("a"), 10);
+        // Faded by the decay.
+        append(
+            CanvasEvent::Recoloured {
+                position: Position::new(1, 0, 0),
+                colour: Colour::new(3, 0, 0),
+            },
+            None,
+            30,
+        );
+        assert_eq!(archive.save_checkpoint("castle", 100).unwrap(), 2);
+        archive
+            .append_events(&[CanvasEvent::CanvasCleared], &EventMetadata::default())
+            .unwrap();
+        assert_eq!(archive.save_checkpoint("empty", 200).unwrap(), 0);
+        archive
+            .append_event(
+                Uuid::new_v4(),
+                &CanvasEvent::CubePlaced(cube(2, 4)),
+                &EventMetadata {
+                    author: Some("c".to_owned()),
+                    timestamp: 40,
+                    viewer: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(archive.get_checkpoints().unwrap(), vec!["empty", "castle"]);
+
+        let moderator = EventMetadata {
+            author: Some("moderator".to_owned()),
+            timestamp: 300,
+            ..Default::default()
+        };
+        assert_eq!(
+            archive.restore_checkpoint("moat", &moderator).unwrap(),
+            None
+        );
+        let entries = archive
+            .restore_checkpoint("castle", &moderator)
+            .unwrap()
+            .unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].event, CanvasEvent::CanvasCleared);
+        assert_eq!(entries[0].metadata, moderator);
+        // In the order they were placed, the colour of the decay kept.
+        assert_eq!(entries[1].event, CanvasEvent::CubePlaced(cube(0, 1)));
+        assert_eq!(entries[2].event, CanvasEvent::CubePlaced(cube(1, 3)));
+        assert_eq!(archive.get_journal().unwrap()[5..], entries[..]);
+        assert_eq!(archive.get_cubes().unwrap(), vec![cube(0, 1), cube(1, 3)]);
+        let (_, entry) = archive.lookup(Position::new(1, 0, 0)).unwrap().unwrap();
+        assert_eq!(
+            entry.metadata,
+            EventMetadata {
+                author: Some("b".to_owned()),
+                timestamp: 20,
+                ..Default::default()
+            }
+        );
+        // Only placed once by chat.
+        assert_eq!(archive.placed_since(0).unwrap(), 3);
+        std::fs::remove_file(&sqlite_path).unwrap();
+    }
+
+    #[test]
+    fn test_import_chunks() {
+        let sqlite_path = std::path::PathBuf::from(".testlite-imports");
+        let _ = std::fs::remove_file(&sqlite_path);
+        let mut archive = CubeArchive::new(sqlite_path.clone());
+        let cube = |x| Cube::new(x, 0, 0, Colour::new(0, 0, 0));
+        assert_eq!(archive.import_progress("castle").unwrap(), None);
+        archive
+            .import_chunk("castle", &[cube(0), cube(1)], 2)
+            .unwrap();
+        archive.import_chunk("castle", &[cube(2)], 5).unwrap();
+        archive.import_chunk("tree", &[], 1).unwrap();
+        assert_eq!(archive.import_progress("castle").unwrap(), Some(5));
+        assert_eq!(archive.import_progress("tree").unwrap(), Some(1));
+        assert_eq!(
+            archive.get_cubes().unwrap(),
+            vec![cube(0), cube(1), cube(2)]
+        );
+        std::fs::remove_file(&sqlite_path).unwrap();
+    }
+
+    #[test]
+    fn test_remap_positions() {
+        let sqlite_path = std::path::PathBuf::from(".testlite-remap");
+        let backup_path = std::path::PathBuf::from(".testlite-remap-backup");
+        let _ = std::fs::remove_file(&sqlite_path);
+        let _ = std::fs::remove_file(&backup_path);
+        let mut archive = CubeArchive::new(sqlite_path.clone());
+        let cube = |x| Cube::new(x, 1, 2, Colour::new(0, 0, 0));
+        for x in 0..3 {
+            archive.add_cube(cube(x)).unwrap();
+        }
+        let mut append = |event: CanvasEvent| {
+            archive
+                .append_event(Uuid::new_v4(), &event, &EventMetadata::default())
+                .unwrap();
+        };
+        append(CanvasEvent::CubeRemoved(Position::new(2, 1, 2)));
+        append(CanvasEvent::Recoloured {
+            position: Position::new(0, 1, 2),
+            colour: Colour::new(1, 1, 1),
+        });
+        archive.quarantine("troll", &[cube(1), cube(2)]).unwrap();
+        archive.backup(&backup_path).unwrap();
+
+        // Drops x = 2, moves the others one step right.
+        let remap = |p: Position| match p.x < 2 {
+            true => Some(Position::new(p.x + 1, p.y, p.z)),
+            false => None,
+        };
+        assert_eq!(archive.remap_positions(remap).unwrap(), (3, 2));
+        assert_eq!(
+            archive.get_cubes().unwrap(),
+            vec![
+                Cube::new(1, 1, 2, Colour::new(1, 1, 1)),
+                Cube::new(2, 1, 2, Colour::new(0, 0, 0)),
+            ]
+        );
+        assert_eq!(archive.release_quarantine("troll").unwrap(), vec![cube(2)]);
+        // The backup is untouched.
+        let mut backup = CubeArchive::new(backup_path.clone());
+        assert_eq!(backup.get_cubes().unwrap().len(), 2);
+        assert!(archive.backup(&backup_path).is_err());
+        std::fs::remove_file(&sqlite_path).unwrap();
+        std::fs::remove_file(&backup_path).unwrap();
+    }
+
+    #[test]
+    fn test_resample() {
+        let sqlite_path = std::path::PathBuf::from(".testlite-resample");
+        let _ = std::fs::remove_file(&sqlite_path);
+        let mut archive = CubeArchive::new(sqlite_path.clone());
+        let (red, blue) = (Colour::new(255, 0, 0), Colour::new(0, 0, 255));
+        archive.add_cube(Cube::new(2, 3, 4, red)).unwrap();
+        archive.add_cube(Cube::new(3, 3, 5, red)).unwrap();
+        let metadata = EventMetadata {
+            author: Some("builder".to_owned()),
+            timestamp: 1_600_000_000,
+            ..Default::default()
+        };
+        let event = CanvasEvent::CubePlaced(Cube::new(3, 2, 5, blue));
+        archive
+            .append_event(Uuid::new_v4(), &event, &metadata)
+            .unwrap();
+        archive
+            .quarantine("troll", &[Cube::new(5, 5, 5, blue)])
+            .unwrap();
+
+        let downsample = Resample::Down(2, crate::MergeColours::Majority);
+        assert_eq!(archive.resample(downsample).unwrap(), 1);
+        assert_eq!(archive.get_cubes().unwrap(), vec![Cube::new(1, 1, 2, red)]);
+        let (_, entry) = archive.lookup(Position::new(1, 1, 2)).unwrap().unwrap();
+        assert_eq!(entry.metadata, metadata);
+        assert_eq!(archive.resample(Resample::Up(2)).unwrap(), 8);
+        assert_eq!(archive.get_cubes().unwrap()[7], Cube::new(3, 3, 5, red));
+        let (_, entry) = archive.lookup(Position::new(2, 2, 4)).unwrap().unwrap();
+        assert_eq!(entry.metadata, metadata);
+        assert_eq!(
+            archive.release_quarantine("troll").unwrap(),
+            Resample::Up(2).apply(&[Cube::new(2, 2, 2, blue)])
+        );
+        std::fs::remove_file(&sqlite_path).unwrap();
+    }
+
+    #[test]
+    fn test_external_events() {
+        let sqlite_path = std::path::PathBuf::from(".testlite-external");
+        let _ = std::fs::remove_file(&sqlite_path);
+        let mut archive = CubeArchive::new(sqlite_path.clone());
+        let mut other = CubeArchive::new(sqlite_path.clone());
+        let red = Colour::new(255, 0, 0);
+        archive.add_cube(Cube::new(0, 0, 0, red)).unwrap();
+        assert!(archive.external_events().unwrap().is_empty());
+        archive.add_cube(Cube::new(1, 0, 0, red)).unwrap();
+        assert!(archive.external_events().unwrap().is_empty());
+
+        other.add_cube(Cube::new(2, 0, 0, red)).unwrap();
+        archive.add_cube(Cube::new(3, 0, 0, red)).unwrap();
+        other.add_cube(Cube::new(4, 0, 0, red)).unwrap();
+        let events: Vec<CanvasEvent> = archive
+            .external_events()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.event)
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                CanvasEvent::CubePlaced(Cube::new(2, 0, 0, red)),
+                CanvasEvent::CubePlaced(Cube::new(4, 0, 0, red)),
+            ]
+        );
+        assert!(archive.external_events().unwrap().is_empty());
+
+        let events = [
+            CanvasEvent::CubeRemoved(Position::new(0, 0, 0)),
+            CanvasEvent::CubeRemoved(Position::new(2, 0, 0)),
+        ];
+        other
+            .append_events(&events, &EventMetadata::default())
+            .unwrap();
+        assert_eq!(archive.external_events().unwrap().len(), 2);
+        assert_eq!(archive.get_cubes().unwrap().len(), 3);
+        std::fs::remove_file(&sqlite_path).unwrap();
+    }
+
+    #[test]
+    fn test_prune() {
+        let sqlite_path = std::path::PathBuf::from(".testlite-prune");
+        let _ = std::fs::remove_file(&sqlite_path);
+        let mut archive = CubeArchive::new(sqlite_path.clone());
+        let mut append = |event: CanvasEvent, timestamp| {
+            let metadata = EventMetadata {
+                timestamp,
+                ..Default::default()
+            };
+            archive
+                .append_event(Uuid::new_v4(), &event, &metadata)
+                .unwrap();
+        };
+        let cube = |x, r| Cube::new(x, 0, 0, Colour::new(r, 0, 0));
+        append(CanvasEvent::CubePlaced(cube(0, 1)), 100);
+        append(CanvasEvent::CubePlaced(cube(0, 2)), 200);
+        append(CanvasEvent::CubePlaced(cube(1, 3)), 300);
+        append(CanvasEvent::CubeRemoved(Position::new(1, 0, 0)), 400);
+        append(CanvasEvent::CubePlaced(cube(2, 4)), 500);
+        let cubes = archive.get_cubes().unwrap();
+
+        let report = archive.prune(Some(450), None, true).unwrap();
+        assert_eq!(
+            report,
+            PruneReport {
+                events: 5,
+                pruned: 3,
+                until: Some(400),
+            }
+        );
+        assert_eq!(archive.get_journal().unwrap().len(), 5);
+        assert_eq!(archive.prune(Some(450), None, false).unwrap(), report);
+        assert_eq!(archive.get_journal().unwrap().len(), 2);
+        assert_eq!(archive.get_cubes().unwrap(), cubes);
+        assert_eq!(archive.prune(None, Some(1), false).unwrap().pruned, 0);
+        std::fs::remove_file(&sqlite_path).unwrap();
+    }
+
+    #[test]
+    fn test_connection_pool() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut archive = CubeArchive::new(dir.path().join("archive.sqlite"));
+        let cube = Cube::new(1, 2, 3, Colour::new(4, 5, 6));
+        archive.add_cube(cube.clone()).unwrap();
+        {
+            let mut reader = archive.reopen();
+            assert_eq!(reader.get_cubes().unwrap(), vec![cube.clone()]);
+        }
+        // The reader's connection is kept for the next one.
+        assert_eq!(archive.pool.idle.lock().unwrap().len(), 1);
+        let mut reader = archive.reopen();
+        assert!(reader.contains_command(Uuid::new_v4()).is_ok());
+        assert!(archive.pool.idle.lock().unwrap().is_empty());
+        drop(archive);
+        assert_eq!(reader.get_cubes().unwrap(), vec![cube]);
+    }
 }
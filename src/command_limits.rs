@@ -0,0 +1,358 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct CommandSettings {
+    pub enabled: bool,
+    /// Seconds a chatter waits between two uses of the command.
+    pub cooldown_secs: u64,
+    /// Most cubes a single use of the command can change.
+    pub max_volume: Option<u64>,
+}
+
+impl Default for CommandSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cooldown_secs: 0,
+            max_volume: None,
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CommandRejected {
+    #[error("!{0} is disabled")]
+    Disabled(String),
+    #[error("wait {0} more seconds")]
+    Cooldown(u64),
+    #[error("that's too big, at most {0} cubes at once")]
+    TooLarge(u64),
+    #[error("chat is changing the canvas too fast, wait {0} more seconds")]
+    OverBudget(u64),
+}
+
+// Past this many tracked uses, the ones whose cooldown ended are forgotten.
+const MAX_TRACKED_USES: usize = 10_000;
+
+/// Enforces the per command settings, commands without settings are enabled
+/// and unlimited. Placements go by the name `place`.
+#[derive(Clone, Debug, Default)]
+pub struct CommandLimits {
+    settings: HashMap<String, CommandSettings>,
+    // When each chatter last used each command.
+    last_used: HashMap<(String, String), Instant>,
+}
+
+impl CommandLimits {
+    pub fn new(settings: &HashMap<String, CommandSettings>) -> Self {
+        Self {
+            settings: settings.clone(),
+            last_used: HashMap::new(),
+        }
+    }
+
+    /// Checks whether `login` can run `command` changing `volume` cubes, and
+    /// if so starts its cooldown. Moderators are exempt from cooldowns and
+    /// volume limits, not from disabled commands.
+    pub fn check(
+        &mut self,
+        command: &str,
+        login: &str,
+        moderator: bool,
+        volume: u64,
+        now: Instant,
+    ) -> Result<(), CommandRejected> {
+        let settings = match self.settings.get(command) {
+            Some(settings) => settings,
+            None => return Ok(()),
+        };
+        if !settings.enabled {
+            return Err(CommandRejected::Disabled(command.to_owned()));
+        }
+        if moderator {
+            return Ok(());
+        }
+        if let Some(max_volume) = settings.max_volume {
+            if volume > max_volume {
+                return Err(CommandRejected::TooLarge(max_volume));
+            }
+        }
+        let cooldown = Duration::from_secs(settings.cooldown_secs);
+        if cooldown.is_zero() {
+            return Ok(());
+        }
+        let key = (command.to_owned(), login.to_owned());
+        if let Some(last_used) = self.last_used.get(&key) {
+            let elapsed = now.saturating_duration_since(*last_used);
+            if elapsed < cooldown {
+                return Err(CommandRejected::Cooldown(
+                    (cooldown - elapsed).as_secs_f32().ceil() as u64,
+                ));
+            }
+        }
+        if self.last_used.len() >= MAX_TRACKED_USES {
+            let settings = &self.settings;
+            self.last_used.retain(|(command, _), last_used| {
+                let cooldown = settings.get(command).map_or(0, |s| s.cooldown_secs);
+                now.saturating_duration_since(*last_used) < Duration::from_secs(cooldown)
+            });
+        }
+        self.last_used.insert(key, now);
+        Ok(())
+    }
+}
+
+/// A single use of a command by a chatter, which the events it produced
+/// count towards.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommandUse {
+    pub command: String,
+    pub id: Uuid,
+}
+
+// Uses whose volume is still counted, their events are queued together.
+const MAX_COUNTED_USES: usize = 1_000;
+
+/// Counts the cubes changed by each use of a command as its events are
+/// applied, so that `max_volume` holds whatever produced them: an import, a
+/// plugin or a script.
+#[derive(Clone, Debug, Default)]
+pub struct VolumeCounter {
+    max_volumes: HashMap<String, u64>,
+    volumes: HashMap<Uuid, u64>,
+    // Oldest use first.
+    uses: VecDeque<Uuid>,
+}
+
+impl VolumeCounter {
+    pub fn new(settings: &HashMap<String, CommandSettings>) -> Self {
+        Self {
+            max_volumes: settings
+                .iter()
+                .filter_map(|(command, s)| Some((command.clone(), s.max_volume?)))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Counts one more cube changed by `command_use`, rejected past the
+    /// `max_volume` of its command.
+    pub fn count(&mut self, command_use: &CommandUse) -> Result<(), CommandRejected> {
+        let max_volume = match self.max_volumes.get(&command_use.command) {
+            Some(max_volume) => *max_volume,
+            None => return Ok(()),
+        };
+        if !self.volumes.contains_key(&command_use.id) {
+            if self.uses.len() >= MAX_COUNTED_USES {
+                if let Some(oldest) = self.uses.pop_front() {
+                    self.volumes.remove(&oldest);
+                }
+            }
+            self.uses.push_back(command_use.id);
+        }
+        let volume = self.volumes.entry(command_use.id).or_insert(0);
+        if *volume >= max_volume {
+            return Err(CommandRejected::TooLarge(max_volume));
+        }
+        *volume += 1;
+        Ok(())
+    }
+}
+
+/// What happens to the edits of chat over the budget.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetOverflow {
+    /// The edits wait for the budget, up to a minute's worth of them.
+    Queue,
+    Reject,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct EditBudgetConfig {
+    /// Fraction of the canvas chat can change every minute, all chatters
+    /// together, e.g. 0.01 for 1%. 0 sets no budget.
+    pub fraction_per_minute: f64,
+    pub overflow: BudgetOverflow,
+}
+
+impl Default for EditBudgetConfig {
+    fn default() -> Self {
+        Self {
+            fraction_per_minute: 0.0,
+            overflow: BudgetOverflow::Queue,
+        }
+    }
+}
+
+// The budget is spent over a sliding minute.
+const BUDGET_WINDOW: Duration = Duration::from_secs(60);
+
+/// Caps the cubes chat changes every minute, whoever changes them, so that
+/// even a raid can only alter a fraction of the canvas at a time.
+#[derive(Clone, Debug)]
+pub struct EditBudget {
+    per_minute: usize,
+    // When the edits of the last minute were made, oldest first.
+    edits: VecDeque<Instant>,
+}
+
+impl EditBudget {
+    /// Budget of a canvas of `volume` cubes, `None` when the configuration
+    /// sets none.
+    pub fn new(config: &EditBudgetConfig, volume: u64) -> Option<Self> {
+        if config.fraction_per_minute <= 0.0 {
+            return None;
+        }
+        let per_minute = (config.fraction_per_minute * volume as f64).floor() as usize;
+        Some(Self {
+            per_minute: per_minute.max(1),
+            edits: VecDeque::new(),
+        })
+    }
+
+    /// Edits chat can make every minute.
+    pub fn per_minute(&self) -> usize {
+        self.per_minute
+    }
+
+    /// Spends the budget of one edit, rejected while the last minute used it
+    /// all.
+    pub fn spend(&mut self, now: Instant) -> Result<(), CommandRejected> {
+        while self
+            .edits
+            .front()
+            .is_some_and(|edit| *edit + BUDGET_WINDOW <= now)
+        {
+            self.edits.pop_front();
+        }
+        if self.edits.len() >= self.per_minute {
+            let wait = self.next_edit(now).saturating_duration_since(now);
+            return Err(CommandRejected::OverBudget(wait.as_secs_f32().ceil() as u64));
+        }
+        self.edits.push_back(now);
+        Ok(())
+    }
+
+    /// When the budget allows the next edit.
+    pub fn next_edit(&self, now: Instant) -> Instant {
+        match self.edits.len() >= self.per_minute {
+            true => self.edits[self.edits.len() - self.per_minute] + BUDGET_WINDOW,
+            false => now,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits() {
+        let mut settings = HashMap::new();
+        settings.insert(
+            "place".to_owned(),
+            CommandSettings {
+                cooldown_secs: 10,
+                max_volume: Some(1),
+                ..Default::default()
+            },
+        );
+        settings.insert(
+            "vote".to_owned(),
+            CommandSettings {
+                enabled: false,
+                ..Default::default()
+            },
+        );
+        let mut limits = CommandLimits::new(&settings);
+        let now = Instant::now();
+        assert_eq!(limits.check("lookup", "a", false, 1, now), Ok(()));
+        assert_eq!(
+            limits.check("vote", "a", true, 1, now),
+            Err(CommandRejected::Disabled("vote".to_owned()))
+        );
+        assert_eq!(
+            limits.check("place", "a", false, 2, now),
+            Err(CommandRejected::TooLarge(1))
+        );
+        assert_eq!(limits.check("place", "a", false, 1, now), Ok(()));
+        assert_eq!(
+            limits.check("place", "a", false, 1, now + Duration::from_secs(4)),
+            Err(CommandRejected::Cooldown(6))
+        );
+        assert_eq!(limits.check("place", "b", false, 1, now), Ok(()));
+        assert_eq!(limits.check("place", "a", true, 5, now), Ok(()));
+        assert_eq!(
+            limits.check("place", "a", false, 1, now + Duration::from_secs(10)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_volumes() {
+        let mut settings = HashMap::new();
+        settings.insert(
+            "fill".to_owned(),
+            CommandSettings {
+                max_volume: Some(2),
+                ..Default::default()
+            },
+        );
+        let mut volumes = VolumeCounter::new(&settings);
+        let fill = |id| CommandUse {
+            command: "fill".to_owned(),
+            id,
+        };
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        assert_eq!(volumes.count(&fill(first)), Ok(()));
+        assert_eq!(volumes.count(&fill(first)), Ok(()));
+        assert_eq!(
+            volumes.count(&fill(first)),
+            Err(CommandRejected::TooLarge(2))
+        );
+        assert_eq!(volumes.count(&fill(second)), Ok(()));
+        let import = CommandUse {
+            command: "import".to_owned(),
+            id: first,
+        };
+        for _ in 0..5 {
+            assert_eq!(volumes.count(&import), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_edit_budget() {
+        let unlimited = EditBudgetConfig::default();
+        assert!(EditBudget::new(&unlimited, 1000).is_none());
+
+        let config = EditBudgetConfig {
+            fraction_per_minute: 0.002,
+            ..Default::default()
+        };
+        let mut budget = EditBudget::new(&config, 1000).unwrap();
+        assert_eq!(budget.per_minute(), 2);
+        let now = Instant::now();
+        assert_eq!(budget.spend(now), Ok(()));
+        assert_eq!(budget.spend(now + Duration::from_secs(20)), Ok(()));
+        assert_eq!(
+            budget.spend(now + Duration::from_secs(30)),
+            Err(CommandRejected::OverBudget(30))
+        );
+        assert_eq!(
+            budget.next_edit(now + Duration::from_secs(30)),
+            now + BUDGET_WINDOW
+        );
+        assert_eq!(budget.spend(now + BUDGET_WINDOW), Ok(()));
+        assert!(budget.spend(now + BUDGET_WINDOW).is_err());
+
+        // Tiny canvases still get an edit a minute.
+        assert_eq!(EditBudget::new(&config, 8).unwrap().per_minute(), 1);
+    }
+}
@@ -0,0 +1,362 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+/// What happens to commands sent while the queue is full.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowPolicy {
+    /// The command is handed back to the sender, e.g. to tell the chatter.
+    Drop,
+    /// The command is appended to a file, and queued again once there's room.
+    Disk,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct CommandQueueConfig {
+    /// Number of commands waiting to be processed before overflowing.
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+    /// Where commands are stored with the `disk` policy.
+    pub spill_filepath: String,
+}
+
+impl Default for CommandQueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            overflow: OverflowPolicy::Drop,
+            spill_filepath: "twixelbox-commands.jsonl".to_owned(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CommandQueueError<T> {
+    #[error("the command queue is full")]
+    Full(T),
+    #[error("the command queue is closed")]
+    Closed(T),
+    #[error("unable to spill the command to disk {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Counters describing the state of the queue.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CommandQueueMetrics {
+    /// Commands waiting to be processed, including the ones on disk.
+    pub depth: usize,
+    pub spilled: usize,
+    /// Commands rejected because the queue was full, since the start.
+    pub dropped: u64,
+}
+
+// Commands spilled to disk, in order. While any command is on disk new
+// commands go there too, so that they're processed in the order they were
+// sent. The file is only appended to: the commands moved back into the queue
+// are behind the read offset, saved next to it for restarts, and the file is
+// removed once all of it has been read.
+struct Spill {
+    filepath: String,
+    len: usize,
+    offset: u64,
+}
+
+/// Sending side of a bounded command queue.
+pub struct CommandSender<T> {
+    tx: mpsc::Sender<T>,
+    capacity: usize,
+    spill: Option<Arc<Mutex<Spill>>>,
+    dropped: Arc<AtomicU64>,
+}
+
+// Derived Clone would require T: Clone.
+impl<T> Clone for CommandSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            capacity: self.capacity,
+            spill: self.spill.clone(),
+            dropped: self.dropped.clone(),
+        }
+    }
+}
+
+/// Creates a bounded command queue. With the `disk` overflow policy, commands
+/// left on disk by a previous run are queued again, and a task moves spilled
+/// commands back into the queue as room frees up, so this must be called
+/// within a tokio runtime.
+pub fn command_queue<T>(
+    config: &CommandQueueConfig,
+) -> Result<(CommandSender<T>, mpsc::Receiver<T>), CommandQueueError<T>>
+where
+    T: Serialize + DeserializeOwned + Send + 'static,
+{
+    let capacity = config.capacity.max(1);
+    let (tx, rx) = mpsc::channel(capacity);
+    let spill = match config.overflow {
+        OverflowPolicy::Drop => None,
+        OverflowPolicy::Disk => {
+            let spill = Arc::new(Mutex::new(Spill::open(&config.spill_filepath)?));
+            tokio::spawn(drain_spill(spill.clone(), tx.clone()));
+            Some(spill)
+        }
+    };
+    Ok((
+        CommandSender {
+            tx,
+            capacity,
+            spill,
+            dropped: Arc::new(AtomicU64::new(0)),
+        },
+        rx,
+    ))
+}
+
+impl<T: Serialize + DeserializeOwned> CommandSender<T> {
+    /// Queues a command, applying the overflow policy if the queue is full.
+    pub fn send(&self, command: T) -> Result<(), CommandQueueError<T>> {
+        let spill = match &self.spill {
+            None => return self.try_send(command),
+            Some(spill) => spill,
+        };
+        let mut spill = spill.lock().unwrap();
+        if spill.len == 0 {
+            match self.tx.try_send(command) {
+                Ok(()) => return Ok(()),
+                Err(mpsc::error::TrySendError::Closed(command)) => {
+                    return Err(CommandQueueError::Closed(command))
+                }
+                Err(mpsc::error::TrySendError::Full(command)) => {
+                    spill.push(&command)?;
+                    return Ok(());
+                }
+            }
+        }
+        spill.push(&command)?;
+        Ok(())
+    }
+
+    /// Queues a command if there's room, regardless of the overflow policy.
+    /// Meant for commands which are pointless to delay, like rendering a
+    /// frame.
+    pub fn try_send(&self, command: T) -> Result<(), CommandQueueError<T>> {
+        match self.tx.try_send(command) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(command)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                Err(CommandQueueError::Full(command))
+            }
+            Err(mpsc::error::TrySendError::Closed(command)) => {
+                Err(CommandQueueError::Closed(command))
+            }
+        }
+    }
+
+    /// Waits for room in the queue, pushing back on the producer.
+    pub async fn send_wait(&self, command: T) -> Result<(), CommandQueueError<T>> {
+        self.tx
+            .send(command)
+            .await
+            .map_err(|mpsc::error::SendError(command)| CommandQueueError::Closed(command))
+    }
+
+    pub fn metrics(&self) -> CommandQueueMetrics {
+        let spilled = match &self.spill {
+            Some(spill) => spill.lock().unwrap().len,
+            None => 0,
+        };
+        CommandQueueMetrics {
+            depth: self.capacity - self.tx.capacity() + spilled,
+            spilled,
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Spill {
+    // Picks up the commands left unread by a previous run.
+    fn open(filepath: &str) -> Result<Self, std::io::Error> {
+        let offset = match std::fs::read_to_string(offset_filepath(filepath)) {
+            Ok(offset) => offset.trim().parse().unwrap_or(0),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+        let len = match std::fs::File::open(filepath) {
+            Ok(mut file) => {
+                file.seek(SeekFrom::Start(offset))?;
+                BufReader::new(file).lines().count()
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+        Ok(Spill {
+            filepath: filepath.to_owned(),
+            len,
+            offset,
+        })
+    }
+
+    fn push<T: Serialize>(&mut self, command: &T) -> Result<(), std::io::Error> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.filepath)?;
+        writeln!(file, "{}", serde_json::to_string(command)?)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    // Moves as many commands as there's room for back into the queue, reading
+    // the file from the offset.
+    fn drain<T: DeserializeOwned>(&mut self, tx: &mpsc::Sender<T>) -> Result<(), std::io::Error> {
+        let mut file = std::fs::File::open(&self.filepath)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        let offset = self.offset;
+        while self.len > 0 {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 {
+                break;
+            }
+            match serde_json::from_str(line.trim_end()) {
+                Ok(command) => {
+                    if tx.try_send(command).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => eprintln!("Discarding unreadable spilled command {}: {}", line, e),
+            }
+            self.offset += read as u64;
+            self.len -= 1;
+        }
+        if self.len == 0 {
+            remove_if_exists(&self.filepath)?;
+            remove_if_exists(&offset_filepath(&self.filepath))?;
+            self.offset = 0;
+        } else if self.offset != offset {
+            std::fs::write(offset_filepath(&self.filepath), self.offset.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+fn offset_filepath(filepath: &str) -> String {
+    format!("{}.offset", filepath)
+}
+
+fn remove_if_exists(filepath: &str) -> Result<(), std::io::Error> {
+    match std::fs::remove_file(filepath) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+async fn drain_spill<T>(spill: Arc<Mutex<Spill>>, tx: mpsc::Sender<T>)
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let mut interval = tokio::time::interval(std::time::Duration::from_millis(200));
+    while !tx.is_closed() {
+        interval.tick().await;
+        if spill.lock().unwrap().len == 0 {
+            continue;
+        }
+        // Off the runtime threads, senders wait for the file meanwhile.
+        let (spill, tx) = (spill.clone(), tx.clone());
+        match tokio::task::spawn_blocking(move || spill.lock().unwrap().drain(&tx)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => eprintln!("Unable to read the spilled commands: {}", e),
+            Err(e) => eprintln!("Unable to read the spilled commands: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drop() {
+        let (tx, mut rx) = command_queue::<u32>(&CommandQueueConfig {
+            capacity: 2,
+            ..Default::default()
+        })
+        .unwrap();
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        assert!(matches!(tx.send(3), Err(CommandQueueError::Full(3))));
+        assert_eq!(
+            tx.metrics(),
+            CommandQueueMetrics {
+                depth: 2,
+                spilled: 0,
+                dropped: 1
+            }
+        );
+        assert_eq!(rx.recv().await, Some(1));
+        tx.send(4).unwrap();
+        assert_eq!(rx.recv().await, Some(2));
+        assert_eq!(rx.recv().await, Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_disk_keeps_order() {
+        let spill_filepath = ".test-spill.jsonl".to_owned();
+        let _ = std::fs::remove_file(&spill_filepath);
+        let (tx, mut rx) = command_queue::<u32>(&CommandQueueConfig {
+            capacity: 2,
+            overflow: OverflowPolicy::Disk,
+            spill_filepath: spill_filepath.clone(),
+        })
+        .unwrap();
+        for i in 0..5 {
+            tx.send(i).unwrap();
+        }
+        assert_eq!(tx.metrics().depth, 5);
+        assert_eq!(tx.metrics().spilled, 3);
+        let mut received = Vec::new();
+        for _ in 0..5 {
+            received.push(rx.recv().await.unwrap());
+        }
+        assert_eq!(received, vec![0, 1, 2, 3, 4]);
+        assert_eq!(tx.metrics().depth, 0);
+        assert!(!std::path::Path::new(&spill_filepath).exists());
+    }
+
+    #[tokio::test]
+    async fn test_disk_resumes() {
+        let spill_filepath = ".test-spill-resumes.jsonl".to_owned();
+        let _ = std::fs::remove_file(&spill_filepath);
+        let _ = std::fs::remove_file(offset_filepath(&spill_filepath));
+        let config = CommandQueueConfig {
+            capacity: 2,
+            overflow: OverflowPolicy::Disk,
+            spill_filepath: spill_filepath.clone(),
+        };
+        {
+            let (tx, mut rx) = command_queue::<u32>(&config).unwrap();
+            for i in 0..5 {
+                tx.send(i).unwrap();
+            }
+            assert_eq!(rx.recv().await, Some(0));
+            while tx.metrics().spilled > 2 {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+        }
+        // 1 and 2 were in the queue when it stopped, the rest is still read.
+        let (_tx, mut rx) = command_queue::<u32>(&config).unwrap();
+        assert_eq!(rx.recv().await, Some(3));
+        assert_eq!(rx.recv().await, Some(4));
+        let _ = std::fs::remove_file(&spill_filepath);
+        let _ = std::fs::remove_file(offset_filepath(&spill_filepath));
+    }
+}
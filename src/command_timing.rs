@@ -0,0 +1,219 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct CommandTimingConfig {
+    /// Commands taking longer than this, all stages together, are logged
+    /// with the time of each stage and counted as slow.
+    pub slow_ms: u64,
+    /// Stages taking longer than this alone make the command slow, e.g. a
+    /// write to the archive stuck behind a backup.
+    pub slow_stage_ms: u64,
+}
+
+impl Default for CommandTimingConfig {
+    fn default() -> Self {
+        Self {
+            slow_ms: 100,
+            slow_stage_ms: 50,
+        }
+    }
+}
+
+/// Steps a command goes through, from the chat message to the canvas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Reading the chat message into a command.
+    Parse,
+    /// Checking the command against the locks, filters and the canvas.
+    Validate,
+    /// Journaling the event, and everything fed from the journal.
+    Persist,
+    /// Drawing the event on the scene.
+    Apply,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [Stage::Parse, Stage::Validate, Stage::Persist, Stage::Apply];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Stage::Parse => "parse",
+            Stage::Validate => "validate",
+            Stage::Persist => "persist",
+            Stage::Apply => "apply",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Time a single command spent in each stage, timed as laps of a clock.
+#[derive(Clone, Copy, Debug)]
+pub struct CommandTiming {
+    durations: [Duration; 4],
+    // End of the last lap.
+    lap: Instant,
+}
+
+impl CommandTiming {
+    /// Starts the clock of the first lap.
+    pub fn start() -> Self {
+        Self {
+            durations: Default::default(),
+            lap: Instant::now(),
+        }
+    }
+
+    pub fn record(&mut self, stage: Stage, duration: Duration) {
+        self.durations[stage.index()] += duration;
+    }
+
+    /// Records the time since the last lap in `stage`, and starts the next
+    /// one.
+    pub fn lap(&mut self, stage: Stage) {
+        let now = Instant::now();
+        self.record(stage, now.saturating_duration_since(self.lap));
+        self.lap = now;
+    }
+
+    pub fn get(&self, stage: Stage) -> Duration {
+        self.durations[stage.index()]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.durations.iter().sum()
+    }
+}
+
+impl fmt::Display for CommandTiming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, stage) in Stage::ALL.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {:?}", stage, self.get(*stage))?;
+        }
+        Ok(())
+    }
+}
+
+/// Durations of a stage over the commands timed, in milliseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct StageMetrics {
+    pub total_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Counters of the commands timed since the start.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct CommandTimingMetrics {
+    pub commands: u64,
+    /// Commands over one of the thresholds.
+    pub slow: u64,
+    pub parse: StageMetrics,
+    pub validate: StageMetrics,
+    pub persist: StageMetrics,
+    pub apply: StageMetrics,
+}
+
+/// Tells the slow commands apart, and keeps the metrics of all of them.
+#[derive(Clone, Debug)]
+pub struct CommandTimer {
+    slow: Duration,
+    slow_stage: Duration,
+    commands: u64,
+    slow_commands: u64,
+    totals: [Duration; 4],
+    maxima: [Duration; 4],
+}
+
+impl CommandTimer {
+    pub fn new(config: &CommandTimingConfig) -> Self {
+        Self {
+            slow: Duration::from_millis(config.slow_ms),
+            slow_stage: Duration::from_millis(config.slow_stage_ms),
+            commands: 0,
+            slow_commands: 0,
+            totals: Default::default(),
+            maxima: Default::default(),
+        }
+    }
+
+    /// Counts the timing of a command, returns whether it was slow.
+    pub fn observe(&mut self, timing: &CommandTiming) -> bool {
+        self.commands += 1;
+        for (i, duration) in timing.durations.iter().enumerate() {
+            self.totals[i] += *duration;
+            self.maxima[i] = self.maxima[i].max(*duration);
+        }
+        let slow = timing.total() > self.slow
+            || timing
+                .durations
+                .iter()
+                .any(|duration| *duration > self.slow_stage);
+        self.slow_commands += slow as u64;
+        slow
+    }
+
+    pub fn metrics(&self) -> CommandTimingMetrics {
+        let stage = |stage: Stage| StageMetrics {
+            total_ms: self.totals[stage.index()].as_secs_f64() * 1000.0,
+            max_ms: self.maxima[stage.index()].as_secs_f64() * 1000.0,
+        };
+        CommandTimingMetrics {
+            commands: self.commands,
+            slow: self.slow_commands,
+            parse: stage(Stage::Parse),
+            validate: stage(Stage::Validate),
+            persist: stage(Stage::Persist),
+            apply: stage(Stage::Apply),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_timer() {
+        let config = CommandTimingConfig {
+            slow_ms: 100,
+            slow_stage_ms: 50,
+        };
+        let mut timer = CommandTimer::new(&config);
+        let ms = Duration::from_millis;
+        let timing = |durations: [u64; 4]| {
+            let mut timing = CommandTiming::start();
+            for (stage, duration) in Stage::ALL.iter().zip(durations.iter()) {
+                timing.record(*stage, ms(*duration));
+            }
+            timing
+        };
+
+        assert!(!timer.observe(&timing([1, 2, 40, 30])));
+        // A single stage over its threshold.
+        assert!(timer.observe(&timing([0, 0, 60, 0])));
+        // No stage over, but all of them together.
+        assert!(timer.observe(&timing([30, 30, 30, 30])));
+
+        let metrics = timer.metrics();
+        assert_eq!(metrics.commands, 3);
+        assert_eq!(metrics.slow, 2);
+        assert_eq!(metrics.persist.total_ms, 130.0);
+        assert_eq!(metrics.persist.max_ms, 60.0);
+        assert_eq!(metrics.parse.max_ms, 30.0);
+        assert_eq!(
+            timing([1, 2, 3, 4]).to_string(),
+            "parse 1ms, validate 2ms, persist 3ms, apply 4ms"
+        );
+    }
+}
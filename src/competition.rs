@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+/// A timed build competition. Placements journaled while it runs are tagged
+/// with its id, and chatters are ranked by the cubes they placed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Competition {
+    pub id: i64,
+    pub name: String,
+    /// Unix timestamps in seconds.
+    pub started_at: i64,
+    pub ends_at: i64,
+}
+
+/// Parses durations like `90s`, `30m` or `2h`.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let unit = value.chars().last()?;
+    let amount: u64 = value[..value.len() - unit.len_utf8()].parse().ok()?;
+    let seconds = match unit {
+        's' => amount,
+        'm' => amount.checked_mul(60)?,
+        'h' => amount.checked_mul(3600)?,
+        _ => return None,
+    };
+    Some(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Some(Duration::from_secs(1800)));
+        assert_eq!(parse_duration(" 2h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("2d"), None);
+        assert_eq!(parse_duration("m"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+}
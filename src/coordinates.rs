@@ -0,0 +1,234 @@
+use crate::{bounded_position, Cube, Position};
+use serde::Deserialize;
+
+/// Axis pointing up in the coordinates chatters and tools use.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpAxis {
+    /// Nothing is up, y grows downwards as in images. The orientation of the
+    /// canvas itself.
+    YDown,
+    /// Y is up, as in Qubicle and Minecraft.
+    Y,
+    /// Z is up, as in Goxel and Blender.
+    Z,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Handedness {
+    Left,
+    Right,
+}
+
+/// Where coordinates start from.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    /// A corner of the canvas, coordinates are never negative.
+    Corner,
+    /// The middle of the canvas, coordinates are negative on one side.
+    #[serde(alias = "center")]
+    Centre,
+}
+
+/// How the coordinates given in chat and to the importers map to positions
+/// of the canvas. X grows to the right whatever the system, and up is drawn
+/// up by the renderers.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct CoordinateSystem {
+    pub up: UpAxis,
+    /// The usual one of `up` when unset: left handed for y-down, as the
+    /// canvas, right handed otherwise.
+    pub handedness: Option<Handedness>,
+    pub origin: Origin,
+}
+
+impl Default for CoordinateSystem {
+    fn default() -> Self {
+        Self {
+            up: UpAxis::YDown,
+            handedness: None,
+            origin: Origin::Corner,
+        }
+    }
+}
+
+impl CoordinateSystem {
+    // For each axis of the canvas (right, down, front), the axis of the
+    // system it follows, and whether it goes the other way.
+    fn axes(&self) -> [(usize, bool); 3] {
+        let right_handed = match (self.handedness, self.up) {
+            (Some(handedness), _) => handedness == Handedness::Right,
+            (None, up) => up != UpAxis::YDown,
+        };
+        match (self.up, right_handed) {
+            (UpAxis::YDown, false) => [(0, false), (1, false), (2, false)],
+            (UpAxis::YDown, true) => [(0, false), (1, false), (2, true)],
+            // Z towards the front.
+            (UpAxis::Y, true) => [(0, false), (1, true), (2, false)],
+            (UpAxis::Y, false) => [(0, false), (1, true), (2, true)],
+            // Y towards the back.
+            (UpAxis::Z, true) => [(0, false), (2, true), (1, true)],
+            (UpAxis::Z, false) => [(0, false), (2, true), (1, false)],
+        }
+    }
+
+    // Canvas coordinate of the system's 0 along a canvas axis, flipped or
+    // not.
+    fn base(&self, flipped: bool, side_len: u32) -> i64 {
+        match (self.origin, flipped) {
+            (Origin::Corner, false) => 0,
+            (Origin::Corner, true) => side_len as i64 - 1,
+            (Origin::Centre, _) => side_len as i64 / 2,
+        }
+    }
+
+    // Like `to_canvas`, without checking the bounds of the canvas.
+    fn canvas_coordinates(&self, coordinates: [i64; 3], side_len: u32) -> [i64; 3] {
+        let axes = self.axes();
+        let canvas = |axis: usize| {
+            let (from, flipped) = axes[axis];
+            let sign = if flipped { -1 } else { 1 };
+            self.base(flipped, side_len) + sign * coordinates[from]
+        };
+        [canvas(0), canvas(1), canvas(2)]
+    }
+
+    /// Position of the canvas at `coordinates` of this system, `None` when
+    /// it's outside of a canvas of side `side_len`.
+    pub fn to_canvas(&self, coordinates: [i64; 3], side_len: u32) -> Option<Position> {
+        bounded_position(self.canvas_coordinates(coordinates, side_len), side_len).ok()
+    }
+
+    /// Coordinates in this system of `position`, the inverse of `to_canvas`.
+    pub fn from_canvas(&self, position: Position, side_len: u32) -> [i64; 3] {
+        let canvas = [position.x as i64, position.y as i64, position.z as i64];
+        let mut coordinates = [0; 3];
+        for (axis, (to, flipped)) in self.axes().iter().enumerate() {
+            let sign = if *flipped { -1 } else { 1 };
+            coordinates[*to] = sign * (canvas[axis] - self.base(*flipped, side_len));
+        }
+        coordinates
+    }
+
+    /// Moves a model, e.g. imported from a file, so that its corner with the
+    /// lowest coordinates of this system is at `origin`. The cubes outside
+    /// of the canvas are left out, returns the others along with how many
+    /// were.
+    pub fn place(&self, cubes: Vec<Cube>, origin: [i64; 3], side_len: u32) -> (Vec<Cube>, usize) {
+        let axes = |cube: &Cube| {
+            let position = cube.position;
+            [position.x as i64, position.y as i64, position.z as i64]
+        };
+        let mut min = [i64::MAX; 3];
+        let mut max = [i64::MIN; 3];
+        for cube in &cubes {
+            for (axis, value) in axes(cube).iter().enumerate() {
+                min[axis] = min[axis].min(*value);
+                max[axis] = max[axis].max(*value);
+            }
+        }
+        // The lowest corner in this system is the highest one of the canvas
+        // along the flipped axes.
+        let origin = self.canvas_coordinates(origin, side_len);
+        let mut corner = [0; 3];
+        for (axis, (_, flipped)) in self.axes().iter().enumerate() {
+            corner[axis] = match flipped {
+                true => origin[axis] - (max[axis] - min[axis]),
+                false => origin[axis],
+            };
+        }
+        let total = cubes.len();
+        let placed: Vec<Cube> = cubes
+            .into_iter()
+            .filter_map(|cube| {
+                let position = axes(&cube);
+                let canvas = |axis: usize| corner[axis] + position[axis] - min[axis];
+                let within = |c: i64| (0..side_len as i64).contains(&c);
+                let (x, y, z) = (canvas(0), canvas(1), canvas(2));
+                match within(x) && within(y) && within(z) {
+                    true => Some(Cube::new(x as u32, y as u32, z as u32, cube.colour)),
+                    false => None,
+                }
+            })
+            .collect();
+        let skipped = total - placed.len();
+        (placed, skipped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Colour;
+
+    #[test]
+    fn test_presets() {
+        let system = |up, handedness, origin| CoordinateSystem {
+            up,
+            handedness,
+            origin,
+        };
+        let canvas = CoordinateSystem::default();
+        assert_eq!(
+            canvas.to_canvas([1, 2, 3], 10),
+            Some(Position::new(1, 2, 3))
+        );
+        assert_eq!(canvas.to_canvas([1, 2, 10], 10), None);
+
+        let y_up = system(UpAxis::Y, None, Origin::Corner);
+        assert_eq!(y_up.to_canvas([1, 2, 3], 10), Some(Position::new(1, 7, 3)));
+        let z_up = system(UpAxis::Z, None, Origin::Corner);
+        assert_eq!(z_up.to_canvas([1, 2, 3], 10), Some(Position::new(1, 6, 7)));
+        let z_up_left = system(UpAxis::Z, Some(Handedness::Left), Origin::Centre);
+        assert_eq!(
+            z_up_left.to_canvas([-1, 2, 3], 10),
+            Some(Position::new(4, 2, 7))
+        );
+        assert_eq!(z_up_left.to_canvas([-6, 0, 0], 10), None);
+
+        for system in &[canvas, y_up, z_up, z_up_left] {
+            let position = Position::new(0, 4, 9);
+            let coordinates = system.from_canvas(position, 10);
+            assert_eq!(system.to_canvas(coordinates, 10), Some(position));
+        }
+    }
+
+    #[test]
+    fn test_place() {
+        let colour = Colour::new(1, 2, 3);
+        // An L, 2 wide and 3 high, its foot towards the bottom of the canvas.
+        let model = vec![
+            Cube::new(0, 0, 0, colour),
+            Cube::new(0, 1, 0, colour),
+            Cube::new(0, 2, 0, colour),
+            Cube::new(1, 2, 0, colour),
+        ];
+        let y_up = CoordinateSystem {
+            up: UpAxis::Y,
+            ..Default::default()
+        };
+        // The foot lands on the floor.
+        let (placed, skipped) = y_up.place(model.clone(), [2, 0, 4], 10);
+        assert_eq!(skipped, 0);
+        assert_eq!(
+            placed,
+            vec![
+                Cube::new(2, 7, 4, colour),
+                Cube::new(2, 8, 4, colour),
+                Cube::new(2, 9, 4, colour),
+                Cube::new(3, 9, 4, colour),
+            ]
+        );
+        let (placed, skipped) = CoordinateSystem::default().place(model, [9, 8, 0], 10);
+        assert_eq!(
+            (placed, skipped),
+            (
+                vec![Cube::new(9, 8, 0, colour), Cube::new(9, 9, 0, colour)],
+                2
+            )
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use crate::{Canvas, CanvasEvent, JournalEntry, Raytracer, Renderer, Watermark};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame, ImageResult, Rgb, RgbImage};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct CreditsConfig {
+    /// Hours of builders credited when `!credits` doesn't say.
+    pub hours: u64,
+    /// Builders listed at most, the busiest ones.
+    pub max_names: usize,
+    pub frames: u32,
+    pub frame_delay_ms: u32,
+    /// How far the camera orbits around the build while the credits roll.
+    pub orbit_degrees: f32,
+    pub resolution: u32,
+    /// Rays traced per pixel, see the snapshot configuration.
+    pub samples: u32,
+}
+
+impl Default for CreditsConfig {
+    fn default() -> Self {
+        Self {
+            hours: 8,
+            max_names: 20,
+            frames: 120,
+            frame_delay_ms: 100,
+            orbit_degrees: 90.0,
+            resolution: 256,
+            samples: 1,
+        }
+    }
+}
+
+// Glyphs of the credits and the HUD, 5 pixels wide and 7 high, a row per
+// byte with the leftmost pixel in the highest bit. Chat names are made of
+// letters, digits and underscores, anything else shows as `?`.
+const GLYPH_WIDTH: u32 = 5;
+pub(crate) const GLYPH_HEIGHT: u32 = 7;
+const GLYPHS: &[(char, [u8; 7])] = &[
+    (' ', [0, 0, 0, 0, 0, 0, 0]),
+    ('A', [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11]),
+    ('B', [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e]),
+    ('C', [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e]),
+    ('D', [0x1e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1e]),
+    ('E', [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f]),
+    ('F', [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10]),
+    ('G', [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f]),
+    ('H', [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11]),
+    ('I', [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f]),
+    ('M', [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e]),
+    ('P', [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10]),
+    ('Q', [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d]),
+    ('R', [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11]),
+    ('S', [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e]),
+    ('T', [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a]),
+    ('X', [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x0a, 0x04, 0x04, 0x04, 0x04]),
+    ('Z', [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f]),
+    ('0', [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e]),
+    ('1', [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e]),
+    ('2', [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f]),
+    ('3', [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e]),
+    ('4', [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02]),
+    ('5', [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e]),
+    ('6', [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e]),
+    ('7', [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e]),
+    ('9', [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c]),
+    ('_', [0, 0, 0, 0, 0, 0, 0x1f]),
+    ('-', [0, 0, 0, 0x1f, 0, 0, 0]),
+    (':', [0, 0x04, 0x04, 0, 0x04, 0x04, 0]),
+    ('?', [0x0e, 0x11, 0x01, 0x02, 0x04, 0, 0x04]),
+];
+
+/// Builders who placed cubes since the `since` unix timestamp, with how many
+/// each placed, the busiest first.
+pub fn contributors(journal: &[JournalEntry], since: i64) -> Vec<(String, usize)> {
+    let mut cubes: HashMap<&str, usize> = HashMap::new();
+    for entry in journal {
+        let author = match (&entry.event, entry.metadata.author.as_deref()) {
+            (CanvasEvent::CubePlaced(_), Some(author)) => author,
+            _ => continue,
+        };
+        if entry.metadata.timestamp >= since {
+            *cubes.entry(author).or_default() += 1;
+        }
+    }
+    let mut contributors: Vec<_> = cubes
+        .into_iter()
+        .map(|(author, count)| (author.to_owned(), count))
+        .collect();
+    contributors.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    contributors
+}
+
+/// Renders an animated GIF of the credits, the builders since the `since`
+/// unix timestamp rolling over a slow orbit of the build, with the watermark
+/// on every frame if any.
+pub fn render_credits(
+    journal: &[JournalEntry],
+    since: i64,
+    config: &CreditsConfig,
+    frame_side_len: u32,
+    watermark: Option<&Watermark>,
+) -> ImageResult<Vec<u8>> {
+    let mut canvas = Canvas::new(u32::MAX);
+    let mut raytracer = Raytracer::new(config.resolution, frame_side_len, config.samples);
+    for entry in journal {
+        if canvas.apply(&entry.event).is_ok() {
+            raytracer.apply_event(&entry.event);
+        }
+    }
+
+    let mut lines = vec!["Thanks for building".to_owned(), String::new()];
+    lines.extend(
+        contributors(journal, since)
+            .into_iter()
+            .take(config.max_names)
+            .map(|(author, cubes)| format!("{}  {}", author, cubes)),
+    );
+    let scale = (config.resolution / 128).max(1);
+    let line_height = (GLYPH_HEIGHT + 3) * scale;
+    // The text rolls in from the bottom and out at the top.
+    let roll = (config.resolution + lines.len() as u32 * line_height) as f32;
+
+    let frame_count = config.frames.max(1);
+    let delay = Delay::from_numer_denom_ms(config.frame_delay_ms, 1);
+    let mut frames = Vec::new();
+    for i in 0..frame_count {
+        let progress = i as f32 / frame_count as f32;
+        raytracer.set_yaw((config.orbit_degrees * progress).to_radians());
+        let mut img = raytracer.render_image();
+        // Darkened, so the text stands out.
+        for pixel in img.pixels_mut() {
+            pixel.0.iter_mut().for_each(|c| *c /= 2);
+        }
+        let top = config.resolution as f32 - roll * progress;
+        for (row, line) in lines.iter().enumerate() {
+            draw_text(
+                &mut img,
+                line,
+                top as i64 + (row as u32 * line_height) as i64,
+                scale,
+            );
+        }
+        if let Some(watermark) = watermark {
+            watermark.draw(&mut img);
+        }
+        let img = DynamicImage::ImageRgb8(img).into_rgba8();
+        frames.push(Frame::from_parts(img, 0, 0, delay));
+    }
+
+    let mut gif = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut gif);
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames)?;
+    }
+    Ok(gif)
+}
+
+// Draws `text` in white, centred on the line starting at `top`, each pixel of
+// the glyphs `scale` pixels wide.
+fn draw_text(img: &mut RgbImage, text: &str, top: i64, scale: u32) {
+    let left = (img.width() as i64 - text_width(text, scale) as i64) / 2;
+    draw_text_at(img, text, left, top, scale);
+}
+
+// Width of `text` in pixels, drawn at `scale`.
+pub(crate) fn text_width(text: &str, scale: u32) -> u32 {
+    (text.chars().count() as u32 * (GLYPH_WIDTH + 1) * scale).saturating_sub(scale)
+}
+
+// Draws `text` in white from its top left corner, clipped to the image.
+pub(crate) fn draw_text_at(img: &mut RgbImage, text: &str, left: i64, top: i64, scale: u32) {
+    let advance = (GLYPH_WIDTH + 1) * scale;
+    for (i, c) in text.chars().enumerate() {
+        let c = c.to_ascii_uppercase();
+        let rows = GLYPHS
+            .iter()
+            .find(|(glyph, _)| *glyph == c)
+            .or_else(|| GLYPHS.iter().find(|(glyph, _)| *glyph == '?'))
+            .map(|(_, rows)| rows)
+            .expect("the font has a ?");
+        let glyph_left = left + (i as u32 * advance) as i64;
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if row & (1 << (GLYPH_WIDTH - 1 - x)) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = glyph_left + (x * scale + dx) as i64;
+                        let py = top + (y as u32 * scale + dy) as i64;
+                        if (0..img.width() as i64).contains(&px)
+                            && (0..img.height() as i64).contains(&py)
+                        {
+                            img.put_pixel(px as u32, py as u32, Rgb([255, 255, 255]));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colour, Cube, EventMetadata, Position};
+    use image::codecs::gif::GifDecoder;
+    use image::AnimationDecoder;
+    use uuid::Uuid;
+
+    fn entry(x: u32, author: &str, timestamp: i64) -> JournalEntry {
+        JournalEntry {
+            command_id: Uuid::new_v4(),
+            event: CanvasEvent::CubePlaced(Cube::new(x, 0, 0, Colour::new(255, 0, 0))),
+            metadata: EventMetadata {
+                timestamp,
+                author: Some(author.to_owned()),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_contributors() {
+        let journal = vec![
+            entry(0, "early_bird", 10),
+            entry(1, "bob", 100),
+            entry(2, "alice", 110),
+            entry(3, "bob", 120),
+            JournalEntry {
+                command_id: Uuid::new_v4(),
+                event: CanvasEvent::CubeRemoved(Position::new(3, 0, 0)),
+                metadata: EventMetadata {
+                    timestamp: 130,
+                    author: Some("carol".to_owned()),
+                    ..Default::default()
+                },
+            },
+            entry(4, "alan", 140),
+        ];
+        assert_eq!(
+            contributors(&journal, 100),
+            vec![
+                ("bob".to_owned(), 2),
+                ("alan".to_owned(), 1),
+                ("alice".to_owned(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_credits() {
+        let journal = vec![entry(0, "alice", 100), entry(1, "bob", 110)];
+        let config = CreditsConfig {
+            frames: 4,
+            resolution: 16,
+            ..Default::default()
+        };
+        let gif = render_credits(&journal, 0, &config, 10, None).unwrap();
+        let frames = GifDecoder::new(&gif[..])
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(frames.len(), 4);
+
+        let mut img = RgbImage::new(16, 16);
+        draw_text(&mut img, "i", 2, 1);
+        // The stem of the I, in the middle.
+        assert_eq!(img.get_pixel(7, 4), &Rgb([255, 255, 255]));
+        assert_eq!(img.get_pixel(6, 4), &Rgb([0, 0, 0]));
+    }
+}
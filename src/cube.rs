@@ -0,0 +1,452 @@
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Add;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Cube {
+    pub position: Position,
+    pub colour: Colour,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CubeError {
+    #[error("position {0} is outside of the canvas")]
+    OutOfBounds(Position),
+    #[error("invalid colour {0}")]
+    InvalidColour(String),
+    #[error("invalid shape {0}, expected cube, slab or sphere")]
+    InvalidShape(String),
+}
+
+impl Cube {
+    pub fn new(x: u32, y: u32, z: u32, colour: Colour) -> Self {
+        Self {
+            position: Position::new(x, y, z),
+            colour,
+        }
+    }
+
+    /// Opacity of the cube, 255 unless it's translucent, e.g. glass.
+    pub fn alpha(&self) -> u8 {
+        self.colour.a
+    }
+
+    pub fn is_translucent(&self) -> bool {
+        !self.colour.is_opaque()
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        self.colour.emissive
+    }
+
+    pub fn shape(&self) -> Shape {
+        self.colour.shape
+    }
+
+    /// Like `new`, but fails if the cube doesn't fit in a canvas of side
+    /// `side_len`.
+    pub fn bounded(
+        x: u32,
+        y: u32,
+        z: u32,
+        colour: Colour,
+        side_len: u32,
+    ) -> Result<Self, CubeError> {
+        let position = Position::new(x, y, z);
+        if !position.is_within(side_len) {
+            return Err(CubeError::OutOfBounds(position));
+        }
+        Ok(Self { position, colour })
+    }
+}
+
+/// Coordinates of a cube in the canvas, from 0 to the canvas side - 1.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct Position {
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+}
+
+impl Position {
+    pub const fn new(x: u32, y: u32, z: u32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn is_within(&self, side_len: u32) -> bool {
+        self.x < side_len && self.y < side_len && self.z < side_len
+    }
+
+    /// Moves the position by the given deltas, `None` if it would go below 0.
+    pub fn offset(&self, dx: i64, dy: i64, dz: i64) -> Option<Position> {
+        let shift = |p: u32, d: i64| u32::try_from(p as i64 + d).ok();
+        Some(Position::new(
+            shift(self.x, dx)?,
+            shift(self.y, dy)?,
+            shift(self.z, dz)?,
+        ))
+    }
+
+    /// Number of steps along the axes to go from one position to the other.
+    pub fn manhattan_distance(&self, other: &Position) -> u32 {
+        self.x.abs_diff(other.x) + self.y.abs_diff(other.y) + self.z.abs_diff(other.z)
+    }
+
+    pub fn distance_squared(&self, other: &Position) -> u64 {
+        let d = |a: u32, b: u32| (a.abs_diff(b) as u64).pow(2);
+        d(self.x, other.x) + d(self.y, other.y) + d(self.z, other.z)
+    }
+}
+
+impl Add for Position {
+    type Output = Position;
+
+    fn add(self, other: Position) -> Position {
+        Position::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl From<(u32, u32, u32)> for Position {
+    fn from((x, y, z): (u32, u32, u32)) -> Self {
+        Position::new(x, y, z)
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.x, self.y, self.z)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Colour {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// Opacity, from 0 (invisible) to 255 (opaque). Left out of the JSON of
+    /// opaque colours, as it was before translucent cubes.
+    #[serde(default = "opaque", skip_serializing_if = "is_opaque")]
+    pub a: u8,
+    /// Lit by itself, glowing on the snapshots, e.g. for placements paid with
+    /// bits. Left out of the JSON of the colours which don't glow.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub emissive: bool,
+    /// What fills the cell of the cube, for finer details than full cubes.
+    /// Left out of the JSON of full cubes.
+    #[serde(default, skip_serializing_if = "Shape::is_cube")]
+    pub shape: Shape,
+}
+
+fn opaque() -> u8 {
+    255
+}
+
+fn is_opaque(a: &u8) -> bool {
+    *a == 255
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl Default for Colour {
+    fn default() -> Self {
+        Colour::new(0, 0, 0)
+    }
+}
+
+// Colours chat can use by name.
+const NAMED_COLOURS: &[(&str, Colour)] = &[
+    ("black", Colour::new(0, 0, 0)),
+    ("white", Colour::new(255, 255, 255)),
+    ("grey", Colour::new(128, 128, 128)),
+    ("gray", Colour::new(128, 128, 128)),
+    ("red", Colour::new(255, 0, 0)),
+    ("green", Colour::new(0, 128, 0)),
+    ("lime", Colour::new(0, 255, 0)),
+    ("blue", Colour::new(0, 0, 255)),
+    ("yellow", Colour::new(255, 255, 0)),
+    ("cyan", Colour::new(0, 255, 255)),
+    ("magenta", Colour::new(255, 0, 255)),
+    ("orange", Colour::new(255, 165, 0)),
+    ("purple", Colour::new(128, 0, 128)),
+    ("pink", Colour::new(255, 192, 203)),
+    ("brown", Colour::new(139, 69, 19)),
+];
+
+impl Colour {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self {
+            r,
+            g,
+            b,
+            a: 255,
+            emissive: false,
+            shape: Shape::Cube,
+        }
+    }
+
+    pub const fn with_alpha(self, a: u8) -> Self {
+        Self { a, ..self }
+    }
+
+    pub const fn with_emissive(self, emissive: bool) -> Self {
+        Self { emissive, ..self }
+    }
+
+    pub const fn with_shape(self, shape: Shape) -> Self {
+        Self { shape, ..self }
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        is_opaque(&self.a)
+    }
+
+    /// Name chat knows the closest colour by, for colours to be counted
+    /// together however close to each other.
+    pub fn name(&self) -> &'static str {
+        let distance = |other: &Colour| {
+            let channel = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+            channel(self.r, other.r) + channel(self.g, other.g) + channel(self.b, other.b)
+        };
+        NAMED_COLOURS
+            .iter()
+            .min_by_key(|(_, colour)| distance(colour))
+            .map_or("black", |(name, _)| name)
+    }
+
+    /// Channels scaled to [0, 1], as expected by the renderers, without the
+    /// opacity.
+    pub fn to_f32(&self) -> (f32, f32, f32) {
+        (
+            self.r as f32 / 255.0,
+            self.g as f32 / 255.0,
+            self.b as f32 / 255.0,
+        )
+    }
+}
+
+impl TryFrom<(u32, u32, u32)> for Colour {
+    type Error = CubeError;
+
+    fn try_from((r, g, b): (u32, u32, u32)) -> Result<Self, Self::Error> {
+        match (u8::try_from(r), u8::try_from(g), u8::try_from(b)) {
+            (Ok(r), Ok(g), Ok(b)) => Ok(Colour::new(r, g, b)),
+            _ => Err(CubeError::InvalidColour(format!("{} {} {}", r, g, b))),
+        }
+    }
+}
+
+impl From<(u8, u8, u8)> for Colour {
+    fn from((r, g, b): (u8, u8, u8)) -> Self {
+        Colour::new(r, g, b)
+    }
+}
+
+/// What fills the cell of a cube.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Shape {
+    /// The whole cell.
+    #[default]
+    Cube,
+    /// The lower half of the cell, as a step or a floor.
+    Slab,
+    /// A small sphere in the middle of the cell.
+    Sphere,
+}
+
+impl Shape {
+    /// Radius of the sphere, for a cell of side 1.
+    pub const SPHERE_RADIUS: f32 = 0.35;
+
+    pub fn is_cube(&self) -> bool {
+        *self == Shape::Cube
+    }
+
+    /// Smallest box holding the shape, as its lowest and highest corners in a
+    /// cell spanning [-0.5, 0.5] on each axis of the grid, y growing
+    /// downwards.
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        match self {
+            Shape::Cube => ([-0.5; 3], [0.5; 3]),
+            Shape::Slab => ([-0.5, 0.0, -0.5], [0.5; 3]),
+            Shape::Sphere => ([-Self::SPHERE_RADIUS; 3], [Self::SPHERE_RADIUS; 3]),
+        }
+    }
+}
+
+impl FromStr for Shape {
+    type Err = CubeError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "cube" => Ok(Shape::Cube),
+            "slab" => Ok(Shape::Slab),
+            "sphere" => Ok(Shape::Sphere),
+            _ => Err(CubeError::InvalidShape(value.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Shape::Cube => "cube",
+            Shape::Slab => "slab",
+            Shape::Sphere => "sphere",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Accepts names like `red`, and hex codes like `#ff0000`, `ff0000` or `#f00`,
+// or `#ff000080` with an opacity.
+impl FromStr for Colour {
+    type Err = CubeError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let lowercase = value.trim().to_lowercase();
+        if let Some((_, colour)) = NAMED_COLOURS.iter().find(|(name, _)| *name == lowercase) {
+            return Ok(*colour);
+        }
+        let invalid = || CubeError::InvalidColour(value.to_owned());
+        let hex = lowercase.strip_prefix('#').unwrap_or(&lowercase);
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let channel = |s: &str| u8::from_str_radix(s, 16).map_err(|_| invalid());
+        match hex.len() {
+            6 => Ok(Colour::new(
+                channel(&hex[0..2])?,
+                channel(&hex[2..4])?,
+                channel(&hex[4..6])?,
+            )),
+            8 => Ok(Colour::new(
+                channel(&hex[0..2])?,
+                channel(&hex[2..4])?,
+                channel(&hex[4..6])?,
+            )
+            .with_alpha(channel(&hex[6..8])?)),
+            // Short form, each digit is repeated: #f80 is #ff8800.
+            3 => Ok(Colour::new(
+                channel(&hex[0..1])? * 17,
+                channel(&hex[1..2])? * 17,
+                channel(&hex[2..3])? * 17,
+            )),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)?;
+        if !self.is_opaque() {
+            write!(f, "{:02x}", self.a)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded() {
+        let colour = Colour::new(1, 2, 3);
+        assert_eq!(
+            Cube::bounded(1, 2, 3, colour, 4),
+            Ok(Cube::new(1, 2, 3, colour))
+        );
+        assert_eq!(
+            Cube::bounded(1, 4, 3, colour, 4),
+            Err(CubeError::OutOfBounds(Position::new(1, 4, 3)))
+        );
+    }
+
+    #[test]
+    fn test_position_helpers() {
+        let position = Position::new(1, 2, 3);
+        assert_eq!(position.offset(1, -2, 0), Some(Position::new(2, 0, 3)));
+        assert_eq!(position.offset(-2, 0, 0), None);
+        assert_eq!(position + Position::new(1, 1, 1), Position::new(2, 3, 4));
+        assert_eq!(position.manhattan_distance(&Position::new(0, 4, 3)), 3);
+        assert_eq!(position.distance_squared(&Position::new(0, 4, 3)), 5);
+        assert_eq!(position.to_string(), "1 2 3");
+    }
+
+    #[test]
+    fn test_colour_parsing() {
+        assert_eq!("red".parse(), Ok(Colour::new(255, 0, 0)));
+        assert_eq!(" Blue".parse(), Ok(Colour::new(0, 0, 255)));
+        assert_eq!("#00ff80".parse(), Ok(Colour::new(0, 255, 128)));
+        assert_eq!("00FF80".parse(), Ok(Colour::new(0, 255, 128)));
+        assert_eq!("#f80".parse(), Ok(Colour::new(255, 136, 0)));
+        assert!("#ff00".parse::<Colour>().is_err());
+        assert!("#gg0000".parse::<Colour>().is_err());
+        assert!("rainbow".parse::<Colour>().is_err());
+        assert_eq!(Colour::new(0, 255, 128).to_string(), "#00ff80");
+        let glass = Colour::new(0, 255, 128).with_alpha(0x40);
+        assert_eq!("#00ff8040".parse(), Ok(glass));
+        assert_eq!(glass.to_string(), "#00ff8040");
+        assert_eq!("#00ff80ff".parse(), Ok(Colour::new(0, 255, 128)));
+        assert_eq!(Colour::new(250, 10, 5).name(), "red");
+        assert_eq!(Colour::new(120, 130, 125).name(), "grey");
+        assert_eq!(Colour::new(255, 170, 10).with_alpha(0x40).name(), "orange");
+    }
+
+    #[test]
+    fn test_colour_json() {
+        let colour = Colour::new(1, 2, 3);
+        // Opaque colours are stored as before translucency.
+        assert_eq!(
+            serde_json::to_string(&colour).unwrap(),
+            r#"{"r":1,"g":2,"b":3}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Colour>(r#"{"r":1,"g":2,"b":3}"#).unwrap(),
+            colour
+        );
+        let glass = colour.with_alpha(100);
+        let json = serde_json::to_string(&glass).unwrap();
+        assert_eq!(json, r#"{"r":1,"g":2,"b":3,"a":100}"#);
+        assert_eq!(serde_json::from_str::<Colour>(&json).unwrap(), glass);
+        assert!(Cube::new(0, 0, 0, glass).is_translucent());
+        let glowing = colour.with_emissive(true);
+        let json = serde_json::to_string(&glowing).unwrap();
+        assert_eq!(json, r#"{"r":1,"g":2,"b":3,"emissive":true}"#);
+        assert_eq!(serde_json::from_str::<Colour>(&json).unwrap(), glowing);
+        assert!(Cube::new(0, 0, 0, glowing).is_emissive());
+        let slab = colour.with_shape(Shape::Slab);
+        let json = serde_json::to_string(&slab).unwrap();
+        assert_eq!(json, r#"{"r":1,"g":2,"b":3,"shape":"slab"}"#);
+        assert_eq!(serde_json::from_str::<Colour>(&json).unwrap(), slab);
+        assert_eq!(Cube::new(0, 0, 0, slab).shape(), Shape::Slab);
+    }
+
+    #[test]
+    fn test_shape() {
+        assert_eq!("Sphere".parse(), Ok(Shape::Sphere));
+        assert_eq!(" slab".parse(), Ok(Shape::Slab));
+        assert_eq!(
+            "pyramid".parse::<Shape>(),
+            Err(CubeError::InvalidShape("pyramid".to_owned()))
+        );
+        for shape in [Shape::Cube, Shape::Slab, Shape::Sphere] {
+            assert_eq!(shape.to_string().parse(), Ok(shape));
+        }
+    }
+
+    #[test]
+    fn test_colour_from_u32() {
+        assert_eq!(Colour::try_from((1u32, 2, 3)), Ok(Colour::new(1, 2, 3)));
+        assert!(Colour::try_from((1u32, 256, 3)).is_err());
+    }
+}
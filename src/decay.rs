@@ -0,0 +1,213 @@
+use crate::{CanvasEvent, Colour, EventMetadata, Position};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct DecayConfig {
+    /// Hours after which a cube disappears, unless refreshed by another
+    /// chatter. Decay is disabled when unset.
+    pub lifetime_hours: Option<u64>,
+    /// Number of steps in which cubes lose their saturation before
+    /// disappearing.
+    pub stages: u32,
+    /// How often cubes are checked for decay.
+    pub check_interval_secs: u64,
+}
+
+impl Default for DecayConfig {
+    fn default() -> Self {
+        Self {
+            lifetime_hours: None,
+            stages: 4,
+            check_interval_secs: 300,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct PlacedCube {
+    author: Option<String>,
+    placed_at: i64,
+    // Colour the cube was placed with, before fading.
+    colour: Colour,
+    stage: u32,
+}
+
+/// Tracks when each cube of the canvas was placed, and produces the events
+/// making them fade and disappear.
+#[derive(Clone, Debug)]
+pub struct DecayTracker {
+    lifetime_secs: i64,
+    stages: u32,
+    // Stands in for the placement time of events journaled without a
+    // timestamp.
+    started_at: i64,
+    cubes: HashMap<Position, PlacedCube>,
+}
+
+impl DecayTracker {
+    /// Returns None when decay is disabled.
+    pub fn new(config: &DecayConfig, now: i64) -> Option<Self> {
+        let lifetime_hours = config.lifetime_hours?;
+        Some(Self {
+            lifetime_secs: (lifetime_hours * 3600).max(1) as i64,
+            stages: config.stages.max(1),
+            started_at: now,
+            cubes: HashMap::new(),
+        })
+    }
+
+    /// Mirrors an event applied to the canvas. A cube placed over another
+    /// chatter's cube refreshes it, fading events, which have no author,
+    /// don't.
+    pub fn apply(&mut self, event: &CanvasEvent, metadata: &EventMetadata) {
+        let placed_at = if metadata.timestamp > 0 {
+            metadata.timestamp
+        } else {
+            self.started_at
+        };
+        let (position, colour) = match event {
+            CanvasEvent::CubePlaced(cube) => (cube.position, cube.colour),
+            CanvasEvent::Recoloured { position, colour } => (*position, *colour),
+            CanvasEvent::CubeRemoved(position) => {
+                self.cubes.remove(position);
+                return;
+            }
+            CanvasEvent::CanvasCleared => {
+                self.cubes.clear();
+                return;
+            }
+        };
+        match self.cubes.get_mut(&position) {
+            Some(cube) if metadata.author.is_none() => {
+                if let CanvasEvent::CubePlaced(_) = event {
+                    cube.colour = colour;
+                    cube.stage = 0;
+                }
+            }
+            Some(cube) if cube.author == metadata.author => {
+                cube.colour = colour;
+                cube.stage = 0;
+            }
+            _ => {
+                self.cubes.insert(
+                    position,
+                    PlacedCube {
+                        author: metadata.author.clone(),
+                        placed_at,
+                        colour,
+                        stage: 0,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Events fading or removing the cubes which aged since the last step.
+    pub fn step(&mut self, now: i64) -> Vec<CanvasEvent> {
+        let mut events = Vec::new();
+        let mut expired = Vec::new();
+        for (position, cube) in self.cubes.iter_mut() {
+            let age = (now - cube.placed_at).max(0);
+            if age >= self.lifetime_secs {
+                expired.push(*position);
+                continue;
+            }
+            let stage = (age * self.stages as i64 / self.lifetime_secs) as u32;
+            if stage > cube.stage {
+                cube.stage = stage;
+                events.push(CanvasEvent::Recoloured {
+                    position: *position,
+                    colour: desaturate(cube.colour, stage as f32 / self.stages as f32),
+                });
+            }
+        }
+        expired.sort();
+        for position in expired {
+            self.cubes.remove(&position);
+            events.push(CanvasEvent::CubeRemoved(position));
+        }
+        events
+    }
+}
+
+// Blends the colour towards its own grey, `amount` being between 0 and 1.
+fn desaturate(colour: Colour, amount: f32) -> Colour {
+    let (r, g, b) = colour.to_f32();
+    let grey = 0.299 * r + 0.587 * g + 0.114 * b;
+    let mix = |c: f32| ((c + (grey - c) * amount) * 255.0).round() as u8;
+    Colour {
+        r: mix(r),
+        g: mix(g),
+        b: mix(b),
+        ..colour
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cube;
+
+    fn placed_by(author: &str, timestamp: i64) -> EventMetadata {
+        EventMetadata {
+            author: Some(author.to_owned()),
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_decay() {
+        assert!(DecayTracker::new(&DecayConfig::default(), 0).is_none());
+        let config = DecayConfig {
+            lifetime_hours: Some(1),
+            stages: 2,
+            ..Default::default()
+        };
+        let mut decay = DecayTracker::new(&config, 1000).unwrap();
+        let red = Colour::new(255, 0, 0);
+        decay.apply(
+            &CanvasEvent::CubePlaced(Cube::new(0, 0, 0, red)),
+            &placed_by("a", 1000),
+        );
+        decay.apply(
+            &CanvasEvent::CubePlaced(Cube::new(1, 0, 0, red)),
+            &placed_by("a", 1000),
+        );
+        assert_eq!(decay.step(1000), vec![]);
+
+        // Halfway through, both fade.
+        let events = decay.step(1000 + 1800);
+        assert_eq!(events.len(), 2);
+        let faded = desaturate(red, 0.5);
+        assert!(events.contains(&CanvasEvent::Recoloured {
+            position: Position::new(0, 0, 0),
+            colour: faded,
+        }));
+        for event in &events {
+            decay.apply(event, &EventMetadata::default());
+        }
+        assert_eq!(decay.step(1000 + 1900), vec![]);
+
+        // Another chatter refreshes one of them.
+        decay.apply(
+            &CanvasEvent::CubePlaced(Cube::new(1, 0, 0, red)),
+            &placed_by("b", 1000 + 2000),
+        );
+        assert_eq!(
+            decay.step(1000 + 3600),
+            vec![CanvasEvent::CubeRemoved(Position::new(0, 0, 0))]
+        );
+    }
+
+    #[test]
+    fn test_desaturate() {
+        let colour = Colour::new(200, 100, 0);
+        assert_eq!(desaturate(colour, 0.0), colour);
+        let grey = desaturate(colour, 1.0);
+        assert_eq!(grey.r, grey.g);
+        assert_eq!(grey.g, grey.b);
+    }
+}
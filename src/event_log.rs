@@ -0,0 +1,221 @@
+use crate::JournalEntry;
+use serde::Deserialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct EventLogConfig {
+    /// File every journaled event is appended to, as a JSON line, nothing is
+    /// logged when unset.
+    pub filepath: Option<String>,
+    /// The file is rotated before growing past this many bytes.
+    pub max_bytes: u64,
+    /// Rotated files kept, `<filepath>.1` the most recent.
+    pub keep: u32,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        Self {
+            filepath: None,
+            max_bytes: 64 * 1024 * 1024,
+            keep: 5,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum EventLogError {
+    #[error("error from the event log {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid line {line} of {path}: {source}")]
+    InvalidLine {
+        path: String,
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("error from serde_json {0}")]
+    SerdeJson(#[from] serde_json::Error),
+}
+
+/// Append-only log of the journal, one JSON entry per line, for pipelines
+/// tailing it and to rebuild the archive if lost.
+pub struct EventLog {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: u32,
+    file: File,
+    len: u64,
+}
+
+impl EventLog {
+    pub fn open(
+        filepath: impl AsRef<Path>,
+        config: &EventLogConfig,
+    ) -> Result<Self, EventLogError> {
+        let path = filepath.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes: config.max_bytes,
+            keep: config.keep,
+            file,
+            len,
+        })
+    }
+
+    pub fn append(&mut self, entry: &JournalEntry) -> Result<(), EventLogError> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        if self.len > 0 && self.len + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        // In a single write, so that readers never see half a line unless
+        // the disk is full.
+        self.file.write_all(&line)?;
+        self.len += line.len() as u64;
+        Ok(())
+    }
+
+    // Shifts the rotated files by one, dropping the oldest, and starts an
+    // empty file.
+    fn rotate(&mut self) -> Result<(), EventLogError> {
+        let rotated = |i: u32| rotated_path(&self.path, i);
+        if self.keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            for i in (1..self.keep).rev() {
+                if rotated(i).exists() {
+                    std::fs::rename(rotated(i), rotated(i + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, i: u32) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", i));
+    PathBuf::from(rotated)
+}
+
+/// Entries of the log at `filepath` and of its rotated files, oldest first.
+/// A last line cut short, e.g. by a crash while writing it, is left out.
+pub fn read_event_log(filepath: impl AsRef<Path>) -> Result<Vec<JournalEntry>, EventLogError> {
+    let path = filepath.as_ref();
+    let mut paths: Vec<PathBuf> = (1..)
+        .map(|i| rotated_path(path, i))
+        .take_while(|rotated| rotated.exists())
+        .collect();
+    paths.reverse();
+    paths.push(path.to_path_buf());
+    let mut entries = Vec::new();
+    for path in paths {
+        let content = std::fs::read_to_string(&path)?;
+        for (i, line) in content.split_inclusive('\n').enumerate() {
+            if !line.ends_with('\n') {
+                break;
+            }
+            let entry =
+                serde_json::from_str(line).map_err(|source| EventLogError::InvalidLine {
+                    path: path.display().to_string(),
+                    line: i + 1,
+                    source,
+                })?;
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// How long to wait between `previous` and `next` to replay them `speed`
+/// times faster than they happened, `max_wait` at most, e.g. over the nights
+/// the channel was offline.
+pub fn replay_delay(
+    previous: &JournalEntry,
+    next: &JournalEntry,
+    speed: f32,
+    max_wait: Duration,
+) -> Duration {
+    let elapsed = (next.metadata.timestamp - previous.metadata.timestamp).max(0) as f32;
+    match speed > 0.0 {
+        true => Duration::from_secs_f32(elapsed / speed).min(max_wait),
+        false => max_wait,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanvasEvent, Colour, Cube, EventMetadata};
+    use uuid::Uuid;
+
+    fn entry(x: u32) -> JournalEntry {
+        JournalEntry {
+            command_id: Uuid::new_v4(),
+            event: CanvasEvent::CubePlaced(Cube::new(x, 0, 0, Colour::new(1, 2, 3))),
+            metadata: EventMetadata {
+                author: Some("chatter".to_owned()),
+                timestamp: 1_600_000_000 + x as i64 * 60,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_event_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let line_len = serde_json::to_vec(&entry(0)).unwrap().len() as u64 + 1;
+        let config = EventLogConfig {
+            filepath: None,
+            max_bytes: line_len * 2,
+            keep: 2,
+        };
+        let entries: Vec<JournalEntry> = (0..7).map(entry).collect();
+        let mut log = EventLog::open(&path, &config).unwrap();
+        for entry in &entries[..5] {
+            log.append(entry).unwrap();
+        }
+        // Reopened, e.g. after a restart, it carries on where it was.
+        let mut log = EventLog::open(&path, &config).unwrap();
+        for entry in &entries[5..] {
+            log.append(entry).unwrap();
+        }
+        assert!(rotated_path(&path, 2).exists());
+        assert!(!rotated_path(&path, 3).exists());
+        // The first two entries were rotated out.
+        assert_eq!(read_event_log(&path).unwrap(), entries[2..]);
+
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"command_id\":").unwrap();
+        assert_eq!(read_event_log(&path).unwrap(), entries[2..]);
+        file.write_all(b"\n").unwrap();
+        assert!(matches!(
+            read_event_log(&path),
+            Err(EventLogError::InvalidLine { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_replay_delay() {
+        let max_wait = Duration::from_secs(5);
+        let delay = |from, to, speed| replay_delay(&entry(from), &entry(to), speed, max_wait);
+        assert_eq!(delay(0, 1, 30.0), Duration::from_secs(2));
+        assert_eq!(delay(0, 10, 30.0), max_wait);
+        // Out of order, or journaled before timestamps were recorded.
+        assert_eq!(delay(1, 0, 30.0), Duration::ZERO);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::renderer::Renderer;
+use crate::{Background, Colour, Cube, Position, Region};
+use image::{Rgb, RgbImage};
+use std::collections::HashMap;
+
+// Draws the canvas seen straight from the front with an orthographic
+// projection, each column of cubes as a square cell showing its frontmost
+// cube. Meant for the pixel art mode, where the canvas is a single plane.
+pub struct FlatRenderer {
+    size: u32,
+    side_len: u32,
+    cubes: HashMap<Position, Colour>,
+    fog: Option<Region>,
+    ghosts: Vec<(Cube, f32)>,
+    background: Background,
+}
+
+const GRID_COLOUR: Rgb<u8> = Rgb([225, 225, 225]);
+const FOG_COLOUR: Rgb<u8> = Rgb([128, 128, 128]);
+// Cells smaller than this, in pixels, are drawn without grid lines.
+const MIN_GRID_CELL_SIZE: u32 = 6;
+
+impl FlatRenderer {
+    pub fn new(size: u32, side_len: u32) -> Self {
+        Self {
+            size,
+            side_len: side_len.max(1),
+            cubes: HashMap::new(),
+            fog: None,
+            ghosts: Vec::new(),
+            background: Background::default(),
+        }
+    }
+
+    // Background at the middle of the pixels of `row`, in [0, side_len).
+    fn background(&self, row: f32) -> Rgb<u8> {
+        let Colour { r, g, b, .. } = self.background.at_height(row / self.side_len as f32);
+        Rgb([r, g, b])
+    }
+
+    // Colour of each cell, row by row, with the greatest z in front.
+    fn cells(&self) -> Vec<Option<Colour>> {
+        let side = self.side_len as usize;
+        let mut depths = vec![0; side * side];
+        let mut cells = vec![None; side * side];
+        for (position, &colour) in &self.cubes {
+            if position.x >= self.side_len || position.y >= self.side_len {
+                continue;
+            }
+            let index = position.y as usize * side + position.x as usize;
+            if cells[index].is_none() || depths[index] < position.z {
+                depths[index] = position.z;
+                cells[index] = Some(colour);
+            }
+        }
+        cells
+    }
+
+    // Colour of the ghosts over the background, in the cells they left
+    // empty.
+    fn ghost_cells(&self, cells: &[Option<Colour>]) -> Vec<Option<Rgb<u8>>> {
+        let side = self.side_len as usize;
+        let mut ghosts = vec![None; side * side];
+        for (cube, opacity) in &self.ghosts {
+            let Position { x, y, .. } = cube.position;
+            if x >= self.side_len || y >= self.side_len {
+                continue;
+            }
+            let index = y as usize * side + x as usize;
+            if cells[index].is_none() {
+                let Colour { r, g, b, .. } = cube.colour;
+                let colour = [r, g, b];
+                let background = self.background(y as f32 + 0.5);
+                ghosts[index] = Some(Rgb([0, 1, 2].map(|i| {
+                    (colour[i] as f32 * opacity + background[i] as f32 * (1.0 - opacity)) as u8
+                })));
+            }
+        }
+        ghosts
+    }
+}
+
+impl Renderer for FlatRenderer {
+    fn add_cube(&mut self, cube: &Cube) {
+        self.cubes.insert(cube.position, cube.colour);
+    }
+
+    fn remove_cube(&mut self, position: Position) {
+        self.cubes.remove(&position);
+    }
+
+    fn clear(&mut self) {
+        self.cubes.clear();
+    }
+
+    fn set_fog(&mut self, unlocked: Option<Region>) {
+        self.fog = unlocked;
+    }
+
+    fn set_ghosts(&mut self, ghosts: &[(Cube, f32)]) {
+        self.ghosts = ghosts.to_vec();
+    }
+
+    fn set_background(&mut self, background: &Background) {
+        self.background = background.clone();
+    }
+
+    fn render(&mut self) -> Option<RgbImage> {
+        let cells = self.cells();
+        let ghosts = self.ghost_cells(&cells);
+        let side = self.side_len;
+        let grid = self.size / side >= MIN_GRID_CELL_SIZE;
+        let locked = |column: u32, row: u32| {
+            self.fog.is_some_and(|region| {
+                !(region.min.x..=region.max.x).contains(&column)
+                    || !(region.min.y..=region.max.y).contains(&row)
+            })
+        };
+        Some(RgbImage::from_fn(self.size, self.size, |x, y| {
+            let (column, row) = (x * side / self.size, y * side / self.size);
+            let index = (row * side + column) as usize;
+            let pixel = match (cells[index], ghosts[index]) {
+                (Some(Colour { r, g, b, .. }), _) => Rgb([r, g, b]),
+                (None, Some(ghost)) => ghost,
+                // Lines on the first pixels of each empty cell.
+                (None, None)
+                    if grid && (column * self.size / side == x || row * self.size / side == y) =>
+                {
+                    GRID_COLOUR
+                }
+                (None, None) => self.background((y as f32 + 0.5) * side as f32 / self.size as f32),
+            };
+            match locked(column, row) {
+                // Half way between the pixel and the fog.
+                true => {
+                    Rgb([0, 1, 2].map(|i| ((pixel[i] as u16 + FOG_COLOUR[i] as u16) / 2) as u8))
+                }
+                false => pixel,
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut renderer = FlatRenderer::new(40, 4);
+        renderer.add_cube(&Cube::new(1, 2, 0, Colour::new(255, 0, 0)));
+        renderer.add_cube(&Cube::new(1, 2, 3, Colour::new(0, 0, 255)));
+        renderer.add_cube(&Cube::new(3, 0, 0, Colour::new(0, 255, 0)));
+        let img = renderer.render().unwrap();
+        assert_eq!(img.get_pixel(15, 25), &Rgb([0, 0, 255]));
+        assert_eq!(img.get_pixel(35, 5), &Rgb([0, 255, 0]));
+        assert_eq!(img.get_pixel(5, 5), &Rgb([250, 250, 250]));
+        assert_eq!(img.get_pixel(0, 5), &GRID_COLOUR);
+        renderer.remove_cube(Position::new(1, 2, 3));
+        assert_eq!(
+            renderer.render().unwrap().get_pixel(15, 25),
+            &Rgb([255, 0, 0])
+        );
+
+        // Ghosts show through the empty cells only.
+        renderer.set_ghosts(&[
+            (Cube::new(0, 0, 0, Colour::new(0, 0, 0)), 0.5),
+            (Cube::new(1, 2, 1, Colour::new(0, 0, 0)), 0.5),
+        ]);
+        let img = renderer.render().unwrap();
+        assert_eq!(img.get_pixel(0, 5), &Rgb([125, 125, 125]));
+        assert_eq!(img.get_pixel(15, 25), &Rgb([255, 0, 0]));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{bounded_position, Colour, Cube, Position};
+use crate::{export_schematic, import_schematic, BlockPalette, SchematicError};
+use std::str::FromStr;
+use thiserror::Error;
+
+mod goxel;
+mod qubicle;
+
+#[derive(Error, Debug)]
+pub enum FormatError {
+    #[error("unknown format {0}, try gox, qb or schem")]
+    UnknownFormat(String),
+    #[error("invalid file, {0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Schematic(#[from] SchematicError),
+    #[error("invalid image in the file: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("unable to read or write the file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Voxel art files the canvas is imported from and exported to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VoxelFormat {
+    /// Goxel's `.gox`.
+    Goxel,
+    /// Qubicle's binary `.qb`.
+    Qubicle,
+    /// Minecraft's Sponge schematic `.schem`, see `export_schematic`.
+    Schematic,
+}
+
+impl VoxelFormat {
+    pub const ALL: [VoxelFormat; 3] = [
+        VoxelFormat::Goxel,
+        VoxelFormat::Qubicle,
+        VoxelFormat::Schematic,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VoxelFormat::Goxel => "gox",
+            VoxelFormat::Qubicle => "qb",
+            VoxelFormat::Schematic => "schem",
+        }
+    }
+}
+
+impl FromStr for VoxelFormat {
+    type Err = FormatError;
+
+    /// Parses the extension of the format, with or without the dot.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let extension = value.trim().trim_start_matches('.').to_lowercase();
+        VoxelFormat::ALL
+            .iter()
+            .copied()
+            .find(|format| format.extension() == extension)
+            .ok_or_else(|| FormatError::UnknownFormat(value.to_owned()))
+    }
+}
+
+// Voxel read from a file, in canvas orientation: x grows to the right, y
+// downwards and z towards the front. Files place models anywhere, so
+// coordinates can be negative.
+type Voxel = ([i64; 3], Colour);
+
+// Most voxels read from a file, bigger ones are refused rather than filling
+// the memory.
+const MAX_VOXELS: usize = 1 << 24;
+
+/// File of `format` holding `cubes`. The model spans the bounding box of the
+/// cubes, and stands upright in the tools, y being up in Qubicle and z in
+/// Goxel.
+pub fn export_voxels(format: VoxelFormat, cubes: &[Cube]) -> Result<Vec<u8>, FormatError> {
+    match format {
+        VoxelFormat::Goxel => goxel::write(cubes),
+        VoxelFormat::Qubicle => Ok(qubicle::write(cubes)),
+        VoxelFormat::Schematic => Ok(export_schematic(cubes, &BlockPalette::default())?),
+    }
+}
+
+/// Cubes of a file of `format`, the inverse of `export_voxels`: the top left
+/// back corner of the model is placed at `origin`, and the voxels outside of
+/// the canvas are left out. Returns the cubes along with the number of voxels
+/// left out.
+pub fn import_voxels(
+    format: VoxelFormat,
+    data: &[u8],
+    origin: Position,
+    side_len: u32,
+) -> Result<(Vec<Cube>, usize), FormatError> {
+    let voxels = match format {
+        VoxelFormat::Goxel => goxel::read(data)?,
+        VoxelFormat::Qubicle => qubicle::read(data)?,
+        VoxelFormat::Schematic => {
+            let blocks = BlockPalette::default();
+            return Ok(import_schematic(data, &blocks, origin, side_len)?);
+        }
+    };
+    Ok(place(voxels, origin, side_len))
+}
+
+fn place(voxels: Vec<Voxel>, origin: Position, side_len: u32) -> (Vec<Cube>, usize) {
+    let min = |axis: usize| voxels.iter().map(|(p, _)| p[axis]).min().unwrap_or(0);
+    let min = [min(0), min(1), min(2)];
+    let origin = [origin.x as i64, origin.y as i64, origin.z as i64];
+    let mut cubes = Vec::with_capacity(voxels.len());
+    let mut skipped = 0;
+    for (position, colour) in &voxels {
+        let coordinate = |axis: usize| origin[axis] + position[axis] - min[axis];
+        match bounded_position([coordinate(0), coordinate(1), coordinate(2)], side_len) {
+            Ok(position) => cubes.push(Cube {
+                position,
+                colour: *colour,
+            }),
+            Err(_) => skipped += 1,
+        }
+    }
+    (cubes, skipped)
+}
+
+// Smallest and largest coordinates of the cubes along each axis.
+fn bounds(cubes: &[Cube]) -> ([u32; 3], [u32; 3]) {
+    let axes = |cube: &Cube| [cube.position.x, cube.position.y, cube.position.z];
+    let mut min = [u32::MAX; 3];
+    let mut max = [0; 3];
+    for cube in cubes {
+        for (axis, value) in axes(cube).iter().enumerate() {
+            min[axis] = min[axis].min(*value);
+            max[axis] = max[axis].max(*value);
+        }
+    }
+    match cubes.is_empty() {
+        true => ([0; 3], [0; 3]),
+        false => (min, max),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_cubes() -> Vec<Cube> {
+        vec![
+            Cube::new(10, 5, 3, Colour::new(255, 0, 0)),
+            Cube::new(11, 4, 3, Colour::new(0, 255, 0)),
+            Cube::new(10, 4, 40, Colour::new(0, 0, 255)),
+            Cube::new(30, 5, 3, Colour::new(1, 2, 3)),
+        ]
+    }
+
+    fn sorted(mut cubes: Vec<Cube>) -> Vec<Cube> {
+        cubes.sort_by_key(|cube| cube.position);
+        cubes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cubes = sample_cubes();
+        for format in [VoxelFormat::Goxel, VoxelFormat::Qubicle] {
+            let data = export_voxels(format, &cubes).unwrap();
+            let (imported, skipped) =
+                import_voxels(format, &data, Position::new(10, 4, 3), 64).unwrap();
+            assert_eq!(skipped, 0, "{:?}", format);
+            assert_eq!(sorted(imported), sorted(cubes.clone()), "{:?}", format);
+            // Clamped to the canvas.
+            let (imported, skipped) =
+                import_voxels(format, &data, Position::new(0, 0, 0), 32).unwrap();
+            assert_eq!((imported.len(), skipped), (3, 1), "{:?}", format);
+        }
+        assert!(import_voxels(VoxelFormat::Goxel, b"GOX", Position::default(), 8).is_err());
+        assert!(import_voxels(VoxelFormat::Qubicle, b"", Position::default(), 8).is_err());
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(".QB".parse::<VoxelFormat>().unwrap(), VoxelFormat::Qubicle);
+        assert_eq!("gox".parse::<VoxelFormat>().unwrap(), VoxelFormat::Goxel);
+        assert!("vox".parse::<VoxelFormat>().is_err());
+    }
+}
@@ -0,0 +1,150 @@
+// Goxel's format: chunks after a header, see `gox.c` in Goxel's sources. The
+// voxels are stored in blocks of 16 x 16 x 16, each in a chunk as a 64 x 64
+// PNG, and layers place the blocks.
+use super::{bounds, FormatError, Voxel, MAX_VOXELS};
+use crate::{Colour, Cube};
+use flate2::Crc;
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageFormat};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+const MAGIC: &[u8; 4] = b"GOX ";
+const VERSION: i32 = 2;
+const BLOCK_SIDE: u32 = 16;
+const BLOCK_IMAGE_SIDE: u32 = 64;
+
+fn invalid(reason: &str) -> FormatError {
+    FormatError::Invalid(reason.to_owned())
+}
+
+fn read_i32(data: &[u8], at: usize) -> Result<i32, FormatError> {
+    match data.get(at..at + 4) {
+        Some(bytes) => Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err(invalid("the file is truncated")),
+    }
+}
+
+/// Voxels of all the layers of the file, in canvas orientation.
+pub(super) fn read(data: &[u8]) -> Result<Vec<Voxel>, FormatError> {
+    if !data.starts_with(MAGIC) {
+        return Err(invalid("not a Goxel file"));
+    }
+    let mut at = 8;
+    let mut blocks: Vec<Vec<u8>> = Vec::new();
+    let mut voxels = Vec::new();
+    while at < data.len() {
+        let kind = data
+            .get(at..at + 4)
+            .ok_or_else(|| invalid("the file is truncated"))?;
+        let len = read_i32(data, at + 4)?;
+        let start = at + 8;
+        let chunk = usize::try_from(len)
+            .ok()
+            .and_then(|len| data.get(start..start + len))
+            .ok_or_else(|| invalid("the file is truncated"))?;
+        // Followed by its checksum.
+        at = start + chunk.len() + 4;
+        match kind {
+            b"BL16" => {
+                let img = image::load_from_memory_with_format(chunk, ImageFormat::Png)?.to_rgba8();
+                if img.dimensions() != (BLOCK_IMAGE_SIDE, BLOCK_IMAGE_SIDE) {
+                    return Err(invalid("invalid block"));
+                }
+                blocks.push(img.into_raw());
+            }
+            b"LAYR" => {
+                let count = read_i32(chunk, 0)?;
+                for i in 0..count.max(0) as usize {
+                    let field = |n: usize| read_i32(chunk, 4 + i * 20 + n * 4);
+                    let block = usize::try_from(field(0)?)
+                        .ok()
+                        .and_then(|index| blocks.get(index))
+                        .ok_or_else(|| invalid("unknown block"))?;
+                    let origin = [field(1)? as i64, field(2)? as i64, field(3)? as i64];
+                    for (index, pixel) in block.chunks_exact(4).enumerate() {
+                        if pixel[3] == 0 {
+                            continue;
+                        }
+                        let side = BLOCK_SIDE as usize;
+                        let x = origin[0] + (index % side) as i64;
+                        let y = origin[1] + (index / side % side) as i64;
+                        let z = origin[2] + (index / side / side) as i64;
+                        // Z is up, and y towards the back.
+                        voxels.push(([x, -z, -y], Colour::new(pixel[0], pixel[1], pixel[2])));
+                    }
+                    // Layers can place the same block any number of times.
+                    if voxels.len() > MAX_VOXELS {
+                        return Err(invalid("the model is too large"));
+                    }
+                }
+                // The properties of the layer follow, they don't matter here.
+            }
+            // Previews, materials, cameras and lights.
+            _ => {}
+        }
+    }
+    Ok(voxels)
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(kind);
+    out.extend_from_slice(&(data.len() as i32).to_le_bytes());
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc.sum().to_le_bytes());
+}
+
+/// File of a single layer holding `cubes`.
+pub(super) fn write(cubes: &[Cube]) -> Result<Vec<u8>, FormatError> {
+    let (min, max) = bounds(cubes);
+    let side = BLOCK_SIDE;
+    let mut blocks: BTreeMap<[u32; 3], Vec<u8>> = BTreeMap::new();
+    for cube in cubes {
+        let position = cube.position;
+        // Flipped upright, the front facing -y.
+        let voxel = [
+            position.x - min[0],
+            max[2] - position.z,
+            max[1] - position.y,
+        ];
+        let origin = [voxel[0] / side, voxel[1] / side, voxel[2] / side];
+        let block = blocks
+            .entry(origin)
+            .or_insert_with(|| vec![0; (side * side * side * 4) as usize]);
+        let index = (voxel[0] % side + voxel[1] % side * side + voxel[2] % side * side * side) * 4;
+        let colour = cube.colour;
+        block[index as usize..index as usize + 4]
+            .copy_from_slice(&[colour.r, colour.g, colour.b, 255]);
+    }
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    let mut layer = Vec::new();
+    layer.extend_from_slice(&(blocks.len() as i32).to_le_bytes());
+    for (index, (origin, pixels)) in blocks.iter().enumerate() {
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png).encode(
+            pixels,
+            BLOCK_IMAGE_SIDE,
+            BLOCK_IMAGE_SIDE,
+            ColorType::Rgba8,
+        )?;
+        write_chunk(&mut out, b"BL16", &png);
+        for value in &[
+            index as i32,
+            (origin[0] * side) as i32,
+            (origin[1] * side) as i32,
+            (origin[2] * side) as i32,
+            0,
+        ] {
+            layer.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    // No properties.
+    layer.extend_from_slice(&0i32.to_le_bytes());
+    write_chunk(&mut out, b"LAYR", &layer);
+    Ok(out)
+}
@@ -0,0 +1,228 @@
+// Qubicle binary format, see https://getqubicle.com/qubicle/documentation/docs/file/qb/
+use super::{bounds, FormatError, Voxel, MAX_VOXELS};
+use crate::{Colour, Cube};
+use std::collections::HashMap;
+
+const VERSION: [u8; 4] = [1, 1, 0, 0];
+const RGBA: u32 = 0;
+const RIGHT_HANDED: u32 = 1;
+// Markers of the run-length encoding of compressed matrices.
+const CODE_FLAG: u32 = 2;
+const NEXT_SLICE_FLAG: u32 = 6;
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, len: usize) -> Result<&[u8], FormatError> {
+        if self.data.len() < len {
+            return Err(FormatError::Invalid("the file is truncated".to_owned()));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, FormatError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+/// Voxels of all the matrices of the file, in canvas orientation.
+pub(super) fn read(data: &[u8]) -> Result<Vec<Voxel>, FormatError> {
+    let mut reader = Reader { data };
+    let _version = reader.u32()?;
+    let colour_format = reader.u32()?;
+    let z_axis = reader.u32()?;
+    let compressed = reader.u32()? != 0;
+    let _visibility_mask = reader.u32()?;
+    let matrices = reader.u32()?;
+    let colour = |value: u32| {
+        let [a, b, c, alpha] = value.to_le_bytes();
+        match (alpha, colour_format) {
+            (0, _) => None,
+            (_, RGBA) => Some(Colour::new(a, b, c)),
+            _ => Some(Colour::new(c, b, a)),
+        }
+    };
+    let mut voxels = Vec::new();
+    for _ in 0..matrices {
+        let name_len = reader.bytes(1)?[0] as usize;
+        reader.bytes(name_len)?;
+        let size = [reader.u32()?, reader.u32()?, reader.u32()?];
+        // Compressed matrices are as big as they claim, whatever the file.
+        let volume = size.iter().map(|&side| side as u64).product::<u64>();
+        if voxels.len() as u64 + volume > MAX_VOXELS as u64 {
+            return Err(FormatError::Invalid("the model is too large".to_owned()));
+        }
+        let origin = [
+            reader.u32()? as i32 as i64,
+            reader.u32()? as i32 as i64,
+            reader.u32()? as i32 as i64,
+        ];
+        let mut push = |x: u32, y: u32, z: u32, value: u32| {
+            if let Some(colour) = colour(value) {
+                let z = origin[2] + z as i64;
+                // Y is up, z towards the front unless left handed.
+                let position = [
+                    origin[0] + x as i64,
+                    -(origin[1] + y as i64),
+                    if z_axis == RIGHT_HANDED { z } else { -z },
+                ];
+                voxels.push((position, colour));
+            }
+        };
+        if !compressed {
+            for z in 0..size[2] {
+                for y in 0..size[1] {
+                    for x in 0..size[0] {
+                        push(x, y, z, reader.u32()?);
+                    }
+                }
+            }
+            continue;
+        }
+        let slice_len = size[0] as u64 * size[1] as u64;
+        for z in 0..size[2] {
+            let mut index = 0;
+            loop {
+                let (count, value) = match reader.u32()? {
+                    NEXT_SLICE_FLAG => break,
+                    CODE_FLAG => (reader.u32()?, reader.u32()?),
+                    value => (1, value),
+                };
+                for _ in 0..count {
+                    if index >= slice_len {
+                        return Err(FormatError::Invalid("slice overflow".to_owned()));
+                    }
+                    let x = (index % size[0] as u64) as u32;
+                    let y = (index / size[0] as u64) as u32;
+                    push(x, y, z, value);
+                    index += 1;
+                }
+            }
+        }
+    }
+    Ok(voxels)
+}
+
+/// File of a single matrix holding `cubes`, right handed. It's run-length
+/// encoded, as the canvas is mostly empty.
+pub(super) fn write(cubes: &[Cube]) -> Vec<u8> {
+    let (min, max) = bounds(cubes);
+    let size: Vec<u32> = (0..3).map(|axis| max[axis] - min[axis] + 1).collect();
+    let colours: HashMap<[u32; 3], Colour> = cubes
+        .iter()
+        .map(|cube| {
+            let position = cube.position;
+            // Flipped upright.
+            let local = [
+                position.x - min[0],
+                max[1] - position.y,
+                position.z - min[2],
+            ];
+            (local, cube.colour)
+        })
+        .collect();
+    let name = b"twixelbox";
+    let mut out = Vec::new();
+    out.extend_from_slice(&VERSION);
+    for value in &[RGBA, RIGHT_HANDED, 1, 0, 1] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out.push(name.len() as u8);
+    out.extend_from_slice(name);
+    for value in size.iter().chain(&[0, 0, 0]) {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    let write_run = |out: &mut Vec<u8>, count: u32, value: u32| {
+        let words = match count {
+            0 => vec![],
+            1 => vec![value],
+            2 => vec![value, value],
+            count => vec![CODE_FLAG, count, value],
+        };
+        for word in words {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+    };
+    for z in 0..size[2] {
+        let (mut count, mut run) = (0, 0);
+        for y in 0..size[1] {
+            for x in 0..size[0] {
+                // Solid voxels are opaque, so they never look like a marker.
+                let value = match colours.get(&[x, y, z]) {
+                    Some(c) => u32::from_le_bytes([c.r, c.g, c.b, 255]),
+                    None => 0,
+                };
+                if value != run {
+                    write_run(&mut out, count, run);
+                    count = 0;
+                    run = value;
+                }
+                count += 1;
+            }
+        }
+        write_run(&mut out, count, run);
+        out.extend_from_slice(&NEXT_SLICE_FLAG.to_le_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_compressed() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&VERSION);
+        // BGRA, left handed, compressed, 1 matrix.
+        for value in &[1u32, 0, 1, 0, 1] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&[1, b'm']);
+        // 2 x 1 x 2 at 0 0 0.
+        for value in &[2u32, 1, 2, 0, 0, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let blue = u32::from_le_bytes([255, 0, 0, 255]);
+        // First slice: two blue voxels in a run, second: empty then blue.
+        for value in &[
+            CODE_FLAG,
+            2,
+            blue,
+            NEXT_SLICE_FLAG,
+            0,
+            blue,
+            NEXT_SLICE_FLAG,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let blue = Colour::new(0, 0, 255);
+        assert_eq!(
+            read(&data).unwrap(),
+            vec![([0, 0, 0], blue), ([1, 0, 0], blue), ([1, 0, -1], blue)]
+        );
+    }
+
+    #[test]
+    fn test_read_too_large() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&VERSION);
+        for value in &[0u32, 1, 1, 0, 1] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&[1, b'm']);
+        // 65536 x 65536 x 1, a single run filling it.
+        for value in &[1u32 << 16, 1 << 16, 1, 0, 0, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in &[CODE_FLAG, u32::MAX, u32::MAX, NEXT_SLICE_FLAG] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        assert!(matches!(read(&data), Err(FormatError::Invalid(_))));
+    }
+}
@@ -0,0 +1,128 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GoalConfig {
+    /// Cubes for chat to place each day, e.g. 5000. None sets no goal until a
+    /// moderator sets one with `!goal <cubes>`.
+    pub cubes: Option<u64>,
+}
+
+/// How far chat got towards the goal, as drawn on the HUD.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GoalProgress {
+    pub placed: u64,
+    pub target: u64,
+}
+
+impl GoalProgress {
+    /// From 0 to 1, once the goal is reached.
+    pub fn share(&self) -> f32 {
+        match self.target {
+            0 => 1.0,
+            target => (self.placed as f32 / target as f32).min(1.0),
+        }
+    }
+}
+
+impl fmt::Display for GoalProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.placed, self.target)
+    }
+}
+
+/// Cubes to place in a day, counted from midnight and starting over the next
+/// day. Reaching it is only reported once a day.
+#[derive(Clone, Debug)]
+pub struct Goal {
+    target: u64,
+    day: NaiveDate,
+    placed: u64,
+    reached: bool,
+}
+
+impl Goal {
+    /// Resumes the goal of `day`, with the cubes already placed on that day.
+    pub fn new(target: u64, day: NaiveDate, placed: u64) -> Self {
+        Self {
+            target,
+            day,
+            placed,
+            reached: placed >= target,
+        }
+    }
+
+    /// Changes the number of cubes to place, keeping those placed today. A
+    /// target already reached isn't reported.
+    pub fn set_target(&mut self, target: u64) {
+        self.target = target;
+        self.reached = self.placed >= target;
+    }
+
+    pub fn progress(&self) -> GoalProgress {
+        GoalProgress {
+            placed: self.placed,
+            target: self.target,
+        }
+    }
+
+    /// Starts over when `day` is a new day.
+    pub fn roll_over(&mut self, day: NaiveDate) {
+        if day != self.day {
+            *self = Self::new(self.target, day, 0);
+        }
+    }
+
+    /// Counts a cube placed on `day`, returns whether it reached the goal.
+    pub fn count_placement(&mut self, day: NaiveDate) -> bool {
+        self.roll_over(day);
+        self.placed += 1;
+        if self.reached || self.placed < self.target {
+            return false;
+        }
+        self.reached = true;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_goal() {
+        let day = NaiveDate::from_ymd_opt(2021, 3, 10).unwrap();
+        let mut goal = Goal::new(3, day, 1);
+        assert!(!goal.count_placement(day));
+        assert!(goal.count_placement(day));
+        assert!(!goal.count_placement(day));
+        assert_eq!(
+            goal.progress(),
+            GoalProgress {
+                placed: 4,
+                target: 3
+            }
+        );
+        assert_eq!(goal.progress().share(), 1.0);
+
+        // Raised past the cubes placed, it's reported again.
+        goal.set_target(5);
+        assert_eq!(goal.progress().to_string(), "4/5");
+        assert!(goal.count_placement(day));
+
+        let next_day = day.succ_opt().unwrap();
+        goal.roll_over(next_day);
+        assert_eq!(
+            goal.progress(),
+            GoalProgress {
+                placed: 0,
+                target: 5
+            }
+        );
+        assert!(!goal.count_placement(next_day));
+        assert_eq!(goal.progress().share(), 0.2);
+        assert_eq!(Goal::new(0, day, 0).progress().share(), 1.0);
+    }
+}
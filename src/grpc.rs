@@ -0,0 +1,319 @@
+use crate::{ApiAccess, CanvasEvent, HttpServer};
+#[cfg(feature = "grpc")]
+use crate::{Cube, Permission, Position, RequestSignature, SignatureError, UploadError};
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::sync::{broadcast, mpsc};
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GrpcConfig {
+    /// Address the gRPC service listens on, e.g. `0.0.0.0:10669`, the
+    /// service is disabled when unset.
+    pub address: Option<String>,
+    /// Token render requests carry, renders are otherwise refused unless
+    /// they carry the token of a role allowed to render.
+    pub render_token: Option<String>,
+}
+
+// Routes of the methods the HTTP server doesn't serve, as named in the
+// access configuration.
+#[cfg(feature = "grpc")]
+const EVENTS_ROUTE: &str = "StreamEvents";
+#[cfg(feature = "grpc")]
+const RENDER_ROUTE: &str = "Render";
+
+#[derive(Error, Debug)]
+pub enum GrpcError {
+    #[error("gRPC is not available, rebuild with --features grpc")]
+    Unavailable,
+    #[error("invalid gRPC address {0}")]
+    Address(String),
+    #[error("error from the gRPC service {0}")]
+    Serve(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderKind {
+    Snapshot,
+    Timelapse,
+}
+
+/// Event applied to the canvas, as streamed to gRPC clients.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AppliedEvent {
+    pub command_id: Uuid,
+    pub event: CanvasEvent,
+    pub author: Option<String>,
+    /// Unix timestamp in seconds.
+    pub timestamp: i64,
+}
+
+impl AppliedEvent {
+    pub fn new(command_id: Uuid, event: CanvasEvent, author: Option<String>) -> Self {
+        Self {
+            command_id,
+            event,
+            author,
+            timestamp: chrono::Utc::now().timestamp(),
+        }
+    }
+}
+
+// Events kept for the clients falling behind, they miss the older ones.
+const EVENT_BACKLOG: usize = 1024;
+
+/// Control plane for backend services preferring protobuf to JSON, see
+/// `proto/twixelbox.proto`. It serves what the HTTP server publishes, takes
+/// the same uploads, streams the events applied to the canvas and passes
+/// render requests on. Cloning the service gives another handle to publish
+/// events.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub struct GrpcService {
+    config: GrpcConfig,
+    access: ApiAccess,
+    http: Option<HttpServer>,
+    events: broadcast::Sender<AppliedEvent>,
+    renders: mpsc::UnboundedSender<RenderKind>,
+}
+
+impl GrpcService {
+    /// Render requests are sent to `renders`. Requests are allowed by
+    /// `access`, like those to the HTTP server.
+    pub fn new(
+        config: &GrpcConfig,
+        access: ApiAccess,
+        http: Option<HttpServer>,
+        renders: mpsc::UnboundedSender<RenderKind>,
+    ) -> Self {
+        let (events, _) = broadcast::channel(EVENT_BACKLOG);
+        Self {
+            config: config.clone(),
+            access,
+            http,
+            events,
+            renders,
+        }
+    }
+
+    /// Streams `event` to the connected clients.
+    pub fn publish(&self, event: AppliedEvent) {
+        // Nobody listening isn't an error.
+        let _ = self.events.send(event);
+    }
+
+    /// Listens on the configured address until the service fails.
+    #[cfg(feature = "grpc")]
+    pub async fn serve(self) -> Result<(), GrpcError> {
+        let address = self.config.address.clone().unwrap_or_default();
+        let address: std::net::SocketAddr = match address.parse() {
+            Ok(address) => address,
+            Err(_) => return Err(GrpcError::Address(address)),
+        };
+        tonic::transport::Server::builder()
+            .add_service(proto::control_plane_server::ControlPlaneServer::new(self))
+            .serve(address)
+            .await
+            .map_err(|e| GrpcError::Serve(e.to_string()))
+    }
+
+    #[cfg(not(feature = "grpc"))]
+    pub async fn serve(self) -> Result<(), GrpcError> {
+        Err(GrpcError::Unavailable)
+    }
+}
+
+#[cfg(feature = "grpc")]
+mod proto {
+    tonic::include_proto!("twixelbox");
+}
+
+#[cfg(feature = "grpc")]
+fn to_proto_position(position: Position) -> proto::Position {
+    proto::Position {
+        x: position.x,
+        y: position.y,
+        z: position.z,
+    }
+}
+
+#[cfg(feature = "grpc")]
+fn to_proto_cube(position: Position, colour: crate::Colour) -> proto::Cube {
+    proto::Cube {
+        position: Some(to_proto_position(position)),
+        colour: (colour.r as u32) << 16 | (colour.g as u32) << 8 | colour.b as u32,
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl From<AppliedEvent> for proto::CanvasEvent {
+    fn from(applied: AppliedEvent) -> Self {
+        use proto::canvas_event::Event;
+        let event = match applied.event {
+            CanvasEvent::CubePlaced(Cube { position, colour }) => {
+                Event::Placed(to_proto_cube(position, colour))
+            }
+            CanvasEvent::CubeRemoved(position) => Event::Removed(to_proto_position(position)),
+            CanvasEvent::CanvasCleared => Event::Cleared(proto::CanvasCleared {}),
+            CanvasEvent::Recoloured { position, colour } => {
+                Event::Recoloured(to_proto_cube(position, colour))
+            }
+        };
+        Self {
+            command_id: applied.command_id.to_string(),
+            event: Some(event),
+            author: applied.author.unwrap_or_default(),
+            timestamp: applied.timestamp,
+        }
+    }
+}
+
+#[cfg(feature = "grpc")]
+#[tonic::async_trait]
+impl proto::control_plane_server::ControlPlane for GrpcService {
+    async fn get_resource(
+        &self,
+        request: tonic::Request<proto::ResourceRequest>,
+    ) -> Result<tonic::Response<proto::Resource>, tonic::Status> {
+        let request = request.into_inner();
+        let http = match &self.http {
+            Some(http) => http,
+            None => return Err(tonic::Status::not_found(request.path)),
+        };
+        match http.resource(&request.path, &request.token) {
+            Ok((content_type, body)) => Ok(tonic::Response::new(proto::Resource {
+                content_type,
+                body: body.to_vec(),
+            })),
+            Err(UploadError::Unauthorized) => Err(tonic::Status::unauthenticated("unauthorized")),
+            Err(_) => Err(tonic::Status::not_found(request.path)),
+        }
+    }
+
+    async fn upload(
+        &self,
+        request: tonic::Request<proto::UploadRequest>,
+    ) -> Result<tonic::Response<proto::UploadReply>, tonic::Status> {
+        let request = request.into_inner();
+        let http = match &self.http {
+            Some(http) => http.clone(),
+            None => return Err(tonic::Status::not_found(request.path)),
+        };
+        // Handlers take a while, e.g. to decode images.
+        let signature = match request.signature.is_empty() {
+            true => None,
+            false => Some(RequestSignature {
+                timestamp: request.timestamp,
+                signature: request.signature.clone(),
+            }),
+        };
+        let result = tokio::task::spawn_blocking(move || {
+            http.upload(
+                &request.path,
+                &request.token,
+                &request.query,
+                request.body,
+                signature.as_ref(),
+            )
+        })
+        .await
+        .map_err(|e| tonic::Status::internal(e.to_string()))?;
+        match result {
+            Ok(text) => Ok(tonic::Response::new(proto::UploadReply { text })),
+            Err(e @ UploadError::NotFound) => Err(tonic::Status::not_found(e.to_string())),
+            Err(e @ UploadError::Unauthorized) => {
+                Err(tonic::Status::unauthenticated(e.to_string()))
+            }
+            Err(UploadError::Rejected(text)) => Err(tonic::Status::invalid_argument(text)),
+            Err(UploadError::Signature(e @ SignatureError::Replayed)) => {
+                Err(tonic::Status::already_exists(e.to_string()))
+            }
+            Err(UploadError::Signature(e)) => Err(tonic::Status::unauthenticated(e.to_string())),
+        }
+    }
+
+    type StreamEventsStream = std::pin::Pin<
+        Box<dyn tokio_stream::Stream<Item = Result<proto::CanvasEvent, tonic::Status>> + Send>,
+    >;
+
+    async fn stream_events(
+        &self,
+        request: tonic::Request<proto::StreamEventsRequest>,
+    ) -> Result<tonic::Response<Self::StreamEventsStream>, tonic::Status> {
+        use tokio_stream::StreamExt;
+        let token = request.into_inner().token;
+        if !self.access.allows(&token, EVENTS_ROUTE, Permission::Read) {
+            return Err(tonic::Status::unauthenticated("unauthorized"));
+        }
+        // Clients too slow to keep up skip the events they missed.
+        let events = tokio_stream::wrappers::BroadcastStream::new(self.events.subscribe())
+            .filter_map(|event| event.ok().map(|event| Ok(event.into())));
+        Ok(tonic::Response::new(
+            Box::pin(events) as Self::StreamEventsStream
+        ))
+    }
+
+    async fn render(
+        &self,
+        request: tonic::Request<proto::RenderRequest>,
+    ) -> Result<tonic::Response<proto::RenderReply>, tonic::Status> {
+        use proto::render_request::Kind;
+        let request = request.into_inner();
+        let allowed = self.config.render_token.as_deref() == Some(request.token.as_str())
+            || self
+                .access
+                .allows(&request.token, RENDER_ROUTE, Permission::Render);
+        if !allowed {
+            return Err(tonic::Status::unauthenticated("unauthorized"));
+        }
+        let kind = match Kind::from_i32(request.kind) {
+            Some(Kind::Snapshot) => RenderKind::Snapshot,
+            Some(Kind::Timelapse) => RenderKind::Timelapse,
+            None => return Err(tonic::Status::invalid_argument("unknown kind of render")),
+        };
+        self.renders
+            .send(kind)
+            .map_err(|e| tonic::Status::unavailable(e.to_string()))?;
+        Ok(tonic::Response::new(proto::RenderReply {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colour, Cube};
+
+    #[tokio::test]
+    async fn test_publish() {
+        let (renders, _) = mpsc::unbounded_channel();
+        let service = GrpcService::new(&GrpcConfig::default(), ApiAccess::default(), None, renders);
+        // Publishing without clients is fine.
+        let cube = Cube::bounded(1, 2, 3, Colour::new(0, 128, 255), 4).unwrap();
+        service.publish(AppliedEvent::new(
+            Uuid::nil(),
+            CanvasEvent::CanvasCleared,
+            None,
+        ));
+        let mut events = service.events.subscribe();
+        let applied = AppliedEvent::new(
+            Uuid::nil(),
+            CanvasEvent::CubePlaced(cube),
+            Some("ann".to_owned()),
+        );
+        service.clone().publish(applied.clone());
+        assert_eq!(events.recv().await.unwrap(), applied);
+        #[cfg(feature = "grpc")]
+        {
+            let event = proto::CanvasEvent::from(applied);
+            assert_eq!(event.author, "ann");
+            match event.event {
+                Some(proto::canvas_event::Event::Placed(placed)) => {
+                    assert_eq!(placed.colour, 0x0080ff)
+                }
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+    }
+}
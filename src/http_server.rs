@@ -0,0 +1,924 @@
+use crate::{request_hash, token_fingerprint, AuditConfig, RequestSignature, SignatureError};
+use crate::{AccessConfig, ApiAccess, ApiActivity, ApiAudit, Permission, Role};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tiny_http::{Header, Method, Response, Server};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Address the server listens on, the server is disabled when unset.
+    pub address: Option<String>,
+    /// URL the server is reachable at by chatters, used in the links posted
+    /// in chat.
+    pub public_url: String,
+    /// Path the overlay is streamed at as MJPEG, e.g. for a browser source,
+    /// not streamed when unset.
+    pub overlay_stream: Option<String>,
+    /// Seconds between two copies of the archive the API answers from, e.g.
+    /// `/api/cubes` and `/api/history`, which lag behind by as much.
+    pub replica_refresh_secs: u64,
+    /// Lets chatters log in with Twitch to place cubes from the web API, on
+    /// their behalf and within the same limits as in chat, and how long
+    /// they stay logged in.
+    pub web_login: bool,
+    pub session_hours: u64,
+    /// Logins in progress at once, and those each client can start in an
+    /// hour.
+    pub max_pending_logins: usize,
+    pub login_starts_per_hour: usize,
+    /// Roles the API tokens grant and those the routes need, shared with
+    /// gRPC.
+    pub access: AccessConfig,
+    /// Audit of the requests changing something, and their signatures.
+    pub audit: AuditConfig,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            address: None,
+            public_url: "http://localhost:10668".to_owned(),
+            overlay_stream: None,
+            replica_refresh_secs: 10,
+            web_login: false,
+            session_hours: 24,
+            max_pending_logins: 100,
+            login_starts_per_hour: 10,
+            access: AccessConfig::default(),
+            audit: AuditConfig::default(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum HttpServerError {
+    #[error("unable to start the HTTP server {0}")]
+    Start(String),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum UploadError {
+    #[error("not found")]
+    NotFound,
+    #[error("unauthorized")]
+    Unauthorized,
+    #[error("{0}")]
+    Rejected(String),
+    #[error("{0}")]
+    Signature(SignatureError),
+}
+
+impl UploadError {
+    /// HTTP status the error is answered with.
+    pub fn status(&self) -> u16 {
+        match self {
+            UploadError::NotFound => 404,
+            UploadError::Unauthorized => 401,
+            UploadError::Rejected(_) => 400,
+            UploadError::Signature(SignatureError::Replayed) => 409,
+            UploadError::Signature(_) => 401,
+        }
+    }
+}
+
+struct Resource {
+    content_type: String,
+    body: Arc<Vec<u8>>,
+    permission: Permission,
+}
+
+// Answers a POST request from its query string and body, with the text of the
+// response or of the error.
+type Handler = Arc<dyn Fn(&str, Vec<u8>) -> Result<String, String> + Send + Sync>;
+
+// Tells who the bearer of a token is, if anyone.
+type Authorize = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+// Answers a POST request on behalf of whom its token is of, from its query
+// string and body.
+type UserHandler = Arc<dyn Fn(&str, &str, Vec<u8>) -> Result<String, String> + Send + Sync>;
+
+struct Upload {
+    authorize: Authorize,
+    handler: UserHandler,
+    // Lets in the tokens of a role too, when the upload isn't on behalf of
+    // someone.
+    permission: Option<Permission>,
+}
+
+// Answers a GET request from its query string, with JSON or the text of the
+// error.
+type Query = Arc<dyn Fn(&str) -> Result<String, String> + Send + Sync>;
+
+// Answers a POST request from anyone from the address of its client and its
+// query string, with JSON or the text of the error.
+type Form = Arc<dyn Fn(IpAddr, &str) -> Result<String, String> + Send + Sync>;
+
+// POST requests with larger bodies are refused.
+const MAX_UPLOAD_BYTES: u64 = 16 * 1024 * 1024;
+// Separates the JPEG frames of a stream.
+const STREAM_BOUNDARY: &str = "frame";
+// Frames waiting to be sent to a client of a stream, newer ones are dropped
+// while it's full.
+const STREAM_BACKLOG: usize = 2;
+
+// JPEG frame of a stream, shared by its clients.
+type Frame = Arc<Vec<u8>>;
+
+// Clients of each MJPEG stream.
+type StreamClients = Arc<Mutex<HashMap<String, Vec<SyncSender<Frame>>>>>;
+
+// Answers a client of a stream with the frames pushed to it, as a multipart
+// document written straight to the connection: tiny_http's responses are
+// buffered, the frames would only leave in bulk. Returns once the stream is
+// closed or the client goes away.
+fn send_frames(writer: &mut dyn Write, frames: Receiver<Frame>) -> std::io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        STREAM_BOUNDARY
+    )?;
+    writer.flush()?;
+    for frame in frames {
+        write!(
+            writer,
+            "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            STREAM_BOUNDARY,
+            frame.len()
+        )?;
+        writer.write_all(&frame)?;
+        writer.write_all(b"\r\n")?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Serves the files published by the bot, like the timelapse of the day.
+/// Requests are handled on a dedicated thread, and each upload on a thread of
+/// its own. Cloning the server gives another handle to publish files.
+#[derive(Clone)]
+pub struct HttpServer {
+    public_url: String,
+    resources: Arc<Mutex<HashMap<String, Resource>>>,
+    uploads: Arc<Mutex<HashMap<String, Upload>>>,
+    queries: Arc<Mutex<HashMap<String, (Query, Permission)>>>,
+    forms: Arc<Mutex<HashMap<String, Form>>>,
+    streams: StreamClients,
+    access: Arc<Mutex<ApiAccess>>,
+    audit: Arc<Mutex<ApiAudit>>,
+    address: Option<std::net::SocketAddr>,
+}
+
+impl HttpServer {
+    pub fn start(address: &str, public_url: &str) -> Result<Self, HttpServerError> {
+        let server = Server::http(address).map_err(|e| HttpServerError::Start(e.to_string()))?;
+        let http_server = Self {
+            public_url: public_url.trim_end_matches('/').to_owned(),
+            resources: Arc::new(Mutex::new(HashMap::new())),
+            uploads: Arc::new(Mutex::new(HashMap::new())),
+            queries: Arc::new(Mutex::new(HashMap::new())),
+            forms: Arc::new(Mutex::new(HashMap::new())),
+            streams: Arc::new(Mutex::new(HashMap::new())),
+            access: Arc::new(Mutex::new(ApiAccess::default())),
+            audit: Arc::new(Mutex::new(ApiAudit::default())),
+            address: server.server_addr().to_ip(),
+        };
+        let handle = http_server.clone();
+        std::thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                let url = request.url().to_owned();
+                let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+                let token = request
+                    .headers()
+                    .iter()
+                    .find(|header| header.field.equiv("Authorization"))
+                    .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+                    .unwrap_or_default()
+                    .to_owned();
+                let readable = handle.allows(&token, path, Permission::Read);
+                // Streams last as long as their clients watch, each one is
+                // answered on a thread of its own.
+                if let (Method::Get, false, true) =
+                    (request.method(), readable, handle.is_stream(path))
+                {
+                    if let Err(e) = request.respond(upload_error(UploadError::Unauthorized)) {
+                        eprintln!("Unable to answer an HTTP request: {}", e);
+                    }
+                    continue;
+                }
+                if let (Method::Get, Some(frames)) = (request.method(), handle.subscribe(path)) {
+                    std::thread::spawn(move || {
+                        // Fails as soon as the client goes away.
+                        let _ = send_frames(&mut request.into_writer(), frames);
+                    });
+                    continue;
+                }
+                // Answered aside too, they may take a while to look up.
+                if let (Method::Get, Some((answer, permission))) =
+                    (request.method(), handle.query(path))
+                {
+                    let allowed = handle.allows(&token, path, permission);
+                    let query = query.to_owned();
+                    std::thread::spawn(move || {
+                        let response = match allowed.then(|| answer(&query)) {
+                            None => upload_error(UploadError::Unauthorized),
+                            Some(answer) => json_response(answer),
+                        };
+                        if let Err(e) = request.respond(response) {
+                            eprintln!("Unable to answer an HTTP request: {}", e);
+                        }
+                    });
+                    continue;
+                }
+                if let (Method::Post, Some(answer)) = (request.method(), handle.form(path)) {
+                    // Requests over a Unix socket have no address, they
+                    // count as a single client.
+                    let client = request
+                        .remote_addr()
+                        .map(|address| address.ip())
+                        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+                    let query = query.to_owned();
+                    std::thread::spawn(move || {
+                        let response = json_response(answer(client, &query));
+                        if let Err(e) = request.respond(response) {
+                            eprintln!("Unable to answer an HTTP request: {}", e);
+                        }
+                    });
+                    continue;
+                }
+                let response = match request.method() {
+                    Method::Get => match handle.resource(path, &token) {
+                        Ok((content_type, body)) => Response::from_data(body.to_vec()).with_header(
+                            Header::from_bytes("Content-Type", content_type.as_bytes())
+                                .expect("Invalid content type"),
+                        ),
+                        Err(e) => upload_error(e),
+                    },
+                    Method::Post => {
+                        match handle.handler(path, &token) {
+                            Ok(_)
+                                if request.body_length().unwrap_or(0) as u64 > MAX_UPLOAD_BYTES =>
+                            {
+                                too_large()
+                            }
+                            // Read and handled aside, the body may be large and
+                            // slow to decode.
+                            Ok(_) => {
+                                let handle = handle.clone();
+                                let (path, query) = (path.to_owned(), query.to_owned());
+                                let signature = request_signature(&request);
+                                std::thread::spawn(move || {
+                                    let response = match read_upload(&mut request) {
+                                        Ok(body) => match handle.upload(
+                                            &path,
+                                            &token,
+                                            &query,
+                                            body,
+                                            signature.as_ref(),
+                                        ) {
+                                            Ok(text) => Response::from_string(text),
+                                            Err(e) => upload_error(e),
+                                        },
+                                        Err(response) => response,
+                                    };
+                                    if let Err(e) = request.respond(response) {
+                                        eprintln!("Unable to answer an HTTP request: {}", e);
+                                    }
+                                });
+                                continue;
+                            }
+                            Err(e) => {
+                                handle.audit_refusal(path, &token, &e);
+                                upload_error(e)
+                            }
+                        }
+                    }
+                    _ => Response::from_string("method not allowed").with_status_code(405),
+                };
+                if let Err(e) = request.respond(response) {
+                    eprintln!("Unable to answer an HTTP request: {}", e);
+                }
+            }
+        });
+        Ok(http_server)
+    }
+
+    /// Serves `body` at `path`, replacing what was published there before.
+    pub fn publish(&self, path: &str, content_type: &str, body: Vec<u8>) {
+        self.publish_as(path, content_type, body, Permission::Read);
+    }
+
+    /// Serves an export of the canvas at `path`, to the roles allowed to
+    /// download those.
+    pub fn publish_export(&self, path: &str, content_type: &str, body: Vec<u8>) {
+        self.publish_as(path, content_type, body, Permission::Export);
+    }
+
+    fn publish_as(&self, path: &str, content_type: &str, body: Vec<u8>, permission: Permission) {
+        self.resources.lock().unwrap().insert(
+            path.to_owned(),
+            Resource {
+                content_type: content_type.to_owned(),
+                body: Arc::new(body),
+                permission,
+            },
+        );
+    }
+
+    /// Content type and body of what's published at `path`, if `token`
+    /// grants a role allowed to read it.
+    pub fn resource(&self, path: &str, token: &str) -> Result<(String, Arc<Vec<u8>>), UploadError> {
+        let resources = self.resources.lock().unwrap();
+        let resource = resources.get(path).ok_or(UploadError::NotFound)?;
+        if !self.allows(token, path, resource.permission) {
+            return Err(UploadError::Unauthorized);
+        }
+        Ok((resource.content_type.clone(), resource.body.clone()))
+    }
+
+    /// Replaces the roles the tokens grant and those the routes need.
+    pub fn set_access(&self, access: ApiAccess) {
+        *self.access.lock().unwrap() = access;
+    }
+
+    /// Whether a request carrying `token` can use the route at `path`.
+    pub fn allows(&self, token: &str, path: &str, permission: Permission) -> bool {
+        self.access.lock().unwrap().allows(token, path, permission)
+    }
+
+    /// Serves the frames passed to `push_frame` as an MJPEG stream at `path`.
+    pub fn publish_stream(&self, path: &str) {
+        self.streams
+            .lock()
+            .unwrap()
+            .entry(path.to_owned())
+            .or_default();
+    }
+
+    /// Sends a JPEG frame to the clients of the stream at `path`. Clients
+    /// still busy with earlier frames skip it.
+    pub fn push_frame(&self, path: &str, jpeg: Vec<u8>) {
+        let frame = Arc::new(jpeg);
+        if let Some(clients) = self.streams.lock().unwrap().get_mut(path) {
+            clients.retain(|client| match client.try_send(frame.clone()) {
+                Ok(()) | Err(TrySendError::Full(_)) => true,
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+        }
+    }
+
+    /// How many clients watch the stream at `path`, e.g. to only draw
+    /// frames when someone does. Those who left are only noticed on the
+    /// next frame.
+    pub fn stream_clients(&self, path: &str) -> usize {
+        self.streams
+            .lock()
+            .unwrap()
+            .get(path)
+            .map_or(0, |clients| clients.len())
+    }
+
+    fn is_stream(&self, path: &str) -> bool {
+        self.streams.lock().unwrap().contains_key(path)
+    }
+
+    // New client of the stream at `path`, if there's one.
+    fn subscribe(&self, path: &str) -> Option<Receiver<Frame>> {
+        let mut streams = self.streams.lock().unwrap();
+        let clients = streams.get_mut(path)?;
+        let (sender, frames) = mpsc::sync_channel(STREAM_BACKLOG);
+        clients.push(sender);
+        Some(frames)
+    }
+
+    /// Hands an upload to the handler accepting those at `path`, if `token`
+    /// is authorized and the `signature`, if any, is valid. The upload is
+    /// audited either way.
+    pub fn upload(
+        &self,
+        path: &str,
+        token: &str,
+        query: &str,
+        body: Vec<u8>,
+        signature: Option<&RequestSignature>,
+    ) -> Result<String, UploadError> {
+        let (handler, identity) = match self.handler(path, token) {
+            Ok(handler) => handler,
+            Err(e) => {
+                self.audit_refusal(path, token, &e);
+                return Err(e);
+            }
+        };
+        let audit = self.audit.lock().unwrap().clone();
+        let request_hash = request_hash(path, query, &body);
+        let now = chrono::Utc::now().timestamp();
+        let verified = audit.verify(token, &request_hash, signature, now);
+        let signed = verified == Ok(true);
+        let result = match verified {
+            Ok(_) => handler(query, body).map_err(UploadError::Rejected),
+            Err(e) => Err(UploadError::Signature(e)),
+        };
+        audit.record(ApiActivity {
+            timestamp: now,
+            path: path.to_owned(),
+            identity,
+            request_hash: Some(request_hash),
+            signed,
+            status: result.as_ref().map_or_else(UploadError::status, |_| 200),
+        });
+        result
+    }
+
+    // Audits an upload refused before reading it, unless there's nothing at
+    // `path`.
+    fn audit_refusal(&self, path: &str, token: &str, e: &UploadError) {
+        if *e == UploadError::NotFound {
+            return;
+        }
+        self.audit.lock().unwrap().record(ApiActivity {
+            timestamp: chrono::Utc::now().timestamp(),
+            path: path.to_owned(),
+            identity: self.identity(token),
+            request_hash: None,
+            signed: false,
+            status: e.status(),
+        });
+    }
+
+    // Who carries `token`, as audited: the role it grants and a fingerprint
+    // of it.
+    fn identity(&self, token: &str) -> String {
+        if token.is_empty() {
+            return "anonymous".to_owned();
+        }
+        let role = match self.access.lock().unwrap().role(token) {
+            Role::Anyone => "token".to_owned(),
+            role => role.to_string(),
+        };
+        format!("{} {}", role, token_fingerprint(token))
+    }
+
+    /// Replaces the audit of the uploads.
+    pub fn set_audit(&self, audit: ApiAudit) {
+        *self.audit.lock().unwrap() = audit;
+    }
+
+    // Handler of the uploads at `path`, on behalf of the bearer of `token` if
+    // it's authorized, and who they are in the audit.
+    fn handler(&self, path: &str, token: &str) -> Result<(Handler, String), UploadError> {
+        let uploads = self.uploads.lock().unwrap();
+        let upload = uploads.get(path).ok_or(UploadError::NotFound)?;
+        let by_role = upload
+            .permission
+            .filter(|permission| self.allows(token, path, *permission))
+            .map(|_| String::new());
+        let bearer = (upload.authorize)(token)
+            .or(by_role)
+            .ok_or(UploadError::Unauthorized)?;
+        let identity = match bearer.as_str() {
+            "" => self.identity(token),
+            login => login.to_owned(),
+        };
+        let handler = upload.handler.clone();
+        Ok((
+            Arc::new(move |query, body| handler(&bearer, query, body)),
+            identity,
+        ))
+    }
+
+    /// Passes the POST requests to `path` to `handler`, when they carry the
+    /// `Authorization: Bearer <token>` header, or the token of a role allowed
+    /// to change the canvas.
+    pub fn accept_uploads<F>(&self, path: &str, token: &str, handler: F)
+    where
+        F: Fn(&str, Vec<u8>) -> Result<String, String> + Send + Sync + 'static,
+    {
+        let token = token.to_owned();
+        self.add_upload(
+            path,
+            Arc::new(move |bearer| Some(String::new()).filter(|_| bearer == token)),
+            Arc::new(move |_, query, body| handler(query, body)),
+            Some(Permission::Mutation),
+        );
+    }
+
+    /// Passes the POST requests to `path` to `handler`, along with whom
+    /// `authorize` tells their `Authorization: Bearer <token>` header is of.
+    /// The others are refused.
+    pub fn accept_user_uploads<A, F>(&self, path: &str, authorize: A, handler: F)
+    where
+        A: Fn(&str) -> Option<String> + Send + Sync + 'static,
+        F: Fn(&str, &str, Vec<u8>) -> Result<String, String> + Send + Sync + 'static,
+    {
+        self.add_upload(path, Arc::new(authorize), Arc::new(handler), None);
+    }
+
+    fn add_upload(
+        &self,
+        path: &str,
+        authorize: Authorize,
+        handler: UserHandler,
+        permission: Option<Permission>,
+    ) {
+        self.uploads.lock().unwrap().insert(
+            path.to_owned(),
+            Upload {
+                authorize,
+                handler,
+                permission,
+            },
+        );
+    }
+
+    /// Answers the GET requests to `path` with the JSON `handler` makes from
+    /// their query string.
+    pub fn answer_queries<F>(&self, path: &str, handler: F)
+    where
+        F: Fn(&str) -> Result<String, String> + Send + Sync + 'static,
+    {
+        self.answer_queries_as(path, Permission::Read, handler);
+    }
+
+    /// Same as `answer_queries`, to the roles with `permission`.
+    pub fn answer_queries_as<F>(&self, path: &str, permission: Permission, handler: F)
+    where
+        F: Fn(&str) -> Result<String, String> + Send + Sync + 'static,
+    {
+        self.queries
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), (Arc::new(handler), permission));
+    }
+
+    // Handler of the queries at `path` and what they need, if there's one.
+    fn query(&self, path: &str) -> Option<(Query, Permission)> {
+        self.queries.lock().unwrap().get(path).cloned()
+    }
+
+    /// Answers the POST requests to `path` from anyone, e.g. to start a
+    /// login, with the JSON `handler` makes from the address of their client
+    /// and their query string. Their body is ignored.
+    pub fn answer_forms<F>(&self, path: &str, handler: F)
+    where
+        F: Fn(IpAddr, &str) -> Result<String, String> + Send + Sync + 'static,
+    {
+        self.forms
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), Arc::new(handler));
+    }
+
+    // Handler of the forms posted to `path`, if there's one.
+    fn form(&self, path: &str) -> Option<Form> {
+        self.forms.lock().unwrap().get(path).cloned()
+    }
+
+    /// Public link to `path`.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.public_url, path)
+    }
+
+    /// Address the server is bound to.
+    pub fn local_addr(&self) -> Option<std::net::SocketAddr> {
+        self.address
+    }
+}
+
+// Reads the body of an authorized upload, refused past the size limit even
+// when its length wasn't given upfront. Answers the errors.
+fn read_upload(
+    request: &mut tiny_http::Request,
+) -> Result<Vec<u8>, Response<std::io::Cursor<Vec<u8>>>> {
+    let mut body = Vec::new();
+    let read = request
+        .as_reader()
+        .take(MAX_UPLOAD_BYTES + 1)
+        .read_to_end(&mut body);
+    match read {
+        Ok(_) if body.len() as u64 > MAX_UPLOAD_BYTES => Err(too_large()),
+        Ok(_) => Ok(body),
+        Err(e) => Err(Response::from_string(e.to_string()).with_status_code(400)),
+    }
+}
+
+// Signature of an upload, from its headers, if signed.
+fn request_signature(request: &tiny_http::Request) -> Option<RequestSignature> {
+    let header = |name: &'static str| {
+        request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv(name))
+            .map(|header| header.value.as_str().trim().to_owned())
+    };
+    let signature = header("X-Twixelbox-Signature")?;
+    // Signed without a valid timestamp, it's invalid.
+    let timestamp = header("X-Twixelbox-Timestamp")
+        .and_then(|timestamp| timestamp.parse().ok())
+        .unwrap_or_default();
+    Some(RequestSignature {
+        timestamp,
+        signature,
+    })
+}
+
+// Answers with the JSON of a query or form, or the text of its error.
+fn json_response(answer: Result<String, String>) -> Response<std::io::Cursor<Vec<u8>>> {
+    match answer {
+        Ok(json) => Response::from_string(json).with_header(
+            Header::from_bytes("Content-Type", "application/json").expect("Invalid content type"),
+        ),
+        Err(e) => Response::from_string(e).with_status_code(400),
+    }
+}
+
+fn upload_error(e: UploadError) -> Response<std::io::Cursor<Vec<u8>>> {
+    let status = e.status();
+    Response::from_string(e.to_string()).with_status_code(status)
+}
+
+fn too_large() -> Response<std::io::Cursor<Vec<u8>>> {
+    let text = format!(
+        "uploads are limited to {} MB",
+        MAX_UPLOAD_BYTES / 1024 / 1024
+    );
+    Response::from_string(text).with_status_code(413)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Role;
+    use std::io::Write;
+
+    fn send(server: &HttpServer, request: &str) -> String {
+        let mut stream = std::net::TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn get(server: &HttpServer, path: &str) -> String {
+        send(
+            server,
+            &format!(
+                "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                path
+            ),
+        )
+    }
+
+    fn get_as(server: &HttpServer, path: &str, token: &str) -> String {
+        send(
+            server,
+            &format!(
+                "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+                 Authorization: Bearer {}\r\n\r\n",
+                path, token
+            ),
+        )
+    }
+
+    fn post(server: &HttpServer, path: &str, token: &str, body: &str) -> String {
+        send(
+            server,
+            &format!(
+                "POST {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+                 Authorization: Bearer {}\r\nContent-Length: {}\r\n\r\n{}",
+                path,
+                token,
+                body.len(),
+                body
+            ),
+        )
+    }
+
+    #[test]
+    fn test_publish() {
+        let server = HttpServer::start("127.0.0.1:0", "http://example.com/").unwrap();
+        assert_eq!(server.url("/today.gif"), "http://example.com/today.gif");
+        assert!(get(&server, "/hello").starts_with("HTTP/1.1 404"));
+        server.publish("/hello", "text/plain", b"hello chat".to_vec());
+        let response = get(&server, "/hello?cache=0");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("hello chat"));
+    }
+
+    #[test]
+    fn test_queries() {
+        let server = HttpServer::start("127.0.0.1:0", "http://example.com/").unwrap();
+        server.answer_queries("/api/echo", |query| match query {
+            "" => Err("empty".to_owned()),
+            query => Ok(format!("[\"{}\"]", query)),
+        });
+        assert!(get(&server, "/api/echo").starts_with("HTTP/1.1 400"));
+        let response = get(&server, "/api/echo?x=1");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("application/json"));
+        assert!(response.ends_with("[\"x=1\"]"));
+    }
+
+    #[test]
+    fn test_forms() {
+        let server = HttpServer::start("127.0.0.1:0", "http://example.com/").unwrap();
+        server.answer_forms("/api/start", |client, query| match query {
+            "" => Err("empty".to_owned()),
+            query => Ok(format!("[\"{}\", \"{}\"]", client, query)),
+        });
+        assert!(post(&server, "/api/start", "", "").starts_with("HTTP/1.1 400"));
+        let response = post(&server, "/api/start?x=1", "", "ignored");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("[\"127.0.0.1\", \"x=1\"]"));
+        // Only posted.
+        assert!(get(&server, "/api/start?x=1").starts_with("HTTP/1.1 404"));
+    }
+
+    #[test]
+    fn test_uploads() {
+        let server = HttpServer::start("127.0.0.1:0", "http://example.com/").unwrap();
+        server.accept_uploads("/echo", "secret", |query, body| {
+            match String::from_utf8(body) {
+                Ok(body) if !body.is_empty() => Ok(format!("{} {}", query, body)),
+                _ => Err("empty".to_owned()),
+            }
+        });
+        assert!(post(&server, "/echo", "wrong", "hi").starts_with("HTTP/1.1 401"));
+        assert!(post(&server, "/other", "secret", "hi").starts_with("HTTP/1.1 404"));
+        assert!(post(&server, "/echo", "secret", "").starts_with("HTTP/1.1 400"));
+        let too_large = send(
+            &server,
+            &format!(
+                "POST /echo HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+                 Authorization: Bearer secret\r\nContent-Length: {}\r\n\r\n",
+                MAX_UPLOAD_BYTES + 1
+            ),
+        );
+        assert!(too_large.starts_with("HTTP/1.1 413"));
+        let response = post(&server, "/echo?a=1", "secret", "hi");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("a=1 hi"));
+        assert_eq!(
+            server.upload("/echo", "secret", "b=2", b"hey".to_vec(), None),
+            Ok("b=2 hey".to_owned())
+        );
+        assert_eq!(
+            server.upload("/echo", "", "", b"hey".to_vec(), None),
+            Err(UploadError::Unauthorized)
+        );
+
+        server.accept_user_uploads(
+            "/whoami",
+            |token| token.strip_prefix("session-").map(str::to_owned),
+            |login, _, body| Ok(format!("{}: {}", login, String::from_utf8_lossy(&body))),
+        );
+        assert!(post(&server, "/whoami", "secret", "hi").starts_with("HTTP/1.1 401"));
+        let response = post(&server, "/whoami", "session-alice", "hi");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.ends_with("alice: hi"));
+        assert_eq!(
+            server.upload("/whoami", "session-bob", "", b"hey".to_vec(), None),
+            Ok("bob: hey".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_access() {
+        let server = HttpServer::start("127.0.0.1:0", "http://example.com/").unwrap();
+        let config = AccessConfig {
+            viewer_tokens: vec!["viewer".to_owned()],
+            moderator_tokens: vec!["moderator".to_owned()],
+            admin_tokens: vec!["admin".to_owned()],
+            reads: Role::Viewer,
+            exports: Role::Moderator,
+            ..Default::default()
+        };
+        server.set_access(ApiAccess::new(&config).unwrap());
+        server.publish("/stats", "application/json", b"{}".to_vec());
+        server.publish_export("/canvas.qb", "application/octet-stream", b"qb".to_vec());
+        server.answer_queries("/api/echo", |query| Ok(format!("[\"{}\"]", query)));
+        server.publish_stream("/live.mjpg");
+        server.accept_uploads("/echo", "secret", |_, body| {
+            Ok(String::from_utf8_lossy(&body).into_owned())
+        });
+
+        assert!(get(&server, "/stats").starts_with("HTTP/1.1 401"));
+        assert!(get_as(&server, "/stats", "viewer").starts_with("HTTP/1.1 200"));
+        assert!(get_as(&server, "/missing", "viewer").starts_with("HTTP/1.1 404"));
+        assert!(get_as(&server, "/api/echo", "nobody").starts_with("HTTP/1.1 401"));
+        assert!(get_as(&server, "/api/echo", "admin").starts_with("HTTP/1.1 200"));
+        assert!(get(&server, "/live.mjpg").starts_with("HTTP/1.1 401"));
+        assert_eq!(server.stream_clients("/live.mjpg"), 0);
+        assert_eq!(
+            server.resource("/canvas.qb", "viewer"),
+            Err(UploadError::Unauthorized)
+        );
+        assert!(get_as(&server, "/canvas.qb", "moderator").ends_with("qb"));
+        // Changes need the upload token, or an admin's by default.
+        assert!(post(&server, "/echo", "moderator", "hi").starts_with("HTTP/1.1 401"));
+        assert!(post(&server, "/echo", "secret", "hi").ends_with("hi"));
+        assert!(post(&server, "/echo", "admin", "hey").ends_with("hey"));
+    }
+
+    #[test]
+    fn test_audit() {
+        let server = HttpServer::start("127.0.0.1:0", "http://example.com/").unwrap();
+        let config = AccessConfig {
+            admin_tokens: vec!["admin".to_owned()],
+            ..Default::default()
+        };
+        server.set_access(ApiAccess::new(&config).unwrap());
+        let audit = ApiAudit::new(&AuditConfig {
+            signing_secrets: vec![("admin".to_owned(), "signing secret".to_owned())]
+                .into_iter()
+                .collect(),
+            require_signatures: true,
+            ..Default::default()
+        })
+        .unwrap();
+        server.set_audit(audit.clone());
+        server.accept_uploads("/echo", "secret", |_, body| {
+            Ok(String::from_utf8_lossy(&body).into_owned())
+        });
+        server.answer_queries_as("/api/activity", Permission::Audit, |_| Ok("[]".to_owned()));
+
+        let timestamp = chrono::Utc::now().timestamp();
+        let signature = crate::sign_request(
+            "signing secret",
+            timestamp,
+            &request_hash("/echo", "", b"hi"),
+        );
+        let signed = format!(
+            "POST /echo HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+             Authorization: Bearer admin\r\nX-Twixelbox-Timestamp: {}\r\n\
+             X-Twixelbox-Signature: {}\r\nContent-Length: 2\r\n\r\nhi",
+            timestamp, signature
+        );
+        assert!(post(&server, "/echo", "secret", "hi").starts_with("HTTP/1.1 401"));
+        assert!(send(&server, &signed).ends_with("hi"));
+        assert!(send(&server, &signed).starts_with("HTTP/1.1 409"));
+        assert!(post(&server, "/echo", "nobody", "hi").starts_with("HTTP/1.1 401"));
+        assert!(get(&server, "/api/activity").starts_with("HTTP/1.1 401"));
+        assert!(get_as(&server, "/api/activity", "admin").ends_with("[]"));
+
+        let recent = audit.recent(10);
+        let statuses: Vec<_> = recent.iter().map(|activity| activity.status).collect();
+        assert_eq!(statuses, vec![401, 409, 200, 401]);
+        let admin = format!("admin {}", token_fingerprint("admin"));
+        assert_eq!(recent[2].identity, admin);
+        assert!(recent[2].signed);
+        assert_eq!(
+            recent[2].request_hash.as_deref(),
+            Some(request_hash("/echo", "", b"hi").as_str())
+        );
+        assert_eq!(
+            recent[3].identity,
+            format!("token {}", token_fingerprint("secret"))
+        );
+        // Refused before reading the body.
+        assert_eq!(recent[0].request_hash, None);
+    }
+
+    #[test]
+    fn test_stream() {
+        let server = HttpServer::start("127.0.0.1:0", "http://example.com/").unwrap();
+        server.publish_stream("/live.mjpg");
+        let mut stream = std::net::TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        stream
+            .write_all(b"GET /live.mjpg HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        while server.stream_clients("/live.mjpg") == 0 {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        server.push_frame("/live.mjpg", b"first".to_vec());
+        server.push_frame("/live.mjpg", b"second".to_vec());
+        let mut response = Vec::new();
+        let mut buf = [0; 256];
+        while !String::from_utf8_lossy(&response).contains("second") {
+            let read = stream.read(&mut buf).unwrap();
+            assert!(read > 0);
+            response.extend_from_slice(&buf[..read]);
+        }
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("multipart/x-mixed-replace; boundary=frame"));
+        assert!(response
+            .contains("--frame\r\nContent-Type: image/jpeg\r\nContent-Length: 5\r\n\r\nfirst"));
+
+        // Clients who left are dropped on the next frame.
+        drop(stream);
+        for _ in 0..100 {
+            server.push_frame("/live.mjpg", b"third".to_vec());
+            if server.stream_clients("/live.mjpg") == 0 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(server.stream_clients("/live.mjpg"), 0);
+    }
+}
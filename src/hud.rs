@@ -0,0 +1,330 @@
+use crate::credits::{draw_text_at, text_width, GLYPH_HEIGHT};
+use crate::{GoalProgress, MinimapCorner};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, TimeZone};
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use thiserror::Error;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct HudConfig {
+    /// Format of the clock, as in strftime, e.g. `%H:%M`. None hides it.
+    pub clock: Option<String>,
+    /// How long the stream has been live, its category and how many viewers
+    /// watch it, as Helix reports them.
+    pub uptime: bool,
+    pub category: bool,
+    pub viewers: bool,
+    /// The progress towards the goal of the day, when there's one, with a
+    /// bar.
+    pub goal: bool,
+    pub corner: MinimapCorner,
+    /// How often the stream info is fetched from Helix.
+    pub refresh_secs: u64,
+    /// Channel the stream info is of, the one of the bot's account when not
+    /// set.
+    pub broadcaster_id: Option<String>,
+}
+
+impl Default for HudConfig {
+    fn default() -> Self {
+        Self {
+            clock: None,
+            uptime: false,
+            category: false,
+            viewers: false,
+            goal: true,
+            corner: MinimapCorner::TopRight,
+            refresh_secs: 60,
+            broadcaster_id: None,
+        }
+    }
+}
+
+impl HudConfig {
+    /// Whether any line can be shown.
+    pub fn is_enabled(&self) -> bool {
+        self.clock.is_some() || self.needs_stream_info() || self.goal
+    }
+
+    /// Whether the stream info has to be fetched from Helix.
+    pub fn needs_stream_info(&self) -> bool {
+        self.uptime || self.category || self.viewers
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum HudError {
+    #[error("invalid clock format: {0}")]
+    InvalidClockFormat(String),
+}
+
+/// What Helix reports of the stream, nothing while offline.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct StreamInfo {
+    /// Unix timestamp of when the stream went live.
+    pub started_at: Option<i64>,
+    pub category: Option<String>,
+    pub viewers: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct HelixStreams {
+    data: Vec<HelixStream>,
+}
+
+#[derive(Deserialize)]
+struct HelixStream {
+    game_name: String,
+    viewer_count: u32,
+    started_at: String,
+}
+
+impl StreamInfo {
+    /// Reads the response of Helix to `GET streams` for a single channel,
+    /// which lists no stream while it's offline.
+    pub fn from_helix(json: &str) -> Result<Self, serde_json::Error> {
+        let streams: HelixStreams = serde_json::from_str(json)?;
+        let stream = match streams.data.into_iter().next() {
+            Some(stream) => stream,
+            None => return Ok(Self::default()),
+        };
+        Ok(Self {
+            started_at: DateTime::parse_from_rfc3339(&stream.started_at)
+                .ok()
+                .map(|started_at| started_at.timestamp()),
+            category: Some(stream.game_name).filter(|name| !name.is_empty()),
+            viewers: Some(stream.viewer_count),
+        })
+    }
+}
+
+/// Lines of text drawn over the live overlay: a clock, the stream info and
+/// the progress towards the goal, the latter two refreshed by whoever polls
+/// Helix and counts the cubes.
+#[derive(Clone, Debug)]
+pub struct Hud {
+    clock: Option<String>,
+    uptime: bool,
+    category: bool,
+    viewers: bool,
+    show_goal: bool,
+    corner: MinimapCorner,
+    info: StreamInfo,
+    goal: Option<GoalProgress>,
+}
+
+impl Hud {
+    pub fn new(config: &HudConfig) -> Result<Self, HudError> {
+        if let Some(clock) = &config.clock {
+            if StrftimeItems::new(clock).any(|item| item == Item::Error) {
+                return Err(HudError::InvalidClockFormat(clock.clone()));
+            }
+        }
+        Ok(Self {
+            clock: config.clock.clone(),
+            uptime: config.uptime,
+            category: config.category,
+            viewers: config.viewers,
+            show_goal: config.goal,
+            corner: config.corner,
+            info: StreamInfo::default(),
+            goal: None,
+        })
+    }
+
+    pub fn set_stream_info(&mut self, info: StreamInfo) {
+        self.info = info;
+    }
+
+    /// Progress towards the goal of the day, `None` when there's no goal.
+    pub fn set_goal(&mut self, goal: Option<GoalProgress>) {
+        self.goal = goal;
+    }
+
+    // The goal, if shown.
+    fn goal(&self) -> Option<GoalProgress> {
+        self.goal.filter(|_| self.show_goal)
+    }
+
+    /// Lines shown at `now`, the clock in its time zone. The stream info is
+    /// left out while offline.
+    pub fn lines<Tz: TimeZone>(&self, now: DateTime<Tz>) -> Vec<String>
+    where
+        Tz::Offset: Display,
+    {
+        let mut lines = Vec::new();
+        if let Some(clock) = &self.clock {
+            lines.push(now.format(clock).to_string());
+        }
+        if let Some(started_at) = self.info.started_at.filter(|_| self.uptime) {
+            let minutes = (now.timestamp() - started_at).max(0) / 60;
+            lines.push(format!("live {}:{:02}", minutes / 60, minutes % 60));
+        }
+        if let Some(category) = self.info.category.as_ref().filter(|_| self.category) {
+            lines.push(category.clone());
+        }
+        if let Some(viewers) = self.info.viewers.filter(|_| self.viewers) {
+            lines.push(match viewers {
+                1 => "1 viewer".to_owned(),
+                viewers => format!("{} viewers", viewers),
+            });
+        }
+        if let Some(goal) = self.goal() {
+            lines.push(format!("goal {}", goal));
+        }
+        lines
+    }
+
+    /// Draws the lines at `now` in white on a darkened box, in the corner of
+    /// the image and a margin away from its edges, as the minimap. The bar of
+    /// the goal goes under them.
+    pub fn draw<Tz: TimeZone>(&self, img: &mut RgbImage, now: DateTime<Tz>)
+    where
+        Tz::Offset: Display,
+    {
+        let lines = self.lines(now);
+        if lines.is_empty() {
+            return;
+        }
+        let scale = (img.height() / 240).max(1);
+        let margin = (img.height() / 60).max(2);
+        let padding = 2 * scale;
+        let line_height = (GLYPH_HEIGHT + 2) * scale;
+        let bar_height = match self.goal() {
+            Some(_) => line_height,
+            None => 0,
+        };
+        let text_width = lines
+            .iter()
+            .map(|line| text_width(line, scale))
+            .max()
+            .unwrap_or(0);
+        let width = (text_width + 2 * padding).min(img.width().saturating_sub(2 * margin));
+        let height = (lines.len() as u32 * line_height + bar_height + 2 * padding - 2 * scale)
+            .min(img.height().saturating_sub(2 * margin));
+        let left = match self.corner {
+            MinimapCorner::TopLeft | MinimapCorner::BottomLeft => margin,
+            MinimapCorner::TopRight | MinimapCorner::BottomRight => {
+                img.width().saturating_sub(margin + width)
+            }
+        };
+        let top = match self.corner {
+            MinimapCorner::TopLeft | MinimapCorner::TopRight => margin,
+            MinimapCorner::BottomLeft | MinimapCorner::BottomRight => {
+                img.height().saturating_sub(margin + height)
+            }
+        };
+        // Darkened, so the text stands out on light backgrounds.
+        for y in top..top + height {
+            for x in left..left + width {
+                img.get_pixel_mut(x, y).0.iter_mut().for_each(|c| *c /= 3);
+            }
+        }
+        for (row, line) in lines.iter().enumerate() {
+            draw_text_at(
+                img,
+                line,
+                (left + padding) as i64,
+                (top + padding + row as u32 * line_height) as i64,
+                scale,
+            );
+        }
+        if let Some(goal) = self.goal() {
+            let bar_top = top + padding + lines.len() as u32 * line_height;
+            let bar_width = width.saturating_sub(2 * padding);
+            let filled = (bar_width as f32 * goal.share()) as u32;
+            for y in bar_top..(bar_top + GLYPH_HEIGHT * scale).min(top + height) {
+                for x in left + padding..left + padding + bar_width {
+                    // The rest of the bar is outlined.
+                    let edge = y == bar_top
+                        || y + 1 == bar_top + GLYPH_HEIGHT * scale
+                        || x == left + padding
+                        || x + 1 == left + padding + bar_width;
+                    if x < left + padding + filled || edge {
+                        img.put_pixel(x, y, image::Rgb([255, 255, 255]));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    const LIVE: &str = r#"{
+        "data": [{
+            "id": "40952121085",
+            "user_id": "101051819",
+            "user_login": "stuck_overflow",
+            "game_id": "1469308723",
+            "game_name": "Software and Game Development",
+            "type": "live",
+            "title": "Building cubes",
+            "viewer_count": 42,
+            "started_at": "2021-03-10T15:04:21Z",
+            "language": "en"
+        }],
+        "pagination": {}
+    }"#;
+
+    #[test]
+    fn test_stream_info() {
+        let info = StreamInfo::from_helix(LIVE).unwrap();
+        assert_eq!(info.started_at, Some(1615388661));
+        assert_eq!(
+            info.category.as_deref(),
+            Some("Software and Game Development")
+        );
+        assert_eq!(info.viewers, Some(42));
+        assert_eq!(
+            StreamInfo::from_helix(r#"{"data": [], "pagination": {}}"#).unwrap(),
+            StreamInfo::default()
+        );
+        assert!(StreamInfo::from_helix("{}").is_err());
+    }
+
+    #[test]
+    fn test_hud() {
+        assert!(Hud::new(&HudConfig {
+            clock: Some("%H:%Q".to_owned()),
+            ..Default::default()
+        })
+        .is_err());
+
+        let config = HudConfig {
+            clock: Some("%H:%M".to_owned()),
+            uptime: true,
+            viewers: true,
+            ..Default::default()
+        };
+        let mut hud = Hud::new(&config).unwrap();
+        let now = DateTime::parse_from_rfc3339("2021-03-10T16:10:00+01:00").unwrap();
+        // Offline, only the clock.
+        assert_eq!(hud.lines(now), vec!["16:10"]);
+        hud.set_stream_info(StreamInfo::from_helix(LIVE).unwrap());
+        assert_eq!(hud.lines(now), vec!["16:10", "live 0:05", "42 viewers"]);
+
+        hud.set_goal(Some(GoalProgress {
+            placed: 25,
+            target: 100,
+        }));
+        assert_eq!(
+            hud.lines(now),
+            vec!["16:10", "live 0:05", "42 viewers", "goal 25/100"]
+        );
+
+        let mut img = RgbImage::from_pixel(120, 120, Rgb([240, 240, 240]));
+        hud.draw(&mut img, now);
+        // Darkened in the top right corner, with the text in white.
+        assert_eq!(img.get_pixel(117, 3).0, [80, 80, 80]);
+        assert_eq!(img.get_pixel(3, 3).0, [240, 240, 240]);
+        assert!(img.pixels().any(|pixel| pixel.0 == [255, 255, 255]));
+        assert_eq!(img.get_pixel(3, 117).0, [240, 240, 240]);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{CanvasEvent, GoalProgress, Position, Region, Slice, WeatherEffect};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+
+// Longest line accepted from a bot, a highlight listing every cube of a
+// chatter being the longest message.
+const MAX_LINE_BYTES: usize = 1 << 20;
+// Time a bot has to introduce itself once connected.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+// First line of a connection, the bot introducing itself with the token
+// shared with the renderer.
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    token: String,
+}
+
+// Protocol spoken between the chat bot process and the renderer process. Each
+// message is serialized as a single line of JSON, so the stream can also be
+// inspected or injected by hand with tools like netcat.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum IpcMessage {
+    // Carries the id of the command which produced the event, so that events
+    // sent again after a reconnection are only applied once.
+    Event {
+        id: Uuid,
+        event: CanvasEvent,
+        // Team of the author, for the scoreboard.
+        #[serde(default)]
+        team: Option<String>,
+    },
+    Snapshot,
+    // Slice the overlay, see SliceFilter.
+    Slice(Slice),
+    // Cubes highlighted on the overlay for a while, the others fading.
+    Highlight(Vec<Position>),
+    // Region which can be built on, sent on connection and on each unlock.
+    Fog(Option<Region>),
+    // Degrees per second the overlay orbits at.
+    Spin(f32),
+    // Name of the theme of the overlay.
+    Theme(String),
+    // Name of the camera preset the overlay moves to.
+    View(String),
+    // Effect over the overlay, none to clear it.
+    Weather(Option<WeatherEffect>),
+    // Progress towards the goal of the day for the HUD, sent on connection
+    // and as it changes.
+    Goal(Option<GoalProgress>),
+}
+
+#[derive(Error, Debug)]
+pub enum IpcError {
+    #[error("io error {0}")]
+    Io(#[from] std::io::Error),
+    #[error("error from serde_json {0}")]
+    SerdeJson(#[from] serde_json::Error),
+    #[error("the bot didn't introduce itself with the token")]
+    Handshake,
+    #[error("message over {} bytes", MAX_LINE_BYTES)]
+    LineTooLong,
+}
+
+/// Bot side of the connection, forwards messages to a renderer.
+pub struct IpcSender {
+    stream: TcpStream,
+}
+
+impl IpcSender {
+    /// Connects to the renderer at `address`, introducing the bot with the
+    /// `token` they share.
+    pub async fn connect(address: &str, token: &str) -> Result<Self, IpcError> {
+        let stream = TcpStream::connect(address).await?;
+        let mut sender = Self { stream };
+        let hello = Hello {
+            token: token.to_owned(),
+        };
+        sender.write_line(serde_json::to_string(&hello)?).await?;
+        Ok(sender)
+    }
+
+    pub async fn send(&mut self, message: &IpcMessage) -> Result<(), IpcError> {
+        self.write_line(serde_json::to_string(message)?).await
+    }
+
+    async fn write_line(&mut self, mut line: String) -> Result<(), IpcError> {
+        line.push('\n');
+        self.stream.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+/// Renderer side, waits for bots to connect.
+pub struct IpcListener {
+    listener: TcpListener,
+    token: String,
+}
+
+impl IpcListener {
+    /// Listens on `address` for bots introducing themselves with `token`.
+    pub async fn bind(address: &str, token: &str) -> Result<Self, IpcError> {
+        let listener = TcpListener::bind(address).await?;
+        Ok(Self {
+            listener,
+            token: token.to_owned(),
+        })
+    }
+
+    /// Waits for the next bot, which must introduce itself with the token
+    /// in time.
+    pub async fn accept(&self) -> Result<IpcReceiver, IpcError> {
+        let (stream, _) = self.listener.accept().await?;
+        let mut receiver = IpcReceiver {
+            reader: BufReader::new(stream),
+        };
+        let hello = tokio::time::timeout(HELLO_TIMEOUT, receiver.next_line())
+            .await
+            .map_err(|_| IpcError::Handshake)??
+            .ok_or(IpcError::Handshake)?;
+        let hello: Hello = serde_json::from_slice(&hello).map_err(|_| IpcError::Handshake)?;
+        let matches = hello.token.len() == self.token.len()
+            && openssl::memcmp::eq(hello.token.as_bytes(), self.token.as_bytes());
+        if !matches {
+            return Err(IpcError::Handshake);
+        }
+        Ok(receiver)
+    }
+}
+
+/// A single bot connection accepted by an `IpcListener`.
+pub struct IpcReceiver {
+    reader: BufReader<TcpStream>,
+}
+
+impl IpcReceiver {
+    /// Returns the next message, or `None` once the bot closed the connection.
+    pub async fn recv(&mut self) -> Result<Option<IpcMessage>, IpcError> {
+        match self.next_line().await? {
+            Some(line) => Ok(Some(serde_json::from_slice(&line)?)),
+            None => Ok(None),
+        }
+    }
+
+    // Reads up to the end of the next line, refusing to buffer more than
+    // `MAX_LINE_BYTES` of it.
+    async fn next_line(&mut self) -> Result<Option<Vec<u8>>, IpcError> {
+        let mut line = Vec::new();
+        let read = (&mut self.reader)
+            .take(MAX_LINE_BYTES as u64 + 1)
+            .read_until(b'\n', &mut line)
+            .await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        } else if line.len() > MAX_LINE_BYTES {
+            return Err(IpcError::LineTooLong);
+        }
+        Ok(Some(line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colour, Cube};
+
+    #[tokio::test]
+    async fn test_send_recv() {
+        let listener = IpcListener::bind("127.0.0.1:0", "shared").await.unwrap();
+        let address = listener.listener.local_addr().unwrap().to_string();
+        let expected_message = IpcMessage::Event {
+            id: Uuid::new_v4(),
+            event: CanvasEvent::CubePlaced(Cube::new(1, 2, 3, Colour::new(4, 5, 6))),
+            team: Some("red".to_owned()),
+        };
+
+        let mut sender = IpcSender::connect(&address, "shared").await.unwrap();
+        let mut receiver = listener.accept().await.unwrap();
+        sender.send(&expected_message).await.unwrap();
+        drop(sender);
+
+        assert_eq!(
+            receiver.recv().await.unwrap(),
+            Some(expected_message.clone())
+        );
+        assert_eq!(receiver.recv().await.unwrap(), None);
+
+        let mut sender = IpcSender::connect(&address, "guessed").await.unwrap();
+        assert!(matches!(listener.accept().await, Err(IpcError::Handshake)));
+        drop(sender);
+        // Sent straight away, without introducing itself.
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        let message = serde_json::to_string(&expected_message).unwrap();
+        stream.write_all(message.as_bytes()).await.unwrap();
+        stream.write_all(b"\n").await.unwrap();
+        assert!(matches!(listener.accept().await, Err(IpcError::Handshake)));
+
+        sender = IpcSender::connect(&address, "shared").await.unwrap();
+        let mut receiver = listener.accept().await.unwrap();
+        let endless = vec![b' '; MAX_LINE_BYTES + 1];
+        tokio::spawn(async move { sender.stream.write_all(&endless).await });
+        assert!(matches!(receiver.recv().await, Err(IpcError::LineTooLong)));
+    }
+}
@@ -1,9 +1,158 @@
+mod achievements;
+mod announcements;
+mod api_access;
+mod api_audit;
+mod auxiliary;
+mod background;
+mod build_sheet;
+mod camera_path;
+mod canvas;
+mod canvas_event;
+mod chat_command;
+mod colour_correction;
+mod colour_stats;
 mod command_archive;
+mod command_limits;
+mod command_queue;
+mod command_timing;
+mod competition;
+mod coordinates;
+mod credits;
+mod cube;
+mod decay;
+mod event_log;
+mod flat_renderer;
+mod formats;
+mod goal;
+mod grpc;
+mod http_server;
+mod hud;
+mod ipc;
+mod macros;
+mod markers;
+mod minimap;
+mod mqtt;
+mod nbt;
+mod octree;
+mod onion_skin;
+mod outbox;
+mod palette;
+mod plugin;
+mod poll;
+mod post_processing;
+mod progression;
+mod protection;
+mod quality;
+mod raytracer;
+mod renderer;
+mod replica;
+mod report;
+mod resize;
+mod retention;
+mod schematic;
+mod screensaver;
+mod scripting;
+mod slice;
+mod spin;
+mod stereo;
+mod summary;
+mod teams;
+mod templates;
+mod terminal_renderer;
+mod terrain;
+mod timelapse;
+mod transform;
+mod user_filter;
+mod views;
+mod watermark;
+mod weather;
+mod web_login;
+#[cfg(feature = "wgpu-renderer")]
+mod wgpu_renderer;
 
-pub use command_archive::CubeArchive;
-
-#[derive(Clone, Debug, PartialEq)]
-pub struct Cube {
-    pub position: (u32, u32, u32),
-    pub colour: (u8, u8, u8),
-}
+pub use achievements::{Achievement, AchievementConfig, AchievementTracker};
+pub use announcements::{AnnouncementError, Announcements};
+pub use api_access::{AccessConfig, AccessError, ApiAccess, Permission, Role};
+pub use api_audit::{
+    request_hash, sign_request, token_fingerprint, ApiActivity, ApiAudit, AuditConfig, AuditError,
+    RequestSignature, SignatureError,
+};
+pub use auxiliary::{cube_at, cube_id, AuxiliaryBuffer, AuxiliaryBuffers};
+pub use background::{Background, BackgroundConfig, BackgroundError};
+pub use build_sheet::{BuildSheet, Projection};
+pub use camera_path::{render_camera_path, CameraPath, CameraPathError, CameraPose, Keyframe};
+pub use canvas::{bounded_position, Canvas, CanvasDifference, CanvasError};
+pub use canvas_event::CanvasEvent;
+pub use chat_command::{ChatCommand, ChatCommandError};
+pub use colour_correction::{linear_to_srgb, srgb_to_linear, ColourCorrection};
+pub use colour_stats::{ColourCount, ColourStats, Compliance, NamedCount};
+pub use command_archive::{
+    CanvasStats, CubeArchive, CubeArchiveError, EventMetadata, JournalEntry,
+};
+pub use command_limits::{
+    BudgetOverflow, CommandLimits, CommandRejected, CommandSettings, CommandUse, EditBudget,
+    EditBudgetConfig, VolumeCounter,
+};
+pub use command_queue::{
+    command_queue, CommandQueueConfig, CommandQueueError, CommandQueueMetrics, CommandSender,
+    OverflowPolicy,
+};
+pub use command_timing::{
+    CommandTimer, CommandTiming, CommandTimingConfig, CommandTimingMetrics, Stage, StageMetrics,
+};
+pub use competition::{parse_duration, Competition};
+pub use coordinates::{CoordinateSystem, Handedness, Origin, UpAxis};
+pub use credits::{contributors, render_credits, CreditsConfig};
+pub use cube::{Colour, Cube, CubeError, Position, Shape};
+pub use decay::{DecayConfig, DecayTracker};
+pub use event_log::{read_event_log, replay_delay, EventLog, EventLogConfig, EventLogError};
+pub use flat_renderer::FlatRenderer;
+pub use formats::{export_voxels, import_voxels, FormatError, VoxelFormat};
+pub use goal::{Goal, GoalConfig, GoalProgress};
+pub use grpc::{AppliedEvent, GrpcConfig, GrpcError, GrpcService, RenderKind};
+pub use http_server::{HttpConfig, HttpServer, HttpServerError, UploadError};
+pub use hud::{Hud, HudConfig, HudError, StreamInfo};
+pub use ipc::{IpcError, IpcListener, IpcMessage, IpcReceiver, IpcSender};
+pub use macros::{is_step_of, step_id, MacroConfig, MacroError, Macros};
+pub use markers::{MarkerConfig, MarkerMoment, StreamMarkers};
+pub use minimap::{ActivityHeat, Minimap, MinimapCorner};
+pub use mqtt::{MqttConfig, MqttError, MqttPublisher};
+pub use octree::{Octree, OctreeError};
+pub use onion_skin::{OnionSkin, OnionSkinConfig};
+pub use outbox::{ChatMessage, Outbox, OutboxConfig, Outgoing};
+pub use palette::{Palette, PaletteConfig, PaletteError, Quantization};
+pub use plugin::{
+    Caller, CanvasApi, CommandPlugin, FreePlugin, HistoryPlugin, LookupPlugin, PixelPlugin,
+    PluginError, PluginRegistry,
+};
+pub use poll::{Poll, PollConfig};
+pub use post_processing::{PostProcessingConfig, PostProcessingError, PostProcessor, Themes};
+pub use progression::{Progression, ProgressionConfig, ProgressionStage, Region};
+pub use protection::{format_region, parse_region, ProtectedRegions, ProtectionError};
+pub use quality::{AdaptiveQuality, QualityConfig};
+pub use raytracer::Raytracer;
+pub use renderer::Renderer;
+pub use replica::{ArchiveReplica, ReplicaError};
+pub use report::{BuilderSummary, Report, ReportError};
+pub use resize::{MergeColours, Resample, Resize, ResizeError, ResizeStrategy};
+pub use retention::{PruneReport, RetentionConfig};
+pub use schematic::{export_schematic, import_schematic, BlockPalette, SchematicError};
+pub use screensaver::{Screensaver, ScreensaverConfig, ScreensaverGame};
+pub use scripting::{ScriptAction, ScriptConfig, ScriptError, ScriptHost};
+pub use slice::{Axis, Slice, SliceConfig, SliceError, SliceFilter};
+pub use spin::{Spin, SpinConfig, SpinError};
+pub use stereo::{render_stereo, StereoMode};
+pub use summary::{webhook_body, SummaryConfig, SummaryError, SummaryTime};
+pub use teams::{draw_scoreboard, TeamConfig, TeamScore, TeamTracker, Teams};
+pub use templates::{CanvasTemplate, TemplateError};
+pub use terminal_renderer::TerminalRenderer;
+pub use terrain::generate_terrain;
+pub use timelapse::{render_timelapse, TimelapseConfig};
+pub use transform::{SceneTransform, SCENE_SIDE};
+pub use user_filter::{UserFilter, UserFilterConfig};
+pub use views::{ViewConfig, Views};
+pub use watermark::{Watermark, WatermarkConfig, WatermarkError};
+pub use weather::{EffectConfig, Weather, WeatherConfig, WeatherEffect, WeatherError};
+pub use web_login::{form_body, validated_login, DeviceCode, TokenPoll, WebLoginError, WebLogins};
+#[cfg(feature = "wgpu-renderer")]
+pub use wgpu_renderer::{WgpuRenderer, WgpuRendererError};
@@ -0,0 +1,272 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct MacroConfig {
+    /// How deep macros can use other macros.
+    pub max_depth: usize,
+    /// Most commands a single use of a macro expands to.
+    pub max_steps: usize,
+}
+
+impl Default for MacroConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: 4,
+            max_steps: 100,
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum MacroError {
+    #[error("write it as !alias name = command ; command ; ...")]
+    InvalidDefinition,
+    #[error("macro names are made of letters, digits and dashes")]
+    InvalidName,
+    #[error("!{0} is taken")]
+    Reserved(String),
+    #[error("!{0} needs at least {1} arguments")]
+    MissingArgument(String, usize),
+    #[error("!{0} uses other macros too deeply")]
+    TooDeep(String),
+    #[error("!{0} expands to more than {1} commands")]
+    TooLarge(String, usize),
+}
+
+/// Chat commands defined by moderators as a sequence of other commands,
+/// separated by `;`. In each step `$1` is replaced by the first argument of
+/// the macro, `$1+2` or `$1-2` by the first argument plus or minus 2, and so
+/// on, so macros can build relatively to a position.
+#[derive(Clone, Debug, Default)]
+pub struct Macros {
+    config: MacroConfig,
+    definitions: HashMap<String, String>,
+}
+
+impl Macros {
+    pub fn new(config: &MacroConfig, definitions: HashMap<String, String>) -> Self {
+        Self {
+            config: config.clone(),
+            definitions,
+        }
+    }
+
+    /// Parses `name = step ; step`, returns the name and the steps to save.
+    /// Names in `reserved` can't be defined.
+    pub fn define(
+        &mut self,
+        definition: &str,
+        reserved: &[&str],
+    ) -> Result<(String, String), MacroError> {
+        let (name, body) = definition
+            .split_once('=')
+            .ok_or(MacroError::InvalidDefinition)?;
+        let name = name.trim().trim_start_matches('!').to_lowercase();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(MacroError::InvalidName);
+        }
+        if reserved.contains(&name.as_str()) {
+            return Err(MacroError::Reserved(name));
+        }
+        let steps: Vec<&str> = body
+            .split(';')
+            .map(str::trim)
+            .filter(|step| !step.is_empty())
+            .collect();
+        if steps.is_empty() {
+            return Err(MacroError::InvalidDefinition);
+        }
+        let body = steps.join(" ; ");
+        self.definitions.insert(name.clone(), body.clone());
+        Ok((name, body))
+    }
+
+    /// Name of the removed macro, `None` if there was no such macro.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let name = name.trim().trim_start_matches('!').to_lowercase();
+        self.definitions.remove(&name).map(|_| name)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.definitions.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// The commands `text` stands for, `None` if it doesn't use a macro.
+    pub fn expand(&self, text: &str) -> Option<Result<Vec<String>, MacroError>> {
+        let name = text.trim().strip_prefix('!')?.split_whitespace().next()?;
+        if !self.definitions.contains_key(&name.to_lowercase()) {
+            return None;
+        }
+        let mut steps = Vec::new();
+        Some(
+            self.expand_into(text.trim(), name, 0, &mut steps)
+                .map(|_| steps),
+        )
+    }
+
+    fn expand_into(
+        &self,
+        text: &str,
+        root: &str,
+        depth: usize,
+        steps: &mut Vec<String>,
+    ) -> Result<(), MacroError> {
+        let mut words = text.split_whitespace();
+        let definition = words
+            .next()
+            .and_then(|word| word.strip_prefix('!'))
+            .and_then(|name| self.definitions.get(&name.to_lowercase()));
+        let definition = match definition {
+            Some(definition) => definition,
+            None => {
+                if steps.len() >= self.config.max_steps {
+                    return Err(MacroError::TooLarge(root.to_owned(), self.config.max_steps));
+                }
+                steps.push(text.to_owned());
+                return Ok(());
+            }
+        };
+        if depth >= self.config.max_depth {
+            return Err(MacroError::TooDeep(root.to_owned()));
+        }
+        let args: Vec<&str> = words.collect();
+        for step in definition.split(';') {
+            let step = substitute(step, &args)
+                .map_err(|needed| MacroError::MissingArgument(root.to_owned(), needed))?;
+            self.expand_into(&step, root, depth + 1, steps)?;
+        }
+        Ok(())
+    }
+}
+
+// Replaces the `$n`, `$n+k` and `$n-k` words of `step`, fails with the number
+// of arguments needed when one is missing.
+fn substitute(step: &str, args: &[&str]) -> Result<String, usize> {
+    let words: Result<Vec<String>, usize> = step
+        .split_whitespace()
+        .map(|word| {
+            let reference = match word.strip_prefix('$') {
+                Some(reference) => reference,
+                None => return Ok(word.to_owned()),
+            };
+            let split = reference.find(['+', '-']).unwrap_or(reference.len());
+            let (index, offset) = reference.split_at(split);
+            let index: usize = match index.parse() {
+                Ok(index) if index > 0 => index,
+                _ => return Ok(word.to_owned()),
+            };
+            let arg = *args.get(index - 1).ok_or(index)?;
+            match (offset.parse::<i64>(), arg.parse::<i64>()) {
+                (Ok(offset), Ok(value)) => Ok((value + offset).to_string()),
+                _ if offset.is_empty() => Ok(arg.to_owned()),
+                // Only numbers can be shifted.
+                _ => Ok(word.to_owned()),
+            }
+        })
+        .collect();
+    Ok(words?.join(" "))
+}
+
+/// Id of the command of the step `index` of a chat message of id `base`, e.g.
+/// of a macro or of a plugin. The first step keeps the id of the message, so
+/// that a single command is journaled once however often it's delivered.
+pub fn step_id(base: Uuid, index: usize) -> Uuid {
+    Uuid::from_u128(base.as_u128() ^ index as u128)
+}
+
+/// Whether `id` is the id of one of the steps of the message `base`, see
+/// `step_id`. Steps only differ from the message in their lowest 32 bits,
+/// which are random in message ids.
+pub fn is_step_of(id: Uuid, base: Uuid) -> bool {
+    (id.as_u128() ^ base.as_u128()) >> 32 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_ids() {
+        let base = Uuid::new_v4();
+        let steps: Vec<Uuid> = (0..100).map(|index| step_id(base, index)).collect();
+        assert_eq!(steps[0], base);
+        assert!(steps.iter().all(|&id| is_step_of(id, base)));
+        let mut distinct = steps.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), steps.len());
+        assert!(!is_step_of(Uuid::new_v4(), base));
+    }
+
+    #[test]
+    fn test_expand() {
+        let mut macros = Macros::new(&MacroConfig::default(), HashMap::new());
+        assert_eq!(
+            macros.define("!trunk = $1 $2 $3 120 80 40 ; $1 $2-1 $3 120 80 40", &[]),
+            Ok((
+                "trunk".to_owned(),
+                "$1 $2 $3 120 80 40 ; $1 $2-1 $3 120 80 40".to_owned()
+            ))
+        );
+        macros
+            .define("Tree = !trunk $1 $2 $3 ; !px $1 $2-2 $4", &[])
+            .unwrap();
+        assert_eq!(macros.expand("hello"), None);
+        assert_eq!(macros.expand("!vote clear"), None);
+        assert_eq!(
+            macros.expand("!tree 5 10 2 green"),
+            Some(Ok(vec![
+                "5 10 2 120 80 40".to_owned(),
+                "5 9 2 120 80 40".to_owned(),
+                "!px 5 8 green".to_owned(),
+            ]))
+        );
+        assert_eq!(
+            macros.expand("!tree 5 10"),
+            Some(Err(MacroError::MissingArgument("tree".to_owned(), 3)))
+        );
+        assert_eq!(macros.names(), vec!["tree", "trunk"]);
+        assert_eq!(macros.remove("!Trunk"), Some("trunk".to_owned()));
+        assert_eq!(macros.remove("trunk"), None);
+    }
+
+    #[test]
+    fn test_limits() {
+        let config = MacroConfig {
+            max_depth: 4,
+            max_steps: 5,
+        };
+        let mut macros = Macros::new(&config, HashMap::new());
+        assert_eq!(
+            macros.define("vote = 1 2 3 0 0 0", &["vote"]),
+            Err(MacroError::Reserved("vote".to_owned()))
+        );
+        assert_eq!(macros.define("a b = 1", &[]), Err(MacroError::InvalidName));
+        assert_eq!(
+            macros.define("a =  ; ", &[]),
+            Err(MacroError::InvalidDefinition)
+        );
+        // Defining a macro in terms of itself never ends.
+        macros.define("loop = !loop", &[]).unwrap();
+        assert_eq!(
+            macros.expand("!loop"),
+            Some(Err(MacroError::TooDeep("loop".to_owned())))
+        );
+        macros
+            .define("two = 1 1 1 0 0 0 ; 2 2 2 0 0 0", &[])
+            .unwrap();
+        macros.define("four = !two ; !two", &[]).unwrap();
+        assert_eq!(macros.expand("!four").unwrap().unwrap().len(), 4);
+        macros.define("eight = !four ; !four", &[]).unwrap();
+        assert_eq!(
+            macros.expand("!eight"),
+            Some(Err(MacroError::TooLarge("eight".to_owned(), 5)))
+        );
+    }
+}
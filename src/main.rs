@@ -3,30 +3,216 @@ mod token_storage;
 extern crate kiss3d;
 extern crate nalgebra as na;
 
+use chrono::TimeZone;
 use image::RgbImage;
+use kiss3d::camera::ArcBall;
 use kiss3d::light::Light;
+use kiss3d::scene::SceneNode;
+use kiss3d::text::Font;
 use kiss3d::window::Window;
-use log::{debug, trace, LevelFilter};
-use na::Translation3;
-use serde::Deserialize;
+use log::{debug, info, trace, warn, LevelFilter};
+use na::{Point2, Point3, Translation3, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
 use simple_logger::SimpleLogger;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use structopt::StructOpt;
 use tempfile::tempdir;
 use token_storage::CustomTokenStorage;
 use tokio::sync::mpsc;
 use twitch_api2::twitch_oauth2::Scope;
 use twitch_irc::login::{RefreshingLoginCredentials, TokenStorage};
-use twitch_irc::message::ServerMessage;
+use twitch_irc::message::{ClearChatAction, PrivmsgMessage, ServerMessage};
 use twitch_irc::{ClientConfig, TCPTransport, TwitchIRCClient};
-use twixelbox_bot::Cube;
-use twixelbox_bot::CubeArchive;
+use twixelbox_bot::Announcements;
+use twixelbox_bot::ArchiveReplica;
+use twixelbox_bot::ColourCorrection;
+use twixelbox_bot::ColourStats;
+use twixelbox_bot::JournalEntry;
+use twixelbox_bot::Octree;
+use twixelbox_bot::Report;
+use twixelbox_bot::Shape;
+#[cfg(feature = "wgpu-renderer")]
+use twixelbox_bot::WgpuRenderer;
+use twixelbox_bot::{
+    command_queue, CommandQueueConfig, CommandQueueError, CommandSender, OverflowPolicy,
+};
+use twixelbox_bot::{draw_scoreboard, TeamConfig, TeamTracker, Teams};
+use twixelbox_bot::{export_voxels, import_voxels, VoxelFormat};
+use twixelbox_bot::{form_body, validated_login, DeviceCode, TokenPoll, WebLoginError, WebLogins};
+use twixelbox_bot::{format_region, parse_region, ProtectedRegions};
+use twixelbox_bot::{generate_terrain, Canvas, CanvasEvent, CanvasTemplate, ChatCommand};
+use twixelbox_bot::{is_step_of, step_id, MacroConfig, Macros};
+use twixelbox_bot::{parse_duration, CanvasStats, Competition, CubeArchive, EventMetadata};
+use twixelbox_bot::{read_event_log, replay_delay, EventLog, EventLogConfig};
+use twixelbox_bot::{render_camera_path, CameraPath, CameraPose};
+use twixelbox_bot::{render_credits, CreditsConfig};
+use twixelbox_bot::{render_stereo, StereoMode};
+use twixelbox_bot::{render_timelapse, HttpConfig, HttpServer, TimelapseConfig};
+use twixelbox_bot::{webhook_body, SummaryConfig, SummaryTime};
+use twixelbox_bot::{AccessConfig, ApiAccess, ApiAudit, Permission};
+use twixelbox_bot::{Achievement, AchievementConfig, AchievementTracker};
+use twixelbox_bot::{ActivityHeat, Minimap, MinimapCorner};
+use twixelbox_bot::{AdaptiveQuality, QualityConfig};
+use twixelbox_bot::{AppliedEvent, GrpcConfig, GrpcService, RenderKind};
+use twixelbox_bot::{AuxiliaryBuffer, AuxiliaryBuffers};
+use twixelbox_bot::{Axis, Slice, SliceConfig, SliceFilter};
+use twixelbox_bot::{Background, BackgroundConfig};
+use twixelbox_bot::{BudgetOverflow, EditBudget, EditBudgetConfig};
+use twixelbox_bot::{BuildSheet, Palette, PaletteConfig, Projection, Quantization};
+use twixelbox_bot::{Caller, CanvasApi, FreePlugin, HistoryPlugin, LookupPlugin};
+use twixelbox_bot::{ChatMessage, Outbox, OutboxConfig, Outgoing};
+use twixelbox_bot::{Colour, CoordinateSystem, Cube, FlatRenderer, Position, SceneTransform};
+use twixelbox_bot::{CommandLimits, CommandSettings, CommandUse, VolumeCounter};
+use twixelbox_bot::{CommandTimer, CommandTiming, CommandTimingConfig, Stage};
+use twixelbox_bot::{DecayConfig, DecayTracker};
+use twixelbox_bot::{Goal, GoalConfig, GoalProgress};
+use twixelbox_bot::{Hud, HudConfig, StreamInfo};
+use twixelbox_bot::{IpcListener, IpcMessage, IpcSender};
+use twixelbox_bot::{MarkerConfig, MarkerMoment, StreamMarkers};
+use twixelbox_bot::{MqttConfig, MqttPublisher};
+use twixelbox_bot::{OnionSkin, OnionSkinConfig};
+use twixelbox_bot::{PixelPlugin, PluginRegistry};
+use twixelbox_bot::{Poll, PollConfig};
+use twixelbox_bot::{PostProcessingConfig, PostProcessor, Themes};
+use twixelbox_bot::{Progression, ProgressionConfig, Region};
+use twixelbox_bot::{PruneReport, RetentionConfig};
+use twixelbox_bot::{Raytracer, Renderer, TerminalRenderer};
+use twixelbox_bot::{Screensaver, ScreensaverConfig};
+use twixelbox_bot::{ScriptAction, ScriptConfig, ScriptError, ScriptHost};
+use twixelbox_bot::{Spin, SpinConfig};
+use twixelbox_bot::{UserFilter, UserFilterConfig};
+use twixelbox_bot::{ViewConfig, Views};
+use twixelbox_bot::{Watermark, WatermarkConfig};
+use twixelbox_bot::{Weather, WeatherConfig, WeatherEffect};
+use uuid::Uuid;
 
 #[derive(Clone, Deserialize)]
 struct TwixelBoxBotConfig {
     twitch: TwitchConfig,
     twixelbox: TwixelBoxConfig,
+    #[serde(default)]
+    ipc: IpcConfig,
+    #[serde(default)]
+    snapshot: SnapshotConfig,
+    #[serde(default)]
+    command_queue: CommandQueueConfig,
+    // Time spent by the commands in each stage, and what counts as slow.
+    #[serde(default)]
+    timing: CommandTimingConfig,
+    #[serde(default)]
+    votes: PollConfig,
+    // Teams chatters can join with `!team <name>`, none disables team mode.
+    #[serde(default)]
+    teams: Vec<TeamConfig>,
+    #[serde(default)]
+    decay: DecayConfig,
+    // Pruning of the old events of the archive, so that it doesn't grow
+    // forever on channels that never go offline.
+    #[serde(default)]
+    retention: RetentionConfig,
+    #[serde(default)]
+    http: HttpConfig,
+    // Same as the HTTP API for backend services, plus a live feed of events.
+    #[serde(default)]
+    grpc: GrpcConfig,
+    // Mirror of the canvas for LED cubes and other displays.
+    #[serde(default)]
+    mqtt: MqttConfig,
+    // Every journaled event as a JSON line, for analytics pipelines and to
+    // rebuild the archive.
+    #[serde(default)]
+    event_log: EventLogConfig,
+    // Stream markers on notable moments of the canvas, to find them in VODs.
+    #[serde(default)]
+    markers: MarkerConfig,
+    // Animated GIF of the recent events, linked with `!today`.
+    #[serde(default)]
+    timelapse: TimelapseConfig,
+    // End of stream credits, rolled with `!credits`.
+    #[serde(default)]
+    credits: CreditsConfig,
+    // What the bot says in chat, by announcement.
+    #[serde(default)]
+    announcements: Announcements,
+    // How fast the bot talks in chat.
+    #[serde(default)]
+    outbox: OutboxConfig,
+    // Who can place cubes.
+    #[serde(default)]
+    users: UserFilterConfig,
+    #[serde(default)]
+    moderation: ModerationConfig,
+    // Settings of each chat command by name, e.g. `place` or `vote`.
+    #[serde(default)]
+    commands: HashMap<String, CommandSettings>,
+    #[serde(default)]
+    stats: StatsConfig,
+    // Animation shown on the overlay while chat is quiet.
+    #[serde(default)]
+    screensaver: ScreensaverConfig,
+    // Views of the inside of the canvas with `!slice`.
+    #[serde(default)]
+    slice: SliceConfig,
+    #[serde(default)]
+    shoutout: ShoutoutConfig,
+    // Regions of the canvas unlocked as chat places cubes.
+    #[serde(default)]
+    progression: ProgressionConfig,
+    // Colours chat builds with, set from an image uploaded by a moderator.
+    #[serde(default)]
+    palette: PaletteConfig,
+    // Chat commands defined by moderators with `!alias`.
+    #[serde(default)]
+    macros: MacroConfig,
+    // Custom chat commands written by the streamer.
+    #[serde(default)]
+    scripts: ScriptConfig,
+    // Axes and origin of the coordinates given in chat and to the importers.
+    #[serde(default)]
+    coordinates: CoordinateSystem,
+    // Orbit of the overlay around the canvas, changed with `!spin`.
+    #[serde(default)]
+    spin: SpinConfig,
+    // Camera presets of the overlay, switched to with `!view`.
+    #[serde(default)]
+    views: ViewConfig,
+    // Snow, rain or particles over the overlay, changed with `!weather`.
+    #[serde(default)]
+    weather: WeatherConfig,
+    // Ghosts of the cubes removed or overwritten lately, on the overlay.
+    #[serde(default)]
+    onion_skin: OnionSkinConfig,
+    // Cubes chat can change every minute, all chatters together.
+    #[serde(default)]
+    edit_budget: EditBudgetConfig,
+    // Lower quality while the overlay can't keep up.
+    #[serde(default)]
+    quality: QualityConfig,
+    #[serde(default)]
+    drift: DriftConfig,
+    // Glowing cubes, placed with a channel point reward or a cheer.
+    #[serde(default)]
+    glow: GlowConfig,
+    // Logo over the snapshots and the animations.
+    #[serde(default)]
+    watermark: WatermarkConfig,
+    // Clock and stream info over the live overlay.
+    #[serde(default)]
+    hud: HudConfig,
+    // Cubes for chat to place each day.
+    #[serde(default)]
+    goal: GoalConfig,
+    // Milestones of the chatters, announced in chat.
+    #[serde(default)]
+    achievements: AchievementConfig,
+    // Summaries of the canvas posted on schedule, between streams.
+    #[serde(default)]
+    summary: SummaryConfig,
 }
 
 #[derive(Clone, Deserialize)]
@@ -43,6 +229,245 @@ struct TwixelBoxConfig {
     window_resolution: u32,
     cube_size: u32,
     img_filepath: String,
+    #[serde(default)]
+    renderer: RendererBackend,
+    // Width of the terminal preview, in characters.
+    #[serde(default = "default_terminal_columns")]
+    terminal_columns: u32,
+    #[serde(default)]
+    post_processing: PostProcessingConfig,
+    // Post-processing of the overlay chat can vote for by name, e.g. with
+    // `!vote theme night`, the one above being the `default` theme.
+    #[serde(default)]
+    themes: HashMap<String, PostProcessingConfig>,
+    // Builds on the z = 0 plane only, with `!px x y colour`, and draws it
+    // flat instead of with the configured renderer.
+    #[serde(default)]
+    pixel_art: bool,
+    // Gamma, brightness and contrast of the cubes on the overlay and the
+    // snapshots.
+    #[serde(default)]
+    colour_correction: ColourCorrection,
+    // Behind the canvas, on the overlay and the snapshots.
+    #[serde(default)]
+    background: BackgroundConfig,
+}
+
+fn default_terminal_columns() -> u32 {
+    80
+}
+
+// Cubes the canvas holds, on a single plane in pixel art.
+fn canvas_volume(config: &TwixelBoxConfig) -> u64 {
+    let side = config.cube_size as u64;
+    match config.pixel_art {
+        true => side * side,
+        false => side * side * side,
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum RendererBackend {
+    #[default]
+    Kiss3d,
+    // Only available when built with the `wgpu-renderer` feature.
+    Wgpu,
+    // Coarse preview drawn on the terminal, for servers without a GPU.
+    Terminal,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum IpcMode {
+    // Chat handling and rendering in the same process.
+    Standalone,
+    // Only handle chat and the archive, forward placements to a renderer.
+    Bot,
+    // Only render, placements are received from a bot.
+    Renderer,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+struct IpcConfig {
+    mode: IpcMode,
+    // Address the renderer listens on and the bot connects to.
+    address: String,
+    // Shared by the bot and the renderer, which only accepts a bot
+    // introducing itself with it. Required to listen beyond the loopback
+    // interface.
+    token: String,
+}
+
+impl Default for IpcConfig {
+    fn default() -> Self {
+        IpcConfig {
+            mode: IpcMode::Standalone,
+            address: "127.0.0.1:10667".to_owned(),
+            token: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+struct StatsConfig {
+    // Minutes between two announcements of the canvas statistics in chat,
+    // 0 to never announce them.
+    announce_interval_mins: u64,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        StatsConfig {
+            announce_interval_mins: 30,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+struct DriftConfig {
+    // Minutes between two checks of the overlay against the archive, 0 to
+    // never check.
+    check_interval_mins: u64,
+    // Seconds between two checks for events journaled by other processes,
+    // e.g. populate_from_csv, 0 to never check.
+    external_poll_secs: u64,
+}
+
+impl Default for DriftConfig {
+    fn default() -> Self {
+        DriftConfig {
+            check_interval_mins: 10,
+            external_poll_secs: 5,
+        }
+    }
+}
+
+// What happens to the cubes of banned chatters, or placed by deleted messages.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ModerationAction {
+    // The cubes stay on the canvas.
+    Keep,
+    Remove,
+    // The cubes are removed, but kept aside so that moderators can bring
+    // them back with `!restore <user>`.
+    Quarantine,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+struct ModerationConfig {
+    on_ban: ModerationAction,
+    on_message_deleted: ModerationAction,
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        ModerationConfig {
+            on_ban: ModerationAction::Quarantine,
+            on_message_deleted: ModerationAction::Remove,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+struct ShoutoutConfig {
+    // Seconds the cubes of the chatter stay highlighted after a `!so`.
+    highlight_secs: u64,
+}
+
+impl Default for ShoutoutConfig {
+    fn default() -> Self {
+        ShoutoutConfig { highlight_secs: 30 }
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+struct GlowConfig {
+    // Id of the channel point reward whose placements glow.
+    reward_id: Option<String>,
+    // Bits a placement has to be cheered with to glow, none for cheers to
+    // never make cubes glow.
+    min_bits: Option<u64>,
+}
+
+impl GlowConfig {
+    // Whether the cube placed by the message was paid for to glow.
+    fn paid(&self, msg: &PrivmsgMessage) -> bool {
+        let redeemed =
+            self.reward_id.is_some() && self.reward_id.as_deref() == redeemed_reward(msg);
+        let cheered = match (self.min_bits, msg.bits) {
+            (Some(min_bits), Some(bits)) => bits >= min_bits,
+            _ => false,
+        };
+        redeemed || cheered
+    }
+}
+
+// The message without its cheermotes, e.g. `Cheer100`, which come first or
+// anywhere in the text of cheers.
+fn without_cheermotes(text: &str) -> String {
+    let is_cheermote = |word: &&str| {
+        let prefix = word.trim_end_matches(|c: char| c.is_ascii_digit());
+        !prefix.is_empty()
+            && prefix.len() < word.len()
+            && prefix.chars().all(|c| c.is_ascii_alphabetic())
+    };
+    text.split_whitespace()
+        .filter(|word| !is_cheermote(word))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+struct SnapshotConfig {
+    // Where the raytraced beauty shots are saved.
+    filepath: String,
+    resolution: u32,
+    // Rays traced per pixel, more gives smoother images but slower renders.
+    samples: u32,
+    // If set, a beauty shot is also rendered every `interval_hours`.
+    interval_hours: Option<u64>,
+    post_processing: PostProcessingConfig,
+    // Renders both eyes, side by side or as a red-cyan anaglyph.
+    stereo: Option<StereoMode>,
+    // Distance between the eyes, as a share of the distance to the canvas.
+    eye_separation: f32,
+    // Depth, normal or cube id maps saved next to the snapshot and served
+    // over HTTP, for compositing elsewhere.
+    auxiliary: Vec<AuxiliaryBuffer>,
+    // Corner of the top-down minimap, with the field of view of the camera.
+    minimap: Option<MinimapCorner>,
+    // Side of the minimap, as a share of the height of the snapshot.
+    minimap_size: f32,
+    // If set, the minimap shows where chat edited lately, each edit counting
+    // half as much every `minimap_heat_mins`.
+    minimap_heat_mins: Option<f32>,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        SnapshotConfig {
+            filepath: "twixelbox-snapshot.png".to_owned(),
+            resolution: 1080,
+            samples: 16,
+            interval_hours: None,
+            post_processing: PostProcessingConfig::default(),
+            stereo: None,
+            eye_separation: 0.03,
+            auxiliary: Vec::new(),
+            minimap: None,
+            minimap_size: 0.25,
+            minimap_heat_mins: None,
+        }
+    }
 }
 
 // Command-line arguments for the tool.
@@ -55,121 +480,831 @@ struct Cli {
     /// Twitch credential files.
     #[structopt(short, long, default_value = "twixelbox-bot.toml")]
     config_file: String,
+
+    /// Only takes the placements from chat and persists them, without a
+    /// window nor snapshots, e.g. when another machine shows the canvas.
+    #[structopt(long)]
+    no_render: bool,
+
+    #[structopt(subcommand)]
+    command: Option<CliCommand>,
+}
+
+#[derive(StructOpt)]
+enum CliCommand {
+    /// Starts a fresh archive from a starter canvas, sized after the
+    /// configuration.
+    Init {
+        /// border, an empty canvas framed by its edges, terrain, hills to
+        /// build on, or grid, guide lines on the ground.
+        #[structopt(long, default_value = "border")]
+        template: CanvasTemplate,
+
+        /// Seed of the terrain, a random one if not given.
+        #[structopt(long)]
+        seed: Option<u64>,
+    },
+    /// Journals the events of the event log and its rotated files into the
+    /// archive, all at once to rebuild it after losing the database, or paced
+    /// with --speed into the archive of a running bot, which draws them as
+    /// they come. Events already in the archive are skipped.
+    Replay {
+        /// Event log to replay, the configured one if not given.
+        #[structopt(long)]
+        path: Option<String>,
+
+        /// Plays the events this many times faster than they happened.
+        #[structopt(long)]
+        speed: Option<f32>,
+
+        /// Longest wait between two paced events, in seconds.
+        #[structopt(long, default_value = "5")]
+        max_wait: f32,
+
+        /// Journals the events under new command ids, so that those already
+        /// in the archive play again.
+        #[structopt(long)]
+        new_ids: bool,
+    },
+    /// Renders a flythrough of the archived canvas along a camera path, a
+    /// JSON file of keyframes, to an animated GIF.
+    RenderPath {
+        /// JSON file of the keyframes, e.g.
+        /// {"keyframes": [{"time": 0, "position": [x, y, z], "target": [x, y, z]}]}
+        /// with the position of the camera and what it looks at on the grid
+        /// of the canvas, y growing downwards.
+        path: String,
+
+        #[structopt(long, default_value = "flythrough.gif")]
+        output: String,
+
+        #[structopt(long, default_value = "20")]
+        fps: f32,
+
+        /// Side of the frames in pixels, that of snapshots if not given.
+        #[structopt(long)]
+        resolution: Option<u32>,
+
+        /// Rays traced per pixel, as many as for snapshots if not given.
+        #[structopt(long)]
+        samples: Option<u32>,
+    },
+    /// Writes an HTML report of the archive: the placements over time, the
+    /// colours used, how many were in the palette and a table of the
+    /// builders.
+    Report {
+        #[structopt(long, default_value = "report.html")]
+        output: String,
+
+        /// Only the events of this last period, e.g. 3h or 168h, the whole
+        /// archive if not given.
+        #[structopt(long)]
+        since: Option<String>,
+
+        /// Period the placements are counted by, e.g. 15m or 24h.
+        #[structopt(long, default_value = "1h")]
+        bucket: String,
+
+        /// Colours of the palette the cubes were meant to be in, e.g.
+        /// "red #ffffff #202020", to chart how many were.
+        #[structopt(long)]
+        palette: Option<String>,
+    },
 }
 
-struct Canvas {
-    frame_side_len: u32,
+// Where the canvas is journaled.
+const ARCHIVE_FILEPATH: &str = "cube_archive.db";
+
+// Renders the canvas in a kiss3d window.
+struct Kiss3dRenderer {
+    window: Window,
+    window_size_pixels: u32,
+    transform: SceneTransform,
+    cubes: HashMap<Position, SceneNode>,
+    // Outline of the buildable region. kiss3d can't draw translucent
+    // surfaces, so the fog is left out and only its inner boundary is drawn.
+    fog: Option<SceneNode>,
+    // Cubes removed or overwritten lately. Without translucency, they're
+    // drawn in their colour faded into the background, as an outline around
+    // the cube which overwrote them.
+    ghosts: Vec<SceneNode>,
+    yaw: f32,
+    // Replaces kiss3d's default camera and the yaw, see Renderer::set_camera.
+    camera: Option<CameraPose>,
+    arc_ball: ArcBall,
+    // Text drawn above the cube at the position, e.g. who placed it.
+    label: Option<(Position, String)>,
+    correction: ColourCorrection,
+    // Clear colour of the window, translucent cubes and ghosts are faded into
+    // it.
+    background: (f32, f32, f32),
 }
 
-impl Canvas {
-    fn add_cube(
-        &mut self,
-        window: &mut Window,
-        x: u32,
-        y: u32,
-        z: u32,
-        r: u8,
-        g: u8,
-        b: u8,
-    ) -> kiss3d::scene::SceneNode {
-        // TODO: what if the cube already exists? store all the cubes and if it already exists only
-        // set_color on existing cube.
-        // TODO: check x, y, z < frame_side_len or bail out
-
-        let voxel_side_len = 1.0 / self.frame_side_len as f32;
-        let mut voxel = window.add_cube(voxel_side_len, voxel_side_len, voxel_side_len);
-        voxel.set_color(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
-        // x = [0.25 (leftmost), -0.25 (rightmost)]
-        // y = [0.25 (upmost), -0.25 (downmost)]
-        // z = [0.25 (backmost), -0.25 (frontmost)]
-
-        let x =
-            ((self.frame_side_len as f32 - x as f32) / (self.frame_side_len as f32 / 0.5)) - 0.25;
-        let y =
-            ((self.frame_side_len as f32 - y as f32) / (self.frame_side_len as f32 / 0.5)) - 0.25;
-        let z =
-            ((self.frame_side_len as f32 - z as f32) / (self.frame_side_len as f32 / 0.5)) - 0.25;
-        voxel.append_translation(&Translation3::new(x, y, z));
-        voxel
-    }
-
-    // TODO: do we need to add a remove_cube?
-}
-
-#[derive(Debug)]
-enum Command {
-    Render,
-    AddCube(Cube),
+// Height of the label text, in pixels.
+const LABEL_SIZE: f32 = 40.0;
+// Side of the outline of an overwritten cube, relative to the cube.
+const GHOST_OUTLINE_SCALE: f32 = 1.15;
+
+// Blends `colour` with the background, kiss3d drawing no translucent surfaces.
+fn faded(
+    (r, g, b): (f32, f32, f32),
+    opacity: f32,
+    (bg_r, bg_g, bg_b): (f32, f32, f32),
+) -> (f32, f32, f32) {
+    let fade = |colour: f32, background: f32| colour * opacity + background * (1.0 - opacity);
+    (fade(r, bg_r), fade(g, bg_g), fade(b, bg_b))
 }
 
-#[derive(Debug)]
-struct ChatCommand {
-    x: u32,
-    y: u32,
-    z: u32,
-    r: u8,
-    g: u8,
-    b: u8,
+// Adds the node of `shape`, in a cell of side `side` centred on `centre` of the
+// scene, whose axes go the other way from those of the grid.
+fn add_shape(window: &mut Window, shape: Shape, side: f32, [x, y, z]: [f32; 3]) -> SceneNode {
+    let (low, high) = shape.bounds();
+    let mut node = match shape {
+        Shape::Sphere => window.add_sphere(Shape::SPHERE_RADIUS * side),
+        _ => window.add_cube(
+            (high[0] - low[0]) * side,
+            (high[1] - low[1]) * side,
+            (high[2] - low[2]) * side,
+        ),
+    };
+    let offset = |axis: usize| -(low[axis] + high[axis]) / 2.0 * side;
+    node.append_translation(&Translation3::new(
+        x + offset(0),
+        y + offset(1),
+        z + offset(2),
+    ));
+    node
 }
 
-impl FromStr for ChatCommand {
-    type Err = &'static str;
+impl Kiss3dRenderer {
+    fn new(window_size_pixels: u32, frame_side_len: u32, correction: ColourCorrection) -> Self {
+        let mut window =
+            Window::new_with_size("Kiss3d: points", window_size_pixels, window_size_pixels);
 
-    fn from_str(value: &str) -> Result<Self, Self::Err> {
-        let r: Result<Vec<_>, _> = value.split(' ').map(|v| v.parse::<u32>()).collect();
-        match r {
-            Ok(v) => {
-                if v.len() != 6usize {
-                    return Err("too many args");
-                }
-                if v[3] > 255 || v[4] > 255 || v[5] > 255 {
-                    return Err("invalid r g b");
-                }
-                Ok(ChatCommand {
-                    x: v[0],
-                    y: v[1],
-                    z: v[2],
-                    r: v[3] as u8,
-                    g: v[4] as u8,
-                    b: v[5] as u8,
-                })
+        // TODO: fake the wireframe so that diagonals are not rendered.
+        let mut c = window.add_cube(0.5, 0.5, 0.5);
+
+        c.set_color(0.99, 0.99, 0.99);
+        c.set_points_size(10.0);
+        c.set_lines_width(0.1);
+        c.set_surface_rendering_activation(false);
+
+        window.set_light(Light::StickToCamera);
+        let background = Background::default().clear_colour().to_f32();
+        let (r, g, b) = background;
+        window.set_background_color(r, g, b);
+
+        Kiss3dRenderer {
+            window,
+            window_size_pixels,
+            transform: SceneTransform::new(frame_side_len),
+            cubes: HashMap::new(),
+            fog: None,
+            ghosts: Vec::new(),
+            yaw: 0.0,
+            camera: None,
+            arc_ball: ArcBall::new(Point3::new(0.0, 0.0, -1.0), Point3::origin()),
+            label: None,
+            correction,
+            background,
+        }
+    }
+
+    // Draws the label for the next frame, just above its cube.
+    fn draw_label(&mut self) {
+        let (position, text) = match &self.label {
+            Some(label) => label,
+            None => return,
+        };
+        let above = [
+            position.x as f32,
+            position.y as f32 - 1.0,
+            position.z as f32,
+        ];
+        let projected = match &self.camera {
+            Some(camera) => camera.project(above),
+            None => self.transform.project(above, self.yaw),
+        };
+        if let Some((x, y)) = projected {
+            let (width, height) = (self.window.width() as f32, self.window.height() as f32);
+            self.window.draw_text(
+                text,
+                &Point2::new(x * width, y * height - LABEL_SIZE),
+                LABEL_SIZE,
+                &Font::default(),
+                &Point3::new(0.0, 0.0, 0.0),
+            );
+        }
+    }
+}
+
+impl Renderer for Kiss3dRenderer {
+    fn add_cube(&mut self, cube: &Cube) {
+        // A cube placed where another one already exists replaces it.
+        if let Some(mut existing) = self.cubes.remove(&cube.position) {
+            self.window.remove_node(&mut existing);
+        }
+        let centre = match self.transform.scene_position(cube.position) {
+            Some(centre) => centre,
+            None => return,
+        };
+        let voxel_side_len = self.transform.cube_side();
+        let mut voxel = add_shape(&mut self.window, cube.shape(), voxel_side_len, centre);
+        // Without translucency, translucent cubes are faded into the
+        // background as much as they're transparent.
+        let (r, g, b) = faded(
+            self.correction.srgb(cube.colour),
+            cube.alpha() as f32 / 255.0,
+            self.background,
+        );
+        voxel.set_color(r, g, b);
+        self.cubes.insert(cube.position, voxel);
+    }
+
+    fn remove_cube(&mut self, position: Position) {
+        if let Some(mut existing) = self.cubes.remove(&position) {
+            self.window.remove_node(&mut existing);
+        }
+    }
+
+    fn clear(&mut self) {
+        for (_, mut existing) in self.cubes.drain() {
+            self.window.remove_node(&mut existing);
+        }
+    }
+
+    fn set_fog(&mut self, unlocked: Option<Region>) {
+        if let Some(mut existing) = self.fog.take() {
+            self.window.remove_node(&mut existing);
+        }
+        let region = match unlocked {
+            Some(region) => region,
+            None => return,
+        };
+        let transform = self.transform;
+        let ([min_x, min_y, min_z], [max_x, max_y, max_z]) = match (
+            transform.scene_position(region.min),
+            transform.scene_position(region.max),
+        ) {
+            (Some(min), Some(max)) => (min, max),
+            // Stages bigger than the canvas.
+            _ => return,
+        };
+        let side = region.side() as f32 * self.transform.cube_side();
+        let mut outline = self.window.add_cube(side, side, side);
+        outline.set_color(0.5, 0.5, 0.5);
+        outline.set_lines_width(1.0);
+        outline.set_surface_rendering_activation(false);
+        outline.append_translation(&Translation3::new(
+            (min_x + max_x) / 2.0,
+            (min_y + max_y) / 2.0,
+            (min_z + max_z) / 2.0,
+        ));
+        self.fog = Some(outline);
+    }
+
+    fn set_yaw(&mut self, yaw: f32) {
+        self.yaw = yaw;
+    }
+
+    fn set_camera(&mut self, camera: Option<CameraPose>) {
+        self.camera = camera;
+    }
+
+    // Cleared with a single colour, the cubes already drawn keep the fading
+    // into the previous one.
+    fn set_background(&mut self, background: &Background) {
+        self.background = background.clear_colour().to_f32();
+        let (r, g, b) = self.background;
+        self.window.set_background_color(r, g, b);
+    }
+
+    fn hovered(&self) -> Option<Position> {
+        let (x, y) = self.window.cursor_pos()?;
+        let (width, height) = (self.window.width() as f32, self.window.height() as f32);
+        let (x, y) = (x as f32 / width, y as f32 / height);
+        let is_filled = |position| self.cubes.contains_key(&position);
+        match &self.camera {
+            Some(camera) => {
+                let (origin, dir) = camera.ray(x, y);
+                self.transform.pick_ray(origin, dir, is_filled)
+            }
+            None => self.transform.pick(x, y, self.yaw, is_filled),
+        }
+    }
+
+    fn set_label(&mut self, label: Option<(Position, String)>) {
+        self.label = label;
+    }
+
+    fn set_ghosts(&mut self, ghosts: &[(Cube, f32)]) {
+        for mut existing in self.ghosts.drain(..) {
+            self.window.remove_node(&mut existing);
+        }
+        for (cube, opacity) in ghosts {
+            let centre = match self.transform.scene_position(cube.position) {
+                Some(centre) => centre,
+                None => continue,
+            };
+            let overwritten = self.cubes.contains_key(&cube.position);
+            let side = match overwritten {
+                true => self.transform.cube_side() * GHOST_OUTLINE_SCALE,
+                false => self.transform.cube_side(),
+            };
+            let mut ghost = add_shape(&mut self.window, cube.shape(), side, centre);
+            let opacity = opacity * cube.alpha() as f32 / 255.0;
+            let (r, g, b) = faded(self.correction.srgb(cube.colour), opacity, self.background);
+            ghost.set_color(r, g, b);
+            if overwritten {
+                ghost.set_lines_width(2.0);
+                ghost.set_surface_rendering_activation(false);
+            }
+            self.ghosts.push(ghost);
+        }
+    }
+
+    fn render(&mut self) -> Option<RgbImage> {
+        let mut v = Vec::new();
+        self.draw_label();
+        match self.camera {
+            Some(camera) => {
+                self.window
+                    .scene_mut()
+                    .set_local_rotation(UnitQuaternion::identity());
+                let point = |p: [f32; 3]| {
+                    let [x, y, z] = self.transform.scene_point(p);
+                    Point3::new(x, y, z)
+                };
+                self.arc_ball
+                    .look_at(point(camera.position), point(camera.target));
+                self.window.render_with_camera(&mut self.arc_ball);
+            }
+            None => {
+                let rotation = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), self.yaw);
+                self.window.scene_mut().set_local_rotation(rotation);
+                self.window.render();
             }
-            Err(_) => Err("error parsing"),
         }
+        self.window.snap(&mut v);
+        RgbImage::from_raw(self.window_size_pixels, self.window_size_pixels, v)
     }
 }
 
-#[tokio::main]
-pub async fn main() {
-    let args = Cli::from_args();
-    SimpleLogger::new()
-        .with_level(args.log_level)
-        .init()
-        .unwrap();
+// Quality of the streamed frames, unless lowered under load.
+const JPEG_QUALITY: u8 = 85;
 
-    let config = match fs::read_to_string(&args.config_file) {
-        Ok(config) => config,
-        Err(e) => {
+fn encode_jpeg(img: &RgbImage, quality: u8) -> image::ImageResult<Vec<u8>> {
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, quality).encode_image(img)?;
+    Ok(jpeg)
+}
+
+// Saves to a temporary file first, so that readers of `filepath` never see a
+// partially written image.
+fn save_image(img: &RgbImage, filepath: &str) -> image::ImageResult<()> {
+    let tmpdir = tempdir()?;
+    let tmpfile = tmpdir.path().join("img.png");
+    img.save(&tmpfile)?;
+    fs::rename(tmpfile, filepath)?;
+    Ok(())
+}
+
+// Where the auxiliary buffers of the last snapshot are served, e.g.
+// /snapshot/depth.png.
+const SNAPSHOT_PATH: &str = "/snapshot";
+
+// Saves the `auxiliary` buffers of the snapshot at `filepath` next to it, e.g.
+// twixelbox-snapshot.depth.png, and serves them when there's a server.
+fn save_auxiliary(
+    rendered: &AuxiliaryBuffers,
+    auxiliary: &[AuxiliaryBuffer],
+    filepath: &str,
+    http: Option<&HttpServer>,
+) {
+    for buffer in auxiliary {
+        let mut png = Vec::new();
+        let img = rendered.image(*buffer);
+        if let Err(e) = img.write_to(&mut png, image::ImageOutputFormat::Png) {
             eprintln!(
-                "Error opening the configuration file {}: {}",
-                args.config_file, e
+                "Unable to encode the {} of the snapshot: {}",
+                buffer.name(),
+                e
             );
-            eprintln!("Create the file or use the --config_file flag to specify an alternative file location");
-            return;
+            continue;
         }
-    };
+        let aux_filepath =
+            std::path::Path::new(filepath).with_extension(format!("{}.png", buffer.name()));
+        let saved = tempdir().and_then(|tmpdir| {
+            let tmpfile = tmpdir.path().join("img.png");
+            fs::write(&tmpfile, &png)?;
+            fs::rename(tmpfile, &aux_filepath)
+        });
+        if let Err(e) = saved {
+            eprintln!("Unable to save {}: {}", aux_filepath.display(), e);
+        }
+        if let Some(http) = http {
+            let path = format!("{}/{}.png", SNAPSHOT_PATH, buffer.name());
+            http.publish(&path, "image/png", png);
+        }
+    }
+}
 
-    let config: TwixelBoxBotConfig = match toml::from_str(&config) {
-        Ok(config) => config,
-        Err(e) => {
-            eprintln!(
-                "Error parsing configuration file {}: {}",
-                args.config_file, e
-            );
-            return;
+fn create_renderer(config: &TwixelBoxConfig) -> Option<Box<dyn Renderer>> {
+    let window_size_pixels = 1080;
+    // The renderers fit the whole canvas in their frame.
+    let frame_side_len = config.cube_size;
+    if config.pixel_art {
+        return Some(Box::new(FlatRenderer::new(
+            window_size_pixels,
+            config.cube_size,
+        )));
+    }
+    match config.renderer {
+        RendererBackend::Kiss3d => Some(Box::new(Kiss3dRenderer::new(
+            window_size_pixels,
+            frame_side_len,
+            config.colour_correction,
+        ))),
+        #[cfg(feature = "wgpu-renderer")]
+        RendererBackend::Wgpu => match WgpuRenderer::new(window_size_pixels, frame_side_len) {
+            Ok(renderer) => Some(Box::new(renderer)),
+            Err(e) => {
+                eprintln!("Unable to initialise the wgpu renderer: {}", e);
+                None
+            }
+        },
+        #[cfg(not(feature = "wgpu-renderer"))]
+        RendererBackend::Wgpu => {
+            eprintln!("The wgpu renderer is not available, rebuild with --features wgpu-renderer");
+            None
         }
-    };
+        RendererBackend::Terminal => Some(Box::new(TerminalRenderer::new(
+            config.terminal_columns,
+            window_size_pixels,
+            frame_side_len,
+        ))),
+    }
+}
+
+// Commands can be spilled to disk when the queue overflows, hence serde.
+#[derive(Debug, Serialize, Deserialize)]
+enum Command {
+    Render,
+    // Every command changing the canvas has its own id, see CubeArchive.
+    Event {
+        id: Uuid,
+        event: CanvasEvent,
+        // Login of the chatter who sent the command.
+        author: Option<String>,
+        // Team of the author, when already known, e.g. from the bot process.
+        #[serde(default)]
+        team: Option<String>,
+        // Chat message the command was parsed from.
+        #[serde(default)]
+        message: Option<String>,
+        // Twitch id of that message, which the feedback replies to.
+        #[serde(default)]
+        reply_to: Option<String>,
+        // Use of a command the event counts towards, none for moderators and
+        // the bot itself.
+        #[serde(default)]
+        command_use: Option<CommandUse>,
+        // Time spent parsing the chat message, counted in the timing of the
+        // command.
+        #[serde(default)]
+        parse_time: Option<std::time::Duration>,
+    },
+    JoinTeam {
+        login: String,
+        team: String,
+    },
+    // Render a raytraced beauty shot of the canvas.
+    Snapshot,
+    // While locked, placements from viewers are rejected.
+    Lock(bool),
+    StartCompetition {
+        name: String,
+        duration_secs: u64,
+    },
+    // Ends the given competition, or the running one.
+    EndCompetition(Option<i64>),
+    // Fade and remove the cubes which aged, see DecayTracker.
+    Decay,
+    // Prune the old events of the archive, see RetentionConfig.
+    Prune,
+    // Post the summary of the canvas over the last period, in chat and to
+    // the webhook.
+    PostSummary {
+        period_secs: i64,
+    },
+    // Render the timelapse of the recent events and serve it over HTTP.
+    Timelapse,
+    // Render the credits of the builders of the last seconds, or of the
+    // configured hours, and serve them over HTTP.
+    Credits(Option<u64>),
+    // Take the cubes of `login` off the canvas, only the one placed by the
+    // given command if any.
+    Moderate {
+        login: String,
+        command_id: Option<Uuid>,
+        action: ModerationAction,
+    },
+    // Put back the quarantined cubes of a chatter.
+    Restore(String),
+    // Keep viewers from changing the cubes of the region, or let them again
+    // in the protected regions overlapping it.
+    Protect {
+        region: Region,
+        protected: bool,
+    },
+    // Ignore, or stop ignoring, the placements of a chatter.
+    Ignore {
+        login: String,
+        ignored: bool,
+    },
+    // Refresh the canvas statistics and occupancy.
+    Stats,
+    // Step the screensaver, if chat has been quiet for long enough.
+    Screensaver,
+    // Only show the cubes within the slice on the overlay for a while, or
+    // all of them again once the last slice is over.
+    Slice(Option<Slice>),
+    // Name the chatter in chat, and highlight their cubes.
+    Shoutout(String),
+    // Fade every cube but these on the overlay for a while, or all of them
+    // again once the last highlight is over.
+    Highlight(Option<Vec<Position>>),
+    // Restrict the colours of placements to these for the rest of the
+    // session, or lift the restriction.
+    Palette(Option<Vec<Colour>>),
+    // Publish the build sheet of an image, given as RGB pixels.
+    BuildSheet {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+        projection: Projection,
+    },
+    // Export the canvas to voxel art files and serve them over HTTP.
+    Export,
+    // Fill the bottom of the canvas with the terrain of the seed.
+    Terrain(u64),
+    // Place these cubes, e.g. imported from a voxel art file, taking the
+    // closest colours of the palette in use if `restrict` is set.
+    Import {
+        cubes: Vec<Cube>,
+        restrict: bool,
+    },
+    // Grey out the canvas outside of the region which can be built on, see
+    // Progression.
+    Fog(Option<Region>),
+    // Save the chat macro `name`, or forget it when there's no body.
+    Alias {
+        name: String,
+        body: Option<String>,
+    },
+    // Save the whole canvas as the checkpoint `name`.
+    SaveCheckpoint(String),
+    // Put the canvas back as it was saved in the checkpoint `name`, on
+    // behalf of the moderator `login`.
+    RestoreCheckpoint {
+        name: String,
+        login: String,
+    },
+    // Run the chat command `!name` defined by the script.
+    Script {
+        name: String,
+        args: Vec<String>,
+        // Display name of the chatter.
+        user: String,
+        login: String,
+        moderator: bool,
+    },
+    // Run the command `!name args` of the plugins, `id` being the one of
+    // the message, and `reply_to` its Twitch id.
+    Plugin {
+        id: Uuid,
+        name: String,
+        args: String,
+        caller: Caller,
+        message: String,
+        reply_to: String,
+    },
+    // Orbit the overlay around the canvas at this many degrees per second,
+    // 0 stops it.
+    SetSpin(f32),
+    // Switch the overlay to the named theme.
+    Theme(String),
+    // Show the effect over the overlay, none clears it.
+    SetWeather(Option<WeatherEffect>),
+    // Move the camera of the overlay to the named preset.
+    View(String),
+    // Draw a frame for the overlay stream only, in between those of the
+    // image file while the overlay spins or the weather is on.
+    StreamFrame,
+    // Compare the overlay with the archive, and rebuild it if they differ.
+    CheckDrift,
+    // Tear down the scene and rebuild it from the archive, e.g. after it was
+    // edited while the bot runs.
+    Resync,
+    // Draw the events journaled by other processes since the last check.
+    ExternalEvents,
+    // Show the stream info fetched from Helix on the HUD.
+    StreamInfo(StreamInfo),
+    // Tell chat how far it got towards the goal of the day.
+    ShowGoal,
+    // Set the goal of the day for the rest of the session, or drop it.
+    SetGoal(Option<u64>),
+    // Show the progress towards the goal on the HUD, none hides it.
+    GoalProgress(Option<GoalProgress>),
+    // Tell chat which colours it placed the most cubes in today.
+    ShowColours,
+    // Tell chat the achievements of the chatter.
+    ShowAchievements(String),
+    // Apply the edits of chat which waited for the edit budget, as far as it
+    // allows.
+    ReleaseEdits,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Lane {
+    // Moderator and system commands.
+    Priority,
+    Viewer,
+}
+
+// Sending side of the command lanes. Viewer placements go through the
+// configured command queue, moderator and system commands through a small
+// separate queue, so that they are not stuck behind thousands of placements.
+#[derive(Clone)]
+struct CommandSenders {
+    priority: CommandSender<Command>,
+    viewer: CommandSender<Command>,
+}
+
+struct CommandLanes {
+    priority: mpsc::Receiver<Command>,
+    viewer: mpsc::Receiver<Command>,
+}
+
+impl CommandLanes {
+    // Returns the next command, always from the priority lane if it has any.
+    async fn recv(&mut self) -> Option<(Lane, Command)> {
+        tokio::select! {
+            biased;
+            Some(command) = self.priority.recv() => Some((Lane::Priority, command)),
+            Some(command) = self.viewer.recv() => Some((Lane::Viewer, command)),
+            else => None,
+        }
+    }
+}
+
+fn command_lanes(
+    config: &CommandQueueConfig,
+) -> Result<(CommandSenders, CommandLanes), Box<CommandQueueError<Command>>> {
+    let (priority_tx, priority_rx) = command_queue(&CommandQueueConfig {
+        capacity: 64,
+        overflow: OverflowPolicy::Drop,
+        ..config.clone()
+    })?;
+    let (viewer_tx, viewer_rx) = command_queue(config)?;
+    Ok((
+        CommandSenders {
+            priority: priority_tx,
+            viewer: viewer_tx,
+        },
+        CommandLanes {
+            priority: priority_rx,
+            viewer: viewer_rx,
+        },
+    ))
+}
+
+fn is_moderator(msg: &PrivmsgMessage) -> bool {
+    msg.badges
+        .iter()
+        .any(|b| b.name == "moderator" || b.name == "broadcaster")
+}
 
+// Id of the channel point reward the message was sent with, if any.
+fn redeemed_reward(msg: &PrivmsgMessage) -> Option<&str> {
+    msg.source
+        .tags
+        .0
+        .get("custom-reward-id")
+        .and_then(Option::as_deref)
+}
+
+// Canvas-wide actions chat can vote for with `!vote <action>`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum VoteAction {
+    Clear,
+    Lock,
+    Unlock,
+    // Name of the theme, in lowercase.
+    Theme(String),
+    // Name of the camera preset, in lowercase.
+    View(String),
+}
+
+impl VoteAction {
+    fn command(&self) -> Command {
+        match self {
+            VoteAction::Clear => Command::Event {
+                id: Uuid::new_v4(),
+                event: CanvasEvent::CanvasCleared,
+                author: None,
+                team: None,
+                message: None,
+                reply_to: None,
+                command_use: None,
+                parse_time: None,
+            },
+            VoteAction::Lock => Command::Lock(true),
+            VoteAction::Unlock => Command::Lock(false),
+            VoteAction::Theme(name) => Command::Theme(name.clone()),
+            VoteAction::View(name) => Command::View(name.clone()),
+        }
+    }
+}
+
+impl FromStr for VoteAction {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["clear"] => Ok(VoteAction::Clear),
+            ["lock"] => Ok(VoteAction::Lock),
+            ["unlock"] => Ok(VoteAction::Unlock),
+            ["theme", name] => Ok(VoteAction::Theme(name.to_lowercase())),
+            ["view", name] => Ok(VoteAction::View(name.to_lowercase())),
+            _ => Err("you can vote for clear, lock, unlock, theme <name> or view <name>"),
+        }
+    }
+}
+
+impl std::fmt::Display for VoteAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            VoteAction::Clear => write!(f, "clear"),
+            VoteAction::Lock => write!(f, "lock"),
+            VoteAction::Unlock => write!(f, "unlock"),
+            VoteAction::Theme(name) => write!(f, "theme {}", name),
+            VoteAction::View(name) => write!(f, "view {}", name),
+        }
+    }
+}
+
+// Commands handled by the bot itself, which macros can't be named after,
+// nor after the plugins.
+const BUILTIN_COMMANDS: &[&str] = &[
+    "achievements",
+    "alias",
+    "aliases",
+    "checkpoint",
+    "clear",
+    "colours",
+    "credits",
+    "event",
+    "export",
+    "goal",
+    "ignore",
+    "lock",
+    "palette",
+    "protect",
+    "restore",
+    "resync",
+    "slice",
+    "snapshot",
+    "so",
+    "spin",
+    "team",
+    "terrain",
+    "today",
+    "unalias",
+    "unignore",
+    "unlock",
+    "unprotect",
+    "view",
+    "vote",
+    "weather",
+];
+
+type ChatClient = TwitchIRCClient<TCPTransport, RefreshingLoginCredentials<CustomTokenStorage>>;
+
+// Queues what the bot says in chat, see `chat_announcer`.
+type Announcer = mpsc::UnboundedSender<ChatMessage>;
+
+// Authenticates with Twitch, joins the configured channel and forwards valid
+// cube placements from chat as commands. Returns the announcer through which
+// the bot talks in chat, replies included. Chatters are told when their
+// placement is dropped because the command queue is full. Chat macros are
+// expanded here, `macros` are the ones saved in the archive.
+async fn connect_to_chat(
+    config: &TwixelBoxBotConfig,
+    tx: CommandSenders,
+    mut macros: Macros,
+    limits: Arc<Mutex<CommandLimits>>,
+) -> Option<Announcer> {
     let mut token_storage = CustomTokenStorage {
         token_checkpoint_file: config.twitch.token_filepath.clone(),
     };
@@ -178,16 +1313,20 @@ pub async fn main() {
     // stored one before or it's unparsable, go through the authentication
     // workflow.
     if let Err(_) = token_storage.load_token().await {
+        let mut scopes = vec![Scope::ChatRead, Scope::ChatEdit];
+        if config.markers.enabled {
+            scopes.push(Scope::ChannelManageBroadcast);
+        }
         let user_token = match twitch_oauth2_auth_flow::auth_flow_surf(
             &config.twitch.client_id,
             &config.twitch.secret,
-            Some(vec![Scope::ChatRead]),
+            Some(scopes),
             "http://localhost:10666/twitch/token",
         ) {
             Ok(t) => t,
             Err(e) => {
                 eprintln!("Error during the authentication flow: {}", e);
-                return;
+                return None;
             }
         };
         token_storage
@@ -211,152 +1350,4827 @@ pub async fn main() {
     // join a channel
     twitch_irc_client.join(config.twitch.channel_name.to_owned());
 
-    // Window initialisation.
-    let window_size_pixels = 1080;
-    let mut window =
-        Window::new_with_size("Kiss3d: points", window_size_pixels, window_size_pixels);
-
-    // TODO: fake the wireframe so that diagonals are not rendered.
-    let mut c = window.add_cube(0.5, 0.5, 0.5);
-
-    c.set_color(0.99, 0.99, 0.99);
-    c.set_points_size(10.0);
-    c.set_lines_width(0.1);
-    c.set_surface_rendering_activation(false);
-
-    window.set_light(Light::StickToCamera);
-    window.set_background_color(250.0 / 255.0, 250.0 / 255.0, 250.0 / 255.0);
-
-    let mut canvas = Canvas {
-        frame_side_len: 500,
-    };
-
-    // Set up the channel to send commands to the main thread which controls the canvas.
-    let (tx, mut rx) = mpsc::unbounded_channel::<Command>();
-
-    let fps: f32 = 0.5;
-
-    // Spawn the renderer timer thread.
-    let tx2 = tx.clone();
-    tokio::spawn(async move {
-        let frame_time_millis = std::time::Duration::from_millis((1000.0 / fps) as u64);
-        loop {
-            // send render message
-            tx.send(Command::Render).unwrap();
-            tokio::time::sleep(frame_time_millis).await;
-        }
-    });
+    let announcer = chat_announcer(
+        twitch_irc_client.clone(),
+        config.twitch.channel_name.clone(),
+        &config.outbox,
+        config.announcements.clone(),
+    );
 
     // Message processing thread.
     let cube_size = config.twixelbox.cube_size;
+    let pixel_art = config.twixelbox.pixel_art;
+    let coordinates = config.coordinates;
+    let plugins = command_plugins(&config.twixelbox, coordinates);
+    let replies = announcer.clone();
+    let poll_config = config.votes.clone();
+    let mut themes: Vec<String> = config
+        .twixelbox
+        .themes
+        .keys()
+        .map(|name| name.to_lowercase())
+        .chain(std::iter::once("default".to_owned()))
+        .collect();
+    themes.sort_unstable();
+    let views = Views::new(&config.views, config.twixelbox.cube_size).names();
+    let poll: Arc<Mutex<Option<Poll<VoteAction>>>> = Arc::new(Mutex::new(None));
+    let today_url = config.http.address.as_ref().map(|_| {
+        format!(
+            "{}{}",
+            config.http.public_url.trim_end_matches('/'),
+            TIMELAPSE_PATH
+        )
+    });
+    let moderation = config.moderation.clone();
+    let announcements = config.announcements.clone();
+    let scripting = config.scripts.filepath.is_some();
+    let spin = config.spin.clone();
+    let weather = config.weather.clone();
+    let glow = config.glow.clone();
     tokio::spawn(async move {
-        while let Some(message) = incoming_messages.recv().await {
+        // Commands a macro expanded to, handled before the next message,
+        // with the id of the macro's message the feedback replies to.
+        let mut expanded: VecDeque<(PrivmsgMessage, String)> = VecDeque::new();
+        loop {
+            let (message, parent) = match expanded.pop_front() {
+                Some((msg, parent)) => (ServerMessage::Privmsg(msg), Some(parent)),
+                None => match incoming_messages.recv().await {
+                    Some(message) => (message, None),
+                    None => break,
+                },
+            };
+            let from_macro = parent.is_some();
             trace!("{:?}", message);
             match message {
-                ServerMessage::Privmsg(msg) => {
-                    let chat_command = match msg.message_text.parse::<ChatCommand>() {
-                        Err(_) => continue,
-                        Ok(c) => c,
+                ServerMessage::ClearChat(msg) => {
+                    let login = match msg.action {
+                        ClearChatAction::UserBanned { user_login, .. } => user_login,
+                        _ => continue,
                     };
-                    debug!("{:?}", chat_command);
-                    if [chat_command.x, chat_command.y, chat_command.z]
-                        .iter()
-                        .any(|p| p >= &cube_size)
-                    {
+                    if moderation.on_ban == ModerationAction::Keep {
                         continue;
                     }
-
-                    debug!("{:?} sending", chat_command);
-                    tx2.send(Command::AddCube(Cube {
-                        position: (chat_command.x, chat_command.y, chat_command.z),
-                        colour: (chat_command.r, chat_command.g, chat_command.b),
-                    }))
-                    .unwrap();
+                    let command = Command::Moderate {
+                        login,
+                        command_id: None,
+                        action: moderation.on_ban,
+                    };
+                    if let Err(e) = tx.priority.send(command) {
+                        eprintln!("Unable to queue the ban: {}", e);
+                    }
                 }
-                _ => continue,
-            }
-        }
-    });
-
-    // Read previous cubes from db and add the to the canvas.
-
-    let sqlite_path = std::path::PathBuf::from("cube_archive.db");
-    let mut archive = CubeArchive::new(sqlite_path.clone());
-    let cubes = archive.get_cubes().expect("failed to extract cubes");
-    for cube in cubes {
-        canvas.add_cube(
-            &mut window,
-            cube.position.0,
-            cube.position.1,
-            cube.position.2,
-            cube.colour.0,
-            cube.colour.1,
-            cube.colour.2,
-        );
-    }
-
-    let frame_time = std::time::Duration::from_millis((1000.0 / fps) as u64);
-    let mut next_expected_frame = std::time::Instant::now();
-    // The main thread now only receives commands and alters the canvas as required.
-    while let Some(command) = rx.recv().await {
-        match command {
-            Command::Render => {
-                let current_time = std::time::Instant::now();
-                if current_time < next_expected_frame {
-                    eprintln!(
-                        "skipping frame at {:?}, next expected at {:?}",
-                        current_time, next_expected_frame
-                    );
-                    continue;
-                }
-                let mut v = Vec::new();
-                window.render();
-                window.snap(&mut v);
-                match RgbImage::from_raw(window_size_pixels, window_size_pixels, v) {
-                    Some(img) => {
-                        let tmpdir = tempdir().unwrap();
-                        let tmpfile = tmpdir.path().join("img.png");
-                        if let Err(e) = img.save(&tmpfile) {
-                            eprintln!("Unable to save to tmpfile: {}", e);
-                            continue;
-                        }
-
-                        fs::rename(tmpfile, &config.twixelbox.img_filepath).unwrap();
+                ServerMessage::ClearMsg(msg) => {
+                    if moderation.on_message_deleted == ModerationAction::Keep {
+                        continue;
                     }
-                    None => eprintln!("Unable to convert pixels to RgbImage!"),
-                }
-                let last_attempted_frame = current_time;
-                let current_time = std::time::Instant::now();
-                let render_time = current_time.duration_since(last_attempted_frame);
-                let frames_lost = render_time.as_millis() / frame_time.as_millis();
-                if frames_lost > 0 {
-                    eprintln!(
-                        "Lost {} frames! Render time {:?}, frame time {:?}",
-                        frames_lost, render_time, frame_time
-                    );
-                }
-                next_expected_frame = last_attempted_frame
-                    .checked_add(
-                        frame_time
-                            .checked_mul(frames_lost as u32 + 1)
-                            .expect("Failed to compute lost frames"),
-                    )
-                    .expect("Failed to compute next expected frame");
-            }
-            Command::AddCube(cube) => {
-                canvas.add_cube(
-                    &mut window,
-                    cube.position.0,
-                    cube.position.1,
-                    cube.position.2,
-                    cube.colour.0,
-                    cube.colour.1,
-                    cube.colour.2,
-                );
-                archive
-                    .add_cube(cube)
-                    .expect("Failed to add cube to database");
+                    let command_id = match Uuid::parse_str(&msg.message_id) {
+                        Ok(id) => id,
+                        Err(_) => continue,
+                    };
+                    let command = Command::Moderate {
+                        login: msg.sender_login,
+                        command_id: Some(command_id),
+                        action: moderation.on_message_deleted,
+                    };
+                    if let Err(e) = tx.priority.send(command) {
+                        eprintln!("Unable to queue the message deletion: {}", e);
+                    }
+                }
+                ServerMessage::Privmsg(msg) => {
+                    let reply_to = parent.unwrap_or_else(|| msg.message_id.clone());
+                    let in_reply = |text: String| ChatMessage::Reply {
+                        message_id: reply_to.clone(),
+                        text,
+                    };
+                    // Every `!` command goes through its limits first,
+                    // placements once parsed. Those expanded from a macro
+                    // were counted with the macro.
+                    let name = msg
+                        .message_text
+                        .trim()
+                        .strip_prefix('!')
+                        .and_then(|text| text.split_whitespace().next())
+                        .filter(|_| !from_macro);
+                    if let Some(name) = name {
+                        let moderator = is_moderator(&msg);
+                        let checked = limits.lock().unwrap().check(
+                            name,
+                            &msg.sender.login,
+                            moderator,
+                            1,
+                            Instant::now(),
+                        );
+                        if let Err(e) = checked {
+                            trace!("Rejected !{} from {}: {}", name, msg.sender.login, e);
+                            continue;
+                        }
+                    }
+                    let expansion = match from_macro {
+                        true => None,
+                        false => macros.expand(&msg.message_text),
+                    };
+                    match expansion {
+                        Some(Ok(steps)) => {
+                            let volume = steps.len() as u64;
+                            let moderator = is_moderator(&msg);
+                            if let Err(e) = limits.lock().unwrap().check(
+                                "macro",
+                                &msg.sender.login,
+                                moderator,
+                                volume,
+                                Instant::now(),
+                            ) {
+                                trace!("Rejected macro from {}: {}", msg.sender.login, e);
+                                continue;
+                            }
+                            // Each step gets its own command id, the first
+                            // one keeps the id of the message.
+                            let base =
+                                Uuid::parse_str(&msg.message_id).unwrap_or_else(|_| Uuid::new_v4());
+                            for (index, step) in steps.into_iter().enumerate() {
+                                let mut step_msg = msg.clone();
+                                step_msg.message_text = step;
+                                step_msg.message_id = step_id(base, index).to_string();
+                                expanded.push_back((step_msg, reply_to.clone()));
+                            }
+                            continue;
+                        }
+                        Some(Err(e)) => {
+                            let reply = format!("@{} {}", msg.sender.name, e);
+                            let _ = replies.send(in_reply(reply));
+                            continue;
+                        }
+                        None => {}
+                    }
+                    if msg.message_text.trim() == "!aliases" {
+                        let reply = match macros.names().is_empty() {
+                            true => format!("@{} there are no macros yet", msg.sender.name),
+                            false => format!(
+                                "@{} macros: !{}",
+                                msg.sender.name,
+                                macros.names().join(", !")
+                            ),
+                        };
+                        let _ = replies.send(in_reply(reply));
+                        continue;
+                    }
+                    // Anyone can vote, the first vote opens the poll.
+                    if let Some(option) = msg.message_text.trim().strip_prefix("!vote ") {
+                        let action = match option.trim().parse::<VoteAction>() {
+                            Ok(VoteAction::Theme(name)) if !themes.contains(&name) => {
+                                Err(format!("the themes are {}", themes.join(", ")))
+                            }
+                            Ok(VoteAction::View(name)) if !views.contains(&name) => {
+                                Err(format!("the views are {}", views.join(", ")))
+                            }
+                            Ok(action) => Ok(action),
+                            Err(e) => Err(e.to_owned()),
+                        };
+                        let action = match action {
+                            Ok(action) => action,
+                            Err(e) => {
+                                let reply = format!("@{} {}", msg.sender.name, e);
+                                let _ = replies.send(in_reply(reply));
+                                continue;
+                            }
+                        };
+                        let now = Instant::now();
+                        let opened = {
+                            let mut poll = poll.lock().unwrap();
+                            let opened = poll.is_none();
+                            poll.get_or_insert_with(|| Poll::new(&poll_config, now))
+                                .vote(&msg.sender.login, action.clone(), now);
+                            opened
+                        };
+                        if opened {
+                            tokio::spawn(close_poll(
+                                poll.clone(),
+                                replies.clone(),
+                                tx.clone(),
+                                announcements.clone(),
+                            ));
+                            let announcement = announcements.format(
+                                "vote_started",
+                                &[
+                                    ("user", &msg.sender.name),
+                                    ("action", &action),
+                                    ("seconds", &poll_config.window_secs),
+                                ],
+                            );
+                            let _ = replies.send(announcement.into());
+                        }
+                        continue;
+                    }
+                    if msg.message_text.trim() == "!goal" {
+                        if let Err(e) = tx.viewer.send(Command::ShowGoal) {
+                            eprintln!("Unable to queue the goal: {}", e);
+                        }
+                        continue;
+                    }
+                    let text = msg.message_text.trim();
+                    if text == "!achievements" || text.starts_with("!achievements ") {
+                        // Their own, or those of the chatter named.
+                        let user = match text.split_whitespace().nth(1) {
+                            Some(user) => user.trim_start_matches('@').to_lowercase(),
+                            None => msg.sender.login.clone(),
+                        };
+                        if let Err(e) = tx.viewer.send(Command::ShowAchievements(user)) {
+                            eprintln!("Unable to queue the achievements: {}", e);
+                        }
+                        continue;
+                    }
+                    if msg.message_text.trim() == "!colours" {
+                        if let Err(e) = tx.viewer.send(Command::ShowColours) {
+                            eprintln!("Unable to queue the colour trivia: {}", e);
+                        }
+                        continue;
+                    }
+                    if msg.message_text.trim() == "!today" {
+                        if let Some(url) = &today_url {
+                            let reply = announcements
+                                .format("today", &[("user", &msg.sender.name), ("url", url)]);
+                            let _ = replies.send(in_reply(reply));
+                        }
+                        continue;
+                    }
+                    let plugin = msg
+                        .message_text
+                        .trim()
+                        .strip_prefix('!')
+                        .map(|text| text.split_once(' ').unwrap_or((text, "")));
+                    if let Some((name, args)) = plugin {
+                        let name = name.to_lowercase();
+                        let caller = Caller {
+                            name: msg.sender.name.clone(),
+                            login: msg.sender.login.clone(),
+                            moderator: is_moderator(&msg),
+                        };
+                        match plugins.check(&name, &caller, args) {
+                            Some(Ok(())) => {
+                                // Twitch message ids are uuids, see below.
+                                let command = Command::Plugin {
+                                    id: Uuid::parse_str(&msg.message_id)
+                                        .unwrap_or_else(|_| Uuid::new_v4()),
+                                    name,
+                                    args: args.trim().to_owned(),
+                                    caller,
+                                    message: msg.message_text.clone(),
+                                    reply_to: reply_to.clone(),
+                                };
+                                if let Err(e) = tx.viewer.send(command) {
+                                    eprintln!("Unable to queue the command: {}", e);
+                                }
+                                continue;
+                            }
+                            Some(Err(e)) => {
+                                let reply = format!("@{} {}", msg.sender.name, e);
+                                let _ = replies.send(in_reply(reply));
+                                continue;
+                            }
+                            None => {}
+                        }
+                    }
+                    if let Some(args) = msg.message_text.trim().strip_prefix("!slice ") {
+                        match args.parse::<Slice>() {
+                            Ok(slice) => {
+                                if let Err(e) = tx.viewer.send(Command::Slice(Some(slice))) {
+                                    eprintln!("Unable to queue the slice: {}", e);
+                                }
+                            }
+                            Err(e) => {
+                                let reply = format!("@{} {}", msg.sender.name, e);
+                                let _ = replies.send(in_reply(reply));
+                            }
+                        }
+                        continue;
+                    }
+                    if let Some(team) = msg.message_text.trim().strip_prefix("!team ") {
+                        let command = Command::JoinTeam {
+                            login: msg.sender.login.clone(),
+                            team: team.trim().to_owned(),
+                        };
+                        if let Err(e) = tx.viewer.send(command) {
+                            eprintln!("Unable to queue the team change: {}", e);
+                        }
+                        continue;
+                    }
+                    // Moderators can always spin the overlay, others with the
+                    // channel point reward when there's one.
+                    if let Some(args) = msg.message_text.trim().strip_prefix("!spin ") {
+                        let redeemed = spin.reward_id.is_some()
+                            && spin.reward_id.as_deref() == redeemed_reward(&msg);
+                        if !is_moderator(&msg) && !redeemed {
+                            continue;
+                        }
+                        match spin.parse_speed(args) {
+                            Ok(speed) => {
+                                if let Err(e) = tx.priority.send(Command::SetSpin(speed)) {
+                                    eprintln!("Unable to queue the spin: {}", e);
+                                }
+                            }
+                            Err(e) => {
+                                let reply = format!("@{} {}", msg.sender.name, e);
+                                let _ = replies.send(in_reply(reply));
+                            }
+                        }
+                        continue;
+                    }
+                    // Shoutouts are for moderators, as on Twitch.
+                    if let Some(args) = msg.message_text.trim().strip_prefix("!so ") {
+                        let user = args.split_whitespace().next().unwrap_or_default();
+                        let user = user.trim_start_matches('@');
+                        if !is_moderator(&msg) || user.is_empty() {
+                            continue;
+                        }
+                        if let Err(e) = tx.priority.send(Command::Shoutout(user.to_owned())) {
+                            eprintln!("Unable to queue the shoutout: {}", e);
+                        }
+                        continue;
+                    }
+                    if let Some(name) = msg.message_text.trim().strip_prefix("!view ") {
+                        if !is_moderator(&msg) {
+                            continue;
+                        }
+                        let name = name.trim().to_lowercase();
+                        if !views.contains(&name) {
+                            let reply =
+                                format!("@{} the views are {}", msg.sender.name, views.join(", "));
+                            let _ = replies.send(in_reply(reply));
+                        } else if let Err(e) = tx.priority.send(Command::View(name)) {
+                            eprintln!("Unable to queue the view: {}", e);
+                        }
+                        continue;
+                    }
+                    // The same goes for the weather.
+                    if let Some(args) = msg.message_text.trim().strip_prefix("!weather ") {
+                        let redeemed = weather.reward_id.is_some()
+                            && weather.reward_id.as_deref() == redeemed_reward(&msg);
+                        if !is_moderator(&msg) && !redeemed {
+                            continue;
+                        }
+                        match weather.parse_effect(args) {
+                            Ok(effect) => {
+                                if let Err(e) = tx.priority.send(Command::SetWeather(effect)) {
+                                    eprintln!("Unable to queue the weather: {}", e);
+                                }
+                            }
+                            Err(e) => {
+                                let reply = format!("@{} {}", msg.sender.name, e);
+                                let _ = replies.send(in_reply(reply));
+                            }
+                        }
+                        continue;
+                    }
+                    // Any other command may be defined by the script.
+                    let script = msg
+                        .message_text
+                        .trim()
+                        .strip_prefix('!')
+                        .map(str::split_whitespace)
+                        .filter(|_| scripting);
+                    if let Some(mut words) = script {
+                        let name = words.next().unwrap_or_default().to_lowercase();
+                        if !BUILTIN_COMMANDS.contains(&name.as_str()) {
+                            let command = Command::Script {
+                                name,
+                                args: words.map(str::to_owned).collect(),
+                                user: msg.sender.name.clone(),
+                                login: msg.sender.login.clone(),
+                                moderator: is_moderator(&msg),
+                            };
+                            if let Err(e) = tx.viewer.send(command) {
+                                eprintln!("Unable to queue the script command: {}", e);
+                            }
+                            continue;
+                        }
+                    }
+                    // Moderator commands, ignored from anyone else. Beauty
+                    // shots are expensive, so they're moderator only too.
+                    if msg.message_text.starts_with('!') {
+                        if !is_moderator(&msg) {
+                            continue;
+                        }
+                        let text = msg.message_text.trim();
+                        let command = if let Some(login) = text.strip_prefix("!restore ") {
+                            Command::Restore(parse_login(login))
+                        } else if let Some(login) = text.strip_prefix("!ignore ") {
+                            Command::Ignore {
+                                login: parse_login(login),
+                                ignored: true,
+                            }
+                        } else if let Some(login) = text.strip_prefix("!unignore ") {
+                            Command::Ignore {
+                                login: parse_login(login),
+                                ignored: false,
+                            }
+                        } else if let Some((protected, args)) = text
+                            .strip_prefix("!protect ")
+                            .map(|args| (true, args))
+                            .or_else(|| text.strip_prefix("!unprotect ").map(|args| (false, args)))
+                        {
+                            match parse_region(args, coordinates, cube_size) {
+                                Ok(region) => Command::Protect { region, protected },
+                                Err(e) => {
+                                    let reply = format!("@{} {}", msg.sender.name, e);
+                                    let _ = replies.send(in_reply(reply));
+                                    continue;
+                                }
+                            }
+                        } else if let Some(definition) = text.strip_prefix("!alias ") {
+                            let reserved: Vec<&str> = BUILTIN_COMMANDS
+                                .iter()
+                                .copied()
+                                .chain(plugins.names())
+                                .collect();
+                            match macros.define(definition, &reserved) {
+                                Ok((name, body)) => Command::Alias {
+                                    name,
+                                    body: Some(body),
+                                },
+                                Err(e) => {
+                                    let reply = format!("@{} {}", msg.sender.name, e);
+                                    let _ = replies.send(in_reply(reply));
+                                    continue;
+                                }
+                            }
+                        } else if let Some(name) = text.strip_prefix("!unalias ") {
+                            match macros.remove(name) {
+                                Some(name) => Command::Alias { name, body: None },
+                                None => continue,
+                            }
+                        } else if let Some(args) = text.strip_prefix("!checkpoint ") {
+                            match parse_checkpoint(args) {
+                                Some((name, false)) => Command::SaveCheckpoint(name),
+                                Some((name, true)) => Command::RestoreCheckpoint {
+                                    name,
+                                    login: msg.sender.login.clone(),
+                                },
+                                None => {
+                                    let reply = format!(
+                                        "@{} usage: !checkpoint save|restore <name>",
+                                        msg.sender.name
+                                    );
+                                    let _ = replies.send(in_reply(reply));
+                                    continue;
+                                }
+                            }
+                        } else if let Some(args) = text
+                            .strip_prefix("!credits")
+                            .filter(|args| args.is_empty() || args.starts_with(' '))
+                        {
+                            match args.trim() {
+                                "" => Command::Credits(None),
+                                window => match parse_duration(window) {
+                                    Some(window) => Command::Credits(Some(window.as_secs())),
+                                    None => continue,
+                                },
+                            }
+                        } else if let Some(args) = text
+                            .strip_prefix("!terrain")
+                            .filter(|args| args.is_empty() || args.starts_with(' '))
+                        {
+                            match args.trim() {
+                                "" => Command::Terrain(fastrand::u64(..)),
+                                seed => match seed.parse() {
+                                    Ok(seed) => Command::Terrain(seed),
+                                    Err(_) => {
+                                        let reply = format!(
+                                            "@{} the seed is a whole number, e.g. !terrain 42",
+                                            msg.sender.name
+                                        );
+                                        let _ = replies.send(in_reply(reply));
+                                        continue;
+                                    }
+                                },
+                            }
+                        } else if let Some(args) = text.strip_prefix("!goal ") {
+                            match args.trim() {
+                                "off" => Command::SetGoal(None),
+                                cubes => match cubes.parse() {
+                                    Ok(cubes) => Command::SetGoal(Some(cubes)),
+                                    Err(_) => {
+                                        let reply = format!(
+                                            "@{} the goal is a number of cubes, e.g. !goal 5000, or off",
+                                            msg.sender.name
+                                        );
+                                        let _ = replies.send(in_reply(reply));
+                                        continue;
+                                    }
+                                },
+                            }
+                        } else if let Some(args) = text.strip_prefix("!event start ") {
+                            match parse_competition(args) {
+                                Some((name, duration)) => Command::StartCompetition {
+                                    name,
+                                    duration_secs: duration.as_secs(),
+                                },
+                                None => continue,
+                            }
+                        } else {
+                            match text {
+                                "!snapshot" => Command::Snapshot,
+                                "!clear" => Command::Event {
+                                    id: Uuid::new_v4(),
+                                    event: CanvasEvent::CanvasCleared,
+                                    author: Some(msg.sender.login.clone()),
+                                    team: None,
+                                    message: Some(msg.message_text.clone()),
+                                    reply_to: None,
+                                    command_use: None,
+                                    parse_time: None,
+                                },
+                                "!lock" => Command::Lock(true),
+                                "!unlock" => Command::Lock(false),
+                                "!event stop" => Command::EndCompetition(None),
+                                "!palette off" => Command::Palette(None),
+                                "!export" => Command::Export,
+                                "!resync" => Command::Resync,
+                                _ => continue,
+                            }
+                        };
+                        if let Err(e) = tx.priority.send(command) {
+                            eprintln!("Unable to queue the moderator command: {}", e);
+                        }
+                        continue;
+                    }
+                    let parsing = Instant::now();
+                    let text = match msg.bits {
+                        Some(_) => Cow::Owned(without_cheermotes(&msg.message_text)),
+                        None => Cow::Borrowed(msg.message_text.as_str()),
+                    };
+                    let chat_command = match text.parse::<ChatCommand>() {
+                        Err(_) => continue,
+                        Ok(c) => c,
+                    };
+                    debug!("{:?}", chat_command);
+                    let (x, y, z) = (chat_command.x, chat_command.y, chat_command.z);
+                    let position = match coordinates.to_canvas([x, y, z], cube_size) {
+                        None => continue,
+                        Some(position) => position,
+                    };
+                    // The pixel art canvas is a single plane.
+                    if pixel_art && position.z != 0 {
+                        continue;
+                    }
+                    // Not the cubes of macros, which would all glow from a
+                    // single cheer.
+                    let emissive = !from_macro && glow.paid(&msg);
+                    let cube = Cube {
+                        position,
+                        colour: chat_command.colour.with_emissive(emissive),
+                    };
+                    let parse_time = parsing.elapsed();
+                    let moderator = is_moderator(&msg);
+                    if !from_macro {
+                        let checked = limits.lock().unwrap().check(
+                            "place",
+                            &msg.sender.login,
+                            moderator,
+                            1,
+                            Instant::now(),
+                        );
+                        if let Err(e) = checked {
+                            trace!("Rejected placement from {}: {}", msg.sender.login, e);
+                            continue;
+                        }
+                    }
+
+                    debug!("{:?} sending", cube);
+                    // Twitch message ids are uuids, using them as command ids
+                    // links the cube to its message, e.g. when it's deleted.
+                    let id = Uuid::parse_str(&msg.message_id).unwrap_or_else(|_| Uuid::new_v4());
+                    match tx.viewer.send(Command::Event {
+                        id,
+                        event: CanvasEvent::CubePlaced(cube),
+                        author: Some(msg.sender.login.clone()),
+                        team: None,
+                        message: Some(msg.message_text.clone()),
+                        reply_to: Some(reply_to.clone()),
+                        command_use: Some(CommandUse {
+                            command: "place".to_owned(),
+                            id,
+                        })
+                        .filter(|_| !moderator),
+                        parse_time: Some(parse_time),
+                    }) {
+                        Ok(()) => {}
+                        Err(CommandQueueError::Full(_)) => {
+                            let reply = announcements.format(
+                                "canvas_busy",
+                                &[("user", &msg.sender.name), ("x", &x), ("y", &y), ("z", &z)],
+                            );
+                            let _ = replies.send(in_reply(reply));
+                        }
+                        Err(e) => eprintln!("Unable to queue the placement: {}", e),
+                    }
+                }
+                // Slow mode comes with the channel when joining it, and
+                // whenever it changes. Zero turns it off.
+                ServerMessage::RoomState(msg) => {
+                    if let Some(slow_mode) = msg.slow_mode {
+                        let slow_mode = Some(slow_mode).filter(|wait| !wait.is_zero());
+                        let _ = replies.send(ChatMessage::SlowMode(slow_mode));
+                    }
+                }
+                _ => continue,
+            }
+        }
+    });
+
+    Some(announcer)
+}
+
+// Waits for the end of the poll, announces the result and queues the winning
+// action, if any.
+async fn close_poll(
+    poll: Arc<Mutex<Option<Poll<VoteAction>>>>,
+    announcer: Announcer,
+    tx: CommandSenders,
+    announcements: Announcements,
+) {
+    let deadline = match poll.lock().unwrap().as_ref() {
+        Some(poll) => poll.deadline(),
+        None => return,
+    };
+    tokio::time::sleep_until(tokio::time::Instant::from_std(deadline)).await;
+    let poll = match poll.lock().unwrap().take() {
+        Some(poll) => poll,
+        None => return,
+    };
+    let tally = poll
+        .tally()
+        .iter()
+        .map(|(action, count)| format!("{} {}", action, count))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let announcement = match poll.winner() {
+        Some(action) => {
+            if let Err(e) = tx.priority.send(action.command()) {
+                eprintln!("Unable to queue the voted command: {}", e);
+            }
+            announcements.format("vote_won", &[("action", &action), ("tally", &tally)])
+        }
+        None => announcements.format("vote_tied", &[("tally", &tally)]),
+    };
+    let _ = announcer.send(announcement.into());
+}
+
+// Parses the arguments of `!event start "build a tree" 30m`.
+fn parse_competition(args: &str) -> Option<(String, std::time::Duration)> {
+    let (name, duration) = args.trim().rsplit_once(' ')?;
+    let name = name.trim().trim_matches('"').trim();
+    if name.is_empty() {
+        return None;
+    }
+    Some((name.to_owned(), parse_duration(duration)?))
+}
+
+// Parses the argument of `!ignore @someone`.
+fn parse_login(arg: &str) -> String {
+    arg.trim().trim_start_matches('@').to_lowercase()
+}
+
+// Loads the chatters ignored by the moderators in previous runs.
+fn load_user_filter(config: &UserFilterConfig, archive: &mut CubeArchive) -> UserFilter {
+    let ignored = archive.get_ignored().expect("Failed to read from database");
+    UserFilter::new(config, ignored)
+}
+
+fn load_macros(config: &MacroConfig, archive: &mut CubeArchive) -> Macros {
+    let definitions = archive.get_aliases().expect("Failed to read from database");
+    Macros::new(config, definitions)
+}
+
+// Saves a macro defined with `!alias`, or forgets it after `!unalias`,
+// returns the announcement for the chat.
+fn save_alias(
+    archive: &mut CubeArchive,
+    announcements: &Announcements,
+    name: &str,
+    body: Option<&str>,
+) -> String {
+    archive
+        .set_alias(name, body)
+        .expect("Failed to update database");
+    match body {
+        Some(body) => announcements.format("alias_saved", &[("name", &name), ("body", &body)]),
+        None => announcements.format("alias_removed", &[("name", &name)]),
+    }
+}
+
+// Reads the arguments of `!checkpoint`: the name of the checkpoint, and
+// whether to restore it rather than save it.
+fn parse_checkpoint(args: &str) -> Option<(String, bool)> {
+    let mut words = args.split_whitespace();
+    let restore = match words.next()? {
+        "save" => false,
+        "restore" => true,
+        _ => return None,
+    };
+    let name = words.next()?.to_lowercase();
+    if words.next().is_some() || name.chars().count() > 32 {
+        return None;
+    }
+    Some((name, restore))
+}
+
+// Applies `!checkpoint save`, returns the announcement for the chat.
+fn save_checkpoint(archive: &mut CubeArchive, announcements: &Announcements, name: &str) -> String {
+    let count = archive
+        .save_checkpoint(name, chrono::Utc::now().timestamp())
+        .expect("Failed to update database");
+    announcements.format("checkpoint_saved", &[("name", &name), ("count", &count)])
+}
+
+// Announcement of a `!checkpoint restore` of a checkpoint which wasn't saved.
+fn unknown_checkpoint(
+    archive: &mut CubeArchive,
+    announcements: &Announcements,
+    name: &str,
+) -> String {
+    let checkpoints = archive
+        .get_checkpoints()
+        .expect("Failed to read from database");
+    let checkpoints = if checkpoints.is_empty() {
+        "none".to_owned()
+    } else {
+        checkpoints.join(", ")
+    };
+    announcements.format(
+        "checkpoint_unknown",
+        &[("name", &name), ("checkpoints", &checkpoints)],
+    )
+}
+
+// Applies `!ignore` or `!unignore`, returns the announcement for the chat.
+fn set_ignored(
+    filter: &mut UserFilter,
+    archive: &mut CubeArchive,
+    announcements: &Announcements,
+    login: &str,
+    ignored: bool,
+) -> String {
+    archive
+        .set_ignored(login, ignored)
+        .expect("Failed to update database");
+    let announcement = if ignored {
+        filter.ignore(login);
+        "user_ignored"
+    } else if filter.unignore(login) {
+        "user_unignored"
+    } else {
+        "user_not_ignored"
+    };
+    announcements.format(announcement, &[("user", &login)])
+}
+
+// Protects a region, or stops protecting those overlapping it, and saves
+// them. Returns the announcement for the chat.
+fn protect(
+    regions: &mut ProtectedRegions,
+    archive: &mut CubeArchive,
+    config: &TwixelBoxBotConfig,
+    region: Region,
+    protected: bool,
+) -> String {
+    let unprotected = match protected {
+        true => {
+            regions.protect(region);
+            0
+        }
+        false => regions.unprotect(region),
+    };
+    if protected || unprotected > 0 {
+        archive
+            .set_protected(regions.regions())
+            .expect("Failed to update database");
+    }
+    let described = format_region(region, config.coordinates, config.twixelbox.cube_size);
+    let announcements = &config.announcements;
+    match (protected, unprotected) {
+        (true, _) => announcements.format("region_protected", &[("region", &described)]),
+        (false, 0) => announcements.format("region_not_protected", &[("region", &described)]),
+        (false, count) => announcements.format("region_unprotected", &[("count", &count)]),
+    }
+}
+
+// Chat commands implemented as plugins. Commands from other crates are
+// registered here too.
+fn command_plugins(config: &TwixelBoxConfig, coordinates: CoordinateSystem) -> PluginRegistry {
+    let mut plugins = PluginRegistry::new();
+    plugins.register(LookupPlugin { coordinates });
+    plugins.register(HistoryPlugin {
+        coordinates,
+        changes: 5,
+    });
+    plugins.register(FreePlugin {
+        coordinates,
+        suggestions: 3,
+        flat: config.pixel_art,
+    });
+    if config.pixel_art {
+        plugins.register(PixelPlugin);
+    }
+    plugins
+}
+
+// What plugins see of the canvas: they read the archive, and their changes
+// are queued on the viewer lane as if the caller had sent them from chat.
+struct ChatCanvas<'a> {
+    archive: &'a mut CubeArchive,
+    side_len: u32,
+    tx: &'a CommandSenders,
+    announcer: Option<&'a Announcer>,
+    // Id of the command, each change gets its own id derived from it, the
+    // first one keeps it.
+    id: Uuid,
+    changes: usize,
+    author: String,
+    message: String,
+    reply_to: String,
+    command_use: Option<CommandUse>,
+}
+
+impl ChatCanvas<'_> {
+    fn queue(&mut self, event: CanvasEvent) {
+        let command = Command::Event {
+            id: step_id(self.id, self.changes),
+            event,
+            author: Some(self.author.clone()),
+            team: None,
+            message: Some(self.message.clone()),
+            reply_to: Some(self.reply_to.clone()),
+            command_use: self.command_use.clone(),
+            parse_time: None,
+        };
+        self.changes += 1;
+        if let Err(e) = self.tx.viewer.send(command) {
+            eprintln!("Unable to queue the change: {}", e);
+        }
+    }
+}
+
+impl CanvasApi for ChatCanvas<'_> {
+    fn side_len(&self) -> u32 {
+        self.side_len
+    }
+
+    fn lookup(&mut self, position: Position) -> Option<(Colour, JournalEntry)> {
+        self.archive
+            .lookup(position)
+            .expect("Failed to read from database")
+    }
+
+    fn place(&mut self, cube: Cube) {
+        self.queue(CanvasEvent::CubePlaced(cube));
+    }
+
+    fn remove(&mut self, position: Position) {
+        self.queue(CanvasEvent::CubeRemoved(position));
+    }
+
+    fn last_placed(&mut self, login: &str) -> Option<Position> {
+        let placements = self
+            .archive
+            .placements()
+            .expect("Failed to read from database");
+        placements
+            .into_iter()
+            .filter(|(_, (_, entry))| entry.metadata.author.as_deref() == Some(login))
+            .max_by_key(|(_, (_, entry))| entry.metadata.timestamp)
+            .map(|(position, _)| position)
+    }
+
+    fn history(&mut self, position: Position, limit: usize) -> Vec<JournalEntry> {
+        self.archive
+            .history(position, limit)
+            .expect("Failed to read from database")
+    }
+
+    fn reply(&mut self, text: String) {
+        if let Some(announcer) = self.announcer {
+            let _ = announcer.send(ChatMessage::Reply {
+                message_id: self.reply_to.clone(),
+                text,
+            });
+        }
+    }
+}
+
+// Runs a command of the plugins, telling the caller when it fails.
+fn run_plugin(
+    plugins: &PluginRegistry,
+    canvas: &mut ChatCanvas,
+    name: &str,
+    caller: &Caller,
+    args: &str,
+) {
+    if let Some(Err(e)) = plugins.execute(name, caller, args, canvas) {
+        canvas.reply(format!("@{} {}", caller.name, e));
+    }
+}
+
+// Says in chat every message sent to the returned channel, as fast as the
+// rate limit and slow mode of the channel allow. Placement confirmations
+// waiting meanwhile are summed up.
+fn chat_announcer(
+    client: ChatClient,
+    channel_name: String,
+    config: &OutboxConfig,
+    announcements: Announcements,
+) -> Announcer {
+    let (tx, mut rx) = mpsc::unbounded_channel::<ChatMessage>();
+    let mut outbox = Outbox::new(config);
+    tokio::spawn(async move {
+        loop {
+            let next_send = outbox.next_send().map(tokio::time::Instant::from_std);
+            tokio::select! {
+                message = rx.recv() => match message {
+                    Some(message) => outbox.push(message, Instant::now()),
+                    None => break,
+                },
+                _ = tokio::time::sleep_until(next_send.unwrap_or_else(tokio::time::Instant::now)),
+                    if next_send.is_some() => {}
+            }
+            while let Some(outgoing) = outbox.pop(Instant::now()) {
+                let (message, reply_to) = match outgoing {
+                    Outgoing::Say(text) => (text, None),
+                    Outgoing::Reply { message_id, text } => (text, Some(message_id)),
+                    Outgoing::Summary { cubes, users, secs } => (
+                        announcements.format(
+                            "cubes_placed",
+                            &[("count", &cubes), ("users", &users), ("seconds", &secs)],
+                        ),
+                        None,
+                    ),
+                };
+                let sent = client
+                    .say_in_response(channel_name.clone(), message, reply_to)
+                    .await;
+                if let Err(e) = sent {
+                    eprintln!("Unable to announce in the chat: {}", e);
+                }
+            }
+        }
+    });
+    tx
+}
+
+// Keeps track of the running build competition, in the process journaling
+// the events.
+struct Competitions {
+    active: Option<Competition>,
+    tx: CommandSenders,
+    announcer: Option<Announcer>,
+    announcements: Announcements,
+}
+
+impl Competitions {
+    // Resumes the competition which was running before a restart, if any.
+    fn resume(
+        archive: &mut CubeArchive,
+        tx: CommandSenders,
+        announcer: Option<Announcer>,
+        announcements: Announcements,
+    ) -> Self {
+        let active = archive
+            .active_competition()
+            .expect("Failed to read from database");
+        if let Some(competition) = &active {
+            schedule_competition_end(&tx, competition);
+        }
+        Competitions {
+            active,
+            tx,
+            announcer,
+            announcements,
+        }
+    }
+
+    fn announce(&self, message: String) {
+        if let Some(announcer) = &self.announcer {
+            let _ = announcer.send(message.into());
+        }
+    }
+
+    fn start(&mut self, archive: &mut CubeArchive, name: &str, duration_secs: u64) {
+        if let Some(active) = &self.active {
+            self.announce(
+                self.announcements
+                    .format("competition_running", &[("name", &active.name)]),
+            );
+            return;
+        }
+        let now = chrono::Utc::now().timestamp();
+        let competition = archive
+            .start_competition(name, now, now + duration_secs as i64)
+            .expect("Failed to add competition to database");
+        schedule_competition_end(&self.tx, &competition);
+        self.announce(self.announcements.format(
+            "competition_started",
+            &[("name", &name), ("minutes", &(duration_secs / 60))],
+        ));
+        self.active = Some(competition);
+    }
+
+    fn end(&mut self, archive: &mut CubeArchive, id: Option<i64>) {
+        let competition = match self.active.take() {
+            Some(competition) if id.is_none_or(|id| id == competition.id) => competition,
+            // Stale timer from a competition stopped early.
+            other => {
+                self.active = other;
+                return;
+            }
+        };
+        let contributions = archive
+            .competition_contributions(competition.id)
+            .expect("Failed to read from database");
+        archive
+            .finish_competition(competition.id)
+            .expect("Failed to update database");
+        let winners = contributions
+            .iter()
+            .take(3)
+            .enumerate()
+            .map(|(i, (author, count))| format!("{}. {} ({} cubes)", i + 1, author, count))
+            .collect::<Vec<_>>();
+        let name = &competition.name;
+        if winners.is_empty() {
+            self.announce(
+                self.announcements
+                    .format("competition_empty", &[("name", name)]),
+            );
+        } else {
+            self.announce(self.announcements.format(
+                "competition_over",
+                &[
+                    ("name", name),
+                    ("count", &contributions.len()),
+                    ("winners", &winners.join(", ")),
+                ],
+            ));
+        }
+    }
+
+    fn metadata(&self, author: Option<String>, team: Option<String>) -> EventMetadata {
+        EventMetadata {
+            author,
+            timestamp: chrono::Utc::now().timestamp(),
+            competition_id: self.active.as_ref().map(|c| c.id),
+            team,
+            message: None,
+            viewer: false,
+        }
+    }
+}
+
+// Keeps track of who is in which team, in the process journaling the events.
+struct TeamMembers {
+    teams: Teams,
+    members: HashMap<String, String>,
+    announcer: Option<Announcer>,
+    announcements: Announcements,
+}
+
+impl TeamMembers {
+    fn load(
+        archive: &mut CubeArchive,
+        teams: Teams,
+        announcer: Option<Announcer>,
+        announcements: Announcements,
+    ) -> Self {
+        let members = archive.get_teams().expect("Failed to read from database");
+        TeamMembers {
+            teams,
+            members,
+            announcer,
+            announcements,
+        }
+    }
+
+    fn announce(&self, message: String) {
+        if let Some(announcer) = &self.announcer {
+            let _ = announcer.send(message.into());
+        }
+    }
+
+    fn join(&mut self, archive: &mut CubeArchive, login: &str, team: &str) {
+        if self.teams.is_empty() {
+            return;
+        }
+        let team = match self.teams.find(team) {
+            Some(team) => team.to_owned(),
+            None => {
+                let names = self.teams.names().collect::<Vec<_>>().join(", ");
+                self.announce(
+                    self.announcements
+                        .format("team_unknown", &[("user", &login), ("teams", &names)]),
+                );
+                return;
+            }
+        };
+        archive
+            .set_team(login, &team)
+            .expect("Failed to update database");
+        self.announce(
+            self.announcements
+                .format("team_joined", &[("user", &login), ("team", &team)]),
+        );
+        self.members.insert(login.to_owned(), team);
+    }
+
+    fn team_of(&self, author: &Option<String>) -> Option<String> {
+        self.members.get(author.as_ref()?).cloned()
+    }
+}
+
+// Rebuilds the placement times from the journal and starts the periodic decay
+// checks. Returns None when decay is disabled.
+fn start_decay(
+    config: &DecayConfig,
+    archive: &mut CubeArchive,
+    tx: &CommandSenders,
+) -> Option<DecayTracker> {
+    let mut decay = DecayTracker::new(config, chrono::Utc::now().timestamp())?;
+    let journal = archive.get_journal().expect("failed to extract events");
+    for entry in &journal {
+        decay.apply(&entry.event, &entry.metadata);
+    }
+    let interval = std::time::Duration::from_secs(config.check_interval_secs.max(1));
+    let tx = tx.priority.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            // The check is skipped if the queue is full, cubes decay a bit
+            // later.
+            let _ = tx.try_send(Command::Decay);
+        }
+    });
+    Some(decay)
+}
+
+// Reports a pruning in the log, or what it would prune on a dry run.
+fn log_pruning(report: &PruneReport, dry_run: bool) {
+    let until = report
+        .until
+        .and_then(|until| chrono::NaiveDateTime::from_timestamp_opt(until, 0))
+        .map(|until| format!(", journaled up to {}", until))
+        .unwrap_or_default();
+    match dry_run {
+        true => info!(
+            "Pruning would drop {} of the {} events of the archive{}",
+            report.pruned, report.events, until
+        ),
+        false => info!(
+            "Pruned {} of the {} events of the archive{}",
+            report.pruned, report.events, until
+        ),
+    }
+}
+
+// Queues events produced by the bot itself, e.g. a decay step, or by a
+// single command. They can be many, so they're sent from a separate task
+// waiting for room in the queue.
+fn queue_events(
+    tx: &CommandSenders,
+    events: Vec<CanvasEvent>,
+    author: Option<String>,
+    command_use: Option<CommandUse>,
+) {
+    if events.is_empty() {
+        return;
+    }
+    debug!("Queueing {} events", events.len());
+    let tx = tx.priority.clone();
+    tokio::spawn(async move {
+        for event in events {
+            let command = Command::Event {
+                id: Uuid::new_v4(),
+                event,
+                author: author.clone(),
+                team: None,
+                message: None,
+                reply_to: None,
+                command_use: command_use.clone(),
+                parse_time: None,
+            };
+            if tx.send_wait(command).await.is_err() {
+                return;
+            }
+        }
+    });
+}
+
+// Takes the cubes of a banned chatter, or those of a deleted message, off
+// the canvas. A message placing cubes through a macro or a plugin placed
+// each with an id derived from its own.
+fn moderate(
+    archive: &mut CubeArchive,
+    tx: &CommandSenders,
+    login: &str,
+    command_id: Option<Uuid>,
+    action: ModerationAction,
+) {
+    if action == ModerationAction::Keep {
+        return;
+    }
+    let cubes: Vec<Cube> = archive
+        .placements()
+        .expect("Failed to read from database")
+        .into_iter()
+        .filter(|(_, (_, entry))| {
+            entry.metadata.author.as_deref() == Some(login)
+                && command_id.is_none_or(|id| is_step_of(entry.command_id, id))
+        })
+        .map(|(position, (colour, _))| Cube { position, colour })
+        .collect();
+    if action == ModerationAction::Quarantine {
+        archive
+            .quarantine(login, &cubes)
+            .expect("Failed to update database");
+    }
+    debug!("Taking {} cubes of {} off the canvas", cubes.len(), login);
+    let events = cubes
+        .iter()
+        .map(|cube| CanvasEvent::CubeRemoved(cube.position))
+        .collect();
+    queue_events(tx, events, None, None);
+}
+
+// Positions of the cubes on the canvas placed by `login`.
+fn cubes_of(archive: &mut CubeArchive, login: &str) -> Vec<Position> {
+    archive
+        .placements()
+        .expect("Failed to read from database")
+        .into_iter()
+        .filter(|(_, (_, entry))| entry.metadata.author.as_deref() == Some(login))
+        .map(|(position, _)| position)
+        .collect()
+}
+
+// Puts back the quarantined cubes of a chatter, returns the announcement for
+// the chat.
+fn restore(
+    archive: &mut CubeArchive,
+    tx: &CommandSenders,
+    announcements: &Announcements,
+    login: &str,
+) -> String {
+    let cubes = archive
+        .release_quarantine(login)
+        .expect("Failed to update database");
+    let announcement = announcements.format(
+        "cubes_restored",
+        &[("user", &login), ("count", &cubes.len())],
+    );
+    let events = cubes.into_iter().map(CanvasEvent::CubePlaced).collect();
+    queue_events(tx, events, Some(login.to_owned()), None);
+    announcement
+}
+
+const STATS_PATH: &str = "/api/stats";
+// Time spent by the commands in each stage, see CommandTimingMetrics.
+const TIMINGS_PATH: &str = "/api/timings";
+// Occupancy of the canvas, see Octree::to_bytes for the format.
+const OCTREE_PATH: &str = "/octree";
+
+// Publishes the canvas statistics and occupancy over HTTP and MQTT, refreshed
+// every minute, and announces the statistics in chat every configured interval.
+struct StatsReporter {
+    side_len: u32,
+    mqtt: Option<MqttPublisher>,
+    announce_interval: Option<std::time::Duration>,
+    last_announced: Instant,
+    announcements: Announcements,
+}
+
+impl StatsReporter {
+    // Starts the periodic refresh, returns None when the statistics are
+    // neither served nor announced.
+    fn start(
+        config: &TwixelBoxBotConfig,
+        tx: &CommandSenders,
+        served: bool,
+        mqtt: Option<MqttPublisher>,
+    ) -> Option<Self> {
+        let announce_interval = match config.stats.announce_interval_mins {
+            0 => None,
+            mins => Some(std::time::Duration::from_secs(mins * 60)),
+        };
+        if announce_interval.is_none() && !served && mqtt.is_none() {
+            return None;
+        }
+        let tx = tx.priority.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                let _ = tx.try_send(Command::Stats);
+            }
+        });
+        Some(StatsReporter {
+            side_len: config.twixelbox.cube_size,
+            mqtt,
+            announce_interval,
+            last_announced: Instant::now(),
+            announcements: config.announcements.clone(),
+        })
+    }
+
+    fn report(
+        &mut self,
+        archive: &mut CubeArchive,
+        http: Option<&HttpServer>,
+        announcer: Option<&Announcer>,
+    ) {
+        let stats = archive
+            .stats(self.side_len, chrono::Utc::now().timestamp())
+            .expect("Failed to read from database");
+        if let Some(http) = http {
+            match serde_json::to_vec(&stats) {
+                Ok(json) => http.publish(STATS_PATH, "application/json", json),
+                Err(e) => eprintln!("Unable to serialize the statistics: {}", e),
+            }
+            let cubes = archive.get_cubes().expect("Failed to read from database");
+            let octree = Octree::new(self.side_len, cubes.iter().map(|cube| cube.position));
+            http.publish_export(OCTREE_PATH, "application/octet-stream", octree.to_bytes());
+        }
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.publish_stats(&stats);
+        }
+        let due = self
+            .announce_interval
+            .is_some_and(|interval| self.last_announced.elapsed() >= interval);
+        if let (true, Some(announcer)) = (due, announcer) {
+            self.last_announced = Instant::now();
+            let _ = announcer.send(stats_announcement(&self.announcements, &stats).into());
+        }
+    }
+}
+
+// Publishes the timing metrics of the commands, refreshed with the statistics.
+fn publish_timings(http: &HttpServer, timer: &CommandTimer) {
+    match serde_json::to_vec(&timer.metrics()) {
+        Ok(json) => http.publish(TIMINGS_PATH, "application/json", json),
+        Err(e) => eprintln!("Unable to serialize the command timings: {}", e),
+    }
+}
+
+fn stats_announcement(announcements: &Announcements, stats: &CanvasStats) -> String {
+    announcements.format(
+        "stats",
+        &[
+            ("fill", &format!("{:.1}", stats.fill_percentage)),
+            ("count", &stats.cubes),
+            ("builders", &stats.builders_today),
+            ("last_hour", &stats.cubes_last_hour),
+        ],
+    )
+}
+
+const TIMELAPSE_PATH: &str = "/today.gif";
+const CREDITS_PATH: &str = "/credits.gif";
+
+// Starts the HTTP server if configured.
+fn start_http_server(config: &HttpConfig) -> Option<HttpServer> {
+    let address = config.address.as_ref()?;
+    let access = match ApiAccess::new(&config.access) {
+        Ok(access) => access,
+        Err(e) => {
+            eprintln!("Error in the API access configuration: {}", e);
+            return None;
+        }
+    };
+    let audit = match ApiAudit::new(&config.audit) {
+        Ok(audit) => audit,
+        Err(e) => {
+            eprintln!("{}", e);
+            return None;
+        }
+    };
+    match HttpServer::start(address, &config.public_url) {
+        Ok(server) => {
+            server.set_access(access);
+            server.set_audit(audit.clone());
+            answer_activity_queries(&server, audit);
+            Some(server)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            None
+        }
+    }
+}
+
+const ACTIVITY_PATH: &str = "/api/activity";
+
+// Lists the recent requests changing something through the API to admins,
+// e.g. `/api/activity?limit=20`, the most recent first.
+fn answer_activity_queries(http: &HttpServer, audit: ApiAudit) {
+    http.answer_queries_as(ACTIVITY_PATH, Permission::Audit, move |query| {
+        let limit = query_param(query, "limit", 50)?;
+        serde_json::to_string(&audit.recent(limit)).map_err(|e| e.to_string())
+    });
+}
+
+// Where Helix creates stream markers.
+const MARKERS_URL: &str = "https://api.twitch.tv/helix/streams/markers";
+
+// Creates stream markers on the moments `StreamMarkers` picks, with the token
+// of the bot's account.
+struct MarkerService {
+    markers: StreamMarkers,
+    token_storage: CustomTokenStorage,
+    client_id: String,
+    broadcaster_id: Option<String>,
+}
+
+impl MarkerService {
+    fn start(config: &TwixelBoxBotConfig) -> Option<Self> {
+        if !config.markers.enabled {
+            return None;
+        }
+        Some(Self {
+            markers: StreamMarkers::new(&config.markers),
+            token_storage: CustomTokenStorage {
+                token_checkpoint_file: config.twitch.token_filepath.clone(),
+            },
+            client_id: config.twitch.client_id.clone(),
+            broadcaster_id: config.markers.broadcaster_id.clone(),
+        })
+    }
+
+    fn observe(&mut self, event: &CanvasEvent) {
+        if let Some(moment) = self.markers.observe(event, Instant::now()) {
+            self.create(moment);
+        }
+    }
+
+    fn mark(&mut self, moment: MarkerMoment) {
+        if let Some(moment) = self.markers.mark(moment, Instant::now()) {
+            self.create(moment);
+        }
+    }
+
+    // In the background, a failure is only reported, e.g. while offline
+    // since Twitch only marks live streams.
+    fn create(&self, moment: MarkerMoment) {
+        let description = moment.description();
+        let mut token_storage = self.token_storage.clone();
+        let client_id = self.client_id.clone();
+        let broadcaster_id = self.broadcaster_id.clone();
+        tokio::spawn(async move {
+            let result =
+                create_marker(&mut token_storage, &client_id, broadcaster_id, &description).await;
+            match result {
+                Ok(()) => debug!("Created the stream marker {}", description),
+                Err(e) => eprintln!("Unable to create the stream marker: {}", e),
+            }
+        });
+    }
+}
+
+async fn create_marker(
+    token_storage: &mut CustomTokenStorage,
+    client_id: &str,
+    broadcaster_id: Option<String>,
+    description: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let token = token_storage.load_token().await?;
+    let broadcaster_id = match broadcaster_id {
+        Some(id) => id,
+        None => token_storage.load_user_id()?,
+    };
+    let body = serde_json::json!({
+        "user_id": broadcaster_id,
+        "description": description,
+    });
+    let mut response = surf::post(MARKERS_URL)
+        .header("Client-Id", client_id)
+        .header("Authorization", format!("Bearer {}", token.access_token))
+        .body_json(&body)
+        .map_err(|e| e.to_string())?
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        let text = response.body_string().await.unwrap_or_default();
+        return Err(format!("{} {}", response.status(), text).into());
+    }
+    Ok(())
+}
+
+// Where Helix lists the live streams.
+const STREAMS_URL: &str = "https://api.twitch.tv/helix/streams";
+
+// Fetches the stream info for the HUD on its schedule, with the token of the
+// bot's account. A failure is only reported, the HUD keeps the last info.
+fn poll_stream_info(config: &TwixelBoxBotConfig, tx: &CommandSenders) {
+    let mut token_storage = CustomTokenStorage {
+        token_checkpoint_file: config.twitch.token_filepath.clone(),
+    };
+    let client_id = config.twitch.client_id.clone();
+    let broadcaster_id = config.hud.broadcaster_id.clone();
+    let interval = std::time::Duration::from_secs(config.hud.refresh_secs.max(1));
+    let tx = tx.priority.clone();
+    tokio::spawn(async move {
+        loop {
+            let result =
+                fetch_stream_info(&mut token_storage, &client_id, broadcaster_id.clone()).await;
+            match result {
+                Ok(info) => {
+                    let _ = tx.try_send(Command::StreamInfo(info));
+                }
+                Err(e) => eprintln!("Unable to fetch the stream info: {}", e),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+async fn fetch_stream_info(
+    token_storage: &mut CustomTokenStorage,
+    client_id: &str,
+    broadcaster_id: Option<String>,
+) -> Result<StreamInfo, Box<dyn std::error::Error + Send + Sync>> {
+    let token = token_storage.load_token().await?;
+    let broadcaster_id = match broadcaster_id {
+        Some(id) => id,
+        None => token_storage.load_user_id()?,
+    };
+    let mut response = surf::get(format!("{}?user_id={}", STREAMS_URL, broadcaster_id))
+        .header("Client-Id", client_id)
+        .header("Authorization", format!("Bearer {}", token.access_token))
+        .await
+        .map_err(|e| e.to_string())?;
+    let text = response.body_string().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("{} {}", response.status(), text).into());
+    }
+    Ok(StreamInfo::from_helix(&text)?)
+}
+
+// Opens the event log if configured.
+fn open_event_log(config: &EventLogConfig) -> Option<EventLog> {
+    let filepath = config.filepath.as_ref()?;
+    match EventLog::open(filepath, config) {
+        Ok(event_log) => Some(event_log),
+        Err(e) => {
+            eprintln!("Unable to open the event log {}: {}", filepath, e);
+            None
+        }
+    }
+}
+
+// Loads the watermark if configured, images are shared without it when it
+// can't be read.
+fn load_watermark(config: &WatermarkConfig) -> Option<Watermark> {
+    match Watermark::new(config) {
+        Ok(watermark) => watermark,
+        Err(e) => {
+            eprintln!("Unable to load the watermark: {}", e);
+            None
+        }
+    }
+}
+
+// Connects to the MQTT broker if configured.
+fn connect_mqtt(config: &MqttConfig) -> Option<MqttPublisher> {
+    config.broker.as_ref()?;
+    match MqttPublisher::connect(config) {
+        Ok(mqtt) => Some(mqtt),
+        Err(e) => {
+            eprintln!("{}", e);
+            None
+        }
+    }
+}
+
+// Starts the gRPC service if configured, render requests from its clients are
+// queued like the ones from moderators.
+fn start_grpc_service(
+    config: &GrpcConfig,
+    access: &AccessConfig,
+    http: Option<&HttpServer>,
+    tx: &CommandSenders,
+) -> Option<GrpcService> {
+    config.address.as_ref()?;
+    let access = match ApiAccess::new(access) {
+        Ok(access) => access,
+        Err(e) => {
+            eprintln!("Error in the API access configuration: {}", e);
+            return None;
+        }
+    };
+    let (renders, mut requests) = mpsc::unbounded_channel();
+    let service = GrpcService::new(config, access, http.cloned(), renders);
+    let server = service.clone();
+    tokio::spawn(async move {
+        if let Err(e) = server.serve().await {
+            eprintln!("{}", e);
+        }
+    });
+    let tx = tx.priority.clone();
+    tokio::spawn(async move {
+        while let Some(kind) = requests.recv().await {
+            let command = match kind {
+                RenderKind::Snapshot => Command::Snapshot,
+                RenderKind::Timelapse => Command::Timelapse,
+            };
+            if let Err(e) = tx.send(command) {
+                eprintln!("Unable to queue the render: {}", e);
+            }
+        }
+    });
+    Some(service)
+}
+
+// Queues a timelapse right away, then every configured period.
+fn schedule_timelapse(tx: &CommandSenders, config: &TimelapseConfig) {
+    let interval = std::time::Duration::from_secs(config.hours.max(1) * 3600);
+    let tx = tx.priority.clone();
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = tx.send(Command::Timelapse) {
+                eprintln!("Unable to queue the timelapse: {}", e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+// Queues a pruning of the archive right away, then every configured period.
+fn schedule_pruning(tx: &CommandSenders, config: &RetentionConfig) {
+    if !config.is_enabled() {
+        return;
+    }
+    let interval = std::time::Duration::from_secs(config.interval_mins.max(1) * 60);
+    let tx = tx.priority.clone();
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = tx.send(Command::Prune) {
+                eprintln!("Unable to queue the pruning: {}", e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+// Queues the summaries at their local times, each after a snapshot if the
+// configuration asks for one. Invalid times are skipped.
+fn schedule_summaries(tx: &CommandSenders, config: &SummaryConfig) {
+    let snapshot_wait = match config.snapshot && config.discord_webhook.is_some() {
+        true => Some(std::time::Duration::from_secs(config.snapshot_wait_secs)),
+        false => None,
+    };
+    for time in &config.times {
+        let time: SummaryTime = match time.parse() {
+            Ok(time) => time,
+            Err(e) => {
+                eprintln!("Skipping the summary: {}", e);
+                continue;
+            }
+        };
+        let tx = tx.priority.clone();
+        tokio::spawn(async move {
+            loop {
+                let now = chrono::Local::now();
+                let next = time.next_after(now.naive_local());
+                let next = chrono::Local
+                    .from_local_datetime(&next)
+                    .earliest()
+                    .map_or(0, |next| next.timestamp());
+                let wait = (next - now.timestamp()).max(1) as u64;
+                tokio::time::sleep(std::time::Duration::from_secs(wait)).await;
+                if let Some(snapshot_wait) = snapshot_wait {
+                    let _ = tx.send(Command::Snapshot);
+                    tokio::time::sleep(snapshot_wait).await;
+                }
+                let period_secs = time.period_secs();
+                if let Err(e) = tx.send(Command::PostSummary { period_secs }) {
+                    eprintln!("Unable to queue the summary: {}", e);
+                }
+            }
+        });
+    }
+}
+
+fn schedule_drift_checks(tx: &CommandSenders, config: &DriftConfig) {
+    if config.check_interval_mins == 0 {
+        return;
+    }
+    let interval = std::time::Duration::from_secs(config.check_interval_mins * 60);
+    let tx = tx.priority.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(e) = tx.send(Command::CheckDrift) {
+                eprintln!("Unable to queue the drift check: {}", e);
+            }
+        }
+    });
+}
+
+// Starts watching the archive for events journaled by other processes.
+fn watch_archive(archive: &mut CubeArchive, tx: &CommandSenders, config: &DriftConfig) {
+    if config.external_poll_secs == 0 {
+        return;
+    }
+    archive
+        .external_events()
+        .expect("Failed to read from database");
+    let interval = std::time::Duration::from_secs(config.external_poll_secs);
+    let tx = tx.priority.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            let _ = tx.try_send(Command::ExternalEvents);
+        }
+    });
+}
+
+// Renders the timelapse on a blocking thread, and publishes it once done.
+fn publish_timelapse(
+    archive: &mut CubeArchive,
+    config: &TimelapseConfig,
+    http: &HttpServer,
+    side_len: u32,
+    watermark: Option<&Watermark>,
+) {
+    let journal = archive.get_journal().expect("failed to extract events");
+    let since = chrono::Utc::now().timestamp() - config.hours as i64 * 3600;
+    let config = config.clone();
+    let http = http.clone();
+    let watermark = watermark.cloned();
+    tokio::task::spawn_blocking(move || {
+        match render_timelapse(&journal, since, &config, side_len, watermark.as_ref()) {
+            Ok(gif) => http.publish(TIMELAPSE_PATH, "image/gif", gif),
+            Err(e) => eprintln!("Unable to render the timelapse: {}", e),
+        }
+    });
+}
+
+// Renders the credits on a blocking thread, and announces them once done.
+fn publish_credits(
+    archive: &mut CubeArchive,
+    config: &TwixelBoxBotConfig,
+    http: &HttpServer,
+    since: i64,
+    announcer: Option<Announcer>,
+    watermark: Option<&Watermark>,
+) {
+    let journal = archive.get_journal().expect("failed to extract events");
+    let credits = config.credits.clone();
+    let announcements = config.announcements.clone();
+    let side_len = config.twixelbox.cube_size;
+    let http = http.clone();
+    let watermark = watermark.cloned();
+    tokio::task::spawn_blocking(move || {
+        match render_credits(&journal, since, &credits, side_len, watermark.as_ref()) {
+            Ok(gif) => {
+                http.publish(CREDITS_PATH, "image/gif", gif);
+                if let Some(announcer) = announcer {
+                    let url = http.url(CREDITS_PATH);
+                    let _ =
+                        announcer.send(announcements.format("credits", &[("url", &url)]).into());
+                }
+            }
+            Err(e) => eprintln!("Unable to render the credits: {}", e),
+        }
+    });
+}
+
+fn schedule_competition_end(tx: &CommandSenders, competition: &Competition) {
+    let remaining = (competition.ends_at - chrono::Utc::now().timestamp()).max(0) as u64;
+    let tx = tx.priority.clone();
+    let id = competition.id;
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_secs(remaining)).await;
+        if let Err(e) = tx.send(Command::EndCompetition(Some(id))) {
+            eprintln!("Unable to queue the end of the competition: {}", e);
+        }
+    });
+}
+
+// Whether `address` only resolves to the loopback interface, which the
+// renderer listens on without a token.
+fn is_loopback(address: &str) -> bool {
+    match std::net::ToSocketAddrs::to_socket_addrs(address) {
+        Ok(mut addresses) => addresses.all(|address| address.ip().is_loopback()),
+        Err(_) => false,
+    }
+}
+
+async fn connect_to_renderer(
+    ipc: &IpcConfig,
+    archive: &mut CubeArchive,
+    fog: Option<Region>,
+    goal: Option<GoalProgress>,
+) -> Option<IpcSender> {
+    let address = &ipc.address;
+    let mut sender = match IpcSender::connect(address, &ipc.token).await {
+        Ok(sender) => sender,
+        Err(e) => {
+            debug!("Unable to connect to the renderer at {}: {}", address, e);
+            return None;
+        }
+    };
+    let journal = archive.get_journal().expect("failed to extract events");
+    for entry in journal {
+        let message = IpcMessage::Event {
+            id: entry.command_id,
+            event: entry.event,
+            team: entry.metadata.team,
+        };
+        if let Err(e) = sender.send(&message).await {
+            eprintln!("Lost connection to the renderer: {}", e);
+            return None;
+        }
+    }
+    for message in [IpcMessage::Fog(fog), IpcMessage::Goal(goal)] {
+        if let Err(e) = sender.send(&message).await {
+            eprintln!("Lost connection to the renderer: {}", e);
+            return None;
+        }
+    }
+    debug!("Connected to the renderer at {}", address);
+    Some(sender)
+}
+
+// Renderer side of the split setup: accepts bot connections, one at a time,
+// and forwards the received placements to the main thread.
+// Waiting for room in the queue pushes back on the bot, which keeps the events
+// in its archive meanwhile. The bot already sorted out priorities and locks,
+// events are kept in the order of its journal.
+async fn receive_from_bot(listener: IpcListener, tx: CommandSenders) {
+    loop {
+        let mut receiver = match listener.accept().await {
+            Ok(receiver) => receiver,
+            Err(e) => {
+                eprintln!("Unable to accept bot connection: {}", e);
+                continue;
+            }
+        };
+        // The bot sends its whole journal on connecting, which is drawn again
+        // from a blank canvas. The ids of its commands are unique in there.
+        let clear = Command::Event {
+            id: Uuid::new_v4(),
+            event: CanvasEvent::CanvasCleared,
+            author: None,
+            team: None,
+            message: None,
+            reply_to: None,
+            command_use: None,
+            parse_time: None,
+        };
+        if let Err(e) = tx.viewer.send_wait(clear).await {
+            eprintln!("Unable to queue the commands of the bot: {}", e);
+            continue;
+        }
+        loop {
+            let message = match receiver.recv().await {
+                Ok(Some(message)) => message,
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("Error receiving from the bot: {}", e);
+                    break;
+                }
+            };
+            let sent = match message {
+                IpcMessage::Event { id, event, team } => {
+                    let command = Command::Event {
+                        id,
+                        event,
+                        author: None,
+                        team,
+                        message: None,
+                        reply_to: None,
+                        command_use: None,
+                        parse_time: None,
+                    };
+                    tx.viewer.send_wait(command).await
+                }
+                IpcMessage::Snapshot => tx.priority.send_wait(Command::Snapshot).await,
+                IpcMessage::Fog(region) => tx.priority.send_wait(Command::Fog(region)).await,
+                IpcMessage::Goal(progress) => {
+                    tx.priority.send_wait(Command::GoalProgress(progress)).await
+                }
+                IpcMessage::Spin(speed) => tx.priority.send_wait(Command::SetSpin(speed)).await,
+                IpcMessage::Theme(name) => tx.priority.send_wait(Command::Theme(name)).await,
+                IpcMessage::View(name) => tx.priority.send_wait(Command::View(name)).await,
+                IpcMessage::Weather(effect) => {
+                    tx.priority.send_wait(Command::SetWeather(effect)).await
+                }
+                IpcMessage::Slice(slice) => tx.viewer.send_wait(Command::Slice(Some(slice))).await,
+                IpcMessage::Highlight(positions) => {
+                    tx.viewer
+                        .send_wait(Command::Highlight(Some(positions)))
+                        .await
+                }
+            };
+            // Only fails once the renderer stopped, the bot reconnects later.
+            if let Err(e) = sent {
+                eprintln!("Unable to queue the commands of the bot: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+const PALETTE_PATH: &str = "/api/palette";
+const BUILD_SHEET_UPLOAD_PATH: &str = "/api/build-sheet";
+// Published with the .html and .csv extensions.
+const BUILD_SHEET_PATH: &str = "/build-sheet";
+
+// Lets moderators upload images to set the palette or to make a build sheet,
+// e.g. with `curl -H "Authorization: Bearer <token>" --data-binary @art.png
+// <public url>/api/palette?colours=6`.
+fn accept_image_uploads(http: &HttpServer, config: &PaletteConfig, tx: &CommandSenders) {
+    let token = match &config.upload_token {
+        Some(token) => token,
+        None => return,
+    };
+    let default_colours = config.colours;
+    let palette_tx = tx.priority.clone();
+    http.accept_uploads(PALETTE_PATH, token, move |query, body| {
+        let colours = query_param(query, "colours", default_colours)?;
+        let img = decode_upload(&body)?;
+        let seed = chrono::Utc::now().timestamp() as u64;
+        let palette = Palette::extract(&img, colours, seed).ok_or("the image is empty")?;
+        palette_tx
+            .send(Command::Palette(Some(palette.colours().to_vec())))
+            .map_err(|e| format!("unable to set the palette: {}", e))?;
+        Ok(palette.to_string())
+    });
+    let sheet_tx = tx.priority.clone();
+    let sheet_url = http.url(BUILD_SHEET_PATH);
+    http.accept_uploads(BUILD_SHEET_UPLOAD_PATH, token, move |query, body| {
+        let projection = Projection {
+            axis: query_param(query, "axis", Axis::Z)?,
+            layer: query_param(query, "layer", 0)?,
+            origin: (query_param(query, "u", 0)?, query_param(query, "v", 0)?),
+            width: query_param(query, "width", 64)?,
+        };
+        let img = decode_upload(&body)?;
+        let command = Command::BuildSheet {
+            width: img.width(),
+            height: img.height(),
+            pixels: img.into_raw(),
+            projection,
+        };
+        sheet_tx
+            .send(command)
+            .map_err(|e| format!("unable to make the build sheet: {}", e))?;
+        Ok(format!(
+            "The build sheet will be at {0}.html and {0}.csv",
+            sheet_url
+        ))
+    });
+}
+
+const LOGIN_PATH: &str = "/api/login";
+const PLACE_PATH: &str = "/api/place";
+// Where Twitch runs the device flow, and tells whose a token is.
+const DEVICE_URL: &str = "https://id.twitch.tv/oauth2/device";
+const TOKEN_URL: &str = "https://id.twitch.tv/oauth2/token";
+const VALIDATE_URL: &str = "https://id.twitch.tv/oauth2/validate";
+
+// Lets chatters log in with Twitch through the device flow, and place cubes
+// from the web API on their behalf, within the limits they have in chat.
+// Moderators aren't told apart, on the web they're chatters like any other.
+fn accept_web_placements(
+    http: &HttpServer,
+    config: &TwixelBoxBotConfig,
+    tx: &CommandSenders,
+    limits: &Arc<Mutex<CommandLimits>>,
+) {
+    if !config.http.web_login {
+        return;
+    }
+    let logins = WebLogins::new(
+        std::time::Duration::from_secs(config.http.session_hours.max(1) * 3600),
+        config.http.max_pending_logins,
+        config.http.login_starts_per_hour,
+    );
+    // The requests are answered on threads of the server, off the runtime.
+    let runtime = tokio::runtime::Handle::current();
+    let client_id = config.twitch.client_id.clone();
+    let pending = logins.clone();
+    // Each login starts a device flow under the client id of the bot, they're
+    // limited before asking Twitch.
+    http.answer_forms(LOGIN_PATH, move |client, _| {
+        let id = pending
+            .reserve(client, Instant::now())
+            .map_err(|e| e.to_string())?;
+        let code = match runtime.block_on(request_device_code(&client_id)) {
+            Ok(code) => code,
+            Err(e) => {
+                pending.cancel(&id);
+                return Err(e);
+            }
+        };
+        pending
+            .start(&id, &code, Instant::now())
+            .map_err(|e| e.to_string())?;
+        let started = serde_json::json!({
+            "id": id,
+            "user_code": code.user_code,
+            "verification_uri": code.verification_uri,
+            "expires_in": code.expires_in,
+            "interval": code.interval,
+        });
+        Ok(started.to_string())
+    });
+    let runtime = tokio::runtime::Handle::current();
+    let client_id = config.twitch.client_id.clone();
+    let pending = logins.clone();
+    http.answer_queries(LOGIN_PATH, move |query| {
+        let id: String = query_param(query, "id", String::new())?;
+        if id.is_empty() {
+            return Err("start a login with a POST".to_owned());
+        }
+        let device_code = pending
+            .device_code(&id, Instant::now())
+            .map_err(|e| e.to_string())?;
+        let access_token = match runtime.block_on(poll_device_token(&client_id, &device_code)) {
+            Ok(TokenPoll::Pending) => return Ok(serde_json::json!({ "pending": true }).to_string()),
+            Ok(TokenPoll::Authorized(access_token)) => access_token,
+            Err(e) => {
+                if matches!(e, WebLoginError::Denied | WebLoginError::Expired) {
+                    pending.cancel(&id);
+                }
+                return Err(e.to_string());
+            }
+        };
+        let login = runtime.block_on(validate_token(&access_token))?;
+        let token = pending
+            .complete(&id, &login, Instant::now())
+            .map_err(|e| e.to_string())?;
+        let session = serde_json::json!({ "login": login, "token": token });
+        Ok(session.to_string())
+    });
+
+    let cube_size = config.twixelbox.cube_size;
+    let pixel_art = config.twixelbox.pixel_art;
+    let coordinates = config.coordinates;
+    let limits = limits.clone();
+    let place_tx = tx.viewer.clone();
+    http.accept_user_uploads(
+        PLACE_PATH,
+        move |token| logins.login(token, Instant::now()),
+        move |login, _, body| {
+            let text = String::from_utf8(body).map_err(|_| "expected text".to_owned())?;
+            let chat_command: ChatCommand = text.parse().map_err(|e| format!("{}", e))?;
+            let (x, y, z) = (chat_command.x, chat_command.y, chat_command.z);
+            let position = coordinates
+                .to_canvas([x, y, z], cube_size)
+                .ok_or("outside of the canvas")?;
+            // The pixel art canvas is a single plane.
+            if pixel_art && position.z != 0 {
+                return Err("outside of the canvas".to_owned());
+            }
+            limits
+                .lock()
+                .unwrap()
+                .check("place", login, false, 1, Instant::now())
+                .map_err(|e| e.to_string())?;
+            let id = Uuid::new_v4();
+            place_tx
+                .send(Command::Event {
+                    id,
+                    event: CanvasEvent::CubePlaced(Cube {
+                        position,
+                        colour: chat_command.colour,
+                    }),
+                    author: Some(login.to_owned()),
+                    team: None,
+                    message: Some(text.clone()),
+                    reply_to: None,
+                    command_use: Some(CommandUse {
+                        command: "place".to_owned(),
+                        id,
+                    }),
+                    parse_time: None,
+                })
+                .map_err(|e| format!("unable to queue the placement: {}", e))?;
+            Ok(format!("Placing a cube at {} {} {}", x, y, z))
+        },
+    );
+}
+
+// Starts a device flow, the code to enter at Twitch to log in.
+async fn request_device_code(client_id: &str) -> Result<DeviceCode, String> {
+    let body = form_body(&[("client_id", client_id), ("scopes", "")]);
+    let mut response = surf::post(DEVICE_URL)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body_bytes(body)
+        .await
+        .map_err(|e| format!("unable to reach Twitch: {}", e))?;
+    let text = response
+        .body_string()
+        .await
+        .map_err(|e| format!("unable to reach Twitch: {}", e))?;
+    DeviceCode::from_twitch(&text).map_err(|e| e.to_string())
+}
+
+// Asks Twitch whether the code of a device flow was entered yet.
+async fn poll_device_token(client_id: &str, device_code: &str) -> Result<TokenPoll, WebLoginError> {
+    let body = form_body(&[
+        ("client_id", client_id),
+        ("scopes", ""),
+        ("device_code", device_code),
+        ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+    ]);
+    let mut response = surf::post(TOKEN_URL)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body_bytes(body)
+        .await
+        .map_err(|e| WebLoginError::Twitch(e.to_string()))?;
+    let text = response
+        .body_string()
+        .await
+        .map_err(|e| WebLoginError::Twitch(e.to_string()))?;
+    TokenPoll::from_twitch(response.status().is_success(), &text)
+}
+
+// Login of the chatter `access_token` is of.
+async fn validate_token(access_token: &str) -> Result<String, String> {
+    let mut response = surf::get(VALIDATE_URL)
+        .header("Authorization", format!("OAuth {}", access_token))
+        .await
+        .map_err(|e| format!("unable to reach Twitch: {}", e))?;
+    let text = response
+        .body_string()
+        .await
+        .map_err(|e| format!("unable to reach Twitch: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Twitch refused the login: {}", text));
+    }
+    validated_login(&text).map_err(|e| e.to_string())
+}
+
+const CUBES_PATH: &str = "/api/cubes";
+const HISTORY_PATH: &str = "/api/history";
+const COLOURS_PATH: &str = "/api/colours";
+// Changes listed at most by the history queries.
+const MAX_HISTORY: usize = 100;
+
+// Answers the queries about the archive from a copy of it, refreshed every
+// configured period, so that no amount of them holds up the journal.
+fn serve_archive_queries(
+    http: &HttpServer,
+    archive: &CubeArchive,
+    config: &HttpConfig,
+    coordinates: CoordinateSystem,
+    side_len: u32,
+) {
+    let replica = match ArchiveReplica::new(archive) {
+        Ok(replica) => replica,
+        Err(e) => {
+            eprintln!("Unable to copy the archive for the HTTP API: {}", e);
+            return;
+        }
+    };
+    let interval = std::time::Duration::from_secs(config.replica_refresh_secs.max(1));
+    let refreshed = replica.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            let replica = refreshed.clone();
+            if let Ok(Err(e)) = tokio::task::spawn_blocking(move || replica.refresh()).await {
+                eprintln!("Unable to refresh the copy of the archive: {}", e);
+            }
+        }
+    });
+    answer_cubes_queries(http, replica.clone());
+    answer_colours_queries(http, replica.clone());
+    answer_history_queries(http, replica, coordinates, side_len);
+}
+
+// Answers `<public url>/api/cubes` with the cubes on the canvas, as JSON.
+fn answer_cubes_queries(http: &HttpServer, replica: ArchiveReplica) {
+    http.answer_queries(CUBES_PATH, move |_| {
+        let cubes = replica
+            .read(|archive| archive.get_cubes())
+            .map_err(|e| e.to_string())?;
+        serde_json::to_string(&cubes).map_err(|e| e.to_string())
+    });
+}
+
+// Answers `<public url>/api/colours?since=86400&bucket=3600&palette=ff0000,000000`
+// with the colours of the cubes placed over the last `since` seconds, the
+// whole archive if not given, and by periods of `bucket` seconds how many were
+// in a colour of `palette`, as JSON.
+fn answer_colours_queries(http: &HttpServer, replica: ArchiveReplica) {
+    http.answer_queries(COLOURS_PATH, move |query| {
+        let since = match query_param(query, "since", 0i64)? {
+            0 => 0,
+            since => chrono::Utc::now().timestamp() - since,
+        };
+        let bucket_secs = query_param(query, "bucket", 3600i64)?;
+        let palette = parse_palette(&query_param(query, "palette", String::new())?)?;
+        let journal = replica
+            .read(|archive| archive.get_journal())
+            .map_err(|e| e.to_string())?;
+        let stats = ColourStats::new(&journal, since, bucket_secs, palette.as_ref());
+        serde_json::to_string(&stats).map_err(|e| e.to_string())
+    });
+}
+
+// Answers `<public url>/api/history?x=1&y=2&z=3&limit=10` with the last
+// changes at the position, latest first, as JSON.
+fn answer_history_queries(
+    http: &HttpServer,
+    replica: ArchiveReplica,
+    coordinates: CoordinateSystem,
+    side_len: u32,
+) {
+    http.answer_queries(HISTORY_PATH, move |query| {
+        let chat_coordinates = [
+            query_param(query, "x", 0)?,
+            query_param(query, "y", 0)?,
+            query_param(query, "z", 0)?,
+        ];
+        let limit = query_param(query, "limit", 10)?.min(MAX_HISTORY);
+        let position = coordinates
+            .to_canvas(chat_coordinates, side_len)
+            .ok_or("the position is outside of the canvas")?;
+        let history = replica
+            .read(|archive| archive.history(position, limit))
+            .map_err(|e| e.to_string())?;
+        serde_json::to_string(&history).map_err(|e| e.to_string())
+    });
+}
+
+// Value of `name` in the query string of a request, `default` if missing.
+fn query_param<T: FromStr>(query: &str, name: &str, default: T) -> Result<T, String> {
+    let prefix = format!("{}=", name);
+    match query
+        .split('&')
+        .find_map(|p| p.strip_prefix(prefix.as_str()))
+    {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("invalid {} {}", name, value)),
+        None => Ok(default),
+    }
+}
+
+fn decode_upload(body: &[u8]) -> Result<RgbImage, String> {
+    Ok(image::load_from_memory(body)
+        .map_err(|e| format!("unable to read the image: {}", e))?
+        .to_rgb8())
+}
+
+// Matches the image to the current palette, then serves the build sheet and
+// links it in chat.
+fn publish_build_sheet(
+    http: &HttpServer,
+    img: RgbImage,
+    projection: Projection,
+    palette: Option<Palette>,
+    side_len: u32,
+    announcer: Option<Announcer>,
+    announcements: &Announcements,
+) {
+    let http = http.clone();
+    let announcements = announcements.clone();
+    tokio::task::spawn_blocking(move || {
+        let sheet = BuildSheet::new(&img, &projection, palette.as_ref(), side_len);
+        let path = |extension: &str| format!("{}.{}", BUILD_SHEET_PATH, extension);
+        http.publish(&path("csv"), "text/csv", sheet.to_csv().into_bytes());
+        http.publish(
+            &path("html"),
+            "text/html; charset=utf-8",
+            sheet.to_html().into_bytes(),
+        );
+        if let Some(announcer) = announcer {
+            let url = http.url(&path("html"));
+            let _ = announcer.send(announcements.format("build_sheet", &[("url", &url)]).into());
+        }
+    });
+}
+
+// Exports are published with the extension of their format.
+const EXPORT_PATH: &str = "/twixelbox";
+const IMPORT_PATH: &str = "/api/import";
+
+// Lets moderators seed the canvas with a voxel model, placed with its corner of
+// lowest coordinates at those in the query string, e.g. with `curl -H
+// "Authorization: Bearer <token>" --data-binary @castle.qb
+// 'http://host/api/import?format=qb&x=10&y=0&z=10'`. Minecraft schematics are
+// expected unless the format is given. The colours of the model are kept
+// unless `quantize` asks to reduce them to a few `colours` with kmeans or
+// median-cut, or to take those of the palette in use in chat.
+fn accept_model_uploads(
+    http: &HttpServer,
+    config: &PaletteConfig,
+    coordinates: CoordinateSystem,
+    tx: &CommandSenders,
+    side_len: u32,
+) {
+    let token = match &config.upload_token {
+        Some(token) => token,
+        None => return,
+    };
+    let default_colours = config.colours;
+    let tx = tx.priority.clone();
+    http.accept_uploads(IMPORT_PATH, token, move |query, body| {
+        let format = query_param(query, "format", VoxelFormat::Schematic)?;
+        let quantization = query_param(query, "quantize", Quantization::None)?;
+        let colours = query_param(query, "colours", default_colours)?;
+        let origin = [
+            query_param(query, "x", 0)?,
+            query_param(query, "y", 0)?,
+            query_param(query, "z", 0)?,
+        ];
+        // Read whole, then placed in the coordinates of the channel. Voxels
+        // further than the side of the canvas from the corner never fit.
+        let (model, unknown) = import_voxels(format, &body, Position::default(), side_len)
+            .map_err(|e| e.to_string())?;
+        let (mut cubes, outside) = coordinates.place(model, origin, side_len);
+        let skipped = unknown + outside;
+        let seed = chrono::Utc::now().timestamp() as u64;
+        let model_colours: Vec<Colour> = cubes.iter().map(|cube| cube.colour).collect();
+        if let Some(palette) = quantization.reduce(&model_colours, colours, seed) {
+            for cube in &mut cubes {
+                cube.colour = palette.nearest(cube.colour);
+            }
+        }
+        let placed = cubes.len();
+        let restrict = quantization == Quantization::Palette;
+        tx.send(Command::Import { cubes, restrict })
+            .map_err(|e| format!("unable to import the model: {}", e))?;
+        Ok(format!(
+            "Placing {} cubes, {} voxels are unknown or outside of the canvas",
+            placed, skipped
+        ))
+    });
+}
+
+// Exports the canvas in every format on a blocking thread, and publishes the
+// files once done.
+fn publish_exports(
+    archive: &mut CubeArchive,
+    http: &HttpServer,
+    announcer: Option<Announcer>,
+    announcements: &Announcements,
+) {
+    let cubes = archive.get_cubes().expect("Failed to read from database");
+    let http = http.clone();
+    let announcements = announcements.clone();
+    tokio::task::spawn_blocking(move || {
+        let path = |format: VoxelFormat| format!("{}.{}", EXPORT_PATH, format.extension());
+        for format in VoxelFormat::ALL.iter().copied() {
+            match export_voxels(format, &cubes) {
+                Ok(data) => http.publish_export(&path(format), "application/octet-stream", data),
+                Err(e) => eprintln!("Unable to export the canvas to {:?}: {}", format, e),
+            }
+        }
+        if let Some(announcer) = announcer {
+            let _ = announcer.send(
+                announcements
+                    .format(
+                        "exports",
+                        &[
+                            ("schematic", &http.url(&path(VoxelFormat::Schematic))),
+                            ("goxel", &http.url(&path(VoxelFormat::Goxel))),
+                            ("qubicle", &http.url(&path(VoxelFormat::Qubicle))),
+                        ],
+                    )
+                    .into(),
+            );
+        }
+    });
+}
+
+// Placements from chat take the closest colour of the palette, if any.
+fn restrict_colours(palette: Option<&Palette>, event: CanvasEvent) -> CanvasEvent {
+    match (palette, event) {
+        (Some(palette), CanvasEvent::CubePlaced(cube)) => {
+            // Only the channels, the cube keeps its opacity, glow and shape.
+            let Colour { r, g, b, .. } = palette.nearest(cube.colour);
+            CanvasEvent::CubePlaced(Cube {
+                colour: Colour {
+                    r,
+                    g,
+                    b,
+                    ..cube.colour
+                },
+                ..cube
+            })
+        }
+        (_, event) => event,
+    }
+}
+
+fn describe_palette(announcements: &Announcements, palette: Option<&Palette>) -> String {
+    match palette {
+        Some(palette) => announcements.format("palette", &[("colours", palette)]),
+        None => announcements.format("palette_lifted", &[]),
+    }
+}
+
+// Palette of the colours listed in `colours`, separated by commas or spaces,
+// `None` if there's none.
+fn parse_palette(colours: &str) -> Result<Option<Palette>, String> {
+    let colours = colours
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|colour| !colour.is_empty())
+        .map(|colour| colour.parse().map_err(|e| format!("{}: {}", colour, e)))
+        .collect::<Result<Vec<Colour>, String>>()?;
+    Ok(Palette::new(colours))
+}
+
+// Summary of the canvas over the last `period_secs`, with its busiest
+// builders.
+fn describe_summary(config: &TwixelBoxBotConfig, report: &Report, period_secs: i64) -> String {
+    let top: Vec<String> = report
+        .builders()
+        .iter()
+        .take(config.summary.top_builders)
+        .map(|builder| format!("{} ({})", builder.login, builder.placed))
+        .collect();
+    let period = match period_secs {
+        secs if secs > 24 * 3600 => "week",
+        _ => "day",
+    };
+    config.announcements.format(
+        "summary",
+        &[
+            ("period", &period),
+            ("count", &report.colours().placed),
+            ("builders", &report.builders().len()),
+            ("top", &top.join(", ")),
+        ],
+    )
+}
+
+// Posts `content` to the Discord webhook at `url`, with the PNG `image` if
+// any. A failure is only reported.
+fn post_to_webhook(url: String, content: String, image: Option<Vec<u8>>) {
+    tokio::spawn(async move {
+        let (content_type, body) = webhook_body(&content, image.as_deref());
+        let result = surf::post(&url)
+            .header("Content-Type", content_type)
+            .body_bytes(body)
+            .await;
+        match result {
+            Ok(mut response) if !response.status().is_success() => {
+                let text = response.body_string().await.unwrap_or_default();
+                eprintln!("Unable to post the summary: {} {}", response.status(), text);
+            }
+            Ok(_) => debug!("Posted the summary to the webhook"),
+            Err(e) => eprintln!("Unable to post the summary: {}", e),
+        }
+    });
+}
+
+// Announcement of the colours most placed, by name.
+fn describe_colours(announcements: &Announcements, stats: &ColourStats) -> String {
+    let top = match stats.named.first() {
+        Some(top) => top,
+        None => return announcements.format("no_colours", &[]),
+    };
+    let share = |count: u64| count * 100 / stats.placed;
+    let colours: Vec<String> = stats
+        .named
+        .iter()
+        .take(3)
+        .map(|named| format!("{} {}%", named.name, share(named.count)))
+        .collect();
+    announcements.format(
+        "colours",
+        &[
+            ("top", &top.name),
+            ("share", &share(top.count)),
+            ("count", &stats.placed),
+            ("colours", &colours.join(", ")),
+        ],
+    )
+}
+
+// Loads the script of custom chat commands, if one is configured, `cubes`
+// being on the canvas.
+fn load_scripts(config: &TwixelBoxBotConfig, cubes: Vec<Cube>) -> Option<ScriptHost> {
+    config.scripts.filepath.as_ref()?;
+    let mut canvas = Canvas::new(config.twixelbox.cube_size);
+    for cube in cubes {
+        let _ = canvas.add_cube(cube);
+    }
+    match ScriptHost::load(&config.scripts, canvas) {
+        Ok(scripts) => Some(scripts),
+        Err(e) => {
+            eprintln!("Scripts disabled: {}", e);
+            None
+        }
+    }
+}
+
+// Picks up the changes to the script file before running it.
+fn reload_scripts(scripts: &mut ScriptHost) {
+    match scripts.reload() {
+        Ok(true) => debug!("Reloaded the script"),
+        Ok(false) => {}
+        Err(e) => eprintln!("Keeping the previous script: {}", e),
+    }
+}
+
+// Queues what a script did: its cubes go on the priority lane, so that they
+// don't run `on_placed` again, and its replies to chat.
+fn apply_script_actions(
+    result: Result<Vec<ScriptAction>, ScriptError>,
+    author: Option<String>,
+    command_use: Option<CommandUse>,
+    tx: &CommandSenders,
+    announcer: Option<&Announcer>,
+) {
+    let actions = match result {
+        Ok(actions) => actions,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    for action in actions {
+        let event = match action {
+            ScriptAction::Place(cube) => CanvasEvent::CubePlaced(cube),
+            ScriptAction::Remove(position) => CanvasEvent::CubeRemoved(position),
+            ScriptAction::Reply(text) => {
+                if let Some(announcer) = announcer {
+                    let _ = announcer.send(text.into());
+                }
+                continue;
+            }
+        };
+        let command = Command::Event {
+            id: Uuid::new_v4(),
+            event,
+            author: author.clone(),
+            team: None,
+            message: None,
+            reply_to: None,
+            command_use: command_use.clone(),
+            parse_time: None,
+        };
+        if let Err(e) = tx.priority.send(command) {
+            eprintln!("Unable to queue the script changes: {}", e);
+        }
+    }
+}
+
+// Resumes the progression where it was before a restart, if enabled.
+fn load_progression(config: &TwixelBoxBotConfig, archive: &mut CubeArchive) -> Option<Progression> {
+    let (stage, placed) = archive
+        .get_progression()
+        .expect("Failed to read from database");
+    Progression::new(
+        &config.progression,
+        config.twixelbox.cube_size,
+        stage,
+        placed,
+    )
+}
+
+// The local date, and the unix timestamp of its midnight.
+fn today() -> (chrono::NaiveDate, i64) {
+    let day = chrono::Local::now().naive_local().date();
+    let midnight = chrono::Local
+        .from_local_datetime(&day.and_hms_opt(0, 0, 0).expect("midnight is a time"))
+        .earliest()
+        .map_or(0, |midnight| midnight.timestamp());
+    (day, midnight)
+}
+
+// Starts the goal of `target` cubes for today, counting those already placed
+// since midnight.
+fn load_goal(target: Option<u64>, archive: &mut CubeArchive) -> Option<Goal> {
+    let target = target?;
+    let (day, midnight) = today();
+    let placed = archive
+        .placed_since(midnight)
+        .expect("Failed to read from database");
+    Some(Goal::new(target, day, placed))
+}
+
+// Counts a journaled placement of chat towards the goal, and returns the
+// progress for the HUD. Reaching it is announced and marked.
+fn count_towards_goal(
+    goal: &mut Goal,
+    event: &CanvasEvent,
+    metadata: &EventMetadata,
+    markers: Option<&mut MarkerService>,
+    announcer: Option<&Announcer>,
+    announcements: &Announcements,
+) -> Option<GoalProgress> {
+    if !matches!(event, CanvasEvent::CubePlaced(_)) || !metadata.viewer {
+        return None;
+    }
+    let (day, _) = today();
+    if goal.count_placement(day) {
+        let target = goal.progress().target;
+        if let Some(announcer) = announcer {
+            let _ = announcer.send(
+                announcements
+                    .format("goal_reached", &[("target", &target)])
+                    .into(),
+            );
+        }
+        if let Some(markers) = markers {
+            markers.mark(MarkerMoment::GoalReached(target));
+        }
+    }
+    Some(goal.progress())
+}
+
+// Local date of the unix timestamp `timestamp`.
+fn local_day(timestamp: i64) -> chrono::NaiveDate {
+    chrono::Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map_or_else(|| today().0, |time| time.naive_local().date())
+}
+
+// Resumes the achievements from the archive, and those the journal unlocks
+// which it doesn't have yet, e.g. the first time they're enabled, are saved
+// without being announced. Returns None when they're disabled.
+fn load_achievements(
+    config: &TwixelBoxBotConfig,
+    archive: &mut CubeArchive,
+) -> Option<AchievementTracker> {
+    let mut achievements =
+        AchievementTracker::new(&config.achievements, config.twixelbox.cube_size)?;
+    let unlocked = archive
+        .get_achievements()
+        .expect("Failed to read from database");
+    for (login, achievement) in unlocked {
+        match achievement.parse() {
+            Ok(achievement) => achievements.restore(&login, achievement),
+            Err(e) => eprintln!("Skipping the achievement of {}: {}", login, e),
+        }
+    }
+    let journal = archive.get_journal().expect("failed to extract events");
+    for entry in &journal {
+        let metadata = &entry.metadata;
+        let day = local_day(metadata.timestamp);
+        for achievement in achievements.apply(&entry.event, metadata, day) {
+            let login = metadata.author.as_deref().unwrap_or_default();
+            archive
+                .unlock_achievement(login, achievement.id(), metadata.timestamp)
+                .expect("Failed to add achievement to database");
+        }
+    }
+    Some(achievements)
+}
+
+// Saves and announces the achievements a journaled event unlocked.
+fn unlock_achievements(
+    achievements: &mut AchievementTracker,
+    event: &CanvasEvent,
+    metadata: &EventMetadata,
+    archive: &mut CubeArchive,
+    announcer: Option<&Announcer>,
+    announcements: &Announcements,
+) {
+    let login = match metadata.author.as_deref() {
+        Some(login) => login,
+        None => return,
+    };
+    for achievement in achievements.apply(event, metadata, local_day(metadata.timestamp)) {
+        archive
+            .unlock_achievement(login, achievement.id(), metadata.timestamp)
+            .expect("Failed to add achievement to database");
+        let name = format!("achievement_{}", achievement.id());
+        let announcement = announcements.format(
+            &name,
+            &[
+                ("user", &login),
+                ("count", &achievements.cubes()),
+                ("days", &achievements.streak_days()),
+            ],
+        );
+        if let Some(announcer) = announcer {
+            let _ = announcer.send(announcement.into());
+        }
+    }
+}
+
+// Announcement of the achievements of `login`.
+fn describe_achievements(
+    announcements: &Announcements,
+    achievements: &AchievementTracker,
+    login: &str,
+) -> String {
+    let unlocked: Vec<String> = achievements
+        .unlocked(login)
+        .into_iter()
+        .map(|achievement| match achievement {
+            Achievement::FirstCube => "first cube".to_owned(),
+            Achievement::Cubes => format!("{} cubes", achievements.cubes()),
+            Achievement::Streak => format!("{}-day streak", achievements.streak_days()),
+            Achievement::FullLine => "full line".to_owned(),
+        })
+        .collect();
+    match unlocked.is_empty() {
+        true => announcements.format("no_achievements", &[("user", &login)]),
+        false => announcements.format(
+            "achievements",
+            &[("user", &login), ("achievements", &unlocked.join(", "))],
+        ),
+    }
+}
+
+// Whether `event` places a cube in a region which isn't unlocked yet.
+fn is_locked(progression: Option<&Progression>, event: &CanvasEvent) -> bool {
+    match (progression, event) {
+        (Some(progression), CanvasEvent::CubePlaced(cube)) => {
+            !progression.is_unlocked(cube.position)
+        }
+        _ => false,
+    }
+}
+
+// Counts a journaled placement of chat towards the next stage. When it unlocks
+// one, saves the stage, announces it and returns the new buildable region.
+fn progress(
+    progression: &mut Progression,
+    event: &CanvasEvent,
+    metadata: &EventMetadata,
+    archive: &mut CubeArchive,
+    announcer: Option<&Announcer>,
+    announcements: &Announcements,
+) -> Option<Region> {
+    if !matches!(event, CanvasEvent::CubePlaced(_)) || !metadata.viewer {
+        return None;
+    }
+    let region = progression.count_placement()?;
+    archive
+        .set_progression_stage(progression.stage())
+        .expect("Failed to update database");
+    if let Some(announcer) = announcer {
+        let side = region.side();
+        let _ = announcer.send(
+            match progression.remaining() {
+                Some(remaining) => announcements
+                    .format("stage_unlocked", &[("side", &side), ("count", &remaining)]),
+                None => announcements.format("last_stage_unlocked", &[("side", &side)]),
+            }
+            .into(),
+        );
+    }
+    Some(region)
+}
+
+// Replaces the screensaver cubes drawn over the canvas.
+fn swap_layer(renderer: &mut dyn Renderer, layer: &mut Vec<Cube>, next: Vec<Cube>) {
+    let kept: HashSet<Position> = next.iter().map(|cube| cube.position).collect();
+    for cube in layer.iter() {
+        if !kept.contains(&cube.position) {
+            renderer.remove_cube(cube.position);
+        }
+    }
+    for cube in &next {
+        renderer.add_cube(cube);
+    }
+    *layer = next;
+}
+
+// Positions logged when the overlay drifted from the archive, the rest are
+// only counted.
+const MAX_LOGGED_DIFFERENCES: usize = 20;
+
+// What the process handling the chat keeps: the archive and everything
+// derived from it. The renderer of the split setup has none of it.
+struct Journal {
+    archive: CubeArchive,
+    competitions: Competitions,
+    members: TeamMembers,
+    decay: Option<DecayTracker>,
+    filter: UserFilter,
+    progression: Option<Progression>,
+    // Regions viewers can't change, set by the moderators.
+    protected: ProtectedRegions,
+    scripts: Option<ScriptHost>,
+    http: Option<HttpServer>,
+    grpc: Option<GrpcService>,
+    mqtt: Option<MqttPublisher>,
+    event_log: Option<EventLog>,
+    markers: Option<MarkerService>,
+    stats: Option<StatsReporter>,
+    plugins: PluginRegistry,
+    timer: CommandTimer,
+    // Drawn over the timelapse and the credits.
+    watermark: Option<Watermark>,
+    // Cubes for chat to place today, if any.
+    goal: Option<Goal>,
+    achievements: Option<AchievementTracker>,
+}
+
+impl Journal {
+    // Resumes everything from the archive and starts the services publishing
+    // the canvas.
+    fn load(
+        config: &TwixelBoxBotConfig,
+        mut archive: CubeArchive,
+        teams: Teams,
+        tx: &CommandSenders,
+        announcer: Option<&Announcer>,
+        limits: &Arc<Mutex<CommandLimits>>,
+    ) -> Self {
+        let announcements = &config.announcements;
+        let members = TeamMembers::load(
+            &mut archive,
+            teams,
+            announcer.cloned(),
+            announcements.clone(),
+        );
+        let competitions = Competitions::resume(
+            &mut archive,
+            tx.clone(),
+            announcer.cloned(),
+            announcements.clone(),
+        );
+        let decay = start_decay(&config.decay, &mut archive, tx);
+        schedule_pruning(tx, &config.retention);
+        schedule_summaries(tx, &config.summary);
+        let filter = load_user_filter(&config.users, &mut archive);
+        let progression = load_progression(config, &mut archive);
+        let protected = ProtectedRegions::new(
+            archive
+                .get_protected()
+                .expect("Failed to read from database"),
+        );
+        let scripts = match config.scripts.filepath {
+            Some(_) => load_scripts(
+                config,
+                archive.get_cubes().expect("Failed to read from database"),
+            ),
+            None => None,
+        };
+        let http = start_http_server(&config.http);
+        if let Some(http) = &http {
+            schedule_timelapse(tx, &config.timelapse);
+            serve_archive_queries(
+                http,
+                &archive,
+                &config.http,
+                config.coordinates,
+                config.twixelbox.cube_size,
+            );
+            accept_image_uploads(http, &config.palette, tx);
+            accept_web_placements(http, config, tx, limits);
+            accept_model_uploads(
+                http,
+                &config.palette,
+                config.coordinates,
+                tx,
+                config.twixelbox.cube_size,
+            );
+        }
+        let grpc = start_grpc_service(&config.grpc, &config.http.access, http.as_ref(), tx);
+        let mqtt = connect_mqtt(&config.mqtt);
+        let event_log = open_event_log(&config.event_log);
+        let goal = load_goal(config.goal.cubes, &mut archive);
+        let achievements = load_achievements(config, &mut archive);
+        let stats = StatsReporter::start(config, tx, http.is_some(), mqtt.clone());
+        watch_archive(&mut archive, tx, &config.drift);
+        Journal {
+            archive,
+            competitions,
+            members,
+            decay,
+            filter,
+            progression,
+            protected,
+            scripts,
+            http,
+            grpc,
+            mqtt,
+            event_log,
+            markers: MarkerService::start(config),
+            stats,
+            plugins: command_plugins(&config.twixelbox, config.coordinates),
+            timer: CommandTimer::new(&config.timing),
+            watermark: load_watermark(&config.watermark),
+            goal,
+            achievements,
+        }
+    }
+
+    // Appends to the event log, if any. The archive stays the reference, a
+    // failure is only reported.
+    fn log(&mut self, entry: JournalEntry) {
+        if let Some(event_log) = self.event_log.as_mut() {
+            if let Err(e) = event_log.append(&entry) {
+                eprintln!("Unable to append to the event log: {}", e);
+            }
+        }
+    }
+}
+
+// The live overlay and the snapshots, drawn by this process.
+struct Overlay {
+    renderer: SliceFilter,
+    raytracer: Raytracer,
+    snapshot_resolution: u32,
+    // Mode and eye separation of stereo snapshots.
+    snapshot_stereo: Option<(StereoMode, f32)>,
+    snapshot_auxiliary: Vec<AuxiliaryBuffer>,
+    snapshot_minimap: Option<(MinimapCorner, f32)>,
+    snapshot_watermark: Option<Watermark>,
+    // Edits of chat lately, for the minimap.
+    activity: Option<ActivityHeat>,
+    // Lowers the resolution of snapshots and the quality of the stream while
+    // frames are lost.
+    quality: AdaptiveQuality,
+    overlay_post_processor: PostProcessor,
+    themes: Themes,
+    snapshot_post_processor: PostProcessor,
+    canvas: Canvas,
+    teams: Teams,
+    tracker: TeamTracker,
+    frame_time: std::time::Duration,
+    next_expected_frame: Instant,
+    // The screensaver only draws on the scene of the live overlay, not on the
+    // canvas nor on snapshots.
+    screensaver: Option<Screensaver>,
+    idle_time: std::time::Duration,
+    layer: Vec<Cube>,
+    last_placement: Instant,
+    // Only the live overlay spins, snapshots keep the default view.
+    spin: Spin,
+    last_spin: Instant,
+    // Camera presets, the live overlay moves to them smoothly while
+    // snapshots are taken from where it ends up.
+    views: Views,
+    // Never saved nor on snapshots either, the effects it can switch to are
+    // in `weather_config`.
+    weather: Option<Weather>,
+    weather_config: WeatherConfig,
+    last_weather: Instant,
+    // Clock and stream info, on the live overlay only.
+    hud: Option<Hud>,
+    // Ghosts of the cubes removed or overwritten lately, when enabled.
+    onion_skin: Option<OnionSkin>,
+    // Server and path of the MJPEG stream of the overlay.
+    stream: Option<(HttpServer, String)>,
+    // Serves the auxiliary buffers of snapshots.
+    http: Option<HttpServer>,
+    slice_duration: std::time::Duration,
+    slice_end: Instant,
+    highlight_duration: std::time::Duration,
+    highlight_end: Instant,
+}
+
+impl Overlay {
+    // Sets up the renderers and the timers asking for frames, snapshots and
+    // screensaver steps.
+    fn new(config: &TwixelBoxBotConfig, tx: &CommandSenders, teams: Teams) -> Option<Self> {
+        let background = match Background::new(&config.twixelbox.background) {
+            Ok(background) => background,
+            Err(e) => {
+                eprintln!("Error setting up the background: {}", e);
+                return None;
+            }
+        };
+        let mut renderer = SliceFilter::new(create_renderer(&config.twixelbox)?);
+        renderer.set_background(&background);
+
+        let (themes, snapshot_post_processor) = match (
+            Themes::new(&config.twixelbox.post_processing, &config.twixelbox.themes),
+            PostProcessor::new(&config.snapshot.post_processing),
+        ) {
+            (Ok(themes), Ok(snapshot)) => (themes, snapshot),
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("Error setting up the post-processing: {}", e);
+                return None;
+            }
+        };
+
+        let weather = match config.weather.effect.map(|effect| {
+            Weather::new(
+                effect,
+                &config.weather,
+                chrono::Utc::now().timestamp() as u64,
+            )
+        }) {
+            Some(Ok(weather)) => Some(weather),
+            Some(Err(e)) => {
+                eprintln!("Error setting up the weather: {}", e);
+                return None;
+            }
+            None => None,
+        };
+
+        let hud = match config.hud.is_enabled().then(|| Hud::new(&config.hud)) {
+            Some(Ok(hud)) => Some(hud),
+            Some(Err(e)) => {
+                eprintln!("Error setting up the HUD: {}", e);
+                return None;
+            }
+            None => None,
+        };
+        if config.hud.needs_stream_info() {
+            poll_stream_info(config, tx);
+        }
+
+        let mut raytracer = Raytracer::new(
+            config.snapshot.resolution,
+            config.twixelbox.cube_size,
+            config.snapshot.samples,
+        );
+        raytracer.set_colour_correction(config.twixelbox.colour_correction);
+        raytracer.set_background(&background);
+
+        let fps: f32 = 0.5;
+        let frame_time = std::time::Duration::from_millis((1000.0 / fps) as u64);
+
+        // Periodic showcase renders, e.g. nightly.
+        if let Some(interval_hours) = config.snapshot.interval_hours {
+            let tx = tx.priority.clone();
+            tokio::spawn(async move {
+                let interval = std::time::Duration::from_secs(interval_hours * 3600);
+                loop {
+                    tokio::time::sleep(interval).await;
+                    if let Err(e) = tx.send(Command::Snapshot) {
+                        eprintln!("Unable to queue the periodic snapshot: {}", e);
+                    }
+                }
+            });
+        }
+
+        // Spawn the renderer timer thread.
+        let render_tx = tx.priority.clone();
+        tokio::spawn(async move {
+            loop {
+                // send render message, the frame is skipped if the queue is full.
+                let _ = render_tx.try_send(Command::Render);
+                tokio::time::sleep(frame_time).await;
+            }
+        });
+
+        // Frames of the stream in between, only drawn while spinning or with
+        // weather.
+        if config.http.overlay_stream.is_some() && config.spin.stream_fps > 0.0 {
+            let stream_frame_time =
+                std::time::Duration::from_secs_f32(1.0 / config.spin.stream_fps);
+            let tx = tx.priority.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(stream_frame_time).await;
+                    let _ = tx.try_send(Command::StreamFrame);
+                }
+            });
+        }
+
+        let screensaver = if config.screensaver.idle_secs > 0 {
+            let step = std::time::Duration::from_millis(config.screensaver.step_ms.max(100));
+            let tx = tx.priority.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(step).await;
+                    let _ = tx.try_send(Command::Screensaver);
+                }
+            });
+            Some(Screensaver::new(
+                config.screensaver.game,
+                config.twixelbox.cube_size,
+                chrono::Utc::now().timestamp() as u64,
+            ))
+        } else {
+            None
+        };
+
+        Some(Overlay {
+            renderer,
+            raytracer,
+            snapshot_resolution: config.snapshot.resolution,
+            snapshot_stereo: config
+                .snapshot
+                .stereo
+                .map(|mode| (mode, config.snapshot.eye_separation)),
+            snapshot_auxiliary: config.snapshot.auxiliary.clone(),
+            snapshot_minimap: config
+                .snapshot
+                .minimap
+                .map(|corner| (corner, config.snapshot.minimap_size)),
+            snapshot_watermark: load_watermark(&config.watermark),
+            activity: match (config.snapshot.minimap, config.snapshot.minimap_heat_mins) {
+                (Some(_), Some(mins)) => Some(ActivityHeat::new(
+                    config.twixelbox.cube_size,
+                    std::time::Duration::from_secs_f32(mins.max(0.0) * 60.0),
+                    Instant::now(),
+                )),
+                _ => None,
+            },
+            quality: AdaptiveQuality::new(&config.quality),
+            overlay_post_processor: themes.get("default")?.clone(),
+            themes,
+            snapshot_post_processor,
+            canvas: Canvas::new(config.twixelbox.cube_size),
+            teams,
+            tracker: TeamTracker::new(),
+            frame_time,
+            next_expected_frame: Instant::now(),
+            screensaver,
+            idle_time: std::time::Duration::from_secs(config.screensaver.idle_secs),
+            layer: Vec::new(),
+            last_placement: Instant::now(),
+            spin: Spin::new(config.spin.degrees_per_sec),
+            last_spin: Instant::now(),
+            views: Views::new(&config.views, config.twixelbox.cube_size),
+            weather,
+            weather_config: config.weather.clone(),
+            last_weather: Instant::now(),
+            hud,
+            onion_skin: match config.onion_skin.enabled {
+                true => Some(OnionSkin::new(&config.onion_skin)),
+                false => None,
+            },
+            stream: None,
+            http: None,
+            slice_duration: std::time::Duration::from_secs(config.slice.duration_secs),
+            slice_end: Instant::now(),
+            highlight_duration: std::time::Duration::from_secs(config.shoutout.highlight_secs),
+            highlight_end: Instant::now(),
+        })
+    }
+
+    // Compares the canvas with the one of the archive, the overlay is rebuilt
+    // from the archive if they differ.
+    fn check_drift(&mut self, archive: &mut CubeArchive) {
+        let events = archive.get_events().expect("failed to extract events");
+        let (expected, _) = Canvas::replay(self.canvas.side_len(), &events);
+        if self.canvas.checksum() == expected.checksum() {
+            return;
+        }
+        let differences = self.canvas.differences(&expected);
+        eprintln!(
+            "The overlay drifted from the archive at {} positions, rebuilding it",
+            differences.len()
+        );
+        for difference in differences.iter().take(MAX_LOGGED_DIFFERENCES) {
+            eprintln!(
+                "  {}: {:?} on the overlay, {:?} in the archive",
+                difference.position, difference.actual, difference.expected
+            );
+        }
+        self.resync(archive);
+    }
+
+    // Tears down the scene and rebuilds it from the archive.
+    fn resync(&mut self, archive: &mut CubeArchive) {
+        self.canvas.clear();
+        self.tracker = TeamTracker::new();
+        self.layer.clear();
+        if let Some(onion_skin) = self.onion_skin.as_mut() {
+            onion_skin.clear();
+        }
+        self.renderer.clear();
+        self.raytracer.clear();
+        self.replay(archive);
+    }
+
+    // Replays the journal from db to rebuild the canvas.
+    fn replay(&mut self, archive: &mut CubeArchive) {
+        let journal = archive.get_journal().expect("failed to extract events");
+        for entry in journal {
+            if let Err(e) = self.canvas.apply(&entry.event) {
+                eprintln!("Skipping archived event {:?}: {}", entry.event, e);
+                continue;
+            }
+            self.tracker
+                .apply(&entry.event, entry.metadata.team.as_deref());
+        }
+        for cube in self.canvas.cubes() {
+            self.renderer.add_cube(&cube);
+            self.raytracer.add_cube(&cube);
+        }
+    }
+
+    fn render(&mut self, img_filepath: &str, archive: Option<&mut CubeArchive>) {
+        let current_time = std::time::Instant::now();
+        if current_time < self.next_expected_frame {
+            eprintln!(
+                "skipping frame at {:?}, next expected at {:?}",
+                current_time, self.next_expected_frame
+            );
+            return;
+        }
+        if let Some(archive) = archive {
+            self.label_hovered(archive);
+        }
+        match self.draw(current_time) {
+            Some(img) => {
+                self.push_frame(&img);
+                if let Err(e) = save_image(&img, img_filepath) {
+                    eprintln!("Unable to save the rendered frame: {}", e);
+                    return;
+                }
+            }
+            None => eprintln!("Unable to capture the rendered frame!"),
+        }
+        let last_attempted_frame = current_time;
+        let current_time = std::time::Instant::now();
+        let render_time = current_time.duration_since(last_attempted_frame);
+        let frames_lost = render_time.as_millis() / self.frame_time.as_millis();
+        if frames_lost > 0 {
+            eprintln!(
+                "Lost {} frames! Render time {:?}, frame time {:?}",
+                frames_lost, render_time, self.frame_time
+            );
+        }
+        if let Some(scale) = self.quality.record(frames_lost as u32) {
+            eprintln!(
+                "Rendering snapshots and the stream at {:.0}% of their quality",
+                scale * 100.0
+            );
+        }
+        self.next_expected_frame = last_attempted_frame
+            .checked_add(
+                self.frame_time
+                    .checked_mul(frames_lost as u32 + 1)
+                    .expect("Failed to compute lost frames"),
+            )
+            .expect("Failed to compute next expected frame");
+    }
+
+    // Names who placed the cube under the cursor of the window, so that it
+    // can be credited on air.
+    fn label_hovered(&mut self, archive: &mut CubeArchive) {
+        let label = self.renderer.hovered().and_then(|position| {
+            let (_, entry) = archive
+                .lookup(position)
+                .expect("Failed to read from database")?;
+            Some((position, entry.metadata.author?))
+        });
+        self.renderer.set_label(label);
+    }
+
+    // Draws the overlay as it is at `now`: seen from where the camera got on
+    // its way to the view, turned as far as the spin got, under the weather.
+    fn draw(&mut self, now: Instant) -> Option<RgbImage> {
+        let yaw = self.spin.advance(now.duration_since(self.last_spin));
+        self.renderer.set_yaw(yaw);
+        self.last_spin = now;
+        let transform = SceneTransform::new(self.canvas.side_len());
+        let camera = self.views.pose(now);
+        self.renderer
+            .set_camera(camera.map(|camera| transform.turn_camera(camera, yaw)));
+        if let Some(onion_skin) = self.onion_skin.as_mut() {
+            self.renderer.set_ghosts(&onion_skin.ghosts(now));
+        }
+        let mut img = self.renderer.render()?;
+        self.overlay_post_processor.apply(&mut img, None);
+        if let Some(weather) = self.weather.as_mut() {
+            weather.advance(now.duration_since(self.last_weather));
+            weather.draw(&mut img);
+        }
+        self.last_weather = now;
+        if !self.teams.is_empty() {
+            let scores = self.tracker.scoreboard(self.teams.names());
+            draw_scoreboard(&mut img, &self.teams, &scores);
+        }
+        if let Some(hud) = &self.hud {
+            hud.draw(&mut img, chrono::Local::now());
+        }
+        Some(img)
+    }
+
+    // Draws a frame of the stream in between those of the image file, so
+    // that the orbit looks smooth there.
+    fn stream_frame(&mut self) {
+        let watched = self
+            .stream
+            .as_ref()
+            .is_some_and(|(http, path)| http.stream_clients(path) > 0);
+        let now = Instant::now();
+        let moving = self.spin.is_spinning() || self.views.is_moving(now);
+        if !watched || (!moving && self.weather.is_none()) {
+            return;
+        }
+        match self.draw(now) {
+            Some(img) => self.push_frame(&img),
+            None => eprintln!("Unable to capture the streamed frame!"),
+        }
+    }
+
+    fn push_frame(&self, img: &RgbImage) {
+        if let Some((http, path)) = &self.stream {
+            match encode_jpeg(img, self.quality.jpeg_quality(JPEG_QUALITY)) {
+                Ok(jpeg) => http.push_frame(path, jpeg),
+                Err(e) => eprintln!("Unable to encode the streamed frame: {}", e),
+            }
+        }
+    }
+
+    // Traces on a copy of the scene, so the live overlay keeps being rendered
+    // in the meantime.
+    fn snapshot(&self, filepath: &str) {
+        let mut raytracer = self.raytracer.clone();
+        raytracer.set_size(self.quality.resolution(self.snapshot_resolution));
+        raytracer.set_camera(self.views.target());
+        let filepath = filepath.to_owned();
+        let post_processor = self.snapshot_post_processor.clone();
+        let stereo = self.snapshot_stereo;
+        let auxiliary = self.snapshot_auxiliary.clone();
+        let minimap = self.snapshot_minimap.map(|(corner, share)| {
+            let minimap = Minimap::new(&self.canvas);
+            let minimap = match self.activity.as_ref() {
+                Some(activity) => minimap.with_heat(activity, Instant::now()),
+                None => minimap,
+            };
+            (minimap, corner, share)
+        });
+        let watermark = self.snapshot_watermark.clone();
+        let http = self.http.clone();
+        tokio::task::spawn_blocking(move || {
+            let (mut img, depth) = match stereo {
+                Some((mode, separation)) => render_stereo(&raytracer, mode, separation),
+                None => raytracer.render_with_depth(),
+            };
+            // Only the image of a single eye has an emission to match.
+            if post_processor.glows() && stereo.is_none() {
+                post_processor.glow(&mut img, &raytracer.render_emission());
+            }
+            post_processor.apply(&mut img, Some(&depth));
+            if let Some((minimap, corner, share)) = minimap {
+                minimap.draw(&mut img, corner, share, &raytracer.camera());
+            }
+            if let Some(watermark) = watermark.as_ref() {
+                watermark.draw(&mut img);
+            }
+            if let Err(e) = save_image(&img, &filepath) {
+                eprintln!("Unable to save the snapshot: {}", e);
+            }
+            if !auxiliary.is_empty() {
+                let buffers = raytracer.render_auxiliary();
+                save_auxiliary(&buffers, &auxiliary, &filepath, http.as_ref());
+            }
+        });
+    }
+
+    fn set_theme(&mut self, name: &str) {
+        match self.themes.get(name) {
+            Some(post_processor) => self.overlay_post_processor = post_processor.clone(),
+            None => eprintln!("Unknown theme {}", name),
+        }
+    }
+
+    fn set_view(&mut self, name: &str) {
+        if !self.views.switch(name, Instant::now()) {
+            eprintln!("Unknown view {}", name);
+        }
+    }
+
+    // Starts the effect afresh, or clears the weather.
+    fn set_weather(&mut self, effect: Option<WeatherEffect>) {
+        self.weather = match effect {
+            Some(effect) => {
+                let seed = chrono::Utc::now().timestamp() as u64;
+                match Weather::new(effect, &self.weather_config, seed) {
+                    Ok(weather) => Some(weather),
+                    Err(e) => {
+                        eprintln!("Unable to change the weather: {}", e);
+                        return;
+                    }
+                }
+            }
+            None => None,
+        };
+    }
+
+    fn step_screensaver(&mut self) {
+        if let Some(screensaver) = self.screensaver.as_mut() {
+            if self.last_placement.elapsed() >= self.idle_time {
+                let next = screensaver.step(&self.canvas);
+                swap_layer(&mut self.renderer, &mut self.layer, next);
+            }
+        }
+    }
+
+    fn set_slice(&mut self, slice: Slice, tx: &CommandSenders) {
+        self.renderer.set_slice(Some(slice));
+        self.slice_end = Instant::now() + self.slice_duration;
+        let slice_duration = self.slice_duration;
+        let tx = tx.priority.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(slice_duration).await;
+            let _ = tx.send(Command::Slice(None));
+        });
+    }
+
+    fn set_goal(&mut self, progress: Option<GoalProgress>) {
+        if let Some(hud) = self.hud.as_mut() {
+            hud.set_goal(progress);
+        }
+    }
+
+    // Only the timer of the last slice lifts it.
+    fn lift_slice(&mut self) {
+        if Instant::now() >= self.slice_end {
+            self.renderer.set_slice(None);
+        }
+    }
+
+    fn set_highlight(&mut self, positions: Vec<Position>, tx: &CommandSenders) {
+        self.renderer
+            .set_highlight(Some(positions.into_iter().collect()));
+        self.highlight_end = Instant::now() + self.highlight_duration;
+        let highlight_duration = self.highlight_duration;
+        let tx = tx.priority.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(highlight_duration).await;
+            let _ = tx.send(Command::Highlight(None));
+        });
+    }
+
+    // Only the timer of the last highlight lifts it.
+    fn lift_highlight(&mut self) {
+        if Instant::now() >= self.highlight_end {
+            self.renderer.set_highlight(None);
+        }
+    }
+
+    // Applies an event which passed the checks of the canvas.
+    fn apply_event(&mut self, lane: Lane, event: &CanvasEvent, team: Option<&str>) {
+        if lane == Lane::Viewer {
+            self.last_placement = Instant::now();
+            if let Some(position) = event.position() {
+                self.views.observe(position, self.last_placement);
+                if let Some(activity) = self.activity.as_mut() {
+                    activity.observe(position, self.last_placement);
+                }
+            }
+        }
+        // Out of the way before the canvas changes under it.
+        if !self.layer.is_empty() {
+            swap_layer(&mut self.renderer, &mut self.layer, Vec::new());
+        }
+        if let Some(onion_skin) = self.onion_skin.as_mut() {
+            onion_skin.observe(event, &self.canvas, Instant::now());
+        }
+        self.canvas.apply(event).expect("Event already checked");
+        self.tracker.apply(event, team);
+        self.renderer.apply_event(event);
+        self.raytracer.apply_event(event);
+    }
+}
+
+// Where the applied events and the changes to the view go.
+enum Scene {
+    Local(Box<Overlay>),
+    // The renderer process of the split setup, while connected.
+    Remote(Option<IpcSender>),
+    // Nothing is drawn, with `--no-render`.
+    Headless,
+}
+
+impl Scene {
+    // Sends to the renderer process, if connected. The connection is dropped
+    // on error, the whole journal is sent again once it's re-established.
+    async fn forward(&mut self, message: &IpcMessage) {
+        if let Scene::Remote(Some(sender)) = self {
+            if let Err(e) = sender.send(message).await {
+                eprintln!("Lost connection to the renderer: {}", e);
+                *self = Scene::Remote(None);
+            }
+        }
+    }
+}
+
+// Everything the commands act on, in every mode. Placements are persisted
+// only when there's a journal, i.e. when this process also handles the chat.
+// Events are journaled before being applied to the scene, so that after a
+// crash they're replayed on restart, and events from commands which were
+// already applied are ignored.
+struct State<'a> {
+    config: &'a TwixelBoxBotConfig,
+    tx: CommandSenders,
+    announcer: Option<Announcer>,
+    journal: Option<Journal>,
+    scene: Scene,
+    locked: bool,
+    palette: Option<Palette>,
+    volumes: VolumeCounter,
+    budget: Option<EditBudget>,
+    // Edits of chat waiting for the budget, oldest first.
+    over_budget: VecDeque<(Lane, Command)>,
+    // Whether chat was told it's over the budget, since it last had some.
+    budget_notified: bool,
+    // Stages of the event being handled.
+    timing: CommandTiming,
+}
+
+impl<'a> State<'a> {
+    fn new(
+        config: &'a TwixelBoxBotConfig,
+        tx: CommandSenders,
+        announcer: Option<Announcer>,
+        journal: Option<Journal>,
+        scene: Scene,
+    ) -> Self {
+        State {
+            config,
+            tx,
+            announcer,
+            journal,
+            scene,
+            locked: false,
+            palette: None,
+            volumes: VolumeCounter::new(&config.commands),
+            budget: EditBudget::new(&config.edit_budget, canvas_volume(&config.twixelbox)),
+            over_budget: VecDeque::new(),
+            budget_notified: false,
+            timing: CommandTiming::start(),
+        }
+    }
+
+    fn announce(&self, message: String) {
+        if let Some(announcer) = self.announcer.as_ref() {
+            let _ = announcer.send(message.into());
+        }
+    }
+
+    async fn reconnect_renderer(&mut self) {
+        if let (Scene::Remote(renderer @ None), Some(journal)) =
+            (&mut self.scene, self.journal.as_mut())
+        {
+            let fog = journal.progression.as_ref().map(Progression::region);
+            let goal = journal.goal.as_ref().map(Goal::progress);
+            *renderer =
+                connect_to_renderer(&self.config.ipc, &mut journal.archive, fog, goal).await;
+        }
+    }
+
+    async fn handle_command(&mut self, lane: Lane, command: Command) {
+        let command = match self.check_budget(lane, command) {
+            Some(command) => command,
+            None => return,
+        };
+        let config = self.config;
+        let tx = &self.tx;
+        let announcer = self.announcer.as_ref();
+        match command {
+            command @ Command::Event { .. } => self.handle_event(lane, command).await,
+            Command::ReleaseEdits => self.release_edits().await,
+            Command::Render => {
+                if let Scene::Local(overlay) = &mut self.scene {
+                    let metrics = tx.viewer.metrics();
+                    if metrics.depth > 0 || metrics.dropped > 0 {
+                        debug!("Command queue: {:?}", metrics);
+                    }
+                    let archive = self.journal.as_mut().map(|journal| &mut journal.archive);
+                    overlay.render(&config.twixelbox.img_filepath, archive);
+                }
+            }
+            Command::StreamFrame => {
+                if let Scene::Local(overlay) = &mut self.scene {
+                    overlay.stream_frame();
+                }
+            }
+            Command::GoalProgress(progress) => match &mut self.scene {
+                Scene::Local(overlay) => overlay.set_goal(progress),
+                Scene::Remote(_) => self.scene.forward(&IpcMessage::Goal(progress)).await,
+                Scene::Headless => {}
+            },
+            Command::StreamInfo(info) => {
+                if let Scene::Local(overlay) = &mut self.scene {
+                    if let Some(hud) = overlay.hud.as_mut() {
+                        hud.set_stream_info(info);
+                    }
+                }
+            }
+            Command::Resync => {
+                if self.resync().await {
+                    self.announce(self.config.announcements.format("resynced", &[]));
+                }
+            }
+            Command::RestoreCheckpoint { name, login } => {
+                self.restore_checkpoint(&name, login).await
+            }
+            Command::ExternalEvents => {
+                let entries = match self.journal.as_mut() {
+                    Some(journal) => {
+                        let entries = journal
+                            .archive
+                            .external_events()
+                            .expect("Failed to read from database");
+                        for entry in &entries {
+                            journal.log(entry.clone());
+                        }
+                        entries
+                    }
+                    None => return,
+                };
+                if !entries.is_empty() {
+                    debug!("Drawing {} events journaled elsewhere", entries.len());
+                }
+                for entry in entries {
+                    self.draw_journaled(entry).await;
+                }
+            }
+            // Only a renderer which journals the events can check them.
+            Command::CheckDrift => {
+                if let (Scene::Local(overlay), Some(journal)) =
+                    (&mut self.scene, self.journal.as_mut())
+                {
+                    overlay.check_drift(&mut journal.archive);
+                }
+            }
+            Command::Snapshot => match &self.scene {
+                Scene::Local(overlay) => overlay.snapshot(&config.snapshot.filepath),
+                Scene::Remote(_) => self.scene.forward(&IpcMessage::Snapshot).await,
+                Scene::Headless => {}
+            },
+            Command::Screensaver => {
+                if let Scene::Local(overlay) = &mut self.scene {
+                    overlay.step_screensaver();
+                }
+            }
+            Command::Fog(region) => match &mut self.scene {
+                Scene::Local(overlay) => overlay.renderer.set_fog(region),
+                Scene::Remote(_) => self.scene.forward(&IpcMessage::Fog(region)).await,
+                Scene::Headless => {}
+            },
+            Command::SetSpin(speed) => match &mut self.scene {
+                Scene::Local(overlay) => overlay.spin.set_speed(speed),
+                Scene::Remote(_) => self.scene.forward(&IpcMessage::Spin(speed)).await,
+                Scene::Headless => {}
+            },
+            Command::Theme(name) => match &mut self.scene {
+                Scene::Local(overlay) => overlay.set_theme(&name),
+                Scene::Remote(_) => self.scene.forward(&IpcMessage::Theme(name)).await,
+                Scene::Headless => {}
+            },
+            Command::View(name) => match &mut self.scene {
+                Scene::Local(overlay) => overlay.set_view(&name),
+                Scene::Remote(_) => self.scene.forward(&IpcMessage::View(name)).await,
+                Scene::Headless => {}
+            },
+            Command::SetWeather(effect) => match &mut self.scene {
+                Scene::Local(overlay) => overlay.set_weather(effect),
+                Scene::Remote(_) => self.scene.forward(&IpcMessage::Weather(effect)).await,
+                Scene::Headless => {}
+            },
+            Command::Slice(Some(slice)) => match &mut self.scene {
+                Scene::Local(overlay) => overlay.set_slice(slice, tx),
+                Scene::Remote(_) => self.scene.forward(&IpcMessage::Slice(slice)).await,
+                Scene::Headless => {}
+            },
+            // The renderer lifts the slices it's sent by itself.
+            Command::Slice(None) => {
+                if let Scene::Local(overlay) = &mut self.scene {
+                    overlay.lift_slice();
+                }
+            }
+            Command::Highlight(Some(positions)) => match &mut self.scene {
+                Scene::Local(overlay) => overlay.set_highlight(positions, tx),
+                Scene::Remote(_) => self.scene.forward(&IpcMessage::Highlight(positions)).await,
+                Scene::Headless => {}
+            },
+            // Same for the highlights.
+            Command::Highlight(None) => {
+                if let Scene::Local(overlay) = &mut self.scene {
+                    overlay.lift_highlight();
+                }
+            }
+            Command::Lock(lock) => self.locked = lock,
+            Command::Palette(colours) => {
+                self.palette = colours.and_then(Palette::new);
+                self.announce(describe_palette(
+                    &self.config.announcements,
+                    self.palette.as_ref(),
+                ));
+            }
+            Command::Import { cubes, restrict } => {
+                let palette = self.palette.as_ref().filter(|_| restrict);
+                let events = cubes
+                    .into_iter()
+                    .map(|cube| restrict_colours(palette, CanvasEvent::CubePlaced(cube)))
+                    .collect();
+                let import = CommandUse {
+                    command: "import".to_owned(),
+                    id: Uuid::new_v4(),
+                };
+                queue_events(tx, events, None, Some(import));
+            }
+            Command::Terrain(seed) => {
+                let cubes =
+                    generate_terrain(config.twixelbox.cube_size, config.twixelbox.pixel_art, seed);
+                let events = cubes.into_iter().map(CanvasEvent::CubePlaced).collect();
+                queue_events(tx, events, None, None);
+                self.announce(
+                    config
+                        .announcements
+                        .format("terrain_generated", &[("seed", &seed)]),
+                );
             }
+            // The rest is handled by whoever journals the events, there's no
+            // chat without an archive.
+            command => {
+                if let Some(journal) = self.journal.as_mut() {
+                    let palette = self.palette.as_ref();
+                    handle_journal_command(journal, config, tx, announcer, palette, command);
+                }
+            }
+        }
+    }
+
+    // Draws an event already journaled, by another process.
+    // Draws the canvas again from the journal. Returns whether there was
+    // something to draw it on.
+    async fn resync(&mut self) -> bool {
+        match &mut self.scene {
+            Scene::Local(overlay) => match self.journal.as_mut() {
+                Some(journal) => overlay.resync(&mut journal.archive),
+                None => return false,
+            },
+            // The renderer draws the journal from scratch on each connection.
+            Scene::Remote(_) => {
+                self.scene = Scene::Remote(None);
+                self.reconnect_renderer().await;
+            }
+            Scene::Headless => return false,
+        }
+        true
+    }
+
+    // Applies `!checkpoint restore`: the clear and the cubes of the
+    // checkpoint are journaled at once, so that no placement lands in
+    // between, and the canvas is drawn again. They are mirrored by the
+    // trackers, but as placed by the bot they count towards neither the
+    // goal, the progression nor the achievements.
+    async fn restore_checkpoint(&mut self, name: &str, login: String) {
+        let journal = match self.journal.as_mut() {
+            Some(journal) => journal,
+            None => return,
+        };
+        let announcements = &self.config.announcements;
+        let metadata = journal.competitions.metadata(Some(login), None);
+        let entries = match journal
+            .archive
+            .restore_checkpoint(name, &metadata)
+            .expect("Failed to update database")
+        {
+            Some(entries) => entries,
+            None => {
+                let announcement = unknown_checkpoint(&mut journal.archive, announcements, name);
+                self.announce(announcement);
+                return;
+            }
+        };
+        for entry in &entries {
+            if let Some(decay) = journal.decay.as_mut() {
+                decay.apply(&entry.event, &entry.metadata);
+            }
+            if let Some(achievements) = journal.achievements.as_mut() {
+                let day = local_day(entry.metadata.timestamp);
+                achievements.apply(&entry.event, &entry.metadata, day);
+            }
+            if let Some(scripts) = journal.scripts.as_mut() {
+                scripts.observe(&entry.event);
+            }
+            journal.log(entry.clone());
+        }
+        self.resync().await;
+        let count = entries.len() - 1;
+        self.announce(
+            self.config
+                .announcements
+                .format("checkpoint_restored", &[("name", &name), ("count", &count)]),
+        );
+    }
+
+    async fn draw_journaled(&mut self, entry: JournalEntry) {
+        let JournalEntry {
+            command_id,
+            event,
+            metadata,
+        } = entry;
+        match &mut self.scene {
+            Scene::Local(overlay) => {
+                if let Err(e) = overlay.canvas.check(&event) {
+                    eprintln!("Skipping journaled event {:?}: {}", event, e);
+                    return;
+                }
+                overlay.apply_event(Lane::Priority, &event, metadata.team.as_deref());
+            }
+            Scene::Remote(_) => {
+                let message = IpcMessage::Event {
+                    id: command_id,
+                    event: event.clone(),
+                    team: metadata.team,
+                };
+                self.scene.forward(&message).await;
+            }
+            Scene::Headless => {}
+        }
+        if let Some(scripts) = self.journal.as_mut().and_then(|j| j.scripts.as_mut()) {
+            scripts.observe(&event);
+        }
+    }
+
+    // Returns whether the event was applied, i.e. not rejected nor already
+    // applied.
+    async fn apply_event(
+        &mut self,
+        lane: Lane,
+        id: Uuid,
+        event: CanvasEvent,
+        author: Option<String>,
+        team: Option<String>,
+        message: Option<String>,
+    ) -> bool {
+        if self.locked && lane == Lane::Viewer {
+            trace!("Canvas locked, rejecting {:?}", event);
+            return false;
+        }
+        if let (Some(journal), Some(author)) = (self.journal.as_ref(), author.as_ref()) {
+            if !journal.filter.is_allowed(author) {
+                trace!("Ignoring {:?} from {}", event, author);
+                return false;
+            }
+        }
+        let progression = self.journal.as_ref().and_then(|j| j.progression.as_ref());
+        if lane == Lane::Viewer && is_locked(progression, &event) {
+            trace!("Region locked, rejecting {:?}", event);
+            return false;
+        }
+        let protected = self.journal.as_ref().map(|j| &j.protected);
+        if lane == Lane::Viewer && protected.is_some_and(|protected| protected.protects(&event)) {
+            trace!("Region protected, rejecting {:?}", event);
+            return false;
+        }
+        let event = match lane {
+            Lane::Viewer => restrict_colours(self.palette.as_ref(), event),
+            Lane::Priority => event,
+        };
+        if let Scene::Local(overlay) = &self.scene {
+            if let Err(e) = overlay.canvas.check(&event) {
+                eprintln!("Rejected event: {}", e);
+                return false;
+            }
+        }
+        self.timing.lap(Stage::Validate);
+        let mut team = team;
+        let mut unlocked = None;
+        let mut goal = None;
+        if let Some(journal) = self.journal.as_mut() {
+            team = team.or_else(|| journal.members.team_of(&author));
+            let metadata = EventMetadata {
+                message,
+                viewer: lane == Lane::Viewer && author.is_some(),
+                ..journal.competitions.metadata(author.clone(), team.clone())
+            };
+            // Command ids are unique in the archive, which tells apart the
+            // commands already applied, e.g. before a restart.
+            let is_new = journal
+                .archive
+                .append_event(id, &event, &metadata)
+                .expect("Failed to add event to database");
+            if !is_new {
+                trace!("Skipping already applied command {}", id);
+                return false;
+            }
+            if let Some(decay) = journal.decay.as_mut() {
+                decay.apply(&event, &metadata);
+            }
+            if let Some(progression) = journal.progression.as_mut() {
+                unlocked = progress(
+                    progression,
+                    &event,
+                    &metadata,
+                    &mut journal.archive,
+                    self.announcer.as_ref(),
+                    &self.config.announcements,
+                );
+            }
+            if let Some(progress) = journal.goal.as_mut() {
+                goal = count_towards_goal(
+                    progress,
+                    &event,
+                    &metadata,
+                    journal.markers.as_mut(),
+                    self.announcer.as_ref(),
+                    &self.config.announcements,
+                );
+            }
+            if let Some(achievements) = journal.achievements.as_mut() {
+                unlock_achievements(
+                    achievements,
+                    &event,
+                    &metadata,
+                    &mut journal.archive,
+                    self.announcer.as_ref(),
+                    &self.config.announcements,
+                );
+            }
+            if let Some(grpc) = &journal.grpc {
+                grpc.publish(AppliedEvent::new(id, event.clone(), author.clone()));
+            }
+            if let Some(mqtt) = &journal.mqtt {
+                mqtt.publish_event(&event);
+            }
+            if let Some(markers) = journal.markers.as_mut() {
+                markers.observe(&event);
+            }
+            journal.log(JournalEntry {
+                command_id: id,
+                event: event.clone(),
+                metadata,
+            });
+        }
+        self.timing.lap(Stage::Persist);
+        if let Scene::Local(overlay) = &mut self.scene {
+            overlay.apply_event(lane, &event, team.as_deref());
+            if unlocked.is_some() {
+                overlay.renderer.set_fog(unlocked);
+            }
+            if goal.is_some() {
+                overlay.set_goal(goal);
+            }
+        } else {
+            let message = IpcMessage::Event {
+                id,
+                event: event.clone(),
+                team,
+            };
+            self.scene.forward(&message).await;
+            if unlocked.is_some() {
+                self.scene.forward(&IpcMessage::Fog(unlocked)).await;
+            }
+            if goal.is_some() {
+                self.scene.forward(&IpcMessage::Goal(goal)).await;
+            }
+        }
+        let scripts = self.journal.as_mut().and_then(|j| j.scripts.as_mut());
+        if let Some(scripts) = scripts {
+            scripts.observe(&event);
+            if let (CanvasEvent::CubePlaced(cube), Lane::Viewer) = (&event, lane) {
+                reload_scripts(scripts);
+                let result = scripts.placed(author.as_deref().unwrap_or_default(), cube);
+                apply_script_actions(result, None, None, &self.tx, self.announcer.as_ref());
+            }
+        }
+        self.timing.lap(Stage::Apply);
+        true
+    }
+
+    // Applies the event of a `Command::Event`, within the volume of the
+    // command it counts towards.
+    async fn handle_event(&mut self, lane: Lane, command: Command) {
+        let (id, event, author, team, message, reply_to, command_use, parse_time) = match command {
+            Command::Event {
+                id,
+                event,
+                author,
+                team,
+                message,
+                reply_to,
+                command_use,
+                parse_time,
+            } => (
+                id,
+                event,
+                author,
+                team,
+                message,
+                reply_to,
+                command_use,
+                parse_time,
+            ),
+            _ => return,
+        };
+        self.timing = CommandTiming::start();
+        self.timing
+            .record(Stage::Parse, parse_time.unwrap_or_default());
+        if let Some(command_use) = command_use.as_ref() {
+            if let Err(e) = self.volumes.count(command_use) {
+                trace!("Rejected {:?} of !{}: {}", event, command_use.command, e);
+                return;
+            }
+        }
+        let placed = match (&event, lane, &author) {
+            (CanvasEvent::CubePlaced(cube), Lane::Viewer, Some(author)) => {
+                Some((cube.position, author.clone()))
+            }
+            _ => None,
+        };
+        let context = (event.clone(), author.clone());
+        let applied = self
+            .apply_event(lane, id, event, author, team, message)
+            .await;
+        // Rejected, or already applied, once checked.
+        if !applied {
+            self.timing.lap(Stage::Validate);
+        }
+        self.observe_timing(id, context);
+        if let (true, Some((position, author))) = (applied, placed) {
+            self.confirm_placement(position, &author, reply_to);
+        }
+    }
+
+    // Counts the timing of the event just handled, and logs it if it's slow.
+    fn observe_timing(&mut self, id: Uuid, (event, author): (CanvasEvent, Option<String>)) {
+        let journal = match self.journal.as_mut() {
+            Some(journal) => journal,
+            None => return,
+        };
+        if journal.timer.observe(&self.timing) {
+            warn!(
+                "Slow command {} from {}, {:?}: {:?} ({})",
+                id,
+                author.as_deref().unwrap_or("the bot"),
+                event,
+                self.timing.total(),
+                self.timing
+            );
+        }
+    }
+
+    // Lets through the commands within the edit budget. The edits of chat
+    // over it wait for it behind the others, or are dropped, and chat is told
+    // once until the budget lets an edit through again.
+    fn check_budget(&mut self, lane: Lane, command: Command) -> Option<Command> {
+        let budget = match (&command, self.budget.as_mut()) {
+            (
+                Command::Event {
+                    command_use: Some(_),
+                    ..
+                },
+                Some(budget),
+            ) => budget,
+            _ => return Some(command),
+        };
+        let now = Instant::now();
+        if self.over_budget.is_empty() {
+            match budget.spend(now) {
+                Ok(()) => {
+                    self.budget_notified = false;
+                    return Some(command);
+                }
+                Err(e) => trace!("Holding back the edits of chat: {}", e),
+            }
+        }
+        let next_edit = budget.next_edit(now);
+        let queued = match self.config.edit_budget.overflow {
+            BudgetOverflow::Queue if self.over_budget.len() < budget.per_minute() => {
+                if self.over_budget.is_empty() {
+                    self.schedule_release(next_edit);
+                }
+                self.over_budget.push_back((lane, command));
+                true
+            }
+            _ => false,
+        };
+        if !self.budget_notified {
+            self.budget_notified = true;
+            let seconds = next_edit
+                .saturating_duration_since(now)
+                .as_secs_f32()
+                .ceil() as u64;
+            let name = match queued {
+                true => "edits_queued",
+                false => "edits_rejected",
+            };
+            self.announce(
+                self.config
+                    .announcements
+                    .format(name, &[("seconds", &seconds)]),
+            );
+        }
+        None
+    }
+
+    // Applies the edits waiting for the budget, as far as it allows.
+    async fn release_edits(&mut self) {
+        let now = Instant::now();
+        loop {
+            let budget = match self.budget.as_mut() {
+                Some(budget) if !self.over_budget.is_empty() => budget,
+                _ => return,
+            };
+            if budget.spend(now).is_err() {
+                let next_edit = budget.next_edit(now);
+                self.schedule_release(next_edit);
+                return;
+            }
+            if let Some((lane, command)) = self.over_budget.pop_front() {
+                self.handle_event(lane, command).await;
+            }
+        }
+    }
+
+    fn schedule_release(&self, at: Instant) {
+        let tx = self.tx.priority.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep_until(at.into()).await;
+            let _ = tx.send(Command::ReleaseEdits);
+        });
+    }
+
+    // Tells `author` where their cube landed, in chat coordinates, in reply
+    // to their message.
+    fn confirm_placement(&self, position: Position, author: &str, reply_to: Option<String>) {
+        let announcer = match &self.announcer {
+            Some(announcer) if self.config.outbox.confirm_placements => announcer,
+            _ => return,
+        };
+        let side = self.config.twixelbox.cube_size;
+        let [x, y, z] = self.config.coordinates.from_canvas(position, side);
+        let text = self.config.announcements.format(
+            "cube_placed",
+            &[("user", &author), ("x", &x), ("y", &y), ("z", &z)],
+        );
+        let _ = announcer.send(ChatMessage::Confirmation {
+            login: author.to_owned(),
+            cubes: 1,
+            text,
+            reply_to,
+        });
+    }
+}
+
+// Commands acting on the archive, or on what's derived from it.
+fn handle_journal_command(
+    journal: &mut Journal,
+    config: &TwixelBoxBotConfig,
+    tx: &CommandSenders,
+    announcer: Option<&Announcer>,
+    palette: Option<&Palette>,
+    command: Command,
+) {
+    let archive = &mut journal.archive;
+    let announcements = &config.announcements;
+    let announce = |message: String| {
+        if let Some(announcer) = announcer {
+            let _ = announcer.send(message.into());
+        }
+    };
+    match command {
+        Command::BuildSheet {
+            width,
+            height,
+            pixels,
+            projection,
+        } => {
+            if let (Some(http), Some(img)) =
+                (&journal.http, RgbImage::from_raw(width, height, pixels))
+            {
+                publish_build_sheet(
+                    http,
+                    img,
+                    projection,
+                    palette.cloned(),
+                    config.twixelbox.cube_size,
+                    announcer.cloned(),
+                    announcements,
+                );
+            }
+        }
+        Command::Export => {
+            if let Some(http) = &journal.http {
+                publish_exports(archive, http, announcer.cloned(), announcements);
+            }
+        }
+        Command::StartCompetition {
+            name,
+            duration_secs,
+        } => {
+            if let Some(markers) = journal.markers.as_mut() {
+                markers.mark(MarkerMoment::EventStarted(name.clone()));
+            }
+            journal.competitions.start(archive, &name, duration_secs)
+        }
+        Command::EndCompetition(id) => journal.competitions.end(archive, id),
+        Command::JoinTeam { login, team } => journal.members.join(archive, &login, &team),
+        Command::Moderate {
+            login,
+            command_id,
+            action,
+        } => {
+            let rollback = action != ModerationAction::Keep;
+            if let Some(markers) = journal.markers.as_mut().filter(|_| rollback) {
+                markers.mark(MarkerMoment::Rollback(login.clone()));
+            }
+            moderate(archive, tx, &login, command_id, action)
+        }
+        Command::Restore(login) => announce(restore(archive, tx, announcements, &login)),
+        Command::ShowGoal => announce(match journal.goal.as_mut() {
+            Some(goal) => {
+                goal.roll_over(today().0);
+                let progress = goal.progress();
+                let percent = (progress.share() * 100.0) as u32;
+                announcements.format(
+                    "goal",
+                    &[
+                        ("placed", &progress.placed),
+                        ("target", &progress.target),
+                        ("percent", &percent),
+                    ],
+                )
+            }
+            None => announcements.format("no_goal", &[]),
+        }),
+        Command::SetGoal(target) => {
+            match (target, journal.goal.as_mut()) {
+                (Some(target), Some(goal)) => goal.set_target(target),
+                (target, _) => journal.goal = load_goal(target, archive),
+            }
+            announce(match target {
+                Some(target) => announcements.format("goal_set", &[("target", &target)]),
+                None => announcements.format("no_goal", &[]),
+            });
+            let progress = journal.goal.as_ref().map(Goal::progress);
+            if let Err(e) = tx.priority.send(Command::GoalProgress(progress)) {
+                eprintln!("Unable to queue the progress of the goal: {}", e);
+            }
+        }
+        Command::ShowColours => match archive.get_journal() {
+            Ok(entries) => announce(describe_colours(
+                announcements,
+                &ColourStats::new(&entries, today().1, 3600, None),
+            )),
+            Err(e) => eprintln!("Unable to read the journal: {}", e),
+        },
+        Command::ShowAchievements(login) => {
+            if let Some(achievements) = &journal.achievements {
+                announce(describe_achievements(announcements, achievements, &login));
+            }
+        }
+        Command::Shoutout(user) => {
+            let positions = cubes_of(archive, &user.to_lowercase());
+            announce(
+                announcements.format("shoutout", &[("user", &user), ("count", &positions.len())]),
+            );
+            if !positions.is_empty() {
+                if let Err(e) = tx.priority.send(Command::Highlight(Some(positions))) {
+                    eprintln!("Unable to queue the highlight: {}", e);
+                }
+            }
+        }
+        Command::Protect { region, protected } => announce(protect(
+            &mut journal.protected,
+            archive,
+            config,
+            region,
+            protected,
+        )),
+        Command::Ignore { login, ignored } => announce(set_ignored(
+            &mut journal.filter,
+            archive,
+            announcements,
+            &login,
+            ignored,
+        )),
+        Command::Alias { name, body } => {
+            announce(save_alias(archive, announcements, &name, body.as_deref()))
+        }
+        Command::SaveCheckpoint(name) => announce(save_checkpoint(archive, announcements, &name)),
+        Command::Script {
+            name,
+            args,
+            user,
+            login,
+            moderator,
+        } => {
+            if let Some(scripts) = journal.scripts.as_mut() {
+                reload_scripts(scripts);
+                let result = scripts
+                    .command(&name, &args, &user, &login, moderator)
+                    .map(Option::unwrap_or_default);
+                let command_use = Some(CommandUse {
+                    command: name,
+                    id: Uuid::new_v4(),
+                })
+                .filter(|_| !moderator);
+                apply_script_actions(result, Some(login), command_use, tx, announcer);
+            }
+        }
+        Command::Stats => {
+            if let Some(stats) = journal.stats.as_mut() {
+                stats.report(archive, journal.http.as_ref(), announcer);
+            }
+            if let Some(http) = &journal.http {
+                publish_timings(http, &journal.timer);
+            }
+        }
+        Command::Plugin {
+            id,
+            name,
+            args,
+            caller,
+            message,
+            reply_to,
+        } => {
+            let mut canvas = ChatCanvas {
+                archive,
+                side_len: config.twixelbox.cube_size,
+                tx,
+                announcer,
+                id,
+                changes: 0,
+                author: caller.login.clone(),
+                message,
+                reply_to,
+                command_use: Some(CommandUse {
+                    command: name.clone(),
+                    id,
+                })
+                .filter(|_| !caller.moderator),
+            };
+            run_plugin(&journal.plugins, &mut canvas, &name, &caller, &args);
+        }
+        Command::Timelapse => {
+            if let Some(http) = &journal.http {
+                publish_timelapse(
+                    archive,
+                    &config.timelapse,
+                    http,
+                    config.twixelbox.cube_size,
+                    journal.watermark.as_ref(),
+                );
+            }
+        }
+        Command::Credits(window_secs) => {
+            if let Some(http) = &journal.http {
+                let window_secs = window_secs.unwrap_or(config.credits.hours * 3600);
+                let since = chrono::Utc::now().timestamp() - window_secs as i64;
+                publish_credits(
+                    archive,
+                    config,
+                    http,
+                    since,
+                    announcer.cloned(),
+                    journal.watermark.as_ref(),
+                );
+            }
+        }
+        Command::Decay => {
+            if let Some(decay) = journal.decay.as_mut() {
+                queue_events(tx, decay.step(chrono::Utc::now().timestamp()), None, None);
+            }
+        }
+        Command::PostSummary { period_secs } => {
+            let journal = archive.get_journal().expect("failed to extract events");
+            let since = chrono::Utc::now().timestamp() - period_secs;
+            let report = Report::new(&journal, since, 3600, None);
+            let summary = describe_summary(config, &report, period_secs);
+            if config.summary.chat {
+                announce(summary.clone());
+            }
+            if let Some(url) = &config.summary.discord_webhook {
+                // The snapshot rendered for the summary, or the last one.
+                let image = match config.summary.snapshot {
+                    true => std::fs::read(&config.snapshot.filepath).ok(),
+                    false => None,
+                };
+                post_to_webhook(url.clone(), summary, image);
+            }
+        }
+        Command::Prune => {
+            let retention = &config.retention;
+            let cutoff = retention.cutoff(chrono::Utc::now().timestamp());
+            match archive.prune(cutoff, retention.max_events, retention.dry_run) {
+                Ok(report) => log_pruning(&report, retention.dry_run),
+                Err(e) => eprintln!("Unable to prune the archive: {}", e),
+            }
+        }
+        // Handled by the state, whatever the mode.
+        Command::Event { .. }
+        | Command::Render
+        | Command::StreamFrame
+        | Command::StreamInfo(_)
+        | Command::GoalProgress(_)
+        | Command::CheckDrift
+        | Command::Resync
+        | Command::ExternalEvents
+        | Command::ReleaseEdits
+        | Command::Snapshot
+        | Command::Screensaver
+        | Command::Fog(_)
+        | Command::SetSpin(_)
+        | Command::Theme(_)
+        | Command::SetWeather(_)
+        | Command::View(_)
+        | Command::Slice(_)
+        | Command::Highlight(_)
+        | Command::Lock(_)
+        | Command::Palette(_)
+        | Command::Terrain(_)
+        | Command::Import { .. }
+        | Command::RestoreCheckpoint { .. } => {}
+    }
+}
+
+// What draws the canvas.
+enum SceneKind {
+    // The kiss3d window, or the wgpu renderer with the feature.
+    Local,
+    // The renderer process of the split setup.
+    Remote,
+    Headless,
+}
+
+// Assembles the runtime out of its components: the chat the commands come
+// from, the archive which journals them, the bot process of the split setup
+// sending its own, and what draws the canvas. Defaults to the standalone
+// setup, everything in one process. The HTTP server, the MQTT and gRPC sinks
+// and the schedules come with the archive, as configured.
+struct BotBuilder<'a> {
+    config: &'a TwixelBoxBotConfig,
+    chat: bool,
+    archive: Option<std::path::PathBuf>,
+    listen_for_bot: bool,
+    scene: SceneKind,
+}
+
+impl<'a> BotBuilder<'a> {
+    fn new(config: &'a TwixelBoxBotConfig) -> Self {
+        BotBuilder {
+            config,
+            chat: true,
+            archive: Some(std::path::PathBuf::from(ARCHIVE_FILEPATH)),
+            listen_for_bot: false,
+            scene: SceneKind::Local,
+        }
+    }
+
+    // The components of the configured mode, everything but the renderer
+    // with `--no-render`.
+    fn from_config(config: &'a TwixelBoxBotConfig, render: bool) -> Self {
+        let builder = match config.ipc.mode {
+            IpcMode::Standalone => BotBuilder::new(config),
+            IpcMode::Bot => BotBuilder::new(config).scene(SceneKind::Remote),
+            IpcMode::Renderer => BotBuilder::new(config)
+                .chat(false)
+                .archive(None)
+                .listen_for_bot(true),
+        };
+        match render {
+            true => builder,
+            false => builder.scene(SceneKind::Headless),
+        }
+    }
+
+    fn chat(mut self, chat: bool) -> Self {
+        self.chat = chat;
+        self
+    }
+
+    fn archive(mut self, archive: Option<std::path::PathBuf>) -> Self {
+        self.archive = archive;
+        self
+    }
+
+    // Listens for the bot process on the IPC address.
+    fn listen_for_bot(mut self, listen: bool) -> Self {
+        self.listen_for_bot = listen;
+        self
+    }
+
+    fn scene(mut self, scene: SceneKind) -> Self {
+        self.scene = scene;
+        self
+    }
+
+    // Runs until the commands stop, i.e. forever unless a component fails
+    // to start.
+    async fn run(self) {
+        let config = self.config;
+        if self.listen_for_bot && matches!(self.scene, SceneKind::Headless) {
+            eprintln!("The renderer process can't run with --no-render");
+            return;
+        }
+        let (tx, mut lanes) = match command_lanes(&config.command_queue) {
+            Ok(lanes) => lanes,
+            Err(e) => {
+                eprintln!("Error setting up the command queue: {}", e);
+                return;
+            }
+        };
+        if self.listen_for_bot {
+            if config.ipc.token.is_empty() && !is_loopback(&config.ipc.address) {
+                eprintln!(
+                    "Set an [ipc] token to listen for the bot on {}",
+                    config.ipc.address
+                );
+                return;
+            }
+            let listener = match IpcListener::bind(&config.ipc.address, &config.ipc.token).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    eprintln!("Unable to listen on {}: {}", config.ipc.address, e);
+                    return;
+                }
+            };
+            tokio::spawn(receive_from_bot(listener, tx.clone()));
+        }
+        let mut archive = self.archive.map(CubeArchive::new);
+        // Shared by chat and the web API, a chatter has the same limits on
+        // both.
+        let limits = Arc::new(Mutex::new(CommandLimits::new(&config.commands)));
+        let announcer = match (self.chat, archive.as_mut()) {
+            (true, Some(archive)) => {
+                let macros = load_macros(&config.macros, archive);
+                match connect_to_chat(config, tx.clone(), macros, limits.clone()).await {
+                    Some(announcer) => Some(announcer),
+                    None => return,
+                }
+            }
+            (true, None) => {
+                eprintln!("The chat needs an archive to journal the placements");
+                return;
+            }
+            (false, _) => None,
+        };
+        let teams = match Teams::new(&config.teams) {
+            Ok(teams) => teams,
+            Err(e) => {
+                eprintln!("Error in the teams configuration: {}", e);
+                return;
+            }
+        };
+        let overlay = match self.scene {
+            SceneKind::Local => match Overlay::new(config, &tx, teams.clone()) {
+                Some(overlay) => Some(overlay),
+                None => return,
+            },
+            SceneKind::Remote | SceneKind::Headless => None,
+        };
+        let mut journal = archive
+            .map(|archive| Journal::load(config, archive, teams, &tx, announcer.as_ref(), &limits));
+        let scene = match (overlay, self.scene) {
+            (Some(mut overlay), _) => {
+                if let Some(journal) = journal.as_mut() {
+                    overlay.replay(&mut journal.archive);
+                    let fog = journal.progression.as_ref().map(Progression::region);
+                    overlay.renderer.set_fog(fog);
+                    overlay.set_goal(journal.goal.as_ref().map(Goal::progress));
+                    overlay.http = journal.http.clone();
+                    if let (Some(http), Some(path)) = (&journal.http, &config.http.overlay_stream) {
+                        http.publish_stream(path);
+                        overlay.stream = Some((http.clone(), path.clone()));
+                    }
+                    schedule_drift_checks(&tx, &config.drift);
+                }
+                Scene::Local(Box::new(overlay))
+            }
+            (None, SceneKind::Remote) => Scene::Remote(None),
+            (None, _) => Scene::Headless,
+        };
+        let mut state = State::new(config, tx, announcer, journal, scene);
+        // Every time the connection to the renderer process is
+        // (re-)established the whole archive is sent, so either process can
+        // be restarted independently.
+        let mut reconnect_interval = tokio::time::interval(std::time::Duration::from_secs(5));
+        loop {
+            tokio::select! {
+                command = lanes.recv() => match command {
+                    Some((lane, command)) => state.handle_command(lane, command).await,
+                    None => break,
+                },
+                _ = reconnect_interval.tick() => state.reconnect_renderer().await,
+            }
+        }
+    }
+}
+
+#[tokio::main]
+pub async fn main() {
+    let args = Cli::from_args();
+    SimpleLogger::new()
+        .with_level(args.log_level)
+        .init()
+        .unwrap();
+
+    let config = match fs::read_to_string(&args.config_file) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!(
+                "Error opening the configuration file {}: {}",
+                args.config_file, e
+            );
+            eprintln!("Create the file or use the --config_file flag to specify an alternative file location");
+            return;
+        }
+    };
+
+    let config: TwixelBoxBotConfig = match toml::from_str(&config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!(
+                "Error parsing configuration file {}: {}",
+                args.config_file, e
+            );
+            return;
+        }
+    };
+
+    match args.command {
+        Some(CliCommand::Init { template, seed }) => {
+            if let Err(e) = init_archive(&config, template, seed) {
+                eprintln!("Unable to start the archive: {}", e);
+            }
+            return;
+        }
+        Some(CliCommand::Replay {
+            path,
+            speed,
+            max_wait,
+            new_ids,
+        }) => {
+            let pace =
+                speed.map(|speed| (speed, std::time::Duration::from_secs_f32(max_wait.max(0.0))));
+            if let Err(e) = replay_event_log(&config, path, pace, new_ids) {
+                eprintln!("Unable to replay the event log: {}", e);
+            }
+            return;
+        }
+        Some(CliCommand::RenderPath {
+            path,
+            output,
+            fps,
+            resolution,
+            samples,
+        }) => {
+            let resolution = resolution.unwrap_or(config.snapshot.resolution);
+            let samples = samples.unwrap_or(config.snapshot.samples);
+            if let Err(e) = render_path(&config, &path, &output, fps, resolution, samples) {
+                eprintln!("Unable to render the camera path: {}", e);
+            }
+            return;
+        }
+        Some(CliCommand::Report {
+            output,
+            since,
+            bucket,
+            palette,
+        }) => {
+            let palette = match palette.as_deref().map(parse_palette).transpose() {
+                Ok(palette) => palette.flatten(),
+                Err(e) => {
+                    eprintln!("Invalid palette: {}", e);
+                    return;
+                }
+            };
+            let (since, bucket) = match (
+                since.as_deref().map(parse_duration),
+                parse_duration(&bucket),
+            ) {
+                (Some(None), _) | (_, None) => {
+                    eprintln!("Durations are like 90s, 30m or 48h");
+                    return;
+                }
+                (since, Some(bucket)) => (since.flatten(), bucket),
+            };
+            let since = since.map_or(0, |since| {
+                chrono::Utc::now().timestamp() - since.as_secs() as i64
+            });
+            let bucket_secs = bucket.as_secs() as i64;
+            if let Err(e) = write_report(&output, since, bucket_secs, palette.as_ref()) {
+                eprintln!("Unable to write the report: {}", e);
+            }
+            return;
+        }
+        None => {}
+    }
+
+    BotBuilder::from_config(&config, !args.no_render)
+        .run()
+        .await;
+}
+
+// Writes the report of the archived events since the `since` unix timestamp
+// to the HTML page `output`.
+fn write_report(
+    output: &str,
+    since: i64,
+    bucket_secs: i64,
+    palette: Option<&Palette>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let journal = CubeArchive::new(std::path::PathBuf::from(ARCHIVE_FILEPATH)).get_journal()?;
+    let report = Report::new(&journal, since, bucket_secs, palette);
+    std::fs::write(output, report.to_html()?)?;
+    println!(
+        "Saved the report of {} builders to {}",
+        report.builders().len(),
+        output
+    );
+    Ok(())
+}
+
+// Renders the archived canvas seen along the camera path of `path_filepath`
+// into the GIF `output`.
+fn render_path(
+    config: &TwixelBoxBotConfig,
+    path_filepath: &str,
+    output: &str,
+    fps: f32,
+    resolution: u32,
+    samples: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = CameraPath::from_json(&std::fs::read_to_string(path_filepath)?)?;
+    let events = CubeArchive::new(std::path::PathBuf::from(ARCHIVE_FILEPATH)).get_events()?;
+    let (canvas, _) = Canvas::replay(config.twixelbox.cube_size, &events);
+    let mut raytracer = Raytracer::new(resolution, config.twixelbox.cube_size, samples);
+    raytracer.set_colour_correction(config.twixelbox.colour_correction);
+    raytracer.set_background(&Background::new(&config.twixelbox.background)?);
+    for cube in canvas.cubes() {
+        raytracer.add_cube(&cube);
+    }
+    println!(
+        "Rendering {:.1} seconds of flythrough at {} frames per second",
+        path.duration(),
+        fps
+    );
+    let watermark = Watermark::new(&config.watermark)?;
+    std::fs::write(
+        output,
+        render_camera_path(&raytracer, &path, fps, watermark.as_ref())?,
+    )?;
+    println!("Saved {}", output);
+    Ok(())
+}
+
+// Journals the cubes of `template` into a fresh archive. An existing archive
+// is never touched, it has to be moved away first.
+fn init_archive(
+    config: &TwixelBoxBotConfig,
+    template: CanvasTemplate,
+    seed: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let filepath = std::path::PathBuf::from(ARCHIVE_FILEPATH);
+    if filepath.exists() {
+        return Err(format!("{} already exists, move it away first", filepath.display()).into());
+    }
+    let timestamp = chrono::Utc::now().timestamp();
+    let seed = seed.unwrap_or(timestamp as u64);
+    let cubes = template.cubes(config.twixelbox.cube_size, config.twixelbox.pixel_art, seed);
+    let events: Vec<CanvasEvent> = cubes.into_iter().map(CanvasEvent::CubePlaced).collect();
+    let metadata = EventMetadata {
+        timestamp,
+        ..Default::default()
+    };
+    CubeArchive::new(filepath.clone()).append_events(&events, &metadata)?;
+    // Replaying the log into a fresh archive then starts from the template
+    // too, under other command ids.
+    if let Some(mut event_log) = open_event_log(&config.event_log) {
+        for event in &events {
+            event_log.append(&JournalEntry {
+                command_id: Uuid::new_v4(),
+                event: event.clone(),
+                metadata: metadata.clone(),
+            })?;
+        }
+    }
+    println!(
+        "Started {} with {} cubes of the {:?} template, seed {}",
+        filepath.display(),
+        events.len(),
+        template,
+        seed
+    );
+    Ok(())
+}
+
+// Journals the entries of the event log into the archive with their metadata,
+// paced by their timestamps at `pace`'s speed and longest wait if given.
+fn replay_event_log(
+    config: &TwixelBoxBotConfig,
+    path: Option<String>,
+    pace: Option<(f32, std::time::Duration)>,
+    new_ids: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if pace.is_some_and(|(speed, _)| speed <= 0.0) {
+        return Err("the speed must be positive".into());
+    }
+    let log_path = path
+        .or_else(|| config.event_log.filepath.clone())
+        .ok_or("no event log configured nor given")?;
+    let entries = read_event_log(&log_path)?;
+    let filepath = std::path::PathBuf::from(ARCHIVE_FILEPATH);
+    let mut archive = CubeArchive::new(filepath.clone());
+    let mut journaled = 0;
+    let mut previous: Option<&JournalEntry> = None;
+    for entry in &entries {
+        if let (Some((speed, max_wait)), Some(previous)) = (pace, previous) {
+            std::thread::sleep(replay_delay(previous, entry, speed, max_wait));
+        }
+        previous = Some(entry);
+        let command_id = match new_ids {
+            true => Uuid::new_v4(),
+            false => entry.command_id,
+        };
+        if archive.append_event(command_id, &entry.event, &entry.metadata)? {
+            journaled += 1;
         }
     }
+    println!(
+        "Journaled {} of the {} events of {} into {}",
+        journaled,
+        entries.len(),
+        log_path,
+        filepath.display()
+    );
+    Ok(())
 }
@@ -0,0 +1,170 @@
+use crate::CanvasEvent;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct MarkerConfig {
+    /// Creates stream markers on notable moments of the canvas. The account
+    /// of the bot has to be the broadcaster or one of their editors.
+    pub enabled: bool,
+    /// Twitch user id of the channel, that of the bot's account if unset.
+    pub broadcaster_id: Option<String>,
+    /// Cubes placed within `burst_secs` which make a marker, e.g. a large
+    /// import or a raid building together.
+    pub burst_cubes: usize,
+    pub burst_secs: u64,
+    /// Moments closer than this to the last marker are not marked.
+    pub cooldown_secs: u64,
+}
+
+impl Default for MarkerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            broadcaster_id: None,
+            burst_cubes: 200,
+            burst_secs: 60,
+            cooldown_secs: 30,
+        }
+    }
+}
+
+// Longest description Twitch keeps.
+const MAX_DESCRIPTION_LEN: usize = 140;
+
+/// Notable moment of the canvas, worth finding again in the VOD.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MarkerMoment {
+    /// That many cubes placed in a short time.
+    Burst(usize),
+    /// Cubes of the chatter taken off the canvas by a moderator.
+    Rollback(String),
+    CanvasCleared,
+    /// The event of that name started.
+    EventStarted(String),
+    /// Chat placed the cubes of the goal of the day.
+    GoalReached(u64),
+}
+
+impl MarkerMoment {
+    /// Description of the marker, cut to the length Twitch keeps.
+    pub fn description(&self) -> String {
+        let description = match self {
+            MarkerMoment::Burst(cubes) => format!("twixelbox: {} cubes placed", cubes),
+            MarkerMoment::Rollback(login) => format!("twixelbox: rolled back {}", login),
+            MarkerMoment::CanvasCleared => "twixelbox: canvas cleared".to_owned(),
+            MarkerMoment::EventStarted(name) => format!("twixelbox: event {} started", name),
+            MarkerMoment::GoalReached(cubes) => {
+                format!("twixelbox: goal of {} cubes reached", cubes)
+            }
+        };
+        description.chars().take(MAX_DESCRIPTION_LEN).collect()
+    }
+}
+
+/// Tells which moments of the canvas get a stream marker.
+#[derive(Clone, Debug)]
+pub struct StreamMarkers {
+    burst_cubes: usize,
+    burst_window: Duration,
+    cooldown: Duration,
+    // Placements of the current burst, oldest first.
+    placements: VecDeque<Instant>,
+    last_marker: Option<Instant>,
+}
+
+impl StreamMarkers {
+    pub fn new(config: &MarkerConfig) -> Self {
+        Self {
+            burst_cubes: config.burst_cubes.max(1),
+            burst_window: Duration::from_secs(config.burst_secs),
+            cooldown: Duration::from_secs(config.cooldown_secs),
+            placements: VecDeque::new(),
+            last_marker: None,
+        }
+    }
+
+    /// Notes an event applied to the canvas, returns the moment to mark if it
+    /// completes a burst or clears the canvas.
+    pub fn observe(&mut self, event: &CanvasEvent, now: Instant) -> Option<MarkerMoment> {
+        match event {
+            CanvasEvent::CubePlaced(_) => {
+                self.placements.push_back(now);
+                while let Some(first) = self.placements.front() {
+                    if now.saturating_duration_since(*first) <= self.burst_window {
+                        break;
+                    }
+                    self.placements.pop_front();
+                }
+                if self.placements.len() < self.burst_cubes {
+                    return None;
+                }
+                // The next burst starts afresh.
+                self.placements.clear();
+                self.mark(MarkerMoment::Burst(self.burst_cubes), now)
+            }
+            CanvasEvent::CanvasCleared => self.mark(MarkerMoment::CanvasCleared, now),
+            CanvasEvent::CubeRemoved(_) | CanvasEvent::Recoloured { .. } => None,
+        }
+    }
+
+    /// Returns `moment` unless the last marker is too recent.
+    pub fn mark(&mut self, moment: MarkerMoment, now: Instant) -> Option<MarkerMoment> {
+        if self
+            .last_marker
+            .is_some_and(|last| now.saturating_duration_since(last) < self.cooldown)
+        {
+            return None;
+        }
+        self.last_marker = Some(now);
+        Some(moment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colour, Cube, Position};
+
+    #[test]
+    fn test_stream_markers() {
+        let config = MarkerConfig {
+            burst_cubes: 3,
+            burst_secs: 10,
+            cooldown_secs: 30,
+            ..Default::default()
+        };
+        let mut markers = StreamMarkers::new(&config);
+        let placed = CanvasEvent::CubePlaced(Cube::new(1, 2, 3, Colour::new(0, 0, 0)));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // Too far apart for a burst.
+        assert_eq!(markers.observe(&placed, at(0)), None);
+        assert_eq!(markers.observe(&placed, at(20)), None);
+        assert_eq!(markers.observe(&placed, at(25)), None);
+        let removed = CanvasEvent::CubeRemoved(Position::new(1, 2, 3));
+        assert_eq!(markers.observe(&removed, at(26)), None);
+        assert_eq!(
+            markers.observe(&placed, at(27)),
+            Some(MarkerMoment::Burst(3))
+        );
+        // Within the cooldown.
+        assert_eq!(markers.observe(&CanvasEvent::CanvasCleared, at(40)), None);
+        let moment = MarkerMoment::EventStarted("build a tree".to_owned());
+        assert_eq!(markers.mark(moment.clone(), at(57)), Some(moment));
+
+        assert_eq!(
+            MarkerMoment::Rollback("griefer".to_owned()).description(),
+            "twixelbox: rolled back griefer"
+        );
+        assert_eq!(
+            MarkerMoment::GoalReached(5000).description(),
+            "twixelbox: goal of 5000 cubes reached"
+        );
+        let long = MarkerMoment::EventStarted("x".repeat(200)).description();
+        assert_eq!(long.chars().count(), MAX_DESCRIPTION_LEN);
+    }
+}
@@ -0,0 +1,316 @@
+use crate::transform::FIELD_OF_VIEW;
+use crate::{CameraPose, Canvas, Colour, Position};
+use image::{Rgb, RgbImage};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+/// Corner of the snapshot the minimap is drawn in.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MinimapCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+const BACKGROUND_COLOUR: Rgb<u8> = Rgb([40, 40, 40]);
+const BORDER_COLOUR: Rgb<u8> = Rgb([250, 250, 250]);
+const FRUSTUM_COLOUR: Rgb<u8> = Rgb([255, 210, 0]);
+const HEAT_COLOUR: [u8; 3] = [255, 40, 0];
+// Share of the heat colour in the hottest cells, so that what was built
+// there still shows through.
+const MAX_HEAT_OPACITY: f32 = 0.8;
+
+/// How much each column of the canvas was edited lately, each edit counting
+/// for less and less as time goes by.
+#[derive(Clone, Debug)]
+pub struct ActivityHeat {
+    side_len: u32,
+    half_life: Duration,
+    // Row by row, z growing downwards, as of when each was last edited.
+    cells: Vec<(f32, Instant)>,
+}
+
+impl ActivityHeat {
+    /// Heat of a canvas of side `side_len`, each edit counting half as much
+    /// every `half_life`.
+    pub fn new(side_len: u32, half_life: Duration, now: Instant) -> Self {
+        let side = side_len as usize;
+        Self {
+            side_len,
+            half_life,
+            cells: vec![(0.0, now); side * side],
+        }
+    }
+
+    /// Notes an edit at `position`.
+    pub fn observe(&mut self, position: Position, now: Instant) {
+        if position.x >= self.side_len || position.z >= self.side_len {
+            return;
+        }
+        let index = (position.z * self.side_len + position.x) as usize;
+        let heat = self.decayed(index, now);
+        self.cells[index] = (heat + 1.0, now);
+    }
+
+    /// Heat of each column at `now`, row by row, in edits.
+    pub fn heat(&self, now: Instant) -> Vec<f32> {
+        (0..self.cells.len())
+            .map(|index| self.decayed(index, now))
+            .collect()
+    }
+
+    fn decayed(&self, index: usize, now: Instant) -> f32 {
+        let (heat, at) = self.cells[index];
+        if self.half_life.is_zero() {
+            return 0.0;
+        }
+        let elapsed = now.saturating_duration_since(at).as_secs_f32();
+        heat * 0.5f32.powf(elapsed / self.half_life.as_secs_f32())
+    }
+}
+
+/// The canvas seen from above, each column of cubes as a cell of the average
+/// colour of its cubes. The front of the canvas, where the default camera
+/// stands, is at the bottom.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Minimap {
+    side_len: u32,
+    // Row by row, z growing downwards.
+    cells: Vec<Option<Colour>>,
+    // Share of the heat colour over each cell, in the same order.
+    heat: Option<Vec<f32>>,
+}
+
+impl Minimap {
+    pub fn new(canvas: &Canvas) -> Self {
+        let side = canvas.side_len() as usize;
+        let mut sums = vec![([0u32; 3], 0u32); side * side];
+        for cube in canvas.cubes() {
+            let (sum, count) =
+                &mut sums[cube.position.z as usize * side + cube.position.x as usize];
+            let Colour { r, g, b, .. } = cube.colour;
+            for (total, c) in sum.iter_mut().zip([r, g, b]) {
+                *total += c as u32;
+            }
+            *count += 1;
+        }
+        let cells = sums
+            .into_iter()
+            .map(|(sum, count)| {
+                let [r, g, b] = sum.map(|total| (total as f32 / count as f32).round() as u8);
+                (count > 0).then(|| Colour::new(r, g, b))
+            })
+            .collect();
+        Self {
+            side_len: canvas.side_len(),
+            cells,
+            heat: None,
+        }
+    }
+
+    /// Tints the cells in a hot colour, the more the hotter they are at
+    /// `now`, relative to the hottest one. A single fresh edit is hot enough
+    /// to be the hottest. Ignored when `heat` is of another canvas size.
+    pub fn with_heat(mut self, heat: &ActivityHeat, now: Instant) -> Self {
+        if heat.side_len != self.side_len {
+            return self;
+        }
+        let heat = heat.heat(now);
+        let max = heat.iter().copied().fold(1.0, f32::max);
+        self.heat = Some(heat.iter().map(|h| h / max).collect());
+        self
+    }
+
+    /// Draws the minimap in `corner` of the image, `share` of its height on
+    /// each side, with the edges of what `camera` sees and the columns out of
+    /// its sight darkened.
+    pub fn draw(&self, img: &mut RgbImage, corner: MinimapCorner, share: f32, camera: &CameraPose) {
+        let margin = (img.height() / 60).max(2);
+        let size = (img.height() as f32 * share.clamp(0.0, 1.0)) as u32;
+        let size = size.min(img.width().saturating_sub(margin * 2));
+        if size < 3 {
+            return;
+        }
+        let left = match corner {
+            MinimapCorner::TopLeft | MinimapCorner::BottomLeft => margin,
+            MinimapCorner::TopRight | MinimapCorner::BottomRight => img.width() - margin - size,
+        };
+        let top = match corner {
+            MinimapCorner::TopLeft | MinimapCorner::TopRight => margin,
+            MinimapCorner::BottomLeft | MinimapCorner::BottomRight => {
+                img.height().saturating_sub(margin + size)
+            }
+        };
+        let inner = size - 2;
+        let side = self.side_len.max(1);
+        let scale = inner as f32 / side as f32;
+        for y in 0..size {
+            for x in 0..size {
+                let colour = match (x, y) {
+                    (0, _) | (_, 0) => BORDER_COLOUR,
+                    _ if x == size - 1 || y == size - 1 => BORDER_COLOUR,
+                    _ => {
+                        let cell_x = (x - 1) * side / inner;
+                        let cell_z = (y - 1) * side / inner;
+                        let index = (cell_z * side + cell_x) as usize;
+                        let Rgb(mut colour) = match self.cells[index] {
+                            Some(Colour { r, g, b, .. }) => Rgb([r, g, b]),
+                            None => BACKGROUND_COLOUR,
+                        };
+                        if let Some(heat) = self.heat.as_ref() {
+                            let t = heat[index] * MAX_HEAT_OPACITY;
+                            colour = [0, 1, 2].map(|i| {
+                                (colour[i] as f32 * (1.0 - t) + HEAT_COLOUR[i] as f32 * t).round()
+                                    as u8
+                            });
+                        }
+                        let grid = |p: u32| (p as f32 - 0.5) / scale - 0.5;
+                        match in_sight(camera, grid(x), grid(y)) {
+                            true => Rgb(colour),
+                            false => Rgb(colour.map(|c| c / 2)),
+                        }
+                    }
+                };
+                if left + x < img.width() && top + y < img.height() {
+                    img.put_pixel(left + x, top + y, colour);
+                }
+            }
+        }
+        self.draw_frustum(img, (left + 1, top + 1, scale), camera);
+    }
+
+    // Both edges of the field of view, seen from above, clipped to the map.
+    // The camera usually stands off the canvas, only the edges reaching the
+    // map show.
+    fn draw_frustum(
+        &self,
+        img: &mut RgbImage,
+        (left, top, scale): (u32, u32, f32),
+        camera: &CameraPose,
+    ) {
+        let inner = scale * self.side_len.max(1) as f32;
+        // The grid coordinates of the cube at 0 span [-0.5, 0.5].
+        let to_map = |x: f32, z: f32| ((x + 0.5) * scale, (z + 0.5) * scale);
+        let forward = [
+            camera.target[0] - camera.position[0],
+            camera.target[2] - camera.position[2],
+        ];
+        let length = (forward[0] * forward[0] + forward[1] * forward[1]).sqrt();
+        if length < 1e-6 {
+            return;
+        }
+        let (start_x, start_y) = to_map(camera.position[0], camera.position[2]);
+        // Far enough to cross the whole map from wherever the camera is.
+        let reach = (start_x.abs() + start_y.abs() + inner * 2.0) / scale.max(1e-6);
+        for angle in [-FIELD_OF_VIEW / 2.0, FIELD_OF_VIEW / 2.0] {
+            let (sin, cos) = angle.sin_cos();
+            let dir = [
+                (forward[0] * cos - forward[1] * sin) / length,
+                (forward[0] * sin + forward[1] * cos) / length,
+            ];
+            let (end_x, end_y) = to_map(
+                camera.position[0] + dir[0] * reach,
+                camera.position[2] + dir[1] * reach,
+            );
+            let steps = (end_x - start_x).abs().max((end_y - start_y).abs()).ceil() as u32;
+            for step in 0..=steps {
+                let t = step as f32 / steps.max(1) as f32;
+                let x = start_x + (end_x - start_x) * t;
+                let y = start_y + (end_y - start_y) * t;
+                if x >= 0.0 && y >= 0.0 && x < inner && y < inner {
+                    img.put_pixel(left + x as u32, top + y as u32, FRUSTUM_COLOUR);
+                }
+            }
+        }
+    }
+}
+
+// Whether the point at `x` and `z` on the grid is within the horizontal field
+// of view of `camera`.
+fn in_sight(camera: &CameraPose, x: f32, z: f32) -> bool {
+    let forward = [
+        camera.target[0] - camera.position[0],
+        camera.target[2] - camera.position[2],
+    ];
+    let to_point = [x - camera.position[0], z - camera.position[2]];
+    let along = forward[0] * to_point[0] + forward[1] * to_point[1];
+    let across = (forward[0] * to_point[1] - forward[1] * to_point[0]).abs();
+    along > 0.0 && across <= along * (FIELD_OF_VIEW / 2.0).tan()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cube, SceneTransform};
+
+    #[test]
+    fn test_minimap() {
+        let mut canvas = Canvas::new(4);
+        canvas
+            .add_cube(Cube::new(0, 0, 0, Colour::new(255, 0, 0)))
+            .unwrap();
+        canvas
+            .add_cube(Cube::new(0, 3, 0, Colour::new(0, 0, 255)))
+            .unwrap();
+        canvas
+            .add_cube(Cube::new(3, 1, 3, Colour::new(0, 255, 0)))
+            .unwrap();
+        let minimap = Minimap::new(&canvas);
+        assert_eq!(minimap.cells[0], Some(Colour::new(128, 0, 128)));
+        assert_eq!(minimap.cells[15], Some(Colour::new(0, 255, 0)));
+        assert_eq!(minimap.cells[1], None);
+
+        let mut img = RgbImage::from_pixel(200, 100, Rgb([0, 0, 0]));
+        let camera = CameraPose {
+            position: [1.5, 0.0, 5.5],
+            target: [1.5, 0.0, 1.5],
+        };
+        minimap.draw(&mut img, MinimapCorner::BottomRight, 0.5, &camera);
+        // A 50 pixels map 2 pixels away from the bottom right corner, cells of
+        // 12 pixels inside its border.
+        assert_eq!(img.get_pixel(197, 97).0, BORDER_COLOUR.0);
+        assert_eq!(img.get_pixel(198, 98).0, [0, 0, 0]);
+        assert_eq!(img.get_pixel(148, 48).0, BORDER_COLOUR.0);
+        assert_eq!(img.get_pixel(149 + 2, 49 + 2).0, [128, 0, 128]);
+        assert_eq!(img.get_pixel(149 + 20, 49 + 2).0, BACKGROUND_COLOUR.0);
+        // The camera looks at the canvas from its front, the bottom of the
+        // map, too close to see its bottom corners.
+        assert_eq!(img.get_pixel(149 + 47, 49 + 47).0, [0, 127, 0]);
+        let bottom: Vec<u32> = (149..197)
+            .filter(|&x| img.get_pixel(x, 49 + 47).0 == FRUSTUM_COLOUR.0)
+            .collect();
+        assert!(!bottom.is_empty());
+        assert!(bottom.iter().any(|&x| x < 173) && bottom.iter().any(|&x| x > 173));
+
+        // The default camera sees the whole canvas.
+        let camera = SceneTransform::new(4).camera(0.0);
+        minimap.draw(&mut img, MinimapCorner::TopLeft, 0.5, &camera);
+        assert_eq!(img.get_pixel(3 + 47, 3 + 47).0, [0, 255, 0]);
+    }
+
+    #[test]
+    fn test_activity_heat() {
+        let start = Instant::now();
+        let mut activity = ActivityHeat::new(2, Duration::from_secs(10), start);
+        activity.observe(Position::new(1, 0, 0), start);
+        activity.observe(Position::new(1, 1, 0), start);
+        activity.observe(Position::new(0, 0, 1), start + Duration::from_secs(10));
+        activity.observe(Position::new(5, 0, 0), start);
+        let heat = activity.heat(start + Duration::from_secs(10));
+        assert_eq!(heat, vec![0.0, 1.0, 1.0, 0.0]);
+        let heat = activity.heat(start + Duration::from_secs(20));
+        assert_eq!(heat, vec![0.0, 0.5, 0.5, 0.0]);
+
+        // Relative to the two edits of the hottest column.
+        let minimap = Minimap::new(&Canvas::new(2)).with_heat(&activity, start);
+        assert_eq!(minimap.heat, Some(vec![0.0, 1.0, 0.5, 0.0]));
+        let mut img = RgbImage::new(100, 100);
+        let camera = SceneTransform::new(2).camera(0.0);
+        minimap.draw(&mut img, MinimapCorner::TopLeft, 0.5, &camera);
+        // Cells of 24 pixels from (3, 3), the hot one mostly red.
+        assert_eq!(img.get_pixel(3, 3).0, BACKGROUND_COLOUR.0);
+        assert_eq!(img.get_pixel(3 + 30, 3).0, [212, 40, 8]);
+    }
+}
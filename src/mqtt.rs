@@ -0,0 +1,176 @@
+use crate::{CanvasEvent, CanvasStats, Colour, Position};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct MqttConfig {
+    /// Broker as `host:port`, nothing is published when unset.
+    pub broker: Option<String>,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Topic of the events applied to the canvas.
+    pub events_topic: String,
+    /// Topic of the canvas statistics, retained so that displays get them as
+    /// soon as they subscribe.
+    pub stats_topic: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            broker: None,
+            client_id: "twixelbox-bot".to_owned(),
+            username: None,
+            password: None,
+            events_topic: "twixelbox/events".to_owned(),
+            stats_topic: "twixelbox/stats".to_owned(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MqttError {
+    #[error("MQTT is not available, rebuild with --features mqtt")]
+    Unavailable,
+    #[error("invalid MQTT broker {0}, expected host:port")]
+    Broker(String),
+}
+
+// Payload of the events, flat so that microcontrollers parse it easily.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum EventPayload {
+    Placed {
+        x: u32,
+        y: u32,
+        z: u32,
+        colour: String,
+    },
+    Removed {
+        x: u32,
+        y: u32,
+        z: u32,
+    },
+    Cleared,
+    Recoloured {
+        x: u32,
+        y: u32,
+        z: u32,
+        colour: String,
+    },
+}
+
+impl EventPayload {
+    fn new(event: &CanvasEvent) -> Self {
+        let coloured =
+            |Position { x, y, z }: Position, colour: Colour| (x, y, z, colour.to_string());
+        match *event {
+            CanvasEvent::CubePlaced(ref cube) => {
+                let (x, y, z, colour) = coloured(cube.position, cube.colour);
+                EventPayload::Placed { x, y, z, colour }
+            }
+            CanvasEvent::CubeRemoved(Position { x, y, z }) => EventPayload::Removed { x, y, z },
+            CanvasEvent::CanvasCleared => EventPayload::Cleared,
+            CanvasEvent::Recoloured { position, colour } => {
+                let (x, y, z, colour) = coloured(position, colour);
+                EventPayload::Recoloured { x, y, z, colour }
+            }
+        }
+    }
+}
+
+/// Publishes the canvas to an MQTT broker, for LED cubes and other displays
+/// to mirror it. Events are published as JSON such as
+/// `{"type":"placed","x":1,"y":2,"z":3,"colour":"#ff8800"}`, the statistics as
+/// served by the HTTP server. Publishing never blocks: messages are dropped
+/// while the broker is unreachable.
+#[derive(Clone)]
+pub struct MqttPublisher {
+    config: MqttConfig,
+    #[cfg(feature = "mqtt")]
+    client: rumqttc::AsyncClient,
+}
+
+impl MqttPublisher {
+    /// Connects to the configured broker in the background, must be called
+    /// from within the tokio runtime.
+    #[cfg(feature = "mqtt")]
+    pub fn connect(config: &MqttConfig) -> Result<Self, MqttError> {
+        let broker = config.broker.clone().unwrap_or_default();
+        let (host, port) = match broker.rsplit_once(':').map(|(h, p)| (h, p.parse::<u16>())) {
+            Some((host, Ok(port))) if !host.is_empty() => (host.to_owned(), port),
+            _ => return Err(MqttError::Broker(broker)),
+        };
+        let mut options = rumqttc::MqttOptions::new(&config.client_id, host, port);
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.as_deref().unwrap_or_default());
+        }
+        let (client, mut event_loop) = rumqttc::AsyncClient::new(options, 64);
+        tokio::spawn(async move {
+            loop {
+                // Polling again after an error reconnects.
+                if let Err(e) = event_loop.poll().await {
+                    eprintln!("Lost connection to the MQTT broker: {}", e);
+                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                }
+            }
+        });
+        Ok(Self {
+            config: config.clone(),
+            client,
+        })
+    }
+
+    #[cfg(not(feature = "mqtt"))]
+    pub fn connect(_config: &MqttConfig) -> Result<Self, MqttError> {
+        Err(MqttError::Unavailable)
+    }
+
+    pub fn publish_event(&self, event: &CanvasEvent) {
+        match serde_json::to_vec(&EventPayload::new(event)) {
+            Ok(json) => self.send(&self.config.events_topic, false, json),
+            Err(e) => eprintln!("Unable to serialize {:?}: {}", event, e),
+        }
+    }
+
+    pub fn publish_stats(&self, stats: &CanvasStats) {
+        match serde_json::to_vec(stats) {
+            Ok(json) => self.send(&self.config.stats_topic, true, json),
+            Err(e) => eprintln!("Unable to serialize the statistics: {}", e),
+        }
+    }
+
+    #[cfg(feature = "mqtt")]
+    fn send(&self, topic: &str, retain: bool, payload: Vec<u8>) {
+        let qos = rumqttc::QoS::AtLeastOnce;
+        if let Err(e) = self.client.try_publish(topic, qos, retain, payload) {
+            eprintln!("Unable to publish to {}: {}", topic, e);
+        }
+    }
+
+    #[cfg(not(feature = "mqtt"))]
+    fn send(&self, _topic: &str, _retain: bool, _payload: Vec<u8>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cube;
+
+    #[test]
+    fn test_event_payload() {
+        let cube = Cube::bounded(1, 2, 3, Colour::new(255, 136, 0), 8).unwrap();
+        let json = |event| serde_json::to_string(&EventPayload::new(&event)).unwrap();
+        assert_eq!(
+            json(CanvasEvent::CubePlaced(cube)),
+            r##"{"type":"placed","x":1,"y":2,"z":3,"colour":"#ff8800"}"##
+        );
+        assert_eq!(
+            json(CanvasEvent::CubeRemoved(Position::new(4, 5, 6))),
+            r#"{"type":"removed","x":4,"y":5,"z":6}"#
+        );
+        assert_eq!(json(CanvasEvent::CanvasCleared), r#"{"type":"cleared"}"#);
+    }
+}
@@ -0,0 +1,245 @@
+// Minimal reader and writer of Minecraft's NBT format, for schematics. See
+// https://minecraft.fandom.com/wiki/NBT_format
+use std::io::{self, Read, Write};
+
+// Deepest nesting of lists and compounds read, as in Minecraft.
+const MAX_DEPTH: usize = 512;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    // The elements all have the same type.
+    List(Vec<Tag>),
+    // Entries are written in order.
+    Compound(Vec<(String, Tag)>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    /// Entry `name` of a compound.
+    pub(crate) fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(entries) => entries.iter().find(|(n, _)| n == name).map(|(_, t)| t),
+            _ => None,
+        }
+    }
+
+    /// Value of integer tags of any size.
+    pub(crate) fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(value) => Some(value as i64),
+            Tag::Short(value) => Some(value as i64),
+            Tag::Int(value) => Some(value as i64),
+            Tag::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    // Payload of the tag, without its id nor name.
+    fn write_payload<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match self {
+            Tag::Byte(value) => out.write_all(&value.to_be_bytes()),
+            Tag::Short(value) => out.write_all(&value.to_be_bytes()),
+            Tag::Int(value) => out.write_all(&value.to_be_bytes()),
+            Tag::Long(value) => out.write_all(&value.to_be_bytes()),
+            Tag::Float(value) => out.write_all(&value.to_be_bytes()),
+            Tag::Double(value) => out.write_all(&value.to_be_bytes()),
+            Tag::ByteArray(bytes) => {
+                out.write_all(&(bytes.len() as i32).to_be_bytes())?;
+                out.write_all(bytes)
+            }
+            Tag::String(value) => write_string(out, value),
+            Tag::List(elements) => {
+                // Empty lists have the type of the end tag.
+                out.write_all(&[elements.first().map_or(0, Tag::id)])?;
+                out.write_all(&(elements.len() as i32).to_be_bytes())?;
+                elements.iter().try_for_each(|tag| tag.write_payload(out))
+            }
+            Tag::Compound(entries) => {
+                for (name, tag) in entries {
+                    write_named(out, name, tag)?;
+                }
+                // End tag.
+                out.write_all(&[0])
+            }
+            Tag::IntArray(values) => {
+                out.write_all(&(values.len() as i32).to_be_bytes())?;
+                values
+                    .iter()
+                    .try_for_each(|value| out.write_all(&value.to_be_bytes()))
+            }
+            Tag::LongArray(values) => {
+                out.write_all(&(values.len() as i32).to_be_bytes())?;
+                values
+                    .iter()
+                    .try_for_each(|value| out.write_all(&value.to_be_bytes()))
+            }
+        }
+    }
+
+    fn read_payload<R: Read>(input: &mut R, id: u8, depth: usize) -> io::Result<Tag> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("tags nested too deeply"));
+        }
+        Ok(match id {
+            1 => Tag::Byte(i8::from_be_bytes(read_array(input)?)),
+            2 => Tag::Short(i16::from_be_bytes(read_array(input)?)),
+            3 => Tag::Int(i32::from_be_bytes(read_array(input)?)),
+            4 => Tag::Long(i64::from_be_bytes(read_array(input)?)),
+            5 => Tag::Float(f32::from_be_bytes(read_array(input)?)),
+            6 => Tag::Double(f64::from_be_bytes(read_array(input)?)),
+            7 => {
+                let mut bytes = Vec::new();
+                let len = read_len(input)? as u64;
+                input.take(len).read_to_end(&mut bytes)?;
+                if bytes.len() as u64 != len {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                Tag::ByteArray(bytes)
+            }
+            8 => Tag::String(read_string(input)?),
+            9 => {
+                let element_id = read_array::<_, 1>(input)?[0];
+                let len = read_len(input)?;
+                let elements = (0..len)
+                    .map(|_| Tag::read_payload(input, element_id, depth + 1))
+                    .collect::<io::Result<_>>()?;
+                Tag::List(elements)
+            }
+            10 => {
+                let mut entries = Vec::new();
+                loop {
+                    let id = read_array::<_, 1>(input)?[0];
+                    if id == 0 {
+                        break;
+                    }
+                    let name = read_string(input)?;
+                    entries.push((name, Tag::read_payload(input, id, depth + 1)?));
+                }
+                Tag::Compound(entries)
+            }
+            11 => {
+                let len = read_len(input)?;
+                let values = (0..len)
+                    .map(|_| read_array(input).map(i32::from_be_bytes))
+                    .collect::<io::Result<_>>()?;
+                Tag::IntArray(values)
+            }
+            12 => {
+                let len = read_len(input)?;
+                let values = (0..len)
+                    .map(|_| read_array(input).map(i64::from_be_bytes))
+                    .collect::<io::Result<_>>()?;
+                Tag::LongArray(values)
+            }
+            id => return Err(invalid(&format!("unknown tag type {}", id))),
+        })
+    }
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+fn read_array<R: Read, const N: usize>(input: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_len<R: Read>(input: &mut R) -> io::Result<usize> {
+    let len = i32::from_be_bytes(read_array(input)?);
+    match len < 0 {
+        true => Err(invalid("negative length")),
+        false => Ok(len as usize),
+    }
+}
+
+fn read_string<R: Read>(input: &mut R) -> io::Result<String> {
+    let len = u16::from_be_bytes(read_array(input)?) as usize;
+    let mut bytes = vec![0; len];
+    input.read_exact(&mut bytes)?;
+    // Java's modified UTF-8 only differs for characters names don't use.
+    String::from_utf8(bytes).map_err(|_| invalid("invalid string"))
+}
+
+fn write_string<W: Write>(out: &mut W, value: &str) -> io::Result<()> {
+    out.write_all(&(value.len() as u16).to_be_bytes())?;
+    out.write_all(value.as_bytes())
+}
+
+/// Writes `tag` along with its id and name, as in compounds and at the root
+/// of files.
+pub(crate) fn write_named<W: Write>(out: &mut W, name: &str, tag: &Tag) -> io::Result<()> {
+    out.write_all(&[tag.id()])?;
+    write_string(out, name)?;
+    tag.write_payload(out)
+}
+
+/// Reads the root tag of a file, uncompressed, and its name.
+pub(crate) fn read_named<R: Read>(input: &mut R) -> io::Result<(String, Tag)> {
+    let id = read_array::<_, 1>(input)?[0];
+    let name = read_string(input)?;
+    Ok((name, Tag::read_payload(input, id, 0)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let tag = Tag::Compound(vec![
+            ("Byte".to_owned(), Tag::Byte(-1)),
+            ("Double".to_owned(), Tag::Double(0.5)),
+            (
+                "List".to_owned(),
+                Tag::List(vec![Tag::Short(1), Tag::Short(2)]),
+            ),
+            ("Empty".to_owned(), Tag::List(Vec::new())),
+            ("Longs".to_owned(), Tag::LongArray(vec![i64::MIN])),
+            (
+                "Nested".to_owned(),
+                Tag::Compound(vec![("Name".to_owned(), Tag::String("box".to_owned()))]),
+            ),
+        ]);
+        let mut bytes = Vec::new();
+        write_named(&mut bytes, "Root", &tag).unwrap();
+        assert_eq!(
+            read_named(&mut &bytes[..]).unwrap(),
+            ("Root".to_owned(), tag.clone())
+        );
+        assert_eq!(
+            tag.get("Nested").and_then(|t| t.get("Name")),
+            Some(&Tag::String("box".to_owned()))
+        );
+        assert_eq!(tag.get("Byte").and_then(Tag::as_i64), Some(-1));
+        // Truncated files are errors, not panics.
+        assert!(read_named(&mut &bytes[..bytes.len() - 3]).is_err());
+    }
+}
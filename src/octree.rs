@@ -0,0 +1,215 @@
+use crate::Position;
+use std::convert::TryInto;
+use thiserror::Error;
+
+// Node tags of the binary encoding.
+const EMPTY: u8 = 0;
+const FULL: u8 = 1;
+const SPLIT: u8 = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Empty,
+    Full,
+    // Children indexed by octant, bit 0 set for the upper half along x, bit
+    // 1 along y and bit 2 along z.
+    Split(Box<[Node; 8]>),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum OctreeError {
+    #[error("the octree data is truncated")]
+    Truncated,
+    #[error("invalid octree data at byte {0}")]
+    Invalid(usize),
+}
+
+/// Sparse octree of the occupied cells of the canvas, for tools which only
+/// need to know where the cubes are.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Octree {
+    // Power of two, at least the side of the canvas.
+    side_len: u32,
+    root: Node,
+}
+
+impl Octree {
+    pub fn new<I: IntoIterator<Item = Position>>(side_len: u32, positions: I) -> Self {
+        let side_len = side_len.max(1).next_power_of_two();
+        let positions: Vec<Position> = positions
+            .into_iter()
+            .filter(|position| position.is_within(side_len))
+            .collect();
+        Self {
+            side_len,
+            root: build(positions, Position::new(0, 0, 0), side_len),
+        }
+    }
+
+    pub fn side_len(&self) -> u32 {
+        self.side_len
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        if !position.is_within(self.side_len) {
+            return false;
+        }
+        let mut node = &self.root;
+        let mut origin = Position::new(0, 0, 0);
+        let mut size = self.side_len;
+        loop {
+            match node {
+                Node::Empty => return false,
+                Node::Full => return true,
+                Node::Split(children) => {
+                    size /= 2;
+                    let octant = octant(position, origin, size);
+                    origin = child_origin(origin, size, octant);
+                    node = &children[octant];
+                }
+            }
+        }
+    }
+
+    /// Encodes the octree as the side length, 4 bytes in little endian,
+    /// followed by its nodes in depth first order, one byte each: 0 for an
+    /// empty node, 1 for a full one, or 2 for a node split in 8 children,
+    /// which follow it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.side_len.to_le_bytes().to_vec();
+        encode(&self.root, &mut bytes);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, OctreeError> {
+        if bytes.len() < 4 {
+            return Err(OctreeError::Truncated);
+        }
+        let side_len = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        if !side_len.is_power_of_two() {
+            return Err(OctreeError::Invalid(0));
+        }
+        let mut offset = 4;
+        let root = decode(bytes, &mut offset, side_len)?;
+        if offset != bytes.len() {
+            return Err(OctreeError::Invalid(offset));
+        }
+        Ok(Self { side_len, root })
+    }
+}
+
+fn octant(position: Position, origin: Position, half: u32) -> usize {
+    (position.x >= origin.x + half) as usize
+        | ((position.y >= origin.y + half) as usize) << 1
+        | ((position.z >= origin.z + half) as usize) << 2
+}
+
+fn child_origin(origin: Position, half: u32, octant: usize) -> Position {
+    let offset = |bit: usize| if octant & bit != 0 { half } else { 0 };
+    Position::new(
+        origin.x + offset(1),
+        origin.y + offset(2),
+        origin.z + offset(4),
+    )
+}
+
+fn build(positions: Vec<Position>, origin: Position, size: u32) -> Node {
+    if positions.is_empty() {
+        return Node::Empty;
+    }
+    if size == 1 {
+        return Node::Full;
+    }
+    let half = size / 2;
+    let mut octants: [Vec<Position>; 8] = Default::default();
+    for position in positions {
+        octants[octant(position, origin, half)].push(position);
+    }
+    let children: Vec<Node> = octants
+        .iter_mut()
+        .enumerate()
+        .map(|(i, positions)| {
+            build(
+                std::mem::take(positions),
+                child_origin(origin, half, i),
+                half,
+            )
+        })
+        .collect();
+    if children.iter().all(|child| *child == Node::Full) {
+        return Node::Full;
+    }
+    let children: [Node; 8] = children.try_into().expect("8 octants");
+    Node::Split(Box::new(children))
+}
+
+fn encode(node: &Node, bytes: &mut Vec<u8>) {
+    match node {
+        Node::Empty => bytes.push(EMPTY),
+        Node::Full => bytes.push(FULL),
+        Node::Split(children) => {
+            bytes.push(SPLIT);
+            for child in children.iter() {
+                encode(child, bytes);
+            }
+        }
+    }
+}
+
+fn decode(bytes: &[u8], offset: &mut usize, size: u32) -> Result<Node, OctreeError> {
+    let tag = *bytes.get(*offset).ok_or(OctreeError::Truncated)?;
+    *offset += 1;
+    match tag {
+        EMPTY => Ok(Node::Empty),
+        FULL => Ok(Node::Full),
+        // Cells can't be split further.
+        SPLIT if size > 1 => {
+            let mut children = Vec::with_capacity(8);
+            for _ in 0..8 {
+                children.push(decode(bytes, offset, size / 2)?);
+            }
+            let children: [Node; 8] = children.try_into().expect("8 octants");
+            Ok(Node::Split(Box::new(children)))
+        }
+        _ => Err(OctreeError::Invalid(*offset - 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_octree() {
+        let positions = vec![
+            Position::new(0, 0, 0),
+            Position::new(2, 1, 0),
+            Position::new(2, 2, 2),
+        ];
+        let octree = Octree::new(3, positions.clone());
+        assert_eq!(octree.side_len(), 4);
+        for x in 0..4 {
+            for y in 0..4 {
+                for z in 0..4 {
+                    let position = Position::new(x, y, z);
+                    assert_eq!(octree.contains(position), positions.contains(&position));
+                }
+            }
+        }
+        assert!(!octree.contains(Position::new(10, 0, 0)));
+        let bytes = octree.to_bytes();
+        assert_eq!(Octree::from_bytes(&bytes), Ok(octree));
+        assert_eq!(
+            Octree::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(OctreeError::Truncated)
+        );
+
+        // Full regions collapse into a single node.
+        let full = Octree::new(
+            2,
+            (0..8).map(|i| Position::new(i & 1, (i >> 1) & 1, (i >> 2) & 1)),
+        );
+        assert_eq!(full.to_bytes(), vec![2, 0, 0, 0, FULL]);
+        assert_eq!(Octree::new(2, vec![]).to_bytes(), vec![2, 0, 0, 0, EMPTY]);
+    }
+}
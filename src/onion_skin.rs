@@ -0,0 +1,138 @@
+use crate::{Canvas, CanvasEvent, Colour, Cube, Position};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct OnionSkinConfig {
+    /// Whether the overlay shows the cubes removed or overwritten lately.
+    pub enabled: bool,
+    /// Seconds a ghost takes to fade out.
+    pub seconds: f32,
+    /// Opacity of a ghost when its cube just went, between 0 and 1.
+    pub opacity: f32,
+}
+
+impl Default for OnionSkinConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            seconds: 10.0,
+            opacity: 0.4,
+        }
+    }
+}
+
+/// Ghosts of the cubes removed or overwritten lately, fading out, so that
+/// destructive edits can be seen as they happen.
+pub struct OnionSkin {
+    lifetime: Duration,
+    opacity: f32,
+    // Colour the position had, and when it lost it.
+    ghosts: HashMap<Position, (Colour, Instant)>,
+}
+
+impl OnionSkin {
+    pub fn new(config: &OnionSkinConfig) -> Self {
+        Self {
+            lifetime: Duration::from_secs_f32(config.seconds.max(0.0)),
+            opacity: config.opacity.clamp(0.0, 1.0),
+            ghosts: HashMap::new(),
+        }
+    }
+
+    /// Keeps a ghost of what `event` removes or overwrites, to be called
+    /// before it's applied to `canvas`.
+    pub fn observe(&mut self, event: &CanvasEvent, canvas: &Canvas, now: Instant) {
+        let (position, colour) = match event {
+            CanvasEvent::CubePlaced(cube) => (cube.position, Some(cube.colour)),
+            CanvasEvent::Recoloured { position, colour } => (*position, Some(*colour)),
+            CanvasEvent::CubeRemoved(position) => (*position, None),
+            CanvasEvent::CanvasCleared => {
+                for cube in canvas.cubes() {
+                    self.ghosts.insert(cube.position, (cube.colour, now));
+                }
+                return;
+            }
+        };
+        match canvas.get(position) {
+            Some(previous) if Some(previous.colour) != colour => {
+                self.ghosts.insert(position, (previous.colour, now));
+            }
+            _ => {}
+        }
+    }
+
+    /// The ghosts still showing at `now`, with their opacity.
+    pub fn ghosts(&mut self, now: Instant) -> Vec<(Cube, f32)> {
+        let lifetime = self.lifetime;
+        self.ghosts
+            .retain(|_, (_, since)| now.duration_since(*since) < lifetime);
+        self.ghosts
+            .iter()
+            .map(|(&position, &(colour, since))| {
+                let age = now.duration_since(since).as_secs_f32() / lifetime.as_secs_f32();
+                (Cube { position, colour }, self.opacity * (1.0 - age))
+            })
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.ghosts.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ghosts() {
+        let mut onion_skin = OnionSkin::new(&OnionSkinConfig {
+            enabled: true,
+            seconds: 10.0,
+            opacity: 0.5,
+        });
+        let mut canvas = Canvas::new(4);
+        let red = Cube::new(1, 1, 1, Colour::new(255, 0, 0));
+        let start = Instant::now();
+        let events = [
+            CanvasEvent::CubePlaced(red.clone()),
+            // Placing the same colour again changes nothing.
+            CanvasEvent::CubePlaced(red.clone()),
+            CanvasEvent::Recoloured {
+                position: red.position,
+                colour: Colour::new(0, 0, 255),
+            },
+        ];
+        for event in &events {
+            onion_skin.observe(event, &canvas, start);
+            canvas.apply(event).unwrap();
+        }
+        assert_eq!(onion_skin.ghosts(start), vec![(red.clone(), 0.5)]);
+
+        let later = start + Duration::from_secs(5);
+        let removed = CanvasEvent::CubeRemoved(red.position);
+        onion_skin.observe(&removed, &canvas, later);
+        canvas.apply(&removed).unwrap();
+        let ghosts = onion_skin.ghosts(later);
+        assert_eq!(ghosts.len(), 1);
+        assert_eq!(ghosts[0].0.colour, Colour::new(0, 0, 255));
+        assert_eq!(ghosts[0].1, 0.5);
+
+        let opacity = onion_skin.ghosts(later + Duration::from_secs(5))[0].1;
+        assert!((opacity - 0.25).abs() < 1e-6);
+        assert!(onion_skin
+            .ghosts(later + Duration::from_secs(10))
+            .is_empty());
+
+        // Clearing the canvas leaves a ghost of each cube.
+        let green = Cube::new(2, 0, 3, Colour::new(0, 255, 0));
+        canvas
+            .apply(&CanvasEvent::CubePlaced(green.clone()))
+            .unwrap();
+        onion_skin.observe(&CanvasEvent::CanvasCleared, &canvas, later);
+        assert_eq!(onion_skin.ghosts(later), vec![(green, 0.5)]);
+    }
+}
@@ -0,0 +1,294 @@
+use serde::Deserialize;
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+// Twitch counts the messages of a user over 30 seconds.
+const RATE_WINDOW: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct OutboxConfig {
+    /// Messages the bot sends at most every 30 seconds. Twitch allows 20, or
+    /// 100 in the channels the bot moderates.
+    pub messages_per_30s: usize,
+    /// Whether the bot moderates the channel, so slow mode doesn't apply.
+    pub moderator: bool,
+    /// Once this many confirmations are waiting, they're said as a single
+    /// summary. 0 never summarises them.
+    pub coalesce_after: usize,
+    /// Whether to confirm each cube placed from chat.
+    pub confirm_placements: bool,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            messages_per_30s: 20,
+            moderator: false,
+            coalesce_after: 3,
+            confirm_placements: false,
+        }
+    }
+}
+
+/// What the bot has to say in chat.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChatMessage {
+    Announcement(String),
+    /// Feedback attached to the chat message of Twitch id `message_id`.
+    Reply {
+        message_id: String,
+        text: String,
+    },
+    /// Tells `login` their `cubes` were placed, in reply to their message if
+    /// any, summarised with the others when chat is busy.
+    Confirmation {
+        login: String,
+        cubes: usize,
+        text: String,
+        reply_to: Option<String>,
+    },
+    /// The channel's slow mode changed, `None` when it's off.
+    SlowMode(Option<Duration>),
+}
+
+impl From<String> for ChatMessage {
+    fn from(text: String) -> Self {
+        ChatMessage::Announcement(text)
+    }
+}
+
+/// Message the outbox lets through.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outgoing {
+    Say(String),
+    Reply {
+        message_id: String,
+        text: String,
+    },
+    /// Confirmations of `cubes` placed by `users` builders over `secs`.
+    Summary {
+        cubes: usize,
+        users: usize,
+        secs: u64,
+    },
+}
+
+/// Queue of the messages to say in chat, let through no faster than Twitch
+/// allows: within the rate limit, and slow mode unless the bot moderates the
+/// channel. Confirmations piling up meanwhile are summarised.
+pub struct Outbox {
+    config: OutboxConfig,
+    queue: VecDeque<(Instant, ChatMessage)>,
+    // When the messages of the last 30 seconds were sent.
+    sent: VecDeque<Instant>,
+    slow_mode: Option<Duration>,
+}
+
+impl Outbox {
+    pub fn new(config: &OutboxConfig) -> Self {
+        Self {
+            config: config.clone(),
+            queue: VecDeque::new(),
+            sent: VecDeque::new(),
+            slow_mode: None,
+        }
+    }
+
+    pub fn push(&mut self, message: ChatMessage, now: Instant) {
+        match message {
+            ChatMessage::SlowMode(slow_mode) => self.slow_mode = slow_mode,
+            message => self.queue.push_back((now, message)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// When the next message can be sent, `None` when there's none.
+    pub fn next_send(&self) -> Option<Instant> {
+        let (queued, _) = self.queue.front()?;
+        let mut at = *queued;
+        let limit = self.config.messages_per_30s.max(1);
+        if self.sent.len() >= limit {
+            at = at.max(self.sent[self.sent.len() - limit] + RATE_WINDOW);
+        }
+        if let (Some(slow_mode), false) = (self.slow_mode, self.config.moderator) {
+            if let Some(last) = self.sent.back() {
+                at = at.max(*last + slow_mode);
+            }
+        }
+        Some(at)
+    }
+
+    /// Next message to say, if it can be sent at `now`.
+    pub fn pop(&mut self, now: Instant) -> Option<Outgoing> {
+        if self.next_send()? > now {
+            return None;
+        }
+        while self
+            .sent
+            .front()
+            .is_some_and(|sent| *sent + RATE_WINDOW <= now)
+        {
+            self.sent.pop_front();
+        }
+        self.sent.push_back(now);
+
+        let confirmations = self
+            .queue
+            .iter()
+            .filter(|(_, message)| matches!(message, ChatMessage::Confirmation { .. }))
+            .count();
+        let (since, message) = self.queue.pop_front()?;
+        match message {
+            ChatMessage::Confirmation { login, cubes, .. }
+                if self.config.coalesce_after > 0
+                    && confirmations >= self.config.coalesce_after =>
+            {
+                let mut users: HashSet<String> = HashSet::new();
+                users.insert(login);
+                let mut total = cubes;
+                self.queue.retain(|(_, message)| match message {
+                    ChatMessage::Confirmation { login, cubes, .. } => {
+                        users.insert(login.clone());
+                        total += cubes;
+                        false
+                    }
+                    _ => true,
+                });
+                Some(Outgoing::Summary {
+                    cubes: total,
+                    users: users.len(),
+                    secs: now.duration_since(since).as_secs().max(1),
+                })
+            }
+            ChatMessage::Confirmation {
+                text,
+                reply_to: Some(message_id),
+                ..
+            }
+            | ChatMessage::Reply { message_id, text } => Some(Outgoing::Reply { message_id, text }),
+            ChatMessage::Confirmation { text, .. } | ChatMessage::Announcement(text) => {
+                Some(Outgoing::Say(text))
+            }
+            ChatMessage::SlowMode(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn confirmation(login: &str) -> ChatMessage {
+        ChatMessage::Confirmation {
+            login: login.to_owned(),
+            cubes: 1,
+            text: format!("@{} placed a cube", login),
+            reply_to: None,
+        }
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let config = OutboxConfig {
+            messages_per_30s: 2,
+            ..Default::default()
+        };
+        let mut outbox = Outbox::new(&config);
+        let start = Instant::now();
+        assert_eq!(outbox.next_send(), None);
+        for i in 0..3 {
+            outbox.push(format!("{}", i).into(), start);
+        }
+        assert_eq!(outbox.pop(start), Some(Outgoing::Say("0".to_owned())));
+        assert_eq!(outbox.pop(start), Some(Outgoing::Say("1".to_owned())));
+        assert_eq!(outbox.pop(start), None);
+        assert_eq!(outbox.next_send(), Some(start + RATE_WINDOW));
+        assert_eq!(
+            outbox.pop(start + RATE_WINDOW),
+            Some(Outgoing::Say("2".to_owned()))
+        );
+        assert!(outbox.is_empty());
+
+        // Slow mode spaces the messages out, unless the bot moderates.
+        let mut outbox = Outbox::new(&OutboxConfig::default());
+        outbox.push(ChatMessage::SlowMode(Some(Duration::from_secs(10))), start);
+        outbox.push("a".to_owned().into(), start);
+        outbox.push("b".to_owned().into(), start);
+        assert_eq!(outbox.pop(start), Some(Outgoing::Say("a".to_owned())));
+        assert_eq!(outbox.next_send(), Some(start + Duration::from_secs(10)));
+        outbox.push(ChatMessage::SlowMode(None), start);
+        assert_eq!(outbox.next_send(), Some(start));
+
+        let mut outbox = Outbox::new(&OutboxConfig {
+            moderator: true,
+            ..Default::default()
+        });
+        outbox.push(ChatMessage::SlowMode(Some(Duration::from_secs(10))), start);
+        outbox.push("a".to_owned().into(), start);
+        outbox.push("b".to_owned().into(), start);
+        assert!(outbox.pop(start).is_some());
+        assert_eq!(outbox.next_send(), Some(start));
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let config = OutboxConfig {
+            messages_per_30s: 1,
+            coalesce_after: 3,
+            ..Default::default()
+        };
+        let mut outbox = Outbox::new(&config);
+        let start = Instant::now();
+        outbox.push(confirmation("alice"), start);
+        assert_eq!(
+            outbox.pop(start),
+            Some(Outgoing::Say("@alice placed a cube".to_owned()))
+        );
+
+        // Chat keeps placing while the bot has to wait.
+        outbox.push(confirmation("bob"), start);
+        outbox.push("The vote is over".to_owned().into(), start);
+        outbox.push(confirmation("carol"), start + Duration::from_secs(5));
+        assert_eq!(outbox.len(), 3);
+        outbox.push(confirmation("bob"), start + Duration::from_secs(8));
+        let later = start + RATE_WINDOW;
+        assert_eq!(
+            outbox.pop(later),
+            Some(Outgoing::Summary {
+                cubes: 3,
+                users: 2,
+                secs: 30
+            })
+        );
+        // Announcements are never summarised.
+        assert_eq!(
+            outbox.pop(later + RATE_WINDOW),
+            Some(Outgoing::Say("The vote is over".to_owned()))
+        );
+        assert!(outbox.is_empty());
+
+        // Alone, a confirmation replies to the message of the placement.
+        let reply = ChatMessage::Confirmation {
+            login: "dave".to_owned(),
+            cubes: 1,
+            text: "@dave placed a cube".to_owned(),
+            reply_to: Some("abc".to_owned()),
+        };
+        outbox.push(reply, later);
+        assert_eq!(
+            outbox.pop(later + RATE_WINDOW * 2),
+            Some(Outgoing::Reply {
+                message_id: "abc".to_owned(),
+                text: "@dave placed a cube".to_owned()
+            })
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use crate::Colour;
+use image::imageops::FilterType;
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PaletteError {
+    #[error("unknown quantization {0}, try none, palette, kmeans or median-cut")]
+    UnknownQuantization(String),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct PaletteConfig {
+    /// Moderators upload images to `/api/palette` with this token, uploads
+    /// are disabled when unset.
+    pub upload_token: Option<String>,
+    /// Colours extracted from an image, or kept when quantizing imports,
+    /// unless the upload asks otherwise.
+    pub colours: usize,
+}
+
+impl Default for PaletteConfig {
+    fn default() -> Self {
+        Self {
+            upload_token: None,
+            colours: 8,
+        }
+    }
+}
+
+// Most colours which can be extracted from an image.
+const MAX_PALETTE_COLOURS: usize = 32;
+// Images are shrunk to at most this side before clustering their pixels.
+const SAMPLE_SIDE: u32 = 64;
+const MAX_ITERATIONS: usize = 20;
+
+/// How the colours of imported content are reduced to a few, to match the
+/// restricted palettes used in chat.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Quantization {
+    /// Colours are kept as is.
+    None,
+    /// Colours take the closest colour of the palette in use, if any.
+    Palette,
+    /// Colours take the closest of their dominant colours, found with
+    /// k-means.
+    KMeans,
+    /// Colours take the closest of their median cut.
+    MedianCut,
+}
+
+impl Quantization {
+    /// Palette `colours` are reduced to, with `k` colours at most. `None`
+    /// when they are kept, or left to the palette in use.
+    pub fn reduce(self, colours: &[Colour], k: usize, seed: u64) -> Option<Palette> {
+        match self {
+            Quantization::None | Quantization::Palette => None,
+            Quantization::KMeans => Palette::cluster_colours(colours, k, seed),
+            Quantization::MedianCut => Palette::median_cut(colours, k),
+        }
+    }
+}
+
+impl FromStr for Quantization {
+    type Err = PaletteError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "none" => Ok(Quantization::None),
+            "palette" => Ok(Quantization::Palette),
+            "kmeans" | "k-means" => Ok(Quantization::KMeans),
+            "median-cut" | "mediancut" => Ok(Quantization::MedianCut),
+            _ => Err(PaletteError::UnknownQuantization(value.to_owned())),
+        }
+    }
+}
+
+/// Restricted set of colours chat can build with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    colours: Vec<Colour>,
+}
+
+impl Palette {
+    /// `None` without colours.
+    pub fn new(colours: Vec<Colour>) -> Option<Self> {
+        match colours.is_empty() {
+            true => None,
+            false => Some(Self { colours }),
+        }
+    }
+
+    /// Extracts the `k` dominant colours of `img` with k-means, the most
+    /// common first. Images with fewer distinct colours give fewer.
+    pub fn extract(img: &RgbImage, k: usize, seed: u64) -> Option<Self> {
+        let (width, height) = img.dimensions();
+        let scale = (SAMPLE_SIDE as f32 / width.max(height).max(1) as f32).min(1.0);
+        let sample = image::imageops::resize(
+            img,
+            ((width as f32 * scale) as u32).max(1),
+            ((height as f32 * scale) as u32).max(1),
+            FilterType::Nearest,
+        );
+        let pixels: Vec<[f32; 3]> = sample
+            .pixels()
+            .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+            .collect();
+        Self::cluster(&pixels, k, seed)
+    }
+
+    /// Same as `extract`, for colours of voxels rather than pixels.
+    pub fn cluster_colours(colours: &[Colour], k: usize, seed: u64) -> Option<Self> {
+        // Evenly spread samples, as for images.
+        let step = (colours.len() / (SAMPLE_SIDE * SAMPLE_SIDE) as usize).max(1);
+        let pixels: Vec<[f32; 3]> = colours
+            .iter()
+            .step_by(step)
+            .map(|c| [c.r as f32, c.g as f32, c.b as f32])
+            .collect();
+        Self::cluster(&pixels, k, seed)
+    }
+
+    fn cluster(pixels: &[[f32; 3]], k: usize, seed: u64) -> Option<Self> {
+        if pixels.is_empty() {
+            return None;
+        }
+        let k = k.clamp(1, MAX_PALETTE_COLOURS);
+        let mut centroids = initial_centroids(pixels, k, &fastrand::Rng::with_seed(seed));
+        let mut assignments = vec![0; pixels.len()];
+        for _ in 0..MAX_ITERATIONS {
+            let mut changed = false;
+            for (pixel, assignment) in pixels.iter().zip(assignments.iter_mut()) {
+                let nearest = nearest(&centroids, pixel);
+                changed |= nearest != *assignment;
+                *assignment = nearest;
+            }
+            let mut sums = vec![([0.0; 3], 0usize); centroids.len()];
+            for (pixel, &assignment) in pixels.iter().zip(&assignments) {
+                let (sum, count) = &mut sums[assignment];
+                for c in 0..3 {
+                    sum[c] += pixel[c];
+                }
+                *count += 1;
+            }
+            for (centroid, (sum, count)) in centroids.iter_mut().zip(&sums) {
+                if *count > 0 {
+                    *centroid = [0, 1, 2].map(|c| sum[c] / *count as f32);
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        let mut clusters: Vec<(usize, Colour)> = centroids
+            .iter()
+            .enumerate()
+            .map(|(i, centroid)| {
+                let size = assignments.iter().filter(|&&a| a == i).count();
+                let [r, g, b] = centroid.map(|c| c.round().clamp(0.0, 255.0) as u8);
+                (size, Colour::new(r, g, b))
+            })
+            .filter(|(size, _)| *size > 0)
+            .collect();
+        clusters.sort_by_key(|(size, _)| std::cmp::Reverse(*size));
+        let mut colours: Vec<Colour> = Vec::new();
+        for (_, colour) in clusters {
+            if !colours.contains(&colour) {
+                colours.push(colour);
+            }
+        }
+        Self::new(colours)
+    }
+
+    /// Up to `k` colours splitting `colours` with median cut: the colours
+    /// are split in two at the median of their widest channel, and so on for
+    /// the widest split, each giving their average. The largest splits come
+    /// first.
+    pub fn median_cut(colours: &[Colour], k: usize) -> Option<Self> {
+        let k = k.clamp(1, MAX_PALETTE_COLOURS);
+        let mut boxes: Vec<Vec<[u8; 3]>> = vec![colours.iter().map(|c| [c.r, c.g, c.b]).collect()];
+        while boxes.len() < k {
+            let range = |colours: &[[u8; 3]], c: usize| {
+                let values = colours.iter().map(|colour| colour[c]);
+                values.clone().max().unwrap_or(0) - values.min().unwrap_or(0)
+            };
+            let (index, channel, widest) = boxes
+                .iter()
+                .enumerate()
+                .flat_map(|(i, colours)| (0..3).map(move |c| (i, c, range(colours, c))))
+                .max_by_key(|(_, _, range)| *range)
+                .unwrap();
+            if widest == 0 {
+                // Fewer distinct colours than requested.
+                break;
+            }
+            let mut lower = boxes.swap_remove(index);
+            lower.sort_unstable_by_key(|colour| colour[channel]);
+            let upper = lower.split_off(lower.len() / 2);
+            boxes.push(lower);
+            boxes.push(upper);
+        }
+        boxes.sort_by_key(|colours| std::cmp::Reverse(colours.len()));
+        let mut palette: Vec<Colour> = Vec::new();
+        for colours in boxes.iter().filter(|colours| !colours.is_empty()) {
+            let average = |c: usize| {
+                let sum: usize = colours.iter().map(|colour| colour[c] as usize).sum();
+                ((sum + colours.len() / 2) / colours.len()) as u8
+            };
+            let colour = Colour::new(average(0), average(1), average(2));
+            if !palette.contains(&colour) {
+                palette.push(colour);
+            }
+        }
+        Self::new(palette)
+    }
+
+    pub fn colours(&self) -> &[Colour] {
+        &self.colours
+    }
+
+    /// Colour of the palette closest to `colour`.
+    pub fn nearest(&self, colour: Colour) -> Colour {
+        let target = [colour.r as f32, colour.g as f32, colour.b as f32];
+        let centroids: Vec<[f32; 3]> = self
+            .colours
+            .iter()
+            .map(|c| [c.r as f32, c.g as f32, c.b as f32])
+            .collect();
+        self.colours[nearest(&centroids, &target)]
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let colours: Vec<String> = self.colours.iter().map(Colour::to_string).collect();
+        write!(f, "{}", colours.join(" "))
+    }
+}
+
+fn distance_squared(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    (0..3).map(|c| (a[c] - b[c]) * (a[c] - b[c])).sum()
+}
+
+fn nearest(centroids: &[[f32; 3]], pixel: &[f32; 3]) -> usize {
+    centroids
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| {
+            distance_squared(a, pixel)
+                .partial_cmp(&distance_squared(b, pixel))
+                .unwrap()
+        })
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+// k-means++: each new centroid is picked with a probability growing with its
+// squared distance to the closest centroid picked so far.
+fn initial_centroids(pixels: &[[f32; 3]], k: usize, rng: &fastrand::Rng) -> Vec<[f32; 3]> {
+    let mut centroids = vec![pixels[rng.usize(..pixels.len())]];
+    while centroids.len() < k {
+        let distances: Vec<f32> = pixels
+            .iter()
+            .map(|pixel| distance_squared(&centroids[nearest(&centroids, pixel)], pixel))
+            .collect();
+        let total: f32 = distances.iter().sum();
+        if total == 0.0 {
+            // Fewer distinct colours than requested.
+            break;
+        }
+        let mut target = rng.f32() * total;
+        let mut picked = pixels.len() - 1;
+        for (i, distance) in distances.iter().enumerate() {
+            if target < *distance {
+                picked = i;
+                break;
+            }
+            target -= distance;
+        }
+        centroids.push(pixels[picked]);
+    }
+    centroids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn test_extract() {
+        // Three quarters red, one quarter blue with a little noise.
+        let img = RgbImage::from_fn(100, 100, |x, y| match x < 75 {
+            true => Rgb([250 + (y % 2) as u8, 0, 0]),
+            false => Rgb([0, 0, 200]),
+        });
+        let palette = Palette::extract(&img, 2, 42).unwrap();
+        assert_eq!(palette.colours().len(), 2);
+        assert_eq!(palette.colours()[1], Colour::new(0, 0, 200));
+        assert!(palette.colours()[0].r >= 250 && palette.colours()[0].b == 0);
+        assert_eq!(
+            palette.nearest(Colour::new(20, 0, 255)),
+            Colour::new(0, 0, 200)
+        );
+
+        // A single colour can't make more than one cluster.
+        let plain = RgbImage::from_pixel(10, 10, Rgb([1, 2, 3]));
+        let palette = Palette::extract(&plain, 8, 42).unwrap();
+        assert_eq!(palette.colours(), &[Colour::new(1, 2, 3)]);
+        assert_eq!(palette.to_string(), "#010203");
+        assert!(Palette::new(vec![]).is_none());
+    }
+
+    #[test]
+    fn test_quantize() {
+        // Two shades of red, and blue.
+        let mut colours = vec![Colour::new(250, 0, 0); 20];
+        colours.extend(vec![Colour::new(240, 10, 0); 10]);
+        colours.extend(vec![Colour::new(0, 0, 200); 30]);
+        let blue = Colour::new(0, 0, 200);
+        for quantization in &[Quantization::KMeans, Quantization::MedianCut] {
+            let palette = quantization.reduce(&colours, 2, 42).unwrap();
+            assert_eq!(palette.colours().len(), 2, "{:?}", quantization);
+            assert!(palette.colours().contains(&blue), "{:?}", quantization);
+            assert_eq!(palette.nearest(Colour::new(20, 0, 255)), blue);
+        }
+        let palette = Palette::median_cut(&colours, 2).unwrap();
+        assert!(palette.colours().contains(&Colour::new(247, 3, 0)));
+        let palette = Palette::median_cut(&colours, 8).unwrap();
+        assert_eq!(palette.colours().len(), 3);
+        assert!(Quantization::MedianCut.reduce(&[], 2, 42).is_none());
+        assert!(Quantization::Palette.reduce(&colours, 2, 42).is_none());
+        assert_eq!(
+            "median-cut".parse::<Quantization>().unwrap(),
+            Quantization::MedianCut
+        );
+        assert!("octree".parse::<Quantization>().is_err());
+    }
+}
@@ -0,0 +1,690 @@
+use crate::{CanvasEvent, Colour, CoordinateSystem, Cube, JournalEntry, Position};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Chatter running a command.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Caller {
+    /// Display name, to mention them in replies.
+    pub name: String,
+    pub login: String,
+    pub moderator: bool,
+}
+
+/// What commands see of the canvas, and how they change it. Changes are
+/// queued like placements from chat, so locks, limits and the other rules
+/// still apply to them.
+pub trait CanvasApi {
+    fn side_len(&self) -> u32;
+    /// Current colour of the cube at `position`, along with the entry of the
+    /// event which placed it.
+    fn lookup(&mut self, position: Position) -> Option<(Colour, JournalEntry)>;
+    fn place(&mut self, cube: Cube);
+    fn remove(&mut self, position: Position);
+    /// Position of the cube `login` placed last, among those still on the
+    /// canvas.
+    fn last_placed(&mut self, login: &str) -> Option<Position>;
+    /// The last `limit` events at `position`, latest first.
+    fn history(&mut self, position: Position, limit: usize) -> Vec<JournalEntry>;
+    /// Says `text` in chat.
+    fn reply(&mut self, text: String);
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum PluginError {
+    #[error("you can't use !{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Invalid(String),
+}
+
+/// Chat command `!name args`. The arguments are parsed as soon as the
+/// message is received, so that the chatter is told right away when they're
+/// invalid, then again when the command is executed against the canvas.
+pub trait CommandPlugin: Send + Sync + 'static {
+    type Args;
+
+    fn name(&self) -> &str;
+
+    /// Whether `caller` can run the command, anyone by default.
+    fn authorize(&self, _caller: &Caller) -> bool {
+        true
+    }
+
+    /// The arguments, or the reason they're invalid.
+    fn parse(&self, args: &str) -> Result<Self::Args, String>;
+
+    fn execute(
+        &self,
+        args: Self::Args,
+        caller: &Caller,
+        canvas: &mut dyn CanvasApi,
+    ) -> Result<(), String>;
+}
+
+// Object safe side of CommandPlugin, for the registry to hold any plugin.
+trait Plugin: Send + Sync {
+    fn authorize(&self, caller: &Caller) -> bool;
+    fn check(&self, args: &str) -> Result<(), String>;
+    fn run(&self, args: &str, caller: &Caller, canvas: &mut dyn CanvasApi) -> Result<(), String>;
+}
+
+impl<P: CommandPlugin> Plugin for P {
+    fn authorize(&self, caller: &Caller) -> bool {
+        CommandPlugin::authorize(self, caller)
+    }
+
+    fn check(&self, args: &str) -> Result<(), String> {
+        self.parse(args).map(|_| ())
+    }
+
+    fn run(&self, args: &str, caller: &Caller, canvas: &mut dyn CanvasApi) -> Result<(), String> {
+        self.execute(self.parse(args)?, caller, canvas)
+    }
+}
+
+/// Chat commands by name, cloning the registry gives another handle to the
+/// same plugins.
+#[derive(Clone, Default)]
+pub struct PluginRegistry {
+    plugins: HashMap<String, Arc<dyn Plugin>>,
+}
+
+impl PluginRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `plugin`, in place of the one with the same name if any.
+    pub fn register<P: CommandPlugin>(&mut self, plugin: P) {
+        let name = plugin.name().to_lowercase();
+        self.plugins.insert(name, Arc::new(plugin));
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.plugins.contains_key(name)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.plugins.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Checks that `caller` can run `!name args`, before queuing it. `None`
+    /// when there's no such command.
+    pub fn check(
+        &self,
+        name: &str,
+        caller: &Caller,
+        args: &str,
+    ) -> Option<Result<(), PluginError>> {
+        let plugin = self.plugins.get(name)?;
+        Some(match plugin.authorize(caller) {
+            true => plugin.check(args).map_err(PluginError::Invalid),
+            false => Err(PluginError::Unauthorized(name.to_owned())),
+        })
+    }
+
+    /// Runs `!name args` against `canvas`. `None` when there's no such
+    /// command.
+    pub fn execute(
+        &self,
+        name: &str,
+        caller: &Caller,
+        args: &str,
+        canvas: &mut dyn CanvasApi,
+    ) -> Option<Result<(), PluginError>> {
+        let plugin = self.plugins.get(name)?;
+        Some(match plugin.authorize(caller) {
+            true => plugin
+                .run(args, caller, canvas)
+                .map_err(PluginError::Invalid),
+            false => Err(PluginError::Unauthorized(name.to_owned())),
+        })
+    }
+}
+
+/// `!lookup x y z` tells who placed the cube at a position and when, in the
+/// coordinates of `coordinates`. Moderators also get the command id and the
+/// original message.
+#[derive(Default)]
+pub struct LookupPlugin {
+    pub coordinates: CoordinateSystem,
+}
+
+impl CommandPlugin for LookupPlugin {
+    type Args = [i64; 3];
+
+    fn name(&self) -> &str {
+        "lookup"
+    }
+
+    fn parse(&self, args: &str) -> Result<Self::Args, String> {
+        let coordinates = args
+            .split_whitespace()
+            .map(|v| v.parse::<i64>())
+            .collect::<Result<Vec<_>, _>>();
+        match coordinates.as_deref() {
+            Ok([x, y, z]) => Ok([*x, *y, *z]),
+            _ => Err("try !lookup x y z".to_owned()),
+        }
+    }
+
+    fn execute(
+        &self,
+        coordinates: Self::Args,
+        caller: &Caller,
+        canvas: &mut dyn CanvasApi,
+    ) -> Result<(), String> {
+        let canvas_position = self.coordinates.to_canvas(coordinates, canvas.side_len());
+        let [x, y, z] = coordinates;
+        let position = format!("{} {} {}", x, y, z);
+        let (colour, entry) = match canvas_position.and_then(|p| canvas.lookup(p)) {
+            Some(found) => found,
+            None => {
+                canvas.reply(format!("@{} {} is empty", caller.name, position));
+                return Ok(());
+            }
+        };
+        let author = entry.metadata.author.as_deref().unwrap_or("someone");
+        let when = match entry.metadata.timestamp {
+            0 => "a long time ago".to_owned(),
+            timestamp => chrono::NaiveDateTime::from_timestamp(timestamp, 0)
+                .format("on %Y-%m-%d at %H:%M UTC")
+                .to_string(),
+        };
+        let mut reply = format!(
+            "@{} the {} cube at {} was placed by {} {}",
+            caller.name, colour, position, author, when
+        );
+        if caller.moderator {
+            reply.push_str(&format!(" (command {}", entry.command_id));
+            if let Some(message) = &entry.metadata.message {
+                reply.push_str(&format!(", message \"{}\"", message));
+            }
+            reply.push(')');
+        }
+        canvas.reply(reply);
+        Ok(())
+    }
+}
+
+/// `!px x y colour` places a cube on the z = 0 plane, for pixel art. The
+/// colour is a name or a hex code.
+pub struct PixelPlugin;
+
+impl CommandPlugin for PixelPlugin {
+    type Args = (u32, u32, Colour);
+
+    fn name(&self) -> &str {
+        "px"
+    }
+
+    fn parse(&self, args: &str) -> Result<Self::Args, String> {
+        let invalid = || "try !px x y colour".to_owned();
+        match args.split_whitespace().collect::<Vec<_>>()[..] {
+            [x, y, colour] => Ok((
+                x.parse().map_err(|_| invalid())?,
+                y.parse().map_err(|_| invalid())?,
+                colour.parse().map_err(|_| invalid())?,
+            )),
+            _ => Err(invalid()),
+        }
+    }
+
+    fn execute(
+        &self,
+        (x, y, colour): Self::Args,
+        _caller: &Caller,
+        canvas: &mut dyn CanvasApi,
+    ) -> Result<(), String> {
+        let cube = Cube::bounded(x, y, 0, colour, canvas.side_len()).map_err(|e| e.to_string())?;
+        canvas.place(cube);
+        Ok(())
+    }
+}
+
+/// `!history x y z` lists the last changes at a position and who made them,
+/// in the coordinates of `coordinates`, e.g. to settle who overwrote a build.
+pub struct HistoryPlugin {
+    pub coordinates: CoordinateSystem,
+    /// Changes listed at most, the latest ones.
+    pub changes: usize,
+}
+
+impl CommandPlugin for HistoryPlugin {
+    type Args = [i64; 3];
+
+    fn name(&self) -> &str {
+        "history"
+    }
+
+    fn parse(&self, args: &str) -> Result<Self::Args, String> {
+        let coordinates = args
+            .split_whitespace()
+            .map(|v| v.parse::<i64>())
+            .collect::<Result<Vec<_>, _>>();
+        match coordinates.as_deref() {
+            Ok([x, y, z]) => Ok([*x, *y, *z]),
+            _ => Err("try !history x y z".to_owned()),
+        }
+    }
+
+    fn execute(
+        &self,
+        coordinates: Self::Args,
+        caller: &Caller,
+        canvas: &mut dyn CanvasApi,
+    ) -> Result<(), String> {
+        let position = self
+            .coordinates
+            .to_canvas(coordinates, canvas.side_len())
+            .ok_or_else(|| "that's outside of the canvas".to_owned())?;
+        let [x, y, z] = coordinates;
+        let changes: Vec<String> = canvas
+            .history(position, self.changes)
+            .into_iter()
+            .filter_map(|entry| {
+                let change = match entry.event {
+                    CanvasEvent::CubePlaced(cube) => cube.colour.to_string(),
+                    CanvasEvent::CubeRemoved(_) => "removed".to_owned(),
+                    CanvasEvent::Recoloured { colour, .. } => format!("recoloured {}", colour),
+                    CanvasEvent::CanvasCleared => return None,
+                };
+                let author = entry.metadata.author.as_deref().unwrap_or("someone");
+                Some(match entry.metadata.timestamp {
+                    0 => format!("{} by {}", change, author),
+                    timestamp => format!(
+                        "{} by {} ({})",
+                        change,
+                        author,
+                        chrono::NaiveDateTime::from_timestamp(timestamp, 0)
+                            .format("%Y-%m-%d %H:%M")
+                    ),
+                })
+            })
+            .collect();
+        let reply = match changes.is_empty() {
+            true => format!("@{} nothing happened at {} {} {}", caller.name, x, y, z),
+            false => format!(
+                "@{} latest first at {} {} {}: {}",
+                caller.name,
+                x,
+                y,
+                z,
+                changes.join(", ")
+            ),
+        };
+        canvas.reply(reply);
+        Ok(())
+    }
+}
+
+// How far from the position `!free` looks for empty ones, along each axis.
+const FREE_RADIUS: i64 = 4;
+
+/// `!free x y z` suggests the empty positions nearest to a position, and
+/// `!free` those nearest to the last cube of the caller, in the coordinates
+/// of `coordinates`.
+pub struct FreePlugin {
+    pub coordinates: CoordinateSystem,
+    /// Positions suggested at most.
+    pub suggestions: usize,
+    /// Only suggests positions on the z = 0 plane, for pixel art.
+    pub flat: bool,
+}
+
+impl CommandPlugin for FreePlugin {
+    type Args = Option<[i64; 3]>;
+
+    fn name(&self) -> &str {
+        "free"
+    }
+
+    fn parse(&self, args: &str) -> Result<Self::Args, String> {
+        let coordinates = args
+            .split_whitespace()
+            .map(|v| v.parse::<i64>())
+            .collect::<Result<Vec<_>, _>>();
+        match coordinates.as_deref() {
+            Ok([]) => Ok(None),
+            Ok([x, y, z]) => Ok(Some([*x, *y, *z])),
+            _ => Err("try !free x y z, or !free near your last cube".to_owned()),
+        }
+    }
+
+    fn execute(
+        &self,
+        coordinates: Self::Args,
+        caller: &Caller,
+        canvas: &mut dyn CanvasApi,
+    ) -> Result<(), String> {
+        let side_len = canvas.side_len();
+        let centre = match coordinates {
+            Some(coordinates) => self
+                .coordinates
+                .to_canvas(coordinates, side_len)
+                .ok_or_else(|| "that's outside of the canvas".to_owned())?,
+            None => canvas
+                .last_placed(&caller.login)
+                .ok_or_else(|| "you have no cube on the canvas, try !free x y z".to_owned())?,
+        };
+        let range = -FREE_RADIUS..=FREE_RADIUS;
+        let mut offsets: Vec<[i64; 3]> = range
+            .clone()
+            .flat_map(|dz| {
+                let range = range.clone();
+                range
+                    .clone()
+                    .flat_map(move |dy| range.clone().map(move |dx| [dx, dy, dz]))
+            })
+            .collect();
+        offsets.sort_by_key(|[dx, dy, dz]| dx * dx + dy * dy + dz * dz);
+        let within = |c: i64| (0..side_len as i64).contains(&c);
+        let mut free = Vec::new();
+        for [dx, dy, dz] in offsets {
+            let (x, y, z) = (
+                centre.x as i64 + dx,
+                centre.y as i64 + dy,
+                centre.z as i64 + dz,
+            );
+            if !(within(x) && within(y) && within(z)) || (self.flat && z != 0) {
+                continue;
+            }
+            let position = Position::new(x as u32, y as u32, z as u32);
+            if canvas.lookup(position).is_none() {
+                free.push(position);
+                if free.len() >= self.suggestions {
+                    break;
+                }
+            }
+        }
+        let format = |position: Position| {
+            let [x, y, z] = self.coordinates.from_canvas(position, side_len);
+            format!("{} {} {}", x, y, z)
+        };
+        let reply = match free.is_empty() {
+            true => format!(
+                "@{} there's no free spot around {}",
+                caller.name,
+                format(centre)
+            ),
+            false => format!(
+                "@{} free spots around {}: {}",
+                caller.name,
+                format(centre),
+                free.into_iter().map(format).collect::<Vec<_>>().join(", ")
+            ),
+        };
+        canvas.reply(reply);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanvasEvent, EventMetadata, UpAxis};
+    use uuid::Uuid;
+
+    #[derive(Default)]
+    struct TestCanvas {
+        cubes: HashMap<Position, (Colour, JournalEntry)>,
+        history: Vec<JournalEntry>,
+        placed: Vec<Cube>,
+        replies: Vec<String>,
+    }
+
+    impl CanvasApi for TestCanvas {
+        fn side_len(&self) -> u32 {
+            8
+        }
+
+        fn lookup(&mut self, position: Position) -> Option<(Colour, JournalEntry)> {
+            self.cubes.get(&position).cloned()
+        }
+
+        fn place(&mut self, cube: Cube) {
+            self.placed.push(cube);
+        }
+
+        fn remove(&mut self, _position: Position) {}
+
+        fn last_placed(&mut self, login: &str) -> Option<Position> {
+            self.cubes
+                .iter()
+                .filter(|(_, (_, entry))| entry.metadata.author.as_deref() == Some(login))
+                .max_by_key(|(_, (_, entry))| entry.metadata.timestamp)
+                .map(|(position, _)| *position)
+        }
+
+        fn history(&mut self, position: Position, limit: usize) -> Vec<JournalEntry> {
+            let at = |event: &CanvasEvent| match event {
+                CanvasEvent::CubePlaced(cube) => cube.position == position,
+                CanvasEvent::CubeRemoved(p) | CanvasEvent::Recoloured { position: p, .. } => {
+                    *p == position
+                }
+                CanvasEvent::CanvasCleared => false,
+            };
+            self.history
+                .iter()
+                .rev()
+                .filter(|entry| at(&entry.event))
+                .take(limit)
+                .cloned()
+                .collect()
+        }
+
+        fn reply(&mut self, text: String) {
+            self.replies.push(text);
+        }
+    }
+
+    struct ModeratorOnly;
+
+    impl CommandPlugin for ModeratorOnly {
+        type Args = ();
+
+        fn name(&self) -> &str {
+            "Secret"
+        }
+
+        fn authorize(&self, caller: &Caller) -> bool {
+            caller.moderator
+        }
+
+        fn parse(&self, _args: &str) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn execute(&self, _: (), _: &Caller, canvas: &mut dyn CanvasApi) -> Result<(), String> {
+            canvas.reply("hush".to_owned());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut plugins = PluginRegistry::new();
+        plugins.register(LookupPlugin::default());
+        plugins.register(PixelPlugin);
+        plugins.register(ModeratorOnly);
+        assert_eq!(plugins.names(), vec!["lookup", "px", "secret"]);
+        let mut caller = Caller {
+            name: "Ann".to_owned(),
+            login: "ann".to_owned(),
+            moderator: false,
+        };
+        let mut canvas = TestCanvas::default();
+        assert_eq!(plugins.check("vote", &caller, "clear"), None);
+        assert_eq!(
+            plugins.check("px", &caller, "1 2"),
+            Some(Err(PluginError::Invalid("try !px x y colour".to_owned())))
+        );
+        assert_eq!(
+            plugins.execute("secret", &caller, "", &mut canvas),
+            Some(Err(PluginError::Unauthorized("secret".to_owned())))
+        );
+        assert_eq!(
+            plugins.execute("px", &caller, "1 2 red", &mut canvas),
+            Some(Ok(()))
+        );
+        assert_eq!(
+            canvas.placed,
+            vec![Cube::bounded(1, 2, 0, Colour::new(255, 0, 0), 8).unwrap()]
+        );
+        assert!(plugins
+            .execute("px", &caller, "9 2 red", &mut canvas)
+            .unwrap()
+            .is_err());
+
+        let position = Position::new(1, 2, 3);
+        plugins.execute("lookup", &caller, "1 2 3", &mut canvas);
+        let entry = JournalEntry {
+            command_id: Uuid::nil(),
+            event: CanvasEvent::CanvasCleared,
+            metadata: EventMetadata {
+                author: Some("bob".to_owned()),
+                message: Some("1 2 3 0 0 255".to_owned()),
+                ..EventMetadata::default()
+            },
+        };
+        canvas
+            .cubes
+            .insert(position, (Colour::new(0, 0, 255), entry));
+        caller.moderator = true;
+        plugins.execute("lookup", &caller, "1 2 3", &mut canvas);
+        plugins.execute("secret", &caller, "", &mut canvas);
+        // The same cube, with y up.
+        let y_up = LookupPlugin {
+            coordinates: CoordinateSystem {
+                up: UpAxis::Y,
+                ..CoordinateSystem::default()
+            },
+        };
+        caller.moderator = false;
+        y_up.execute([1, 5, 3], &caller, &mut canvas).unwrap();
+        assert_eq!(
+            canvas.replies,
+            vec![
+                "@Ann 1 2 3 is empty".to_owned(),
+                "@Ann the #0000ff cube at 1 2 3 was placed by bob a long time ago \
+                 (command 00000000-0000-0000-0000-000000000000, message \"1 2 3 0 0 255\")"
+                    .to_owned(),
+                "hush".to_owned(),
+                "@Ann the #0000ff cube at 1 5 3 was placed by bob a long time ago".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_free() {
+        let free = FreePlugin {
+            coordinates: CoordinateSystem::default(),
+            suggestions: 2,
+            flat: false,
+        };
+        let caller = Caller {
+            name: "Ann".to_owned(),
+            login: "ann".to_owned(),
+            moderator: false,
+        };
+        let mut canvas = TestCanvas::default();
+        assert_eq!(
+            free.parse("1 2"),
+            Err("try !free x y z, or !free near your last cube".to_owned())
+        );
+        assert!(free.execute(None, &caller, &mut canvas).is_err());
+
+        let entry = |author: &str, timestamp: i64| JournalEntry {
+            command_id: Uuid::nil(),
+            event: CanvasEvent::CanvasCleared,
+            metadata: EventMetadata {
+                author: Some(author.to_owned()),
+                timestamp,
+                ..EventMetadata::default()
+            },
+        };
+        let red = Colour::new(255, 0, 0);
+        for (timestamp, position) in [(0, 0, 0), (1, 0, 0), (0, 1, 0)].iter().enumerate() {
+            let entry = entry("ann", timestamp as i64);
+            canvas.cubes.insert((*position).into(), (red, entry));
+        }
+        free.execute(Some([0, 0, 0]), &caller, &mut canvas).unwrap();
+        // Around the last cube of Ann.
+        free.execute(None, &caller, &mut canvas).unwrap();
+        // A full plane, for pixel art.
+        let flat = FreePlugin { flat: true, ..free };
+        for x in 0..8 {
+            for y in 0..8 {
+                canvas
+                    .cubes
+                    .insert((x, y, 0).into(), (red, entry("bob", 0)));
+            }
+        }
+        flat.execute(Some([3, 3, 0]), &caller, &mut canvas).unwrap();
+        assert_eq!(
+            canvas.replies,
+            vec![
+                "@Ann free spots around 0 0 0: 0 0 1, 1 1 0".to_owned(),
+                "@Ann free spots around 0 1 0: 1 1 0, 0 2 0".to_owned(),
+                "@Ann there's no free spot around 3 3 0".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let history = HistoryPlugin {
+            coordinates: CoordinateSystem::default(),
+            changes: 2,
+        };
+        let caller = Caller {
+            name: "Ann".to_owned(),
+            login: "ann".to_owned(),
+            moderator: false,
+        };
+        let mut canvas = TestCanvas::default();
+        assert_eq!(history.parse("1 2"), Err("try !history x y z".to_owned()));
+        assert!(history.execute([1, 2, 9], &caller, &mut canvas).is_err());
+
+        let entry = |event: CanvasEvent, author: &str, timestamp: i64| JournalEntry {
+            command_id: Uuid::nil(),
+            event,
+            metadata: EventMetadata {
+                author: Some(author.to_owned()),
+                timestamp,
+                ..EventMetadata::default()
+            },
+        };
+        let position = Position::new(1, 2, 3);
+        let red = Colour::new(255, 0, 0);
+        canvas.history = vec![
+            entry(CanvasEvent::CubePlaced(Cube::new(1, 2, 3, red)), "ann", 0),
+            entry(CanvasEvent::CubePlaced(Cube::new(3, 2, 1, red)), "ann", 0),
+            entry(CanvasEvent::CubeRemoved(position), "bob", 60),
+            entry(
+                CanvasEvent::Recoloured {
+                    position,
+                    colour: Colour::new(0, 0, 255),
+                },
+                "carol",
+                0,
+            ),
+        ];
+        history.execute([1, 2, 3], &caller, &mut canvas).unwrap();
+        history.execute([0, 0, 0], &caller, &mut canvas).unwrap();
+        assert_eq!(
+            canvas.replies,
+            vec![
+                "@Ann latest first at 1 2 3: recoloured #0000ff by carol, \
+                 removed by bob (1970-01-01 00:01)"
+                    .to_owned(),
+                "@Ann nothing happened at 0 0 0".to_owned(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct PollConfig {
+    /// How long a poll stays open after the first vote.
+    pub window_secs: u64,
+    /// Votes the winning option needs for the poll to pass.
+    pub min_votes: usize,
+}
+
+impl Default for PollConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: 60,
+            min_votes: 3,
+        }
+    }
+}
+
+/// Tallies the votes cast by chatters over a time window. Each chatter has a
+/// single vote, voting again replaces the previous one.
+#[derive(Clone, Debug)]
+pub struct Poll<T> {
+    deadline: Instant,
+    min_votes: usize,
+    votes: HashMap<String, T>,
+}
+
+impl<T: Clone + Eq + Hash> Poll<T> {
+    pub fn new(config: &PollConfig, now: Instant) -> Self {
+        Self {
+            deadline: now + Duration::from_secs(config.window_secs),
+            min_votes: config.min_votes.max(1),
+            votes: HashMap::new(),
+        }
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn is_over(&self, now: Instant) -> bool {
+        now >= self.deadline
+    }
+
+    /// Records a vote, ignored once the poll is over.
+    pub fn vote(&mut self, voter: &str, option: T, now: Instant) {
+        if !self.is_over(now) {
+            self.votes.insert(voter.to_owned(), option);
+        }
+    }
+
+    /// Number of votes for each option, most voted first.
+    pub fn tally(&self) -> Vec<(T, usize)> {
+        let mut counts: HashMap<&T, usize> = HashMap::new();
+        for option in self.votes.values() {
+            *counts.entry(option).or_default() += 1;
+        }
+        let mut tally: Vec<(T, usize)> = counts
+            .into_iter()
+            .map(|(option, count)| (option.clone(), count))
+            .collect();
+        tally.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        tally
+    }
+
+    /// The option with the most votes, if it has enough of them and there's
+    /// no tie.
+    pub fn winner(&self) -> Option<T> {
+        let tally = self.tally();
+        let (option, count) = tally.first()?;
+        let tied = tally.get(1).is_some_and(|(_, c)| c == count);
+        if *count < self.min_votes || tied {
+            return None;
+        }
+        Some(option.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_winner() {
+        let now = Instant::now();
+        let config = PollConfig {
+            window_secs: 10,
+            min_votes: 2,
+        };
+        let mut poll = Poll::new(&config, now);
+        poll.vote("a", "clear", now);
+        assert_eq!(poll.winner(), None);
+        poll.vote("b", "clear", now);
+        poll.vote("c", "lock", now);
+        assert_eq!(poll.winner(), Some("clear"));
+        // Changing vote, and late votes.
+        poll.vote("b", "lock", now);
+        poll.vote("d", "clear", now + Duration::from_secs(10));
+        assert_eq!(poll.tally(), vec![("lock", 2), ("clear", 1)]);
+        assert_eq!(poll.winner(), Some("lock"));
+        assert!(poll.is_over(poll.deadline()));
+    }
+
+    #[test]
+    fn test_tie() {
+        let now = Instant::now();
+        let mut poll = Poll::new(
+            &PollConfig {
+                window_secs: 10,
+                min_votes: 1,
+            },
+            now,
+        );
+        poll.vote("a", "clear", now);
+        poll.vote("b", "lock", now);
+        assert_eq!(poll.winner(), None);
+    }
+}
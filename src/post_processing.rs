@@ -0,0 +1,426 @@
+use image::RgbImage;
+use serde::Deserialize;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Post-processing applied to the images of an output before saving them.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PostProcessingConfig {
+    /// Darkening of the corners, from 0 (none) to 1 (black corners).
+    pub vignette: f32,
+    /// Blur radius in pixels of the most out of focus areas, 0 disables the
+    /// depth of field. Only applies to renderers providing depth.
+    pub depth_of_field: u32,
+    /// Colour grading look-up table, in the .cube format.
+    pub lut_filepath: Option<String>,
+    /// Strength of the glow around the glowing cubes, 0 disables it. Only
+    /// applies to the raytraced snapshots.
+    pub glow: f32,
+    /// How far the glow spreads around the glowing cubes, in pixels.
+    pub glow_radius: u32,
+}
+
+#[derive(Error, Debug)]
+pub enum PostProcessingError {
+    #[error("unable to read the LUT {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid LUT: {0}")]
+    InvalidLut(String),
+}
+
+#[derive(Clone)]
+pub struct PostProcessor {
+    vignette: f32,
+    depth_of_field: u32,
+    lut: Option<Lut>,
+    glow: f32,
+    glow_radius: u32,
+}
+
+impl PostProcessor {
+    pub fn new(config: &PostProcessingConfig) -> Result<Self, PostProcessingError> {
+        let lut = match &config.lut_filepath {
+            Some(path) => Some(Lut::parse(&std::fs::read_to_string(path)?)?),
+            None => None,
+        };
+        Ok(Self {
+            vignette: config.vignette.clamp(0.0, 1.0),
+            depth_of_field: config.depth_of_field,
+            lut,
+            glow: config.glow.max(0.0),
+            glow_radius: config.glow_radius,
+        })
+    }
+
+    /// Whether `glow` changes anything, so the emission of the glowing cubes
+    /// is only rendered when needed.
+    pub fn glows(&self) -> bool {
+        self.glow > 0.0 && self.glow_radius > 0
+    }
+
+    /// Makes the glowing cubes bleed light around them, a bloom done before
+    /// `apply`. `emission` holds how much each pixel shows glowing cubes, row
+    /// by row, see Raytracer::render_emission: their colours are blurred and
+    /// added back over the image.
+    pub fn glow(&self, img: &mut RgbImage, emission: &[f32]) {
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        if !self.glows() || emission.len() != width * height {
+            return;
+        }
+        let mut sums = vec![[0f32; 3]; (width + 1) * (height + 1)];
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = img.get_pixel(x as u32, y as u32).0;
+                let e = emission[y * width + x];
+                for c in 0..3 {
+                    sums[(y + 1) * (width + 1) + x + 1][c] = pixel[c] as f32 * e
+                        + sums[y * (width + 1) + x + 1][c]
+                        + sums[(y + 1) * (width + 1) + x][c]
+                        - sums[y * (width + 1) + x][c];
+                }
+            }
+        }
+
+        let radius = self.glow_radius as usize;
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let (x, y) = (x as usize, y as usize);
+            let (x0, y0) = (x.saturating_sub(radius), y.saturating_sub(radius));
+            let (x1, y1) = ((x + radius + 1).min(width), (y + radius + 1).min(height));
+            let area = ((x1 - x0) * (y1 - y0)) as f32;
+            for (c, value) in pixel.0.iter_mut().enumerate() {
+                let sum = sums[y1 * (width + 1) + x1][c] + sums[y0 * (width + 1) + x0][c]
+                    - sums[y0 * (width + 1) + x1][c]
+                    - sums[y1 * (width + 1) + x0][c];
+                let glow = self.glow * sum.max(0.0) / area;
+                *value = (*value as f32 + glow).round().min(255.0) as u8;
+            }
+        }
+    }
+
+    /// Applies the configured effects. `depth` holds the distance from the
+    /// camera of each pixel, row by row, infinite where nothing was hit.
+    pub fn apply(&self, img: &mut RgbImage, depth: Option<&[f32]>) {
+        if let (Some(depth), true) = (depth, self.depth_of_field > 0) {
+            self.blur_by_depth(img, depth);
+        }
+        if let Some(lut) = &self.lut {
+            for pixel in img.pixels_mut() {
+                pixel.0 = lut.apply(pixel.0);
+            }
+        }
+        if self.vignette > 0.0 {
+            let (width, height) = (img.width() as f32, img.height() as f32);
+            let max_distance = (width * width + height * height).sqrt() / 2.0;
+            for (x, y, pixel) in img.enumerate_pixels_mut() {
+                let dx = x as f32 + 0.5 - width / 2.0;
+                let dy = y as f32 + 0.5 - height / 2.0;
+                let distance = (dx * dx + dy * dy).sqrt() / max_distance;
+                let factor = 1.0 - self.vignette * distance * distance;
+                for c in pixel.0.iter_mut() {
+                    *c = (*c as f32 * factor).round() as u8;
+                }
+            }
+        }
+    }
+
+    // Focuses on whatever is at the centre of the image, and blurs every pixel
+    // with a box filter growing with its distance from the focal plane. Box
+    // sums come from a summed-area table, so the cost doesn't depend on the
+    // radius.
+    fn blur_by_depth(&self, img: &mut RgbImage, depth: &[f32]) {
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        if depth.len() != width * height {
+            return;
+        }
+        let centre = depth[height / 2 * width + width / 2];
+        let focus = if centre.is_finite() {
+            centre
+        } else {
+            match depth
+                .iter()
+                .copied()
+                .filter(|d| d.is_finite())
+                .reduce(f32::min)
+            {
+                Some(d) => d,
+                None => return,
+            }
+        };
+
+        let mut sums = vec![[0u64; 3]; (width + 1) * (height + 1)];
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = img.get_pixel(x as u32, y as u32).0;
+                for c in 0..3 {
+                    sums[(y + 1) * (width + 1) + x + 1][c] = pixel[c] as u64
+                        + sums[y * (width + 1) + x + 1][c]
+                        + sums[(y + 1) * (width + 1) + x][c]
+                        - sums[y * (width + 1) + x][c];
+                }
+            }
+        }
+
+        let max_radius = self.depth_of_field as f32;
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let (x, y) = (x as usize, y as usize);
+            let d = depth[y * width + x];
+            let radius = if d.is_finite() {
+                (max_radius * (d - focus).abs() / focus).min(max_radius)
+            } else {
+                max_radius
+            } as usize;
+            if radius == 0 {
+                continue;
+            }
+            let (x0, y0) = (x.saturating_sub(radius), y.saturating_sub(radius));
+            let (x1, y1) = ((x + radius + 1).min(width), (y + radius + 1).min(height));
+            let area = ((x1 - x0) * (y1 - y0)) as u64;
+            for (c, value) in pixel.0.iter_mut().enumerate() {
+                let sum = sums[y1 * (width + 1) + x1][c] + sums[y0 * (width + 1) + x0][c]
+                    - sums[y0 * (width + 1) + x1][c]
+                    - sums[y1 * (width + 1) + x0][c];
+                *value = (sum / area) as u8;
+            }
+        }
+    }
+}
+
+// 3D colour look-up table, as exported by most colour grading tools.
+/// Looks of an output chat can switch between by name, each with its own
+/// post-processing. The configured post-processing is the `default` theme.
+#[derive(Clone)]
+pub struct Themes {
+    themes: HashMap<String, PostProcessor>,
+}
+
+impl Themes {
+    pub fn new(
+        default: &PostProcessingConfig,
+        themes: &HashMap<String, PostProcessingConfig>,
+    ) -> Result<Self, PostProcessingError> {
+        let mut processors = HashMap::new();
+        for (name, config) in themes {
+            processors.insert(name.to_lowercase(), PostProcessor::new(config)?);
+        }
+        processors.insert("default".to_owned(), PostProcessor::new(default)?);
+        Ok(Self { themes: processors })
+    }
+
+    /// Post-processing of the theme, whatever the case of its name.
+    pub fn get(&self, name: &str) -> Option<&PostProcessor> {
+        self.themes.get(&name.to_lowercase())
+    }
+
+    /// Names of the themes, in alphabetical order.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.themes.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Lut {
+    size: usize,
+    // Red changes fastest, then green, then blue.
+    table: Vec<[f32; 3]>,
+}
+
+impl Lut {
+    fn parse(source: &str) -> Result<Self, PostProcessingError> {
+        let mut size = None;
+        let mut table = Vec::new();
+        for line in source.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(value) = line.strip_prefix("LUT_3D_SIZE") {
+                size = Some(value.trim().parse::<usize>().map_err(|_| {
+                    PostProcessingError::InvalidLut(format!("invalid size {}", value.trim()))
+                })?);
+                continue;
+            }
+            if line.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                // TITLE, DOMAIN_MIN, DOMAIN_MAX, ... only the default domain
+                // is supported.
+                continue;
+            }
+            let values: Result<Vec<f32>, _> = line.split_whitespace().map(str::parse).collect();
+            match values {
+                Ok(v) if v.len() == 3 => table.push([v[0], v[1], v[2]]),
+                _ => {
+                    return Err(PostProcessingError::InvalidLut(format!(
+                        "invalid entry {}",
+                        line
+                    )))
+                }
+            }
+        }
+        let size =
+            size.ok_or_else(|| PostProcessingError::InvalidLut("missing LUT_3D_SIZE".to_owned()))?;
+        if size < 2 || table.len() != size * size * size {
+            return Err(PostProcessingError::InvalidLut(format!(
+                "expected {} entries, found {}",
+                size * size * size,
+                table.len()
+            )));
+        }
+        Ok(Self { size, table })
+    }
+
+    // Trilinear interpolation between the eight closest entries.
+    fn apply(&self, colour: [u8; 3]) -> [u8; 3] {
+        let n = self.size - 1;
+        let mut low = [0usize; 3];
+        let mut high = [0usize; 3];
+        let mut fraction = [0f32; 3];
+        for c in 0..3 {
+            let position = colour[c] as f32 / 255.0 * n as f32;
+            low[c] = (position.floor() as usize).min(n);
+            high[c] = (low[c] + 1).min(n);
+            fraction[c] = position - low[c] as f32;
+        }
+        let entry = |r: usize, g: usize, b: usize| {
+            self.table[r + g * self.size + b * self.size * self.size]
+        };
+        let mut result = [0u8; 3];
+        for (c, value) in result.iter_mut().enumerate() {
+            let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+            let c00 = lerp(
+                entry(low[0], low[1], low[2])[c],
+                entry(high[0], low[1], low[2])[c],
+                fraction[0],
+            );
+            let c10 = lerp(
+                entry(low[0], high[1], low[2])[c],
+                entry(high[0], high[1], low[2])[c],
+                fraction[0],
+            );
+            let c01 = lerp(
+                entry(low[0], low[1], high[2])[c],
+                entry(high[0], low[1], high[2])[c],
+                fraction[0],
+            );
+            let c11 = lerp(
+                entry(low[0], high[1], high[2])[c],
+                entry(high[0], high[1], high[2])[c],
+                fraction[0],
+            );
+            let c0 = lerp(c00, c10, fraction[1]);
+            let c1 = lerp(c01, c11, fraction[1]);
+            *value = (lerp(c0, c1, fraction[2]).clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A LUT of size 2 swapping the red and blue channels.
+    const SWAP_LUT: &str = "TITLE \"swap\"
+LUT_3D_SIZE 2
+0 0 0
+0 0 1
+0 1 0
+0 1 1
+1 0 0
+1 0 1
+1 1 0
+1 1 1
+";
+
+    #[test]
+    fn test_lut() {
+        let lut = Lut::parse(SWAP_LUT).unwrap();
+        assert_eq!(lut.apply([255, 0, 0]), [0, 0, 255]);
+        assert_eq!(lut.apply([10, 20, 30]), [30, 20, 10]);
+        assert!(Lut::parse("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+    }
+
+    #[test]
+    fn test_vignette_darkens_corners() {
+        let processor = PostProcessor::new(&PostProcessingConfig {
+            vignette: 0.8,
+            ..Default::default()
+        })
+        .unwrap();
+        let mut img = RgbImage::from_pixel(9, 9, image::Rgb([200, 200, 200]));
+        processor.apply(&mut img, None);
+        assert!(img.get_pixel(4, 4).0[0] == 200);
+        assert!(img.get_pixel(0, 0).0[0] < 120);
+    }
+
+    #[test]
+    fn test_depth_of_field_keeps_focus_sharp() {
+        let processor = PostProcessor::new(&PostProcessingConfig {
+            depth_of_field: 2,
+            ..Default::default()
+        })
+        .unwrap();
+        let mut img = RgbImage::from_fn(5, 5, |x, _| image::Rgb([(x * 50) as u8, 0, 0]));
+        let depth: Vec<f32> = (0..25)
+            .map(|i| if i % 5 < 3 { 1.0 } else { f32::INFINITY })
+            .collect();
+        processor.apply(&mut img, Some(&depth));
+        assert_eq!(img.get_pixel(2, 2).0[0], 100);
+        assert_ne!(img.get_pixel(4, 2).0[0], 200);
+    }
+
+    #[test]
+    fn test_glow_spreads_around_glowing_pixels() {
+        let processor = PostProcessor::new(&PostProcessingConfig {
+            glow: 1.0,
+            glow_radius: 1,
+            ..Default::default()
+        })
+        .unwrap();
+        let mut img = RgbImage::from_pixel(5, 5, image::Rgb([0, 0, 0]));
+        img.put_pixel(2, 2, image::Rgb([180, 0, 0]));
+        let mut emission = vec![0.0; 25];
+        emission[2 * 5 + 2] = 1.0;
+        processor.glow(&mut img, &emission);
+        // A ninth of the glowing pixel around it, nothing further away.
+        assert_eq!(img.get_pixel(1, 1).0, [20, 0, 0]);
+        assert_eq!(img.get_pixel(2, 2).0, [200, 0, 0]);
+        assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0]);
+        // Left alone when the emission doesn't match the image.
+        processor.glow(&mut img, &[1.0; 4]);
+        assert_eq!(img.get_pixel(1, 1).0, [20, 0, 0]);
+        assert!(!PostProcessor::new(&PostProcessingConfig::default())
+            .unwrap()
+            .glows());
+    }
+
+    #[test]
+    fn test_themes() {
+        let mut configs = HashMap::new();
+        configs.insert(
+            "Night".to_owned(),
+            PostProcessingConfig {
+                vignette: 0.8,
+                ..Default::default()
+            },
+        );
+        let themes = Themes::new(&PostProcessingConfig::default(), &configs).unwrap();
+        assert_eq!(themes.names(), vec!["default", "night"]);
+        assert!(themes.get("dawn").is_none());
+
+        let mut img = RgbImage::from_pixel(9, 9, image::Rgb([200, 200, 200]));
+        themes.get("default").unwrap().apply(&mut img, None);
+        assert_eq!(img.get_pixel(0, 0).0[0], 200);
+        themes.get("NIGHT").unwrap().apply(&mut img, None);
+        assert!(img.get_pixel(0, 0).0[0] < 120);
+
+        configs.insert(
+            "broken".to_owned(),
+            PostProcessingConfig {
+                lut_filepath: Some(".missing.cube".to_owned()),
+                ..Default::default()
+            },
+        );
+        assert!(Themes::new(&PostProcessingConfig::default(), &configs).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use crate::Position;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ProgressionStage {
+    /// Side of the buildable region, centred in the canvas.
+    pub side: u32,
+    /// Cubes placed since the beginning before the stage unlocks.
+    pub cubes: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ProgressionConfig {
+    /// Stages in any order, the one with the fewest cubes is the initial
+    /// region. None disables the progression, the whole canvas is buildable.
+    pub stages: Vec<ProgressionStage>,
+}
+
+/// Box of the canvas between two corners, both included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub min: Position,
+    pub max: Position,
+}
+
+impl Region {
+    /// Region of side `side` in the middle of a canvas of side `side_len`.
+    pub fn centred(side: u32, side_len: u32) -> Self {
+        let side = side.clamp(1, side_len.max(1));
+        let min = (side_len.max(1) - side) / 2;
+        let max = min + side - 1;
+        Self {
+            min: Position::new(min, min, min),
+            max: Position::new(max, max, max),
+        }
+    }
+
+    pub fn contains(&self, position: Position) -> bool {
+        (self.min.x..=self.max.x).contains(&position.x)
+            && (self.min.y..=self.max.y).contains(&position.y)
+            && (self.min.z..=self.max.z).contains(&position.z)
+    }
+
+    /// Whether the two regions have a position in common.
+    pub fn overlaps(&self, other: &Region) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+            && self.min.z <= other.max.z
+            && other.min.z <= self.max.z
+    }
+
+    pub fn side(&self) -> u32 {
+        self.max.x - self.min.x + 1
+    }
+}
+
+/// Unlocks bigger regions of the canvas as cubes get placed. Stages are only
+/// ever unlocked, clearing the canvas doesn't lock them again.
+#[derive(Clone, Debug)]
+pub struct Progression {
+    stages: Vec<ProgressionStage>,
+    side_len: u32,
+    stage: usize,
+    placed: u64,
+}
+
+impl Progression {
+    /// Resumes from the stage reached before, and the number of cubes placed
+    /// so far. `None` if there are no stages.
+    pub fn new(
+        config: &ProgressionConfig,
+        side_len: u32,
+        stage: usize,
+        placed: u64,
+    ) -> Option<Self> {
+        if config.stages.is_empty() {
+            return None;
+        }
+        let mut stages = config.stages.clone();
+        stages.sort_by_key(|stage| stage.cubes);
+        let reached = stages.iter().filter(|s| s.cubes <= placed).count();
+        let stage = stage.max(reached.saturating_sub(1)).min(stages.len() - 1);
+        Some(Self {
+            stages,
+            side_len,
+            stage,
+            placed,
+        })
+    }
+
+    /// Index of the last unlocked stage, by number of cubes.
+    pub fn stage(&self) -> usize {
+        self.stage
+    }
+
+    pub fn region(&self) -> Region {
+        Region::centred(self.stages[self.stage].side, self.side_len)
+    }
+
+    pub fn is_unlocked(&self, position: Position) -> bool {
+        self.region().contains(position)
+    }
+
+    /// Cubes left to place before the next stage, if any.
+    pub fn remaining(&self) -> Option<u64> {
+        self.stages
+            .get(self.stage + 1)
+            .map(|next| next.cubes.saturating_sub(self.placed))
+    }
+
+    /// Counts a placed cube, returns the new region when it unlocks a stage.
+    pub fn count_placement(&mut self) -> Option<Region> {
+        self.placed += 1;
+        let mut unlocked = false;
+        while self.stage + 1 < self.stages.len() && self.stages[self.stage + 1].cubes <= self.placed
+        {
+            self.stage += 1;
+            unlocked = true;
+        }
+        match unlocked {
+            true => Some(self.region()),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region() {
+        let region = Region::centred(4, 10);
+        assert_eq!(region.min, Position::new(3, 3, 3));
+        assert_eq!(region.side(), 4);
+        assert!(region.contains(Position::new(6, 3, 5)));
+        assert!(!region.contains(Position::new(7, 3, 5)));
+        assert_eq!(Region::centred(20, 10).side(), 10);
+    }
+
+    #[test]
+    fn test_progression() {
+        let config = ProgressionConfig {
+            stages: vec![
+                ProgressionStage { side: 6, cubes: 3 },
+                ProgressionStage { side: 2, cubes: 0 },
+                ProgressionStage { side: 10, cubes: 5 },
+            ],
+        };
+        let mut progression = Progression::new(&config, 10, 0, 1).unwrap();
+        assert_eq!(progression.region().side(), 2);
+        assert!(!progression.is_unlocked(Position::new(3, 3, 3)));
+        assert_eq!(progression.remaining(), Some(2));
+        assert_eq!(progression.count_placement(), None);
+        assert_eq!(progression.count_placement(), Some(Region::centred(6, 10)));
+        assert!(progression.is_unlocked(Position::new(3, 3, 3)));
+
+        // Resuming keeps the stage reached, even with fewer cubes counted.
+        let progression = Progression::new(&config, 10, 2, 0).unwrap();
+        assert_eq!(progression.region().side(), 10);
+        assert_eq!(progression.remaining(), None);
+        assert!(Progression::new(&ProgressionConfig::default(), 10, 0, 0).is_none());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{CanvasEvent, CoordinateSystem, Position, Region};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ProtectionError {
+    #[error("give the range of each axis, e.g. 0..9 0..2 0..9")]
+    InvalidRange,
+    #[error("that's outside of the canvas")]
+    OutsideCanvas,
+}
+
+/// Region given in chat as the range of each axis in the coordinates of
+/// chat, e.g. `0..9 0..2 0..9`, a single number for a single layer.
+pub fn parse_region(
+    args: &str,
+    coordinates: CoordinateSystem,
+    side_len: u32,
+) -> Result<Region, ProtectionError> {
+    let ranges = args
+        .split_whitespace()
+        .map(|range| {
+            let (first, last) = range.split_once("..").unwrap_or((range, range));
+            Some((first.parse::<i64>().ok()?, last.parse::<i64>().ok()?))
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or(ProtectionError::InvalidRange)?;
+    let (first, last) = match ranges[..] {
+        [(x1, x2), (y1, y2), (z1, z2)] => ([x1, y1, z1], [x2, y2, z2]),
+        _ => return Err(ProtectionError::InvalidRange),
+    };
+    match (
+        coordinates.to_canvas(first, side_len),
+        coordinates.to_canvas(last, side_len),
+    ) {
+        (Some(a), Some(b)) => Ok(Region {
+            min: Position::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            max: Position::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        }),
+        _ => Err(ProtectionError::OutsideCanvas),
+    }
+}
+
+/// `region` as chat gives it, the inverse of `parse_region`.
+pub fn format_region(region: Region, coordinates: CoordinateSystem, side_len: u32) -> String {
+    let a = coordinates.from_canvas(region.min, side_len);
+    let b = coordinates.from_canvas(region.max, side_len);
+    (0..3)
+        .map(|axis| match a[axis].min(b[axis])..=a[axis].max(b[axis]) {
+            range if range.start() == range.end() => range.start().to_string(),
+            range => format!("{}..{}", range.start(), range.end()),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Regions of the canvas chat can't change, e.g. the logo or the borders the
+/// streamer framed the canvas with.
+#[derive(Clone, Debug, Default)]
+pub struct ProtectedRegions {
+    regions: Vec<Region>,
+}
+
+impl ProtectedRegions {
+    pub fn new(regions: Vec<Region>) -> Self {
+        Self { regions }
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn protect(&mut self, region: Region) {
+        if !self.regions.contains(&region) {
+            self.regions.push(region);
+        }
+    }
+
+    /// Stops protecting the regions overlapping `region`, returns how many
+    /// there were.
+    pub fn unprotect(&mut self, region: Region) -> usize {
+        let before = self.regions.len();
+        self.regions
+            .retain(|protected| !protected.overlaps(&region));
+        before - self.regions.len()
+    }
+
+    /// Whether `event` changes a protected cube. Clearing the canvas doesn't
+    /// count, it isn't up to a single chatter.
+    pub fn protects(&self, event: &CanvasEvent) -> bool {
+        let position = match event {
+            CanvasEvent::CubePlaced(cube) => cube.position,
+            CanvasEvent::CubeRemoved(position) => *position,
+            CanvasEvent::Recoloured { position, .. } => *position,
+            CanvasEvent::CanvasCleared => return false,
+        };
+        self.regions.iter().any(|region| region.contains(position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colour, Cube, Origin};
+
+    #[test]
+    fn test_protected_regions() {
+        let coordinates = CoordinateSystem::default();
+        let logo = parse_region("2..0 1 0..3", coordinates, 8).unwrap();
+        assert_eq!(format_region(logo, coordinates, 8), "0..2 1 0..3");
+        assert_eq!(
+            parse_region("0..2 1", coordinates, 8),
+            Err(ProtectionError::InvalidRange)
+        );
+        assert_eq!(
+            parse_region("0..a 1 2", coordinates, 8),
+            Err(ProtectionError::InvalidRange)
+        );
+        assert_eq!(
+            parse_region("0..8 1 2", coordinates, 8),
+            Err(ProtectionError::OutsideCanvas)
+        );
+        let centred = CoordinateSystem {
+            origin: Origin::Centre,
+            ..Default::default()
+        };
+        let region = parse_region("-1..1 -1..1 -1..1", centred, 8).unwrap();
+        assert_eq!(format_region(region, centred, 8), "-1..1 -1..1 -1..1");
+
+        let mut protected = ProtectedRegions::default();
+        protected.protect(logo);
+        protected.protect(logo);
+        let red = Colour::new(255, 0, 0);
+        let placed = |x, y, z| CanvasEvent::CubePlaced(Cube::new(x, y, z, red));
+        assert!(protected.protects(&placed(1, 1, 3)));
+        assert!(protected.protects(&CanvasEvent::CubeRemoved(Position::new(0, 1, 0))));
+        assert!(!protected.protects(&placed(1, 2, 3)));
+        assert!(!protected.protects(&CanvasEvent::CanvasCleared));
+
+        let corner = parse_region("2 1 3", coordinates, 8).unwrap();
+        assert_eq!(protected.unprotect(corner), 1);
+        assert!(protected.regions().is_empty());
+        assert!(!protected.protects(&placed(1, 1, 3)));
+    }
+}
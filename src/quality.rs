@@ -0,0 +1,142 @@
+use serde::Deserialize;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct QualityConfig {
+    /// Frames in a row losing frames before the quality is lowered a step, 0
+    /// keeps it as configured.
+    pub degrade_after: u32,
+    /// Frames in a row on time before it's raised back a step.
+    pub restore_after: u32,
+    /// Lowest the quality goes, as a fraction of the configured one. Each
+    /// step halves it.
+    pub min_scale: f32,
+}
+
+impl Default for QualityConfig {
+    fn default() -> Self {
+        Self {
+            degrade_after: 3,
+            restore_after: 30,
+            min_scale: 0.25,
+        }
+    }
+}
+
+/// Lowers the resolution of snapshots and the quality of the streamed frames
+/// while the overlay keeps losing frames, and restores them once it keeps up
+/// again.
+#[derive(Clone, Debug)]
+pub struct AdaptiveQuality {
+    config: QualityConfig,
+    // Steps below the configured quality.
+    level: u32,
+    // Frames in a row late, or on time.
+    late: u32,
+    on_time: u32,
+}
+
+impl AdaptiveQuality {
+    pub fn new(config: &QualityConfig) -> Self {
+        Self {
+            config: config.clone(),
+            level: 0,
+            late: 0,
+            on_time: 0,
+        }
+    }
+
+    /// Records a frame, `frames_lost` being how many were missed while it
+    /// was rendered. Returns the new scale when it changes.
+    pub fn record(&mut self, frames_lost: u32) -> Option<f32> {
+        if self.config.degrade_after == 0 {
+            return None;
+        }
+        if frames_lost > 0 {
+            self.on_time = 0;
+            self.late += 1;
+            if self.late < self.config.degrade_after || self.step_scale(self.level + 1).is_none() {
+                return None;
+            }
+            self.late = 0;
+            self.level += 1;
+        } else {
+            self.late = 0;
+            self.on_time += 1;
+            if self.on_time < self.config.restore_after || self.level == 0 {
+                return None;
+            }
+            self.on_time = 0;
+            self.level -= 1;
+        }
+        Some(self.scale())
+    }
+
+    /// Fraction of the configured quality to render at.
+    pub fn scale(&self) -> f32 {
+        self.step_scale(self.level).unwrap_or(1.0)
+    }
+
+    /// Side of the snapshots, from the configured one.
+    pub fn resolution(&self, resolution: u32) -> u32 {
+        ((resolution as f32 * self.scale()).round() as u32).max(1)
+    }
+
+    /// Quality of the JPEG encoding, from 1 to 100, from the configured one.
+    pub fn jpeg_quality(&self, quality: u8) -> u8 {
+        ((quality as f32 * self.scale()).round() as u8).clamp(1, 100)
+    }
+
+    // Scale `level` steps down, none if below the lowest.
+    fn step_scale(&self, level: u32) -> Option<f32> {
+        let scale = 0.5f32.powi(level as i32);
+        Some(scale).filter(|scale| *scale >= self.config.min_scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_quality() {
+        let config = QualityConfig {
+            degrade_after: 2,
+            restore_after: 3,
+            min_scale: 0.25,
+        };
+        let mut quality = AdaptiveQuality::new(&config);
+        assert_eq!(quality.record(1), None);
+        assert_eq!(quality.record(0), None);
+        assert_eq!(quality.record(2), None);
+        assert_eq!(quality.record(1), Some(0.5));
+        assert_eq!(quality.resolution(1080), 540);
+        assert_eq!(quality.jpeg_quality(85), 43);
+        assert_eq!(quality.record(1), None);
+        assert_eq!(quality.record(1), Some(0.25));
+        // Never below the lowest.
+        for _ in 0..4 {
+            assert_eq!(quality.record(1), None);
+        }
+        assert_eq!(quality.scale(), 0.25);
+        assert_eq!(quality.record(0), None);
+        assert_eq!(quality.record(0), None);
+        assert_eq!(quality.record(0), Some(0.5));
+        for _ in 0..2 {
+            assert_eq!(quality.record(0), None);
+        }
+        assert_eq!(quality.record(0), Some(1.0));
+        for _ in 0..10 {
+            assert_eq!(quality.record(0), None);
+        }
+
+        let mut fixed = AdaptiveQuality::new(&QualityConfig {
+            degrade_after: 0,
+            ..config
+        });
+        for _ in 0..10 {
+            assert_eq!(fixed.record(5), None);
+        }
+        assert_eq!(fixed.scale(), 1.0);
+    }
+}
@@ -0,0 +1,731 @@
+use crate::colour_correction::{linear_to_srgb, srgb_to_linear};
+use crate::renderer::Renderer;
+use crate::{
+    AuxiliaryBuffers, Background, CameraPose, Colour, ColourCorrection, Cube, Position,
+    SceneTransform, Shape,
+};
+use image::RgbImage;
+use std::collections::HashMap;
+
+// CPU path tracer for beauty shots. Much slower than the GL renderers, but
+// renders soft shadows and ambient occlusion. The scene is traced directly on
+// the voxel grid, where the cube at position p spans [p - 0.5, p + 0.5], seen
+// from the same point of view as kiss3d's default camera, see SceneTransform.
+#[derive(Clone)]
+pub struct Raytracer {
+    size: u32,
+    frame_side_len: u32,
+    samples: u32,
+    // Turn of the canvas, see Renderer::set_yaw.
+    yaw: f32,
+    // Replaces the overlay's camera and its turn, e.g. along a camera path.
+    camera: Option<CameraPose>,
+    // Applied to the colours of the cubes, not to the background.
+    correction: ColourCorrection,
+    background: Background,
+    cubes: HashMap<Position, Colour>,
+}
+
+type Vec3 = [f32; 3];
+
+struct Hit {
+    position: Position,
+    distance: f32,
+    normal: Vec3,
+    colour: Colour,
+}
+
+// Smallest box of cells containing all the cubes.
+struct Bounds {
+    min: [i64; 3],
+    max: [i64; 3],
+}
+
+// Direction towards the light, from above and in front of the canvas.
+const LIGHT_DIRECTION: Vec3 = [-0.4, -1.0, 0.6];
+// Radius of the light seen from the scene, the larger the softer the shadows.
+const LIGHT_RADIUS: f32 = 0.08;
+const SUN_INTENSITY: f32 = 0.8;
+const SKY_INTENSITY: f32 = 0.45;
+// Occluders further than this, in cubes, don't darken a face.
+const AMBIENT_OCCLUSION_DISTANCE: f32 = 6.0;
+// Light left behind translucent cubes under which the cubes further away
+// aren't traced.
+const MIN_TRANSMITTANCE: f32 = 0.01;
+// Light given off by the glowing cubes, as a share of their colour, on top of
+// the light they're lit with.
+const EMISSION: f32 = 1.0;
+
+impl Raytracer {
+    pub fn new(size: u32, frame_side_len: u32, samples: u32) -> Self {
+        Self {
+            size,
+            frame_side_len,
+            samples: samples.max(1),
+            yaw: 0.0,
+            camera: None,
+            correction: ColourCorrection::default(),
+            background: Background::default(),
+            cubes: HashMap::new(),
+        }
+    }
+
+    pub fn set_colour_correction(&mut self, correction: ColourCorrection) {
+        self.correction = correction;
+    }
+
+    /// Changes the side of the rendered images, in pixels.
+    pub fn set_size(&mut self, size: u32) {
+        self.size = size;
+    }
+
+    /// Camera the canvas is seen from, see `set_camera`.
+    pub fn camera(&self) -> CameraPose {
+        match self.camera {
+            Some(camera) => camera,
+            None => SceneTransform::new(self.frame_side_len).camera(self.yaw),
+        }
+    }
+
+    pub fn render_image(&self) -> RgbImage {
+        self.render_with_depth().0
+    }
+
+    /// Renders the image along with the distance from the camera of what is
+    /// seen in each pixel, infinite for the background.
+    pub fn render_with_depth(&self) -> (RgbImage, Vec<f32>) {
+        let size = self.size as usize;
+        let mut pixels = vec![0u8; size * size * 3];
+        let mut depth = vec![f32::INFINITY; size * size];
+        let bounds = match self.bounds() {
+            Some(bounds) => bounds,
+            None => {
+                let img = RgbImage::from_fn(self.size, self.size, |x, y| {
+                    let i = (y * self.size + x) as usize;
+                    let v = (y as f32 + 0.5) / self.size as f32;
+                    let Colour { r, g, b, .. } =
+                        self.background.in_direction(self.centre_ray(i).1, v);
+                    image::Rgb([r, g, b])
+                });
+                return (img, depth);
+            }
+        };
+
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let rows_per_thread = size.div_ceil(threads).max(1);
+        std::thread::scope(|scope| {
+            let chunks = pixels
+                .chunks_mut(rows_per_thread * size * 3)
+                .zip(depth.chunks_mut(rows_per_thread * size));
+            for (chunk_index, (chunk, depth_chunk)) in chunks.enumerate() {
+                let bounds = &bounds;
+                scope.spawn(move || {
+                    let mut rng = fastrand::Rng::with_seed(chunk_index as u64);
+                    let first_row = chunk_index * rows_per_thread;
+                    for (i, (pixel, d)) in chunk.chunks_mut(3).zip(depth_chunk).enumerate() {
+                        let (x, y) = (i % size, first_row + i / size);
+                        let (colour, distance) = self.trace_pixel(x, y, bounds, &mut rng);
+                        pixel.copy_from_slice(&colour);
+                        *d = distance;
+                    }
+                });
+            }
+        });
+        let img =
+            RgbImage::from_raw(self.size, self.size, pixels).expect("buffer matches image size");
+        (img, depth)
+    }
+
+    /// Traces the ray through the centre of each pixel to what it hits
+    /// first, without shading, for the auxiliary buffers of snapshots.
+    pub fn render_auxiliary(&self) -> AuxiliaryBuffers {
+        let pixels = (self.size * self.size) as usize;
+        let mut buffers = AuxiliaryBuffers {
+            size: self.size,
+            side_len: self.frame_side_len,
+            depth: vec![f32::INFINITY; pixels],
+            normals: vec![[0.0; 3]; pixels],
+            cubes: vec![None; pixels],
+        };
+        let bounds = match self.bounds() {
+            Some(bounds) => bounds,
+            None => return buffers,
+        };
+        for i in 0..pixels {
+            let (eye, dir) = self.centre_ray(i);
+            if let Some(hit) = self.trace(eye, dir, &bounds, f32::INFINITY) {
+                buffers.depth[i] = hit.distance;
+                buffers.normals[i] = hit.normal;
+                buffers.cubes[i] = Some(hit.position);
+            }
+        }
+        buffers
+    }
+
+    /// How much of each pixel shows glowing cubes, from 0 to 1, through the
+    /// translucent cubes in front of them. Row by row, as the glow of the
+    /// post-processing expects.
+    pub fn render_emission(&self) -> Vec<f32> {
+        let pixels = (self.size * self.size) as usize;
+        let mut emission = vec![0.0; pixels];
+        let bounds = match self.bounds() {
+            Some(bounds) => bounds,
+            None => return emission,
+        };
+        for (i, glow) in emission.iter_mut().enumerate() {
+            let (mut origin, dir) = self.centre_ray(i);
+            let mut transmittance = 1.0;
+            while transmittance > MIN_TRANSMITTANCE {
+                let hit = match self.trace(origin, dir, &bounds, f32::INFINITY) {
+                    Some(hit) => hit,
+                    None => break,
+                };
+                let opacity = opacity(hit.colour);
+                if hit.colour.emissive {
+                    *glow += transmittance * opacity;
+                }
+                transmittance *= 1.0 - opacity;
+                let point = add(origin, scale(dir, hit.distance));
+                let behind = hit.distance + exit_distance(hit.position, point, dir) + 1e-3;
+                origin = add(origin, scale(dir, behind));
+            }
+        }
+        emission
+    }
+
+    // Ray through the centre of the `i`th pixel, row by row.
+    fn centre_ray(&self, i: usize) -> (Vec3, Vec3) {
+        let (x, y) = (i as u32 % self.size, i as u32 / self.size);
+        let (u, v) = (
+            (x as f32 + 0.5) / self.size as f32,
+            (y as f32 + 0.5) / self.size as f32,
+        );
+        let (eye, dir) = match &self.camera {
+            Some(camera) => camera.ray(u, v),
+            None => SceneTransform::new(self.frame_side_len).ray(u, v, self.yaw),
+        };
+        (eye, normalize(dir))
+    }
+
+    fn bounds(&self) -> Option<Bounds> {
+        let mut positions = self.cubes.keys();
+        let first = positions.next()?;
+        let mut bounds = Bounds {
+            min: [first.x as i64, first.y as i64, first.z as i64],
+            max: [first.x as i64, first.y as i64, first.z as i64],
+        };
+        for p in positions {
+            for (axis, &v) in [p.x as i64, p.y as i64, p.z as i64].iter().enumerate() {
+                bounds.min[axis] = bounds.min[axis].min(v);
+                bounds.max[axis] = bounds.max[axis].max(v);
+            }
+        }
+        Some(bounds)
+    }
+
+    // Returns the colour of the pixel and the mean distance of the hits.
+    fn trace_pixel(
+        &self,
+        x: usize,
+        y: usize,
+        bounds: &Bounds,
+        rng: &mut fastrand::Rng,
+    ) -> ([u8; 3], f32) {
+        let transform = SceneTransform::new(self.frame_side_len);
+        let mut total = [0.0; 3];
+        let mut hits = 0;
+        let mut total_distance = 0.0;
+        for _ in 0..self.samples {
+            let (u, v) = (
+                (x as f32 + rng.f32()) / self.size as f32,
+                (y as f32 + rng.f32()) / self.size as f32,
+            );
+            let (eye, dir) = match &self.camera {
+                Some(camera) => camera.ray(u, v),
+                None => transform.ray(u, v, self.yaw),
+            };
+            let dir = normalize(dir);
+            let (r, g, b) = self.background.in_direction(dir, v).to_f32();
+            let background = [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b)];
+            let (colour, distance) = self.composite(eye, dir, bounds, background, rng);
+            if let Some(distance) = distance {
+                hits += 1;
+                total_distance += distance;
+            }
+            total = add(total, colour);
+        }
+        let mean = scale(total, 1.0 / self.samples as f32);
+        let distance = if hits > 0 {
+            total_distance / hits as f32
+        } else {
+            f32::INFINITY
+        };
+        (
+            [encode(mean[0]), encode(mean[1]), encode(mean[2])],
+            distance,
+        )
+    }
+
+    // Colour seen along the ray, and the distance of the first cube hit. The
+    // cubes crossed are blended front to back, each translucent cube letting
+    // through the light from behind it as much as it's transparent.
+    fn composite(
+        &self,
+        eye: Vec3,
+        dir: Vec3,
+        bounds: &Bounds,
+        background: Vec3,
+        rng: &mut fastrand::Rng,
+    ) -> (Vec3, Option<f32>) {
+        let mut colour = [0.0; 3];
+        let mut transmittance = 1.0;
+        let mut first = None;
+        let (mut origin, mut travelled) = (eye, 0.0);
+        while transmittance > MIN_TRANSMITTANCE {
+            let hit = match self.trace(origin, dir, bounds, f32::INFINITY) {
+                Some(hit) => hit,
+                None => return (add(colour, scale(background, transmittance)), first),
+            };
+            first.get_or_insert(travelled + hit.distance);
+            let point = add(origin, scale(dir, hit.distance));
+            let opacity = opacity(hit.colour);
+            let shaded = self.shade(&hit, point, bounds, rng);
+            colour = add(colour, scale(shaded, transmittance * opacity));
+            transmittance *= 1.0 - opacity;
+            // Carries on from just behind the cube.
+            let behind = hit.distance + exit_distance(hit.position, point, dir) + 1e-3;
+            origin = add(origin, scale(dir, behind));
+            travelled += behind;
+        }
+        (colour, first)
+    }
+
+    // Share of the light going from `origin` along `dir` which gets through
+    // the cubes within `max_distance`, 0 behind an opaque cube.
+    fn transmittance(&self, origin: Vec3, dir: Vec3, bounds: &Bounds, max_distance: f32) -> f32 {
+        let mut transmittance = 1.0;
+        let (mut origin, mut travelled) = (origin, 0.0);
+        while transmittance > MIN_TRANSMITTANCE {
+            let hit = match self.trace(origin, dir, bounds, max_distance - travelled) {
+                Some(hit) => hit,
+                None => return transmittance,
+            };
+            transmittance *= 1.0 - opacity(hit.colour);
+            let point = add(origin, scale(dir, hit.distance));
+            let behind = hit.distance + exit_distance(hit.position, point, dir) + 1e-3;
+            origin = add(origin, scale(dir, behind));
+            travelled += behind;
+        }
+        0.0
+    }
+
+    fn shade(&self, hit: &Hit, point: Vec3, bounds: &Bounds, rng: &mut fastrand::Rng) -> Vec3 {
+        let albedo = self.correction.linear(hit.colour);
+        let origin = add(point, scale(hit.normal, 1e-3));
+
+        // Soft shadows: aim at a random point of the light's disc.
+        let light = normalize(add(
+            normalize(LIGHT_DIRECTION),
+            scale(random_unit_vector(rng), LIGHT_RADIUS),
+        ));
+        let n_dot_l = dot(hit.normal, light);
+        let direct = if n_dot_l > 0.0 {
+            n_dot_l * self.transmittance(origin, light, bounds, f32::INFINITY)
+        } else {
+            0.0
+        };
+
+        // Ambient occlusion: probe the hemisphere for nearby cubes, the
+        // translucent ones only occluding as much as they're opaque.
+        let probe = cosine_weighted_direction(hit.normal, rng);
+        let ambient = self.transmittance(origin, probe, bounds, AMBIENT_OCCLUSION_DISTANCE);
+
+        let lit = scale(albedo, SUN_INTENSITY * direct + SKY_INTENSITY * ambient);
+        if hit.colour.emissive {
+            add(lit, scale(albedo, EMISSION))
+        } else {
+            lit
+        }
+    }
+
+    // Walks the grid cells crossed by the ray (Amanatides & Woo) until a cube
+    // is found, the ray leaves the bounds, or `max_distance` is reached.
+    fn trace(&self, origin: Vec3, dir: Vec3, bounds: &Bounds, max_distance: f32) -> Option<Hit> {
+        let mut t_enter = 0.0f32;
+        let mut t_exit = max_distance;
+        let mut normal = [0.0; 3];
+        for axis in 0..3 {
+            let low = bounds.min[axis] as f32 - 0.5;
+            let high = bounds.max[axis] as f32 + 0.5;
+            if dir[axis] == 0.0 {
+                if origin[axis] < low || origin[axis] > high {
+                    return None;
+                }
+                continue;
+            }
+            let (mut t0, mut t1) = (
+                (low - origin[axis]) / dir[axis],
+                (high - origin[axis]) / dir[axis],
+            );
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > t_enter {
+                t_enter = t0;
+                normal = [0.0; 3];
+                normal[axis] = -dir[axis].signum();
+            }
+            t_exit = t_exit.min(t1);
+        }
+        if t_enter > t_exit {
+            return None;
+        }
+
+        let start = add(origin, scale(dir, t_enter));
+        let mut cell = [0i64; 3];
+        let mut step = [0i64; 3];
+        let mut t_max = [f32::INFINITY; 3];
+        let mut t_delta = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            cell[axis] =
+                ((start[axis] + 0.5).floor() as i64).clamp(bounds.min[axis], bounds.max[axis]);
+            if dir[axis] != 0.0 {
+                step[axis] = dir[axis].signum() as i64;
+                let boundary = cell[axis] as f32 + 0.5 * step[axis] as f32;
+                t_max[axis] = t_enter + (boundary - start[axis]) / dir[axis];
+                t_delta[axis] = (1.0 / dir[axis]).abs();
+            }
+        }
+
+        let mut t = t_enter;
+        loop {
+            if cell.iter().all(|&c| c >= 0) {
+                let position = Position::new(cell[0] as u32, cell[1] as u32, cell[2] as u32);
+                match self.cubes.get(&position) {
+                    Some(&colour) if colour.shape.is_cube() => {
+                        return Some(Hit {
+                            position,
+                            distance: t,
+                            normal,
+                            colour,
+                        })
+                    }
+                    // Other shapes only fill part of the cell, the ray may
+                    // go past them.
+                    Some(&colour) => match intersect_shape(origin, dir, position, colour.shape) {
+                        Some((distance, normal)) if distance <= t_exit => {
+                            return Some(Hit {
+                                position,
+                                distance,
+                                normal,
+                                colour,
+                            })
+                        }
+                        _ => {}
+                    },
+                    None => {}
+                }
+            }
+            let axis = (0..3)
+                .min_by(|&a, &b| t_max[a].partial_cmp(&t_max[b]).unwrap())
+                .unwrap();
+            t = t_max[axis];
+            if t > t_exit {
+                return None;
+            }
+            cell[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+            normal = [0.0; 3];
+            normal[axis] = -step[axis] as f32;
+        }
+    }
+}
+
+impl Renderer for Raytracer {
+    fn add_cube(&mut self, cube: &Cube) {
+        self.cubes.insert(cube.position, cube.colour);
+    }
+
+    fn remove_cube(&mut self, position: Position) {
+        self.cubes.remove(&position);
+    }
+
+    fn clear(&mut self) {
+        self.cubes.clear();
+    }
+
+    fn set_yaw(&mut self, yaw: f32) {
+        self.yaw = yaw;
+    }
+
+    fn set_camera(&mut self, camera: Option<CameraPose>) {
+        self.camera = camera;
+    }
+
+    fn set_background(&mut self, background: &Background) {
+        self.background = background.clone();
+    }
+
+    fn render(&mut self) -> Option<RgbImage> {
+        Some(self.render_image())
+    }
+}
+
+fn add(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn scale(a: Vec3, s: f32) -> Vec3 {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn normalize(a: Vec3) -> Vec3 {
+    scale(a, 1.0 / dot(a, a).sqrt())
+}
+
+fn random_unit_vector(rng: &mut fastrand::Rng) -> Vec3 {
+    let z = rng.f32() * 2.0 - 1.0;
+    let phi = rng.f32() * 2.0 * std::f32::consts::PI;
+    let r = (1.0 - z * z).sqrt();
+    [r * phi.cos(), r * phi.sin(), z]
+}
+
+fn cosine_weighted_direction(normal: Vec3, rng: &mut fastrand::Rng) -> Vec3 {
+    // Normals are always axis aligned, so any other axis completes the basis.
+    let helper = if normal[0].abs() > 0.5 {
+        [0.0, 1.0, 0.0]
+    } else {
+        [1.0, 0.0, 0.0]
+    };
+    let tangent = normalize([
+        helper[1] * normal[2] - helper[2] * normal[1],
+        helper[2] * normal[0] - helper[0] * normal[2],
+        helper[0] * normal[1] - helper[1] * normal[0],
+    ]);
+    let bitangent = [
+        normal[1] * tangent[2] - normal[2] * tangent[1],
+        normal[2] * tangent[0] - normal[0] * tangent[2],
+        normal[0] * tangent[1] - normal[1] * tangent[0],
+    ];
+    let phi = rng.f32() * 2.0 * std::f32::consts::PI;
+    let r2 = rng.f32();
+    let r = r2.sqrt();
+    add(
+        add(
+            scale(tangent, r * phi.cos()),
+            scale(bitangent, r * phi.sin()),
+        ),
+        scale(normal, (1.0 - r2).sqrt()),
+    )
+}
+
+// Distance along `dir` from `origin` to where the ray enters `shape` in the
+// cell at `position`, and the normal there. `None` when it misses the shape,
+// or starts inside of it.
+fn intersect_shape(
+    origin: Vec3,
+    dir: Vec3,
+    position: Position,
+    shape: Shape,
+) -> Option<(f32, Vec3)> {
+    let centre = [position.x as f32, position.y as f32, position.z as f32];
+    if shape == Shape::Sphere {
+        let to_centre = [
+            centre[0] - origin[0],
+            centre[1] - origin[1],
+            centre[2] - origin[2],
+        ];
+        let along = dot(to_centre, dir);
+        let squared = dot(to_centre, to_centre) - along * along;
+        let radius = Shape::SPHERE_RADIUS;
+        if squared > radius * radius {
+            return None;
+        }
+        let distance = along - (radius * radius - squared).sqrt();
+        if distance < 0.0 {
+            return None;
+        }
+        let point = add(origin, scale(dir, distance));
+        let normal = [
+            (point[0] - centre[0]) / radius,
+            (point[1] - centre[1]) / radius,
+            (point[2] - centre[2]) / radius,
+        ];
+        return Some((distance, normal));
+    }
+    let (low, high) = shape.bounds();
+    let (mut t_enter, mut t_exit) = (0.0f32, f32::INFINITY);
+    let mut normal = [0.0; 3];
+    for axis in 0..3 {
+        let (low, high) = (centre[axis] + low[axis], centre[axis] + high[axis]);
+        if dir[axis] == 0.0 {
+            if origin[axis] < low || origin[axis] > high {
+                return None;
+            }
+            continue;
+        }
+        let (mut t0, mut t1) = (
+            (low - origin[axis]) / dir[axis],
+            (high - origin[axis]) / dir[axis],
+        );
+        if t0 > t1 {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+        if t0 > t_enter {
+            t_enter = t0;
+            normal = [0.0; 3];
+            normal[axis] = -dir[axis].signum();
+        }
+        t_exit = t_exit.min(t1);
+    }
+    // Starting inside, the normal is unknown.
+    match t_enter <= t_exit && normal != [0.0; 3] {
+        true => Some((t_enter, normal)),
+        false => None,
+    }
+}
+
+fn opacity(colour: Colour) -> f32 {
+    colour.a as f32 / 255.0
+}
+
+// Distance along `dir` from `point`, inside or on the cube at `position`, to
+// where the ray leaves the cube.
+fn exit_distance(position: Position, point: Vec3, dir: Vec3) -> f32 {
+    let centre = [position.x as f32, position.y as f32, position.z as f32];
+    (0..3)
+        .filter(|&axis| dir[axis] != 0.0)
+        .map(|axis| (centre[axis] + 0.5 * dir[axis].signum() - point[axis]) / dir[axis])
+        .fold(f32::INFINITY, f32::min)
+        .max(0.0)
+}
+
+// Linear light to an sRGB channel of the image.
+fn encode(c: f32) -> u8 {
+    (linear_to_srgb(c) * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_trace_hits_closest_cube() {
+        let mut raytracer = Raytracer::new(1, 10, 1);
+        for z in 2..5 {
+            raytracer.add_cube(&Cube::new(1, 1, z, Colour::new(z as u8, 0, 0)));
+        }
+        let bounds = raytracer.bounds().unwrap();
+        let hit = raytracer
+            .trace([1.0, 1.0, 9.0], [0.0, 0.0, -1.0], &bounds, f32::INFINITY)
+            .unwrap();
+        assert_eq!(hit.colour, Colour::new(4, 0, 0));
+        assert_eq!(hit.normal, [0.0, 0.0, 1.0]);
+        assert!((hit.distance - 4.5).abs() < 1e-5);
+        assert!(raytracer
+            .trace([1.0, 1.0, 9.0], [0.0, 0.0, -1.0], &bounds, 4.0)
+            .is_none());
+    }
+
+    #[test]
+    fn test_trace_shapes() {
+        let mut raytracer = Raytracer::new(1, 10, 1);
+        let slab = Colour::new(1, 0, 0).with_shape(Shape::Slab);
+        let sphere = Colour::new(2, 0, 0).with_shape(Shape::Sphere);
+        raytracer.add_cube(&Cube::new(1, 1, 4, slab));
+        raytracer.add_cube(&Cube::new(1, 1, 3, sphere));
+        raytracer.add_cube(&Cube::new(1, 1, 2, Colour::new(3, 0, 0)));
+        let bounds = raytracer.bounds().unwrap();
+        let trace = |origin| {
+            raytracer
+                .trace(origin, [0.0, 0.0, -1.0], &bounds, f32::INFINITY)
+                .unwrap()
+        };
+        // Through the lower half of the cell, the slab's front face.
+        let hit = trace([1.0, 1.25, 9.0]);
+        assert_eq!(hit.colour, slab);
+        assert_eq!(hit.normal, [0.0, 0.0, 1.0]);
+        assert!((hit.distance - 4.5).abs() < 1e-5);
+        // Over the slab, the front of the sphere.
+        let hit = trace([1.0, 0.75, 9.0]);
+        assert_eq!(hit.colour, sphere);
+        let depth = (Shape::SPHERE_RADIUS.powi(2) - 0.25f32.powi(2)).sqrt();
+        assert!((hit.distance - (6.0 - depth)).abs() < 1e-5);
+        assert!(hit.normal[1] < 0.0);
+        // Over the slab and beside the sphere, the cube behind them.
+        let hit = trace([1.45, 0.6, 9.0]);
+        assert_eq!(hit.colour, Colour::new(3, 0, 0));
+        assert!((hit.distance - 6.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_translucent_cubes() {
+        let mut raytracer = Raytracer::new(1, 10, 1);
+        let glass = Colour::new(0, 0, 255).with_alpha(51);
+        raytracer.add_cube(&Cube::new(1, 1, 4, glass));
+        raytracer.add_cube(&Cube::new(1, 1, 3, glass));
+        raytracer.add_cube(&Cube::new(1, 1, 2, Colour::new(255, 0, 0)));
+        let bounds = raytracer.bounds().unwrap();
+        let (eye, dir) = ([1.0, 1.0, 9.0], [0.0, 0.0, -1.0]);
+        // Through the first glass cube, then the second, then stopped.
+        let transmittance = |max| raytracer.transmittance(eye, dir, &bounds, max);
+        assert!((transmittance(5.0) - 0.8).abs() < 1e-5);
+        assert!((transmittance(6.0) - 0.64).abs() < 1e-5);
+        assert_eq!(transmittance(f32::INFINITY), 0.0);
+
+        // A single glass cube lets through most of the background, and none
+        // of its red.
+        raytracer.remove_cube(Position::new(1, 1, 2));
+        raytracer.remove_cube(Position::new(1, 1, 3));
+        let bounds = raytracer.bounds().unwrap();
+        let background = [0.5; 3];
+        let mut rng = fastrand::Rng::with_seed(1);
+        let (colour, distance) = raytracer.composite(eye, dir, &bounds, background, &mut rng);
+        assert!((distance.unwrap() - 4.5).abs() < 1e-5);
+        assert!((colour[0] - 0.4).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_render_auxiliary() {
+        let mut raytracer = Raytracer::new(9, 9, 1);
+        raytracer.add_cube(&Cube::new(4, 4, 4, Colour::new(255, 0, 0)));
+        let buffers = raytracer.render_auxiliary();
+        // The cube faces the camera in the middle of the image.
+        assert_eq!(buffers.cubes[4 * 9 + 4], Some(Position::new(4, 4, 4)));
+        assert_eq!(buffers.normals[4 * 9 + 4], [0.0, 0.0, 1.0]);
+        assert!(buffers.depth[4 * 9 + 4].is_finite());
+        assert_eq!(buffers.cubes[0], None);
+        assert_eq!(buffers.depth[0], f32::INFINITY);
+    }
+
+    #[test]
+    fn test_render_emission() {
+        let mut raytracer = Raytracer::new(9, 9, 1);
+        let glowing = Colour::new(255, 0, 0).with_emissive(true);
+        raytracer.add_cube(&Cube::new(4, 4, 4, glowing));
+        let emission = raytracer.render_emission();
+        assert_eq!(emission[4 * 9 + 4], 1.0);
+        assert_eq!(emission[0], 0.0);
+        // Lit by itself, even on the faces in the shade.
+        let bounds = raytracer.bounds().unwrap();
+        let hit = raytracer
+            .trace([4.0, 4.0, 9.0], [0.0, 0.0, -1.0], &bounds, f32::INFINITY)
+            .unwrap();
+        let mut rng = fastrand::Rng::with_seed(1);
+        let shaded = raytracer.shade(&hit, [4.0, 4.0, 4.5], &bounds, &mut rng);
+        assert!(shaded[0] >= EMISSION);
+        // Seen through a glass cube, half as bright.
+        let glass = Colour::new(0, 0, 255).with_alpha(128);
+        raytracer.add_cube(&Cube::new(4, 4, 5, glass));
+        assert!((raytracer.render_emission()[4 * 9 + 4] - 0.498).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_empty_canvas_is_background() {
+        let img = Raytracer::new(4, 10, 1).render_image();
+        assert!(img.pixels().all(|p| p.0 == [250, 250, 250]));
+    }
+}
@@ -0,0 +1,60 @@
+use crate::{Background, CameraPose, CanvasEvent, Cube, Position, Region};
+use image::RgbImage;
+
+/// A backend able to draw the canvas into an image.
+pub trait Renderer {
+    /// Adds a cube to the scene, replacing any cube at the same position.
+    fn add_cube(&mut self, cube: &Cube);
+
+    /// Removes the cube at `position`, if any.
+    fn remove_cube(&mut self, position: Position);
+
+    /// Removes all the cubes from the scene.
+    fn clear(&mut self);
+
+    /// Mirrors an event already applied to the canvas.
+    fn apply_event(&mut self, event: &CanvasEvent) {
+        match event {
+            CanvasEvent::CubePlaced(cube) => self.add_cube(cube),
+            CanvasEvent::CubeRemoved(position) => self.remove_cube(*position),
+            CanvasEvent::CanvasCleared => self.clear(),
+            CanvasEvent::Recoloured { position, colour } => self.add_cube(&Cube {
+                position: *position,
+                colour: *colour,
+            }),
+        }
+    }
+
+    /// Greys out the locked volume outside of `unlocked`, `None` when the
+    /// whole canvas can be built on. Backends may not show it.
+    fn set_fog(&mut self, _unlocked: Option<Region>) {}
+
+    /// Turns the scene by `yaw` radians around its vertical axis, to orbit
+    /// around the canvas. Backends may not show it.
+    fn set_yaw(&mut self, _yaw: f32) {}
+
+    /// Views the canvas from `camera` instead of the default point of view
+    /// and its yaw, `None` goes back to them. Backends may not show it.
+    fn set_camera(&mut self, _camera: Option<CameraPose>) {}
+
+    /// Draws `background` behind the canvas. Backends may only show a single
+    /// colour of it, see Background::clear_colour.
+    fn set_background(&mut self, _background: &Background) {}
+
+    /// Cube under the cursor, for backends drawing in a window.
+    fn hovered(&self) -> Option<Position> {
+        None
+    }
+
+    /// Shows the text of `label` above the cube at its position, e.g. who
+    /// placed it, `None` hides it. Backends may not show it.
+    fn set_label(&mut self, _label: Option<(Position, String)>) {}
+
+    /// Shows the cubes removed or overwritten lately as ghosts, each with an
+    /// opacity between 0 and 1, replacing the previous ones. Backends may not
+    /// show them.
+    fn set_ghosts(&mut self, _ghosts: &[(Cube, f32)]) {}
+
+    /// Draws the scene, returns `None` if the frame could not be captured.
+    fn render(&mut self) -> Option<RgbImage>;
+}
@@ -0,0 +1,108 @@
+use crate::{CubeArchive, CubeArchiveError};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ReplicaError {
+    #[error("error from the archive {0}")]
+    Archive(#[from] CubeArchiveError),
+    #[error("error from the copy of the archive {0}")]
+    Io(#[from] std::io::Error),
+}
+
+// Copy of the archive the readers are on, and the file it's in.
+struct Snapshot {
+    archive: CubeArchive,
+    path: PathBuf,
+}
+
+/// Copy of the archive for readers which must never hold up the journal,
+/// e.g. the web viewer under heavy traffic. Refreshed by `refresh` from a
+/// connection of its own to the archive, readers keep reading the previous
+/// copy meanwhile. Cloning the replica gives another handle to the same copy.
+#[derive(Clone)]
+pub struct ArchiveReplica {
+    source: Arc<Mutex<CubeArchive>>,
+    dir: Arc<TempDir>,
+    snapshot: Arc<Mutex<Snapshot>>,
+    // Copies made so far, to name the next one.
+    copies: Arc<AtomicU64>,
+}
+
+impl ArchiveReplica {
+    /// Copies `archive` to a temporary directory.
+    pub fn new(archive: &CubeArchive) -> Result<Self, ReplicaError> {
+        let mut source = archive.reopen();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("replica-0.sqlite");
+        source.backup(&path)?;
+        let snapshot = Snapshot {
+            archive: CubeArchive::new(path.clone()),
+            path,
+        };
+        Ok(Self {
+            source: Arc::new(Mutex::new(source)),
+            dir: Arc::new(dir),
+            snapshot: Arc::new(Mutex::new(snapshot)),
+            copies: Arc::new(AtomicU64::new(1)),
+        })
+    }
+
+    /// Copies the archive again, and moves the readers to the new copy once
+    /// it's complete.
+    pub fn refresh(&self) -> Result<(), ReplicaError> {
+        let copy = self.copies.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.path().join(format!("replica-{}.sqlite", copy));
+        self.source.lock().unwrap().backup(&path)?;
+        let mut archive = CubeArchive::new(path.clone());
+        // Opened here rather than by the first reader.
+        archive.init()?;
+        let previous = std::mem::replace(
+            &mut *self.snapshot.lock().unwrap(),
+            Snapshot { archive, path },
+        );
+        // Closed before its file goes.
+        drop(previous.archive);
+        std::fs::remove_file(previous.path)?;
+        Ok(())
+    }
+
+    /// Reads the copy with `read`, as of the last refresh.
+    pub fn read<T>(
+        &self,
+        read: impl FnOnce(&mut CubeArchive) -> Result<T, CubeArchiveError>,
+    ) -> Result<T, CubeArchiveError> {
+        read(&mut self.snapshot.lock().unwrap().archive)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colour, Cube};
+
+    #[test]
+    fn test_replica() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut archive = CubeArchive::new(dir.path().join("archive.sqlite"));
+        let cube = |x| Cube::new(x, 0, 0, Colour::new(1, 2, 3));
+        archive.add_cube(cube(0)).unwrap();
+        let replica = ArchiveReplica::new(&archive).unwrap();
+        archive.add_cube(cube(1)).unwrap();
+        // Still the copy made before the second cube.
+        assert_eq!(
+            replica.read(|copy| copy.get_cubes()).unwrap(),
+            vec![cube(0)]
+        );
+        replica.clone().refresh().unwrap();
+        assert_eq!(
+            replica.read(|copy| copy.get_cubes()).unwrap(),
+            vec![cube(0), cube(1)]
+        );
+        // Only the latest copy is kept.
+        assert_eq!(std::fs::read_dir(replica.dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,407 @@
+use crate::{CanvasEvent, ColourStats, JournalEntry, Palette};
+use plotters::prelude::*;
+use std::collections::HashMap;
+use std::fmt::{Display, Write};
+use thiserror::Error;
+
+// Colours plotted at most, the most placed ones.
+const MAX_COLOURS: usize = 24;
+const CHART_SIZE: (u32, u32) = (900, 320);
+
+#[derive(Error, Debug)]
+#[error("unable to plot the report: {0}")]
+pub struct ReportError(String);
+
+fn plot_error(e: impl Display) -> ReportError {
+    ReportError(e.to_string())
+}
+
+/// What a chatter did on the canvas over the period of the report.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuilderSummary {
+    pub login: String,
+    pub placed: u64,
+    pub removed: u64,
+    /// Unix timestamps of their first and last events.
+    pub first: i64,
+    pub last: i64,
+}
+
+/// Engagement of chat with the canvas, out of the archived journal, for
+/// streamers to look back on an event.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    since: i64,
+    bucket_secs: i64,
+    // Cubes placed in each bucket of `bucket_secs` from the first one with
+    // a placement, empty ones included.
+    placements: Vec<(i64, u64)>,
+    // Busiest first.
+    builders: Vec<BuilderSummary>,
+    colours: ColourStats,
+}
+
+impl Report {
+    /// Sums up the events of `journal` since the unix timestamp `since`, the
+    /// placements counted by periods of `bucket_secs`, as well as how many
+    /// were in a colour of `palette`.
+    pub fn new(
+        journal: &[JournalEntry],
+        since: i64,
+        bucket_secs: i64,
+        palette: Option<&Palette>,
+    ) -> Self {
+        let bucket_secs = bucket_secs.max(1);
+        let mut buckets: HashMap<i64, u64> = HashMap::new();
+        let mut builders: HashMap<&str, BuilderSummary> = HashMap::new();
+        for entry in journal {
+            let timestamp = entry.metadata.timestamp;
+            if timestamp < since {
+                continue;
+            }
+            let (placed, removed) = match &entry.event {
+                CanvasEvent::CubePlaced(_) => {
+                    *buckets
+                        .entry(timestamp.div_euclid(bucket_secs) * bucket_secs)
+                        .or_default() += 1;
+                    (1, 0)
+                }
+                CanvasEvent::CubeRemoved(_) => (0, 1),
+                _ => continue,
+            };
+            let author = match entry.metadata.author.as_deref() {
+                Some(author) => author,
+                None => continue,
+            };
+            let builder = builders.entry(author).or_insert_with(|| BuilderSummary {
+                login: author.to_owned(),
+                placed: 0,
+                removed: 0,
+                first: timestamp,
+                last: timestamp,
+            });
+            builder.placed += placed;
+            builder.removed += removed;
+            builder.first = builder.first.min(timestamp);
+            builder.last = builder.last.max(timestamp);
+        }
+
+        let placements = match (buckets.keys().min(), buckets.keys().max()) {
+            (Some(&first), Some(&last)) => (0..=(last - first) / bucket_secs)
+                .map(|i| first + i * bucket_secs)
+                .map(|start| (start, buckets.get(&start).copied().unwrap_or(0)))
+                .collect(),
+            _ => Vec::new(),
+        };
+        let mut builders: Vec<_> = builders.into_values().collect();
+        builders.sort_by(|a, b| b.placed.cmp(&a.placed).then(a.login.cmp(&b.login)));
+        Self {
+            since,
+            bucket_secs,
+            placements,
+            builders,
+            colours: ColourStats::new(journal, since, bucket_secs, palette),
+        }
+    }
+
+    pub fn placements(&self) -> &[(i64, u64)] {
+        &self.placements
+    }
+
+    pub fn builders(&self) -> &[BuilderSummary] {
+        &self.builders
+    }
+
+    pub fn colours(&self) -> &ColourStats {
+        &self.colours
+    }
+
+    /// Page with the charts of the placements over time and of the colours,
+    /// the colours by name, the compliance with the palette if any, and the
+    /// table of the builders.
+    pub fn to_html(&self) -> Result<String, ReportError> {
+        let placed = self.colours.placed;
+        let mut html = "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+            <title>Canvas report</title><style>\
+            body { font-family: sans-serif; margin: 2em; }\
+            table { border-collapse: collapse; }\
+            th, td { padding: 4px 12px; border-bottom: 1px solid #ddd; text-align: right; }\
+            th:first-child, td:first-child { text-align: left; }\
+            </style></head><body>\n<h1>Canvas report</h1>\n"
+            .to_owned();
+        let _ = writeln!(
+            html,
+            "<p>{} cubes placed by {} builders since {}.</p>",
+            placed,
+            self.builders.len(),
+            format_time(self.since, "%Y-%m-%d %H:%M UTC")
+        );
+        html.push_str("<h2>Placements over time</h2>\n");
+        html.push_str(&self.placements_chart()?);
+        html.push_str("\n<h2>Colours</h2>\n");
+        html.push_str(&self.colours_chart()?);
+        html.push_str("\n<table><tr><th>Colour</th><th>Placed</th><th>Share</th></tr>\n");
+        for named in &self.colours.named {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{:.1}%</td></tr>",
+                named.name,
+                named.count,
+                named.count as f64 * 100.0 / placed as f64,
+            );
+        }
+        html.push_str("</table>\n");
+        if let Some(share) = self.colours.compliance_share() {
+            let _ = writeln!(
+                html,
+                "<h2>Palette</h2>\n<p>{:.1}% of the cubes in a colour of the palette.</p>",
+                share * 100.0
+            );
+            html.push_str(&self.compliance_chart()?);
+        }
+        html.push_str(
+            "\n<h2>Builders</h2>\n<table><tr><th>Builder</th><th>Placed</th>\
+             <th>Removed</th><th>Share</th><th>First</th><th>Last</th></tr>\n",
+        );
+        for builder in &self.builders {
+            let share = match placed {
+                0 => 0.0,
+                placed => builder.placed as f64 * 100.0 / placed as f64,
+            };
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td><td>{}</td><td>{}</td></tr>",
+                escape(&builder.login),
+                builder.placed,
+                builder.removed,
+                share,
+                format_time(builder.first, "%Y-%m-%d %H:%M"),
+                format_time(builder.last, "%Y-%m-%d %H:%M"),
+            );
+        }
+        html.push_str("</table>\n</body></html>\n");
+        Ok(html)
+    }
+
+    // Line of the cubes placed in each bucket, as SVG.
+    fn placements_chart(&self) -> Result<String, ReportError> {
+        let mut svg = String::new();
+        {
+            let root = SVGBackend::with_string(&mut svg, CHART_SIZE).into_drawing_area();
+            root.fill(&WHITE).map_err(plot_error)?;
+            let first = self
+                .placements
+                .first()
+                .map_or(self.since, |(start, _)| *start);
+            let last = self.placements.last().map_or(first, |(start, _)| *start);
+            let max = self.placements.iter().map(|(_, count)| *count).max();
+            let format = match self.bucket_secs % (24 * 3600) {
+                0 => "%m-%d",
+                _ => "%m-%d %H:%M",
+            };
+            let mut chart = ChartBuilder::on(&root)
+                .margin(10)
+                .x_label_area_size(30)
+                .y_label_area_size(50)
+                .build_cartesian_2d(first..last.max(first + 1), 0..max.unwrap_or(0).max(1))
+                .map_err(plot_error)?;
+            chart
+                .configure_mesh()
+                .x_labels(8)
+                .x_label_formatter(&|timestamp| format_time(*timestamp, format))
+                .y_desc("cubes")
+                .draw()
+                .map_err(plot_error)?;
+            chart
+                .draw_series(LineSeries::new(
+                    self.placements.iter().copied(),
+                    BLUE.stroke_width(2),
+                ))
+                .map_err(plot_error)?;
+            root.present().map_err(plot_error)?;
+        }
+        Ok(svg)
+    }
+
+    // Line of the share of the cubes placed in a colour of the palette in
+    // each bucket with placements, as SVG.
+    fn compliance_chart(&self) -> Result<String, ReportError> {
+        let compliance = &self.colours.compliance;
+        let mut svg = String::new();
+        {
+            let root = SVGBackend::with_string(&mut svg, CHART_SIZE).into_drawing_area();
+            root.fill(&WHITE).map_err(plot_error)?;
+            let first = compliance.first().map_or(self.since, |bucket| bucket.since);
+            let last = compliance.last().map_or(first, |bucket| bucket.since);
+            let mut chart = ChartBuilder::on(&root)
+                .margin(10)
+                .x_label_area_size(30)
+                .y_label_area_size(50)
+                .build_cartesian_2d(first..last.max(first + 1), 0.0..100.0)
+                .map_err(plot_error)?;
+            chart
+                .configure_mesh()
+                .x_labels(8)
+                .x_label_formatter(&|timestamp| format_time(*timestamp, "%m-%d %H:%M"))
+                .y_desc("% in the palette")
+                .draw()
+                .map_err(plot_error)?;
+            chart
+                .draw_series(LineSeries::new(
+                    compliance
+                        .iter()
+                        .filter(|bucket| bucket.placed > 0)
+                        .map(|bucket| {
+                            let share = bucket.in_palette as f64 * 100.0 / bucket.placed as f64;
+                            (bucket.since, share)
+                        }),
+                    GREEN.stroke_width(2),
+                ))
+                .map_err(plot_error)?;
+            root.present().map_err(plot_error)?;
+        }
+        Ok(svg)
+    }
+
+    // Bars of the most placed colours, each in its colour, as SVG.
+    fn colours_chart(&self) -> Result<String, ReportError> {
+        let colours = &self.colours.exact[..self.colours.exact.len().min(MAX_COLOURS)];
+        let mut svg = String::new();
+        {
+            let root = SVGBackend::with_string(&mut svg, CHART_SIZE).into_drawing_area();
+            root.fill(&WHITE).map_err(plot_error)?;
+            let max = colours.iter().map(|colour| colour.count).max();
+            let mut chart = ChartBuilder::on(&root)
+                .margin(10)
+                .x_label_area_size(30)
+                .y_label_area_size(50)
+                .build_cartesian_2d(
+                    (0..colours.len().max(1)).into_segmented(),
+                    0..max.unwrap_or(0).max(1),
+                )
+                .map_err(plot_error)?;
+            chart
+                .configure_mesh()
+                .disable_x_mesh()
+                .x_labels(colours.len().max(1))
+                .x_label_formatter(&|value| match value {
+                    SegmentValue::CenterOf(i) => colours
+                        .get(*i)
+                        .map(|colour| colour.colour.to_string())
+                        .unwrap_or_default(),
+                    _ => String::new(),
+                })
+                .y_desc("cubes")
+                .draw()
+                .map_err(plot_error)?;
+            chart
+                .draw_series(colours.iter().enumerate().map(|(i, colour)| {
+                    let fill = RGBColor(colour.colour.r, colour.colour.g, colour.colour.b).filled();
+                    Rectangle::new(
+                        [
+                            (SegmentValue::Exact(i), 0),
+                            (SegmentValue::Exact(i + 1), colour.count),
+                        ],
+                        fill,
+                    )
+                }))
+                .map_err(plot_error)?;
+            root.present().map_err(plot_error)?;
+        }
+        Ok(svg)
+    }
+}
+
+// `timestamp` in UTC, with the strftime `format`.
+fn format_time(timestamp: i64, format: &str) -> String {
+    chrono::NaiveDateTime::from_timestamp_opt(timestamp, 0)
+        .map(|time| time.format(format).to_string())
+        .unwrap_or_default()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colour, Cube, EventMetadata, Position};
+    use uuid::Uuid;
+
+    fn entry(event: CanvasEvent, author: Option<&str>, timestamp: i64) -> JournalEntry {
+        JournalEntry {
+            command_id: Uuid::new_v4(),
+            event,
+            metadata: EventMetadata {
+                timestamp,
+                author: author.map(str::to_owned),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn placed(x: u32, colour: Colour) -> CanvasEvent {
+        CanvasEvent::CubePlaced(Cube::new(x, 0, 0, colour))
+    }
+
+    #[test]
+    fn test_report() {
+        let red = Colour::new(255, 0, 0);
+        let blue = Colour::new(0, 0, 255);
+        let journal = vec![
+            entry(placed(0, red), Some("old"), 100),
+            entry(placed(1, red), Some("alice"), 3600),
+            entry(placed(2, blue), Some("bob"), 3700),
+            entry(placed(3, red), Some("alice"), 3 * 3600 + 5),
+            entry(
+                CanvasEvent::CubeRemoved(Position::new(2, 0, 0)),
+                Some("alice"),
+                3 * 3600 + 10,
+            ),
+            entry(placed(4, blue.with_alpha(128)), None, 3 * 3600 + 20),
+        ];
+        let palette = Palette::new(vec![red]).unwrap();
+        let report = Report::new(&journal, 3600, 3600, Some(&palette));
+        assert_eq!(report.placements(), &[(3600, 2), (7200, 0), (3 * 3600, 2)]);
+        let counts: Vec<_> = report
+            .colours()
+            .exact
+            .iter()
+            .map(|colour| (colour.colour, colour.count))
+            .collect();
+        assert_eq!(counts, vec![(blue, 2), (red, 2)]);
+        assert_eq!(
+            report.builders(),
+            &[
+                BuilderSummary {
+                    login: "alice".to_owned(),
+                    placed: 2,
+                    removed: 1,
+                    first: 3600,
+                    last: 3 * 3600 + 10,
+                },
+                BuilderSummary {
+                    login: "bob".to_owned(),
+                    placed: 1,
+                    removed: 0,
+                    first: 3700,
+                    last: 3700,
+                },
+            ]
+        );
+
+        let html = report.to_html().unwrap();
+        assert!(html.contains("4 cubes placed by 2 builders"));
+        assert!(html.contains("50.0% of the cubes in a colour of the palette"));
+        assert_eq!(html.matches("<svg").count(), 3);
+        assert_eq!(html.matches("<tr><td>").count(), 4);
+        // An empty period still makes a page.
+        let empty = Report::new(&journal, 10 * 3600, 3600, None);
+        assert!(empty.placements().is_empty());
+        assert!(empty.to_html().unwrap().contains("0 cubes placed"));
+    }
+}
@@ -0,0 +1,298 @@
+use crate::{Colour, Cube, Position};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ResizeError {
+    #[error("unknown strategy {0}, try crop, scale or recentre")]
+    UnknownStrategy(String),
+    #[error("the canvas must be at least one cube wide")]
+    EmptyCanvas,
+    #[error("scaling from {0} to {1} isn't by a whole factor, try crop or recentre")]
+    UnevenScale(u32, u32),
+}
+
+/// What happens to the cubes when the canvas changes size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResizeStrategy {
+    /// Cubes keep their position, those outside of the new canvas are
+    /// dropped.
+    Crop,
+    /// The build is scaled with the canvas, by a whole factor. Blocks of
+    /// cubes merge when it shrinks, and cubes are split into blocks when it
+    /// grows, see `Resample`.
+    Scale,
+    /// The build stays in the middle of the canvas, the cubes outside of the
+    /// new canvas are dropped.
+    Recentre,
+}
+
+impl FromStr for ResizeStrategy {
+    type Err = ResizeError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "crop" => Ok(ResizeStrategy::Crop),
+            "scale" => Ok(ResizeStrategy::Scale),
+            "recentre" | "recenter" => Ok(ResizeStrategy::Recentre),
+            _ => Err(ResizeError::UnknownStrategy(value.to_owned())),
+        }
+    }
+}
+
+/// Change of the side of the canvas, from `from` to `to` cubes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Resize {
+    from: u32,
+    to: u32,
+    strategy: ResizeStrategy,
+}
+
+impl Resize {
+    pub fn new(from: u32, to: u32, strategy: ResizeStrategy) -> Result<Self, ResizeError> {
+        if from == 0 || to == 0 {
+            return Err(ResizeError::EmptyCanvas);
+        }
+        if strategy == ResizeStrategy::Scale && !to.is_multiple_of(from) && !from.is_multiple_of(to)
+        {
+            return Err(ResizeError::UnevenScale(from, to));
+        }
+        Ok(Self { from, to, strategy })
+    }
+
+    /// How the build is resampled when scaled, `None` for the strategies
+    /// which only move the cubes, see `position`.
+    pub fn resample(&self) -> Option<Resample> {
+        match self.strategy {
+            ResizeStrategy::Scale if self.to >= self.from => {
+                Some(Resample::Up(self.to / self.from))
+            }
+            ResizeStrategy::Scale => {
+                Some(Resample::Down(self.from / self.to, MergeColours::Majority))
+            }
+            ResizeStrategy::Crop | ResizeStrategy::Recentre => None,
+        }
+    }
+
+    /// Where the cube at `position` goes, `None` if it's dropped. When
+    /// scaling, that's the corner of the block it becomes.
+    pub fn position(&self, position: Position) -> Option<Position> {
+        let (from, to) = (self.from as i64, self.to as i64);
+        let moved = |c: u32| match self.strategy {
+            ResizeStrategy::Crop => c as i64,
+            ResizeStrategy::Scale => c as i64 * to / from,
+            ResizeStrategy::Recentre => c as i64 + (to - from) / 2,
+        };
+        let (x, y, z) = (moved(position.x), moved(position.y), moved(position.z));
+        let within = |c: i64| (0..to).contains(&c);
+        match within(x) && within(y) && within(z) {
+            true => Some(Position::new(x as u32, y as u32, z as u32)),
+            false => None,
+        }
+    }
+}
+
+/// How the colours of the cubes merged when downsampling are combined.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergeColours {
+    /// The most common colour, the first cube's one on a tie.
+    Majority,
+    /// The mean of each channel.
+    Average,
+}
+
+/// Change of the resolution of a build, e.g. to switch between coarse and
+/// fine building.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resample {
+    /// Merges each block of `factor` cubes on each side into one, present if
+    /// any cube of the block is.
+    Down(u32, MergeColours),
+    /// Splits each cube into a block of `factor` cubes on each side.
+    Up(u32),
+}
+
+impl Resample {
+    /// The resampled cubes, sorted by position. Those which would overflow
+    /// the coordinates are left out.
+    pub fn apply(&self, cubes: &[Cube]) -> Vec<Cube> {
+        match *self {
+            Resample::Down(factor, merge) => {
+                let factor = factor.max(1);
+                let mut blocks: BTreeMap<Position, Vec<Colour>> = BTreeMap::new();
+                for cube in cubes {
+                    let p = cube.position;
+                    blocks
+                        .entry(Position::new(p.x / factor, p.y / factor, p.z / factor))
+                        .or_default()
+                        .push(cube.colour);
+                }
+                blocks
+                    .into_iter()
+                    .map(|(position, colours)| Cube {
+                        position,
+                        colour: merge.colour(&colours),
+                    })
+                    .collect()
+            }
+            Resample::Up(factor) => {
+                let factor = factor.max(1);
+                let mut resampled = Vec::new();
+                for cube in cubes {
+                    let p = cube.position;
+                    let corner = match (
+                        p.x.checked_mul(factor),
+                        p.y.checked_mul(factor),
+                        p.z.checked_mul(factor),
+                    ) {
+                        (Some(x), Some(y), Some(z)) => (x, y, z),
+                        _ => continue,
+                    };
+                    for (dx, dy, dz) in (0..factor).flat_map(|dx| {
+                        (0..factor).flat_map(move |dy| (0..factor).map(move |dz| (dx, dy, dz)))
+                    }) {
+                        if let (Some(x), Some(y), Some(z)) = (
+                            corner.0.checked_add(dx),
+                            corner.1.checked_add(dy),
+                            corner.2.checked_add(dz),
+                        ) {
+                            resampled.push(Cube::new(x, y, z, cube.colour));
+                        }
+                    }
+                }
+                resampled.sort_by_key(|cube| cube.position);
+                resampled
+            }
+        }
+    }
+}
+
+impl MergeColours {
+    // Colour of a block holding `colours`, never empty.
+    fn colour(&self, colours: &[Colour]) -> Colour {
+        match self {
+            MergeColours::Majority => {
+                let mut counts: Vec<(Colour, usize)> = Vec::new();
+                for colour in colours {
+                    match counts.iter_mut().find(|(c, _)| c == colour) {
+                        Some((_, count)) => *count += 1,
+                        None => counts.push((*colour, 1)),
+                    }
+                }
+                // max_by_key keeps the last of the equal counts.
+                counts
+                    .into_iter()
+                    .rev()
+                    .max_by_key(|(_, count)| *count)
+                    .map(|(colour, _)| colour)
+                    .unwrap_or_default()
+            }
+            MergeColours::Average => {
+                let n = colours.len().max(1) as u32;
+                let mean = |channel: fn(&Colour) -> u8| {
+                    let sum: u32 = colours.iter().map(|c| channel(c) as u32).sum();
+                    ((sum + n / 2) / n) as u8
+                };
+                Colour::new(mean(|c| c.r), mean(|c| c.g), mean(|c| c.b))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strategies() {
+        let position = Position::new(0, 50, 99);
+        let resize = |from, to, strategy| Resize::new(from, to, strategy).unwrap();
+        let crop = resize(100, 60, ResizeStrategy::Crop);
+        assert_eq!(
+            crop.position(Position::new(0, 50, 59)),
+            Some(Position::new(0, 50, 59))
+        );
+        assert_eq!(crop.position(position), None);
+        let scale = resize(100, 50, ResizeStrategy::Scale);
+        assert_eq!(scale.position(position), Some(Position::new(0, 25, 49)));
+        assert_eq!(
+            scale.resample(),
+            Some(Resample::Down(2, MergeColours::Majority))
+        );
+        let scale = resize(100, 200, ResizeStrategy::Scale);
+        assert_eq!(scale.position(position), Some(Position::new(0, 100, 198)));
+        assert_eq!(
+            Resize::new(100, 150, ResizeStrategy::Scale),
+            Err(ResizeError::UnevenScale(100, 150))
+        );
+        assert_eq!(crop.resample(), None);
+        let grow = resize(100, 200, ResizeStrategy::Recentre);
+        assert_eq!(grow.position(position), Some(Position::new(50, 100, 149)));
+        let shrink = resize(100, 60, ResizeStrategy::Recentre);
+        assert_eq!(
+            shrink.position(Position::new(20, 50, 79)),
+            Some(Position::new(0, 30, 59))
+        );
+        assert_eq!(shrink.position(position), None);
+        assert_eq!(
+            Resize::new(0, 10, ResizeStrategy::Crop),
+            Err(ResizeError::EmptyCanvas)
+        );
+        assert_eq!("Recenter".parse(), Ok(ResizeStrategy::Recentre));
+        assert!("stretch".parse::<ResizeStrategy>().is_err());
+    }
+
+    #[test]
+    fn test_resample() {
+        let (red, blue) = (Colour::new(255, 0, 0), Colour::new(0, 0, 255));
+        let cubes = vec![
+            Cube::new(0, 0, 0, blue),
+            Cube::new(0, 1, 0, red),
+            Cube::new(1, 1, 1, red),
+            Cube::new(2, 0, 0, blue),
+        ];
+        let majority = Resample::Down(2, MergeColours::Majority).apply(&cubes);
+        assert_eq!(
+            majority,
+            vec![Cube::new(0, 0, 0, red), Cube::new(1, 0, 0, blue)]
+        );
+        let average = Resample::Down(2, MergeColours::Average).apply(&cubes);
+        assert_eq!(average[0].colour, Colour::new(170, 0, 85));
+        // A tie keeps the first cube's colour.
+        let tie = Resample::Down(2, MergeColours::Majority).apply(&cubes[..2]);
+        assert_eq!(tie, vec![Cube::new(0, 0, 0, blue)]);
+
+        let up = Resample::Up(2).apply(&majority);
+        assert_eq!(up.len(), 16);
+        assert_eq!(up[0], Cube::new(0, 0, 0, red));
+        assert_eq!(up[15], Cube::new(3, 1, 1, blue));
+        assert_eq!(
+            Resample::Down(2, MergeColours::Majority).apply(&up),
+            majority
+        );
+        assert!(Resample::Up(2)
+            .apply(&[Cube::new(u32::MAX, 0, 0, red)])
+            .is_empty());
+    }
+
+    #[test]
+    fn test_scale_stays_solid() {
+        let red = Colour::new(255, 0, 0);
+        let block: Vec<Cube> = (0..8)
+            .map(|i| Cube::new(i >> 2, i >> 1 & 1, i & 1, red))
+            .collect();
+        let scale = Resize::new(2, 4, ResizeStrategy::Scale).unwrap();
+        let scaled = scale.resample().unwrap().apply(&block);
+        assert_eq!(scaled.len(), 64);
+        for x in 0..4 {
+            for y in 0..4 {
+                for z in 0..4 {
+                    assert!(scaled.contains(&Cube::new(x, y, z, red)));
+                }
+            }
+        }
+        let shrink = Resize::new(4, 2, ResizeStrategy::Scale).unwrap();
+        assert_eq!(shrink.resample().unwrap().apply(&scaled), block);
+    }
+}
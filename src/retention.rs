@@ -0,0 +1,173 @@
+use crate::{CanvasEvent, Position};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Events older than this many days are pruned down to the canvas they
+    /// left, every event is kept when unset.
+    pub keep_days: Option<u32>,
+    /// Events kept in the journal at most, the oldest pruned down to the
+    /// canvas they left. The cubes on the canvas are never pruned, so the
+    /// journal may stay above it.
+    pub max_events: Option<usize>,
+    /// Minutes between two prunings.
+    pub interval_mins: u64,
+    /// Only reports what would be pruned, leaving the journal as is.
+    pub dry_run: bool,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            keep_days: None,
+            max_events: None,
+            interval_mins: 60,
+            dry_run: false,
+        }
+    }
+}
+
+impl RetentionConfig {
+    /// Whether anything is ever pruned.
+    pub fn is_enabled(&self) -> bool {
+        self.keep_days.is_some() || self.max_events.is_some()
+    }
+
+    /// Unix timestamp the events journaled before are pruned, at `now`.
+    pub fn cutoff(&self, now: i64) -> Option<i64> {
+        self.keep_days.map(|days| now - days as i64 * 86400)
+    }
+}
+
+/// What a pruning of the journal did, or would do on a dry run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PruneReport {
+    /// Events journaled before the pruning.
+    pub events: usize,
+    pub pruned: usize,
+    /// Timestamp of the latest event pruned, if any.
+    pub until: Option<i64>,
+}
+
+// Events still making the canvas: the last placement at each position unless
+// removed or cleared since, and the last recolouring after it.
+#[derive(Default)]
+struct Live {
+    cubes: HashMap<Position, (i64, Option<i64>)>,
+    // Ids in `cubes`, placements and recolourings.
+    len: usize,
+}
+
+impl Live {
+    fn apply(&mut self, id: i64, event: &CanvasEvent) {
+        match event {
+            CanvasEvent::CubePlaced(cube) => {
+                self.remove(cube.position);
+                self.cubes.insert(cube.position, (id, None));
+                self.len += 1;
+            }
+            CanvasEvent::CubeRemoved(position) => self.remove(*position),
+            CanvasEvent::Recoloured { position, .. } => {
+                if let Some((_, recoloured)) = self.cubes.get_mut(position) {
+                    if recoloured.replace(id).is_none() {
+                        self.len += 1;
+                    }
+                }
+            }
+            CanvasEvent::CanvasCleared => {
+                self.cubes.clear();
+                self.len = 0;
+            }
+        }
+    }
+
+    fn remove(&mut self, position: Position) {
+        if let Some((_, recoloured)) = self.cubes.remove(&position) {
+            self.len -= 1 + recoloured.is_some() as usize;
+        }
+    }
+
+    fn ids(&self) -> HashSet<i64> {
+        self.cubes
+            .values()
+            .flat_map(|(placed, recoloured)| std::iter::once(*placed).chain(*recoloured))
+            .collect()
+    }
+}
+
+/// Ids of the events of `journal`, given as `(id, timestamp, event)` oldest
+/// first, which can be dropped without changing the canvas: those journaled
+/// before `cutoff` and, while the journal has more than `max_events`, the
+/// next oldest ones, unless they still make the canvas.
+pub fn prunable(
+    journal: &[(i64, i64, CanvasEvent)],
+    cutoff: Option<i64>,
+    max_events: Option<usize>,
+) -> Vec<i64> {
+    // Only a prefix of the journal is pruned, so that the events after it
+    // still apply to the same canvas.
+    let mut live = Live::default();
+    let mut end = 0;
+    for (i, (id, timestamp, event)) in journal.iter().enumerate() {
+        let old = cutoff.is_some_and(|cutoff| *timestamp < cutoff);
+        let over = max_events.is_some_and(|max| journal.len() - (i - live.len) > max);
+        if !old && !over {
+            break;
+        }
+        live.apply(*id, event);
+        end = i + 1;
+    }
+    let kept = live.ids();
+    journal[..end]
+        .iter()
+        .map(|(id, _, _)| *id)
+        .filter(|id| !kept.contains(id))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colour, Cube};
+
+    #[test]
+    fn test_prunable() {
+        let place = |x| CanvasEvent::CubePlaced(Cube::new(x, 0, 0, Colour::new(1, 2, 3)));
+        let recolour = |x| CanvasEvent::Recoloured {
+            position: Position::new(x, 0, 0),
+            colour: Colour::new(4, 5, 6),
+        };
+        let journal: Vec<(i64, i64, CanvasEvent)> = vec![
+            place(0),
+            place(1),
+            CanvasEvent::CanvasCleared,
+            place(2),
+            recolour(2),
+            place(3),
+            place(3),
+            CanvasEvent::CubeRemoved(Position::new(2, 0, 0)),
+            place(4),
+            recolour(4),
+            recolour(4),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, event)| (i as i64 + 1, i as i64 * 100, event))
+        .collect();
+
+        assert_eq!(prunable(&journal, None, None), Vec::<i64>::new());
+        assert_eq!(prunable(&journal, Some(0), None), Vec::<i64>::new());
+        // Up to the second placement at x = 3.
+        assert_eq!(prunable(&journal, Some(650), None), vec![1, 2, 3, 6]);
+        assert_eq!(
+            prunable(&journal, Some(2000), None),
+            vec![1, 2, 3, 4, 5, 6, 8, 10]
+        );
+        // Dropping the first three events is enough to keep eight.
+        assert_eq!(prunable(&journal, None, Some(8)), vec![1, 2, 3]);
+        // Never below what makes the canvas.
+        assert_eq!(prunable(&journal, None, Some(1)).len(), 8);
+    }
+}
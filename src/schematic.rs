@@ -0,0 +1,346 @@
+use crate::nbt::{self, Tag};
+use crate::{Colour, Cube, Palette, Position};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SchematicError {
+    #[error("invalid block {0}, expected minecraft:name,#rrggbb")]
+    InvalidBlock(String),
+    #[error("no blocks to build with")]
+    NoBlocks,
+    #[error("the build is too large for a schematic, {0} blocks across")]
+    TooLarge(u32),
+    #[error("invalid schematic, {0}")]
+    InvalidSchematic(String),
+    #[error("unable to read or write the schematic: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+// Version of the Sponge schematic format written.
+const SCHEMATIC_VERSION: i32 = 2;
+// Minecraft 1.16.5, old enough for the blocks to exist in any recent version.
+const DATA_VERSION: i32 = 2586;
+const AIR: &str = "minecraft:air";
+
+/// Minecraft blocks cubes are built with, by colour.
+#[derive(Clone, Debug)]
+pub struct BlockPalette {
+    colours: Palette,
+    blocks: Vec<(String, Colour)>,
+}
+
+impl BlockPalette {
+    /// Parses lines of `minecraft:name,#rrggbb`, blank lines and lines
+    /// starting with `#` are skipped.
+    pub fn parse(table: &str) -> Result<Self, SchematicError> {
+        let blocks = table
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let invalid = || SchematicError::InvalidBlock(line.to_owned());
+                let (name, colour) = line.split_once(',').ok_or_else(invalid)?;
+                let colour = colour.parse().map_err(|_| invalid())?;
+                Ok((name.trim().to_owned(), colour))
+            })
+            .collect::<Result<Vec<_>, SchematicError>>()?;
+        let colours = Palette::new(blocks.iter().map(|(_, colour)| *colour).collect())
+            .ok_or(SchematicError::NoBlocks)?;
+        Ok(Self { colours, blocks })
+    }
+
+    /// Block of the closest colour to `colour`.
+    pub fn nearest(&self, colour: Colour) -> &str {
+        let nearest = self.colours.nearest(colour);
+        self.blocks
+            .iter()
+            .find(|(_, colour)| *colour == nearest)
+            .map(|(name, _)| name.as_str())
+            .unwrap_or(AIR)
+    }
+
+    /// Colour of `block`, `None` for air and unknown blocks. Block states
+    /// such as `[facing=north]` are ignored.
+    pub fn colour_of(&self, block: &str) -> Option<Colour> {
+        let name = block.split('[').next().unwrap_or_default();
+        self.blocks
+            .iter()
+            .find(|(known, _)| known == name)
+            .map(|(_, colour)| *colour)
+    }
+}
+
+impl Default for BlockPalette {
+    /// Concrete, wool, terracotta and common building blocks, see `src/data/minecraft_blocks.csv`.
+    fn default() -> Self {
+        Self::parse(include_str!("data/minecraft_blocks.csv")).expect("invalid block table")
+    }
+}
+
+/// Gzipped Sponge schematic (`.schem`) of `cubes`, which WorldEdit and most
+/// other Minecraft tools import. The schematic spans the bounding box of the
+/// cubes, and as y grows downwards on the canvas it's flipped so that the
+/// build stands upright, its front facing south.
+pub fn export_schematic(cubes: &[Cube], blocks: &BlockPalette) -> Result<Vec<u8>, SchematicError> {
+    let bound = |axis: fn(&Cube) -> u32| {
+        let min = cubes.iter().map(axis).min().unwrap_or(0);
+        let max = cubes.iter().map(axis).max().unwrap_or(0);
+        (min, max - min + 1)
+    };
+    let (min_x, width) = bound(|cube| cube.position.x);
+    let (min_y, height) = bound(|cube| cube.position.y);
+    let (min_z, length) = bound(|cube| cube.position.z);
+    let mut palette: HashMap<&str, i32> = HashMap::new();
+    palette.insert(AIR, 0);
+    let mut indices = vec![0; (width * height * length) as usize];
+    for cube in cubes {
+        let block = blocks.nearest(cube.colour);
+        let next = palette.len() as i32;
+        let index = *palette.entry(block).or_insert(next);
+        let x = cube.position.x - min_x;
+        let y = height - 1 - (cube.position.y - min_y);
+        let z = cube.position.z - min_z;
+        indices[((y * length + z) * width + x) as usize] = index;
+    }
+    let mut block_data = Vec::with_capacity(indices.len());
+    for index in indices {
+        write_varint(&mut block_data, index);
+    }
+    let mut palette: Vec<(&str, i32)> = palette.into_iter().collect();
+    palette.sort_by_key(|(_, index)| *index);
+    let palette: Vec<(String, Tag)> = palette
+        .into_iter()
+        .map(|(name, index)| (name.to_owned(), Tag::Int(index)))
+        .collect();
+    let schematic = Tag::Compound(vec![
+        ("Version".to_owned(), Tag::Int(SCHEMATIC_VERSION)),
+        ("DataVersion".to_owned(), Tag::Int(DATA_VERSION)),
+        ("Width".to_owned(), Tag::Short(dimension(width)?)),
+        ("Height".to_owned(), Tag::Short(dimension(height)?)),
+        ("Length".to_owned(), Tag::Short(dimension(length)?)),
+        ("Offset".to_owned(), Tag::IntArray(vec![0, 0, 0])),
+        (
+            "Metadata".to_owned(),
+            Tag::Compound(vec![(
+                "Name".to_owned(),
+                Tag::String("twixelbox".to_owned()),
+            )]),
+        ),
+        ("PaletteMax".to_owned(), Tag::Int(palette.len() as i32)),
+        ("Palette".to_owned(), Tag::Compound(palette)),
+        ("BlockData".to_owned(), Tag::ByteArray(block_data)),
+    ]);
+    let mut out = GzEncoder::new(Vec::new(), Compression::default());
+    nbt::write_named(&mut out, "Schematic", &schematic)?;
+    Ok(out.finish()?)
+}
+
+/// Cubes of a Sponge schematic, gzipped or not, the inverse of
+/// `export_schematic`: the top left back corner of the schematic is placed
+/// at `origin`, and blocks outside of the canvas are left out, as are air and
+/// the blocks `blocks` doesn't know. Returns the cubes along with the number
+/// of blocks left out other than air.
+pub fn import_schematic(
+    data: &[u8],
+    blocks: &BlockPalette,
+    origin: Position,
+    side_len: u32,
+) -> Result<(Vec<Cube>, usize), SchematicError> {
+    let invalid = |reason: &str| SchematicError::InvalidSchematic(reason.to_owned());
+    let (_, root) = match data.starts_with(&[0x1f, 0x8b]) {
+        true => nbt::read_named(&mut GzDecoder::new(data))?,
+        false => nbt::read_named(&mut &data[..])?,
+    };
+    // Version 3 nests everything in a compound, and the blocks in another.
+    let schematic = root.get("Schematic").unwrap_or(&root);
+    let (palette, block_data) = match schematic.get("Blocks") {
+        Some(blocks) => (blocks.get("Palette"), blocks.get("Data")),
+        None => (schematic.get("Palette"), schematic.get("BlockData")),
+    };
+    let dimension = |name: &str| -> Result<u32, SchematicError> {
+        let len = schematic
+            .get(name)
+            .and_then(Tag::as_i64)
+            .ok_or_else(|| invalid(name))?;
+        // Stored as signed shorts.
+        Ok(len as u16 as u32)
+    };
+    let (width, height, length) = (
+        dimension("Width")?,
+        dimension("Height")?,
+        dimension("Length")?,
+    );
+    let colours: HashMap<i64, Option<Colour>> = match palette {
+        Some(Tag::Compound(entries)) => entries
+            .iter()
+            .filter_map(|(name, index)| Some((index.as_i64()?, blocks.colour_of(name))))
+            .collect(),
+        _ => return Err(invalid("no palette")),
+    };
+    let air: Vec<i64> = match palette {
+        Some(palette) => ["minecraft:air", "minecraft:cave_air", "minecraft:void_air"]
+            .iter()
+            .filter_map(|name| palette.get(name).and_then(Tag::as_i64))
+            .collect(),
+        None => Vec::new(),
+    };
+    let mut data = match block_data {
+        Some(Tag::ByteArray(data)) => data.iter().copied(),
+        _ => return Err(invalid("no block data")),
+    };
+    let mut cubes = Vec::new();
+    let mut skipped = 0;
+    for y in 0..height {
+        for z in 0..length {
+            for x in 0..width {
+                let index =
+                    read_varint(&mut data).ok_or_else(|| invalid("block data too short"))?;
+                if air.contains(&index) {
+                    continue;
+                }
+                let cube = colours.get(&index).copied().flatten().and_then(|colour| {
+                    let x = origin.x.saturating_add(x);
+                    let y = origin.y.saturating_add(height - 1 - y);
+                    let z = origin.z.saturating_add(z);
+                    Cube::bounded(x, y, z, colour, side_len).ok()
+                });
+                match cube {
+                    Some(cube) => cubes.push(cube),
+                    None => skipped += 1,
+                }
+            }
+        }
+    }
+    Ok((cubes, skipped))
+}
+
+// Dimensions are unsigned shorts, stored as signed ones.
+fn dimension(len: u32) -> Result<i16, SchematicError> {
+    match len > u16::MAX as u32 {
+        true => Err(SchematicError::TooLarge(len)),
+        false => Ok(len as u16 as i16),
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    while value >= 0x80 {
+        out.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint<I: Iterator<Item = u8>>(bytes: &mut I) -> Option<i64> {
+    let mut value = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = bytes.next()?;
+        value |= ((byte & 0x7f) as i64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_nearest_block() {
+        let blocks = BlockPalette::default();
+        assert_eq!(
+            blocks.nearest(Colour::new(160, 40, 35)),
+            "minecraft:red_wool"
+        );
+        assert_eq!(
+            blocks.nearest(Colour::new(0, 0, 0)),
+            "minecraft:black_concrete"
+        );
+        assert!(BlockPalette::parse("# nothing\n").is_err());
+        assert!(BlockPalette::parse("minecraft:stone").is_err());
+    }
+
+    #[test]
+    fn test_export_schematic() {
+        let blocks =
+            BlockPalette::parse("minecraft:stone,#808080\nminecraft:gold_block,#ffd700").unwrap();
+        let cubes = vec![
+            Cube::new(10, 5, 3, Colour::new(128, 128, 128)),
+            Cube::new(11, 4, 3, Colour::new(250, 210, 0)),
+        ];
+        let mut nbt = Vec::new();
+        GzDecoder::new(&export_schematic(&cubes, &blocks).unwrap()[..])
+            .read_to_end(&mut nbt)
+            .unwrap();
+        // Root compound named "Schematic".
+        assert_eq!(&nbt[..12], b"\x0a\x00\x09Schematic");
+        let find = |name: &str| {
+            let name = [&(name.len() as u16).to_be_bytes()[..], name.as_bytes()].concat();
+            let at = nbt.windows(name.len()).position(|w| w == name).unwrap();
+            at + name.len()
+        };
+        let short = |name| i16::from_be_bytes([nbt[find(name)], nbt[find(name) + 1]]);
+        // 2 wide, 2 high and 1 long.
+        assert_eq!(
+            (short("Width"), short("Height"), short("Length")),
+            (2, 2, 1)
+        );
+        let data = find("BlockData");
+        assert_eq!(&nbt[data..data + 4], &4i32.to_be_bytes());
+        // The stone cube is lower, at the bottom left, the gold one above it
+        // on the right.
+        assert_eq!(&nbt[data + 4..data + 8], &[1, 0, 0, 2]);
+    }
+
+    #[test]
+    fn test_import_schematic() {
+        let blocks = BlockPalette::default();
+        let red = blocks.colour_of("minecraft:red_wool").unwrap();
+        let black = blocks.colour_of("minecraft:black_concrete").unwrap();
+        let cubes = vec![
+            Cube::new(10, 5, 3, red),
+            Cube::new(11, 4, 3, black),
+            Cube::new(10, 4, 4, red),
+        ];
+        let schematic = export_schematic(&cubes, &blocks).unwrap();
+        let (imported, skipped) =
+            import_schematic(&schematic, &blocks, Position::new(10, 4, 3), 16).unwrap();
+        assert_eq!(skipped, 0);
+        let sorted = |mut cubes: Vec<Cube>| {
+            cubes.sort_by_key(|cube| cube.position);
+            cubes
+        };
+        assert_eq!(sorted(imported), sorted(cubes));
+        // Clamped to the canvas.
+        let (imported, skipped) =
+            import_schematic(&schematic, &blocks, Position::new(15, 0, 0), 16).unwrap();
+        assert_eq!(
+            (imported, skipped),
+            (vec![Cube::new(15, 1, 0, red), Cube::new(15, 0, 1, red)], 1)
+        );
+        assert_eq!(
+            blocks.colour_of("minecraft:red_wool[facing=north]"),
+            Some(red)
+        );
+        assert_eq!(blocks.colour_of("minecraft:air"), None);
+        assert!(import_schematic(b"not a schematic", &blocks, Position::new(0, 0, 0), 16).is_err());
+    }
+
+    #[test]
+    fn test_varint() {
+        let mut out = Vec::new();
+        write_varint(&mut out, 1);
+        write_varint(&mut out, 300);
+        assert_eq!(out, vec![1, 0xac, 0x02]);
+        assert_eq!(read_varint(&mut out.into_iter()), Some(1));
+        assert_eq!(read_varint(&mut vec![0xac, 0x02].into_iter()), Some(300));
+        assert_eq!(read_varint(&mut vec![0xac].into_iter()), None);
+    }
+}
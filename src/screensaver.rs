@@ -0,0 +1,249 @@
+use crate::{Canvas, Colour, Cube, Position};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScreensaverGame {
+    /// 3D Game of Life, cells are born with 5 neighbours and survive with 4
+    /// or 5.
+    Life,
+    /// A snake wandering around the cubes.
+    Snake,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ScreensaverConfig {
+    /// Seconds without placements before the screensaver starts, 0 disables
+    /// it.
+    pub idle_secs: u64,
+    pub game: ScreensaverGame,
+    /// Milliseconds between two steps of the game.
+    pub step_ms: u64,
+}
+
+impl Default for ScreensaverConfig {
+    fn default() -> Self {
+        Self {
+            idle_secs: 0,
+            game: ScreensaverGame::Life,
+            step_ms: 2000,
+        }
+    }
+}
+
+// Side of the region seeded with live cells, centred in the canvas.
+const LIFE_SEED_SIDE: u32 = 12;
+const LIFE_SEED_DENSITY: f32 = 0.2;
+// Steps before a stable or looping world is seeded again.
+const LIFE_MAX_STEPS: u32 = 200;
+const SNAKE_LEN: usize = 16;
+
+const NEIGHBOURS: [(i64, i64, i64); 6] = [
+    (-1, 0, 0),
+    (1, 0, 0),
+    (0, -1, 0),
+    (0, 1, 0),
+    (0, 0, -1),
+    (0, 0, 1),
+];
+
+#[derive(Clone, Debug)]
+enum Game {
+    Life {
+        cells: HashSet<Position>,
+        steps: u32,
+    },
+    Snake {
+        body: VecDeque<Position>,
+    },
+}
+
+/// Animation drawn on a layer of its own over the canvas while chat is quiet.
+/// The layer is never persisted, and only uses cells which are empty on the
+/// canvas.
+#[derive(Clone, Debug)]
+pub struct Screensaver {
+    side_len: u32,
+    colour: Colour,
+    game: Game,
+    rng: fastrand::Rng,
+}
+
+impl Screensaver {
+    pub fn new(game: ScreensaverGame, side_len: u32, seed: u64) -> Self {
+        let game = match game {
+            ScreensaverGame::Life => Game::Life {
+                cells: HashSet::new(),
+                steps: 0,
+            },
+            ScreensaverGame::Snake => Game::Snake {
+                body: VecDeque::new(),
+            },
+        };
+        Self {
+            side_len,
+            colour: Colour::new(0, 0, 0),
+            game,
+            rng: fastrand::Rng::with_seed(seed),
+        }
+    }
+
+    /// Advances the game by a step, returns the cubes of the layer.
+    pub fn step(&mut self, canvas: &Canvas) -> Vec<Cube> {
+        let rng = &self.rng;
+        let free = |position: &Position| canvas.check_free(*position).is_ok();
+        let reseed = match &mut self.game {
+            Game::Life { cells, steps } => {
+                let next = life_step(cells, &free);
+                *steps += 1;
+                let reseed = next.is_empty() || next == *cells || *steps >= LIFE_MAX_STEPS;
+                *cells = next;
+                reseed
+            }
+            Game::Snake { body } => match body.front().copied() {
+                Some(head) => {
+                    let moves: Vec<Position> = NEIGHBOURS
+                        .iter()
+                        .filter_map(|(dx, dy, dz)| head.offset(*dx, *dy, *dz))
+                        .filter(|position| free(position) && !body.contains(position))
+                        .collect();
+                    match moves.len() {
+                        // Trapped, start over somewhere else.
+                        0 => true,
+                        len => {
+                            body.push_front(moves[rng.usize(..len)]);
+                            body.truncate(SNAKE_LEN);
+                            false
+                        }
+                    }
+                }
+                None => true,
+            },
+        };
+        if reseed {
+            self.reseed(canvas);
+        }
+        let colour = self.colour;
+        let positions: Vec<Position> = match &self.game {
+            Game::Life { cells, .. } => cells.iter().copied().collect(),
+            Game::Snake { body } => body.iter().copied().collect(),
+        };
+        positions
+            .into_iter()
+            .filter(|position| !canvas.contains(*position))
+            .map(|position| Cube { position, colour })
+            .collect()
+    }
+
+    fn reseed(&mut self, canvas: &Canvas) {
+        let rng = &self.rng;
+        self.colour = Colour::new(rng.u8(..), rng.u8(..), rng.u8(..));
+        let seed_side = LIFE_SEED_SIDE.min(self.side_len);
+        let start = (self.side_len - seed_side) / 2;
+        let free = |position: &Position| !canvas.contains(*position);
+        match &mut self.game {
+            Game::Life { cells, steps } => {
+                *steps = 0;
+                cells.clear();
+                for x in start..start + seed_side {
+                    for y in start..start + seed_side {
+                        for z in start..start + seed_side {
+                            let position = Position::new(x, y, z);
+                            if free(&position) && rng.f32() < LIFE_SEED_DENSITY {
+                                cells.insert(position);
+                            }
+                        }
+                    }
+                }
+            }
+            Game::Snake { body } => {
+                body.clear();
+                // A few tries to find a free cell, the canvas may be full.
+                for _ in 0..32 {
+                    let position = Position::new(
+                        rng.u32(..self.side_len.max(1)),
+                        rng.u32(..self.side_len.max(1)),
+                        rng.u32(..self.side_len.max(1)),
+                    );
+                    if free(&position) {
+                        body.push_front(position);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Next generation of 3D Life, counting the 26 surrounding cells.
+fn life_step(cells: &HashSet<Position>, free: &dyn Fn(&Position) -> bool) -> HashSet<Position> {
+    let mut counts: HashMap<Position, u32> = HashMap::new();
+    for cell in cells {
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if (dx, dy, dz) == (0, 0, 0) {
+                        continue;
+                    }
+                    if let Some(neighbour) = cell.offset(dx, dy, dz) {
+                        *counts.entry(neighbour).or_default() += 1;
+                    }
+                }
+            }
+        }
+    }
+    counts
+        .into_iter()
+        .filter(|(position, count)| match cells.contains(position) {
+            true => *count == 4 || *count == 5,
+            false => *count == 5,
+        })
+        .map(|(position, _)| position)
+        .filter(|position| free(position))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_life_step() {
+        // A 2x2x2 block dies out, each of its cells has 7 neighbours and no
+        // empty cell has exactly 5.
+        let block: HashSet<Position> = (0..8)
+            .map(|i| Position::new(1 + (i & 1), 1 + ((i >> 1) & 1), 1 + ((i >> 2) & 1)))
+            .collect();
+        assert!(life_step(&block, &|_| true).is_empty());
+        // A 2x2x1 plane: cells have 3 neighbours, and the cells next to it
+        // 4 at most.
+        let plane: HashSet<Position> = (0..4)
+            .map(|i| Position::new(1 + (i & 1), 1 + ((i >> 1) & 1), 1))
+            .collect();
+        assert!(life_step(&plane, &|_| true).is_empty());
+    }
+
+    #[test]
+    fn test_layer_avoids_cubes() {
+        let mut canvas = Canvas::new(8);
+        canvas
+            .add_cube(Cube::new(4, 4, 4, Colour::new(255, 0, 0)))
+            .unwrap();
+        for game in [ScreensaverGame::Life, ScreensaverGame::Snake].iter() {
+            let mut screensaver = Screensaver::new(*game, 8, 42);
+            for _ in 0..20 {
+                let layer = screensaver.step(&canvas);
+                assert!(layer
+                    .iter()
+                    .all(|cube| cube.position.is_within(8) && !canvas.contains(cube.position)));
+            }
+        }
+        let mut snake = Screensaver::new(ScreensaverGame::Snake, 8, 42);
+        for _ in 0..SNAKE_LEN * 2 {
+            snake.step(&canvas);
+        }
+        assert!(!snake.step(&canvas).is_empty());
+    }
+}
@@ -0,0 +1,387 @@
+#[cfg(feature = "scripting")]
+use crate::{bounded_position, Colour};
+use crate::{Canvas, CanvasEvent, Cube, Position};
+#[cfg(feature = "scripting")]
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde::Deserialize;
+#[cfg(feature = "scripting")]
+use std::convert::TryFrom;
+#[cfg(feature = "scripting")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "scripting")]
+use std::time::SystemTime;
+use thiserror::Error;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ScriptConfig {
+    /// Rhai script defining custom chat commands, scripting is disabled when
+    /// unset. It's loaded again whenever it changes.
+    pub filepath: Option<String>,
+    /// Operations a single call can run before being aborted.
+    pub max_operations: u64,
+    /// Cubes placed or removed and chat replies of a single call.
+    pub max_actions: usize,
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        Self {
+            filepath: None,
+            max_operations: 100_000,
+            max_actions: 200,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ScriptError {
+    #[error("scripting is not available, rebuild with --features scripting")]
+    Unavailable,
+    #[error("unable to load the script {0}")]
+    Load(String),
+    #[error("script error {0}")]
+    Run(String),
+}
+
+/// What a script asked for, in order. Nothing happens when the script fails.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScriptAction {
+    Place(Cube),
+    Remove(Position),
+    Reply(String),
+}
+
+#[cfg(feature = "scripting")]
+struct ScriptState {
+    // Mirror of the canvas, the scripts only read it.
+    canvas: Canvas,
+    actions: Vec<ScriptAction>,
+    max_actions: usize,
+}
+
+#[cfg(feature = "scripting")]
+impl ScriptState {
+    fn position(&self, x: i64, y: i64, z: i64) -> Result<Position, Box<EvalAltResult>> {
+        bounded_position([x, y, z], self.canvas.side_len()).map_err(|e| e.to_string().into())
+    }
+
+    fn push(&mut self, action: ScriptAction) -> Result<(), Box<EvalAltResult>> {
+        if self.actions.len() >= self.max_actions {
+            return Err(format!("more than {} actions", self.max_actions).into());
+        }
+        self.actions.push(action);
+        Ok(())
+    }
+}
+
+/// Custom chat commands and automations written by the streamer in
+/// [Rhai](https://rhai.rs). `!name a b` calls `fn cmd_name(user, args)` of the
+/// script, with `user.name`, `user.login` and `user.moderator`, and the
+/// arguments as strings. `fn on_placed(login, x, y, z, colour)` is called
+/// after each cube placed by chat.
+///
+/// Scripts can only read the canvas with `side()` and `colour_at(x, y, z)`,
+/// and act with `place(x, y, z, colour)`, `place(x, y, z, r, g, b)`,
+/// `remove(x, y, z)` and `reply(text)`. They can't reach files or the
+/// network, and run within limits.
+#[cfg(feature = "scripting")]
+pub struct ScriptHost {
+    engine: Engine,
+    ast: AST,
+    path: std::path::PathBuf,
+    modified: Option<SystemTime>,
+    state: Arc<Mutex<ScriptState>>,
+}
+
+#[cfg(feature = "scripting")]
+impl ScriptHost {
+    /// Loads the configured script, `canvas` being the current state of the
+    /// canvas, kept up to date with `observe`.
+    pub fn load(config: &ScriptConfig, canvas: Canvas) -> Result<Self, ScriptError> {
+        let path = match &config.filepath {
+            Some(filepath) => std::path::PathBuf::from(filepath),
+            None => return Err(ScriptError::Load("no script configured".to_owned())),
+        };
+        let state = Arc::new(Mutex::new(ScriptState {
+            canvas,
+            actions: Vec::new(),
+            max_actions: config.max_actions,
+        }));
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(config.max_operations)
+            .set_max_call_levels(32)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(10_000)
+            .set_max_array_size(10_000)
+            .set_max_map_size(1_000)
+            .set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new())
+            .on_print(|text| log::info!("Script: {}", text));
+        engine.disable_symbol("eval");
+        register_api(&mut engine, &state);
+        let ast = engine
+            .compile_file(path.clone())
+            .map_err(|e| ScriptError::Load(e.to_string()))?;
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        Ok(Self {
+            engine,
+            ast,
+            path,
+            modified,
+            state,
+        })
+    }
+
+    /// Compiles the script again if the file changed since, returns whether
+    /// it did. The previous version stays in use when the new one is broken.
+    pub fn reload(&mut self) -> Result<bool, ScriptError> {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok();
+        if modified == self.modified {
+            return Ok(false);
+        }
+        self.modified = modified;
+        self.ast = self
+            .engine
+            .compile_file(self.path.clone())
+            .map_err(|e| ScriptError::Load(e.to_string()))?;
+        Ok(true)
+    }
+
+    /// Updates the script's view of the canvas.
+    pub fn observe(&mut self, event: &CanvasEvent) {
+        let _ = self.state.lock().unwrap().canvas.apply(event);
+    }
+
+    /// Runs `!name`, `None` if the script doesn't define it.
+    pub fn command(
+        &mut self,
+        name: &str,
+        args: &[String],
+        user: &str,
+        login: &str,
+        moderator: bool,
+    ) -> Result<Option<Vec<ScriptAction>>, ScriptError> {
+        let function = format!("cmd_{}", name);
+        if !self.defines(&function, 2) {
+            return Ok(None);
+        }
+        let mut caller = Map::new();
+        caller.insert("name".into(), Dynamic::from(user.to_owned()));
+        caller.insert("login".into(), Dynamic::from(login.to_owned()));
+        caller.insert("moderator".into(), Dynamic::from(moderator));
+        let args: Array = args.iter().map(|a| Dynamic::from(a.clone())).collect();
+        self.call(&function, (caller, args)).map(Some)
+    }
+
+    /// Runs the `on_placed` hook of the script, if any, for a cube placed by
+    /// `login`.
+    pub fn placed(&mut self, login: &str, cube: &Cube) -> Result<Vec<ScriptAction>, ScriptError> {
+        if !self.defines("on_placed", 5) {
+            return Ok(Vec::new());
+        }
+        let position = cube.position;
+        let args = (
+            login.to_owned(),
+            position.x as i64,
+            position.y as i64,
+            position.z as i64,
+            cube.colour.to_string(),
+        );
+        self.call("on_placed", args)
+    }
+
+    fn defines(&self, function: &str, params: usize) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|f| f.name == function && f.params.len() == params)
+    }
+
+    fn call(
+        &mut self,
+        function: &str,
+        args: impl rhai::FuncArgs,
+    ) -> Result<Vec<ScriptAction>, ScriptError> {
+        self.state.lock().unwrap().actions.clear();
+        let mut scope = Scope::new();
+        let result = self
+            .engine
+            .call_fn::<Dynamic>(&mut scope, &self.ast, function, args);
+        let actions = std::mem::take(&mut self.state.lock().unwrap().actions);
+        result.map_err(|e| ScriptError::Run(e.to_string()))?;
+        Ok(actions)
+    }
+}
+
+#[cfg(feature = "scripting")]
+fn register_api(engine: &mut Engine, state: &Arc<Mutex<ScriptState>>) {
+    let s = state.clone();
+    engine.register_fn("side", move || s.lock().unwrap().canvas.side_len() as i64);
+    let s = state.clone();
+    engine.register_fn(
+        "colour_at",
+        move |x: i64, y: i64, z: i64| -> Result<Dynamic, Box<EvalAltResult>> {
+            let state = s.lock().unwrap();
+            let position = state.position(x, y, z)?;
+            Ok(match state.canvas.get(position) {
+                Some(cube) => Dynamic::from(cube.colour.to_string()),
+                None => Dynamic::UNIT,
+            })
+        },
+    );
+    let s = state.clone();
+    engine.register_fn(
+        "place",
+        move |x: i64, y: i64, z: i64, colour: &str| -> Result<(), Box<EvalAltResult>> {
+            let colour = colour
+                .parse::<Colour>()
+                .map_err(|_| format!("invalid colour {}", colour))?;
+            let mut state = s.lock().unwrap();
+            let position = state.position(x, y, z)?;
+            state.push(ScriptAction::Place(Cube { position, colour }))
+        },
+    );
+    let s = state.clone();
+    engine.register_fn(
+        "place",
+        move |x: i64, y: i64, z: i64, r: i64, g: i64, b: i64| -> Result<(), Box<EvalAltResult>> {
+            let colour = match [r, g, b].map(|c| u8::try_from(c).ok()) {
+                [Some(r), Some(g), Some(b)] => Colour::new(r, g, b),
+                _ => return Err(format!("invalid colour {} {} {}", r, g, b).into()),
+            };
+            let mut state = s.lock().unwrap();
+            let position = state.position(x, y, z)?;
+            state.push(ScriptAction::Place(Cube { position, colour }))
+        },
+    );
+    let s = state.clone();
+    engine.register_fn(
+        "remove",
+        move |x: i64, y: i64, z: i64| -> Result<(), Box<EvalAltResult>> {
+            let mut state = s.lock().unwrap();
+            let position = state.position(x, y, z)?;
+            state.push(ScriptAction::Remove(position))
+        },
+    );
+    let s = state.clone();
+    engine.register_fn(
+        "reply",
+        move |text: &str| -> Result<(), Box<EvalAltResult>> {
+            s.lock().unwrap().push(ScriptAction::Reply(text.to_owned()))
+        },
+    );
+}
+
+/// Stands in for the scripting engine when built without it, it can't be
+/// loaded.
+#[cfg(not(feature = "scripting"))]
+pub struct ScriptHost {
+    _private: (),
+}
+
+#[cfg(not(feature = "scripting"))]
+impl ScriptHost {
+    pub fn load(_config: &ScriptConfig, _canvas: Canvas) -> Result<Self, ScriptError> {
+        Err(ScriptError::Unavailable)
+    }
+
+    pub fn reload(&mut self) -> Result<bool, ScriptError> {
+        Ok(false)
+    }
+
+    pub fn observe(&mut self, _event: &CanvasEvent) {}
+
+    pub fn command(
+        &mut self,
+        _name: &str,
+        _args: &[String],
+        _user: &str,
+        _login: &str,
+        _moderator: bool,
+    ) -> Result<Option<Vec<ScriptAction>>, ScriptError> {
+        Ok(None)
+    }
+
+    pub fn placed(&mut self, _login: &str, _cube: &Cube) -> Result<Vec<ScriptAction>, ScriptError> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(all(test, feature = "scripting"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script() {
+        let path = std::env::temp_dir().join("twixelbox-test-script.rhai");
+        std::fs::write(
+            &path,
+            r##"
+            fn cmd_pillar(user, args) {
+                let x = parse_int(args[0]);
+                for y in 0..3 {
+                    place(x, y, 0, "#ff0000");
+                }
+                reply(user.name + " built a pillar");
+            }
+            fn cmd_paint(user, args) {
+                if colour_at(0, 0, 0) != () {
+                    place(0, 0, 0, 0, 0, 255);
+                }
+            }
+            fn cmd_spill(user, args) {
+                place(side(), 0, 0, "red");
+            }
+            fn on_placed(login, x, y, z, colour) {
+                remove(x, y, z);
+            }
+            "##,
+        )
+        .unwrap();
+        let config = ScriptConfig {
+            filepath: Some(path.to_string_lossy().into_owned()),
+            ..ScriptConfig::default()
+        };
+        let mut host = ScriptHost::load(&config, Canvas::new(4)).unwrap();
+        let actions = host
+            .command("pillar", &["2".to_owned()], "Ann", "ann", false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(actions.len(), 4);
+        assert_eq!(
+            actions[0],
+            ScriptAction::Place(Cube::bounded(2, 0, 0, Colour::new(255, 0, 0), 4).unwrap())
+        );
+        assert_eq!(
+            actions[3],
+            ScriptAction::Reply("Ann built a pillar".to_owned())
+        );
+        assert_eq!(
+            host.command("nope", &[], "Ann", "ann", false).unwrap(),
+            None
+        );
+
+        // Scripts see the canvas as it changes.
+        assert_eq!(
+            host.command("paint", &[], "Ann", "ann", false).unwrap(),
+            Some(vec![])
+        );
+        let cube = Cube::bounded(0, 0, 0, Colour::new(1, 2, 3), 4).unwrap();
+        host.observe(&CanvasEvent::CubePlaced(cube));
+        assert_eq!(
+            host.command("paint", &[], "Ann", "ann", false)
+                .unwrap()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(host.command("spill", &[], "Ann", "ann", false).is_err());
+        assert_eq!(
+            host.placed("ann", &cube).unwrap(),
+            vec![ScriptAction::Remove(cube.position)]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
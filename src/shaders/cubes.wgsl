@@ -0,0 +1,35 @@
+struct Uniforms {
+    view_proj: mat4x4<f32>;
+    eye: vec3<f32>;
+    voxel_side_len: f32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> uniforms: Uniforms;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] colour: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[location(0)]] position: vec3<f32>,
+    [[location(1)]] normal: vec3<f32>,
+    [[location(2)]] offset: vec3<f32>,
+    [[location(3)]] colour: vec3<f32>,
+    [[location(4)]] scale: vec3<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    let world = position * scale * uniforms.voxel_side_len + offset;
+    out.position = uniforms.view_proj * vec4<f32>(world, 1.0);
+    // The light follows the camera, like kiss3d's Light::StickToCamera.
+    let light = abs(dot(normal, normalize(uniforms.eye - world)));
+    out.colour = colour * (0.3 + 0.7 * light);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(vertex: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(vertex.colour, 1.0);
+}
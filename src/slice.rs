@@ -0,0 +1,314 @@
+use crate::renderer::Renderer;
+use crate::{Background, CameraPose, Colour, Cube, Position, Region};
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SliceConfig {
+    /// Seconds the overlay stays sliced after a `!slice`.
+    pub duration_secs: u64,
+}
+
+impl Default for SliceConfig {
+    fn default() -> Self {
+        Self { duration_secs: 30 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SliceError {
+    #[error("the axis must be x, y or z")]
+    InvalidAxis,
+    #[error("give the layer, or the first and last layers, e.g. z 12 or z 10 14")]
+    InvalidRange,
+}
+
+impl FromStr for Axis {
+    type Err = SliceError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "x" => Ok(Axis::X),
+            "y" => Ok(Axis::Y),
+            "z" => Ok(Axis::Z),
+            _ => Err(SliceError::InvalidAxis),
+        }
+    }
+}
+
+/// Slab of the canvas between two layers along an axis, both included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Slice {
+    pub axis: Axis,
+    pub first: u32,
+    pub last: u32,
+}
+
+impl Slice {
+    pub fn contains(&self, position: Position) -> bool {
+        let coordinate = match self.axis {
+            Axis::X => position.x,
+            Axis::Y => position.y,
+            Axis::Z => position.z,
+        };
+        self.first <= coordinate && coordinate <= self.last
+    }
+}
+
+// Parses `z 12` or `z 10 14`, the layers in any order.
+impl FromStr for Slice {
+    type Err = SliceError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut args = value.split_whitespace();
+        let axis = args.next().ok_or(SliceError::InvalidAxis)?.parse()?;
+        let layers = args
+            .map(|v| v.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| SliceError::InvalidRange)?;
+        let (first, last) = match layers[..] {
+            [layer] => (layer, layer),
+            [a, b] => (a.min(b), a.max(b)),
+            _ => return Err(SliceError::InvalidRange),
+        };
+        Ok(Self { axis, first, last })
+    }
+}
+
+impl fmt::Display for Axis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Axis::X => "x",
+            Axis::Y => "y",
+            Axis::Z => "z",
+        })
+    }
+}
+
+impl fmt::Display for Slice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.first == self.last {
+            true => write!(f, "{} {}", self.axis, self.first),
+            false => write!(f, "{} {} to {}", self.axis, self.first, self.last),
+        }
+    }
+}
+
+// Grey the cubes out of a highlight fade into, and how much.
+const FADE_COLOUR: [u8; 3] = [160, 160, 160];
+const FADE: f32 = 0.8;
+
+/// Render filter hiding the cubes outside of a slice, to look inside dense
+/// builds, and fading those outside of a highlight. Keeps track of all the
+/// cubes, so they're shown again once the slice or highlight is lifted.
+pub struct SliceFilter {
+    renderer: Box<dyn Renderer>,
+    cubes: HashMap<Position, Colour>,
+    slice: Option<Slice>,
+    highlight: Option<HashSet<Position>>,
+}
+
+impl SliceFilter {
+    pub fn new(renderer: Box<dyn Renderer>) -> Self {
+        Self {
+            renderer,
+            cubes: HashMap::new(),
+            slice: None,
+            highlight: None,
+        }
+    }
+
+    /// Fades every cube but those at `highlight` into grey, or none with
+    /// `None`.
+    pub fn set_highlight(&mut self, highlight: Option<HashSet<Position>>) {
+        self.highlight = highlight;
+        for (&position, &colour) in &self.cubes {
+            if self.slice.is_none_or(|slice| slice.contains(position)) {
+                self.renderer
+                    .add_cube(&self.shown(Cube { position, colour }));
+            }
+        }
+    }
+
+    // The cube as drawn, faded when out of the highlight.
+    fn shown(&self, cube: Cube) -> Cube {
+        match &self.highlight {
+            Some(highlight) if !highlight.contains(&cube.position) => {
+                let Colour { r, g, b, .. } = cube.colour;
+                let colour = [r, g, b];
+                let [r, g, b] = [0, 1, 2].map(|i| {
+                    (colour[i] as f32 * (1.0 - FADE) + FADE_COLOUR[i] as f32 * FADE).round() as u8
+                });
+                Cube {
+                    position: cube.position,
+                    colour: Colour::new(r, g, b),
+                }
+            }
+            _ => cube,
+        }
+    }
+
+    pub fn slice(&self) -> Option<Slice> {
+        self.slice
+    }
+
+    /// Shows only the cubes within `slice`, or all of them with `None`.
+    pub fn set_slice(&mut self, slice: Option<Slice>) {
+        let visible = |slice: Option<Slice>, position: Position| {
+            slice.is_none_or(|slice| slice.contains(position))
+        };
+        for (&position, &colour) in &self.cubes {
+            match (visible(self.slice, position), visible(slice, position)) {
+                (true, false) => self.renderer.remove_cube(position),
+                (false, true) => self
+                    .renderer
+                    .add_cube(&self.shown(Cube { position, colour })),
+                _ => {}
+            }
+        }
+        self.slice = slice;
+    }
+
+    fn is_visible(&self, position: Position) -> bool {
+        self.slice.is_none_or(|slice| slice.contains(position))
+    }
+}
+
+impl Renderer for SliceFilter {
+    fn add_cube(&mut self, cube: &Cube) {
+        self.cubes.insert(cube.position, cube.colour);
+        if self.is_visible(cube.position) {
+            self.renderer.add_cube(&self.shown(cube.clone()));
+        }
+    }
+
+    fn remove_cube(&mut self, position: Position) {
+        if self.cubes.remove(&position).is_some() && self.is_visible(position) {
+            self.renderer.remove_cube(position);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.cubes.clear();
+        self.renderer.clear();
+    }
+
+    fn set_fog(&mut self, unlocked: Option<Region>) {
+        self.renderer.set_fog(unlocked);
+    }
+
+    fn set_yaw(&mut self, yaw: f32) {
+        self.renderer.set_yaw(yaw);
+    }
+
+    fn set_camera(&mut self, camera: Option<CameraPose>) {
+        self.renderer.set_camera(camera);
+    }
+
+    fn set_background(&mut self, background: &Background) {
+        self.renderer.set_background(background);
+    }
+
+    fn hovered(&self) -> Option<Position> {
+        self.renderer.hovered()
+    }
+
+    fn set_label(&mut self, label: Option<(Position, String)>) {
+        self.renderer.set_label(label);
+    }
+
+    fn set_ghosts(&mut self, ghosts: &[(Cube, f32)]) {
+        let visible: Vec<_> = ghosts
+            .iter()
+            .filter(|(cube, _)| self.is_visible(cube.position))
+            .cloned()
+            .collect();
+        self.renderer.set_ghosts(&visible);
+    }
+
+    fn render(&mut self) -> Option<RgbImage> {
+        self.renderer.render()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TerminalRenderer;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "z 12".parse(),
+            Ok(Slice {
+                axis: Axis::Z,
+                first: 12,
+                last: 12
+            })
+        );
+        assert_eq!(
+            "X 14 10".parse(),
+            Ok(Slice {
+                axis: Axis::X,
+                first: 10,
+                last: 14
+            })
+        );
+        assert_eq!("w 1".parse::<Slice>(), Err(SliceError::InvalidAxis));
+        assert_eq!("y".parse::<Slice>(), Err(SliceError::InvalidRange));
+        assert_eq!("y 1 2 3".parse::<Slice>(), Err(SliceError::InvalidRange));
+    }
+
+    #[test]
+    fn test_filter() {
+        let empty = TerminalRenderer::new(8, 8, 8).render().unwrap();
+        let mut filter = SliceFilter::new(Box::new(TerminalRenderer::new(8, 8, 8)));
+        filter.add_cube(&Cube::new(1, 1, 1, Colour::new(255, 0, 0)));
+        assert_ne!(filter.render().unwrap(), empty);
+        filter.set_slice(Some("z 2".parse().unwrap()));
+        assert_eq!(filter.render().unwrap(), empty);
+        // Hidden cubes are still tracked.
+        filter.add_cube(&Cube::new(1, 1, 3, Colour::new(0, 255, 0)));
+        assert_eq!(filter.render().unwrap(), empty);
+        filter.set_slice(None);
+        let all = filter.render().unwrap();
+        filter.remove_cube(Position::new(1, 1, 3));
+        assert_ne!(filter.render().unwrap(), all);
+        assert_ne!(filter.render().unwrap(), empty);
+    }
+
+    #[test]
+    fn test_highlight() {
+        let mut filter = SliceFilter::new(Box::new(TerminalRenderer::new(8, 8, 8)));
+        filter.add_cube(&Cube::new(1, 1, 1, Colour::new(255, 0, 0)));
+        filter.add_cube(&Cube::new(5, 5, 1, Colour::new(0, 0, 255)));
+        let all = filter.render().unwrap();
+        let highlight: HashSet<Position> = std::iter::once(Position::new(1, 1, 1)).collect();
+        filter.set_highlight(Some(highlight));
+        let highlighted = filter.render().unwrap();
+        assert_ne!(highlighted, all);
+        // New cubes out of the highlight are faded too.
+        filter.add_cube(&Cube::new(5, 5, 1, Colour::new(0, 0, 255)));
+        assert_eq!(filter.render().unwrap(), highlighted);
+        filter.set_highlight(None);
+        assert_eq!(filter.render().unwrap(), all);
+
+        let faded = filter.shown(Cube::new(0, 0, 0, Colour::new(255, 0, 0)));
+        assert_eq!(faded.colour, Colour::new(255, 0, 0));
+        filter.set_highlight(Some(HashSet::new()));
+        let faded = filter.shown(Cube::new(0, 0, 0, Colour::new(255, 0, 0)));
+        assert_eq!(faded.colour, Colour::new(179, 128, 128));
+    }
+}
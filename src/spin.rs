@@ -0,0 +1,114 @@
+use serde::Deserialize;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SpinError {
+    #[error("try !spin <degrees per second> or !spin stop")]
+    InvalidSpeed,
+    #[error("the overlay spins at most {0} degrees per second")]
+    TooFast(f32),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SpinConfig {
+    /// Degrees per second the overlay turns around the canvas on start, 0
+    /// keeps it still.
+    pub degrees_per_sec: f32,
+    /// Fastest spin chat can ask for, either way.
+    pub max_degrees_per_sec: f32,
+    /// Id of the channel point reward which lets anyone use `!spin`,
+    /// moderators always can.
+    pub reward_id: Option<String>,
+    /// Frames per second of the overlay's MJPEG stream while it spins, drawn
+    /// in between those of the image file. 0 only streams the latter.
+    pub stream_fps: f32,
+}
+
+impl Default for SpinConfig {
+    fn default() -> Self {
+        Self {
+            degrees_per_sec: 0.0,
+            max_degrees_per_sec: 90.0,
+            reward_id: None,
+            stream_fps: 10.0,
+        }
+    }
+}
+
+impl SpinConfig {
+    /// Speed asked for by the arguments of `!spin`, a number of degrees per
+    /// second, negative to turn the other way, or `stop`.
+    pub fn parse_speed(&self, args: &str) -> Result<f32, SpinError> {
+        let args = args.trim();
+        if args.eq_ignore_ascii_case("stop") {
+            return Ok(0.0);
+        }
+        let speed = args
+            .parse::<f32>()
+            .ok()
+            .filter(|speed| speed.is_finite())
+            .ok_or(SpinError::InvalidSpeed)?;
+        match speed.abs() <= self.max_degrees_per_sec {
+            true => Ok(speed),
+            false => Err(SpinError::TooFast(self.max_degrees_per_sec)),
+        }
+    }
+}
+
+/// Orbit of the overlay around the canvas, its speed changed live from chat.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spin {
+    degrees_per_sec: f32,
+    // In radians, within [0, 2π).
+    yaw: f32,
+}
+
+impl Spin {
+    pub fn new(degrees_per_sec: f32) -> Self {
+        Self {
+            degrees_per_sec,
+            yaw: 0.0,
+        }
+    }
+
+    pub fn set_speed(&mut self, degrees_per_sec: f32) {
+        self.degrees_per_sec = degrees_per_sec;
+    }
+
+    pub fn is_spinning(&self) -> bool {
+        self.degrees_per_sec != 0.0
+    }
+
+    /// Turns for `elapsed`, returns the angle reached, in radians.
+    pub fn advance(&mut self, elapsed: Duration) -> f32 {
+        let turn = (self.degrees_per_sec * elapsed.as_secs_f32()).to_radians();
+        self.yaw = (self.yaw + turn).rem_euclid(std::f32::consts::TAU);
+        self.yaw
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spin() {
+        let config = SpinConfig::default();
+        assert_eq!(config.parse_speed("stop"), Ok(0.0));
+        assert_eq!(config.parse_speed(" -45 "), Ok(-45.0));
+        assert_eq!(config.parse_speed("fast"), Err(SpinError::InvalidSpeed));
+        assert_eq!(config.parse_speed("inf"), Err(SpinError::InvalidSpeed));
+        assert_eq!(config.parse_speed("180"), Err(SpinError::TooFast(90.0)));
+
+        let mut spin = Spin::new(90.0);
+        let quarter = std::f32::consts::FRAC_PI_2;
+        assert_eq!(spin.advance(Duration::from_secs(1)), quarter);
+        spin.set_speed(-90.0);
+        assert_eq!(spin.advance(Duration::from_secs(2)), 3.0 * quarter);
+        spin.set_speed(0.0);
+        assert!(!spin.is_spinning());
+        assert_eq!(spin.advance(Duration::from_secs(5)), 3.0 * quarter);
+    }
+}
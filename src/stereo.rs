@@ -0,0 +1,95 @@
+use crate::{Raytracer, Renderer};
+use image::RgbImage;
+use serde::Deserialize;
+
+/// How the images of both eyes are put together.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StereoMode {
+    /// The left eye on the left half, the right eye on the right half, for
+    /// VR headsets and cross-eyed viewing.
+    SideBySide,
+    /// Red-cyan, for the paper glasses: the red channel of the left eye, the
+    /// green and blue ones of the right eye.
+    Anaglyph,
+}
+
+/// Renders the canvas of `raytracer` from both eyes, `separation` apart as a
+/// share of the distance to what the camera looks at, and puts them together
+/// as `mode` says. Both eyes look at the same point, which sits on the
+/// screen, the closer cubes popping out. The depth is that of the left eye,
+/// and of both side by side.
+pub fn render_stereo(
+    raytracer: &Raytracer,
+    mode: StereoMode,
+    separation: f32,
+) -> (RgbImage, Vec<f32>) {
+    let camera = raytracer.camera();
+    let offset = separation * camera.focus() / 2.0;
+    let mut eye = raytracer.clone();
+    eye.set_camera(Some(camera.sideways(-offset)));
+    let (left, left_depth) = eye.render_with_depth();
+    eye.set_camera(Some(camera.sideways(offset)));
+    let (right, right_depth) = eye.render_with_depth();
+    match mode {
+        StereoMode::SideBySide => {
+            let width = left.width() as usize;
+            let depth = left_depth
+                .chunks(width)
+                .zip(right_depth.chunks(width))
+                .flat_map(|(l, r)| l.iter().chain(r.iter()).copied())
+                .collect();
+            (side_by_side(&left, &right), depth)
+        }
+        StereoMode::Anaglyph => (anaglyph(&left, &right), left_depth),
+    }
+}
+
+fn side_by_side(left: &RgbImage, right: &RgbImage) -> RgbImage {
+    let width = left.width();
+    RgbImage::from_fn(width * 2, left.height(), |x, y| match x < width {
+        true => *left.get_pixel(x, y),
+        false => *right.get_pixel(x - width, y),
+    })
+}
+
+fn anaglyph(left: &RgbImage, right: &RgbImage) -> RgbImage {
+    RgbImage::from_fn(left.width(), left.height(), |x, y| {
+        let (l, r) = (left.get_pixel(x, y).0, right.get_pixel(x, y).0);
+        image::Rgb([l[0], r[1], r[2]])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Colour, Cube};
+
+    #[test]
+    fn test_stereo() {
+        let mut raytracer = Raytracer::new(16, 9, 1);
+        for i in 0..27 {
+            let cube = Cube::new(3 + i % 3, 3 + i / 3 % 3, 3 + i / 9, Colour::new(255, 0, 0));
+            raytracer.add_cube(&cube);
+        }
+
+        let (img, depth) = render_stereo(&raytracer, StereoMode::SideBySide, 0.2);
+        assert_eq!(img.dimensions(), (32, 16));
+        assert_eq!(depth.len(), 32 * 16);
+        // The eyes see the cubes in the middle from either side.
+        let (left, right) = (img.get_pixel(8, 8), img.get_pixel(24, 8));
+        assert_ne!(left.0, [250, 250, 250]);
+        assert_ne!(right.0, [250, 250, 250]);
+
+        let (img, depth) = render_stereo(&raytracer, StereoMode::Anaglyph, 0.2);
+        assert_eq!(img.dimensions(), (16, 16));
+        assert_eq!(depth.len(), 16 * 16);
+
+        let red = RgbImage::from_pixel(2, 1, image::Rgb([200, 10, 20]));
+        let cyan = RgbImage::from_pixel(2, 1, image::Rgb([30, 40, 50]));
+        assert_eq!(anaglyph(&red, &cyan).get_pixel(1, 0).0, [200, 40, 50]);
+        let both = side_by_side(&red, &cyan);
+        assert_eq!(both.get_pixel(1, 0).0, [200, 10, 20]);
+        assert_eq!(both.get_pixel(2, 0).0, [30, 40, 50]);
+    }
+}
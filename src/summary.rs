@@ -0,0 +1,184 @@
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+// Separates the parts of the multipart body posted to the webhook.
+const BOUNDARY: &str = "twixelbox-summary";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SummaryConfig {
+    /// Local times the summary is posted at, e.g. `09:00` every day, or
+    /// `sun 18:00` every week. It covers the day, or the week, before.
+    pub times: Vec<String>,
+    /// Posts the summary in chat.
+    pub chat: bool,
+    /// Discord webhook the summary is also posted to, with the snapshot.
+    pub discord_webhook: Option<String>,
+    /// Builders named in the summary, the busiest first.
+    pub top_builders: usize,
+    /// Renders a snapshot before posting the summary to the webhook, and how
+    /// long to wait for it.
+    pub snapshot: bool,
+    pub snapshot_wait_secs: u64,
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        Self {
+            times: Vec::new(),
+            chat: true,
+            discord_webhook: None,
+            top_builders: 3,
+            snapshot: true,
+            snapshot_wait_secs: 60,
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SummaryError {
+    #[error("invalid summary time {0}, expected e.g. 09:00 or sun 18:00")]
+    InvalidTime(String),
+}
+
+/// When a summary is posted: every day at a time, or every week on a day at a
+/// time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SummaryTime {
+    weekday: Option<Weekday>,
+    time: NaiveTime,
+}
+
+impl SummaryTime {
+    /// Seconds the summary covers, a day or a week.
+    pub fn period_secs(&self) -> i64 {
+        match self.weekday {
+            Some(_) => 7 * 24 * 3600,
+            None => 24 * 3600,
+        }
+    }
+
+    /// First time it's due after `now`, both local.
+    pub fn next_after(&self, now: NaiveDateTime) -> NaiveDateTime {
+        let mut next = now.date().and_time(self.time);
+        if next <= now {
+            next += Duration::days(1);
+        }
+        if let Some(weekday) = self.weekday {
+            while next.weekday() != weekday {
+                next += Duration::days(1);
+            }
+        }
+        next
+    }
+}
+
+impl FromStr for SummaryTime {
+    type Err = SummaryError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || SummaryError::InvalidTime(value.to_owned());
+        let mut words = value.split_whitespace().rev();
+        let time = words
+            .next()
+            .and_then(|time| NaiveTime::parse_from_str(time, "%H:%M").ok())
+            .ok_or_else(invalid)?;
+        let weekday = match words.next() {
+            Some(weekday) => Some(weekday.parse().map_err(|_| invalid())?),
+            None => None,
+        };
+        if words.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Self { weekday, time })
+    }
+}
+
+impl fmt::Display for SummaryTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(weekday) = self.weekday {
+            write!(f, "{} ", weekday)?;
+        }
+        write!(f, "{}", self.time.format("%H:%M"))
+    }
+}
+
+/// Content type and body of a message with `content` posted to a Discord
+/// webhook, with the PNG `image` attached if any.
+pub fn webhook_body(content: &str, image: Option<&[u8]>) -> (String, Vec<u8>) {
+    let payload = serde_json::json!({ "content": content });
+    let mut body = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"payload_json\"\r\n\
+         Content-Type: application/json\r\n\r\n{}\r\n",
+        BOUNDARY, payload
+    )
+    .into_bytes();
+    if let Some(image) = image {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"files[0]\"; \
+                 filename=\"twixelbox.png\"\r\nContent-Type: image/png\r\n\r\n",
+                BOUNDARY
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(image);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+    (format!("multipart/form-data; boundary={}", BOUNDARY), body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_summary_time() {
+        let daily: SummaryTime = "09:00".parse().unwrap();
+        let weekly: SummaryTime = " Sun  18:30".parse().unwrap();
+        assert_eq!(daily.period_secs(), 24 * 3600);
+        assert_eq!(weekly.period_secs(), 7 * 24 * 3600);
+        assert_eq!(weekly.to_string(), "Sun 18:30");
+        for invalid in &["", "9h", "25:00", "someday 09:00", "every sun 09:00"] {
+            assert_eq!(
+                invalid.parse::<SummaryTime>(),
+                Err(SummaryError::InvalidTime(invalid.to_string()))
+            );
+        }
+
+        // A Wednesday.
+        let at = |day: u32, h: u32, m: u32| {
+            NaiveDate::from_ymd_opt(2021, 3, day)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+        };
+        assert_eq!(daily.next_after(at(10, 8, 0)), at(10, 9, 0));
+        assert_eq!(daily.next_after(at(10, 9, 0)), at(11, 9, 0));
+        assert_eq!(weekly.next_after(at(10, 8, 0)), at(14, 18, 30));
+        assert_eq!(weekly.next_after(at(14, 18, 30)), at(21, 18, 30));
+    }
+
+    #[test]
+    fn test_webhook_body() {
+        let (content_type, body) = webhook_body("5 \"cubes\"", Some(b"png"));
+        assert_eq!(
+            content_type,
+            "multipart/form-data; boundary=twixelbox-summary"
+        );
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.starts_with("--twixelbox-summary\r\n"));
+        assert!(body.contains(r#"{"content":"5 \"cubes\""}"#));
+        assert!(
+            body.contains("filename=\"twixelbox.png\"\r\nContent-Type: image/png\r\n\r\npng\r\n")
+        );
+        assert!(body.ends_with("--twixelbox-summary--\r\n"));
+        let (_, body) = webhook_body("5 cubes", None);
+        assert!(!String::from_utf8(body).unwrap().contains("files[0]"));
+    }
+}
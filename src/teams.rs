@@ -0,0 +1,244 @@
+use crate::{CanvasEvent, Colour, CubeError, Position};
+use image::{Rgb, RgbImage};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TeamConfig {
+    pub name: String,
+    /// Colour of the team on the scoreboard, as a name or hex code.
+    pub colour: String,
+}
+
+/// The teams chatters can join, with their colours.
+#[derive(Clone, Debug)]
+pub struct Teams {
+    teams: Vec<(String, Colour)>,
+}
+
+impl Teams {
+    pub fn new(config: &[TeamConfig]) -> Result<Self, CubeError> {
+        let teams = config
+            .iter()
+            .map(|team| Ok((team.name.to_lowercase(), team.colour.parse()?)))
+            .collect::<Result<_, CubeError>>()?;
+        Ok(Self { teams })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.teams.is_empty()
+    }
+
+    /// Case insensitive lookup of a team, returns its canonical name.
+    pub fn find(&self, name: &str) -> Option<&str> {
+        let name = name.trim().to_lowercase();
+        self.teams
+            .iter()
+            .find(|(team, _)| *team == name)
+            .map(|(team, _)| team.as_str())
+    }
+
+    pub fn colour(&self, name: &str) -> Option<Colour> {
+        self.teams
+            .iter()
+            .find(|(team, _)| team == name)
+            .map(|(_, colour)| *colour)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.teams.iter().map(|(team, _)| team.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TeamScore {
+    pub team: String,
+    pub cubes: usize,
+    /// Number of separate groups of touching cubes.
+    pub regions: usize,
+    /// Cubes in the largest of those groups.
+    pub largest_region: usize,
+}
+
+/// Tracks which team owns each cube of the canvas, i.e. the team of whoever
+/// placed it last.
+#[derive(Clone, Debug, Default)]
+pub struct TeamTracker {
+    owners: HashMap<Position, String>,
+}
+
+impl TeamTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mirrors an event applied to the canvas, `team` being the team of its
+    /// author, if any.
+    pub fn apply(&mut self, event: &CanvasEvent, team: Option<&str>) {
+        match event {
+            CanvasEvent::CubePlaced(cube) => match team {
+                Some(team) => {
+                    self.owners.insert(cube.position, team.to_owned());
+                }
+                None => {
+                    self.owners.remove(&cube.position);
+                }
+            },
+            CanvasEvent::CubeRemoved(position) => {
+                self.owners.remove(position);
+            }
+            CanvasEvent::CanvasCleared => self.owners.clear(),
+            // Recolouring doesn't change hands.
+            CanvasEvent::Recoloured { .. } => {}
+        }
+    }
+
+    /// Scores of the given teams, best first. Teams are ranked by cubes, then
+    /// by largest region.
+    pub fn scoreboard<'a>(&self, teams: impl Iterator<Item = &'a str>) -> Vec<TeamScore> {
+        let mut scores: Vec<TeamScore> = teams
+            .map(|team| {
+                let positions: HashSet<Position> = self
+                    .owners
+                    .iter()
+                    .filter(|(_, owner)| *owner == team)
+                    .map(|(position, _)| *position)
+                    .collect();
+                let regions = connected_regions(&positions);
+                TeamScore {
+                    team: team.to_owned(),
+                    cubes: positions.len(),
+                    regions: regions.len(),
+                    largest_region: regions.into_iter().max().unwrap_or(0),
+                }
+            })
+            .collect();
+        scores.sort_by(|a, b| {
+            b.cubes
+                .cmp(&a.cubes)
+                .then(b.largest_region.cmp(&a.largest_region))
+        });
+        scores
+    }
+}
+
+// Sizes of the groups of cubes touching by a face.
+fn connected_regions(positions: &HashSet<Position>) -> Vec<usize> {
+    let mut visited = HashSet::new();
+    let mut regions = Vec::new();
+    for start in positions {
+        if !visited.insert(*start) {
+            continue;
+        }
+        let mut size = 0;
+        let mut stack = vec![*start];
+        while let Some(position) = stack.pop() {
+            size += 1;
+            let neighbours = [
+                (-1, 0, 0),
+                (1, 0, 0),
+                (0, -1, 0),
+                (0, 1, 0),
+                (0, 0, -1),
+                (0, 0, 1),
+            ];
+            for (dx, dy, dz) in neighbours.iter() {
+                if let Some(neighbour) = position.offset(*dx, *dy, *dz) {
+                    if positions.contains(&neighbour) && visited.insert(neighbour) {
+                        stack.push(neighbour);
+                    }
+                }
+            }
+        }
+        regions.push(size);
+    }
+    regions
+}
+
+/// Draws the scoreboard in the top left corner of the image, as one bar per
+/// team in its colour, proportional to its share of the cubes.
+pub fn draw_scoreboard(img: &mut RgbImage, teams: &Teams, scores: &[TeamScore]) {
+    let total: usize = scores.iter().map(|score| score.cubes).sum();
+    let margin = (img.height() / 60).max(2);
+    let bar_height = (img.height() / 40).max(2);
+    let max_width = img.width() / 4;
+    for (i, score) in scores.iter().enumerate() {
+        let colour = match teams.colour(&score.team) {
+            Some(colour) => colour,
+            None => continue,
+        };
+        let share = if total == 0 {
+            0.0
+        } else {
+            score.cubes as f32 / total as f32
+        };
+        // Teams with no cubes still show a sliver, so every team is listed.
+        let width = ((max_width as f32 * share) as u32).max(2);
+        let top = margin + i as u32 * (bar_height + margin);
+        for y in top..(top + bar_height).min(img.height()) {
+            for x in margin..(margin + width).min(img.width()) {
+                img.put_pixel(x, y, Rgb([colour.r, colour.g, colour.b]));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cube;
+
+    fn place(tracker: &mut TeamTracker, x: u32, y: u32, z: u32, team: &str) {
+        let event = CanvasEvent::CubePlaced(Cube::new(x, y, z, Colour::new(0, 0, 0)));
+        tracker.apply(&event, Some(team));
+    }
+
+    #[test]
+    fn test_scoreboard() {
+        let teams = Teams::new(&[
+            TeamConfig {
+                name: "Red".to_owned(),
+                colour: "red".to_owned(),
+            },
+            TeamConfig {
+                name: "blue".to_owned(),
+                colour: "#00f".to_owned(),
+            },
+        ])
+        .unwrap();
+        assert_eq!(teams.find("RED"), Some("red"));
+        assert_eq!(teams.find("green"), None);
+
+        let mut tracker = TeamTracker::new();
+        place(&mut tracker, 0, 0, 0, "red");
+        place(&mut tracker, 0, 0, 1, "red");
+        place(&mut tracker, 5, 5, 5, "red");
+        place(&mut tracker, 1, 1, 1, "blue");
+        place(&mut tracker, 2, 2, 2, "blue");
+        // Taken over by blue.
+        place(&mut tracker, 5, 5, 5, "blue");
+        tracker.apply(&CanvasEvent::CubeRemoved(Position::new(2, 2, 2)), None);
+
+        assert_eq!(
+            tracker.scoreboard(teams.names()),
+            vec![
+                TeamScore {
+                    team: "red".to_owned(),
+                    cubes: 2,
+                    regions: 1,
+                    largest_region: 2,
+                },
+                TeamScore {
+                    team: "blue".to_owned(),
+                    cubes: 2,
+                    regions: 2,
+                    largest_region: 1,
+                },
+            ]
+        );
+
+        let mut img = RgbImage::new(100, 100);
+        draw_scoreboard(&mut img, &teams, &tracker.scoreboard(teams.names()));
+        assert_eq!(img.get_pixel(2, 2), &Rgb([255, 0, 0]));
+    }
+}
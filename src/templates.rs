@@ -0,0 +1,145 @@
+use crate::{generate_terrain, Colour, Cube, Position};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum TemplateError {
+    #[error("unknown template {0}, try border, terrain or grid")]
+    UnknownTemplate(String),
+}
+
+/// Starter canvas, generated into a fresh archive with `init`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CanvasTemplate {
+    /// Empty, framed by the edges of the canvas.
+    Border,
+    /// Hills and lakes on the bottom of the canvas, see `generate_terrain`.
+    Terrain,
+    /// Guide lines on the bottom of the canvas every few cubes, and the axes
+    /// from the origin of the canvas in red, green and blue.
+    Grid,
+}
+
+impl FromStr for CanvasTemplate {
+    type Err = TemplateError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "border" => Ok(CanvasTemplate::Border),
+            "terrain" => Ok(CanvasTemplate::Terrain),
+            "grid" => Ok(CanvasTemplate::Grid),
+            _ => Err(TemplateError::UnknownTemplate(value.to_owned())),
+        }
+    }
+}
+
+const BORDER: Colour = Colour::new(60, 60, 60);
+const GUIDE: Colour = Colour::new(200, 200, 200);
+// Cubes between two guide lines of the grid.
+const GRID_SPACING: u32 = 8;
+
+impl CanvasTemplate {
+    /// Cubes of the template on a canvas of side `side_len`, only on the
+    /// z = 0 plane when `flat`, as in pixel art. `seed` varies the terrain.
+    pub fn cubes(&self, side_len: u32, flat: bool, seed: u64) -> Vec<Cube> {
+        if side_len == 0 {
+            return Vec::new();
+        }
+        match self {
+            CanvasTemplate::Border => border(side_len, flat),
+            CanvasTemplate::Terrain => generate_terrain(side_len, flat, seed),
+            CanvasTemplate::Grid => grid(side_len, flat),
+        }
+    }
+}
+
+// The edges of the canvas, those of the plane when flat.
+fn border(side_len: u32, flat: bool) -> Vec<Cube> {
+    let last = side_len - 1;
+    let on_side = |c: u32| c == 0 || c == last;
+    let depth = if flat { 1 } else { side_len };
+    let mut cubes = Vec::new();
+    for x in 0..side_len {
+        for y in 0..side_len {
+            for z in 0..depth {
+                let sides = [x, y, z].iter().filter(|c| on_side(**c)).count();
+                let edge = match flat {
+                    true => on_side(x) || on_side(y),
+                    false => sides >= 2,
+                };
+                if edge {
+                    cubes.push(Cube::new(x, y, z, BORDER));
+                }
+            }
+        }
+    }
+    cubes
+}
+
+fn grid(side_len: u32, flat: bool) -> Vec<Cube> {
+    let last = side_len - 1;
+    let on_line = |c: u32| c.is_multiple_of(GRID_SPACING) || c == last;
+    let mut cubes = Vec::new();
+    for a in 0..side_len {
+        for b in 0..side_len {
+            if !on_line(a) && !on_line(b) {
+                continue;
+            }
+            let position = match flat {
+                true => Position::new(a, b, 0),
+                false => Position::new(a, last, b),
+            };
+            // The axes from the origin, x in red, z in blue, or y when flat.
+            let colour = match (a, b) {
+                (_, 0) => Colour::new(220, 50, 50),
+                (0, _) if flat => Colour::new(50, 180, 50),
+                (0, _) => Colour::new(50, 80, 220),
+                _ => GUIDE,
+            };
+            cubes.push(Cube { position, colour });
+        }
+    }
+    if !flat {
+        // A vertical guide on the corner of the grid.
+        for y in 0..last {
+            cubes.push(Cube::new(0, y, 0, Colour::new(50, 180, 50)));
+        }
+    }
+    cubes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_templates() {
+        assert_eq!("Terrain".parse(), Ok(CanvasTemplate::Terrain));
+        assert_eq!(
+            "castle".parse::<CanvasTemplate>(),
+            Err(TemplateError::UnknownTemplate("castle".to_owned()))
+        );
+
+        // The 8 corners and 2 cubes on each of the 12 edges.
+        assert_eq!(CanvasTemplate::Border.cubes(4, false, 0).len(), 32);
+        assert_eq!(CanvasTemplate::Border.cubes(4, true, 0).len(), 12);
+
+        for template in [
+            CanvasTemplate::Border,
+            CanvasTemplate::Terrain,
+            CanvasTemplate::Grid,
+        ] {
+            for flat in [false, true] {
+                let cubes = template.cubes(16, flat, 7);
+                assert!(!cubes.is_empty());
+                let positions: HashSet<Position> = cubes.iter().map(|c| c.position).collect();
+                assert_eq!(positions.len(), cubes.len(), "{:?} overlaps", template);
+                assert!(cubes.iter().all(|c| {
+                    let p = c.position;
+                    p.x < 16 && p.y < 16 && p.z < 16 && (!flat || p.z == 0)
+                }));
+            }
+        }
+    }
+}
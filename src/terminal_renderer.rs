@@ -0,0 +1,158 @@
+use crate::renderer::Renderer;
+use crate::{Background, Colour, Cube, Position};
+use image::imageops::FilterType;
+use image::{Rgb, RgbImage};
+use std::collections::HashMap;
+use std::io::Write;
+
+// Draws a coarse isometric projection of the canvas as coloured half-block
+// characters on the terminal. Each character cell holds two square pixels, the
+// upper one as foreground and the lower one as background colour. The same
+// pixels, upscaled, are returned as the snapshot for a retro "terminal cam".
+pub struct TerminalRenderer {
+    columns: u32,
+    snapshot_size: u32,
+    frame_side_len: u32,
+    cubes: HashMap<Position, Colour>,
+    // Only on the snapshots, the terminal keeps its own.
+    background: Background,
+}
+
+impl TerminalRenderer {
+    pub fn new(columns: u32, snapshot_size: u32, frame_side_len: u32) -> Self {
+        Self {
+            columns,
+            snapshot_size,
+            frame_side_len,
+            cubes: HashMap::new(),
+            background: Background::default(),
+        }
+    }
+
+    // Projects the cubes into a `columns` x `columns` grid of pixels, keeping
+    // for each pixel the colour of the cube closest to the viewer.
+    fn project(&self) -> Vec<Option<Colour>> {
+        let side = self.columns as i64;
+        let frame = self.frame_side_len as i64;
+        let mut depths = vec![i64::MIN; (side * side) as usize];
+        let mut pixels = vec![None; (side * side) as usize];
+        for (position, &colour) in &self.cubes {
+            let (x, y, z) = (position.x as i64, position.y as i64, position.z as i64);
+            // The viewer looks from the front right, slightly from above, so
+            // the greater x + z and the smaller y, the closer the cube.
+            let screen_x = x - z + frame;
+            let screen_y = y + (x + z) / 2;
+            let column = screen_x * side / (2 * frame);
+            let row = screen_y * side / (2 * frame);
+            if column < 0 || column >= side || row < 0 || row >= side {
+                continue;
+            }
+            let depth = x + z - y;
+            let index = (row * side + column) as usize;
+            if pixels[index].is_none() || depths[index] < depth {
+                depths[index] = depth;
+                pixels[index] = Some(colour);
+            }
+        }
+        pixels
+    }
+
+    /// Returns the frame as lines of ANSI escaped half-block characters.
+    pub fn ansi_frame(&self) -> String {
+        let pixels = self.project();
+        let side = self.columns as usize;
+        let mut frame = String::new();
+        for row in (0..side).step_by(2) {
+            for column in 0..side {
+                let top = pixels[row * side + column];
+                let bottom = pixels.get((row + 1) * side + column).copied().flatten();
+                match top {
+                    Some(Colour { r, g, b, .. }) => {
+                        frame.push_str(&format!("\x1b[38;2;{};{};{}m", r, g, b))
+                    }
+                    None => frame.push_str("\x1b[39m"),
+                }
+                match bottom {
+                    Some(Colour { r, g, b, .. }) => {
+                        frame.push_str(&format!("\x1b[48;2;{};{};{}m", r, g, b))
+                    }
+                    None => frame.push_str("\x1b[49m"),
+                }
+                frame.push(if top.is_some() { '▀' } else { ' ' });
+            }
+            frame.push_str("\x1b[0m\n");
+        }
+        frame
+    }
+}
+
+impl Renderer for TerminalRenderer {
+    fn add_cube(&mut self, cube: &Cube) {
+        self.cubes.insert(cube.position, cube.colour);
+    }
+
+    fn remove_cube(&mut self, position: Position) {
+        self.cubes.remove(&position);
+    }
+
+    fn clear(&mut self) {
+        self.cubes.clear();
+    }
+
+    fn set_background(&mut self, background: &Background) {
+        self.background = background.clone();
+    }
+
+    fn render(&mut self) -> Option<RgbImage> {
+        // Move the cursor home so that each frame is drawn over the previous.
+        let mut stdout = std::io::stdout();
+        if write!(stdout, "\x1b[H{}", self.ansi_frame())
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            return None;
+        }
+
+        let pixels = self.project();
+        let img = RgbImage::from_fn(self.columns, self.columns, |x, y| {
+            let Colour { r, g, b, .. } = match pixels[(y * self.columns + x) as usize] {
+                Some(colour) => colour,
+                None => self
+                    .background
+                    .at_height((y as f32 + 0.5) / self.columns as f32),
+            };
+            Rgb([r, g, b])
+        });
+        Some(image::imageops::resize(
+            &img,
+            self.snapshot_size,
+            self.snapshot_size,
+            FilterType::Nearest,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_closest_cube_wins() {
+        let mut renderer = TerminalRenderer::new(2, 2, 4);
+        renderer.add_cube(&Cube::new(0, 0, 0, Colour::new(1, 1, 1)));
+        renderer.add_cube(&Cube::new(1, 0, 1, Colour::new(2, 2, 2)));
+        assert_eq!(
+            renderer.project(),
+            vec![None, Some(Colour::new(2, 2, 2)), None, None]
+        );
+    }
+
+    #[test]
+    fn test_ansi_frame() {
+        let mut renderer = TerminalRenderer::new(2, 2, 4);
+        renderer.add_cube(&Cube::new(0, 0, 0, Colour::new(1, 2, 3)));
+        assert_eq!(
+            renderer.ansi_frame(),
+            "\x1b[39m\x1b[49m \x1b[38;2;1;2;3m\x1b[49m▀\x1b[0m\n"
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{Colour, Cube};
+
+const WATER: Colour = Colour::new(60, 110, 200);
+const SAND: Colour = Colour::new(220, 200, 140);
+const GRASS: Colour = Colour::new(86, 160, 60);
+const DIRT: Colour = Colour::new(120, 85, 50);
+const ROCK: Colour = Colour::new(128, 128, 128);
+const SNOW: Colour = Colour::new(240, 240, 245);
+// Layers of noise summed up, each twice finer and half as strong.
+const OCTAVES: u32 = 4;
+// Perlin noise in 2D stays within about ±0.7, stretched to ±1.
+const NOISE_RANGE: f32 = 0.7;
+// Share of the canvas the terrain rises to.
+const MAX_HEIGHT: f32 = 0.4;
+// Share of the terrain's height under water.
+const WATER_LEVEL: f32 = 0.3;
+
+// Gradient noise of Ken Perlin, in 2D, shuffled by a seed.
+struct Perlin {
+    // The shuffled 0..=255 twice over, so that hashes need no wrapping.
+    permutation: Vec<u8>,
+}
+
+impl Perlin {
+    fn new(seed: u64) -> Self {
+        let rng = fastrand::Rng::with_seed(seed);
+        let mut values: Vec<u8> = (0..=255).collect();
+        rng.shuffle(&mut values);
+        Self {
+            permutation: values.iter().chain(values.iter()).copied().collect(),
+        }
+    }
+
+    // Between -NOISE_RANGE and NOISE_RANGE, 0 on whole coordinates.
+    fn noise(&self, x: f32, y: f32) -> f32 {
+        let (xi, yi) = (
+            (x.floor() as i64 & 255) as usize,
+            (y.floor() as i64 & 255) as usize,
+        );
+        let (xf, yf) = (x - x.floor(), y - y.floor());
+        let (u, v) = (fade(xf), fade(yf));
+        let p = &self.permutation;
+        let hash = |i: usize, j: usize| p[p[i] as usize + j];
+        let lerp = |t: f32, a: f32, b: f32| a + t * (b - a);
+        lerp(
+            v,
+            lerp(
+                u,
+                gradient(hash(xi, yi), xf, yf),
+                gradient(hash(xi + 1, yi), xf - 1.0, yf),
+            ),
+            lerp(
+                u,
+                gradient(hash(xi, yi + 1), xf, yf - 1.0),
+                gradient(hash(xi + 1, yi + 1), xf - 1.0, yf - 1.0),
+            ),
+        )
+    }
+
+    // Octaves of noise, between 0 and 1.
+    fn fractal(&self, x: f32, y: f32) -> f32 {
+        let (mut total, mut amplitude, mut frequency, mut max) = (0.0, 1.0, 1.0, 0.0);
+        for _ in 0..OCTAVES {
+            total += self.noise(x * frequency, y * frequency) * amplitude;
+            max += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        (total / max / NOISE_RANGE * 0.5 + 0.5).clamp(0.0, 1.0)
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+// Dot product with one of 8 directions picked by the hash.
+fn gradient(hash: u8, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+// Colour of the surface of a column, by its height between 0 and 1 relative
+// to the highest the terrain goes.
+fn surface(height: f32) -> Colour {
+    match height {
+        h if h < WATER_LEVEL + 0.1 => SAND,
+        h if h < 0.6 => GRASS,
+        h if h < 0.8 => ROCK,
+        _ => SNOW,
+    }
+}
+
+// Colour of the ground `depth` cubes under a surface.
+fn underground(surface: Colour, depth: u32) -> Colour {
+    match (surface, depth) {
+        (surface, 0) => surface,
+        (GRASS, 1..=2) | (SAND, 1..=2) => DIRT,
+        _ => ROCK,
+    }
+}
+
+/// Hills and lakes from Perlin noise filling the bottom of a canvas of side
+/// `side_len`, up to 40% of it, coloured by height: sand by the water, then
+/// grass, rock and snow. Seen from above on the z = 0 plane when `flat`, as
+/// in pixel art. The same `seed` gives the same terrain.
+pub fn generate_terrain(side_len: u32, flat: bool, seed: u64) -> Vec<Cube> {
+    if side_len == 0 {
+        return Vec::new();
+    }
+    let perlin = Perlin::new(seed);
+    // About four hills across the canvas.
+    let scale = 4.0 / side_len as f32;
+    let max_height = ((side_len as f32 * MAX_HEIGHT) as u32).max(1);
+    let heights: Vec<u32> = (0..side_len * side_len)
+        .map(|i| {
+            let (a, b) = (i % side_len, i / side_len);
+            let noise = perlin.fractal(a as f32 * scale, b as f32 * scale);
+            1 + (noise * (max_height - 1) as f32).round() as u32
+        })
+        .collect();
+    let height_at = |a: u32, b: u32| match a < side_len && b < side_len {
+        true => heights[(b * side_len + a) as usize],
+        false => 0,
+    };
+    let water_level = (max_height as f32 * WATER_LEVEL).round() as u32;
+    let relative = |height: u32| height as f32 / max_height as f32;
+    let columns = (0..side_len).flat_map(|b| (0..side_len).map(move |a| (a, b)));
+    if flat {
+        return columns
+            .map(|(x, y)| {
+                let colour = match height_at(x, y) < water_level {
+                    true => WATER,
+                    false => surface(relative(height_at(x, y))),
+                };
+                Cube::new(x, y, 0, colour)
+            })
+            .collect();
+    }
+    let last = side_len - 1;
+    let mut cubes = Vec::new();
+    for (x, z) in columns {
+        let height = height_at(x, z);
+        let top = surface(relative(height));
+        // Only the cubes which can be seen are placed: down to the lowest
+        // neighbouring column, and all the way on the sides of the canvas.
+        let lowest = [
+            height_at(x.wrapping_sub(1), z),
+            height_at(x + 1, z),
+            height_at(x, z.wrapping_sub(1)),
+            height_at(x, z + 1),
+        ]
+        .iter()
+        .copied()
+        .min()
+        .unwrap_or(0);
+        let visible = (height - lowest.min(height - 1)).max(3).min(height);
+        for depth in 0..visible {
+            // The canvas grows downwards, the ground is at its bottom.
+            let y = last - (height - 1 - depth);
+            cubes.push(Cube::new(x, y, z, underground(top, depth)));
+        }
+        for level in height..water_level {
+            cubes.push(Cube::new(x, last - level, z, WATER));
+        }
+    }
+    cubes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_terrain() {
+        let perlin = Perlin::new(3);
+        assert_eq!(perlin.noise(2.0, 5.0), 0.0);
+        assert!((0..100).all(|i| {
+            let noise = perlin.fractal(i as f32 * 0.37, i as f32 * 0.11);
+            (0.0..=1.0).contains(&noise)
+        }));
+
+        let terrain = generate_terrain(32, false, 7);
+        assert_eq!(terrain, generate_terrain(32, false, 7));
+        assert_ne!(terrain, generate_terrain(32, false, 8));
+        // In the lower 40% of the canvas, which grows downwards.
+        assert!(terrain.iter().all(|c| c.position.y >= 19));
+        // The columns on the sides stand on the bottom of the canvas.
+        let sides: HashSet<(u32, u32)> = terrain
+            .iter()
+            .filter(|c| c.position.y == 31 && (c.position.x == 0 || c.position.x == 31))
+            .map(|c| (c.position.x, c.position.z))
+            .collect();
+        assert_eq!(sides.len(), 2 * 32);
+        let colours: HashSet<Colour> = terrain.iter().map(|c| c.colour).collect();
+        assert!(colours.contains(&GRASS) && colours.contains(&ROCK));
+
+        let flat = generate_terrain(32, true, 7);
+        assert_eq!(flat.len(), 32 * 32);
+        assert!(flat.iter().all(|c| c.position.z == 0));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{Canvas, JournalEntry, Raytracer, Renderer, Watermark};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame, ImageResult};
+use serde::Deserialize;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct TimelapseConfig {
+    /// Hours of events in the timelapse, also how often it's generated.
+    pub hours: u64,
+    /// Events applied between two frames.
+    pub events_per_frame: usize,
+    /// Caps the length of the timelapse on busy days, by putting more events
+    /// in each frame.
+    pub max_frames: usize,
+    pub resolution: u32,
+    /// Rays traced per pixel, see the snapshot configuration.
+    pub samples: u32,
+    pub frame_delay_ms: u32,
+}
+
+impl Default for TimelapseConfig {
+    fn default() -> Self {
+        Self {
+            hours: 24,
+            events_per_frame: 20,
+            max_frames: 120,
+            resolution: 256,
+            samples: 1,
+            frame_delay_ms: 150,
+        }
+    }
+}
+
+/// Renders an animated GIF of the events journaled since the `since` unix
+/// timestamp, starting from the canvas as it was at that time, with the
+/// watermark on every frame if any.
+pub fn render_timelapse(
+    journal: &[JournalEntry],
+    since: i64,
+    config: &TimelapseConfig,
+    frame_side_len: u32,
+    watermark: Option<&Watermark>,
+) -> ImageResult<Vec<u8>> {
+    let mut canvas = Canvas::new(u32::MAX);
+    let mut raytracer = Raytracer::new(config.resolution, frame_side_len, config.samples);
+    let recent = journal
+        .iter()
+        .filter(|entry| entry.metadata.timestamp >= since)
+        .count();
+    let events_per_frame = config
+        .events_per_frame
+        .max(1)
+        .max(recent.div_ceil(config.max_frames.max(1)));
+    let delay = Delay::from_numer_denom_ms(config.frame_delay_ms, 1);
+    let frame = |raytracer: &Raytracer| {
+        let mut img = raytracer.render_image();
+        if let Some(watermark) = watermark {
+            watermark.draw(&mut img);
+        }
+        let img = DynamicImage::ImageRgb8(img).into_rgba8();
+        Frame::from_parts(img, 0, 0, delay)
+    };
+
+    let mut frames = Vec::new();
+    let mut pending = 0;
+    for entry in journal {
+        if canvas.apply(&entry.event).is_err() {
+            continue;
+        }
+        raytracer.apply_event(&entry.event);
+        if entry.metadata.timestamp < since {
+            continue;
+        }
+        if frames.is_empty() && pending == 0 {
+            // The canvas at the start of the period.
+            frames.push(frame(&raytracer));
+        }
+        pending += 1;
+        if pending == events_per_frame {
+            frames.push(frame(&raytracer));
+            pending = 0;
+        }
+    }
+    if pending > 0 || frames.is_empty() {
+        frames.push(frame(&raytracer));
+    }
+
+    let mut gif = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut gif);
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames)?;
+    }
+    Ok(gif)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanvasEvent, Colour, Cube, EventMetadata};
+    use image::codecs::gif::GifDecoder;
+    use image::AnimationDecoder;
+    use uuid::Uuid;
+
+    fn entry(x: u32, timestamp: i64) -> JournalEntry {
+        JournalEntry {
+            command_id: Uuid::new_v4(),
+            event: CanvasEvent::CubePlaced(Cube::new(x, 0, 0, Colour::new(255, 0, 0))),
+            metadata: EventMetadata {
+                timestamp,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_render_timelapse() {
+        let journal = vec![entry(0, 10), entry(1, 100), entry(2, 110), entry(3, 120)];
+        let config = TimelapseConfig {
+            events_per_frame: 2,
+            resolution: 8,
+            ..Default::default()
+        };
+        let gif = render_timelapse(&journal, 100, &config, 10, None).unwrap();
+        let frames = GifDecoder::new(&gif[..])
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        // Start, after 2 events, and the last event.
+        assert_eq!(frames.len(), 3);
+    }
+}
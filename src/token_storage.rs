@@ -125,6 +125,11 @@ impl CustomTokenStorage {
         self.write_stored_token(stored_token)
     }
 
+    /// Twitch user id of the account the stored token belongs to.
+    pub fn load_user_id(&self) -> Result<String, std::io::Error> {
+        Ok(self.load_stored_token()?.user_id)
+    }
+
     fn load_stored_token(&self) -> Result<StoredUserToken, std::io::Error> {
         let token = fs::read_to_string(&self.token_checkpoint_file)?;
         let token = serde_json::from_str::<StoredUserToken>(&(token)).map_err(|_| {
@@ -0,0 +1,291 @@
+use crate::{CameraPose, Position};
+
+/// Side of the scene cube the canvas is drawn in.
+pub const SCENE_SIDE: f32 = 0.5;
+// Field of view of kiss3d's default camera, in radians.
+pub(crate) const FIELD_OF_VIEW: f32 = std::f32::consts::FRAC_PI_4;
+// Where kiss3d's default camera sits in the scene, looking at the origin.
+const EYE: [f32; 3] = [0.0, 0.0, -1.0];
+
+/// Maps the positions of a canvas to the scene the renderers draw, and back,
+/// e.g. to pick the cube under the cursor. The canvas is drawn inside a cube
+/// of side `SCENE_SIDE` centred in the origin, seen by kiss3d's camera from
+/// (0, 0, -1), so the axes of the canvas run against those of the scene:
+/// x = [0.25 (leftmost), -0.25 (rightmost)]
+/// y = [0.25 (upmost), -0.25 (downmost)]
+/// z = [0.25 (backmost), -0.25 (frontmost)]
+/// Each cube fills a cell of side `SCENE_SIDE / side_len`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SceneTransform {
+    side_len: u32,
+}
+
+impl SceneTransform {
+    /// Transform of a canvas of side `side_len`, at least 1.
+    pub fn new(side_len: u32) -> Self {
+        Self {
+            side_len: side_len.max(1),
+        }
+    }
+
+    pub fn side_len(&self) -> u32 {
+        self.side_len
+    }
+
+    /// Side of a cube in the scene.
+    pub fn cube_side(&self) -> f32 {
+        SCENE_SIDE / self.side_len as f32
+    }
+
+    /// Centre of the cube at `position` in the scene, `None` when it's
+    /// outside of the canvas.
+    pub fn scene_position(&self, position: Position) -> Option<[f32; 3]> {
+        if !position.is_within(self.side_len) {
+            return None;
+        }
+        let to_scene = |p: u32| SCENE_SIDE / 2.0 - (p as f32 + 0.5) * self.cube_side();
+        Some([
+            to_scene(position.x),
+            to_scene(position.y),
+            to_scene(position.z),
+        ])
+    }
+
+    /// Position of the cube whose cell holds `point` of the scene, the
+    /// inverse of `scene_position`. `None` when the point is outside of the
+    /// canvas.
+    pub fn canvas_position(&self, point: [f32; 3]) -> Option<Position> {
+        let [x, y, z] = self.grid_point(point);
+        let to_canvas = |g: f32| {
+            let cell = (g + 0.5).floor();
+            match cell >= 0.0 && cell < self.side_len as f32 {
+                true => Some(cell as u32),
+                false => None,
+            }
+        };
+        Some(Position::new(to_canvas(x)?, to_canvas(y)?, to_canvas(z)?))
+    }
+
+    /// `point` of the scene on the grid of the canvas, in cubes, where the
+    /// cube at `p` spans from `p - 0.5` to `p + 0.5`. The raytracer works on
+    /// this grid.
+    pub fn grid_point(&self, point: [f32; 3]) -> [f32; 3] {
+        let to_grid = |s: f32| (SCENE_SIDE / 2.0 - s) / self.cube_side() - 0.5;
+        [to_grid(point[0]), to_grid(point[1]), to_grid(point[2])]
+    }
+
+    /// Ray from the camera through `(x, y)` of the image, as fractions of its
+    /// side from the top left, on the grid of the canvas turned by `yaw`
+    /// radians as by `Renderer::set_yaw`. Returns its origin and direction,
+    /// which isn't normalised.
+    pub fn ray(&self, x: f32, y: f32, yaw: f32) -> ([f32; 3], [f32; 3]) {
+        let tan = (FIELD_OF_VIEW / 2.0).tan();
+        let dir = [(x * 2.0 - 1.0) * tan, (y * 2.0 - 1.0) * tan, -1.0];
+        // Turning the canvas one way is turning the camera the other.
+        let centre = self.centre();
+        let eye = self.grid_point(EYE);
+        let eye = turn([eye[0] - centre, eye[1], eye[2] - centre], -yaw);
+        ([eye[0] + centre, eye[1], eye[2] + centre], turn(dir, -yaw))
+    }
+
+    /// Camera of the overlay with the canvas turned by `yaw`, looking at the
+    /// centre of the canvas, as used by `ray`.
+    pub fn camera(&self, yaw: f32) -> CameraPose {
+        let camera = CameraPose {
+            position: self.grid_point(EYE),
+            target: [self.centre(); 3],
+        };
+        self.turn_camera(camera, yaw)
+    }
+
+    /// `camera` seeing the canvas turned by `yaw`, as by
+    /// `Renderer::set_yaw`: orbited the other way around the vertical axis
+    /// of the canvas.
+    pub fn turn_camera(&self, camera: CameraPose, yaw: f32) -> CameraPose {
+        let centre = self.centre();
+        let turned = |[x, y, z]: [f32; 3]| {
+            let [x, y, z] = turn([x - centre, y, z - centre], -yaw);
+            [x + centre, y, z + centre]
+        };
+        CameraPose {
+            position: turned(camera.position),
+            target: turned(camera.target),
+        }
+    }
+
+    /// `point` of the grid in the scene, the inverse of `grid_point`.
+    pub fn scene_point(&self, point: [f32; 3]) -> [f32; 3] {
+        point.map(|g| SCENE_SIDE / 2.0 - (g + 0.5) * self.cube_side())
+    }
+
+    /// Where `point` of the grid shows in the image, as fractions of its side
+    /// from the top left, with the canvas turned by `yaw`. The inverse of
+    /// `ray`, `None` when the point is behind the camera.
+    pub fn project(&self, point: [f32; 3], yaw: f32) -> Option<(f32, f32)> {
+        let centre = self.centre();
+        let [x, y, z] = turn([point[0] - centre, point[1], point[2] - centre], yaw);
+        let eye = self.grid_point(EYE);
+        let (x, y, z) = (x + centre - eye[0], y - eye[1], z + centre - eye[2]);
+        if z >= 0.0 {
+            return None;
+        }
+        let tan = (FIELD_OF_VIEW / 2.0).tan();
+        Some(((x / -z / tan + 1.0) / 2.0, (y / -z / tan + 1.0) / 2.0))
+    }
+
+    /// Cube seen at `(x, y)` of the image, see `ray`: the first position of
+    /// the canvas along the ray for which `is_filled` holds.
+    pub fn pick(
+        &self,
+        x: f32,
+        y: f32,
+        yaw: f32,
+        is_filled: impl Fn(Position) -> bool,
+    ) -> Option<Position> {
+        let (origin, dir) = self.ray(x, y, yaw);
+        self.pick_ray(origin, dir, is_filled)
+    }
+
+    /// First position of the canvas along the ray from `origin` towards
+    /// `dir` on the grid for which `is_filled` holds, e.g. from a
+    /// `CameraPose`.
+    pub fn pick_ray(
+        &self,
+        origin: [f32; 3],
+        dir: [f32; 3],
+        is_filled: impl Fn(Position) -> bool,
+    ) -> Option<Position> {
+        let (low, high) = (-0.5, self.side_len as f32 - 0.5);
+        let mut t_enter = 0.0f32;
+        let mut t_exit = f32::INFINITY;
+        for axis in 0..3 {
+            if dir[axis] == 0.0 {
+                if origin[axis] < low || origin[axis] > high {
+                    return None;
+                }
+                continue;
+            }
+            let t0 = (low - origin[axis]) / dir[axis];
+            let t1 = (high - origin[axis]) / dir[axis];
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+        }
+        if t_enter > t_exit {
+            return None;
+        }
+
+        // Walks the cells crossed by the ray, as the raytracer does.
+        let last = self.side_len as i64 - 1;
+        let mut cell = [0i64; 3];
+        let mut step = [0i64; 3];
+        let mut t_max = [f32::INFINITY; 3];
+        let mut t_delta = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            let start = origin[axis] + dir[axis] * t_enter;
+            cell[axis] = ((start + 0.5).floor() as i64).clamp(0, last);
+            if dir[axis] != 0.0 {
+                step[axis] = dir[axis].signum() as i64;
+                let boundary = cell[axis] as f32 + 0.5 * step[axis] as f32;
+                t_max[axis] = t_enter + (boundary - start) / dir[axis];
+                t_delta[axis] = (1.0 / dir[axis]).abs();
+            }
+        }
+        loop {
+            let position = Position::new(cell[0] as u32, cell[1] as u32, cell[2] as u32);
+            if is_filled(position) {
+                return Some(position);
+            }
+            let axis = (0..3)
+                .min_by(|&a, &b| t_max[a].total_cmp(&t_max[b]))
+                .unwrap();
+            cell[axis] += step[axis];
+            if t_max[axis] > t_exit || !(0..=last).contains(&cell[axis]) {
+                return None;
+            }
+            t_max[axis] += t_delta[axis];
+        }
+    }
+
+    // Centre of the canvas on the grid, along any axis.
+    fn centre(&self) -> f32 {
+        (self.side_len as f32 - 1.0) / 2.0
+    }
+}
+
+// Turns `v` by `angle` radians around the vertical axis, as nalgebra does.
+fn turn([x, y, z]: [f32; 3], angle: f32) -> [f32; 3] {
+    let (sin, cos) = angle.sin_cos();
+    [x * cos + z * sin, y, z * cos - x * sin]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let transform = SceneTransform::new(500);
+        for position in &[
+            Position::new(0, 0, 0),
+            Position::new(499, 0, 250),
+            Position::new(12, 345, 499),
+        ] {
+            let point = transform.scene_position(*position).unwrap();
+            assert_eq!(transform.canvas_position(point), Some(*position));
+        }
+        assert_eq!(transform.scene_position(Position::new(500, 0, 0)), None);
+
+        let transform = SceneTransform::new(4);
+        assert_eq!(transform.cube_side(), 0.125);
+        assert_eq!(
+            transform.scene_position(Position::new(0, 1, 3)),
+            Some([0.1875, 0.0625, -0.1875])
+        );
+        // Any point of a cell picks its cube.
+        assert_eq!(
+            transform.canvas_position([0.249, 0.0, -0.249]),
+            Some(Position::new(0, 2, 3))
+        );
+        assert_eq!(transform.canvas_position([0.26, 0.0, 0.0]), None);
+        assert_eq!(transform.canvas_position([0.0, -0.25, 0.0]), None);
+        assert_eq!(transform.grid_point([0.0, 0.0, -1.0]), [1.5, 1.5, 9.5]);
+        assert_eq!(transform.scene_point([1.5, 1.5, 9.5]), [0.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn test_pick() {
+        let transform = SceneTransform::new(9);
+        let filled = [Position::new(4, 4, 4), Position::new(4, 4, 6)];
+        // The closest cube to the camera hides the others.
+        assert_eq!(
+            transform.pick(0.5, 0.5, 0.0, |p| filled.contains(&p)),
+            Some(Position::new(4, 4, 6))
+        );
+        assert_eq!(transform.pick(0.5, 0.5, 0.0, |_| false), None);
+        assert_eq!(transform.pick(0.0, 0.0, 0.0, |p| filled.contains(&p)), None);
+
+        let cube = Position::new(1, 4, 4);
+        for &yaw in &[0.0, 0.7, std::f32::consts::PI, -2.0] {
+            let point = [cube.x as f32, cube.y as f32, cube.z as f32];
+            let (x, y) = transform.project(point, yaw).unwrap();
+            assert_eq!(transform.pick(x, y, yaw, |p| p == cube), Some(cube));
+        }
+        // The camera of the overlay casts the same rays.
+        let camera = transform.camera(0.7);
+        let (eye, dir) = transform.ray(0.2, 0.9, 0.7);
+        let (camera_eye, camera_dir) = camera.ray(0.2, 0.9);
+        assert!((0..3).all(|i| {
+            (eye[i] - camera_eye[i]).abs() < 1e-4 && (dir[i] - camera_dir[i]).abs() < 1e-4
+        }));
+        // Half a turn shows the canvas the other way round.
+        let (x, y) = transform.project([1.0, 4.0, 4.0], 0.0).unwrap();
+        let (turned_x, turned_y) = transform
+            .project([1.0, 4.0, 4.0], std::f32::consts::PI)
+            .unwrap();
+        assert!((turned_x - (1.0 - x)).abs() < 1e-4 && (turned_y - y).abs() < 1e-4);
+        assert_eq!(
+            transform.pick(1.0 - x, y, std::f32::consts::PI, |p| p == cube),
+            Some(cube)
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use serde::Deserialize;
+use std::collections::HashSet;
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct UserFilterConfig {
+    /// When not empty, only these chatters can place cubes.
+    pub allow: Vec<String>,
+    /// Chatters who can never place cubes, e.g. other bots of the channel.
+    pub deny: Vec<String>,
+}
+
+/// Decides who can place cubes, from the configured lists and the chatters
+/// ignored by moderators at runtime. Logins are case insensitive.
+#[derive(Clone, Debug, Default)]
+pub struct UserFilter {
+    allow: HashSet<String>,
+    deny: HashSet<String>,
+    ignored: HashSet<String>,
+}
+
+impl UserFilter {
+    pub fn new(config: &UserFilterConfig, ignored: HashSet<String>) -> Self {
+        let lowercase = |logins: &[String]| logins.iter().map(|l| l.to_lowercase()).collect();
+        Self {
+            allow: lowercase(&config.allow),
+            deny: lowercase(&config.deny),
+            ignored: ignored.iter().map(|l| l.to_lowercase()).collect(),
+        }
+    }
+
+    pub fn is_allowed(&self, login: &str) -> bool {
+        let login = login.to_lowercase();
+        (self.allow.is_empty() || self.allow.contains(&login))
+            && !self.deny.contains(&login)
+            && !self.ignored.contains(&login)
+    }
+
+    /// Returns false if the chatter was already ignored.
+    pub fn ignore(&mut self, login: &str) -> bool {
+        self.ignored.insert(login.to_lowercase())
+    }
+
+    /// Returns false if the chatter wasn't ignored. Chatters denied in the
+    /// configuration stay denied.
+    pub fn unignore(&mut self, login: &str) -> bool {
+        self.ignored.remove(&login.to_lowercase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let mut filter = UserFilter::new(
+            &UserFilterConfig {
+                allow: vec![],
+                deny: vec!["Nightbot".to_owned()],
+            },
+            vec!["spammer".to_owned()].into_iter().collect(),
+        );
+        assert!(filter.is_allowed("someone"));
+        assert!(!filter.is_allowed("nightbot"));
+        assert!(!filter.is_allowed("Spammer"));
+        assert!(filter.unignore("spammer"));
+        assert!(filter.is_allowed("spammer"));
+        assert!(filter.ignore("someone"));
+        assert!(!filter.ignore("someone"));
+        assert!(!filter.is_allowed("someone"));
+        assert!(!filter.unignore("nightbot"));
+
+        let filter = UserFilter::new(
+            &UserFilterConfig {
+                allow: vec!["builder".to_owned()],
+                deny: vec![],
+            },
+            HashSet::new(),
+        );
+        assert!(filter.is_allowed("builder"));
+        assert!(!filter.is_allowed("someone"));
+    }
+}
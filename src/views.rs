@@ -0,0 +1,338 @@
+use crate::transform::FIELD_OF_VIEW;
+use crate::{CameraPose, Position, SceneTransform};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ViewConfig {
+    /// Seconds the camera takes to move to another view.
+    pub transition_secs: f32,
+    /// Views chat can switch to by name, besides the built-in `front`, `top`
+    /// and `corner`, which they can replace. Positions are in sides of the
+    /// canvas from its centre, y growing downwards: `front` stands at
+    /// `[0, 0, 2]` looking at `[0, 0, 0]`.
+    pub presets: HashMap<String, CameraPose>,
+    /// The `follow` view frames the placements of the last this many seconds.
+    pub follow_secs: f32,
+    /// Seconds the `follow` view takes to get most of the way to a new
+    /// framing, the larger the smoother.
+    pub follow_smoothing_secs: f32,
+    /// Cubes left around the placements framed by the `follow` view.
+    pub follow_margin: f32,
+}
+
+impl Default for ViewConfig {
+    fn default() -> Self {
+        Self {
+            transition_secs: 2.0,
+            presets: HashMap::new(),
+            follow_secs: 60.0,
+            follow_smoothing_secs: 1.5,
+            follow_margin: 3.0,
+        }
+    }
+}
+
+// View framing the latest placements, which no preset can replace.
+const FOLLOW: &str = "follow";
+
+// Built-in views, in sides of the canvas from its centre.
+const FRONT: CameraPose = CameraPose {
+    position: [0.0, 0.0, 2.0],
+    target: [0.0; 3],
+};
+// Slightly tilted, as looking straight down leaves no way to tell which way
+// is up in the image.
+const TOP: CameraPose = CameraPose {
+    position: [0.0, -2.0, 0.05],
+    target: [0.0; 3],
+};
+const CORNER: CameraPose = CameraPose {
+    position: [1.2, -1.2, 1.2],
+    target: [0.0; 3],
+};
+
+// Where the `follow` view is, and where it is heading.
+#[derive(Clone, Copy, Debug)]
+struct Follow {
+    // Pose the view looks from when nothing was placed lately, along which it
+    // keeps looking.
+    base: CameraPose,
+    pose: CameraPose,
+    goal: CameraPose,
+    last: Instant,
+}
+
+/// Camera presets of the overlay, and the move to the last one chosen.
+#[derive(Clone, Debug)]
+pub struct Views {
+    transform: SceneTransform,
+    // In lowercase, on the grid of the canvas.
+    presets: HashMap<String, CameraPose>,
+    transition: Duration,
+    // Pose the camera moves from, and when it left it.
+    from: Option<(CameraPose, Instant)>,
+    current: Option<CameraPose>,
+    follow: Option<Follow>,
+    // Latest placements, oldest first, for the `follow` view.
+    recent: VecDeque<(Instant, Position)>,
+    follow_window: Duration,
+    follow_smoothing: f32,
+    follow_margin: f32,
+}
+
+impl Views {
+    pub fn new(config: &ViewConfig, side_len: u32) -> Self {
+        let transform = SceneTransform::new(side_len);
+        let centre = (transform.side_len() as f32 - 1.0) / 2.0;
+        let side = transform.side_len() as f32;
+        let to_grid = |pose: &CameraPose| CameraPose {
+            position: pose.position.map(|p| centre + p * side),
+            target: pose.target.map(|p| centre + p * side),
+        };
+        let builtin = [("front", FRONT), ("top", TOP), ("corner", CORNER)];
+        let presets = builtin
+            .iter()
+            .map(|(name, pose)| (name.to_string(), *pose))
+            .chain(
+                config
+                    .presets
+                    .iter()
+                    .map(|(name, pose)| (name.to_lowercase(), *pose)),
+            )
+            .map(|(name, pose)| (name, to_grid(&pose)))
+            .collect();
+        Self {
+            transform,
+            presets,
+            transition: Duration::from_secs_f32(config.transition_secs.max(0.0)),
+            from: None,
+            current: None,
+            follow: None,
+            recent: VecDeque::new(),
+            follow_window: Duration::from_secs_f32(config.follow_secs.max(0.0)),
+            follow_smoothing: config.follow_smoothing_secs.max(0.0),
+            follow_margin: config.follow_margin.max(0.0),
+        }
+    }
+
+    /// Names of the presets and of `follow`, in alphabetical order.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.presets.keys().cloned().collect();
+        names.retain(|name| name != FOLLOW);
+        names.push(FOLLOW.to_owned());
+        names.sort_unstable();
+        names
+    }
+
+    /// Starts moving the camera to the preset `name` from where it is at
+    /// `now`, or following the placements for `follow`, looking the same way
+    /// as the last preset. Returns false for an unknown preset.
+    pub fn switch(&mut self, name: &str, now: Instant) -> bool {
+        let name = name.to_lowercase();
+        let from = self.pose(now).unwrap_or_else(|| self.transform.camera(0.0));
+        if name == FOLLOW {
+            let base = self.current.unwrap_or_else(|| self.transform.camera(0.0));
+            self.follow = Some(Follow {
+                base,
+                pose: from,
+                goal: from,
+                last: now,
+            });
+            return true;
+        }
+        let preset = match self.presets.get(&name) {
+            Some(preset) => *preset,
+            None => return false,
+        };
+        self.follow = None;
+        self.from = Some((from, now));
+        self.current = Some(preset);
+        true
+    }
+
+    /// Notes a placement at `position`, framed by the `follow` view.
+    pub fn observe(&mut self, position: Position, now: Instant) {
+        self.recent.push_back((now, position));
+        self.forget(now);
+    }
+
+    /// Pose of the camera at `now`, eased along the move to the preset, or
+    /// smoothed on its way to frame the latest placements. `None` until a
+    /// view is chosen.
+    pub fn pose(&mut self, now: Instant) -> Option<CameraPose> {
+        let follow = match self.follow {
+            Some(follow) => follow,
+            None => return self.eased(now),
+        };
+        let goal = self.framing(&follow.base, now);
+        let elapsed = now.saturating_duration_since(follow.last).as_secs_f32();
+        // Exponential smoothing, so that the camera drifts instead of jumping
+        // at each placement.
+        let t = match self.follow_smoothing > 0.0 {
+            true => 1.0 - (-elapsed / self.follow_smoothing).exp(),
+            false => 1.0,
+        };
+        let pose = follow.pose.lerp(&goal, t);
+        self.follow = Some(Follow {
+            pose,
+            goal,
+            last: now,
+            ..follow
+        });
+        Some(pose)
+    }
+
+    fn eased(&self, now: Instant) -> Option<CameraPose> {
+        let current = self.current?;
+        let (from, start) = match self.from {
+            Some(from) => from,
+            None => return Some(current),
+        };
+        let t = match self.transition.is_zero() {
+            true => 1.0,
+            false => {
+                now.saturating_duration_since(start).as_secs_f32() / self.transition.as_secs_f32()
+            }
+        };
+        let t = t.clamp(0.0, 1.0);
+        // Smoothstep, so that the camera speeds up and slows down.
+        Some(from.lerp(&current, t * t * (3.0 - 2.0 * t)))
+    }
+
+    /// Where the camera ends up, the last preset chosen, or the latest
+    /// framing of the `follow` view.
+    pub fn target(&self) -> Option<CameraPose> {
+        match self.follow {
+            Some(follow) => Some(follow.goal),
+            None => self.current,
+        }
+    }
+
+    /// Whether the camera is still moving at `now`.
+    pub fn is_moving(&self, now: Instant) -> bool {
+        if let Some(follow) = self.follow {
+            let apart = |a: [f32; 3], b: [f32; 3]| (0..3).any(|i| (a[i] - b[i]).abs() > 0.05);
+            return apart(follow.pose.position, follow.goal.position)
+                || apart(follow.pose.target, follow.goal.target);
+        }
+        self.from
+            .is_some_and(|(_, start)| now.saturating_duration_since(start) < self.transition)
+    }
+
+    fn forget(&mut self, now: Instant) {
+        while let Some((at, _)) = self.recent.front() {
+            if now.saturating_duration_since(*at) <= self.follow_window {
+                break;
+            }
+            self.recent.pop_front();
+        }
+    }
+
+    // Looks at the centre of the box around the latest placements, along
+    // `base`, from as close as the whole box fits. `base` itself when nothing
+    // was placed lately.
+    fn framing(&mut self, base: &CameraPose, now: Instant) -> CameraPose {
+        self.forget(now);
+        let mut positions = self
+            .recent
+            .iter()
+            .map(|(_, p)| [p.x, p.y, p.z].map(|c| c as f32));
+        let first = match positions.next() {
+            Some(first) => first,
+            None => return *base,
+        };
+        let (min, max) = positions.fold((first, first), |(min, max), c| {
+            (
+                [0, 1, 2].map(|i| min[i].min(c[i])),
+                [0, 1, 2].map(|i| max[i].max(c[i])),
+            )
+        });
+        let centre = [0, 1, 2].map(|i| (min[i] + max[i]) / 2.0);
+        // Each cube spans half a cube around its position.
+        let diagonal = (0..3).map(|i| (max[i] - min[i] + 1.0).powi(2)).sum::<f32>();
+        let radius = diagonal.sqrt() / 2.0 + self.follow_margin;
+        let distance = radius / (FIELD_OF_VIEW / 2.0).sin();
+        let back = [0, 1, 2].map(|i| base.position[i] - base.target[i]);
+        let length = base.focus().max(1e-6);
+        CameraPose {
+            position: [0, 1, 2].map(|i| centre[i] + back[i] / length * distance),
+            target: centre,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Option<CameraPose>, b: CameraPose) -> bool {
+        let a = a.unwrap();
+        (0..3).all(|i| {
+            (a.position[i] - b.position[i]).abs() < 1e-4 && (a.target[i] - b.target[i]).abs() < 1e-4
+        })
+    }
+
+    #[test]
+    fn test_views() {
+        let mut config = ViewConfig::default();
+        config.presets.insert(
+            "Side".to_owned(),
+            CameraPose {
+                position: [2.0, 0.0, 0.0],
+                target: [0.0; 3],
+            },
+        );
+        let mut views = Views::new(&config, 5);
+        assert_eq!(
+            views.names(),
+            vec!["corner", "follow", "front", "side", "top"]
+        );
+        let start = Instant::now();
+        assert_eq!(views.pose(start), None);
+        assert!(!views.switch("back", start));
+
+        // The camera leaves the default view of the overlay, which is the
+        // front one.
+        assert!(views.switch("front", start));
+        let default = SceneTransform::new(5).camera(0.0);
+        assert_eq!(views.pose(start), Some(default));
+        assert!(close(views.target(), default));
+        assert!(views.is_moving(start + Duration::from_secs(1)));
+        assert!(!views.is_moving(start + Duration::from_secs(2)));
+
+        assert!(views.switch("SIDE", start + Duration::from_secs(2)));
+        let side = CameraPose {
+            position: [12.0, 2.0, 2.0],
+            target: [2.0; 3],
+        };
+        assert_eq!(views.target(), Some(side));
+        let halfway = views.pose(start + Duration::from_secs(3));
+        assert!(close(halfway, default.lerp(&side, 0.5)));
+        assert!(close(views.pose(start + Duration::from_secs(9)), side));
+
+        // Nothing was placed yet, the camera stays where it is, and then
+        // frames the placements, looking the same way.
+        let now = start + Duration::from_secs(10);
+        assert!(views.switch("follow", now));
+        assert!(close(views.pose(now), side));
+        views.observe(Position::new(0, 0, 0), now);
+        views.observe(Position::new(2, 0, 0), now);
+        let framed = views.pose(now + Duration::from_secs(30)).unwrap();
+        assert!(close(Some(framed), views.target().unwrap()));
+        assert!((framed.target[0] - 1.0).abs() < 1e-4);
+        let distance = (11f32.sqrt() / 2.0 + 3.0) / (FIELD_OF_VIEW / 2.0).sin();
+        assert!((framed.position[0] - 1.0 - distance).abs() < 1e-3);
+        assert_eq!(framed.position[1], 0.0);
+        assert!(!views.is_moving(now + Duration::from_secs(30)));
+
+        // Back along the last preset once the placements are forgotten.
+        views.pose(now + Duration::from_secs(61));
+        assert!(close(views.target(), side));
+        assert!(close(views.pose(now + Duration::from_secs(90)), side));
+        assert!(views.switch("front", now + Duration::from_secs(90)));
+        assert!(close(views.target(), default));
+    }
+}
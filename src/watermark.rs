@@ -0,0 +1,133 @@
+use crate::MinimapCorner;
+use image::imageops::FilterType;
+use image::{RgbImage, RgbaImage};
+use serde::Deserialize;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct WatermarkConfig {
+    /// Image drawn over the snapshots and the animations, e.g. the logo of
+    /// the channel as a PNG with transparency. None draws nothing.
+    pub filepath: Option<String>,
+    pub corner: MinimapCorner,
+    /// Width of the watermark, as a share of the width of the image.
+    pub size: f32,
+    /// From 0 (invisible) to 1, on top of the transparency of the image.
+    pub opacity: f32,
+}
+
+impl Default for WatermarkConfig {
+    fn default() -> Self {
+        Self {
+            filepath: None,
+            corner: MinimapCorner::BottomRight,
+            size: 0.15,
+            opacity: 0.8,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("unable to read the watermark: {0}")]
+pub struct WatermarkError(#[from] image::ImageError);
+
+/// Logo composited in a corner of the images shared out of the stream, so
+/// that they carry their attribution.
+#[derive(Clone, Debug)]
+pub struct Watermark {
+    logo: Arc<RgbaImage>,
+    corner: MinimapCorner,
+    size: f32,
+    opacity: f32,
+}
+
+impl Watermark {
+    /// Loads the image of the watermark, `None` when there's none.
+    pub fn new(config: &WatermarkConfig) -> Result<Option<Self>, WatermarkError> {
+        let logo = match &config.filepath {
+            Some(filepath) => image::open(filepath)?.to_rgba8(),
+            None => return Ok(None),
+        };
+        Ok(Some(Self::from_image(logo, config)))
+    }
+
+    fn from_image(logo: RgbaImage, config: &WatermarkConfig) -> Self {
+        Self {
+            logo: Arc::new(logo),
+            corner: config.corner,
+            size: config.size.clamp(0.0, 1.0),
+            opacity: config.opacity.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Blends the watermark over `img`, scaled to its share of the width and
+    /// a margin away from the edges, as the minimap.
+    pub fn draw(&self, img: &mut RgbImage) {
+        let margin = (img.height() / 60).max(2);
+        let width =
+            ((img.width() as f32 * self.size) as u32).min(img.width().saturating_sub(margin * 2));
+        let height =
+            (width as u64 * self.logo.height() as u64 / self.logo.width().max(1) as u64) as u32;
+        let height = height.min(img.height().saturating_sub(margin * 2));
+        if width == 0 || height == 0 {
+            return;
+        }
+        let logo = image::imageops::resize(&*self.logo, width, height, FilterType::Triangle);
+        let left = match self.corner {
+            MinimapCorner::TopLeft | MinimapCorner::BottomLeft => margin,
+            MinimapCorner::TopRight | MinimapCorner::BottomRight => img.width() - margin - width,
+        };
+        let top = match self.corner {
+            MinimapCorner::TopLeft | MinimapCorner::TopRight => margin,
+            MinimapCorner::BottomLeft | MinimapCorner::BottomRight => {
+                img.height() - margin - height
+            }
+        };
+        for (x, y, logo_pixel) in logo.enumerate_pixels() {
+            let opacity = logo_pixel.0[3] as f32 / 255.0 * self.opacity;
+            let pixel = img.get_pixel_mut(left + x, top + y);
+            for (c, logo_c) in pixel.0.iter_mut().zip(logo_pixel.0.iter()) {
+                *c = (*c as f32 * (1.0 - opacity) + *logo_c as f32 * opacity).round() as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watermark() {
+        assert!(Watermark::new(&WatermarkConfig::default())
+            .unwrap()
+            .is_none());
+        assert!(Watermark::new(&WatermarkConfig {
+            filepath: Some(".missing.png".to_owned()),
+            ..Default::default()
+        })
+        .is_err());
+
+        // An opaque red square, half of it transparent.
+        let logo = RgbaImage::from_fn(4, 4, |x, _| match x < 2 {
+            true => image::Rgba([255, 0, 0, 255]),
+            false => image::Rgba([255, 0, 0, 0]),
+        });
+        let config = WatermarkConfig {
+            filepath: None,
+            corner: MinimapCorner::BottomRight,
+            size: 0.25,
+            opacity: 0.5,
+        };
+        let watermark = Watermark::from_image(logo, &config);
+        let mut img = RgbImage::from_pixel(40, 40, image::Rgb([0, 0, 200]));
+        watermark.draw(&mut img);
+        // 10 pixels wide, 2 pixels away from the bottom right corner.
+        assert_eq!(img.get_pixel(27, 27).0, [0, 0, 200]);
+        assert_eq!(img.get_pixel(28, 37).0, [128, 0, 100]);
+        assert_eq!(img.get_pixel(37, 37).0, [0, 0, 200]);
+        assert_eq!(img.get_pixel(39, 39).0, [0, 0, 200]);
+    }
+}
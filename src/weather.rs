@@ -0,0 +1,290 @@
+use crate::{Colour, CubeError};
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum WeatherError {
+    #[error("try !weather snow, rain, particles or clear")]
+    UnknownEffect,
+    #[error("invalid weather colour: {0}")]
+    InvalidColour(#[from] CubeError),
+}
+
+/// Ambient effect falling or floating over the overlay.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WeatherEffect {
+    /// Flakes drifting down from side to side.
+    Snow,
+    /// Slanted streaks falling fast.
+    Rain,
+    /// Motes floating up and twinkling.
+    Particles,
+}
+
+impl FromStr for WeatherEffect {
+    type Err = WeatherError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "snow" => Ok(WeatherEffect::Snow),
+            "rain" => Ok(WeatherEffect::Rain),
+            "particles" => Ok(WeatherEffect::Particles),
+            _ => Err(WeatherError::UnknownEffect),
+        }
+    }
+}
+
+/// Look of one of the effects.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct EffectConfig {
+    /// Particles on the overlay at once.
+    pub count: u32,
+    /// Heights of the overlay a particle crosses every second.
+    pub speed: f32,
+    /// Side of a particle in pixels, a quarter of the length of the streaks
+    /// of rain.
+    pub size: u32,
+    /// Name or hex code, as in chat.
+    pub colour: String,
+    /// From 0 (invisible) to 1 (opaque).
+    pub opacity: f32,
+}
+
+impl Default for EffectConfig {
+    fn default() -> Self {
+        Self {
+            count: 150,
+            speed: 0.08,
+            size: 3,
+            colour: "white".to_owned(),
+            opacity: 0.8,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct WeatherConfig {
+    /// Effect on the overlay on start, none by default.
+    pub effect: Option<WeatherEffect>,
+    /// Id of the channel point reward which lets anyone use `!weather`,
+    /// moderators always can.
+    pub reward_id: Option<String>,
+    pub snow: EffectConfig,
+    pub rain: EffectConfig,
+    pub particles: EffectConfig,
+}
+
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        Self {
+            effect: None,
+            reward_id: None,
+            snow: EffectConfig::default(),
+            rain: EffectConfig {
+                count: 200,
+                speed: 0.9,
+                size: 3,
+                colour: "#a0b4dc".to_owned(),
+                opacity: 0.5,
+            },
+            particles: EffectConfig {
+                count: 60,
+                speed: 0.03,
+                size: 2,
+                colour: "#ffe08a".to_owned(),
+                opacity: 0.9,
+            },
+        }
+    }
+}
+
+impl WeatherConfig {
+    /// Effect asked for by the arguments of `!weather`, none for `clear`.
+    pub fn parse_effect(&self, args: &str) -> Result<Option<WeatherEffect>, WeatherError> {
+        match args.trim() {
+            args if args.eq_ignore_ascii_case("clear") => Ok(None),
+            args => args.parse().map(Some),
+        }
+    }
+
+    pub fn effect(&self, effect: WeatherEffect) -> &EffectConfig {
+        match effect {
+            WeatherEffect::Snow => &self.snow,
+            WeatherEffect::Rain => &self.rain,
+            WeatherEffect::Particles => &self.particles,
+        }
+    }
+}
+
+// Position as a share of the width and height of the overlay, so that it
+// doesn't depend on its resolution.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Particle {
+    x: f32,
+    y: f32,
+    // Offset of its sway and twinkle, so that particles don't move as one.
+    phase: f32,
+}
+
+// Sideways drift of rain for every unit it falls.
+const RAIN_SLANT: f32 = 0.15;
+// Widest sway of snow and particles, as a share of the width.
+const SWAY: f32 = 0.01;
+
+/// Particles of an effect, moved along with the frames of the overlay and
+/// drawn over them. Never part of the canvas.
+#[derive(Clone, Debug)]
+pub struct Weather {
+    effect: WeatherEffect,
+    speed: f32,
+    size: u32,
+    colour: Colour,
+    opacity: f32,
+    particles: Vec<Particle>,
+    // Seconds since the weather started, for the sway and twinkle.
+    time: f32,
+}
+
+impl Weather {
+    /// Scatters the particles of `effect` as its config says, `seed` picks
+    /// where.
+    pub fn new(
+        effect: WeatherEffect,
+        config: &WeatherConfig,
+        seed: u64,
+    ) -> Result<Self, WeatherError> {
+        let config = config.effect(effect);
+        let rng = fastrand::Rng::with_seed(seed);
+        let particles = (0..config.count)
+            .map(|_| Particle {
+                x: rng.f32(),
+                y: rng.f32(),
+                phase: rng.f32() * std::f32::consts::TAU,
+            })
+            .collect();
+        Ok(Self {
+            effect,
+            speed: config.speed,
+            size: config.size.max(1),
+            colour: config.colour.parse()?,
+            opacity: config.opacity.clamp(0.0, 1.0),
+            particles,
+            time: 0.0,
+        })
+    }
+
+    /// Moves the particles for `elapsed`, those leaving the overlay come
+    /// back on the other side.
+    pub fn advance(&mut self, elapsed: Duration) {
+        let elapsed = elapsed.as_secs_f32();
+        self.time += elapsed;
+        let fall = self.speed * elapsed;
+        for particle in self.particles.iter_mut() {
+            let (dx, dy) = match self.effect {
+                WeatherEffect::Snow => (0.0, fall),
+                WeatherEffect::Rain => (fall * RAIN_SLANT, fall),
+                WeatherEffect::Particles => (0.0, -fall),
+            };
+            particle.x = (particle.x + dx).rem_euclid(1.0);
+            particle.y = (particle.y + dy).rem_euclid(1.0);
+        }
+    }
+
+    /// Blends the particles into `img`.
+    pub fn draw(&self, img: &mut RgbImage) {
+        let (width, height) = (img.width() as f32, img.height() as f32);
+        let size = self.size as i64;
+        for particle in &self.particles {
+            let sway = (self.time + particle.phase).sin() * SWAY;
+            let (x, y) = (particle.x * width, particle.y * height);
+            match self.effect {
+                WeatherEffect::Snow => {
+                    let x = x + sway * width;
+                    self.blend_square(img, x as i64, y as i64, size, self.opacity);
+                }
+                WeatherEffect::Rain => {
+                    let length = 4 * size;
+                    for step in 0..length {
+                        let dx = (step as f32 * RAIN_SLANT) as i64;
+                        self.blend(img, x as i64 - dx, y as i64 - step, self.opacity);
+                    }
+                }
+                WeatherEffect::Particles => {
+                    let x = x + sway * width;
+                    let twinkle = 0.5 + 0.5 * (self.time * 3.0 + particle.phase).sin();
+                    self.blend_square(img, x as i64, y as i64, size, self.opacity * twinkle);
+                }
+            }
+        }
+    }
+
+    fn blend_square(&self, img: &mut RgbImage, x: i64, y: i64, size: i64, opacity: f32) {
+        for dy in 0..size {
+            for dx in 0..size {
+                self.blend(img, x + dx, y + dy, opacity);
+            }
+        }
+    }
+
+    // Pixels out of the image are skipped, particles cross its edges.
+    fn blend(&self, img: &mut RgbImage, x: i64, y: i64, opacity: f32) {
+        if x < 0 || y < 0 || x >= img.width() as i64 || y >= img.height() as i64 {
+            return;
+        }
+        let pixel = img.get_pixel_mut(x as u32, y as u32);
+        let colour = [self.colour.r, self.colour.g, self.colour.b];
+        for (c, target) in pixel.0.iter_mut().zip(colour.iter()) {
+            *c = (*c as f32 + (*target as f32 - *c as f32) * opacity).round() as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weather() {
+        let config = WeatherConfig::default();
+        assert_eq!(config.parse_effect(" Snow "), Ok(Some(WeatherEffect::Snow)));
+        assert_eq!(config.parse_effect("clear"), Ok(None));
+        assert_eq!(
+            config.parse_effect("hail"),
+            Err(WeatherError::UnknownEffect)
+        );
+
+        let mut rain = Weather::new(WeatherEffect::Rain, &config, 7).unwrap();
+        assert_eq!(rain.particles.len(), 200);
+        let before = rain.particles.clone();
+        rain.advance(Duration::from_secs(3));
+        assert_ne!(rain.particles, before);
+        assert!(rain
+            .particles
+            .iter()
+            .all(|p| (0.0..1.0).contains(&p.x) && (0.0..1.0).contains(&p.y)));
+
+        let mut img = RgbImage::new(64, 64);
+        rain.draw(&mut img);
+        assert!(img.pixels().any(|p| p.0 != [0, 0, 0]));
+        let mut invisible = config.clone();
+        invisible.snow.opacity = 0.0;
+        let mut img = RgbImage::new(64, 64);
+        Weather::new(WeatherEffect::Snow, &invisible, 7)
+            .unwrap()
+            .draw(&mut img);
+        assert!(img.pixels().all(|p| p.0 == [0, 0, 0]));
+
+        let mut muddy = config.clone();
+        muddy.particles.colour = "mud".to_owned();
+        assert!(matches!(
+            Weather::new(WeatherEffect::Particles, &muddy, 7),
+            Err(WeatherError::InvalidColour(_))
+        ));
+    }
+}
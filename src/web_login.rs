@@ -0,0 +1,404 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum WebLoginError {
+    #[error("too many logins in progress, try again later")]
+    TooManyLogins,
+    #[error("too many logins started from here, try again later")]
+    RateLimited,
+    #[error("unknown login, start another one")]
+    UnknownLogin,
+    #[error("the login expired, start another one")]
+    Expired,
+    #[error("the login was denied")]
+    Denied,
+    #[error("unexpected answer from Twitch: {0}")]
+    Twitch(String),
+}
+
+/// What Twitch answers to the start of a device flow: the code the chatter
+/// enters at the verification URI, and the one the bot polls the token with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeviceCode {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    /// Seconds the codes are valid, and between two polls.
+    pub expires_in: u64,
+    pub interval: u64,
+}
+
+impl DeviceCode {
+    pub fn from_twitch(json: &str) -> Result<Self, WebLoginError> {
+        serde_json::from_str(json).map_err(|_| WebLoginError::Twitch(json.to_owned()))
+    }
+}
+
+/// Where a device flow is, from what Twitch answers to a poll of its token.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenPoll {
+    /// The chatter didn't enter the code yet.
+    Pending,
+    /// The access token of the chatter.
+    Authorized(String),
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct TokenError {
+    message: String,
+}
+
+impl TokenPoll {
+    /// Reads the answer of Twitch to a poll of the token, `success` when its
+    /// status was.
+    pub fn from_twitch(success: bool, json: &str) -> Result<Self, WebLoginError> {
+        let unexpected = || WebLoginError::Twitch(json.to_owned());
+        if success {
+            let token: TokenResponse = serde_json::from_str(json).map_err(|_| unexpected())?;
+            return Ok(TokenPoll::Authorized(token.access_token));
+        }
+        let error: TokenError = serde_json::from_str(json).map_err(|_| unexpected())?;
+        match error.message.as_str() {
+            "authorization_pending" | "slow_down" => Ok(TokenPoll::Pending),
+            "access_denied" => Err(WebLoginError::Denied),
+            "expired_token" | "invalid device code" => Err(WebLoginError::Expired),
+            _ => Err(unexpected()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Validation {
+    login: String,
+}
+
+/// Login of the chatter an access token is of, from the answer of Twitch to
+/// its validation.
+pub fn validated_login(json: &str) -> Result<String, WebLoginError> {
+    let validation: Validation =
+        serde_json::from_str(json).map_err(|_| WebLoginError::Twitch(json.to_owned()))?;
+    Ok(validation.login.to_lowercase())
+}
+
+// Logins started by a client are counted over this long.
+const RATE_PERIOD: Duration = Duration::from_secs(3600);
+// How long a login is kept before Twitch answers with its device code.
+const RESERVATION: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+struct Pending {
+    // None until Twitch answers with it.
+    device_code: Option<String>,
+    expires_at: Instant,
+}
+
+#[derive(Clone, Debug)]
+struct Session {
+    login: String,
+    expires_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Logins {
+    pending: HashMap<String, Pending>,
+    sessions: HashMap<String, Session>,
+    // When each client started its logins, over the last rate period.
+    starts: HashMap<IpAddr, VecDeque<Instant>>,
+}
+
+impl Logins {
+    fn forget_expired(&mut self, now: Instant) {
+        self.pending.retain(|_, pending| pending.expires_at > now);
+        self.sessions.retain(|_, session| session.expires_at > now);
+        self.starts.retain(|_, starts| {
+            while let Some(start) = starts.front() {
+                if now.saturating_duration_since(*start) < RATE_PERIOD {
+                    break;
+                }
+                starts.pop_front();
+            }
+            !starts.is_empty()
+        });
+    }
+}
+
+/// Chatters logging in to the web API with Twitch, and the sessions of those
+/// who did, which act on their behalf. Both are only kept in memory, a
+/// restart logs everyone out. At most `max_pending` logins are in progress
+/// at once, and each client starts at most `starts_per_hour` of them. Cloning
+/// gives another handle to the same logins.
+#[derive(Clone, Debug)]
+pub struct WebLogins {
+    session_duration: Duration,
+    max_pending: usize,
+    starts_per_hour: usize,
+    logins: Arc<Mutex<Logins>>,
+}
+
+impl WebLogins {
+    pub fn new(session_duration: Duration, max_pending: usize, starts_per_hour: usize) -> Self {
+        Self {
+            session_duration,
+            max_pending,
+            starts_per_hour,
+            logins: Arc::new(Mutex::new(Logins::default())),
+        }
+    }
+
+    /// Makes room for a login started by `client` at `now`, before asking
+    /// Twitch for its device flow, and returns the id it's polled by.
+    pub fn reserve(&self, client: IpAddr, now: Instant) -> Result<String, WebLoginError> {
+        let mut logins = self.logins.lock().unwrap();
+        logins.forget_expired(now);
+        let logins = &mut *logins;
+        let starts = logins.starts.entry(client).or_default();
+        if starts.len() >= self.starts_per_hour {
+            return Err(WebLoginError::RateLimited);
+        }
+        if logins.pending.len() >= self.max_pending {
+            return Err(WebLoginError::TooManyLogins);
+        }
+        starts.push_back(now);
+        let id = Uuid::new_v4().to_string();
+        logins.pending.insert(
+            id.clone(),
+            Pending {
+                device_code: None,
+                expires_at: now + RESERVATION,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Follows the device flow of `code` for the login `id`, reserved
+    /// before `now`.
+    pub fn start(&self, id: &str, code: &DeviceCode, now: Instant) -> Result<(), WebLoginError> {
+        let mut logins = self.logins.lock().unwrap();
+        let pending = logins
+            .pending
+            .get_mut(id)
+            .ok_or(WebLoginError::UnknownLogin)?;
+        pending.device_code = Some(code.device_code.clone());
+        pending.expires_at = now + Duration::from_secs(code.expires_in);
+        Ok(())
+    }
+
+    /// Device code of the login `id`, to poll its token with.
+    pub fn device_code(&self, id: &str, now: Instant) -> Result<String, WebLoginError> {
+        let logins = self.logins.lock().unwrap();
+        match logins.pending.get(id) {
+            Some(pending) if pending.expires_at > now => pending
+                .device_code
+                .clone()
+                .ok_or(WebLoginError::UnknownLogin),
+            Some(_) => Err(WebLoginError::Expired),
+            None => Err(WebLoginError::UnknownLogin),
+        }
+    }
+
+    /// Drops the login `id`, e.g. once denied or when Twitch couldn't start
+    /// it.
+    pub fn cancel(&self, id: &str) {
+        self.logins.lock().unwrap().pending.remove(id);
+    }
+
+    /// Ends the login `id` as `login`, and returns the token of their
+    /// session.
+    pub fn complete(&self, id: &str, login: &str, now: Instant) -> Result<String, WebLoginError> {
+        let mut logins = self.logins.lock().unwrap();
+        logins
+            .pending
+            .remove(id)
+            .ok_or(WebLoginError::UnknownLogin)?;
+        let token = Uuid::new_v4().to_string();
+        logins.sessions.insert(
+            token.clone(),
+            Session {
+                login: login.to_owned(),
+                expires_at: now + self.session_duration,
+            },
+        );
+        Ok(token)
+    }
+
+    /// Login of the chatter whose session `token` is, while it lasts.
+    pub fn login(&self, token: &str, now: Instant) -> Option<String> {
+        let logins = self.logins.lock().unwrap();
+        logins
+            .sessions
+            .get(token)
+            .filter(|session| session.expires_at > now)
+            .map(|session| session.login.clone())
+    }
+}
+
+/// Body of a form posted to Twitch, its values percent-encoded.
+pub fn form_body(fields: &[(&str, &str)]) -> String {
+    let encode = |value: &str| {
+        value
+            .bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    (b as char).to_string()
+                }
+                b => format!("%{:02X}", b),
+            })
+            .collect::<String>()
+    };
+    fields
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE_CODE: &str = r#"{
+        "device_code": "ike3GM8QIdYZs43KdrWPIO36LofILoCyFEzjlQ91",
+        "expires_in": 1800,
+        "interval": 5,
+        "user_code": "ABCDEFGH",
+        "verification_uri": "https://www.twitch.tv/activate?public=true&device-code=ABCDEFGH"
+    }"#;
+
+    #[test]
+    fn test_twitch_answers() {
+        let code = DeviceCode::from_twitch(DEVICE_CODE).unwrap();
+        assert_eq!(code.user_code, "ABCDEFGH");
+        assert_eq!((code.expires_in, code.interval), (1800, 5));
+        assert!(DeviceCode::from_twitch("{}").is_err());
+
+        let pending = r#"{"status": 400, "message": "authorization_pending"}"#;
+        assert_eq!(
+            TokenPoll::from_twitch(false, pending),
+            Ok(TokenPoll::Pending)
+        );
+        let denied = r#"{"status": 400, "message": "access_denied"}"#;
+        assert_eq!(
+            TokenPoll::from_twitch(false, denied),
+            Err(WebLoginError::Denied)
+        );
+        let authorized = r#"{
+            "access_token": "rfx2uswqe8l4g1mkagrvg5tv0ks3",
+            "expires_in": 14124,
+            "refresh_token": "5b93chm6hdve3mycz05zfzatkfdenfspp1h1ar2xxdalen01",
+            "scope": [],
+            "token_type": "bearer"
+        }"#;
+        assert_eq!(
+            TokenPoll::from_twitch(true, authorized),
+            Ok(TokenPoll::Authorized(
+                "rfx2uswqe8l4g1mkagrvg5tv0ks3".to_owned()
+            ))
+        );
+        assert!(TokenPoll::from_twitch(false, "oops").is_err());
+
+        let validation = r#"{
+            "client_id": "wbmytr93xzw8zbg0p1izqyzzc5mbiz",
+            "login": "Stuck_Overflow",
+            "scopes": [],
+            "user_id": "101051819",
+            "expires_in": 5520838
+        }"#;
+        assert_eq!(validated_login(validation).unwrap(), "stuck_overflow");
+    }
+
+    #[test]
+    fn test_logins() {
+        let logins = WebLogins::new(Duration::from_secs(3600), 10, 10);
+        let code = DeviceCode::from_twitch(DEVICE_CODE).unwrap();
+        let client = IpAddr::from([127, 0, 0, 1]);
+        let now = Instant::now();
+        let id = logins.reserve(client, now).unwrap();
+        // Not polled until Twitch starts it.
+        assert_eq!(
+            logins.device_code(&id, now),
+            Err(WebLoginError::UnknownLogin)
+        );
+        logins.start(&id, &code, now).unwrap();
+        assert_eq!(logins.device_code(&id, now).unwrap(), code.device_code);
+        assert_eq!(
+            logins.device_code("other", now),
+            Err(WebLoginError::UnknownLogin)
+        );
+        assert_eq!(
+            logins.device_code(&id, now + Duration::from_secs(1800)),
+            Err(WebLoginError::Expired)
+        );
+
+        let session = logins.complete(&id, "alice", now).unwrap();
+        assert_eq!(logins.login(&session, now).as_deref(), Some("alice"));
+        assert_eq!(logins.login(&id, now), None);
+        assert_eq!(
+            logins.login(&session, now + Duration::from_secs(3600)),
+            None
+        );
+        // A login ends once.
+        assert_eq!(
+            logins.complete(&id, "alice", now),
+            Err(WebLoginError::UnknownLogin)
+        );
+
+        let id = logins.clone().reserve(client, now).unwrap();
+        logins.cancel(&id);
+        assert_eq!(
+            logins.start(&id, &code, now),
+            Err(WebLoginError::UnknownLogin)
+        );
+    }
+
+    #[test]
+    fn test_login_limits() {
+        let logins = WebLogins::new(Duration::from_secs(3600), 3, 2);
+        let (alice, bob, carol) = (
+            IpAddr::from([10, 0, 0, 1]),
+            IpAddr::from([10, 0, 0, 2]),
+            IpAddr::from([10, 0, 0, 3]),
+        );
+        let now = Instant::now();
+        let id = logins.reserve(alice, now).unwrap();
+        logins.reserve(alice, now).unwrap();
+        assert_eq!(logins.reserve(alice, now), Err(WebLoginError::RateLimited));
+        // Cancelled logins still count towards the rate.
+        logins.cancel(&id);
+        assert_eq!(logins.reserve(alice, now), Err(WebLoginError::RateLimited));
+        logins.reserve(bob, now).unwrap();
+        logins.reserve(bob, now).unwrap();
+        assert_eq!(
+            logins.reserve(carol, now),
+            Err(WebLoginError::TooManyLogins)
+        );
+
+        // Reservations Twitch didn't answer for expire, and the rate resets
+        // after an hour.
+        let later = now + RESERVATION;
+        logins.reserve(carol, later).unwrap();
+        assert_eq!(
+            logins.reserve(alice, later),
+            Err(WebLoginError::RateLimited)
+        );
+        logins.reserve(alice, now + RATE_PERIOD).unwrap();
+    }
+
+    #[test]
+    fn test_form_body() {
+        assert_eq!(
+            form_body(&[("client_id", "abc"), ("scopes", ""), ("x", "a b&c/é")]),
+            "client_id=abc&scopes=&x=a%20b%26c%2F%C3%A9"
+        );
+    }
+}
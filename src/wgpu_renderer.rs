@@ -0,0 +1,458 @@
+use crate::renderer::Renderer;
+use crate::{Background, CameraPose, Cube, Position, SceneTransform};
+use bytemuck::{Pod, Zeroable};
+use image::RgbImage;
+use nalgebra::{Isometry3, Matrix4, Perspective3, Point3, Vector3};
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+use thiserror::Error;
+use wgpu::util::DeviceExt;
+
+// Offscreen renderer for machines where kiss3d fails to create a GL context.
+// It draws the same scene as the kiss3d renderer, as seen from kiss3d's
+// default camera, without opening any window.
+pub struct WgpuRenderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    vertex_buffer: wgpu::Buffer,
+    instance_buffer: Option<wgpu::Buffer>,
+    target: wgpu::Texture,
+    target_view: wgpu::TextureView,
+    depth_view: wgpu::TextureView,
+    output_buffer: wgpu::Buffer,
+    extent: wgpu::Extent3d,
+    padded_bytes_per_row: u32,
+    transform: SceneTransform,
+    instances: HashMap<Position, Instance>,
+    instances_changed: bool,
+    yaw: f32,
+    camera: Option<CameraPose>,
+    // Clear colour of the frames.
+    background: wgpu::Color,
+}
+
+#[derive(Error, Debug)]
+pub enum WgpuRendererError {
+    #[error("no suitable graphics adapter found")]
+    NoAdapter,
+    #[error("error requesting the device {0}")]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Vertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Instance {
+    offset: [f32; 3],
+    colour: [f32; 3],
+    // Size of the box drawn, as a share of the cell on each axis.
+    scale: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Uniforms {
+    view_proj: [[f32; 4]; 4],
+    eye: [f32; 3],
+    voxel_side_len: f32,
+}
+
+const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+    wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+const INSTANCE_ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+    wgpu::vertex_attr_array![2 => Float32x3, 3 => Float32x3, 4 => Float32x3];
+
+impl WgpuRenderer {
+    pub fn new(window_size_pixels: u32, frame_side_len: u32) -> Result<Self, WgpuRendererError> {
+        let transform = SceneTransform::new(frame_side_len);
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        }))
+        .ok_or(WgpuRendererError::NoAdapter)?;
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::downlevel_defaults(),
+            },
+            None,
+        ))?;
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("cubes"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/cubes.wgsl").into()),
+        });
+
+        let uniforms = Uniforms::new(&transform, 0.0, None);
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("uniforms"),
+            contents: bytemuck::bytes_of(&uniforms),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("cubes"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &VERTEX_ATTRIBUTES,
+                    },
+                    wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &INSTANCE_ATTRIBUTES,
+                    },
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: TEXTURE_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("cube vertices"),
+            contents: bytemuck::cast_slice(&cube_vertices()),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let extent = wgpu::Extent3d {
+            width: window_size_pixels,
+            height: window_size_pixels,
+            depth_or_array_layers: 1,
+        };
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("render target"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TEXTURE_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("depth"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+        let depth_view = depth.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Rows copied out of a texture must be aligned, the padding is
+        // stripped when converting to an image.
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = (window_size_pixels * 4).div_ceil(alignment) * alignment;
+        let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("output"),
+            size: (padded_bytes_per_row * window_size_pixels) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Ok(Self {
+            device,
+            queue,
+            pipeline,
+            bind_group,
+            vertex_buffer,
+            uniform_buffer,
+            instance_buffer: None,
+            target,
+            target_view,
+            depth_view,
+            output_buffer,
+            extent,
+            padded_bytes_per_row,
+            transform,
+            instances: HashMap::new(),
+            instances_changed: false,
+            yaw: 0.0,
+            camera: None,
+            background: clear_colour(&Background::default()),
+        })
+    }
+
+    fn write_uniforms(&self) {
+        let uniforms = Uniforms::new(&self.transform, self.yaw, self.camera);
+        self.queue
+            .write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+}
+
+fn clear_colour(background: &Background) -> wgpu::Color {
+    let (r, g, b) = background.clear_colour().to_f32();
+    wgpu::Color {
+        r: r as f64,
+        g: g as f64,
+        b: b as f64,
+        a: 1.0,
+    }
+}
+
+impl Renderer for WgpuRenderer {
+    fn add_cube(&mut self, cube: &Cube) {
+        let [x, y, z] = match self.transform.scene_position(cube.position) {
+            Some(offset) => offset,
+            None => return,
+        };
+        let (r, g, b) = cube.colour.to_f32();
+        // Every shape is drawn as its box, spheres too, the scene's axes
+        // going the other way from those of the grid.
+        let (low, high) = cube.shape().bounds();
+        let side = self.transform.cube_side();
+        let offset = |axis: usize| -(low[axis] + high[axis]) / 2.0 * side;
+        self.instances.insert(
+            cube.position,
+            Instance {
+                offset: [x + offset(0), y + offset(1), z + offset(2)],
+                colour: [r, g, b],
+                scale: [high[0] - low[0], high[1] - low[1], high[2] - low[2]],
+            },
+        );
+        self.instances_changed = true;
+    }
+
+    fn remove_cube(&mut self, position: Position) {
+        if self.instances.remove(&position).is_some() {
+            self.instances_changed = true;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.instances.clear();
+        self.instances_changed = true;
+    }
+
+    fn set_yaw(&mut self, yaw: f32) {
+        self.yaw = yaw;
+        self.write_uniforms();
+    }
+
+    fn set_camera(&mut self, camera: Option<CameraPose>) {
+        self.camera = camera;
+        self.write_uniforms();
+    }
+
+    fn set_background(&mut self, background: &Background) {
+        self.background = clear_colour(background);
+    }
+
+    fn render(&mut self) -> Option<RgbImage> {
+        if self.instances_changed {
+            let instances: Vec<Instance> = self.instances.values().copied().collect();
+            self.instance_buffer = Some(self.device.create_buffer_init(
+                &wgpu::util::BufferInitDescriptor {
+                    label: Some("cube instances"),
+                    contents: bytemuck::cast_slice(&instances),
+                    usage: wgpu::BufferUsages::VERTEX,
+                },
+            ));
+            self.instances_changed = false;
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.target_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.background),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            if let (Some(instance_buffer), false) =
+                (&self.instance_buffer, self.instances.is_empty())
+            {
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &self.bind_group, &[]);
+                pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                pass.set_vertex_buffer(1, instance_buffer.slice(..));
+                pass.draw(0..36, 0..self.instances.len() as u32);
+            }
+        }
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.target,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.output_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(self.padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            self.extent,
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = self.output_buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        self.device.poll(wgpu::Maintain::Wait);
+        if pollster::block_on(mapping).is_err() {
+            return None;
+        }
+        let width = self.extent.width as usize;
+        let mut pixels = Vec::with_capacity(width * self.extent.height as usize * 3);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(self.padded_bytes_per_row as usize) {
+                for pixel in row[..width * 4].chunks(4) {
+                    pixels.extend_from_slice(&pixel[..3]);
+                }
+            }
+        }
+        self.output_buffer.unmap();
+        RgbImage::from_raw(self.extent.width, self.extent.height, pixels)
+    }
+}
+
+impl Uniforms {
+    // Turning the scene by `yaw` is the same as orbiting the camera the
+    // other way. A `camera` replaces both.
+    fn new(transform: &SceneTransform, yaw: f32, camera: Option<CameraPose>) -> Self {
+        let (eye, target) = match camera {
+            Some(camera) => {
+                let point = |p: [f32; 3]| {
+                    let [x, y, z] = transform.scene_point(p);
+                    Point3::new(x, y, z)
+                };
+                (point(camera.position), point(camera.target))
+            }
+            None => (Point3::new(yaw.sin(), 0.0, -yaw.cos()), Point3::origin()),
+        };
+        Uniforms {
+            view_proj: camera_view_proj(&eye, &target).into(),
+            eye: eye.coords.into(),
+            voxel_side_len: transform.cube_side(),
+        }
+    }
+}
+
+// Same camera kiss3d sets up by default: looking at `target` from `eye`, the
+// origin from (0, 0, -1) unless the scene turns, with a 45 degrees field of
+// view.
+fn camera_view_proj(eye: &Point3<f32>, target: &Point3<f32>) -> Matrix4<f32> {
+    let view = Isometry3::look_at_rh(eye, target, &Vector3::y());
+    let projection = Perspective3::new(1.0, std::f32::consts::FRAC_PI_4, 0.1, 1024.0);
+    // nalgebra follows the OpenGL convention of a [-1, 1] depth range, wgpu
+    // expects [0, 1].
+    #[rustfmt::skip]
+    let opengl_to_wgpu = Matrix4::new(
+        1.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 0.5, 0.5,
+        0.0, 0.0, 0.0, 1.0,
+    );
+    opengl_to_wgpu * projection.as_matrix() * view.to_homogeneous()
+}
+
+// Unit cube centred in the origin as a triangle list, scaled to the voxel
+// size in the vertex shader.
+fn cube_vertices() -> Vec<Vertex> {
+    let mut vertices = Vec::with_capacity(36);
+    for axis in 0..3 {
+        for &sign in &[-1.0f32, 1.0] {
+            let mut normal = [0.0; 3];
+            normal[axis] = sign;
+            let corner = |u: f32, v: f32| {
+                let mut position = [0.0; 3];
+                position[axis] = sign * 0.5;
+                position[(axis + 1) % 3] = u * 0.5;
+                position[(axis + 2) % 3] = v * 0.5;
+                Vertex { position, normal }
+            };
+            for &(u, v) in &[
+                (-1.0, -1.0),
+                (1.0, -1.0),
+                (1.0, 1.0),
+                (-1.0, -1.0),
+                (1.0, 1.0),
+                (-1.0, 1.0),
+            ] {
+                vertices.push(corner(u, v));
+            }
+        }
+    }
+    vertices
+}